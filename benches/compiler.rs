@@ -0,0 +1,127 @@
+//! Benchmarks of each compiler pass on large synthetic programs.
+//!
+//! Name resolution and type checking happen inside the parser and the code
+//! generator respectively, so they are measured as part of those passes.
+//!
+//! ```sh
+//! $ cargo bench --bench compiler
+//! ```
+
+use chigusa::c0::ast::Program;
+use chigusa::c0::gen::{generate, GenConfig};
+use chigusa::c0::lexer::{Lexer, Token};
+use chigusa::c0::parser::Parser;
+use chigusa::minivm::Codegen;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use std::fmt::Write;
+
+/// `n` functions, each calling the one before it
+fn many_functions(n: usize) -> String {
+    let mut src = String::new();
+    src += "int f0(int a, int b) { return a + b; }\n";
+    for i in 1..n {
+        writeln!(
+            src,
+            "int f{i}(int a, int b) {{
+    int c = a * {i} + b;
+    double d = c / 2.0;
+    if (c > 10) {{
+        return f{prev}(c - 1, b) + (int)d;
+    }} else if (c < -10) {{
+        return -c;
+    }}
+    while (c > 0) {{
+        c = c - 1;
+    }}
+    return c;
+}}",
+            i = i,
+            prev = i - 1
+        )
+        .unwrap();
+    }
+    writeln!(src, "int main() {{ return f{}(1, 2); }}", n - 1).unwrap();
+    src
+}
+
+/// One expression nested `depth` levels deep
+fn deep_expr(depth: usize) -> String {
+    let mut src = String::from("int main() {\n    int a = 1;\n    return ");
+    for _ in 0..depth {
+        src += "(a + ";
+    }
+    src += "1";
+    for _ in 0..depth {
+        src += ")";
+    }
+    src += ";\n}\n";
+    src
+}
+
+/// One flat expression with `len` operands
+fn long_expr(len: usize) -> String {
+    let mut src = String::from("int main() {\n    int a = 1;\n    return a");
+    for i in 0..len {
+        src += [" + a", " * 2", " - a", " / 3"][i % 4];
+    }
+    src += ";\n}\n";
+    src
+}
+
+fn inputs() -> Vec<(&'static str, String)> {
+    vec![
+        ("10k_functions", many_functions(10_000)),
+        ("deep_expr_10k", deep_expr(10_000)),
+        ("long_expr_100k", long_expr(100_000)),
+        (
+            "generated_1k",
+            generate(&GenConfig {
+                functions: 1_000,
+                depth: 6,
+                seed: 42,
+            }),
+        ),
+    ]
+}
+
+fn lex(src: &str) -> Vec<Token<'_>> {
+    Lexer::new(src).collect()
+}
+
+fn parse(tokens: Vec<Token>) -> Program {
+    Parser::new(tokens.into_iter()).parse().unwrap()
+}
+
+fn bench_passes(c: &mut Criterion) {
+    for (name, src) in inputs() {
+        let mut group = c.benchmark_group(name);
+        group.sample_size(10);
+        group.throughput(Throughput::Bytes(src.len() as u64));
+
+        group.bench_function(BenchmarkId::new("lex", name), |b| b.iter(|| lex(&src)));
+
+        let tokens = lex(&src);
+        group.bench_function(BenchmarkId::new("parse", name), |b| {
+            // * Programs are big; keeping a whole batch of them alive runs
+            // * out of memory.
+            b.iter_batched(|| tokens.clone(), parse, BatchSize::PerIteration)
+        });
+
+        let prog = parse(tokens);
+        group.bench_function(BenchmarkId::new("codegen", name), |b| {
+            b.iter(|| Codegen::new(&prog).compile().unwrap())
+        });
+
+        group.bench_function(BenchmarkId::new("all", name), |b| {
+            b.iter(|| {
+                let prog = parse(lex(&src));
+                Codegen::new(&prog).compile().unwrap()
+            })
+        });
+
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_passes);
+criterion_main!(benches);
@@ -0,0 +1,592 @@
+//! Reading and writing O0 binary files.
+//!
+//! The layout follows the C0 VM standard. All numbers are big endian in the
+//! standard format; little endian files are the same with every number's
+//! bytes reversed, which readers tell apart by the magic number.
+//!
+//! ```text
+//! o0 {
+//!     u4 magic;            // 0x43303A29
+//!     u4 version;          // 0x00000001
+//!     u2 constants_count;
+//!     constant_info constants[constants_count];
+//!     u2 start_instructions_count;
+//!     instruction start_code[start_instructions_count];
+//!     u2 functions_count;
+//!     function_info functions[functions_count];
+//! }
+//!
+//! constant_info {
+//!     u1 type;             // 0: string (u2 length; u1 bytes[length])
+//!                          // 1: int (u4), 2: double (u8)
+//!     ...value;
+//! }
+//!
+//! function_info {
+//!     u2 name_index;
+//!     u2 params_size;
+//!     u2 level;
+//!     u2 instructions_count;
+//!     instruction instructions[instructions_count];
+//! }
+//! ```
+//!
+//! An instruction is its opcode in one byte, followed by its operands.
+//! Calls hold the index of the function called; relocations, which say what
+//! each call was to, are not written, so binaries read back have none.
+//!
+//! Debug info lives in a file of its own, so that binaries stay standard.
+//! It is always big endian, and lines are stored plus one, leaving 0 for
+//! instructions without a line. Variables and block starts come last, and
+//! files without them are read as having none.
+//!
+//! ```text
+//! c0_debug {
+//!     u4 magic;            // 0x43304447
+//!     u2 source_length;
+//!     u1 source[source_length];
+//!     u2 start_count;
+//!     u4 start_lines[start_count];
+//!     u2 functions_count;
+//!     { u2 count; u4 lines[count]; } functions[functions_count];
+//!     u2 globals_count;
+//!     var_info globals[globals_count];
+//!     u2 fn_vars_count;
+//!     { u2 count; var_info vars[count]; } fn_vars[fn_vars_count];
+//!     u2 fn_blocks_count;
+//!     { u2 count; { u4 block; u4 start; } blocks[count]; } fn_blocks[fn_blocks_count];
+//! }
+//!
+//! var_info {
+//!     u2 name_length;
+//!     u1 name[name_length];
+//!     u1 kind;             // 0: int, 1: double, 2: char, 3: other
+//!     u4 offset;
+//!     u4 slots;
+//!     u4 first_line;
+//!     u4 last_line;
+//! }
+//! ```
+//!
+//! Snapshots of running programs are big endian too. Counts take four bytes
+//! here, as stacks and heaps grow large, and function indices and the slots
+//! of globals are stored plus one, leaving 0 for start code and for before
+//! `main` is called.
+//!
+//! ```text
+//! c0_snapshot {
+//!     u4 magic;            // 0x43305353
+//!     u8 steps;
+//!     u4 globals;
+//!     u1 has_rng;          // 1 if the machine provides `rand`
+//!     u8 rng;
+//!     u4 stack_count;
+//!     u4 stack[stack_count];
+//!     u4 heap_count;
+//!     u4 heap[heap_count];
+//!     u4 allocs_count;
+//!     { u4 start; u4 length; } allocs[allocs_count];
+//!     u4 frames_count;
+//!     { u2 function; u4 base; u4 ip; } frames[frames_count];
+//! }
+//! ```
+
+use crate::vm::{SavedFrame, Snapshot};
+use crate::{Constant, DebugInfo, FnInfo, Inst, StartCodeInfo, VarInfo, VarKind, O0};
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, Read, Write};
+
+pub const MAGIC: u32 = 0x43303A29;
+
+pub const DEBUG_MAGIC: u32 = 0x43304447;
+
+pub const SNAPSHOT_MAGIC: u32 = 0x43305353;
+
+/// The only version of the format there is
+pub const VERSION: u32 = 1;
+
+/// Byte order of numbers in a binary file
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Endian {
+    Big,
+    Little,
+}
+
+/// An error found while reading a binary file
+#[derive(Debug)]
+pub enum BinError {
+    BadMagic(u32),
+    BadVersion(u32),
+    BadConstantType(u8),
+    BadOpcode(u8),
+    Io(io::Error),
+}
+
+impl Display for BinError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        use BinError::*;
+        match self {
+            BadMagic(m) => write!(f, "Not an O0 file: magic number is {:#010x}", m),
+            BadVersion(v) => write!(f, "Unsupported O0 version {}", v),
+            BadConstantType(t) => write!(f, "Unknown constant type {:#04x}", t),
+            BadOpcode(op) => write!(f, "Unknown opcode {:#04x}", op),
+            Io(e) => write!(f, "IO error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for BinError {}
+
+impl From<io::Error> for BinError {
+    fn from(e: io::Error) -> Self {
+        BinError::Io(e)
+    }
+}
+
+/// Write `o0` as a binary file in its byte order
+pub fn write(o0: &O0, w: &mut impl Write) -> io::Result<()> {
+    o0.write_to(w, o0.endian)
+}
+
+/// Read a binary file of either byte order
+pub fn read(r: &mut impl Read) -> Result<O0, BinError> {
+    let mut magic = [0; 4];
+    r.read_exact(&mut magic)?;
+    let e = if u32::from_be_bytes(magic) == MAGIC {
+        Endian::Big
+    } else if u32::from_le_bytes(magic) == MAGIC {
+        Endian::Little
+    } else {
+        return Err(BinError::BadMagic(u32::from_be_bytes(magic)));
+    };
+    let mut r = Reader { r, e };
+
+    let version = r.u32()?;
+    if version != VERSION {
+        return Err(BinError::BadVersion(version));
+    }
+    let constants = r.many(Reader::constant)?;
+    let start_code = StartCodeInfo {
+        ins: r.many(Reader::inst)?,
+    };
+    let functions = r.many(|r| {
+        Ok(FnInfo {
+            name_idx: r.u16()?,
+            param_siz: r.u16()?,
+            lvl: r.u16()?,
+            ins: r.many(Reader::inst)?,
+        })
+    })?;
+
+    Ok(O0 {
+        version,
+        constants,
+        start_code,
+        functions,
+        endian: e,
+        debug: None,
+        relocs: vec![],
+        host_fns: vec![],
+    })
+}
+
+/// Bytes `inst` takes in a binary file
+pub fn inst_size(inst: &Inst) -> usize {
+    use Inst::*;
+    1 + match inst {
+        CPush(_) => 1,
+        IPush(_) | PopN(_) | SNew(_) => 4,
+        LoadA(..) => 6,
+        LoadC(_) | Jmp(_) | JE(_) | JNe(_) | JL(_) | JGe(_) | JG(_) | JLe(_) | Call(_) => 2,
+        _ => 0,
+    }
+}
+
+/// Bytes `c` takes in the constant table of a binary file
+pub fn constant_size(c: &Constant) -> usize {
+    1 + match c {
+        Constant::String(s) => 2 + s.len(),
+        Constant::Number(_) => 4,
+        Constant::Float(_) => 8,
+    }
+}
+
+/// Bytes of a function's header and instruction count in a binary file
+pub const FN_HEADER_SIZE: usize = 8;
+
+/// Write the debug info of a binary
+pub fn write_debug(debug: &DebugInfo, w: &mut impl Write) -> io::Result<()> {
+    let e = Endian::Big;
+    let lines = |lines: &Vec<Option<u32>>| -> Vec<u32> {
+        lines.iter().map(|l| l.map_or(0, |l| l + 1)).collect()
+    };
+    DEBUG_MAGIC.write_to(w, e)?;
+    debug.source.as_bytes().to_vec().write_to(w, e)?;
+    lines(&debug.start_lines).write_to(w, e)?;
+    let fn_lines: Vec<_> = debug.fn_lines.iter().map(lines).collect();
+    fn_lines.write_to(w, e)?;
+    debug.globals.write_to(w, e)?;
+    debug.fn_vars.write_to(w, e)?;
+    debug.fn_blocks.write_to(w, e)
+}
+
+/// Read the debug info written by [`write_debug`]
+pub fn read_debug(r: &mut impl Read) -> Result<DebugInfo, BinError> {
+    let mut r = Reader { r, e: Endian::Big };
+    let magic = r.u32()?;
+    if magic != DEBUG_MAGIC {
+        return Err(BinError::BadMagic(magic));
+    }
+    let lines = |r: &mut Reader<_>| -> Result<Vec<Option<u32>>, BinError> {
+        Ok(r.many(Reader::u32)?
+            .into_iter()
+            .map(|l| l.checked_sub(1))
+            .collect())
+    };
+    let source = r.many(Reader::u8)?;
+    let start_lines = lines(&mut r)?;
+    let fn_lines = r.many(lines)?;
+    // * Files written before variables were recorded end here
+    let globals = match r.many(Reader::var) {
+        Err(BinError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof => vec![],
+        globals => globals?,
+    };
+    let fn_vars = match r.many(|r| r.many(Reader::var)) {
+        Err(BinError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof => vec![],
+        fn_vars => fn_vars?,
+    };
+    let fn_blocks = match r.many(|r| r.many(|r| Ok((r.u32()?, r.u32()?)))) {
+        Err(BinError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof => vec![],
+        fn_blocks => fn_blocks?,
+    };
+    Ok(DebugInfo {
+        source: String::from_utf8_lossy(&source).into_owned(),
+        start_lines,
+        fn_lines,
+        globals,
+        fn_vars,
+        fn_blocks,
+    })
+}
+
+/// Write a snapshot of a running program
+pub fn write_snapshot(snapshot: &Snapshot, w: &mut impl Write) -> io::Result<()> {
+    let e = Endian::Big;
+    let slots = |w: &mut _, slots: &Vec<u32>| -> io::Result<()> {
+        (slots.len() as u32).write_to(w, e)?;
+        slots.iter().try_for_each(|s| s.write_to(w, e))
+    };
+    SNAPSHOT_MAGIC.write_to(w, e)?;
+    snapshot.steps.write_to(w, e)?;
+    (snapshot.globals.map_or(0, |g| g + 1) as u32).write_to(w, e)?;
+    (snapshot.rng.is_some() as u8).write_to(w, e)?;
+    snapshot.rng.unwrap_or(0).write_to(w, e)?;
+    slots(w, &snapshot.stack)?;
+    slots(w, &snapshot.heap)?;
+    (snapshot.allocs.len() as u32).write_to(w, e)?;
+    for &(start, len) in &snapshot.allocs {
+        (start as u32).write_to(w, e)?;
+        (len as u32).write_to(w, e)?;
+    }
+    (snapshot.frames.len() as u32).write_to(w, e)?;
+    for frame in &snapshot.frames {
+        frame.func.map_or(0, |f| f + 1).write_to(w, e)?;
+        (frame.base as u32).write_to(w, e)?;
+        (frame.ip as u32).write_to(w, e)?;
+    }
+    Ok(())
+}
+
+/// Read the snapshot written by [`write_snapshot`]
+pub fn read_snapshot(r: &mut impl Read) -> Result<Snapshot, BinError> {
+    let mut r = Reader { r, e: Endian::Big };
+    let magic = r.u32()?;
+    if magic != SNAPSHOT_MAGIC {
+        return Err(BinError::BadMagic(magic));
+    }
+    let steps = r.u64()?;
+    let globals = (r.u32()? as usize).checked_sub(1);
+    let has_rng = r.u8()? != 0;
+    let rng = Some(r.u64()?).filter(|_| has_rng);
+    let stack = r.many32(Reader::u32)?;
+    let heap = r.many32(Reader::u32)?;
+    let allocs = r.many32(|r| Ok((r.u32()? as usize, r.u32()? as usize)))?;
+    let frames = r.many32(|r| {
+        Ok(SavedFrame {
+            func: r.u16()?.checked_sub(1),
+            base: r.u32()? as usize,
+            ip: r.u32()? as usize,
+        })
+    })?;
+    Ok(Snapshot {
+        stack,
+        heap,
+        allocs,
+        frames,
+        globals,
+        steps,
+        rng,
+    })
+}
+
+trait Writable {
+    fn write_to(&self, w: &mut impl Write, e: Endian) -> io::Result<()>;
+}
+
+macro_rules! bytes {
+    ($val:expr, $e:expr) => {
+        match $e {
+            Endian::Big => $val.to_be_bytes(),
+            Endian::Little => $val.to_le_bytes(),
+        }
+    };
+}
+
+impl Writable for O0 {
+    fn write_to(&self, w: &mut impl Write, e: Endian) -> io::Result<()> {
+        MAGIC.write_to(w, e)?;
+        self.version.write_to(w, e)?;
+        self.constants.write_to(w, e)?;
+        self.start_code.ins.write_to(w, e)?;
+        self.functions.write_to(w, e)
+    }
+}
+
+impl Writable for Constant {
+    fn write_to(&self, w: &mut impl Write, e: Endian) -> io::Result<()> {
+        match self {
+            Constant::String(s) => {
+                0x00u8.write_to(w, e)?;
+                s.write_to(w, e)
+            }
+            Constant::Number(n) => {
+                0x01u8.write_to(w, e)?;
+                n.write_to(w, e)
+            }
+            Constant::Float(f) => {
+                0x02u8.write_to(w, e)?;
+                f.write_to(w, e)
+            }
+        }
+    }
+}
+
+impl Writable for FnInfo {
+    fn write_to(&self, w: &mut impl Write, e: Endian) -> io::Result<()> {
+        self.name_idx.write_to(w, e)?;
+        self.param_siz.write_to(w, e)?;
+        self.lvl.write_to(w, e)?;
+        self.ins.write_to(w, e)
+    }
+}
+
+impl Writable for VarInfo {
+    fn write_to(&self, w: &mut impl Write, e: Endian) -> io::Result<()> {
+        self.name.as_bytes().to_vec().write_to(w, e)?;
+        let kind: u8 = match self.kind {
+            VarKind::Int => 0,
+            VarKind::Double => 1,
+            VarKind::Char => 2,
+            VarKind::Other => 3,
+        };
+        kind.write_to(w, e)?;
+        self.offset.write_to(w, e)?;
+        self.slots.write_to(w, e)?;
+        self.lines.0.write_to(w, e)?;
+        self.lines.1.write_to(w, e)
+    }
+}
+
+impl Writable for (u32, u32) {
+    fn write_to(&self, w: &mut impl Write, e: Endian) -> io::Result<()> {
+        self.0.write_to(w, e)?;
+        self.1.write_to(w, e)
+    }
+}
+
+impl Writable for Inst {
+    fn write_to(&self, w: &mut impl Write, e: Endian) -> io::Result<()> {
+        w.write_all(&[self.opcode()])?;
+        use Inst::*;
+        match self {
+            CPush(c) => c.write_to(w, e),
+            IPush(i) => i.write_to(w, e),
+            PopN(n) => n.write_to(w, e),
+
+            SNew(s) => s.write_to(w, e),
+            LoadC(c) => c.write_to(w, e),
+            LoadA(a, i) => {
+                a.write_to(w, e)?;
+                i.write_to(w, e)
+            }
+
+            Jmp(c) | JE(c) | JNe(c) | JL(c) | JGe(c) | JG(c) | JLe(c) | Call(c) => c.write_to(w, e),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<T> Writable for Vec<T>
+where
+    T: Writable,
+{
+    fn write_to(&self, w: &mut impl Write, e: Endian) -> io::Result<()> {
+        (self.len() as u16).write_to(w, e)?;
+        for i in self {
+            i.write_to(w, e)?;
+        }
+        Ok(())
+    }
+}
+
+impl Writable for u8 {
+    #[inline(always)]
+    fn write_to(&self, w: &mut impl Write, _e: Endian) -> io::Result<()> {
+        w.write_all(&[*self])
+    }
+}
+
+macro_rules! writable_number {
+    ($($t:ty),*) => {
+        $(impl Writable for $t {
+            #[inline(always)]
+            fn write_to(&self, w: &mut impl Write, e: Endian) -> io::Result<()> {
+                w.write_all(&bytes!(self, e))
+            }
+        })*
+    };
+}
+
+writable_number!(u16, u32, i32, u64, f64);
+
+struct Reader<'a, R> {
+    r: &'a mut R,
+    e: Endian,
+}
+
+macro_rules! read_number {
+    ($($name:ident: $t:ty),*) => {
+        $(fn $name(&mut self) -> Result<$t, BinError> {
+            let mut buf = [0; std::mem::size_of::<$t>()];
+            self.r.read_exact(&mut buf)?;
+            Ok(match self.e {
+                Endian::Big => <$t>::from_be_bytes(buf),
+                Endian::Little => <$t>::from_le_bytes(buf),
+            })
+        })*
+    };
+}
+
+impl<R: Read> Reader<'_, R> {
+    read_number!(u8: u8, u16: u16, u32: u32, i32: i32, u64: u64, f64: f64);
+
+    /// Read a count, then that many items
+    fn many<T>(
+        &mut self,
+        mut item: impl FnMut(&mut Self) -> Result<T, BinError>,
+    ) -> Result<Vec<T>, BinError> {
+        let len = self.u16()?;
+        (0..len).map(|_| item(self)).collect()
+    }
+
+    /// Read a four byte count, then that many items
+    fn many32<T>(
+        &mut self,
+        mut item: impl FnMut(&mut Self) -> Result<T, BinError>,
+    ) -> Result<Vec<T>, BinError> {
+        let len = self.u32()?;
+        (0..len).map(|_| item(self)).collect()
+    }
+
+    fn var(&mut self) -> Result<VarInfo, BinError> {
+        let name = self.many(Reader::u8)?;
+        let kind = match self.u8()? {
+            0 => VarKind::Int,
+            1 => VarKind::Double,
+            2 => VarKind::Char,
+            _ => VarKind::Other,
+        };
+        Ok(VarInfo {
+            name: String::from_utf8_lossy(&name).into_owned(),
+            kind,
+            offset: self.u32()?,
+            slots: self.u32()?,
+            lines: (self.u32()?, self.u32()?),
+        })
+    }
+
+    fn constant(&mut self) -> Result<Constant, BinError> {
+        match self.u8()? {
+            0x00 => Ok(Constant::String(self.many(Reader::u8)?)),
+            0x01 => Ok(Constant::Number(self.u32()?)),
+            0x02 => Ok(Constant::Float(self.f64()?)),
+            t => Err(BinError::BadConstantType(t)),
+        }
+    }
+
+    fn inst(&mut self) -> Result<Inst, BinError> {
+        use Inst::*;
+        Ok(match self.u8()? {
+            0x00 => Nop,
+            0x01 => CPush(self.u8()?),
+            0x02 => IPush(self.i32()?),
+            0x04 => Pop1,
+            0x05 => Pop2,
+            0x06 => PopN(self.u32()?),
+            0x07 => Dup,
+            0x08 => Dup2,
+            0x09 => LoadC(self.u16()?),
+            0x0a => LoadA(self.u16()?, self.i32()?),
+            0x0b => New,
+            0x0c => SNew(self.u32()?),
+            0x10 => ILoad,
+            0x11 => DLoad,
+            0x12 => ALoad,
+            0x18 => IALoad,
+            0x19 => DALoad,
+            0x1a => AALoad,
+            0x20 => IStore,
+            0x21 => DStore,
+            0x22 => AStore,
+            0x28 => IAStore,
+            0x29 => DAStore,
+            0x2a => AAStore,
+            0x30 => IAdd,
+            0x31 => DAdd,
+            0x34 => ISub,
+            0x35 => DSub,
+            0x38 => IMul,
+            0x39 => DMul,
+            0x3c => IDiv,
+            0x3d => DDiv,
+            0x40 => INeg,
+            0x41 => DNeg,
+            0x44 => ICmp,
+            0x45 => DCmp,
+            0x60 => I2D,
+            0x61 => D2I,
+            0x62 => I2C,
+            0x70 => Jmp(self.u16()?),
+            0x71 => JE(self.u16()?),
+            0x72 => JNe(self.u16()?),
+            0x73 => JL(self.u16()?),
+            0x74 => JGe(self.u16()?),
+            0x75 => JG(self.u16()?),
+            0x76 => JLe(self.u16()?),
+            0x80 => Call(self.u16()?),
+            0x88 => Ret,
+            0x89 => IRet,
+            0x8a => DRet,
+            0x8b => ARet,
+            0xa0 => IPrint,
+            0xa1 => DPrint,
+            0xa2 => CPrint,
+            0xa3 => SPrint,
+            0xaf => PrintLn,
+            0xb0 => IScan,
+            0xb1 => DScan,
+            0xb2 => CScan,
+            op => return Err(BinError::BadOpcode(op)),
+        })
+    }
+}
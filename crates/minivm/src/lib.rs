@@ -1,3 +1,7 @@
+pub mod binfmt;
+pub use binfmt::Endian;
 mod s0;
 pub use s0::*;
+pub mod value;
+pub use value::Value;
 pub mod vm;
@@ -1,14 +1,8 @@
+use crate::binfmt::{self, BinError, Endian};
+use crate::value::Kind;
 use std::io::{Read, Write};
 pub mod out;
 
-trait Readable {
-    fn read_from(&self, w: &mut impl Read) -> std::io::Result<()>;
-}
-
-trait Writable {
-    fn write_to(&self, w: &mut impl Write) -> std::io::Result<()>;
-}
-
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum Inst {
     /// No-op
@@ -96,7 +90,7 @@ pub enum Inst {
     DNeg,
     /// i32 - i32 -> i32 [+1, 0, -1]
     ICmp,
-    /// f64 - f64 -> i32 [+1, 0, -1]
+    /// f64 - f64 -> i32 [+1, 0, -1], 2 if unordered
     DCmp,
     /// i32 -> f64
     I2D,
@@ -155,6 +149,9 @@ pub enum Inst {
     _Gte,
     _Lte,
     _Neq,
+    /// Call of a function known by name, as symbol #n of the compiler's
+    /// table. Relocation turns it into `Call`.
+    _Call(u16),
 }
 
 impl Inst {
@@ -225,38 +222,7 @@ impl Inst {
     }
 }
 
-impl Writable for Inst {
-    fn write_to(&self, w: &mut impl Write) -> std::io::Result<()> {
-        w.write_all(&self.opcode().to_be_bytes())?;
-        use Inst::*;
-        match self {
-            CPush(c) => c.write_to(w),
-            IPush(i) => i.write_to(w),
-            PopN(n) => n.write_to(w),
-
-            SNew(s) => s.write_to(w),
-            LoadC(c) => c.write_to(w),
-            LoadA(a, i) => {
-                a.write_to(w)?;
-                i.write_to(w)
-            }
-
-            Jmp(c) => c.write_to(w),
-            JE(c) => c.write_to(w),
-            JNe(c) => c.write_to(w),
-            JL(c) => c.write_to(w),
-            JGe(c) => c.write_to(w),
-            JG(c) => c.write_to(w),
-            JLe(c) => c.write_to(w),
-            Call(c) => c.write_to(w),
-            _ => Ok(()),
-        }
-    }
-}
-
-const MAGIC: u32 = 0x43303A29;
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct FnInfo {
     pub name_idx: u16,
     pub param_siz: u16,
@@ -264,128 +230,149 @@ pub struct FnInfo {
     pub ins: Vec<Inst>,
 }
 
-impl Writable for FnInfo {
-    fn write_to(&self, w: &mut impl Write) -> std::io::Result<()> {
-        self.name_idx.write_to(w)?;
-        self.param_siz.write_to(w)?;
-        self.lvl.write_to(w)?;
-        self.ins.write_to(w)
-    }
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct StartCodeInfo {
     pub ins: Vec<Inst>,
 }
 
-impl Writable for StartCodeInfo {
-    fn write_to(&self, w: &mut impl Write) -> std::io::Result<()> {
-        self.ins.write_to(w)
-    }
-}
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Constant {
     Number(u32),
     Float(f64),
     String(Vec<u8>),
 }
 
-impl Writable for Constant {
-    fn write_to(&self, w: &mut impl Write) -> std::io::Result<()> {
-        match self {
-            Constant::Number(n) => {
-                0x01u8.write_to(w)?;
-                n.write_to(w)
-            }
-
-            Constant::Float(f) => {
-                0x02u8.write_to(w)?;
-                f.write_to(w)
-            }
-
-            Constant::String(s) => {
-                0x00u8.write_to(w)?;
-                s.write_to(w)
-            }
-        }
-    }
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct O0 {
     pub version: u32,
     pub constants: Vec<Constant>,
     pub start_code: StartCodeInfo,
     pub functions: Vec<FnInfo>,
+    /// Byte order used by `write_binary`. The standard VM reads big endian.
+    pub endian: Endian,
+    /// Where instructions came from, if asked for. Not part of the binary.
+    pub debug: Option<DebugInfo>,
+    /// Every call, by the name of the function called, so calls can be
+    /// pointed at other functions after compiling. Not part of the binary.
+    pub relocs: Vec<Reloc>,
+    /// Functions the host provides that the program calls, by index of the
+    /// stub standing for each. Not part of the binary.
+    pub host_fns: Vec<(u16, HostSig)>,
 }
 
-impl Writable for O0 {
-    fn write_to(&self, w: &mut impl Write) -> std::io::Result<()> {
-        MAGIC.write_to(w)?;
-        w.write_all(&self.version.to_be_bytes())?;
-        self.constants.write_to(w)?;
-        self.start_code.write_to(w)?;
-        self.functions.write_to(w)
-    }
+/// A `Call` instruction and the function it calls
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reloc {
+    /// Function the call is in, or `None` for start code
+    pub func: Option<u16>,
+    /// Index of the instruction
+    pub idx: usize,
+    /// Name of the function called
+    pub symbol: String,
 }
 
-impl O0 {
-    pub fn write_binary(&self, w: &mut impl Write) -> std::io::Result<()> {
-        self.write_to(w)
-    }
+/// The signature of a function the host provides instead of the program,
+/// which [`crate::vm::MiniVM::register_host_fn`] gives a body. Parameters
+/// and the return value are `Int`, `Double` or `Char`; a return value may be
+/// `Void` too.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostSig {
+    pub name: String,
+    pub params: Vec<Kind>,
+    pub ret: Kind,
 }
 
-impl Writable for u8 {
-    #[inline(always)]
-    fn write_to(&self, w: &mut impl Write) -> std::result::Result<(), std::io::Error> {
-        w.write_all(&[*self])
+impl HostSig {
+    /// The signature of `f`, to be called `name`
+    pub fn of<Args, F: crate::vm::HostFunction<Args>>(name: &str, _f: &F) -> HostSig {
+        HostSig {
+            name: name.into(),
+            params: F::params(),
+            ret: F::ret(),
+        }
+    }
+
+    /// Stack slots the parameters take
+    pub fn param_slots(&self) -> usize {
+        self.params.iter().map(|&k| kind_slots(k)).sum()
     }
 }
 
-impl Writable for u16 {
-    #[inline(always)]
-    fn write_to(&self, w: &mut impl Write) -> std::result::Result<(), std::io::Error> {
-        w.write_all(&self.to_be_bytes())
+/// Stack slots a value of kind `k` takes
+pub(crate) fn kind_slots(k: Kind) -> usize {
+    match k {
+        Kind::Void => 0,
+        Kind::Double => 2,
+        _ => 1,
     }
 }
 
-impl Writable for u32 {
-    #[inline(always)]
-    fn write_to(&self, w: &mut impl Write) -> std::result::Result<(), std::io::Error> {
-        w.write_all(&self.to_be_bytes())
-    }
+/// Source lines of instructions, for debuggers and disassemblers. Lines are
+/// counted from 0.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DebugInfo {
+    /// Path to the source file, or empty if it has none
+    pub source: String,
+    /// Line of each instruction in start code
+    pub start_lines: Vec<Option<u32>>,
+    /// Line of each instruction in each function
+    pub fn_lines: Vec<Vec<Option<u32>>>,
+    /// Global variables, which start code keeps in its frame
+    pub globals: Vec<VarInfo>,
+    /// Parameters and local variables of each function
+    pub fn_vars: Vec<Vec<VarInfo>>,
+    /// Basic blocks of each function the compiler kept apart, as the number
+    /// it gave the block and the instruction it starts at, in code order
+    pub fn_blocks: Vec<Vec<(u32, u32)>>,
 }
 
-impl Writable for i32 {
-    #[inline(always)]
-    fn write_to(&self, w: &mut impl Write) -> std::result::Result<(), std::io::Error> {
-        w.write_all(&self.to_be_bytes())
-    }
+/// A variable, and where it lives in the frame of its function
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VarInfo {
+    pub name: String,
+    pub kind: VarKind,
+    /// First slot of the variable in the frame
+    pub offset: u32,
+    pub slots: u32,
+    /// Lines it is in scope on, counted from 0, both included, with
+    /// `u32::MAX` for the end of the file. Variables of blocks apart from
+    /// each other may share slots.
+    pub lines: (u32, u32),
 }
 
-impl Writable for u64 {
-    #[inline(always)]
-    fn write_to(&self, w: &mut impl Write) -> std::result::Result<(), std::io::Error> {
-        w.write_all(&self.to_be_bytes())
-    }
+/// What a variable holds, as far as showing its value goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VarKind {
+    Int,
+    Double,
+    Char,
+    /// Anything else, shown as its slots
+    Other,
 }
 
-impl Writable for f64 {
-    #[inline(always)]
-    fn write_to(&self, w: &mut impl Write) -> std::result::Result<(), std::io::Error> {
-        w.write_all(&self.to_be_bytes())
+impl O0 {
+    pub fn write_binary(&self, w: &mut impl Write) -> std::io::Result<()> {
+        binfmt::write(self, w)
     }
-}
 
-impl<T> Writable for Vec<T>
-where
-    T: Writable,
-{
-    fn write_to(&self, w: &mut impl Write) -> std::io::Result<()> {
-        w.write_all(&(self.len() as u16).to_be_bytes())?;
-        for i in self {
-            i.write_to(w)?;
+    pub fn read_binary(r: &mut impl Read) -> Result<O0, BinError> {
+        binfmt::read(r)
+    }
+
+    /// Point every call to `symbol` at function #`to` instead, returning how
+    /// many calls changed. Only calls listed in [`O0::relocs`] are found.
+    pub fn patch_calls(&mut self, symbol: &str, to: u16) -> usize {
+        let mut patched = 0;
+        for reloc in self.relocs.iter().filter(|r| r.symbol == symbol) {
+            let ins = match reloc.func {
+                Some(f) => &mut self.functions[f as usize].ins,
+                None => &mut self.start_code.ins,
+            };
+            if let Some(Inst::Call(f)) = ins.get_mut(reloc.idx) {
+                *f = to;
+                patched += 1;
+            }
         }
-        Ok(())
+        patched
     }
 }
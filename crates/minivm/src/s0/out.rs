@@ -1,5 +1,5 @@
 use super::*;
-use std::fmt::{self, *};
+use std::fmt::*;
 
 fn fmt_insts(f: &mut Formatter<'_>, inst: &[Inst]) -> Result {
     let iter = inst.iter().zip(0..);
@@ -100,6 +100,7 @@ impl Display for Inst {
             Inst::JG(a) => write!(f, "jg {}", a),
             Inst::JLe(a) => write!(f, "jle {}", a),
             Inst::Call(a) => write!(f, "call {}", a),
+            Inst::_Call(a) => write!(f, "call @{}", a),
             Inst::Ret => write!(f, "ret"),
             Inst::IRet => write!(f, "iret"),
             Inst::DRet => write!(f, "dret"),
@@ -122,7 +123,7 @@ impl Display for Constant {
         match self {
             Constant::Float(i) => write!(f, "D {}", i),
             Constant::String(s) => {
-                let s = String::from_utf8_lossy(&s);
+                let s = String::from_utf8_lossy(s);
                 let s = s.escape_default();
                 write!(f, "S \"{}\"", s)
             }
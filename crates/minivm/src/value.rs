@@ -0,0 +1,449 @@
+//! Values of c0 programs, and what operators do to them.
+//!
+//! Everything that computes with values goes through here: the VM's
+//! arithmetic instructions, constant folding, constant evaluation and the
+//! reference interpreter. So they can't disagree on what `7 / -2` or
+//! `(int)2.9` is.
+//!
+//! Like C, `char`s and `bool`s are promoted to `int` in arithmetic, `int`s to
+//! `unsigned` next to an `unsigned`, and both to `double` next to a `double`.
+//! Integer arithmetic wraps around, unless done with a `checked_` method.
+//! Comparisons and `!` give an `int`, `1` or `0`.
+
+use std::cmp::Ordering;
+use std::fmt::{self, Display, Formatter};
+
+/// A value of any type
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// What a `void` function returns
+    Void,
+    /// An `int`, which takes one 32-bit slot like every integer
+    Int(i32),
+    UInt(u32),
+    Double(f64),
+    Bool(bool),
+    Char(u8),
+    /// An address in VM memory
+    Ref(u32),
+    Struct(Vec<Value>),
+    Array(Vec<Value>),
+}
+
+/// Types a value can be converted to
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Kind {
+    Void,
+    Int,
+    UInt,
+    Double,
+    Bool,
+    Char,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Eq,
+    Ne,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum UnOp {
+    Neg,
+    Pos,
+    Not,
+    BitNot,
+}
+
+/// Why an operator can't be applied
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ValueError {
+    DivideByZero,
+    /// The exact result does not fit in its type. Only `checked_` methods
+    /// report this.
+    Overflow,
+    /// A `void` value is used
+    Void,
+    /// The operator is not defined for the value, like adding structs
+    Unsupported(String),
+}
+
+impl Display for ValueError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ValueError::DivideByZero => write!(f, "Integer division by zero"),
+            ValueError::Overflow => write!(f, "Value does not fit in its type"),
+            ValueError::Void => write!(f, "A void value is used"),
+            ValueError::Unsupported(what) => write!(f, "{} is not supported", what),
+        }
+    }
+}
+
+impl std::error::Error for ValueError {}
+
+pub type ValueResult<T> = Result<T, ValueError>;
+
+/// A value promoted for arithmetic
+#[derive(Debug, Clone, Copy)]
+enum Num {
+    I(i32),
+    U(u32),
+    D(f64),
+}
+
+impl Kind {
+    /// The value variables of this type start with
+    pub fn zero(self) -> Value {
+        match self {
+            Kind::Void => Value::Void,
+            Kind::Int => Value::Int(0),
+            Kind::UInt => Value::UInt(0),
+            Kind::Double => Value::Double(0.0),
+            Kind::Bool => Value::Bool(false),
+            Kind::Char => Value::Char(0),
+        }
+    }
+}
+
+impl Value {
+    fn num(&self, what: &str) -> ValueResult<Num> {
+        match self {
+            Value::Int(i) => Ok(Num::I(*i)),
+            Value::UInt(u) => Ok(Num::U(*u)),
+            Value::Double(d) => Ok(Num::D(*d)),
+            Value::Bool(b) => Ok(Num::I(*b as i32)),
+            Value::Char(c) => Ok(Num::I(*c as i32)),
+            Value::Void => Err(ValueError::Void),
+            other => Err(ValueError::Unsupported(format!(
+                "{} on {}",
+                what,
+                other.kind_name()
+            ))),
+        }
+    }
+
+    fn kind_name(&self) -> &'static str {
+        match self {
+            Value::Void => "void",
+            Value::Int(_) => "int",
+            Value::UInt(_) => "unsigned",
+            Value::Double(_) => "double",
+            Value::Bool(_) => "bool",
+            Value::Char(_) => "char",
+            Value::Ref(_) => "reference",
+            Value::Struct(_) => "struct",
+            Value::Array(_) => "array",
+        }
+    }
+
+    /// Is the value non-zero, as a condition?
+    pub fn is_true(&self) -> ValueResult<bool> {
+        match self {
+            Value::Ref(addr) => Ok(*addr != 0),
+            other => Ok(match other.num("Condition")? {
+                Num::I(i) => i != 0,
+                Num::U(u) => u != 0,
+                Num::D(d) => d != 0.0,
+            }),
+        }
+    }
+
+    /// The value converted to an `int`, as in `(int)val`
+    pub fn as_int(&self) -> ValueResult<i32> {
+        match self.cast(Kind::Int)? {
+            Value::Int(i) => Ok(i),
+            _ => unreachable!(),
+        }
+    }
+
+    /// The value converted to a `double`, as in `(double)val`
+    pub fn as_double(&self) -> ValueResult<f64> {
+        match self.cast(Kind::Double)? {
+            Value::Double(d) => Ok(d),
+            _ => unreachable!(),
+        }
+    }
+
+    /// The value converted to `kind`, as in casts and assignments. `double`s
+    /// are rounded towards zero, and integers are truncated to fit.
+    pub fn cast(&self, kind: Kind) -> ValueResult<Value> {
+        self.cast_impl(kind, false)
+    }
+
+    /// Like [`cast`](Value::cast), but fails if the value does not fit
+    pub fn checked_cast(&self, kind: Kind) -> ValueResult<Value> {
+        self.cast_impl(kind, true)
+    }
+
+    fn cast_impl(&self, kind: Kind, checked: bool) -> ValueResult<Value> {
+        if kind == Kind::Void {
+            return Ok(Value::Void);
+        }
+        let num = self.num("Conversion")?;
+        if kind == Kind::Double {
+            return Ok(Value::Double(match num {
+                Num::I(i) => i as f64,
+                Num::U(u) => u as f64,
+                Num::D(d) => d,
+            }));
+        }
+        if kind == Kind::Bool {
+            return self.is_true().map(Value::Bool);
+        }
+
+        // * Integers go through 64 bits, where every source value fits
+        let wide = match num {
+            Num::I(i) => i as i64,
+            Num::U(u) => u as i64,
+            Num::D(d) if checked && (d.is_nan() || d.abs() >= 1e18) => {
+                return Err(ValueError::Overflow)
+            }
+            Num::D(d) if checked => d.trunc() as i64,
+            // * Saturating like the VM's `d2i`
+            Num::D(d) => d as i32 as i64,
+        };
+        let (val, fits) = match kind {
+            Kind::Int => (Value::Int(wide as i32), wide as i32 as i64 == wide),
+            Kind::UInt => (Value::UInt(wide as u32), wide as u32 as i64 == wide),
+            Kind::Char => (Value::Char(wide as u8), wide as u8 as i64 == wide),
+            Kind::Void | Kind::Double | Kind::Bool => unreachable!(),
+        };
+        if checked && !fits {
+            return Err(ValueError::Overflow);
+        }
+        Ok(val)
+    }
+
+    /// `self op rhs`, wrapping around on overflow
+    pub fn binary(&self, op: BinOp, rhs: &Value) -> ValueResult<Value> {
+        self.binary_impl(op, rhs, false)
+    }
+
+    /// `self op rhs`, failing on overflow
+    pub fn checked_binary(&self, op: BinOp, rhs: &Value) -> ValueResult<Value> {
+        self.binary_impl(op, rhs, true)
+    }
+
+    fn binary_impl(&self, op: BinOp, rhs: &Value, checked: bool) -> ValueResult<Value> {
+        if let Some(ord) = op.ordering() {
+            let res = self.compare(rhs)?;
+            return Ok(Value::Int(ord(res) as i32));
+        }
+
+        let what = || format!("`{}`", op);
+        let wrap = |val: Option<Value>, wrapped: Value| match val {
+            Some(val) => Ok(val),
+            None if checked => Err(ValueError::Overflow),
+            None => Ok(wrapped),
+        };
+        match promote(self.num(&what())?, rhs.num(&what())?) {
+            (Num::D(l), Num::D(r)) => Ok(Value::Double(match op {
+                BinOp::Add => l + r,
+                BinOp::Sub => l - r,
+                BinOp::Mul => l * r,
+                BinOp::Div => l / r,
+                _ => return Err(ValueError::Unsupported(format!("{} on double", what()))),
+            })),
+            (Num::U(l), Num::U(r)) => match op {
+                BinOp::Add => wrap(
+                    l.checked_add(r).map(Value::UInt),
+                    Value::UInt(l.wrapping_add(r)),
+                ),
+                BinOp::Sub => wrap(
+                    l.checked_sub(r).map(Value::UInt),
+                    Value::UInt(l.wrapping_sub(r)),
+                ),
+                BinOp::Mul => wrap(
+                    l.checked_mul(r).map(Value::UInt),
+                    Value::UInt(l.wrapping_mul(r)),
+                ),
+                BinOp::Div if r == 0 => Err(ValueError::DivideByZero),
+                BinOp::Div => Ok(Value::UInt(l / r)),
+                BinOp::BitAnd => Ok(Value::UInt(l & r)),
+                BinOp::BitOr => Ok(Value::UInt(l | r)),
+                BinOp::BitXor => Ok(Value::UInt(l ^ r)),
+                _ => unreachable!(),
+            },
+            (Num::I(l), Num::I(r)) => match op {
+                BinOp::Add => wrap(
+                    l.checked_add(r).map(Value::Int),
+                    Value::Int(l.wrapping_add(r)),
+                ),
+                BinOp::Sub => wrap(
+                    l.checked_sub(r).map(Value::Int),
+                    Value::Int(l.wrapping_sub(r)),
+                ),
+                BinOp::Mul => wrap(
+                    l.checked_mul(r).map(Value::Int),
+                    Value::Int(l.wrapping_mul(r)),
+                ),
+                BinOp::Div if r == 0 => Err(ValueError::DivideByZero),
+                BinOp::Div => wrap(
+                    l.checked_div(r).map(Value::Int),
+                    Value::Int(l.wrapping_div(r)),
+                ),
+                BinOp::BitAnd => Ok(Value::Int(l & r)),
+                BinOp::BitOr => Ok(Value::Int(l | r)),
+                BinOp::BitXor => Ok(Value::Int(l ^ r)),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    /// How `self` compares to `rhs` after promotion. `None` if either is a
+    /// NaN.
+    pub fn compare(&self, rhs: &Value) -> ValueResult<Option<Ordering>> {
+        if let (Value::Ref(l), Value::Ref(r)) = (self, rhs) {
+            return Ok(Some(l.cmp(r)));
+        }
+        Ok(
+            match promote(self.num("Comparison")?, rhs.num("Comparison")?) {
+                (Num::D(l), Num::D(r)) => l.partial_cmp(&r),
+                (Num::U(l), Num::U(r)) => Some(l.cmp(&r)),
+                (Num::I(l), Num::I(r)) => Some(l.cmp(&r)),
+                _ => unreachable!(),
+            },
+        )
+    }
+
+    /// `op self`, wrapping around on overflow
+    pub fn unary(&self, op: UnOp) -> ValueResult<Value> {
+        self.unary_impl(op, false)
+    }
+
+    /// `op self`, failing on overflow
+    pub fn checked_unary(&self, op: UnOp) -> ValueResult<Value> {
+        self.unary_impl(op, true)
+    }
+
+    fn unary_impl(&self, op: UnOp, checked: bool) -> ValueResult<Value> {
+        if op == UnOp::Not {
+            return Ok(Value::Int(!self.is_true()? as i32));
+        }
+        let num = self.num(&format!("`{}`", op))?;
+        Ok(match (op, num) {
+            (UnOp::Pos, Num::I(i)) => Value::Int(i),
+            (UnOp::Pos, Num::U(u)) => Value::UInt(u),
+            (UnOp::Pos, Num::D(d)) => Value::Double(d),
+            (UnOp::Neg, Num::I(i)) => match i.checked_neg() {
+                Some(i) => Value::Int(i),
+                None if checked => return Err(ValueError::Overflow),
+                None => Value::Int(i.wrapping_neg()),
+            },
+            (UnOp::Neg, Num::U(u)) => Value::UInt(u.wrapping_neg()),
+            (UnOp::Neg, Num::D(d)) => Value::Double(-d),
+            (UnOp::BitNot, Num::I(i)) => Value::Int(!i),
+            (UnOp::BitNot, Num::U(u)) => Value::UInt(!u),
+            (UnOp::BitNot, Num::D(_)) => {
+                return Err(ValueError::Unsupported("`~` on double".into()))
+            }
+            (UnOp::Not, _) => unreachable!(),
+        })
+    }
+}
+
+/// Bring both operands to the same type
+fn promote(l: Num, r: Num) -> (Num, Num) {
+    let double = |n| match n {
+        Num::I(i) => i as f64,
+        Num::U(u) => u as f64,
+        Num::D(d) => d,
+    };
+    match (l, r) {
+        (Num::D(_), _) | (_, Num::D(_)) => (Num::D(double(l)), Num::D(double(r))),
+        (Num::U(_), _) | (_, Num::U(_)) => {
+            let unsigned = |n| match n {
+                Num::I(i) => i as u32,
+                Num::U(u) => u,
+                Num::D(_) => unreachable!(),
+            };
+            (Num::U(unsigned(l)), Num::U(unsigned(r)))
+        }
+        _ => (l, r),
+    }
+}
+
+impl BinOp {
+    /// For comparisons, which orderings make it true
+    fn ordering(self) -> Option<fn(Option<Ordering>) -> bool> {
+        Some(match self {
+            BinOp::Lt => |o| o == Some(Ordering::Less),
+            BinOp::Gt => |o| o == Some(Ordering::Greater),
+            BinOp::Le => |o| matches!(o, Some(Ordering::Less | Ordering::Equal)),
+            BinOp::Ge => |o| matches!(o, Some(Ordering::Greater | Ordering::Equal)),
+            BinOp::Eq => |o| o == Some(Ordering::Equal),
+            BinOp::Ne => |o| o != Some(Ordering::Equal),
+            _ => return None,
+        })
+    }
+}
+
+impl Display for BinOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::BitAnd => "&",
+            BinOp::BitOr => "|",
+            BinOp::BitXor => "^",
+            BinOp::Lt => "<",
+            BinOp::Gt => ">",
+            BinOp::Le => "<=",
+            BinOp::Ge => ">=",
+            BinOp::Eq => "==",
+            BinOp::Ne => "!=",
+        })
+    }
+}
+
+impl Display for UnOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            UnOp::Neg => "-",
+            UnOp::Pos => "+",
+            UnOp::Not => "!",
+            UnOp::BitNot => "~",
+        })
+    }
+}
+
+/// Values are shown the way `print` writes them
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Void => Ok(()),
+            Value::Int(i) => write!(f, "{}", i),
+            Value::UInt(u) => write!(f, "{}", u),
+            // * Same as `printf("%f")`, which spells NaN in lowercase
+            Value::Double(d) if d.is_nan() => f.write_str("nan"),
+            Value::Double(d) => write!(f, "{:.6}", d),
+            Value::Bool(b) => write!(f, "{}", *b as i32),
+            Value::Char(c) => write!(f, "{}", *c as char),
+            Value::Ref(addr) => write!(f, "{:#010x}", addr),
+            Value::Struct(vals) | Value::Array(vals) => {
+                f.write_str("{")?;
+                for (idx, val) in vals.iter().enumerate() {
+                    if idx != 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", val)?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
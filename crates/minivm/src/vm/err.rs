@@ -0,0 +1,111 @@
+use super::UbError;
+use crate::value::ValueError;
+use std::fmt::{self, Display, Formatter};
+use std::time::Duration;
+
+pub type VmResult<T> = Result<T, VmError>;
+
+/// An error that stops the virtual machine.
+#[derive(Debug)]
+pub enum VmError {
+    NoMainFunction,
+    MainHasParams(u16),
+    BadFunction(u16),
+    BadConstant(u16),
+    BadAddress(u32),
+    BadInstruction(crate::Inst),
+    InstructionOverflow,
+    StackUnderflow,
+    DivideByZero,
+    /// An operator applied to a value it is not defined for
+    BadValue(ValueError),
+    LimitExceeded(Limit),
+    BadInput(String),
+    UnexpectedEof,
+    /// A snapshot restored that cannot be of the program running
+    BadSnapshot(&'static str),
+    /// A host function registered with another signature than declared
+    HostFnMismatch(String),
+    /// A host function called that is not registered
+    NoHostFn(String),
+    /// A host function that returned an error, and the error
+    HostFnFailed(String, String),
+    /// A check for undefined behavior that failed, see
+    /// [`sanitizer_sigs`](super::sanitizer_sigs)
+    UndefinedBehavior(UbError),
+    Io(std::io::Error),
+}
+
+impl Display for VmError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        use VmError::*;
+        match self {
+            NoMainFunction => write!(f, "Cannot find function `main`"),
+            MainHasParams(n) => write!(f, "`main` should take no parameters, found {} slots", n),
+            BadFunction(idx) => write!(f, "Function #{} does not exist", idx),
+            BadConstant(idx) => write!(f, "Constant #{} does not exist or has wrong type", idx),
+            BadAddress(addr) => write!(f, "Bad memory address {:#010x}", addr),
+            BadInstruction(inst) => write!(f, "Instruction {:?} cannot be executed", inst),
+            InstructionOverflow => write!(f, "Instruction pointer ran past the end of function"),
+            StackUnderflow => write!(f, "Stack underflow"),
+            DivideByZero => write!(f, "Integer division by zero"),
+            BadValue(e) => write!(f, "{}", e),
+            LimitExceeded(limit) => write!(f, "{}", limit),
+            BadInput(s) => write!(f, "Bad input: {:?}", s),
+            UnexpectedEof => write!(f, "Input ended unexpectedly"),
+            BadSnapshot(s) => write!(f, "Bad snapshot: {}", s),
+            HostFnMismatch(name) => {
+                write!(f, "Host function `{}` does not match its declaration", name)
+            }
+            NoHostFn(name) => write!(f, "Host function `{}` is not registered", name),
+            HostFnFailed(name, e) => write!(f, "Host function `{}` failed: {}", name, e),
+            UndefinedBehavior(e) => write!(f, "{}", e),
+            Io(e) => write!(f, "IO error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for VmError {}
+
+/// A resource limit that stopped a program, with the limit it ran into.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Limit {
+    /// Instructions executed
+    Steps(u64),
+    /// 32-bit slots on the stack
+    StackSlots(usize),
+    /// Calls nested in each other
+    CallDepth(usize),
+    /// Bytes allocated with `new`
+    HeapBytes(usize),
+    /// Wall-clock time
+    Time(Duration),
+}
+
+impl Display for Limit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        use Limit::*;
+        match self {
+            Steps(n) => write!(f, "Program did not finish in {} steps", n),
+            StackSlots(n) => write!(f, "Stack overflow: more than {} slots used", n),
+            CallDepth(n) => write!(f, "Stack overflow: more than {} nested calls", n),
+            HeapBytes(n) => write!(f, "Out of memory: more than {} bytes allocated", n),
+            Time(t) => write!(f, "Program did not finish in {:?}", t),
+        }
+    }
+}
+
+impl From<ValueError> for VmError {
+    fn from(e: ValueError) -> Self {
+        match e {
+            ValueError::DivideByZero => VmError::DivideByZero,
+            e => VmError::BadValue(e),
+        }
+    }
+}
+
+impl From<std::io::Error> for VmError {
+    fn from(e: std::io::Error) -> Self {
+        VmError::Io(e)
+    }
+}
@@ -0,0 +1,175 @@
+use super::*;
+use crate::s0::kind_slots;
+
+/// A value passed between programs and host functions: `i32` for `int`,
+/// `f64` for `double` and `u8` for `char`
+pub trait HostValue: Sized {
+    const KIND: Kind;
+    /// Read the value from its stack slots
+    fn from_slots(slots: &[u32]) -> Self;
+    fn push_slots(self, stack: &mut Vec<u32>);
+}
+
+impl HostValue for i32 {
+    const KIND: Kind = Kind::Int;
+    fn from_slots(slots: &[u32]) -> Self {
+        slots[0] as i32
+    }
+    fn push_slots(self, stack: &mut Vec<u32>) {
+        stack.push(self as u32)
+    }
+}
+
+impl HostValue for f64 {
+    const KIND: Kind = Kind::Double;
+    fn from_slots(slots: &[u32]) -> Self {
+        f64::from_bits((slots[0] as u64) << 32 | slots[1] as u64)
+    }
+    fn push_slots(self, stack: &mut Vec<u32>) {
+        let bits = self.to_bits();
+        stack.push((bits >> 32) as u32);
+        stack.push(bits as u32);
+    }
+}
+
+impl HostValue for u8 {
+    const KIND: Kind = Kind::Char;
+    fn from_slots(slots: &[u32]) -> Self {
+        slots[0] as u8
+    }
+    fn push_slots(self, stack: &mut Vec<u32>) {
+        stack.push(self as u32)
+    }
+}
+
+/// What a host function returns: a [`HostValue`], `()` for `void`, or
+/// either in a `Result`, whose error stops the program
+pub trait HostReturn {
+    const KIND: Kind;
+    fn push_slots(self, stack: &mut Vec<u32>) -> Result<(), String>;
+}
+
+impl<T: HostValue> HostReturn for T {
+    const KIND: Kind = T::KIND;
+    fn push_slots(self, stack: &mut Vec<u32>) -> Result<(), String> {
+        HostValue::push_slots(self, stack);
+        Ok(())
+    }
+}
+
+impl HostReturn for () {
+    const KIND: Kind = Kind::Void;
+    fn push_slots(self, _: &mut Vec<u32>) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl<T: HostReturn> HostReturn for Result<T, String> {
+    const KIND: Kind = T::KIND;
+    fn push_slots(self, stack: &mut Vec<u32>) -> Result<(), String> {
+        self?.push_slots(stack)
+    }
+}
+
+/// A Rust closure callable from programs, taking `Args` as a tuple of
+/// [`HostValue`]s. Implemented for closures of up to four parameters.
+pub trait HostFunction<Args> {
+    fn params() -> Vec<Kind>;
+    fn ret() -> Kind;
+    /// Call with the parameters in `args`, pushing the result onto `stack`
+    fn call(&mut self, args: &[u32], stack: &mut Vec<u32>) -> Result<(), String>;
+}
+
+macro_rules! host_function {
+    ($($arg:ident),*) => {
+        impl<F, R, $($arg),*> HostFunction<($($arg,)*)> for F
+        where
+            F: FnMut($($arg),*) -> R,
+            R: HostReturn,
+            $($arg: HostValue),*
+        {
+            fn params() -> Vec<Kind> {
+                vec![$($arg::KIND),*]
+            }
+
+            fn ret() -> Kind {
+                R::KIND
+            }
+
+            #[allow(unused_mut, unused_variables, unused_assignments, non_snake_case)]
+            fn call(&mut self, args: &[u32], stack: &mut Vec<u32>) -> Result<(), String> {
+                let mut rest = args;
+                $(
+                    let (slots, next) = rest.split_at(kind_slots($arg::KIND));
+                    let $arg = $arg::from_slots(slots);
+                    rest = next;
+                )*
+                self($($arg),*).push_slots(stack)
+            }
+        }
+    };
+}
+
+host_function!();
+host_function!(A);
+host_function!(A, B);
+host_function!(A, B, C);
+host_function!(A, B, C, D);
+
+/// A host function, erased to be stored with others. It may not borrow
+/// anything, so the machine holds no borrows longer than before when dropped.
+pub(super) type HostFn = Box<dyn FnMut(&[u32], &mut Vec<u32>) -> Result<(), String>>;
+
+impl<'a> MiniVM<'a> {
+    /// Run `f` whenever the program calls its host function `name`, which
+    /// must have been declared to the program with the signature of `f`, see
+    /// [`HostSig::of`]. Programs that never call it don't need it, and
+    /// registering it does nothing. Arguments and the return value are
+    /// converted from and to stack slots; an `Err` returned stops the
+    /// program. State shared with the host goes in an `Rc<RefCell<_>>` or the
+    /// like, as `f` cannot borrow.
+    pub fn register_host_fn<Args, F>(&mut self, name: &str, f: F) -> VmResult<()>
+    where
+        F: HostFunction<Args> + 'static,
+    {
+        let (idx, sig) = match self.prog.host_fns.iter().find(|(_, sig)| sig.name == name) {
+            Some(found) => found,
+            None => return Ok(()),
+        };
+        if sig.params != F::params() || sig.ret != F::ret() {
+            return Err(VmError::HostFnMismatch(name.into()));
+        }
+        let mut f = f;
+        self.host
+            .insert(*idx, Some(Box::new(move |args, stack| f.call(args, stack))));
+        Ok(())
+    }
+
+    /// Call host function `idx` in place of its stub, if it is one. Returns
+    /// whether it was.
+    pub(super) fn call_host(&mut self, idx: u16) -> VmResult<bool> {
+        if let Some(None) = self.host.get(&idx) {
+            if self.call_check(idx)? || self.call_intrinsic(idx)? {
+                return Ok(true);
+            }
+        }
+        let f = match self.host.get_mut(&idx) {
+            Some(Some(f)) => f,
+            Some(None) => {
+                let name = function_name(self.prog, Some(idx));
+                return Err(VmError::NoHostFn(name));
+            }
+            None => return Ok(false),
+        };
+        let params = self.prog.functions[idx as usize].param_siz as usize;
+        let base = (self.stack.len())
+            .checked_sub(params)
+            .ok_or(VmError::StackUnderflow)?;
+        let args: Vec<u32> = self.stack.drain(base..).collect();
+        if let Err(e) = f(&args, &mut self.stack) {
+            let name = function_name(self.prog, Some(idx));
+            return Err(VmError::HostFnFailed(name, e));
+        }
+        Ok(true)
+    }
+}
@@ -0,0 +1,162 @@
+use super::*;
+use crate::s0::kind_slots;
+
+/// A variable of the program and its value. Variables of kind
+/// [`VarKind::Other`] are shown as an [`Value::Array`] of their slots, each
+/// a [`Value::UInt`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct VarValue {
+    pub name: String,
+    /// `None` while the frame has not allocated it yet
+    pub value: Option<Value>,
+}
+
+/// A call being run and its variables in scope, see
+/// [`MiniVM::read_stack_frame`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameView {
+    /// Index of function, or `None` for start code
+    pub func: Option<u16>,
+    pub name: String,
+    /// Line it is at, counted from 0, if known
+    pub line: Option<u32>,
+    /// Parameters and local variables in scope, in the order declared. Of
+    /// variables of the same name only the innermost is shown.
+    pub vars: Vec<VarValue>,
+}
+
+/// Memory allocated with one `new`, see [`MiniVM::heap_blocks`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapBlock<'v> {
+    /// Address of the first slot
+    pub addr: u32,
+    pub slots: &'v [u32],
+}
+
+impl HeapBlock<'_> {
+    /// The value of kind `kind` starting at slot `offset`, if it fits in the
+    /// block
+    pub fn read(&self, offset: usize, kind: Kind) -> Option<Value> {
+        let end = offset.checked_add(kind_slots(kind))?;
+        decode(self.slots.get(offset..end)?, kind)
+    }
+}
+
+/// The value of kind `kind` held in `slots`, which must be as many as it
+/// takes
+fn decode(slots: &[u32], kind: Kind) -> Option<Value> {
+    let val = match (kind, slots) {
+        (Kind::Void, []) => Value::Void,
+        (Kind::Int, [val]) => Value::Int(*val as i32),
+        (Kind::UInt, [val]) => Value::UInt(*val),
+        (Kind::Bool, [val]) => Value::Bool(*val != 0),
+        (Kind::Char, [val]) => Value::Char(*val as u8),
+        (Kind::Double, [hi, lo]) => Value::Double(f64::from_bits((*hi as u64) << 32 | *lo as u64)),
+        _ => return None,
+    };
+    Some(val)
+}
+
+impl<'a> MiniVM<'a> {
+    /// The value of global variable `name`, which stays readable after the
+    /// program returns. `None` if it was compiled without debug info, has
+    /// no such global or has not started.
+    pub fn read_global(&self, name: &str) -> Option<Value> {
+        let debug = self.prog.debug.as_ref()?;
+        let var = debug.globals.iter().rev().find(|v| v.name == name)?;
+        self.read_var(0, var)
+    }
+
+    /// The call `depth` calls out from the innermost one, which is 0, with
+    /// its variables in scope. Start code is the outermost, holding the
+    /// globals. Without debug info there are no variables or lines.
+    pub fn read_stack_frame(&self, depth: usize) -> Option<FrameView> {
+        let idx = self.frames.len().checked_sub(depth + 1)?;
+        let frame = &self.frames[idx];
+        // * Callers are at the instruction after their call
+        let ip = match depth {
+            0 => frame.ip,
+            _ => frame.ip.saturating_sub(1),
+        };
+        let mut view = FrameView {
+            func: frame.func,
+            name: function_name(self.prog, frame.func),
+            line: None,
+            vars: vec![],
+        };
+        let debug = match &self.prog.debug {
+            Some(debug) => debug,
+            None => return Some(view),
+        };
+        let (lines, vars) = match frame.func {
+            Some(f) => (
+                debug.fn_lines.get(f as usize),
+                debug.fn_vars.get(f as usize),
+            ),
+            None => (Some(&debug.start_lines), Some(&debug.globals)),
+        };
+        view.line = lines.and_then(|lines| lines.get(ip).copied().flatten());
+
+        // * Of variables of the same name, the one declared last is innermost
+        let mut shown: Vec<&VarInfo> = vec![];
+        for var in vars.into_iter().flatten() {
+            let in_scope = (view.line).is_none_or(|l| var.lines.0 <= l && l <= var.lines.1);
+            if !in_scope {
+                continue;
+            }
+            match shown.iter_mut().find(|v| v.name == var.name) {
+                Some(v) if v.lines.0 <= var.lines.0 => *v = var,
+                Some(_) => (),
+                None => shown.push(var),
+            }
+        }
+        view.vars = (shown.into_iter())
+            .map(|var| VarValue {
+                name: var.name.clone(),
+                value: self.read_var(frame.base, var),
+            })
+            .collect();
+        Some(view)
+    }
+
+    /// The value of kind `kind` at address `addr` on the stack or the heap,
+    /// if all of it is allocated
+    pub fn read(&self, addr: u32, kind: Kind) -> Option<Value> {
+        let (mem, idx) = match addr {
+            _ if addr >= HEAP_BASE => (&self.heap, addr - HEAP_BASE),
+            _ if addr >= CONST_BASE => return None,
+            _ => (&self.stack, addr),
+        };
+        let start = idx as usize;
+        decode(mem.get(start..start + kind_slots(kind))?, kind)
+    }
+
+    /// Memory allocated by the program so far, in the order allocated
+    pub fn heap_blocks(&self) -> Vec<HeapBlock<'_>> {
+        (self.allocs.iter())
+            .filter_map(|&(start, len)| {
+                Some(HeapBlock {
+                    addr: HEAP_BASE + start as u32,
+                    slots: self.heap.get(start..start + len)?,
+                })
+            })
+            .collect()
+    }
+
+    /// The value of `var` in the frame at `base`, if it is allocated
+    fn read_var(&self, base: usize, var: &VarInfo) -> Option<Value> {
+        let start = base + var.offset as usize;
+        let slots = self.stack.get(start..start + var.slots as usize)?;
+        let kind = match var.kind {
+            VarKind::Int => Kind::Int,
+            VarKind::Double => Kind::Double,
+            VarKind::Char => Kind::Char,
+            VarKind::Other => {
+                return Some(Value::Array(
+                    slots.iter().map(|s| Value::UInt(*s)).collect(),
+                ))
+            }
+        };
+        decode(slots, kind)
+    }
+}
@@ -0,0 +1,134 @@
+use super::*;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+/// Instructions in a millisecond of the virtual clock
+pub const VIRTUAL_CLOCK_STEPS: u64 = 1000;
+
+/// Where `rand` and `clock` get their values from, see
+/// [`MiniVM::with_intrinsics`]
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct Intrinsics {
+    /// Seed of `rand` until the program calls `srand`, or `None` to seed it
+    /// at random
+    pub seed: Option<u64>,
+    /// Count time in instructions executed, [`VIRTUAL_CLOCK_STEPS`] to the
+    /// millisecond, instead of real time
+    pub virtual_clock: bool,
+}
+
+/// Functions the VM provides itself, declared to programs as `int rand()`,
+/// `void srand(int seed)` and `int clock()`. `rand` gives a number from 0 to
+/// 2147483647; `clock` the milliseconds since the program started.
+pub fn intrinsic_sigs() -> Vec<HostSig> {
+    let sig = |name: &str, params: &[Kind], ret| HostSig {
+        name: name.into(),
+        params: params.to_vec(),
+        ret,
+    };
+    vec![
+        sig("rand", &[], Kind::Int),
+        sig("srand", &[Kind::Int], Kind::Void),
+        sig("clock", &[], Kind::Int),
+    ]
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Intrinsic {
+    Rand,
+    Srand,
+    Clock,
+}
+
+pub(super) struct IntrinsicState {
+    /// Intrinsics by index of their stubs
+    functions: BTreeMap<u16, Intrinsic>,
+    /// What `rand` starts from each run
+    seed: u64,
+    pub(super) rng: u64,
+    virtual_clock: bool,
+    started: Instant,
+}
+
+impl<'a> MiniVM<'a> {
+    /// Provide the functions of [`intrinsic_sigs`] to programs declaring
+    /// them, unless registered with [`MiniVM::register_host_fn`]. With a seed
+    /// and the virtual clock, every run of a program goes the same way.
+    pub fn with_intrinsics(mut self, opts: Intrinsics) -> Self {
+        let functions = (self.prog.host_fns.iter())
+            .filter_map(|(idx, sig)| {
+                let intrinsic = match (sig.name.as_str(), &sig.params[..], sig.ret) {
+                    ("rand", [], Kind::Int) => Intrinsic::Rand,
+                    ("srand", [Kind::Int], Kind::Void) => Intrinsic::Srand,
+                    ("clock", [], Kind::Int) => Intrinsic::Clock,
+                    _ => return None,
+                };
+                Some((*idx, intrinsic))
+            })
+            .collect();
+        let seed = opts
+            .seed
+            .unwrap_or_else(|| RandomState::new().build_hasher().finish());
+        self.intrinsics = Some(IntrinsicState {
+            functions,
+            seed,
+            rng: seed,
+            virtual_clock: opts.virtual_clock,
+            started: Instant::now(),
+        });
+        self
+    }
+
+    /// Start counting time for `clock` over, and `rand` from its seed
+    pub(super) fn reset_intrinsics(&mut self) {
+        if let Some(state) = &mut self.intrinsics {
+            state.started = Instant::now();
+            state.rng = state.seed;
+        }
+    }
+
+    /// Run intrinsic `idx` in place of its stub, if it is one. Returns
+    /// whether it was.
+    pub(super) fn call_intrinsic(&mut self, idx: u16) -> VmResult<bool> {
+        let intrinsic = match &self.intrinsics {
+            Some(state) => state.functions.get(&idx).copied(),
+            None => None,
+        };
+        let intrinsic = match intrinsic {
+            Some(intrinsic) => intrinsic,
+            None => return Ok(false),
+        };
+        let seed = match intrinsic {
+            Intrinsic::Srand => Some(self.pop()? as i32 as u64),
+            _ => None,
+        };
+        let steps = self.steps;
+        let state = self.intrinsics.as_mut().unwrap();
+        let val = match intrinsic {
+            Intrinsic::Rand => {
+                // * SplitMix64
+                state.rng = state.rng.wrapping_add(0x9e37_79b9_7f4a_7c15);
+                let mut z = state.rng;
+                z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+                z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+                z ^= z >> 31;
+                Some((z >> 33) as u32)
+            }
+            Intrinsic::Srand => {
+                state.rng = seed.unwrap();
+                None
+            }
+            Intrinsic::Clock => {
+                let ms = match state.virtual_clock {
+                    true => steps / VIRTUAL_CLOCK_STEPS,
+                    false => state.started.elapsed().as_millis() as u64,
+                };
+                Some(ms.min(i32::MAX as u64) as u32)
+            }
+        };
+        if let Some(val) = val {
+            self.push(val);
+        }
+        Ok(true)
+    }
+}
@@ -1,153 +1,806 @@
-//! Should be the virtual machine for c0 code.
+//! The virtual machine for c0 code.
 //!
-//! This thing is mostly unimplemented and I dont want to deal with it for now.
+//! Every value lives in 32-bit slots; `double`s take two. All stack frames
+//! share one stack, so a stack address is just an index into it. Constants
+//! and heap memory are mapped above the stack:
+//!
+//! ```plain
+//! 00[stack_index:30]
+//! 01[constant_index:30]
+//! 1[heap_index:31]
+//! ```
+
+mod err;
+mod host;
+mod inspect;
+mod intrinsics;
+mod profile;
+mod sanitize;
+mod snapshot;
+mod trace;
+pub use err::*;
+pub use host::*;
+pub use inspect::*;
+pub use intrinsics::*;
+pub use profile::*;
+pub use sanitize::{sanitizer_sigs, UbError, UbKind};
+pub use snapshot::*;
+pub use trace::*;
 
-#![allow(unreachable_patterns)]
-#![allow(unused_variables)]
+use crate::value::{BinOp, Kind, UnOp, Value};
 use crate::*;
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{BufRead, Write};
+use std::time::{Duration, Instant};
 
-pub struct CallStack<'a> {
-    pub stack: Vec<u32>,
-    pub ip: u16,
-    pub f: &'a Vec<Inst>,
-}
+const CONST_BASE: u32 = 0x4000_0000;
+const HEAP_BASE: u32 = 0x8000_0000;
 
-impl<'a> CallStack<'a> {
-    pub fn of(f: &'a Vec<Inst>) -> CallStack<'a> {
-        CallStack {
-            f,
-            ip: 0,
-            stack: Vec::new(),
-        }
-    }
+/// Default stack limit in slots (4 MiB).
+pub const MAX_STACK_SLOTS: usize = 1 << 20;
+
+/// Default limit of nested calls.
+pub const MAX_CALL_DEPTH: usize = 1 << 20;
+
+/// Heap addresses have 31 bits, so this much can be allocated at most.
+const MAX_HEAP_SLOTS: usize = (1 << 31) - 1;
+
+/// Steps between checks of the clock, which is slow to read.
+const TIME_CHECK_STEPS: u64 = 1 << 12;
+
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    /// Index of function, or `None` for start code
+    func: Option<u16>,
+    /// Stack index of the first slot (param or local) in this frame
+    base: usize,
+    /// Next instruction to execute
+    ip: usize,
+    lvl: u16,
 }
 
 pub struct MiniVM<'a> {
     pub prog: &'a O0,
-    pub call_stack: Vec<CallStack<'a>>,
+    stack: Vec<u32>,
+    heap: Vec<u32>,
+    /// Start and length of each allocation on the heap
+    allocs: Vec<(usize, usize)>,
+    frames: Vec<Frame>,
+    /// Stack slots of global variables, once `main` is called
+    globals: Option<usize>,
+    input: &'a mut dyn BufRead,
+    output: &'a mut dyn Write,
+    max_stack: usize,
+    max_calls: usize,
+    max_heap_slots: usize,
+    max_steps: Option<u64>,
+    steps: u64,
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+    coverage: Option<Coverage>,
+    profile: Option<Profile>,
+    trace: Option<Trace<'a>>,
+    /// States before the last instructions executed, oldest first
+    history: VecDeque<Snapshot>,
+    max_history: usize,
+    /// Host functions by index of their stubs, once registered
+    host: BTreeMap<u16, Option<HostFn>>,
+    intrinsics: Option<IntrinsicState>,
+    /// Checks for undefined behavior, by index of their stubs
+    checks: BTreeMap<u16, sanitize::Check>,
+}
+
+/// Where [`MiniVM::run_steps`] left a program
+#[derive(Debug)]
+pub enum RunState {
+    /// Out of steps, to go on with another call
+    Paused,
+    /// Returned from `main` with this value. Later calls return it again.
+    Finished(i32),
+    /// Stopped by a runtime error, at the instruction that caused it
+    Trapped(VmError),
+}
+
+/// How many times each instruction ran
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Coverage {
+    pub start: Vec<u64>,
+    pub functions: Vec<Vec<u64>>,
+}
+
+impl Coverage {
+    /// Counts for `prog`, all zero
+    pub fn new(prog: &O0) -> Coverage {
+        Coverage {
+            start: vec![0; prog.start_code.ins.len()],
+            functions: (prog.functions.iter())
+                .map(|f| vec![0; f.ins.len()])
+                .collect(),
+        }
+    }
+
+    /// Counts of function `func`, or of start code if it is `None`
+    pub fn counts(&self, func: Option<usize>) -> &[u64] {
+        match func {
+            Some(f) => self.functions.get(f).map_or(&[], |c| &c[..]),
+            None => &self.start,
+        }
+    }
+
+    /// Runs of instructions of `func` that ran at least once
+    pub fn executed_ranges(&self, func: Option<usize>) -> Vec<std::ops::Range<usize>> {
+        let counts = self.counts(func);
+        let mut ranges: Vec<std::ops::Range<usize>> = vec![];
+        for (idx, _) in counts.iter().enumerate().filter(|(_, c)| **c > 0) {
+            match ranges.last_mut() {
+                Some(r) if r.end == idx => r.end += 1,
+                _ => ranges.push(idx..idx + 1),
+            }
+        }
+        ranges
+    }
+
+    /// Add the counts of another run of the same program
+    pub fn merge(&mut self, other: &Coverage) {
+        let add = |a: &mut Vec<u64>, b: &Vec<u64>| {
+            if a.len() < b.len() {
+                a.resize(b.len(), 0);
+            }
+            for (a, b) in a.iter_mut().zip(b) {
+                *a += b;
+            }
+        };
+        add(&mut self.start, &other.start);
+        if self.functions.len() < other.functions.len() {
+            self.functions.resize(other.functions.len(), vec![]);
+        }
+        for (a, b) in self.functions.iter_mut().zip(&other.functions) {
+            add(a, b);
+        }
+    }
+
+    fn hit(&mut self, func: Option<u16>, ip: usize) {
+        let counts = match func {
+            Some(f) => self.functions.get_mut(f as usize),
+            None => Some(&mut self.start),
+        };
+        if let Some(c) = counts.and_then(|c| c.get_mut(ip)) {
+            *c += 1;
+        }
+    }
 }
 
 impl<'a> MiniVM<'a> {
-    pub fn of(prog: &'a O0) -> MiniVM<'a> {
+    pub fn new(prog: &'a O0, input: &'a mut dyn BufRead, output: &'a mut dyn Write) -> MiniVM<'a> {
         MiniVM {
             prog,
-            call_stack: Vec::new(),
+            stack: Vec::new(),
+            heap: Vec::new(),
+            allocs: Vec::new(),
+            frames: Vec::new(),
+            globals: None,
+            input,
+            output,
+            max_stack: MAX_STACK_SLOTS,
+            max_calls: MAX_CALL_DEPTH,
+            max_heap_slots: MAX_HEAP_SLOTS,
+            max_steps: None,
+            steps: 0,
+            timeout: None,
+            deadline: None,
+            coverage: None,
+            profile: None,
+            trace: None,
+            history: VecDeque::new(),
+            max_history: 0,
+            host: prog.host_fns.iter().map(|(idx, _)| (*idx, None)).collect(),
+            intrinsics: None,
+            checks: sanitize::checks_of(prog),
         }
     }
 
-    /*
-        Address space:
-            Stack address:
-                00[stack_index:16][ptr_offset:14]
-            Constant address:
-                01[constant_index:16][ptr_offset:14]
-            Heap address:
-                1[heap_index:16][ptr_offset:15]
-    */
+    /// Count instructions run by each call stack, see [`MiniVM::profile`].
+    pub fn with_profile(mut self) -> Self {
+        self.profile = Some(Profile::new(self.prog));
+        self
+    }
+
+    /// Where time went so far, if asked for with [`MiniVM::with_profile`]
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// Keep the states before the last `steps` instructions, to go back to
+    /// with [`MiniVM::step_back`]. Each step then copies the stack and heap,
+    /// which is slow for large programs.
+    pub fn with_history(mut self, steps: usize) -> Self {
+        self.max_history = steps;
+        self
+    }
+
+    /// Count how many times each instruction runs, see [`MiniVM::coverage`].
+    pub fn with_coverage(mut self) -> Self {
+        self.coverage = Some(Coverage::new(self.prog));
+        self
+    }
+
+    /// Instructions run so far, if asked for with [`MiniVM::with_coverage`]
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Stop the program after executing `steps` instructions.
+    pub fn with_step_limit(mut self, steps: u64) -> Self {
+        self.max_steps = Some(steps);
+        self
+    }
+
+    /// Limit the stack to `slots` 32-bit slots.
+    pub fn with_stack_limit(mut self, slots: usize) -> Self {
+        self.max_stack = slots;
+        self
+    }
+
+    /// Limit calls to `depth` nested in each other.
+    pub fn with_call_depth_limit(mut self, depth: usize) -> Self {
+        self.max_calls = depth;
+        self
+    }
+
+    /// Limit memory allocated with `new` to `bytes`, rounded down to whole
+    /// slots.
+    pub fn with_heap_limit(mut self, bytes: usize) -> Self {
+        self.max_heap_slots = (bytes / 4).min(MAX_HEAP_SLOTS);
+        self
+    }
+
+    /// Stop the program once it has run for `timeout`. The clock is checked
+    /// every few thousand instructions, so it may run slightly longer.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
 
-    pub fn run(&mut self) {
-        self.call_stack
-            .push(CallStack::of(&self.prog.start_code.ins))
+    /// Run start code, then `main`. Returns the value `main` returns, or 0 if
+    /// it returns nothing.
+    pub fn run(&mut self) -> VmResult<i32> {
+        self.start();
+        self.resume()
     }
 
-    fn run_f(&mut self) {
-        let cur_f = self.call_stack.last_mut().unwrap();
+    /// Carry on running the program from where it stopped, such as at a
+    /// limit or at a state put back with [`MiniVM::restore`], and return as
+    /// [`MiniVM::run`] does. The step limit counts steps taken before too,
+    /// while the timeout starts over. Runs the program from the start if it
+    /// has not started.
+    pub fn resume(&mut self) -> VmResult<i32> {
+        self.deadline = self.timeout.map(|t| Instant::now() + t);
         loop {
-            let inst = *cur_f
-                .f
-                .get(cur_f.ip as usize)
-                .expect("Instruction pointer overflow!");
-            cur_f.ip += 1;
-            match inst {
-                Inst::Nop => {}
-                Inst::CPush(a) => cur_f.stack.push(a as u32),
-                Inst::IPush(a) => cur_f.stack.push(a as u32),
-                Inst::Pop1 => {
-                    cur_f.stack.pop();
+            if let Some(code) = self.step()? {
+                return Ok(code);
+            }
+        }
+    }
+
+    /// Execute at most `steps` instructions, as [`MiniVM::resume`] would,
+    /// and say where that left the program. A host running programs on its
+    /// own thread, like a browser, calls this over and over, doing other
+    /// work in between. The timeout is not checked.
+    pub fn run_steps(&mut self, steps: u64) -> RunState {
+        for _ in 0..steps {
+            match self.step() {
+                Ok(None) => (),
+                Ok(Some(code)) => return RunState::Finished(code),
+                Err(e) => return RunState::Trapped(e),
+            }
+        }
+        RunState::Paused
+    }
+
+    /// Execute one instruction, starting the program if it has not started
+    /// and calling `main` after start code. Returns what `main` returned
+    /// instead once it has returned, without executing anything.
+    pub fn step(&mut self) -> VmResult<Option<i32>> {
+        if self.frames.is_empty() {
+            self.start();
+        }
+        if self.globals.is_none() && self.frames[0].ip >= self.prog.start_code.ins.len() {
+            let main = self.find_main()?;
+            let main_info = &self.prog.functions[main as usize];
+            if main_info.param_siz != 0 {
+                return Err(VmError::MainHasParams(main_info.param_siz));
+            }
+            let len = self.stack.len();
+            self.call(main)?;
+            self.globals = Some(len);
+        }
+        if let (Some(globals), 1) = (self.globals, self.frames.len()) {
+            self.output.flush()?;
+            return Ok(Some(match self.stack.len() > globals {
+                true => *self.stack.last().unwrap() as i32,
+                false => 0,
+            }));
+        }
+        if self.max_history > 0 {
+            if self.history.len() == self.max_history {
+                self.history.pop_front();
+            }
+            let snapshot = self.snapshot();
+            self.history.push_back(snapshot);
+        }
+        self.exec()?;
+        Ok(None)
+    }
+
+    /// Clear all state and get ready to run start code, without executing
+    /// anything yet
+    pub fn start(&mut self) {
+        self.stack.clear();
+        self.heap.clear();
+        self.allocs.clear();
+        self.frames.clear();
+        self.globals = None;
+        self.steps = 0;
+        self.history.clear();
+        self.reset_intrinsics();
+
+        self.frames.push(Frame {
+            func: None,
+            base: 0,
+            ip: 0,
+            lvl: 0,
+        });
+        if let Some(p) = &mut self.profile {
+            p.reset_stack();
+            p.enter(None);
+        }
+    }
+
+    fn find_main(&self) -> VmResult<u16> {
+        self.prog
+            .functions
+            .iter()
+            .position(|f| match self.prog.constants.get(f.name_idx as usize) {
+                Some(Constant::String(s)) => s.as_slice() == b"main",
+                _ => false,
+            })
+            .map(|idx| idx as u16)
+            .ok_or(VmError::NoMainFunction)
+    }
+
+    fn code(&self, frame: &Frame) -> &'a [Inst] {
+        let prog = self.prog;
+        match frame.func {
+            Some(f) => &prog.functions[f as usize].ins,
+            None => &prog.start_code.ins,
+        }
+    }
+
+    fn call(&mut self, idx: u16) -> VmResult<()> {
+        if !self.host.is_empty() && self.call_host(idx)? {
+            return Ok(());
+        }
+        let func = self
+            .prog
+            .functions
+            .get(idx as usize)
+            .ok_or(VmError::BadFunction(idx))?;
+        let base = self
+            .stack
+            .len()
+            .checked_sub(func.param_siz as usize)
+            .ok_or(VmError::StackUnderflow)?;
+        // * Start code has a frame too, but is not a call
+        if self.frames.len() > self.max_calls {
+            return Err(VmError::LimitExceeded(Limit::CallDepth(self.max_calls)));
+        }
+        self.frames.push(Frame {
+            func: Some(idx),
+            base,
+            ip: 0,
+            lvl: func.lvl,
+        });
+        if let Some(p) = &mut self.profile {
+            p.enter(Some(idx));
+        }
+        Ok(())
+    }
+
+    /// Leave the current frame, carrying `slots` return value slots over to
+    /// the caller.
+    fn ret(&mut self, slots: usize) -> VmResult<()> {
+        let frame = self.frames.pop().ok_or(VmError::StackUnderflow)?;
+        if let Some(p) = &mut self.profile {
+            p.leave();
+        }
+        let val_start = self
+            .stack
+            .len()
+            .checked_sub(slots)
+            .ok_or(VmError::StackUnderflow)?;
+        if val_start < frame.base {
+            return Err(VmError::StackUnderflow);
+        }
+        self.stack.drain(frame.base..val_start);
+        Ok(())
+    }
+
+    fn push(&mut self, val: u32) {
+        self.stack.push(val)
+    }
+
+    fn pop(&mut self) -> VmResult<u32> {
+        self.stack.pop().ok_or(VmError::StackUnderflow)
+    }
+
+    fn push_f64(&mut self, val: f64) {
+        let bits = val.to_bits();
+        self.push((bits >> 32) as u32);
+        self.push(bits as u32);
+    }
+
+    fn pop_f64(&mut self) -> VmResult<f64> {
+        let lo = self.pop()? as u64;
+        let hi = self.pop()? as u64;
+        Ok(f64::from_bits(hi << 32 | lo))
+    }
+
+    fn pop_n(&mut self, n: usize) -> VmResult<()> {
+        let len = self
+            .stack
+            .len()
+            .checked_sub(n)
+            .ok_or(VmError::StackUnderflow)?;
+        self.stack.truncate(len);
+        Ok(())
+    }
+
+    /// Address of slot `off` in the frame `lvl` levels outside the current one
+    fn frame_addr(&self, lvl: u16, off: i32) -> VmResult<u32> {
+        let cur = self.frames.last().unwrap();
+        let target_lvl = cur
+            .lvl
+            .checked_sub(lvl)
+            .ok_or(VmError::BadInstruction(Inst::LoadA(lvl, off)))?;
+        let frame = self
+            .frames
+            .iter()
+            .rev()
+            .find(|f| f.lvl == target_lvl)
+            .ok_or(VmError::BadInstruction(Inst::LoadA(lvl, off)))?;
+        let addr = frame.base as i64 + off as i64;
+        if addr < 0 || addr >= CONST_BASE as i64 {
+            return Err(VmError::BadAddress(addr as u32));
+        }
+        Ok(addr as u32)
+    }
+
+    fn mem(&mut self, addr: u32) -> VmResult<&mut u32> {
+        let slot = if addr >= HEAP_BASE {
+            self.heap.get_mut((addr - HEAP_BASE) as usize)
+        } else if addr >= CONST_BASE {
+            None
+        } else {
+            self.stack.get_mut(addr as usize)
+        };
+        slot.ok_or(VmError::BadAddress(addr))
+    }
+
+    fn load(&mut self, addr: u32, slots: u32) -> VmResult<()> {
+        for i in 0..slots {
+            let val = *self.mem(addr.wrapping_add(i))?;
+            self.push(val);
+        }
+        Ok(())
+    }
+
+    fn store(&mut self, slots: u32) -> VmResult<()> {
+        let start = self
+            .stack
+            .len()
+            .checked_sub(slots as usize + 1)
+            .ok_or(VmError::StackUnderflow)?;
+        let addr = self.stack[start];
+        for i in 0..slots {
+            let val = self.stack[start + 1 + i as usize];
+            *self.mem(addr.wrapping_add(i))? = val;
+        }
+        self.stack.truncate(start);
+        Ok(())
+    }
+
+    /// Pop an array offset and base address, returning the element address
+    fn array_addr(&mut self, slots: u32) -> VmResult<u32> {
+        let off = self.pop()?;
+        let addr = self.pop()?;
+        Ok(addr.wrapping_add(off.wrapping_mul(slots)))
+    }
+
+    fn int_bin_op(&mut self, op: BinOp) -> VmResult<()> {
+        let rhs = Value::Int(self.pop()? as i32);
+        let lhs = Value::Int(self.pop()? as i32);
+        let res = lhs.binary(op, &rhs)?.as_int()?;
+        self.push(res as u32);
+        Ok(())
+    }
+
+    fn double_bin_op(&mut self, op: BinOp) -> VmResult<()> {
+        let rhs = Value::Double(self.pop_f64()?);
+        let lhs = Value::Double(self.pop_f64()?);
+        let res = lhs.binary(op, &rhs)?.as_double()?;
+        self.push_f64(res);
+        Ok(())
+    }
+
+    /// Push -1, 0 or 1 for `ord`, or 2 for unordered values, as a NaN is
+    /// with anything. Code comparing doubles tests for the results it
+    /// accepts, so every comparison but `!=` with a NaN is false.
+    fn push_ordering(&mut self, ord: Option<std::cmp::Ordering>) {
+        self.push(ord.map_or(2, |ord| ord as i32) as u32);
+    }
+
+    fn jump_if(&mut self, target: u16, cond: impl Fn(i32) -> bool) -> VmResult<()> {
+        let val = self.pop()? as i32;
+        if cond(val) {
+            self.frames.last_mut().unwrap().ip = target as usize;
+        }
+        Ok(())
+    }
+
+    /// Read a whitespace-separated token from input
+    fn read_token(&mut self) -> VmResult<String> {
+        let mut token = Vec::new();
+        loop {
+            let buf = self.input.fill_buf()?;
+            if buf.is_empty() {
+                break;
+            }
+            let skip = if token.is_empty() {
+                buf.iter().take_while(|c| c.is_ascii_whitespace()).count()
+            } else {
+                0
+            };
+            let len = buf[skip..]
+                .iter()
+                .take_while(|c| !c.is_ascii_whitespace())
+                .count();
+            token.extend_from_slice(&buf[skip..skip + len]);
+            let finished = skip + len < buf.len() && !token.is_empty();
+            self.input.consume(skip + len);
+            if finished {
+                break;
+            }
+        }
+        if token.is_empty() {
+            return Err(VmError::UnexpectedEof);
+        }
+        String::from_utf8(token).map_err(|e| VmError::BadInput(format!("{:?}", e.as_bytes())))
+    }
+
+    fn read_char(&mut self) -> VmResult<u8> {
+        loop {
+            let buf = self.input.fill_buf()?;
+            match buf.first() {
+                None => return Err(VmError::UnexpectedEof),
+                Some(c) if c.is_ascii_whitespace() => self.input.consume(1),
+                Some(&c) => {
+                    self.input.consume(1);
+                    return Ok(c);
                 }
-                Inst::Pop2 => cur_f.stack.truncate(cur_f.stack.len() - 2),
-                Inst::PopN(a) => cur_f.stack.truncate(cur_f.stack.len() - a as usize),
-                Inst::Dup => cur_f
-                    .stack
-                    .push(*cur_f.stack.last().expect("Stack is empty")),
-                Inst::Dup2 => {
-                    let last = *cur_f.stack.last().expect("Stack is empty");
-                    let second_last = *cur_f
-                        .stack
-                        .get(cur_f.stack.len() - 2)
-                        .expect("Stack is empty");
-                    cur_f.stack.push(second_last);
-                    cur_f.stack.push(last);
+            }
+        }
+    }
+
+    /// Execute one instruction
+    fn exec(&mut self) -> VmResult<()> {
+        if let Some(max) = self.max_steps {
+            if self.steps >= max {
+                return Err(VmError::LimitExceeded(Limit::Steps(max)));
+            }
+        }
+        if let (Some(deadline), Some(timeout)) = (self.deadline, self.timeout) {
+            if self.steps & (TIME_CHECK_STEPS - 1) == 0 && Instant::now() >= deadline {
+                return Err(VmError::LimitExceeded(Limit::Time(timeout)));
+            }
+        }
+        // * No instruction pushes more than a few slots, so checking here
+        // * keeps overshoot small without a check on every push
+        if self.stack.len() > self.max_stack {
+            return Err(VmError::LimitExceeded(Limit::StackSlots(self.max_stack)));
+        }
+        self.steps += 1;
+
+        let frame = *self.frames.last().unwrap();
+        let inst = *self
+            .code(&frame)
+            .get(frame.ip)
+            .ok_or(VmError::InstructionOverflow)?;
+        if self.trace.is_some() {
+            self.trace_inst(&frame, &inst)?;
+        }
+        self.frames.last_mut().unwrap().ip += 1;
+        if let Some(c) = &mut self.coverage {
+            c.hit(frame.func, frame.ip);
+        }
+        if let Some(p) = &mut self.profile {
+            p.tick();
+        }
+
+        use Inst::*;
+        match inst {
+            Nop => {}
+            CPush(a) => self.push(a as u32),
+            IPush(a) => self.push(a as u32),
+            Pop1 => self.pop_n(1)?,
+            Pop2 => self.pop_n(2)?,
+            PopN(n) => self.pop_n(n as usize)?,
+            Dup => {
+                let val = *self.stack.last().ok_or(VmError::StackUnderflow)?;
+                self.push(val);
+            }
+            Dup2 => {
+                let len = self.stack.len();
+                if len < 2 {
+                    return Err(VmError::StackUnderflow);
+                }
+                self.push(self.stack[len - 2]);
+                self.push(self.stack[len - 1]);
+            }
+            LoadC(idx) => match self.prog.constants.get(idx as usize) {
+                Some(Constant::Number(n)) => self.push(*n),
+                Some(Constant::Float(f)) => self.push_f64(*f),
+                Some(Constant::String(_)) => self.push(CONST_BASE + idx as u32),
+                None => return Err(VmError::BadConstant(idx)),
+            },
+            LoadA(lvl, off) => {
+                let addr = self.frame_addr(lvl, off)?;
+                self.push(addr);
+            }
+            New => {
+                let len = self.pop()? as usize;
+                let addr = HEAP_BASE as usize + self.heap.len();
+                if self.heap.len() + len > self.max_heap_slots {
+                    let bytes = self.max_heap_slots.saturating_mul(4);
+                    return Err(VmError::LimitExceeded(Limit::HeapBytes(bytes)));
+                }
+                self.allocs.push((self.heap.len(), len));
+                self.heap.resize(self.heap.len() + len, 0);
+                self.push(addr as u32);
+            }
+            SNew(n) => {
+                let len = self.stack.len() + n as usize;
+                if len > self.max_stack {
+                    return Err(VmError::LimitExceeded(Limit::StackSlots(self.max_stack)));
                 }
-                Inst::LoadC(a) => {
-                    let const_entry = self
-                        .prog
-                        .constants
-                        .get(a as usize)
-                        .expect("Bad constant entry");
-                    match const_entry {
-                        Constant::Float(f) => {
-                            let f = f.to_bits();
-                            cur_f.stack.push((f & 0xfffffff) as u32);
-                            cur_f.stack.push(((f & (0xfffffff << 8)) >> 8) as u32);
-                        }
-                        Constant::Number(n) => cur_f.stack.push(*n),
-                        Constant::String(s) => todo!(),
+                self.stack.resize(len, 0);
+            }
+
+            ILoad | ALoad => {
+                let addr = self.pop()?;
+                self.load(addr, 1)?;
+            }
+            DLoad => {
+                let addr = self.pop()?;
+                self.load(addr, 2)?;
+            }
+            IALoad | AALoad => {
+                let addr = self.array_addr(1)?;
+                self.load(addr, 1)?;
+            }
+            DALoad => {
+                let addr = self.array_addr(2)?;
+                self.load(addr, 2)?;
+            }
+            IStore | AStore => self.store(1)?,
+            DStore => self.store(2)?,
+            IAStore | AAStore | DAStore => {
+                let slots = if inst == DAStore { 2 } else { 1 };
+                let val_start = self
+                    .stack
+                    .len()
+                    .checked_sub(slots)
+                    .ok_or(VmError::StackUnderflow)?;
+                let val: Vec<_> = self.stack.drain(val_start..).collect();
+                let addr = self.array_addr(slots as u32)?;
+                self.push(addr);
+                self.stack.extend(val);
+                self.store(slots as u32)?;
+            }
+
+            IAdd => self.int_bin_op(BinOp::Add)?,
+            ISub => self.int_bin_op(BinOp::Sub)?,
+            IMul => self.int_bin_op(BinOp::Mul)?,
+            IDiv => self.int_bin_op(BinOp::Div)?,
+            ICmp => {
+                let rhs = Value::Int(self.pop()? as i32);
+                let lhs = Value::Int(self.pop()? as i32);
+                self.push_ordering(lhs.compare(&rhs)?);
+            }
+            DAdd => self.double_bin_op(BinOp::Add)?,
+            DSub => self.double_bin_op(BinOp::Sub)?,
+            DMul => self.double_bin_op(BinOp::Mul)?,
+            DDiv => self.double_bin_op(BinOp::Div)?,
+            DCmp => {
+                let rhs = Value::Double(self.pop_f64()?);
+                let lhs = Value::Double(self.pop_f64()?);
+                self.push_ordering(lhs.compare(&rhs)?);
+            }
+            INeg => {
+                let val = Value::Int(self.pop()? as i32);
+                self.push(val.unary(UnOp::Neg)?.as_int()? as u32);
+            }
+            DNeg => {
+                let val = Value::Double(self.pop_f64()?);
+                self.push_f64(val.unary(UnOp::Neg)?.as_double()?);
+            }
+            I2D => {
+                let val = Value::Int(self.pop()? as i32);
+                self.push_f64(val.as_double()?);
+            }
+            D2I => {
+                let val = Value::Double(self.pop_f64()?);
+                self.push(val.as_int()? as u32);
+            }
+            I2C => {
+                let val = Value::Int(self.pop()? as i32);
+                self.push(val.cast(Kind::Char)?.as_int()? as u32);
+            }
+
+            Jmp(target) => self.frames.last_mut().unwrap().ip = target as usize,
+            JE(target) => self.jump_if(target, |v| v == 0)?,
+            JNe(target) => self.jump_if(target, |v| v != 0)?,
+            JL(target) => self.jump_if(target, |v| v < 0)?,
+            JGe(target) => self.jump_if(target, |v| v >= 0)?,
+            JG(target) => self.jump_if(target, |v| v > 0)?,
+            JLe(target) => self.jump_if(target, |v| v <= 0)?,
+
+            Call(idx) => self.call(idx)?,
+            Ret => self.ret(0)?,
+            IRet | ARet => self.ret(1)?,
+            DRet => self.ret(2)?,
+
+            IPrint => {
+                let val = Value::Int(self.pop()? as i32);
+                write!(self.output, "{}", val)?;
+            }
+            DPrint => {
+                let val = Value::Double(self.pop_f64()?);
+                write!(self.output, "{}", val)?;
+            }
+            CPrint => {
+                let val = self.pop()?;
+                self.output.write_all(&[val as u8])?;
+            }
+            SPrint => {
+                let addr = self.pop()?;
+                let idx = addr.wrapping_sub(CONST_BASE);
+                match self.prog.constants.get(idx as usize) {
+                    Some(Constant::String(s)) if (CONST_BASE..HEAP_BASE).contains(&addr) => {
+                        self.output.write_all(s)?
                     }
+                    _ => return Err(VmError::BadAddress(addr)),
                 }
-                _ => todo!(),
-                Inst::LoadA(a, b) => {}
-                Inst::New => {}
-                Inst::SNew(a) => {}
-                Inst::ILoad => {}
-                Inst::DLoad => {}
-                Inst::ALoad => {}
-                Inst::IALoad => {}
-                Inst::DALoad => {}
-                Inst::AALoad => {}
-                Inst::IStore => {}
-                Inst::DStore => {}
-                Inst::AStore => {}
-                Inst::IAStore => {}
-                Inst::DAStore => {}
-                Inst::AAStore => {}
-                Inst::IAdd => {}
-                Inst::DAdd => {}
-                Inst::ISub => {}
-                Inst::DSub => {}
-                Inst::IMul => {}
-                Inst::DMul => {}
-                Inst::IDiv => {}
-                Inst::DDiv => {}
-                Inst::INeg => {}
-                Inst::DNeg => {}
-                Inst::ICmp => {}
-                Inst::DCmp => {}
-                Inst::I2D => {}
-                Inst::D2I => {}
-                Inst::I2C => {}
-                Inst::Jmp(a) => {}
-                Inst::JE(a) => {}
-                Inst::JNe(a) => {}
-                Inst::JL(a) => {}
-                Inst::JGe(a) => {}
-                Inst::JG(a) => {}
-                Inst::JLe(a) => {}
-                Inst::Call(a) => {}
-                Inst::Ret => {}
-                Inst::IRet => {}
-                Inst::DRet => {}
-                Inst::ARet => {}
-                Inst::IPrint => {}
-                Inst::DPrint => {}
-                Inst::CPrint => {}
-                Inst::SPrint => {}
-                Inst::PrintLn => {}
-                Inst::IScan => {}
-                Inst::DScan => {}
-                Inst::CScan => {}
-                _ => {}
+            }
+            PrintLn => writeln!(self.output)?,
+            IScan => {
+                let token = self.read_token()?;
+                let val: i32 = token.parse().map_err(|_| VmError::BadInput(token))?;
+                self.push(val as u32);
+            }
+            DScan => {
+                let token = self.read_token()?;
+                let val: f64 = token.parse().map_err(|_| VmError::BadInput(token))?;
+                self.push_f64(val);
+            }
+            CScan => {
+                let val = self.read_char()?;
+                self.push(val as u32);
+            }
+
+            _Gt | _Lt | _Eq | _Gte | _Lte | _Neq | _Call(_) => {
+                return Err(VmError::BadInstruction(inst))
             }
         }
+        Ok(())
     }
 }
@@ -0,0 +1,158 @@
+use crate::{Constant, O0};
+use std::collections::HashMap;
+
+/// Instructions run by each call stack, and calls of each function.
+///
+/// Call stacks form a tree, so each distinct stack is counted once however
+/// deep it is, and nothing is hashed per instruction.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    /// Distinct call stacks. The first is the empty stack.
+    nodes: Vec<Node>,
+    children: HashMap<(usize, Option<u16>), usize>,
+    /// Node of each frame on the VM's stack
+    stack: Vec<usize>,
+    calls: Vec<u64>,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    parent: usize,
+    /// Function called, or `None` for start code
+    func: Option<u16>,
+    /// Instructions run with exactly this stack
+    count: u64,
+}
+
+/// Totals of one function over all its calls
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct FunctionProfile {
+    /// Index of the function, or `None` for start code
+    pub func: Option<u16>,
+    pub calls: u64,
+    /// Instructions run in the function itself
+    pub self_insts: u64,
+    /// Instructions run in the function and everything it called
+    pub total_insts: u64,
+}
+
+impl Profile {
+    pub fn new(prog: &O0) -> Profile {
+        Profile {
+            nodes: vec![Node {
+                parent: 0,
+                func: None,
+                count: 0,
+            }],
+            children: HashMap::new(),
+            stack: vec![],
+            calls: vec![0; prog.functions.len()],
+        }
+    }
+
+    pub(super) fn enter(&mut self, func: Option<u16>) {
+        let parent = self.stack.last().copied().unwrap_or(0);
+        let nodes = &mut self.nodes;
+        let node = *self.children.entry((parent, func)).or_insert_with(|| {
+            nodes.push(Node {
+                parent,
+                func,
+                count: 0,
+            });
+            nodes.len() - 1
+        });
+        self.stack.push(node);
+        if let Some(c) = func.and_then(|f| self.calls.get_mut(f as usize)) {
+            *c += 1;
+        }
+    }
+
+    pub(super) fn leave(&mut self) {
+        self.stack.pop();
+    }
+
+    pub(super) fn reset_stack(&mut self) {
+        self.stack.clear();
+    }
+
+    pub(super) fn tick(&mut self) {
+        let node = self.stack.last().copied().unwrap_or(0);
+        self.nodes[node].count += 1;
+    }
+
+    /// Instructions run in total
+    pub fn total(&self) -> u64 {
+        self.nodes.iter().map(|n| n.count).sum()
+    }
+
+    /// Functions in order of the instructions run in themselves, most first
+    pub fn functions(&self) -> Vec<FunctionProfile> {
+        // * (self, total) instructions of each function
+        let mut insts: HashMap<Option<u16>, (u64, u64)> = HashMap::new();
+        for (idx, node) in self.nodes.iter().enumerate().skip(1) {
+            insts.entry(node.func).or_default().0 += node.count;
+            // * Recursive functions count once per stack, not once per frame
+            let mut seen = vec![];
+            let mut n = idx;
+            while n != 0 {
+                let func = self.nodes[n].func;
+                if !seen.contains(&func) {
+                    seen.push(func);
+                    insts.entry(func).or_default().1 += node.count;
+                }
+                n = self.nodes[n].parent;
+            }
+        }
+
+        let mut funcs: Vec<_> = (insts.into_iter())
+            .map(|(func, (self_insts, total_insts))| FunctionProfile {
+                func,
+                calls: func.map_or(1, |f| self.calls[f as usize]),
+                self_insts,
+                total_insts,
+            })
+            .collect();
+        funcs.sort_by(|a, b| (b.self_insts, a.func).cmp(&(a.self_insts, b.func)));
+        funcs
+    }
+
+    /// Stacks in the folded format of flamegraph tools: one line per stack,
+    /// with function names joined by `;` and the instructions it ran
+    pub fn folded(&self, prog: &O0) -> String {
+        let mut lines = vec![];
+        for (idx, node) in self.nodes.iter().enumerate().skip(1) {
+            if node.count == 0 {
+                continue;
+            }
+            let mut names = vec![];
+            let mut n = idx;
+            while n != 0 {
+                let func = self.nodes[n].func;
+                // * Start code is below `main`; only show it when it runs
+                if func.is_some() || n == idx {
+                    names.push(function_name(prog, func));
+                }
+                n = self.nodes[n].parent;
+            }
+            names.reverse();
+            lines.push(format!("{} {}", names.join(";"), node.count));
+        }
+        lines.sort();
+        lines.iter().map(|l| format!("{}\n", l)).collect()
+    }
+}
+
+/// Name of function `func` in `prog`, or `.start` for start code
+pub fn function_name(prog: &O0, func: Option<u16>) -> String {
+    let f = match func {
+        Some(f) => f,
+        None => return ".start".into(),
+    };
+    let name = (prog.functions.get(f as usize)).and_then(|info| {
+        match prog.constants.get(info.name_idx as usize) {
+            Some(Constant::String(s)) => Some(String::from_utf8_lossy(s).into_owned()),
+            _ => None,
+        }
+    });
+    name.unwrap_or_else(|| format!(".F{}", f))
+}
@@ -0,0 +1,162 @@
+use super::*;
+use std::fmt::{self, Display, Formatter};
+
+/// Checks for undefined behavior the compiler calls in code built with
+/// them, declared to the VM as host functions of [`sanitizer_sigs`]. Each
+/// takes the line and column it is at last, counted from 1, or 0 if not
+/// known.
+///
+/// - `int __ub_add(int lhs, int rhs, int line, int col)`, and `__ub_sub`,
+///   `__ub_mul` and `__ub_div` likewise: `lhs op rhs`, unless it is out of
+///   the range of `int` or divides by zero
+/// - `int __ub_neg(int val, int line, int col)`: `-val`, unless out of range
+/// - `int __ub_null(int addr, int line, int col)`: `addr`, unless it is 0.
+///   Code with checks keeps the first slot of start code unused, so no
+///   variable is there.
+/// - `void __ub_uninit(int init, int line, int col)`: nothing, unless
+///   `init` is 0, the shadow slot of a variable never assigned
+pub fn sanitizer_sigs() -> Vec<HostSig> {
+    (CHECKS.iter())
+        .map(|(name, check)| {
+            let params = match check {
+                Check::Arith(_) => 4,
+                _ => 3,
+            };
+            HostSig {
+                name: (*name).into(),
+                params: vec![Kind::Int; params],
+                ret: match check {
+                    Check::Uninit => Kind::Void,
+                    _ => Kind::Int,
+                },
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(super) enum Check {
+    Arith(char),
+    Neg,
+    Null,
+    Uninit,
+}
+
+const CHECKS: [(&str, Check); 7] = [
+    ("__ub_add", Check::Arith('+')),
+    ("__ub_sub", Check::Arith('-')),
+    ("__ub_mul", Check::Arith('*')),
+    ("__ub_div", Check::Arith('/')),
+    ("__ub_neg", Check::Neg),
+    ("__ub_null", Check::Null),
+    ("__ub_uninit", Check::Uninit),
+];
+
+/// Checks among the host functions of `prog`, by index of their stubs
+pub(super) fn checks_of(prog: &O0) -> BTreeMap<u16, Check> {
+    let sigs = sanitizer_sigs();
+    (prog.host_fns.iter())
+        .filter_map(|(idx, sig)| {
+            let found = sigs.iter().position(|s| s == sig)?;
+            Some((*idx, CHECKS[found].1))
+        })
+        .collect()
+}
+
+/// Undefined behavior a program ran into, and where
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct UbError {
+    pub kind: UbKind,
+    /// Line in the source, counted from 1, or 0 if not known
+    pub line: u32,
+    /// Column in the source, counted from 1, or 0 if not known
+    pub col: u32,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum UbKind {
+    /// `lhs op rhs` out of the range of `int`
+    Overflow(i32, char, i32),
+    /// `-val` out of the range of `int`
+    NegOverflow(i32),
+    DivideByZero,
+    NullDeref,
+    /// A local variable read before anything was assigned to it
+    Uninit,
+}
+
+impl Display for UbError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match (self.line, self.col) {
+            (0, _) => write!(f, "undefined behavior: ")?,
+            (line, 0) => write!(f, "undefined behavior at line {}: ", line)?,
+            (line, col) => write!(f, "undefined behavior at {}:{}: ", line, col)?,
+        }
+        match self.kind {
+            UbKind::Overflow(lhs, op, rhs) => write!(
+                f,
+                "signed integer overflow: {} {} {} cannot be represented in type `int`",
+                lhs, op, rhs
+            ),
+            UbKind::NegOverflow(val) => write!(
+                f,
+                "signed integer overflow: -({}) cannot be represented in type `int`",
+                val
+            ),
+            UbKind::DivideByZero => write!(f, "division by zero"),
+            UbKind::NullDeref => write!(f, "null pointer dereference"),
+            UbKind::Uninit => write!(f, "read of uninitialized variable"),
+        }
+    }
+}
+
+impl<'a> MiniVM<'a> {
+    /// Run check `idx` in place of its stub, if it is one. Returns whether
+    /// it was.
+    pub(super) fn call_check(&mut self, idx: u16) -> VmResult<bool> {
+        let check = match self.checks.get(&idx) {
+            Some(check) => *check,
+            None => return Ok(false),
+        };
+        let col = self.pop()?;
+        let line = self.pop()?;
+        let fail = |kind| Err(VmError::UndefinedBehavior(UbError { kind, line, col }));
+        match check {
+            Check::Arith(op) => {
+                let rhs = self.pop()? as i32;
+                let lhs = self.pop()? as i32;
+                let res = match op {
+                    '+' => lhs.checked_add(rhs),
+                    '-' => lhs.checked_sub(rhs),
+                    '*' => lhs.checked_mul(rhs),
+                    _ if rhs == 0 => return fail(UbKind::DivideByZero),
+                    _ => lhs.checked_div(rhs),
+                };
+                match res {
+                    Some(res) => self.push(res as u32),
+                    None => return fail(UbKind::Overflow(lhs, op, rhs)),
+                }
+            }
+            Check::Neg => {
+                let val = self.pop()? as i32;
+                match val.checked_neg() {
+                    Some(res) => self.push(res as u32),
+                    None => return fail(UbKind::NegOverflow(val)),
+                }
+            }
+            Check::Null => {
+                let addr = self.pop()?;
+                if addr == 0 {
+                    return fail(UbKind::NullDeref);
+                }
+                self.push(addr);
+            }
+            Check::Uninit => {
+                if self.pop()? == 0 {
+                    return fail(UbKind::Uninit);
+                }
+            }
+        }
+        Ok(true)
+    }
+}
@@ -0,0 +1,140 @@
+use super::*;
+
+/// The state of a running program, to put back later with
+/// [`MiniVM::restore`]: for checkpointing long runs, or going back to an
+/// earlier point when debugging. [`crate::binfmt::write_snapshot`] saves one
+/// to a file.
+///
+/// Input read and output written so far are not part of it; neither are
+/// limits, coverage or profiles.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    pub stack: Vec<u32>,
+    pub heap: Vec<u32>,
+    /// Start and length of each allocation on the heap
+    pub allocs: Vec<(usize, usize)>,
+    /// Calls being run, outermost first
+    pub frames: Vec<SavedFrame>,
+    /// Stack slots of global variables, once `main` is called
+    pub globals: Option<usize>,
+    /// Instructions executed so far
+    pub steps: u64,
+    /// State of `rand`, if the machine provides it
+    pub rng: Option<u64>,
+}
+
+/// A call being run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SavedFrame {
+    /// Index of function, or `None` for start code
+    pub func: Option<u16>,
+    /// Stack index of the first slot (param or local) in this frame
+    pub base: usize,
+    /// Next instruction to execute
+    pub ip: usize,
+}
+
+impl<'a> MiniVM<'a> {
+    /// Save the state of the program. Taken while the program is stopped
+    /// at a limit, it can be resumed from there.
+    pub fn snapshot(&self) -> Snapshot {
+        let frames = (self.frames.iter())
+            .map(|f| SavedFrame {
+                func: f.func,
+                base: f.base,
+                ip: f.ip,
+            })
+            .collect();
+        Snapshot {
+            stack: self.stack.clone(),
+            heap: self.heap.clone(),
+            allocs: self.allocs.clone(),
+            frames,
+            globals: self.globals,
+            steps: self.steps,
+            rng: self.intrinsics.as_ref().map(|state| state.rng),
+        }
+    }
+
+    /// The innermost call being run, if the program has started
+    pub fn current(&self) -> Option<SavedFrame> {
+        self.frames.last().map(|f| SavedFrame {
+            func: f.func,
+            base: f.base,
+            ip: f.ip,
+        })
+    }
+
+    /// Calls being run, counting start code as one
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Put back a state saved by [`MiniVM::snapshot`] for the same program,
+    /// to carry on with [`MiniVM::resume`]. Fails if the state cannot be of
+    /// this program, leaving the machine as it was.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> VmResult<()> {
+        let mut frames = Vec::with_capacity(snapshot.frames.len());
+        for (i, f) in snapshot.frames.iter().enumerate() {
+            // * Only the first frame runs start code, and only it is not a call
+            let lvl = match f.func {
+                None if i == 0 => 0,
+                Some(idx) if i > 0 => {
+                    (self.prog.functions.get(idx as usize))
+                        .ok_or(VmError::BadFunction(idx))?
+                        .lvl
+                }
+                _ => {
+                    return Err(VmError::BadSnapshot(
+                        "start code runs in the first frame only",
+                    ))
+                }
+            };
+            if f.base > snapshot.stack.len() {
+                return Err(VmError::BadSnapshot("frame above the stack"));
+            }
+            frames.push(Frame {
+                func: f.func,
+                base: f.base,
+                ip: f.ip,
+                lvl,
+            });
+        }
+        if snapshot.globals.is_some_and(|g| g > snapshot.stack.len()) {
+            return Err(VmError::BadSnapshot("globals above the stack"));
+        }
+        if (snapshot.allocs.iter()).any(|&(start, len)| start + len > snapshot.heap.len()) {
+            return Err(VmError::BadSnapshot("allocation outside the heap"));
+        }
+
+        self.stack = snapshot.stack.clone();
+        self.heap = snapshot.heap.clone();
+        self.allocs = snapshot.allocs.clone();
+        self.frames = frames;
+        self.globals = snapshot.globals;
+        self.steps = snapshot.steps;
+        if let (Some(state), Some(rng)) = (&mut self.intrinsics, snapshot.rng) {
+            state.rng = rng;
+        }
+        if let Some(p) = &mut self.profile {
+            p.reset_stack();
+            self.frames.iter().for_each(|f| p.enter(f.func));
+        }
+        Ok(())
+    }
+
+    /// Go back `steps` instructions, as far as the history kept with
+    /// [`MiniVM::with_history`] goes. Returns how many instructions it went
+    /// back. Input read and output written stay as they are.
+    pub fn step_back(&mut self, steps: usize) -> usize {
+        let steps = steps.min(self.history.len());
+        if steps == 0 {
+            return 0;
+        }
+        let at = self.history.len() - steps;
+        let snapshot = self.history.drain(at..).next().unwrap();
+        self.restore(&snapshot)
+            .expect("Snapshots taken of this program restore");
+        steps
+    }
+}
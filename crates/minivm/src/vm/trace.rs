@@ -0,0 +1,167 @@
+use super::*;
+use std::collections::BTreeSet;
+
+/// Which instructions to trace
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum TraceKind {
+    /// Calls and returns
+    Calls,
+    /// Jumps, taken or not
+    Jumps,
+    All,
+}
+
+impl TraceKind {
+    fn includes(self, inst: &Inst) -> bool {
+        use Inst::*;
+        match self {
+            TraceKind::Calls => matches!(inst, Call(_) | Ret | IRet | DRet | ARet),
+            TraceKind::Jumps => {
+                matches!(
+                    inst,
+                    Jmp(_) | JE(_) | JNe(_) | JL(_) | JGe(_) | JG(_) | JLe(_)
+                )
+            }
+            TraceKind::All => true,
+        }
+    }
+}
+
+/// Where and what to trace
+pub(super) struct Trace<'a> {
+    kind: TraceKind,
+    /// Functions traced, `None` standing for start code, or all of them
+    functions: Option<BTreeSet<Option<u16>>>,
+    out: &'a mut dyn Write,
+}
+
+/// What a stack slot, or two, holds
+#[derive(Clone, Copy)]
+enum Slot {
+    Int,
+    Double,
+    Char,
+    Addr,
+}
+
+/// Operands `inst` takes off the stack, the topmost last
+fn operands(inst: &Inst) -> &'static [Slot] {
+    use Inst::*;
+    use Slot::*;
+    match inst {
+        Pop1 | Dup | ILoad | DLoad | ALoad | ARet | SPrint => &[Addr],
+        Pop2 | Dup2 => &[Addr, Addr],
+        New | INeg | I2D | I2C | IRet | IPrint => &[Int],
+        JE(_) | JNe(_) | JL(_) | JGe(_) | JG(_) | JLe(_) => &[Int],
+        IALoad | DALoad | AALoad => &[Addr, Int],
+        IStore => &[Addr, Int],
+        DStore => &[Addr, Double],
+        AStore => &[Addr, Addr],
+        IAStore => &[Addr, Int, Int],
+        DAStore => &[Addr, Int, Double],
+        AAStore => &[Addr, Int, Addr],
+        IAdd | ISub | IMul | IDiv | ICmp => &[Int, Int],
+        DAdd | DSub | DMul | DDiv | DCmp => &[Double, Double],
+        DNeg | D2I | DRet | DPrint => &[Double],
+        CPrint => &[Char],
+        _ => &[],
+    }
+}
+
+impl<'a> MiniVM<'a> {
+    /// Write a line to `out` for each instruction of `kind` executed in
+    /// `functions`, or in any function if it is empty, with the values it
+    /// takes off the stack and, with debug info, its source line. Start code
+    /// is called `.start`.
+    pub fn with_trace(
+        mut self,
+        kind: TraceKind,
+        functions: &[&str],
+        out: &'a mut dyn Write,
+    ) -> Self {
+        let prog = self.prog;
+        let all = std::iter::once(None).chain((0..prog.functions.len()).map(|f| Some(f as u16)));
+        let functions = match functions.is_empty() {
+            true => None,
+            false => Some(
+                all.filter(|&f| functions.contains(&function_name(prog, f).as_str()))
+                    .collect(),
+            ),
+        };
+        self.trace = Some(Trace {
+            kind,
+            functions,
+            out,
+        });
+        self
+    }
+
+    /// Trace `inst`, about to be executed in `frame`
+    pub(super) fn trace_inst(&mut self, frame: &Frame, inst: &Inst) -> VmResult<()> {
+        let trace = self.trace.as_ref().unwrap();
+        if !trace.kind.includes(inst)
+            || !(trace.functions.as_ref()).is_none_or(|fs| fs.contains(&frame.func))
+        {
+            return Ok(());
+        }
+
+        let mut slots: Vec<Slot> = operands(inst).to_vec();
+        if let Inst::Call(idx) = inst {
+            let params = self
+                .prog
+                .functions
+                .get(*idx as usize)
+                .map_or(0, |f| f.param_siz);
+            slots = vec![Slot::Int; params as usize];
+        }
+        let len: usize = slots
+            .iter()
+            .map(|s| if let Slot::Double = s { 2 } else { 1 })
+            .sum();
+        let mut vals = vec![];
+        if let Some(start) = self.stack.len().checked_sub(len) {
+            let mut rest = &self.stack[start..];
+            for slot in slots {
+                let val = match slot {
+                    Slot::Int => format!("{}", rest[0] as i32),
+                    Slot::Char => format!("{:?}", rest[0] as u8 as char),
+                    Slot::Addr => format!("{:#x}", rest[0]),
+                    Slot::Double => {
+                        let bits = (rest[0] as u64) << 32 | rest[1] as u64;
+                        rest = &rest[1..];
+                        format!("{:?}", f64::from_bits(bits))
+                    }
+                };
+                rest = &rest[1..];
+                vals.push(val);
+            }
+        }
+
+        let depth = self.frames.len() - 1;
+        let name = function_name(self.prog, frame.func);
+        let mut line = format!(
+            "{:depth$}{} {}: {}",
+            "",
+            name,
+            frame.ip,
+            inst,
+            depth = depth * 2
+        );
+        if !vals.is_empty() {
+            line += &format!(" ({})", vals.join(", "));
+        }
+        let src_line = (self.prog.debug.as_ref()).and_then(|debug| {
+            let lines = match frame.func {
+                Some(f) => debug.fn_lines.get(f as usize)?,
+                None => &debug.start_lines,
+            };
+            *lines.get(frame.ip)?
+        });
+        if let Some(src_line) = src_line {
+            line += &format!("  ; line {}", src_line + 1);
+        }
+        let trace = self.trace.as_mut().unwrap();
+        writeln!(trace.out, "{}", line)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,13 @@
+#![no_main]
+use chigusa::c0::{lexer::Lexer, parser::Parser, validate};
+use libfuzzer_sys::fuzz_target;
+
+// * Call the parser directly instead of `parse_no_panic`, so that any panic
+// * in the front end is reported as a crash. Trees that parse must also be
+// * well formed.
+fuzz_target!(|data: &[u8]| {
+    let input = String::from_utf8_lossy(data);
+    if let Ok(prog) = Parser::new(Lexer::new(&input)).parse() {
+        validate::validate(&prog).unwrap();
+    }
+});
@@ -0,0 +1,443 @@
+//! The public interface of the compiler.
+//!
+//! Programs go through [`lex`], [`parse`], [`check`] and [`codegen`] in that
+//! order, after [`preprocess`] if they have `#if` directives. [`codegen_with`] runs passes of other crates, written as
+//! [`CompilerPass`](crate::CompilerPass)es, along with the built-in ones,
+//! and [`codegen_streamed`] compiles programs too large to parse whole.
+//! [`typed`] gives the type of every expression, [`call_graph`] shows how the
+//! functions of a program call each other, [`purity`] which of them only
+//! compute their result, [`metrics`] how complex they are, [`mutants`] puts
+//! small bugs into a program to test its tests, and [`fingerprint`] tells
+//! how similar programs are, to find copied ones. [`find_all`] finds nodes
+//! of the tree with selectors, and [`rename`] renames a symbol everywhere.
+//! Errors stopping compilation are
+//! [`CompileError`]s, and everything reported to a user is a
+//! [`Diagnostic`], whose fixes [`apply_fixes`] makes. Items reached any other way are internals and may change
+//! at any time.
+
+#[cfg(feature = "std")]
+use crate::c0::alias::Aliases;
+use crate::c0::ast::Program;
+#[cfg(feature = "std")]
+use crate::c0::callgraph::CallGraph;
+#[cfg(feature = "std")]
+use crate::c0::fingerprint::Fingerprint;
+#[cfg(feature = "std")]
+use crate::c0::hir::TypedProgram;
+#[cfg(feature = "std")]
+use crate::c0::ide::{self, RenameError, TextEdit};
+use crate::c0::lexer::{Lexer, Token};
+#[cfg(feature = "std")]
+use crate::c0::lint::LintRules;
+#[cfg(feature = "std")]
+use crate::c0::metrics::Metrics;
+#[cfg(feature = "std")]
+use crate::c0::mutate::{self, Mutant};
+#[cfg(feature = "std")]
+use crate::c0::parser::{Decl, Parser};
+use crate::c0::preprocess::SourceMap;
+#[cfg(feature = "std")]
+use crate::c0::purity::{self, Purity};
+#[cfg(feature = "std")]
+use crate::c0::query::{Match, Query, QueryError};
+#[cfg(feature = "std")]
+use crate::c0::{
+    ast::{Expr, ExprVariant, StmtVariant},
+    symbol::Symbol,
+};
+use crate::error::{CompileError, ErrorCode, Fix, Note, Severity, Stage};
+use crate::prelude::*;
+use alloc::borrow::Cow;
+use core::fmt;
+
+#[cfg(feature = "std")]
+use crate::minivm::{codegen::has_call, value::Kind, Codegen, HostSig, PassManager, Target, O0};
+#[cfg(feature = "std")]
+use crate::{consteval, minivm::value::Value};
+#[cfg(feature = "std")]
+use alloc::collections::BTreeMap;
+
+/// A problem found in a program
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: ErrorCode,
+    pub stage: Stage,
+    pub message: String,
+    /// Where the problem is in the source, if known
+    pub span: Option<Span>,
+    pub notes: Vec<Note>,
+    /// Changes to the source fixing the problem, which `chigusa fix` makes
+    pub fixes: Vec<Fix>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}]: {}", self.severity, self.code, self.message)?;
+        if let Some(span) = self.span {
+            write!(f, " at {}", span)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<CompileError> for Diagnostic {
+    fn from(e: CompileError) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            code: e.code,
+            stage: e.stage,
+            message: e.message,
+            span: e.span,
+            notes: e.notes,
+            fixes: e.fixes,
+        }
+    }
+}
+
+/// Make `fixes`, like those of [`Diagnostic`]s, to `src`. A fix overlapping
+/// one made before it is left out, to make after the source is checked
+/// again. Returns the fixed source and how many fixes were made.
+pub fn apply_fixes(src: &str, fixes: &[Fix]) -> (String, usize) {
+    let mut fixes: Vec<&Fix> = fixes.iter().collect();
+    fixes.sort_by_key(|fix| (fix.span.start.index, fix.span.end.index));
+    let mut out = String::with_capacity(src.len());
+    let mut chars = src.chars();
+    // * Chars of `src` copied or replaced so far
+    let mut at = 0;
+    let mut made = 0;
+    for fix in fixes {
+        let Span { start, end } = fix.span;
+        if start.index < at {
+            continue;
+        }
+        out.extend(chars.by_ref().take(start.index - at));
+        chars.by_ref().take(end.index - start.index).for_each(drop);
+        out.push_str(&fix.replacement);
+        at = end.index;
+        made += 1;
+    }
+    out.extend(chars);
+    (out, made)
+}
+
+/// Leave out of `src` the lines `#if` directives keep only with features
+/// other than `features`, turning them and the directives into spaces. Every
+/// other token stays where it is, so spans into the result are spans into
+/// `src`. Borrowed if `src` has no directives.
+pub fn preprocess<'a, S: AsRef<str>>(
+    src: &'a str,
+    features: &[S],
+) -> Result<Cow<'a, str>, CompileError> {
+    crate::c0::preprocess::preprocess(src, features).map_err(CompileError::from)
+}
+
+/// [`preprocess`], along with which `#if` compiled each line, to note on
+/// errors found there
+pub fn preprocess_mapped<'a, S: AsRef<str>>(
+    src: &'a str,
+    features: &[S],
+) -> Result<(Cow<'a, str>, SourceMap), CompileError> {
+    crate::c0::preprocess::preprocess_mapped(src, features).map_err(CompileError::from)
+}
+
+/// Split `src` into tokens, leaving out comments other than `///` doc
+/// comments, which become [`TokenType::DocComment`](crate::TokenType::DocComment)
+/// tokens. Text that is not a valid token becomes a
+/// [`TokenType::Error`](crate::TokenType::Error) token. Tokens borrow their text from `src`.
+pub fn lex(src: &str) -> Vec<Token<'_>> {
+    Lexer::new(src).collect()
+}
+
+/// Parse `src` into a program. Names are resolved while parsing, so using
+/// an undeclared name is a parse error.
+pub fn parse(src: &str) -> Result<Program, CompileError> {
+    #[cfg(feature = "std")]
+    let res = crate::c0::parse_no_panic(src);
+    #[cfg(not(feature = "std"))]
+    let res = crate::c0::parser::Parser::new(Lexer::new(src)).parse();
+    res.map_err(CompileError::from)
+}
+
+/// Parse `src` with the functions in `host` declared, for programs to call
+/// like their own. Compiled, each becomes a stub that the VM runs a closure
+/// given to `MiniVM::register_host_fn` for instead. A program's own
+/// function or variable of the same name takes the place of a host function.
+#[cfg(feature = "std")]
+pub fn parse_with_host(src: &str, host: &[HostSig]) -> Result<Program, CompileError> {
+    use crate::c0::ast::{FunctionType, TypeDef};
+    let type_of = |kind: Kind| {
+        let name = match kind {
+            Kind::Void => "void",
+            Kind::Double => "double",
+            Kind::Char => "char",
+            Kind::Int | Kind::UInt | Kind::Bool => "int",
+        };
+        Ptr::new(TypeDef::NamedType(name.into()))
+    };
+    let externs = (host.iter())
+        .map(|sig| {
+            let func = FunctionType {
+                params: sig.params.iter().map(|&k| type_of(k)).collect(),
+                return_type: type_of(sig.ret),
+                body: None,
+                is_extern: true,
+            };
+            (sig.name.clone(), func)
+        })
+        .collect();
+    crate::c0::parse_no_panic_with(src, externs).map_err(CompileError::from)
+}
+
+/// Find which functions of `prog` call which, to see what is never called,
+/// what recurses and how deep calls go
+#[cfg(feature = "std")]
+pub fn call_graph(prog: &Program) -> CallGraph {
+    CallGraph::new(prog)
+}
+
+/// Find which functions of `prog` are pure, doing nothing but compute their
+/// result from their arguments and the globals they read
+#[cfg(feature = "std")]
+pub fn purity(prog: &Program) -> Purity {
+    Purity::new(prog)
+}
+
+/// Measure how complex each function of `prog` is: its statements, paths,
+/// nesting and Halstead counts of operators and operands
+#[cfg(feature = "std")]
+pub fn metrics(prog: &Program) -> Metrics {
+    Metrics::new(prog)
+}
+
+/// Fingerprint `prog` to compare it with others with
+/// [`Fingerprint::similarity`]. Names, literals, comments and layout are left
+/// out, so renaming variables or reformatting a program does not change it.
+#[cfg(feature = "std")]
+pub fn fingerprint(prog: &Program) -> Fingerprint {
+    Fingerprint::new(prog)
+}
+
+/// Nodes of `prog` that `query`, a selector like `FunctionCall[name=foo]`
+/// or `While > Return`, finds, in source order. See [`Query`] for how
+/// queries are written, and for building them from parts.
+#[cfg(feature = "std")]
+pub fn find_all(prog: &Program, query: &str) -> Result<Vec<Match>, QueryError> {
+    Ok(Query::parse(query)?.find_all(prog))
+}
+
+/// Edits to `src` renaming the variable, constant or function declared or
+/// used at line `ln`, column `col`, both counted from 0, to `new_name`. They
+/// cover its declaration and every use, and are refused if a name would
+/// then refer to something else.
+#[cfg(feature = "std")]
+pub fn rename(
+    src: &str,
+    ln: usize,
+    col: usize,
+    new_name: &str,
+) -> Result<Vec<TextEdit>, RenameError> {
+    let prog = parse(src).map_err(|_| RenameError::NoSymbol)?;
+    ide::rename(&prog, ide::pos_at(src, ln, col), new_name)
+}
+
+/// Up to `count` mutants of `src`, each with one small bug put in, for
+/// mutation testing. They are picked at random from `seed`, and the same
+/// seed always picks the same ones. Compile a mutant with [`codegen`] on its
+/// `prog`.
+#[cfg(feature = "std")]
+pub fn mutants(src: &str, seed: u64, count: usize) -> Result<Vec<Mutant>, CompileError> {
+    mutate::sample(src, seed, count).map_err(CompileError::from)
+}
+
+/// Find which variables each reference in `prog` may refer to
+#[cfg(feature = "std")]
+pub fn aliases(prog: &TypedProgram) -> Aliases {
+    Aliases::new(prog)
+}
+
+/// Type check `prog` and build its typed tree, where every expression knows
+/// its type and every name its definition
+#[cfg(feature = "std")]
+pub fn typed(prog: &Program) -> Result<TypedProgram, CompileError> {
+    crate::c0::type_checker::lower(prog).map_err(CompileError::from)
+}
+
+/// Type check `prog`, returning everything wrong with it, then the warnings
+/// from [`lint`]. This is quicker
+/// than [`codegen`], which also optimizes and checks the code it generates.
+#[cfg(feature = "std")]
+pub fn check(prog: &Program) -> Vec<Diagnostic> {
+    check_for(prog, Target::default())
+}
+
+/// Type check `prog` for `target`, which also decides which instructions
+/// may be used
+#[cfg(feature = "std")]
+pub fn check_for(prog: &Program, target: Target) -> Vec<Diagnostic> {
+    let mut diags = match Codegen::new(prog).with_target(target).check() {
+        Ok(()) => vec![],
+        Err(e) => vec![CompileError::from(e).into()],
+    };
+    diags.extend(lint(prog));
+    diags
+}
+
+/// Warnings about code in `prog` that compiles but is likely wrong, like
+/// calls to pure functions whose result is unused. [`lint_with`] checks the
+/// rules of a project too.
+#[cfg(feature = "std")]
+pub fn lint(prog: &Program) -> Vec<Diagnostic> {
+    let purity = Purity::new(prog);
+    (purity::unused_results(prog, &purity).into_iter())
+        .map(|(name, span)| Diagnostic {
+            severity: Severity::Warning,
+            code: ErrorCode(501),
+            stage: Stage::Compile,
+            message: format!("Result of `{}` is unused, and it does nothing else", name),
+            span: Some(span),
+            notes: vec![],
+            fixes: vec![],
+        })
+        .collect()
+}
+
+/// Warnings about where `prog` breaks `rules`, or errors if they deny
+/// it, on top of those of [`lint`]. Fails if a construct `rules` forbid is
+/// not a valid query.
+#[cfg(feature = "std")]
+pub fn lint_with(prog: &Program, rules: &LintRules) -> Result<Vec<Diagnostic>, QueryError> {
+    let severity = match rules.deny {
+        true => Severity::Error,
+        false => Severity::Warning,
+    };
+    let mut diags = lint(prog);
+    diags.extend(rules.check(prog)?.into_iter().map(|v| Diagnostic {
+        severity,
+        code: ErrorCode(v.rule.code()),
+        stage: Stage::Compile,
+        message: v.message,
+        span: Some(v.span),
+        notes: vec![],
+        fixes: vec![],
+    }));
+    Ok(diags)
+}
+
+/// Compile `prog` into an O0 module, which can be written out with
+/// [`O0::write_binary`] or printed as S0 assembly with `Display`
+#[cfg(feature = "std")]
+pub fn codegen(prog: &Program) -> Result<O0, CompileError> {
+    codegen_for(prog, Target::default())
+}
+
+/// Compile `prog` for `target`, which decides the layout of values and which
+/// instructions may be used
+#[cfg(feature = "std")]
+pub fn codegen_for(prog: &Program, target: Target) -> Result<O0, CompileError> {
+    Codegen::new(prog)
+        .with_target(target)
+        .compile()
+        .map_err(CompileError::from)
+}
+
+/// Compile `prog` with the pipeline of `passes`, running the passes of other
+/// crates registered there on the program first, and on the code of each
+/// function once the built-in passes are done with it
+#[cfg(feature = "std")]
+pub fn codegen_with(prog: &mut Program, passes: PassManager) -> Result<O0, CompileError> {
+    passes.run_on_program(prog)?;
+    Codegen::new(prog)
+        .with_passes(passes)
+        .compile()
+        .map_err(CompileError::from)
+}
+
+/// Compile `src` one function at a time, dropping the syntax tree of each
+/// once its code is generated, so that memory grows with the largest
+/// function rather than with the program. `src` is parsed twice: first for
+/// its declarations alone, skipping the bodies of functions, then once more
+/// with them. Globals initialized by calls are evaluated as [`codegen`]
+/// evaluates them, which parses the functions before the last of them a
+/// third time, and keeps them. `configure` sets the options of the
+/// [`Codegen`]; see [`Codegen::stream`] for what compiling this way gives
+/// up.
+#[cfg(feature = "std")]
+pub fn codegen_streamed(
+    src: &str,
+    configure: impl for<'p> FnOnce(Codegen<'p>) -> Codegen<'p>,
+) -> Result<O0, CompileError> {
+    let decls = Parser::new(Lexer::new(src)).with_bodies_skipped().parse()?;
+    let initial = initial_values(src, &decls)?;
+    let codegen = configure(Codegen::new(&decls)).with_initial_values(initial);
+    let mut codegen = codegen.stream()?;
+    let mut parser = Parser::new(Lexer::new(src));
+    let mut prog = parser.begin()?;
+    loop {
+        match parser.parse_decl(&mut prog)? {
+            Decl::Fn(name) => {
+                let scope = prog.blk.scope.cp();
+                let def = scope.borrow().find_def(name);
+                if let Some(def) = def {
+                    codegen.compile_fn(&name, &def, &scope)?;
+                    def.borrow().take_body();
+                }
+            }
+            Decl::Other => (),
+            Decl::End => break,
+        }
+        // * Global variables were compiled with the declarations
+        prog.blk.stmts.clear();
+    }
+    Ok(codegen.finish()?)
+}
+
+/// Values of the initializers of globals in `decls` that call functions,
+/// which the bodies `decls` skipped are needed to evaluate. `src` is parsed
+/// with its bodies up to the last of these globals, if there are any.
+#[cfg(feature = "std")]
+fn initial_values(src: &str, decls: &Program) -> Result<BTreeMap<String, Value>, CompileError> {
+    let calling: Vec<_> = (initializers(decls).into_iter())
+        .filter(|(_, init)| has_call(&init.borrow()))
+        .map(|(name, _)| name)
+        .collect();
+    let mut initial = BTreeMap::new();
+    if calling.is_empty() {
+        return Ok(initial);
+    }
+    let mut parser = Parser::new(Lexer::new(src));
+    let mut prog = parser.begin()?;
+    let scope = prog.blk.scope.cp();
+    while !(calling.iter()).all(|name| scope.borrow().find_def_self(*name).is_some()) {
+        if let Decl::End = parser.parse_decl(&mut prog)? {
+            break;
+        }
+    }
+    for (name, init) in initializers(&prog) {
+        if !calling.contains(&name) {
+            continue;
+        }
+        if let Ok(val) = consteval::eval(&init.borrow(), &scope) {
+            initial.insert(name.to_string(), val);
+        }
+    }
+    Ok(initial)
+}
+
+/// Globals of `prog` with an initializer, and the initializer
+#[cfg(feature = "std")]
+fn initializers(prog: &Program) -> Vec<(Symbol, Ptr<Expr>)> {
+    let decls = (prog.blk.stmts.iter()).filter_map(|stmt| match &stmt.var {
+        StmtVariant::ManyExpr(exprs) => Some(exprs),
+        _ => None,
+    });
+    (decls.flatten())
+        .filter_map(|expr| match &expr.borrow().var {
+            ExprVariant::BinaryOp(b) => match &b.lhs.borrow().var {
+                ExprVariant::Ident(i) => Some((i.name, b.rhs.cp())),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect()
+}
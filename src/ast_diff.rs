@@ -0,0 +1,99 @@
+//! `chigusa ast-diff`: compare two programs by their syntax trees.
+
+use crate::check::{load_failed, report};
+use crate::exit::Exit;
+use crate::opt::ParserConfig;
+use crate::source;
+use chigusa::c0::ast::Program;
+use chigusa::c0::ast_diff::{ast_diff as diff, Change, Node};
+use chigusa::c0::parse_no_panic;
+use chigusa::Span;
+use std::path::Path;
+
+/// Longest piece of source shown for a node
+const MAX_SNIPPET: usize = 40;
+
+/// Print the differences from `old` to `new`, both compiled with the features
+/// `-D` defines, one per line. Returns 1 if there are any, and reports files
+/// that do not parse as `chigusa check` reports errors.
+pub fn ast_diff(old: &Path, new: &Path, opt: &ParserConfig) -> Exit {
+    let (old_src, old_prog) = match read(old, opt) {
+        Ok(read) => read,
+        Err(exit) => return exit,
+    };
+    let (new_src, new_prog) = match read(new, opt) {
+        Ok(read) => read,
+        Err(exit) => return exit,
+    };
+
+    let changes = diff(&old_prog, &new_prog);
+    for change in &changes {
+        let line = match change {
+            Change::Removed(node, span) => format!(
+                "- {}: {} `{}`",
+                at(old, *span),
+                node,
+                snippet(&old_src, node, *span)
+            ),
+            Change::Added(node, span) => format!(
+                "+ {}: {} `{}`",
+                at(new, *span),
+                node,
+                snippet(&new_src, node, *span)
+            ),
+            Change::Changed {
+                node,
+                old: old_span,
+                new: new_span,
+            } => format!(
+                "~ {} -> {}: {} `{}` -> `{}`",
+                at(old, *old_span),
+                at(new, *new_span),
+                node,
+                snippet(&old_src, node, *old_span),
+                snippet(&new_src, node, *new_span)
+            ),
+        };
+        println!("{}", line);
+    }
+    match changes.is_empty() {
+        true => Exit::Success,
+        false => Exit::CompileError,
+    }
+}
+
+fn read(file: &Path, opt: &ParserConfig) -> Result<(String, Program), Exit> {
+    let mut errors = 0;
+    let src =
+        source::load(file, &opt.defines).map_err(|e| load_failed(file, e, opt, &mut errors))?;
+    match parse_no_panic(src.compiled()) {
+        Ok(prog) => Ok((src.text, prog)),
+        Err(e) => Err(report(file, &[src.annotate(e).into()], opt, &mut errors)),
+    }
+}
+
+/// `file:line:col` of the start of `span`
+fn at(file: &Path, span: Span) -> String {
+    format!(
+        "{}:{}:{}",
+        file.display(),
+        span.start.ln + 1,
+        span.start.pos + 1
+    )
+}
+
+/// Source of `node` at `span` on one line, shortened if it is long.
+/// Declarations span only their name, so the whole line is shown for them.
+fn snippet(src: &str, node: &Node, span: Span) -> String {
+    let text = match node {
+        Node::Function(_) | Node::Var(_) => src.lines().nth(span.start.ln).unwrap_or(""),
+        Node::Stmt | Node::Expr => src.get(span.start.index..span.end.index).unwrap_or(""),
+    };
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() > MAX_SNIPPET {
+        let short: String = text.chars().take(MAX_SNIPPET).collect();
+        format!("{}...", short)
+    } else {
+        text
+    }
+}
@@ -0,0 +1,373 @@
+//! Which variables each reference may refer to, and so which accesses to
+//! memory may touch the same variable.
+//!
+//! Every reference starts out as `&x` of some variable `x`, so the variables
+//! whose address is taken are all a reference can reach. This follows where
+//! each `&x` may go: into variables, into parameters of the functions it is
+//! passed to, out of the functions returning it, and through other
+//! references. Like [`escape`](crate::c0::escape), which builds on it, it
+//! does not look at the order of statements or at which branch runs, so the
+//! variables found for a reference may be more than it ever refers to, but
+//! never fewer.
+//!
+//! On top of what it is made from, the type of a reference narrows what it
+//! refers to: a `&int` only refers to `int` variables. The type checker lets
+//! a reference be converted to one of another type, so a variable whose
+//! address goes through such a conversion may be reached as any type.
+
+use super::ast::{OpVar, Scope, TypeDef};
+use super::escape::VarKey;
+use super::hir::*;
+use crate::prelude::*;
+use alloc::collections::{BTreeMap, BTreeSet};
+use core::fmt;
+
+/// Where references may go in a program
+#[derive(Clone)]
+pub struct Aliases {
+    /// Variables each variable holding a reference may refer to
+    pub points_to: BTreeMap<VarKey, BTreeSet<VarKey>>,
+    /// Variables references stored through references may refer to, or ones
+    /// passed to functions without a body
+    pub memory: BTreeSet<VarKey>,
+    /// Variables the reference each function returns may refer to
+    pub returned: BTreeMap<String, BTreeSet<VarKey>>,
+    /// Variables whose address is converted to a reference of another type
+    pub punned: BTreeSet<VarKey>,
+    /// Variables whose address is taken
+    pub taken: BTreeSet<VarKey>,
+    /// The function each local variable belongs to, `""` for globals
+    pub owner: BTreeMap<VarKey, String>,
+    /// Type of each variable
+    types: BTreeMap<VarKey, Type>,
+}
+
+/// Memory read or written
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Place {
+    /// A variable itself
+    Var(VarKey),
+    /// What the reference held by this variable refers to
+    Through(VarKey),
+    /// What a reference to this type, from anywhere else, refers to
+    Anywhere(Type),
+}
+
+impl Aliases {
+    pub fn new(prog: &TypedProgram) -> Aliases {
+        let params: BTreeMap<&str, Vec<VarKey>> = (prog.fns.iter())
+            .filter_map(|f| {
+                let id = f.body.as_ref()?.scope.borrow().id;
+                let keys = f.params.iter().map(|p| (id, p.name.clone())).collect();
+                Some((f.name.as_str(), keys))
+            })
+            .collect();
+        let mut flows = Flows {
+            params: &params,
+            func: "",
+            edges: vec![],
+            owner: BTreeMap::new(),
+            types: BTreeMap::new(),
+        };
+        flows.block(&prog.blk);
+        for f in &prog.fns {
+            if let Some(body) = &f.body {
+                flows.func = &f.name;
+                let id = body.scope.borrow().id;
+                for param in &f.params {
+                    let key = (id, param.name.clone());
+                    flows.owner.insert(key.clone(), f.name.clone());
+                    flows.types.insert(key, param.typ.cp());
+                }
+                flows.block(body);
+            }
+        }
+
+        // * What each node may hold, until nothing more is found
+        let mut holds: BTreeMap<Node, BTreeSet<VarKey>> = BTreeMap::new();
+        loop {
+            let mut changed = false;
+            for (source, sink) in &flows.edges {
+                let addrs: Vec<VarKey> = match source {
+                    Source::Addr(key) => vec![key.clone()],
+                    Source::Value(node) => holds.get(node).into_iter().flatten().cloned().collect(),
+                };
+                let set = holds.entry(sink.clone()).or_default();
+                for addr in addrs {
+                    changed |= set.insert(addr);
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        let taken = (flows.edges.iter())
+            .filter_map(|(source, _)| match source {
+                Source::Addr(key) => Some(key.clone()),
+                _ => None,
+            })
+            .collect();
+        let mut aliases = Aliases {
+            points_to: BTreeMap::new(),
+            memory: BTreeSet::new(),
+            returned: BTreeMap::new(),
+            punned: BTreeSet::new(),
+            taken,
+            owner: flows.owner,
+            types: flows.types,
+        };
+        for (node, addrs) in holds {
+            match node {
+                Node::Var(key) => {
+                    aliases.points_to.insert(key, addrs);
+                }
+                Node::Memory => aliases.memory = addrs,
+                Node::Return(func) => {
+                    aliases.returned.insert(func, addrs);
+                }
+                Node::Punned => aliases.punned = addrs,
+            }
+        }
+        aliases
+    }
+
+    /// Variables `place` may be
+    pub fn targets(&self, place: &Place) -> BTreeSet<VarKey> {
+        let (addrs, target) = match place {
+            Place::Var(key) => return BTreeSet::from([key.clone()]),
+            Place::Through(key) => {
+                let target = match self.types.get(key).map(|t| t.borrow().clone()) {
+                    Some(TypeDef::Ref(r)) => Some(r.target),
+                    _ => None,
+                };
+                (self.points_to.get(key), target)
+            }
+            Place::Anywhere(typ) => (Some(&self.taken), Some(typ.cp())),
+        };
+        (addrs.into_iter().flatten())
+            .filter(|addr| match &target {
+                Some(target) => {
+                    self.punned.contains(*addr)
+                        || self
+                            .types
+                            .get(*addr)
+                            .is_none_or(|t| *t.borrow() == *target.borrow())
+                }
+                None => true,
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Whether `a` and `b` may be the same variable
+    pub fn may_alias(&self, a: &Place, b: &Place) -> bool {
+        !self.targets(a).is_disjoint(&self.targets(b))
+    }
+}
+
+impl fmt::Debug for Aliases {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // * Variables show as `name`scope`, the way codegen names them
+        let names = |set: &BTreeSet<VarKey>| -> Vec<String> {
+            set.iter()
+                .map(|(id, name)| format!("{}`{}", name, id))
+                .collect()
+        };
+        let points_to: BTreeMap<_, _> = (self.points_to.iter())
+            .map(|((id, name), addrs)| (format!("{}`{}", name, id), names(addrs)))
+            .collect();
+        let returned: BTreeMap<_, _> = (self.returned.iter())
+            .map(|(func, addrs)| (func, names(addrs)))
+            .collect();
+        f.debug_struct("Aliases")
+            .field("points_to", &points_to)
+            .field("memory", &names(&self.memory))
+            .field("returned", &returned)
+            .field("punned", &names(&self.punned))
+            .finish()
+    }
+}
+
+/// What holds references
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd)]
+enum Node {
+    Var(VarKey),
+    /// Stored through a reference, or anywhere else nothing more is known of
+    Memory,
+    /// Returned from this function
+    Return(String),
+    /// Converted to a reference of another type
+    Punned,
+}
+
+/// Where a reference comes from
+#[derive(Debug, Clone)]
+enum Source {
+    /// `&x`
+    Addr(VarKey),
+    /// Whatever reference this holds
+    Value(Node),
+}
+
+/// The ways references flow through a program
+struct Flows<'p> {
+    /// Parameters of each function with a body
+    params: &'p BTreeMap<&'p str, Vec<VarKey>>,
+    /// Function being looked at
+    func: &'p str,
+    edges: Vec<(Source, Node)>,
+    owner: BTreeMap<VarKey, String>,
+    types: BTreeMap<VarKey, Type>,
+}
+
+impl<'p> Flows<'p> {
+    fn flow(&mut self, sources: Vec<Source>, sink: Node) {
+        self.edges
+            .extend(sources.into_iter().map(|s| (s, sink.clone())));
+    }
+
+    fn block(&mut self, block: &Block) {
+        let id = block.scope.borrow().id;
+        for var in &block.vars {
+            let key = (id, var.name.clone());
+            self.owner.insert(key.clone(), self.func.into());
+            self.types.insert(key, var.typ.cp());
+        }
+        for stmt in &block.stmts {
+            self.stmt(stmt, &block.scope);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt, scope: &Ptr<Scope>) {
+        maybe_grow(|| match &stmt.var {
+            StmtVariant::If {
+                cond,
+                then,
+                else_ifs,
+                els,
+            } => {
+                self.expr(cond, scope);
+                self.stmt(then, scope);
+                for (cond, stmt) in else_ifs {
+                    self.expr(cond, scope);
+                    self.stmt(stmt, scope);
+                }
+                if let Some(els) = els {
+                    self.stmt(els, scope);
+                }
+            }
+            StmtVariant::While { cond, body, .. } => {
+                self.expr(cond, scope);
+                self.stmt(body, scope);
+            }
+            StmtVariant::Block(b) => self.block(b),
+            StmtVariant::Exprs(es) | StmtVariant::Print(es) => {
+                for e in es {
+                    self.expr(e, scope);
+                }
+            }
+            StmtVariant::Return(Some(e)) => {
+                let sources = self.expr(e, scope);
+                self.flow(sources, Node::Return(self.func.into()));
+            }
+            StmtVariant::Scan(_)
+            | StmtVariant::Return(None)
+            | StmtVariant::Break(_)
+            | StmtVariant::Empty => (),
+        })
+    }
+
+    /// Look through `expr`, returning where the reference it gives may come
+    /// from
+    fn expr(&mut self, expr: &Expr, scope: &Ptr<Scope>) -> Vec<Source> {
+        maybe_grow(|| match &expr.var {
+            ExprVariant::Var(name) => match &*expr.typ.borrow() {
+                TypeDef::Ref(_) => vec![Source::Value(Node::Var(key(name, scope)))],
+                _ => vec![],
+            },
+            ExprVariant::Literal(_) => vec![],
+            ExprVariant::Conv(e) => {
+                let sources = self.expr(e, scope);
+                let punned = match (&*e.typ.borrow(), &*expr.typ.borrow()) {
+                    (TypeDef::Ref(from), TypeDef::Ref(to)) => {
+                        *from.target.borrow() != *to.target.borrow()
+                    }
+                    _ => false,
+                };
+                if punned {
+                    self.flow(sources.clone(), Node::Punned);
+                }
+                sources
+            }
+            ExprVariant::Unary(OpVar::Ref, e) => match &e.var {
+                ExprVariant::Var(name) => {
+                    let key = key(name, scope);
+                    // * What is stored through `&x` is out of sight, so a
+                    // * reference held by `x` could go anywhere, and `x` may
+                    // * hold anything stored through a reference
+                    if matches!(&*e.typ.borrow(), TypeDef::Ref(_)) {
+                        let var = Node::Var(key.clone());
+                        self.flow(vec![Source::Value(var.clone())], Node::Memory);
+                        self.flow(vec![Source::Value(Node::Memory)], var);
+                    }
+                    vec![Source::Addr(key)]
+                }
+                _ => self.expr(e, scope),
+            },
+            // * A reference read through `*` was stored through a reference
+            ExprVariant::Unary(op, e) => {
+                self.expr(e, scope);
+                match (op, &*expr.typ.borrow()) {
+                    (OpVar::Der, TypeDef::Ref(_)) => vec![Source::Value(Node::Memory)],
+                    _ => vec![],
+                }
+            }
+            ExprVariant::Binary(op, lhs, rhs) => {
+                self.expr(lhs, scope);
+                let rhs = self.expr(rhs, scope);
+                match op {
+                    OpVar::_Com => rhs,
+                    _ => vec![],
+                }
+            }
+            ExprVariant::Assign { to, val, .. } => {
+                let sources = self.expr(val, scope);
+                self.flow(sources.clone(), Node::Var(key(to, scope)));
+                sources
+            }
+            ExprVariant::Store { to, val } => {
+                self.expr(to, scope);
+                let sources = self.expr(val, scope);
+                self.flow(sources.clone(), Node::Memory);
+                sources
+            }
+            ExprVariant::Call { func, args } => {
+                let params = self.params.get(func.name.as_str());
+                let result = match params {
+                    Some(_) => vec![Source::Value(Node::Return(func.name.clone()))],
+                    None => vec![Source::Value(Node::Memory)],
+                };
+                for (idx, arg) in args.iter().enumerate() {
+                    let sources = self.expr(arg, scope);
+                    // * Functions without a body may do anything with it
+                    let sink = match params.and_then(|p| p.get(idx)) {
+                        Some(param) => Node::Var(param.clone()),
+                        None => Node::Memory,
+                    };
+                    self.flow(sources, sink);
+                }
+                match &*expr.typ.borrow() {
+                    TypeDef::Ref(_) => result,
+                    _ => vec![],
+                }
+            }
+        })
+    }
+}
+
+/// Key of the variable `name` refers to, seen from `scope`
+fn key(name: &NameRef, scope: &Ptr<Scope>) -> VarKey {
+    let id = (scope.borrow().find_def_depth(&name.name)).map_or(0, |(_, id)| id);
+    (id, name.name.clone())
+}
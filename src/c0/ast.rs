@@ -4,17 +4,12 @@
 */
 
 use super::err::*;
+use super::num;
+use super::symbol::Symbol;
 use crate::prelude::*;
-use failure::Fail;
+use core::fmt::{self, Formatter};
+use core::iter::Iterator;
 use indexmap::IndexMap;
-use once_cell::{self, sync::*};
-use regex;
-use std::cell::{Ref, RefCell, RefMut};
-use std::collections::HashMap;
-use std::fmt::{self, Display, Formatter};
-use std::iter::Iterator;
-use std::ops::{Deref, DerefMut};
-use std::rc::{Rc, Weak};
 
 pub type TypeIdent = u64;
 
@@ -25,6 +20,15 @@ pub struct Program {
     // pub types: Vec<TypeDef>
 }
 
+impl Drop for Program {
+    fn drop(&mut self) {
+        // * Function bodies live in the definitions of the global scope, and
+        // * their scopes point back to it through `last`. Clear the
+        // * definitions to break the cycle, or the whole tree leaks.
+        self.blk.scope.borrow_mut().defs.clear();
+    }
+}
+
 impl fmt::Debug for Program {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.debug_struct("Program").field("blk", &self.blk).finish()
@@ -40,6 +44,11 @@ pub enum SymbolDef {
         typ: Ptr<TypeDef>,
         is_const: bool,
         decl_span: Span,
+        /// Initializer of a constant, for evaluating it at compile time
+        value: Option<Ptr<Expr>>,
+        /// Text of the `///` comments right before the declaration, one
+        /// line each, without the `///` and one space after it
+        doc: Option<String>,
     },
 }
 
@@ -72,100 +81,246 @@ impl SymbolDef {
             _ => None,
         }
     }
+
+    /// Take the body out of the function self defines, leaving it declared
+    /// only
+    pub fn take_body(&self) -> Option<Block> {
+        match self {
+            SymbolDef::Var { typ, .. } => match &mut *typ.borrow_mut() {
+                TypeDef::Function(f) => f.body.take(),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
 }
 
 #[derive(Eq, PartialEq)]
 pub struct Scope {
     pub last: Option<Ptr<Scope>>,
-    pub defs: IndexMap<String, Ptr<SymbolDef>>,
+    pub defs: IndexMap<Symbol, Ptr<SymbolDef>, DefsHasher>,
     pub id: usize,
 }
 
-static mut scope_id: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0usize);
+/// Hasher of symbol tables. It is seeded the same every time, so that nothing
+/// about compiling depends on a random seed. Symbol tables are `IndexMap`s,
+/// which iterate in declaration order whatever the hasher is.
+pub type DefsHasher = core::hash::BuildHasherDefault<FnvHasher>;
+
+/// 64-bit FNV-1a
+pub struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> Self {
+        FnvHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl core::hash::Hasher for FnvHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = (self.0 ^ b as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+// * Id of the next scope. Scope `0` is always the global scope of the
+// * program being parsed, so this is reset every time a parse starts.
+#[cfg(feature = "std")]
+thread_local! {
+    static SCOPE_ID: core::cell::Cell<usize> = const { core::cell::Cell::new(0) };
+}
+// * Without `std` there are no thread locals, so parsing on several threads
+// * at once gives scopes ids that are unique but not reproducible
+#[cfg(not(feature = "std"))]
+static SCOPE_ID: core::sync::atomic::AtomicUsize = core::sync::atomic::AtomicUsize::new(0);
+
+impl Default for Scope {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        // * Dropping the innermost of deeply nested scopes drops them all
+        maybe_grow(|| drop(self.last.take()));
+    }
+}
 
 impl Scope {
+    #[cfg(feature = "std")]
     pub fn reset_id() {
-        unsafe {
-            scope_id.store(0, std::sync::atomic::Ordering::SeqCst);
-        }
+        SCOPE_ID.with(|id| id.set(0));
+    }
+
+    #[cfg(feature = "std")]
+    fn next_id() -> usize {
+        SCOPE_ID.with(|id| {
+            let next = id.get();
+            id.set(next + 1);
+            next
+        })
+    }
+
+    #[cfg(not(feature = "std"))]
+    pub fn reset_id() {
+        SCOPE_ID.store(0, core::sync::atomic::Ordering::Relaxed);
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn next_id() -> usize {
+        SCOPE_ID.fetch_add(1, core::sync::atomic::Ordering::Relaxed)
     }
 
     pub fn new() -> Scope {
-        let id = unsafe { scope_id.fetch_add(1, std::sync::atomic::Ordering::SeqCst) };
         Scope {
             last: None,
-            defs: IndexMap::new(),
-            id,
+            defs: IndexMap::default(),
+            id: Self::next_id(),
         }
     }
 
     pub fn new_with_parent(parent: Ptr<Scope>) -> Scope {
-        let id = unsafe { scope_id.fetch_add(1, std::sync::atomic::Ordering::SeqCst) };
         Scope {
             last: Some(parent),
-            defs: IndexMap::new(),
-            id,
+            defs: IndexMap::default(),
+            id: Self::next_id(),
         }
     }
 
-    pub fn find_def(&self, name: &str) -> Option<Ptr<SymbolDef>> {
-        self.defs.get(name).map(|def| def.cp()).or_else(|| {
-            self.last
-                .as_ref()
-                .and_then(|last| last.borrow().find_def(name))
-        })
+    pub fn find_def(&self, name: impl Into<Symbol>) -> Option<Ptr<SymbolDef>> {
+        self.find_def_depth(name).map(|(def, _)| def)
     }
 
-    pub fn find_def_depth(&self, name: &str) -> Option<(Ptr<SymbolDef>, usize)> {
-        self.defs
-            .get(name)
-            .map(|def| (def.cp(), self.id))
-            .or_else(|| {
-                self.last
-                    .as_ref()
-                    .and_then(|last| last.borrow().find_def_depth(name))
-            })
+    /// The name most like `name` visible from this scope whose definition
+    /// `want` accepts, to suggest in place of a misspelled one. Names
+    /// needing more than a third of `name` to be edited are not alike.
+    pub fn similar_name(&self, name: &str, want: impl Fn(&SymbolDef) -> bool) -> Option<String> {
+        let max = (name.chars().count() / 3).max(1);
+        let (dist, similar) = self.most_similar(name, &want)?;
+        (dist <= max).then_some(similar)
+    }
+
+    /// The name visible from this scope closest to `name` that `want`
+    /// accepts, and how far it is. Names in inner scopes win ties.
+    fn most_similar(
+        &self,
+        name: &str,
+        want: &dyn Fn(&SymbolDef) -> bool,
+    ) -> Option<(usize, String)> {
+        // * Scopes may be nested deeply, so they are walked in a loop
+        let mut outer = vec![];
+        let mut scope = self.last.as_ref().map(|last| last.cp());
+        while let Some(cur) = scope {
+            scope = cur.borrow().last.as_ref().map(|last| last.cp());
+            outer.push(cur);
+        }
+        let mut best: Option<(usize, String)> = None;
+        let mut look_in = |scope: &Scope| {
+            for (other, def) in scope.defs.iter() {
+                let dist = edit_distance(name, other);
+                if best.as_ref().is_none_or(|(d, _)| dist <= *d) && want(&def.borrow()) {
+                    best = Some((dist, other.to_string()));
+                }
+            }
+        };
+        outer
+            .iter()
+            .rev()
+            .for_each(|scope| look_in(&scope.borrow()));
+        look_in(self);
+        best
+    }
+
+    pub fn find_def_depth(&self, name: impl Into<Symbol>) -> Option<(Ptr<SymbolDef>, usize)> {
+        let name = name.into();
+        if let Some(def) = self.defs.get(&name) {
+            return Some((def.cp(), self.id));
+        }
+        // * Scopes may be nested deeply, so they are walked in a loop
+        let mut scope = self.last.as_ref().map(|last| last.cp());
+        while let Some(cur) = scope {
+            let cur = cur.borrow();
+            if let Some(def) = cur.defs.get(&name) {
+                return Some((def.cp(), cur.id));
+            }
+            scope = cur.last.as_ref().map(|last| last.cp());
+        }
+        None
     }
 
-    pub fn find_def_self(&self, name: &str) -> Option<Ptr<SymbolDef>> {
-        self.defs.get(name).map(|def| def.cp())
+    pub fn find_def_self(&self, name: impl Into<Symbol>) -> Option<Ptr<SymbolDef>> {
+        self.defs.get(&name.into()).map(|def| def.cp())
     }
 
-    pub fn insert_def(&mut self, name: &str, def: SymbolDef) -> ParseResult<()> {
-        if self.defs.contains_key(name) {
-            let orig = self.defs.get(name).unwrap().borrow();
+    /// Variables in this scope declared inside `span`, in declaration order.
+    /// Passing the span of a declaration statement gets what it declares.
+    pub fn defs_in(&self, span: Span) -> Vec<(Symbol, Ptr<SymbolDef>)> {
+        self.defs
+            .iter()
+            .filter(|(_, def)| match &*def.borrow() {
+                SymbolDef::Var { decl_span, .. } => span.contains(*decl_span),
+                _ => false,
+            })
+            .map(|(name, def)| (*name, def.cp()))
+            .collect()
+    }
+
+    pub fn insert_def(&mut self, name: impl Into<Symbol>, def: SymbolDef) -> ParseResult<()> {
+        let name = name.into();
+        // * Functions defined outside the program give way to anything the
+        // * program declares of the same name
+        let is_extern = self.defs.get(&name).is_some_and(|orig| {
+            matches!(&*orig.borrow(), SymbolDef::Var { typ, .. }
+                if matches!(&*typ.borrow(), TypeDef::Function(f) if f.is_extern))
+        });
+        if self.defs.contains_key(&name) && !is_extern {
+            let orig = self.defs.get(&name).unwrap().borrow();
 
             // * Compare function declarations. Only allow duplicate declration of function types.
             if let SymbolDef::Var { typ, .. } = &*orig {
                 let orig = typ.borrow();
                 if let SymbolDef::Var { typ, .. } = &def {
                     let other = typ.borrow();
-                    if orig.compare_fns(&*other) {
+                    if orig.compare_fns(&other) {
                         Ok(())
                     } else {
                         Err(parse_err_z(ParseErrVariant::ConflictingDeclaration(
-                            name.into(),
+                            name.to_string(),
                         )))
                     }
                 } else {
                     Err(parse_err_z(ParseErrVariant::ConflictingDeclaration(
-                        name.into(),
+                        name.to_string(),
                     )))
                 }
             } else {
                 Err(parse_err_z(ParseErrVariant::DuplicateDeclaration(
-                    name.into(),
+                    name.to_string(),
                 )))
             }
         } else {
-            if ident_regex.is_match(name) {
+            if is_ident(&name) {
                 Ok(())
             } else {
-                Err(parse_err_z(ParseErrVariant::BadIdentifier(name.into())))
+                Err(parse_err_z(ParseErrVariant::BadIdentifier(
+                    name.to_string(),
+                )))
             }
         }?;
 
-        self.defs.insert(name.into(), Ptr::new(def));
+        tracing::debug!(
+            scope = self.id,
+            name = name.as_str(),
+            "Insert symbol: {:?}",
+            def
+        );
+        self.defs.insert(name, Ptr::new(def));
         Ok(())
     }
 }
@@ -186,8 +341,12 @@ impl fmt::Debug for Scope {
     }
 }
 
-static ident_regex: Lazy<regex::Regex> =
-    Lazy::new(|| regex::Regex::new("^[_a-zA-Z][_a-zA-Z0-9]*$").unwrap());
+/// Whether `name` matches `[_a-zA-Z][_a-zA-Z0-9]*`
+fn is_ident(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c == '_' || c.is_ascii_alphabetic())
+        && chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
+}
 
 #[derive(Clone, Eq, PartialEq)]
 pub enum TypeDef {
@@ -235,24 +394,15 @@ impl TypeDef {
     }
 
     pub fn is_unit(&self) -> bool {
-        match self {
-            TypeDef::Unit => true,
-            _ => false,
-        }
+        matches!(self, TypeDef::Unit)
     }
 
     pub fn is_fn(&self) -> bool {
-        match self {
-            TypeDef::Function(..) => true,
-            _ => false,
-        }
+        matches!(self, TypeDef::Function(..))
     }
 
     pub fn is_primitive(&self) -> bool {
-        match self {
-            TypeDef::Primitive(..) => true,
-            _ => false,
-        }
+        matches!(self, TypeDef::Primitive(..))
     }
 }
 
@@ -360,6 +510,13 @@ impl AstNode for Stmt {
     }
 }
 
+impl Drop for Stmt {
+    fn drop(&mut self) {
+        // * Deeply nested statements would overflow the stack otherwise
+        maybe_grow(|| drop(core::mem::replace(&mut self.var, StmtVariant::Empty)));
+    }
+}
+
 impl fmt::Debug for Stmt {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         if f.alternate() {
@@ -377,12 +534,14 @@ pub enum StmtVariant {
     Block(Block),
     Expr(Ptr<Expr>),
     Print(Vec<Ptr<Expr>>),
-    Scan(Identifier),
+    /// `scan` into the variable named, with the span of its name
+    Scan(Identifier, Span),
     // TODO: Workaround for declaration and similar statements that results
     // in multiple expressions
     ManyExpr(Vec<Ptr<Expr>>),
     Return(Option<Ptr<Expr>>),
-    Break,
+    /// Break out of the innermost loop, or the loop with the given label.
+    Break(Option<Identifier>),
     Empty,
 }
 
@@ -398,11 +557,12 @@ impl fmt::Debug for StmtVariant {
                     f.debug_list().entries(x).finish()?;
                     write!(f, ")")
                 }
-                StmtVariant::Scan(x) => write!(f, "Scan({})", x),
+                StmtVariant::Scan(x, _) => write!(f, "Scan({})", x),
                 StmtVariant::Expr(x) => write!(f, "{:#?}", &*x.borrow()),
                 StmtVariant::ManyExpr(x) => write!(f, "{:#?}", x),
                 StmtVariant::Return(x) => write!(f, "{:#?}", x),
-                StmtVariant::Break => write!(f, "Break"),
+                StmtVariant::Break(None) => write!(f, "Break"),
+                StmtVariant::Break(Some(label)) => write!(f, "Break({})", label),
                 StmtVariant::Empty => write!(f, "Empty"),
             }
         } else {
@@ -415,11 +575,12 @@ impl fmt::Debug for StmtVariant {
                     f.debug_list().entries(x).finish()?;
                     write!(f, ")")
                 }
-                StmtVariant::Scan(x) => write!(f, "Scan({})", x),
+                StmtVariant::Scan(x, _) => write!(f, "Scan({})", x),
                 StmtVariant::Expr(x) => write!(f, "{:?}", &*x.borrow()),
                 StmtVariant::ManyExpr(x) => write!(f, "{:?}", x),
                 StmtVariant::Return(x) => write!(f, "{:?}", x),
-                StmtVariant::Break => write!(f, "Break"),
+                StmtVariant::Break(None) => write!(f, "Break"),
+                StmtVariant::Break(Some(label)) => write!(f, "Break({})", label),
                 StmtVariant::Empty => write!(f, "Empty"),
             }
         }
@@ -432,6 +593,17 @@ pub struct Expr {
     pub span: Span,
 }
 
+impl Drop for Expr {
+    fn drop(&mut self) {
+        // * Dropping is recursive too; deeply nested expressions would
+        // * overflow the stack otherwise.
+        maybe_grow(|| {
+            let placeholder = ExprVariant::Literal(Literal::Boolean { val: false });
+            drop(core::mem::replace(&mut self.var, placeholder));
+        });
+    }
+}
+
 impl fmt::Debug for Expr {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         if f.alternate() {
@@ -464,22 +636,9 @@ pub enum ExprVariant {
     FunctionCall(FunctionCall),
     StructChild(StructChild),
     ArrayChild(ArrayChild),
-    // /// If conditional.
-    // ///
-    // /// `if` `(` Expression `)` (Expression | Statement)
-    // /// (`else` (Expression | Statement))?
-    // IfConditional(IfConditional),
-
-    // /// While conditional. Takes the value on break or the last iteration as its
-    // /// return value.
-    // ///
-    // /// `while` `(` Expression `)` BlockExpression
-    // WhileConditional(WhileConditional),
-
-    // /// Block expression. Similar to that in Rust.
-    // ///
-    // /// `{` Statement* Expression? `}`
-    // Block(Block),
+    // * `if`, `while` and blocks are statements in C0 and never produce a
+    // * value. They live in `StmtVariant`; the parser rejects them in
+    // * expression position with `ParseErrVariant::ControlFlowInExpr`.
 }
 
 impl fmt::Display for ExprVariant {
@@ -514,7 +673,7 @@ impl fmt::Debug for ExprVariant {
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Identifier {
-    pub name: String,
+    pub name: Symbol,
 }
 
 impl fmt::Display for Identifier {
@@ -526,8 +685,8 @@ impl fmt::Display for Identifier {
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum Literal {
     Char { val: char },
-    Integer { val: ramp::Int },
-    Float { val: ramp::rational::Rational },
+    Integer { val: num::Int },
+    Float { val: num::Rational },
     Struct { typ: TypeDef, fields: Vec<Expr> },
     Boolean { val: bool },
     String { val: String },
@@ -546,13 +705,15 @@ impl fmt::Display for Literal {
     }
 }
 
-impl From<super::lexer::Literal> for Literal {
-    fn from(lit: super::lexer::Literal) -> Self {
+impl From<super::lexer::Literal<'_>> for Literal {
+    fn from(lit: super::lexer::Literal<'_>) -> Self {
         use super::lexer::Literal::*;
         match lit {
             Integer(i) => Literal::Integer { val: i },
             Float(i) => Literal::Float { val: i },
-            String(s) => Literal::String { val: s },
+            String(s) => Literal::String {
+                val: s.into_owned(),
+            },
             Boolean(b) => Literal::Boolean { val: b },
             Char(c) => Literal::Char { val: c },
             _Dummy => panic!("Dummy literal cannot be used!"),
@@ -576,6 +737,9 @@ impl fmt::Display for TypeConversion {
 pub struct IfConditional {
     pub cond: Ptr<Expr>,
     pub if_block: Ptr<Stmt>,
+    /// `else if` arms in source order. They are kept flat instead of being
+    /// nested inside `else_block`, so long chains don't recurse.
+    pub else_ifs: Vec<(Ptr<Expr>, Ptr<Stmt>)>,
     pub else_block: Option<Ptr<Stmt>>,
 }
 
@@ -583,6 +747,8 @@ pub struct IfConditional {
 pub struct WhileConditional {
     pub cond: Ptr<Expr>,
     pub block: Ptr<Stmt>,
+    /// Label of this loop, as in `outer: while (...) ...`
+    pub label: Option<Identifier>,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -597,6 +763,8 @@ pub struct BinaryOp {
     pub lhs: Ptr<Expr>,
     pub rhs: Ptr<Expr>,
     pub op: OpVar,
+    /// Where the operator is
+    pub op_span: Span,
 }
 
 impl fmt::Display for BinaryOp {
@@ -619,11 +787,38 @@ impl fmt::Display for UnaryOp {
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct FunctionCall {
-    // TODO: Subject to change
-    pub func: String,
+    /// Name of the function called. Functions are only declared at the top
+    /// level, so this names a definition in the global scope, and stays
+    /// valid however the tree around the call is rewritten.
+    pub func: Symbol,
     pub params: Vec<Ptr<Expr>>,
 }
 
+impl FunctionCall {
+    /// Definition of the function called, found from `scope` or any scope
+    /// inside the global one
+    pub fn def(&self, scope: &Ptr<Scope>) -> Option<Ptr<SymbolDef>> {
+        let mut scope = scope.cp();
+        loop {
+            let last = scope.borrow().last.as_ref().map(|last| last.cp());
+            match last {
+                Some(last) => scope = last,
+                None => break,
+            }
+        }
+        let def = scope.borrow().find_def_self(self.func)?;
+        let is_fn = match &*def.borrow() {
+            SymbolDef::Var { typ, .. } => typ.borrow().is_fn(),
+            SymbolDef::Typ { .. } => false,
+        };
+        if is_fn {
+            Some(def)
+        } else {
+            None
+        }
+    }
+}
+
 impl fmt::Display for FunctionCall {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "({} {:?})", self.func, self.params)
@@ -654,7 +849,7 @@ impl fmt::Display for ArrayChild {
     }
 }
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
 pub enum OpVar {
     // Binary
     /// `+`, Addition
@@ -733,19 +928,16 @@ impl OpVar {
     /// Is this operator a binary operator?
     pub fn is_binary(&self) -> bool {
         use self::OpVar::*;
-        match self {
-            Add | Sub | Mul | Div | Gt | Lt | Eq | Gte | Lte | Neq | _Asn => true,
-            _ => false,
-        }
+        matches!(
+            self,
+            Add | Sub | Mul | Div | Gt | Lt | Eq | Gte | Lte | Neq | _Asn
+        )
     }
 
     /// Is this operator a unary operator?
     pub fn is_unary(&self) -> bool {
         use self::OpVar::*;
-        match self {
-            Neg | Inv | Bin | Ref | Der | Ina | Inb | Dea | Deb => true,
-            _ => false,
-        }
+        matches!(self, Neg | Inv | Bin | Ref | Der | Ina | Inb | Dea | Deb)
     }
 }
 
@@ -754,3 +946,173 @@ impl fmt::Display for OpVar {
         write!(f, "{:?}", self)
     }
 }
+
+/// Structural equality between two programs, ignoring spans and scope ids.
+///
+/// Two programs are `ast_eq` if they would be parsed from sources that differ
+/// only in whitespace, comments and redundant parentheses.
+pub fn ast_eq(lhs: &Program, rhs: &Program) -> bool {
+    lhs.blk.spanless_eq(&rhs.blk)
+}
+
+/// Equality ignoring every `Span` inside the node. Backs [`ast_eq`].
+pub(crate) trait SpanlessEq {
+    fn spanless_eq(&self, other: &Self) -> bool;
+}
+
+impl<T: SpanlessEq> SpanlessEq for Ptr<T> {
+    fn spanless_eq(&self, other: &Self) -> bool {
+        self.borrow().spanless_eq(&*other.borrow())
+    }
+}
+
+impl<T: SpanlessEq> SpanlessEq for Option<T> {
+    fn spanless_eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Some(a), Some(b)) => a.spanless_eq(b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl<T: SpanlessEq> SpanlessEq for Vec<T> {
+    fn spanless_eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().zip(other).all(|(a, b)| a.spanless_eq(b))
+    }
+}
+
+impl<A: SpanlessEq, B: SpanlessEq> SpanlessEq for (A, B) {
+    fn spanless_eq(&self, other: &Self) -> bool {
+        self.0.spanless_eq(&other.0) && self.1.spanless_eq(&other.1)
+    }
+}
+
+impl SpanlessEq for Block {
+    fn spanless_eq(&self, other: &Self) -> bool {
+        self.scope.spanless_eq(&other.scope) && self.stmts.spanless_eq(&other.stmts)
+    }
+}
+
+impl SpanlessEq for Scope {
+    fn spanless_eq(&self, other: &Self) -> bool {
+        self.defs.len() == other.defs.len()
+            && self
+                .defs
+                .iter()
+                .zip(other.defs.iter())
+                .all(|((a_name, a), (b_name, b))| a_name == b_name && a.spanless_eq(b))
+    }
+}
+
+impl SpanlessEq for SymbolDef {
+    fn spanless_eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (SymbolDef::Typ { def: a }, SymbolDef::Typ { def: b }) => a.spanless_eq(b),
+            (
+                SymbolDef::Var {
+                    typ: a_typ,
+                    is_const: a_const,
+                    ..
+                },
+                SymbolDef::Var {
+                    typ: b_typ,
+                    is_const: b_const,
+                    ..
+                },
+            ) => a_const == b_const && a_typ.spanless_eq(b_typ),
+            _ => false,
+        }
+    }
+}
+
+impl SpanlessEq for TypeDef {
+    fn spanless_eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            // * Functions are the only types carrying code, and therefore spans
+            (TypeDef::Function(a), TypeDef::Function(b)) => {
+                a.is_extern == b.is_extern
+                    && a.params.spanless_eq(&b.params)
+                    && a.return_type.spanless_eq(&b.return_type)
+                    && a.body.spanless_eq(&b.body)
+            }
+            (TypeDef::Ref(a), TypeDef::Ref(b)) => a.target.spanless_eq(&b.target),
+            (TypeDef::Array(a), TypeDef::Array(b)) => {
+                a.length == b.length && a.target.spanless_eq(&b.target)
+            }
+            (a, b) => a == b,
+        }
+    }
+}
+
+impl SpanlessEq for Stmt {
+    fn spanless_eq(&self, other: &Self) -> bool {
+        use StmtVariant::*;
+        match (&self.var, &other.var) {
+            (If(a), If(b)) => {
+                a.cond.spanless_eq(&b.cond)
+                    && a.if_block.spanless_eq(&b.if_block)
+                    && a.else_ifs.spanless_eq(&b.else_ifs)
+                    && a.else_block.spanless_eq(&b.else_block)
+            }
+            (While(a), While(b)) => {
+                a.label == b.label && a.cond.spanless_eq(&b.cond) && a.block.spanless_eq(&b.block)
+            }
+            (Block(a), Block(b)) => a.spanless_eq(b),
+            (Expr(a), Expr(b)) => a.spanless_eq(b),
+            (Print(a), Print(b)) => a.spanless_eq(b),
+            (Scan(a, _), Scan(b, _)) => a == b,
+            (ManyExpr(a), ManyExpr(b)) => a.spanless_eq(b),
+            (Return(a), Return(b)) => a.spanless_eq(b),
+            (Break(a), Break(b)) => a == b,
+            (Empty, Empty) => true,
+            _ => false,
+        }
+    }
+}
+
+impl SpanlessEq for Expr {
+    fn spanless_eq(&self, other: &Self) -> bool {
+        use ExprVariant::*;
+        match (&self.var, &other.var) {
+            (Ident(a), Ident(b)) => a == b,
+            (Literal(a), Literal(b)) => a == b,
+            (TypeConversion(a), TypeConversion(b)) => {
+                a.to.spanless_eq(&b.to) && a.expr.spanless_eq(&b.expr)
+            }
+            (UnaryOp(a), UnaryOp(b)) => a.op == b.op && a.val.spanless_eq(&b.val),
+            (BinaryOp(a), BinaryOp(b)) => {
+                a.op == b.op && a.lhs.spanless_eq(&b.lhs) && a.rhs.spanless_eq(&b.rhs)
+            }
+            (FunctionCall(a), FunctionCall(b)) => {
+                a.func == b.func && a.params.spanless_eq(&b.params)
+            }
+            (StructChild(a), StructChild(b)) => a.idx == b.idx && a.val.spanless_eq(&b.val),
+            (ArrayChild(a), ArrayChild(b)) => {
+                a.val.spanless_eq(&b.val) && a.idx.spanless_eq(&b.idx)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Edits turning `a` into `b`, in chars, each inserting, deleting or
+/// changing one or swapping two next to each other
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    // * Distances from prefixes of `a` two, one and zero chars shorter
+    let mut before: Vec<usize> = vec![];
+    let mut last: Vec<usize> = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let change = last[j - 1] + (a[i - 1] != b[j - 1]) as usize;
+            row[j] = change.min(last[j] + 1).min(row[j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(before[j - 2] + 1);
+            }
+        }
+        before = core::mem::replace(&mut last, row);
+    }
+    last[b.len()]
+}
@@ -0,0 +1,275 @@
+//! Structural differences between two programs.
+//!
+//! Programs are compared by their syntax trees, so formatting, comments and
+//! redundant parentheses make no difference. Each block is compared in two
+//! parts: what it declares, matched by name, then its statements, lined up
+//! by their longest common subsequence. Statements left over on both sides
+//! are paired in order, and pairs of the same kind are compared part by
+//! part, so a change is reported at the smallest expression or statement
+//! that differs.
+
+use super::ast::*;
+use crate::prelude::*;
+use core::fmt;
+
+/// A node of the syntax tree, as reported in a [`Change`]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Node {
+    Function(String),
+    /// A variable, global or local, or a parameter
+    Var(String),
+    Stmt,
+    Expr,
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Node::Function(name) => write!(f, "function '{}'", name),
+            Node::Var(name) => write!(f, "variable '{}'", name),
+            Node::Stmt => write!(f, "statement"),
+            Node::Expr => write!(f, "expression"),
+        }
+    }
+}
+
+/// One difference between an old and a new program. Declarations of
+/// functions and variables are at the span of their name or signature.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Change {
+    /// A node only in the new program, at this span of it
+    Added(Node, Span),
+    /// A node only in the old program, at this span of it
+    Removed(Node, Span),
+    /// A node at `old` in the old program that is different at `new` in
+    /// the new one
+    Changed { node: Node, old: Span, new: Span },
+}
+
+/// Differences from `old` to `new`, block by block in the order of `old`.
+/// Empty if they are [`ast_eq`].
+pub fn ast_diff(old: &Program, new: &Program) -> Vec<Change> {
+    let mut differ = Differ { changes: vec![] };
+    differ.block(&old.blk, &new.blk);
+    differ.changes
+}
+
+struct Differ {
+    changes: Vec<Change>,
+}
+
+/// Type, constness and span of a variable or function
+fn var_of(def: &SymbolDef) -> Option<(&Ptr<TypeDef>, bool, Span)> {
+    match def {
+        SymbolDef::Var {
+            typ,
+            is_const,
+            decl_span,
+            ..
+        } => Some((typ, *is_const, *decl_span)),
+        SymbolDef::Typ { .. } => None,
+    }
+}
+
+fn node_of(name: &str, typ: &TypeDef) -> Node {
+    match typ {
+        TypeDef::Function(_) => Node::Function(name.into()),
+        _ => Node::Var(name.into()),
+    }
+}
+
+impl Differ {
+    fn block(&mut self, old: &Block, new: &Block) {
+        self.defs(&old.scope.borrow(), &new.scope.borrow());
+        self.stmts(&old.stmts, &new.stmts);
+    }
+
+    /// Compare what two scopes declare, and the bodies of functions
+    /// declared in both
+    fn defs(&mut self, old: &Scope, new: &Scope) {
+        for (name, old_def) in &old.defs {
+            let old_def = old_def.borrow();
+            let (old_typ, old_const, old_span) = match var_of(&old_def) {
+                Some(var) => var,
+                None => continue,
+            };
+            let old_typ = old_typ.borrow();
+            let new_def = new.defs.get(name).map(|def| def.borrow());
+            let (new_typ, new_const, new_span) = match new_def.as_deref().and_then(var_of) {
+                Some(var) => var,
+                None => {
+                    let removed = Change::Removed(node_of(name, &old_typ), old_span);
+                    self.changes.push(removed);
+                    continue;
+                }
+            };
+            let new_typ = new_typ.borrow();
+            match (&*old_typ, &*new_typ) {
+                (TypeDef::Function(a), TypeDef::Function(b)) => {
+                    let same_signature = a.is_extern == b.is_extern
+                        && a.params.spanless_eq(&b.params)
+                        && a.return_type.spanless_eq(&b.return_type);
+                    if !same_signature {
+                        self.changed(Node::Function(name.to_string()), old_span, new_span);
+                    }
+                    if let (Some(a), Some(b)) = (&a.body, &b.body) {
+                        self.block(a, b);
+                    }
+                }
+                // * A function turned into a variable, or the other way round
+                (TypeDef::Function(_), _) | (_, TypeDef::Function(_)) => {
+                    let removed = Change::Removed(node_of(name, &old_typ), old_span);
+                    self.changes.push(removed);
+                    let added = Change::Added(node_of(name, &new_typ), new_span);
+                    self.changes.push(added);
+                }
+                (a, b) => {
+                    if old_const != new_const || !a.spanless_eq(b) {
+                        self.changed(Node::Var(name.to_string()), old_span, new_span);
+                    }
+                }
+            }
+        }
+        for (name, new_def) in &new.defs {
+            if old.defs.contains_key(name) {
+                continue;
+            }
+            if let Some((typ, _, span)) = var_of(&new_def.borrow()) {
+                let added = Change::Added(node_of(name, &typ.borrow()), span);
+                self.changes.push(added);
+            }
+        }
+    }
+
+    /// Compare two lists of statements. Empty statements are left out, as
+    /// are the ones functions are parsed into.
+    fn stmts(&mut self, old: &[Stmt], new: &[Stmt]) {
+        let is_empty = |s: &&Stmt| matches!(s.var, StmtVariant::Empty);
+        let old: Vec<_> = old.iter().filter(|s| !is_empty(s)).collect();
+        let new: Vec<_> = new.iter().filter(|s| !is_empty(s)).collect();
+
+        // * `common[i][j]` is the length of the longest common subsequence of
+        // * `old[i..]` and `new[j..]`
+        let (n, m) = (old.len(), new.len());
+        let same: Vec<Vec<bool>> = (old.iter())
+            .map(|a| new.iter().map(|b| a.spanless_eq(b)).collect())
+            .collect();
+        let mut common = vec![vec![0usize; m + 1]; n + 1];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                common[i][j] = if same[i][j] {
+                    common[i + 1][j + 1] + 1
+                } else {
+                    common[i + 1][j].max(common[i][j + 1])
+                };
+            }
+        }
+
+        let (mut i, mut j) = (0, 0);
+        let (mut removed, mut added) = (vec![], vec![]);
+        while i < n || j < m {
+            if i < n && j < m && same[i][j] {
+                self.unmatched(&removed, &added);
+                removed.clear();
+                added.clear();
+                i += 1;
+                j += 1;
+            } else if j == m || (i < n && common[i + 1][j] >= common[i][j + 1]) {
+                removed.push(old[i]);
+                i += 1;
+            } else {
+                added.push(new[j]);
+                j += 1;
+            }
+        }
+        self.unmatched(&removed, &added);
+    }
+
+    /// Report statements between two that are the same. They are paired up
+    /// in order, and the rest are removed or added.
+    fn unmatched(&mut self, removed: &[&Stmt], added: &[&Stmt]) {
+        for (a, b) in removed.iter().zip(added) {
+            self.stmt(a, b);
+        }
+        for a in removed.iter().skip(added.len()) {
+            self.changes.push(Change::Removed(Node::Stmt, a.span));
+        }
+        for b in added.iter().skip(removed.len()) {
+            self.changes.push(Change::Added(Node::Stmt, b.span));
+        }
+    }
+
+    fn stmt(&mut self, old: &Stmt, new: &Stmt) {
+        use StmtVariant::*;
+        maybe_grow(|| {
+            if old.spanless_eq(new) {
+                return;
+            }
+            match (&old.var, &new.var) {
+                (If(a), If(b)) if a.else_ifs.len() == b.else_ifs.len() => {
+                    self.expr(&a.cond, &b.cond);
+                    self.stmt(&a.if_block.borrow(), &b.if_block.borrow());
+                    for ((a_cond, a_blk), (b_cond, b_blk)) in a.else_ifs.iter().zip(&b.else_ifs) {
+                        self.expr(a_cond, b_cond);
+                        self.stmt(&a_blk.borrow(), &b_blk.borrow());
+                    }
+                    match (&a.else_block, &b.else_block) {
+                        (Some(a), Some(b)) => self.stmt(&a.borrow(), &b.borrow()),
+                        (Some(a), None) => {
+                            self.changes
+                                .push(Change::Removed(Node::Stmt, a.borrow().span));
+                        }
+                        (None, Some(b)) => {
+                            self.changes
+                                .push(Change::Added(Node::Stmt, b.borrow().span));
+                        }
+                        (None, None) => (),
+                    }
+                }
+                (While(a), While(b)) if a.label == b.label => {
+                    self.expr(&a.cond, &b.cond);
+                    self.stmt(&a.block.borrow(), &b.block.borrow());
+                }
+                (Block(a), Block(b)) => self.block(a, b),
+                (Expr(a), Expr(b)) | (Return(Some(a)), Return(Some(b))) => self.expr(a, b),
+                (Print(a), Print(b)) | (ManyExpr(a), ManyExpr(b)) if a.len() == b.len() => {
+                    a.iter().zip(b).for_each(|(a, b)| self.expr(a, b));
+                }
+                _ => self.changed(Node::Stmt, old.span, new.span),
+            }
+        })
+    }
+
+    fn expr(&mut self, old: &Ptr<Expr>, new: &Ptr<Expr>) {
+        use ExprVariant::*;
+        maybe_grow(|| {
+            let (old, new) = (old.borrow(), new.borrow());
+            if old.spanless_eq(&new) {
+                return;
+            }
+            match (&old.var, &new.var) {
+                (UnaryOp(a), UnaryOp(b)) if a.op == b.op => self.expr(&a.val, &b.val),
+                (BinaryOp(a), BinaryOp(b)) if a.op == b.op => {
+                    self.expr(&a.lhs, &b.lhs);
+                    self.expr(&a.rhs, &b.rhs);
+                }
+                (TypeConversion(a), TypeConversion(b)) if a.to.spanless_eq(&b.to) => {
+                    self.expr(&a.expr, &b.expr)
+                }
+                (FunctionCall(a), FunctionCall(b))
+                    if a.func == b.func && a.params.len() == b.params.len() =>
+                {
+                    a.params
+                        .iter()
+                        .zip(&b.params)
+                        .for_each(|(a, b)| self.expr(a, b));
+                }
+                _ => self.changed(Node::Expr, old.span, new.span),
+            }
+        })
+    }
+
+    fn changed(&mut self, node: Node, old: Span, new: Span) {
+        self.changes.push(Change::Changed { node, old, new });
+    }
+}
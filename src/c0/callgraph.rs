@@ -0,0 +1,317 @@
+//! Which functions of a program call which.
+//!
+//! Names are resolved while parsing, so every call in the tree is to a
+//! function declared at the top level. Functions run from `main` and from the
+//! initializers of global variables, so those are where the graph is entered.
+
+use super::ast::*;
+use crate::prelude::*;
+use core::fmt::Write;
+
+/// A function in a [`CallGraph`]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct FnNode {
+    pub name: String,
+    /// Span of the declaration of the function
+    pub span: Span,
+    /// Whether the function has no body, so nothing is known of its calls
+    pub is_extern: bool,
+    /// Indices of the functions it calls, sorted and without duplicates
+    pub calls: Vec<usize>,
+}
+
+/// The call graph of a program
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CallGraph {
+    /// Functions in the order they are declared
+    pub fns: Vec<FnNode>,
+    /// Indices of the functions where the program starts: `main` and those
+    /// called by initializers of global variables
+    pub roots: Vec<usize>,
+}
+
+impl CallGraph {
+    pub fn new(prog: &Program) -> CallGraph {
+        let scope = prog.blk.scope.borrow();
+        let mut fns = vec![];
+        let mut bodies = vec![];
+        for (name, def) in scope.defs.iter() {
+            if let SymbolDef::Var { typ, decl_span, .. } = &*def.borrow() {
+                if let TypeDef::Function(func) = &*typ.borrow() {
+                    fns.push(FnNode {
+                        name: name.to_string(),
+                        span: *decl_span,
+                        is_extern: func.body.is_none(),
+                        calls: vec![],
+                    });
+                    bodies.push(func.body.clone());
+                }
+            }
+        }
+
+        let index = |names: Vec<String>| {
+            let mut idx: Vec<usize> = names
+                .iter()
+                .filter_map(|name| fns.iter().position(|f| &f.name == name))
+                .collect();
+            idx.sort_unstable();
+            idx.dedup();
+            idx
+        };
+        let calls: Vec<_> = bodies
+            .iter()
+            .map(|body| {
+                let mut names = vec![];
+                if let Some(body) = body {
+                    body.stmts.iter().for_each(|s| stmt_calls(s, &mut names));
+                }
+                index(names)
+            })
+            .collect();
+        let mut names = vec![];
+        prog.blk
+            .stmts
+            .iter()
+            .for_each(|s| stmt_calls(s, &mut names));
+        let mut roots = index(names);
+        if let Some(main) = fns.iter().position(|f| f.name == "main") {
+            roots.push(main);
+            roots.sort_unstable();
+            roots.dedup();
+        }
+
+        for (node, calls) in fns.iter_mut().zip(calls) {
+            node.calls = calls;
+        }
+        CallGraph { fns, roots }
+    }
+
+    /// Index of function `name`
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.fns.iter().position(|f| f.name == name)
+    }
+
+    /// Whether each function can be called when the program runs
+    pub fn reachable(&self) -> Vec<bool> {
+        let mut seen = vec![false; self.fns.len()];
+        let mut stack = self.roots.clone();
+        while let Some(f) = stack.pop() {
+            if !seen[f] {
+                seen[f] = true;
+                stack.extend(&self.fns[f].calls);
+            }
+        }
+        seen
+    }
+
+    /// Indices of functions never called when the program runs
+    pub fn unreachable(&self) -> Vec<usize> {
+        let reachable = self.reachable();
+        (0..self.fns.len()).filter(|&f| !reachable[f]).collect()
+    }
+
+    /// Groups of functions that call each other, directly or not. Each group
+    /// is sorted, and groups are ordered by their first function. A function
+    /// calling itself is a group of one.
+    pub fn cycles(&self) -> Vec<Vec<usize>> {
+        let mut tarjan = Tarjan {
+            graph: self,
+            index: vec![None; self.fns.len()],
+            low: vec![0; self.fns.len()],
+            stack: vec![],
+            on_stack: vec![false; self.fns.len()],
+            next: 0,
+            sccs: vec![],
+        };
+        for f in 0..self.fns.len() {
+            if tarjan.index[f].is_none() {
+                tarjan.visit(f);
+            }
+        }
+        let mut cycles: Vec<_> = tarjan
+            .sccs
+            .into_iter()
+            .filter(|scc| scc.len() > 1 || self.fns[scc[0]].calls.contains(&scc[0]))
+            .map(|mut scc| {
+                scc.sort_unstable();
+                scc
+            })
+            .collect();
+        cycles.sort_unstable();
+        cycles
+    }
+
+    /// Whether the call from `from` to `to` is part of a cycle
+    pub fn is_recursive_call(&self, cycles: &[Vec<usize>], from: usize, to: usize) -> bool {
+        cycles.iter().any(|c| c.contains(&from) && c.contains(&to))
+    }
+
+    /// The most frames on the call stack at once, counting the roots, or
+    /// `None` if a recursive function can be reached and there is no bound.
+    /// Functions without a body count as one frame.
+    pub fn max_depth(&self) -> Option<usize> {
+        let cycles = self.cycles();
+        let reachable = self.reachable();
+        if cycles.iter().flatten().any(|&f| reachable[f]) {
+            return None;
+        }
+        // * Without cycles the graph is a DAG, so depths can be memoized
+        let mut depth = vec![None; self.fns.len()];
+        self.roots
+            .iter()
+            .map(|&f| self.depth(f, &mut depth))
+            .max()
+            .or(Some(0))
+    }
+
+    fn depth(&self, f: usize, memo: &mut Vec<Option<usize>>) -> usize {
+        if let Some(d) = memo[f] {
+            return d;
+        }
+        let d = 1 + self.fns[f]
+            .calls
+            .iter()
+            .map(|&c| self.depth(c, memo))
+            .max()
+            .unwrap_or(0);
+        memo[f] = Some(d);
+        d
+    }
+
+    /// The graph in Graphviz DOT. Functions never called are dashed, and
+    /// calls that recurse are red.
+    pub fn to_dot(&self) -> String {
+        let reachable = self.reachable();
+        let cycles = self.cycles();
+        let mut out = String::from("digraph callgraph {\n");
+        match self.max_depth() {
+            Some(d) => writeln!(out, "    label=\"max call depth: {}\";", d).unwrap(),
+            None => writeln!(out, "    label=\"max call depth: unbounded\";").unwrap(),
+        }
+        for (idx, f) in self.fns.iter().enumerate() {
+            let mut attrs = vec![];
+            if self.roots.contains(&idx) {
+                attrs.push("peripheries=2");
+            }
+            if !reachable[idx] {
+                attrs.push("style=dashed");
+            }
+            if f.is_extern {
+                attrs.push("shape=box");
+            }
+            write!(out, "    \"{}\"", f.name).unwrap();
+            if !attrs.is_empty() {
+                write!(out, " [{}]", attrs.join(", ")).unwrap();
+            }
+            out.push_str(";\n");
+        }
+        for (idx, f) in self.fns.iter().enumerate() {
+            for &callee in &f.calls {
+                write!(out, "    \"{}\" -> \"{}\"", f.name, self.fns[callee].name).unwrap();
+                if self.is_recursive_call(&cycles, idx, callee) {
+                    out.push_str(" [color=red]");
+                }
+                out.push_str(";\n");
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+/// Tarjan's algorithm for strongly connected components
+struct Tarjan<'a> {
+    graph: &'a CallGraph,
+    index: Vec<Option<usize>>,
+    low: Vec<usize>,
+    stack: Vec<usize>,
+    on_stack: Vec<bool>,
+    next: usize,
+    sccs: Vec<Vec<usize>>,
+}
+
+impl Tarjan<'_> {
+    fn visit(&mut self, f: usize) {
+        self.index[f] = Some(self.next);
+        self.low[f] = self.next;
+        self.next += 1;
+        self.stack.push(f);
+        self.on_stack[f] = true;
+
+        for &callee in &self.graph.fns[f].calls {
+            match self.index[callee] {
+                None => {
+                    self.visit(callee);
+                    self.low[f] = self.low[f].min(self.low[callee]);
+                }
+                Some(idx) if self.on_stack[callee] => self.low[f] = self.low[f].min(idx),
+                Some(_) => (),
+            }
+        }
+
+        if Some(self.low[f]) == self.index[f] {
+            let mut scc = vec![];
+            loop {
+                let g = self.stack.pop().unwrap();
+                self.on_stack[g] = false;
+                scc.push(g);
+                if g == f {
+                    break;
+                }
+            }
+            self.sccs.push(scc);
+        }
+    }
+}
+
+/// Names of functions called in `stmt`
+fn stmt_calls(stmt: &Stmt, out: &mut Vec<String>) {
+    maybe_grow(|| match &stmt.var {
+        StmtVariant::If(i) => {
+            expr_calls(&i.cond, out);
+            stmt_calls(&i.if_block.borrow(), out);
+            for (cond, blk) in &i.else_ifs {
+                expr_calls(cond, out);
+                stmt_calls(&blk.borrow(), out);
+            }
+            if let Some(blk) = &i.else_block {
+                stmt_calls(&blk.borrow(), out);
+            }
+        }
+        StmtVariant::While(w) => {
+            expr_calls(&w.cond, out);
+            stmt_calls(&w.block.borrow(), out);
+        }
+        StmtVariant::Block(b) => b.stmts.iter().for_each(|s| stmt_calls(s, out)),
+        StmtVariant::Expr(e) | StmtVariant::Return(Some(e)) => expr_calls(e, out),
+        StmtVariant::Print(es) | StmtVariant::ManyExpr(es) => {
+            es.iter().for_each(|e| expr_calls(e, out))
+        }
+        StmtVariant::Scan(..)
+        | StmtVariant::Return(None)
+        | StmtVariant::Break(_)
+        | StmtVariant::Empty => (),
+    })
+}
+
+/// Names of functions called in `expr`
+fn expr_calls(expr: &Ptr<Expr>, out: &mut Vec<String>) {
+    maybe_grow(|| match &expr.borrow().var {
+        ExprVariant::Ident(_) | ExprVariant::Literal(_) => (),
+        ExprVariant::TypeConversion(t) => expr_calls(&t.expr, out),
+        ExprVariant::UnaryOp(u) => expr_calls(&u.val, out),
+        ExprVariant::BinaryOp(b) => {
+            expr_calls(&b.lhs, out);
+            expr_calls(&b.rhs, out);
+        }
+        ExprVariant::FunctionCall(f) => {
+            out.push(f.func.to_string());
+            f.params.iter().for_each(|p| expr_calls(p, out));
+        }
+        ExprVariant::StructChild(s) => expr_calls(&s.val, out),
+        ExprVariant::ArrayChild(a) => {
+            expr_calls(&a.val, out);
+            expr_calls(&a.idx, out);
+        }
+    })
+}
@@ -0,0 +1,113 @@
+//! API listings of programs, from their `///` doc comments.
+//!
+//! Constants, global variables and functions are listed in that order, each
+//! in source order, with the declaration as written in C0 and the doc
+//! comment before it. Declarations without one are listed too. Doc comments
+//! are split into paragraphs at blank lines, and text in backticks is code.
+
+use super::ast::Program;
+use super::ide::{document_symbols, SymbolInfo, SymbolKind};
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum DocFormat {
+    Markdown,
+    /// A page of its own, without styles or scripts
+    Html,
+}
+
+/// Sections of a listing, and the kind of symbols in each
+const SECTIONS: &[(&str, SymbolKind)] = &[
+    ("Constants", SymbolKind::Constant),
+    ("Global variables", SymbolKind::Variable),
+    ("Functions", SymbolKind::Function),
+];
+
+/// List the top level declarations of `prog` under the heading `title`
+pub fn render(prog: &Program, title: &str, format: DocFormat) -> String {
+    let symbols = document_symbols(prog);
+    let sections = SECTIONS.iter().map(|(heading, kind)| {
+        let items: Vec<_> = symbols.iter().filter(|s| s.kind == *kind).collect();
+        (*heading, items)
+    });
+    let sections: Vec<_> = sections.filter(|(_, items)| !items.is_empty()).collect();
+    match format {
+        DocFormat::Markdown => markdown(title, &sections),
+        DocFormat::Html => html(title, &sections),
+    }
+}
+
+/// Paragraphs of a doc comment, each on one line
+fn paragraphs(doc: &str) -> Vec<String> {
+    doc.split("\n\n")
+        .map(|p| p.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|p| !p.is_empty())
+        .collect()
+}
+
+fn markdown(title: &str, sections: &[(&str, Vec<&SymbolInfo>)]) -> String {
+    let mut out = format!("# {}\n", title);
+    for (heading, items) in sections {
+        write!(out, "\n## {}\n", heading).unwrap();
+        for item in items {
+            write!(out, "\n### `{}`\n\n```c\n{}\n```\n", item.name, item.detail).unwrap();
+            for paragraph in paragraphs(item.doc.as_deref().unwrap_or("")) {
+                write!(out, "\n{}\n", paragraph).unwrap();
+            }
+        }
+    }
+    out
+}
+
+fn html(title: &str, sections: &[(&str, Vec<&SymbolInfo>)]) -> String {
+    let mut out = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    writeln!(out, "<title>{}</title>\n</head>\n<body>", escape(title)).unwrap();
+    writeln!(out, "<h1>{}</h1>", escape(title)).unwrap();
+    for (heading, items) in sections {
+        writeln!(out, "<h2>{}</h2>", heading).unwrap();
+        for item in items {
+            let name = escape(&item.name);
+            writeln!(out, "<section id=\"{}\">", name).unwrap();
+            writeln!(out, "<h3><code>{}</code></h3>", name).unwrap();
+            writeln!(out, "<pre><code>{}</code></pre>", escape(&item.detail)).unwrap();
+            for paragraph in paragraphs(item.doc.as_deref().unwrap_or("")) {
+                writeln!(out, "<p>{}</p>", inline_code(&paragraph)).unwrap();
+            }
+            out += "</section>\n";
+        }
+    }
+    out += "</body>\n</html>\n";
+    out
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::new();
+    for c in text.chars() {
+        match c {
+            '&' => out += "&amp;",
+            '<' => out += "&lt;",
+            '>' => out += "&gt;",
+            '"' => out += "&quot;",
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Escape `text`, putting text in backticks in `<code>`. A backtick without
+/// a closing one is left as it is.
+fn inline_code(text: &str) -> String {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('`') {
+        let end = match rest[start + 1..].find('`') {
+            Some(len) => start + 1 + len,
+            None => break,
+        };
+        out += &escape(&rest[..start]);
+        write!(out, "<code>{}</code>", escape(&rest[start + 1..end])).unwrap();
+        rest = &rest[end + 1..];
+    }
+    out += &escape(rest);
+    out
+}
@@ -1,16 +1,6 @@
-use crate::c0::lexer::TokenType;
+use crate::error::{ErrorCode, Fix};
 use crate::prelude::*;
-use std::str::{Chars, FromStr};
-use std::{
-    cell::{Ref, RefCell, RefMut},
-    cmp::PartialOrd,
-    fmt,
-    fmt::Display,
-    fmt::Formatter,
-    hash::Hash,
-    rc::{Rc, Weak},
-    string::String,
-};
+use core::fmt::{self, Display, Formatter};
 
 use failure::*;
 
@@ -20,6 +10,7 @@ pub fn parse_err(var: ParseErrVariant, span: Span) -> ParseError {
     ParseError {
         var,
         span,
+        fix: None,
         backtrace: Backtrace::new(),
     }
 }
@@ -28,6 +19,7 @@ pub fn parse_err_z(var: ParseErrVariant) -> ParseError {
     ParseError {
         var,
         span: Span::zero(),
+        fix: None,
         backtrace: Backtrace::new(),
     }
 }
@@ -39,11 +31,20 @@ pub fn parse_err_z(var: ParseErrVariant) -> ParseError {
 pub struct ParseError {
     pub var: ParseErrVariant,
     pub span: Span,
+    /// A change to the source that fixes the error, if one is known
+    pub fix: Option<Fix>,
     pub backtrace: Backtrace,
 }
 
+impl ParseError {
+    pub fn with_fix(mut self, fix: Fix) -> ParseError {
+        self.fix = Some(fix);
+        self
+    }
+}
+
 impl Display for ParseError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         write!(f, "{} at {}", self.var, self.span)
     }
 }
@@ -55,23 +56,102 @@ pub enum LexError {
     BadEscaping,
     UnexpectedCharacter(char),
     BadInteger,
+    NumberOutOfRange,
     MalformedString,
     UnexpectedEOL,
     UnexpectedEOF,
     ReservedWord(String),
+    /// `0b`, `0o` or `0x` with no digits after it
+    NoDigits(String),
+    /// A digit too big for the base of the literal
+    BadDigit(char, u32),
+    /// Letters right after a number
+    BadSuffix(String),
+    /// A `.` after the fraction or exponent, or in a literal not in base 10
+    MisplacedPoint,
+    EmptyExponent,
+}
+
+impl LexError {
+    /// Whether the error is in a number literal, which is read whole and
+    /// can stand for a number while parsing goes on
+    pub fn is_number(&self) -> bool {
+        use self::LexError::*;
+        matches!(
+            self,
+            BadInteger
+                | NumberOutOfRange
+                | NoDigits(_)
+                | BadDigit(..)
+                | BadSuffix(_)
+                | MisplacedPoint
+                | EmptyExponent
+        )
+    }
+}
+
+impl LexError {
+    pub fn code(&self) -> ErrorCode {
+        use self::LexError::*;
+        ErrorCode(match self {
+            BadEscaping => 1,
+            UnexpectedCharacter(_) => 2,
+            BadInteger => 3,
+            NumberOutOfRange => 4,
+            MalformedString => 5,
+            UnexpectedEOL => 6,
+            UnexpectedEOF => 7,
+            ReservedWord(_) => 8,
+            NoDigits(_) => 9,
+            BadDigit(..) => 10,
+            BadSuffix(_) => 11,
+            MisplacedPoint => 12,
+            EmptyExponent => 13,
+        })
+    }
+}
+
+impl Display for LexError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        use self::LexError::*;
+        match self {
+            BadEscaping => write!(f, "Bad escaping sequence"),
+            UnexpectedCharacter(ch) => write!(f, "Unexpected character {:?}", ch),
+            BadInteger => write!(f, "Malformed integer literal"),
+            NumberOutOfRange => write!(f, "Number literal is out of range"),
+            MalformedString => write!(f, "Malformed string literal"),
+            UnexpectedEOL => write!(f, "Literal is not closed before the end of line"),
+            UnexpectedEOF => write!(f, "Literal is not closed before the end of file"),
+            ReservedWord(word) => write!(f, "'{}' is a reserved word", word),
+            NoDigits(prefix) => write!(f, "Expected digits after '{}'", prefix),
+            BadDigit(ch, base) => write!(f, "'{}' is not a digit in base {}", ch, base),
+            BadSuffix(suffix) => write!(f, "Number literal has a bad suffix '{}'", suffix),
+            MisplacedPoint => write!(f, "Decimal point cannot be here in a number literal"),
+            EmptyExponent => write!(f, "Exponent of a number literal has no digits"),
+        }
+    }
 }
 
 #[derive(Debug)]
 pub enum ParseErrVariant {
     InvalidToken(String),
-    BadEscaping { cause: Box<dyn Fail> },
+    BadEscaping {
+        cause: Box<dyn Fail>,
+    },
 
-    ExpectToken(TokenType, TokenType),
-    ExpectTokenOneOf(Vec<TokenType>, TokenType),
-    UnexpectedToken(TokenType),
-    UnexpectedTokenMsg { typ: TokenType, msg: &'static str },
+    // * Tokens are kept as they are shown, as symbols in them only mean
+    // * something on the thread that read them
+    ExpectToken(String, String),
+    ExpectTokenOneOf(Vec<String>, String),
+    UnexpectedToken(String),
+    UnexpectedTokenMsg {
+        typ: String,
+        msg: &'static str,
+    },
     NoConstFns,
     ConstTypeNeedExplicitInitialization,
+    ControlFlowInExpr(String),
+    BreakWithValue,
 
     CannotFindIdent(String),
     CannotFindType(String),
@@ -82,11 +162,31 @@ pub enum ParseErrVariant {
     ExpectToBeVar(String),
     ExpectToBeFn(String),
 
-    UnsupportedToken(TokenType),
+    UnsupportedToken(String),
+    /// A delimiter opened at the span that is not closed where it should
+    /// be, at the end of the file or at a closer of another kind
+    UnclosedDelimiter(String, Span),
+    /// An `#else` or `#endif` with no `#if` open
+    UnmatchedDirective(String),
+    /// An `#if` never closed by `#endif`
+    UnclosedIf,
+    /// Text after `#if` that is not the name of a feature
+    BadFeature(String),
+    /// Text after a directive taking nothing
+    DirectiveArgument(String),
+    /// Directives inside a statement, which formatting would move
+    DirectiveInStatement,
 
     DuplicateDeclaration(String),
     BadIdentifier(String),
     ConflictingDeclaration(String),
+    /// Two parameters of a function with the same name
+    DuplicateParameter(String),
+    /// A variable declared in the outermost block of a function with the
+    /// name of one of its parameters
+    RedefinedParameter(String),
+    /// A function declared `auto`, whose return type cannot be inferred
+    AutoFunction(String),
     EarlyEof,
 
     MissingOperandUnary,
@@ -100,6 +200,61 @@ pub enum ParseErrVariant {
 }
 
 impl ParseErrVariant {
+    pub fn code(&self) -> ErrorCode {
+        use self::ParseErrVariant::*;
+        ErrorCode(match self {
+            LexerErr(l) => return l.code(),
+            BadEscaping { .. } => 1,
+
+            InvalidToken(_) => 100,
+            ExpectToken(..) => 101,
+            ExpectTokenOneOf(..) => 102,
+            UnexpectedToken(_) | UnexpectedTokenMsg { .. } => 103,
+            NoConstFns => 104,
+            ConstTypeNeedExplicitInitialization => 105,
+            ControlFlowInExpr(_) => 106,
+            BreakWithValue => 107,
+            EarlyEof => 108,
+            MissingOperandUnary | MissingOperandL | MissingOperandR => 109,
+            UnsupportedToken(_) => 110,
+            UnclosedDelimiter(..) => 111,
+            UnmatchedDirective(_) => 113,
+            UnclosedIf => 114,
+            BadFeature(_) => 115,
+            DirectiveArgument(_) => 116,
+            DirectiveInStatement => 117,
+
+            CannotFindIdent(_) => 201,
+            CannotFindType(_) => 202,
+            CannotFindVar(_) => 203,
+            CannotFindFn(_) => 204,
+            ExpectToBeType(_) => 205,
+            ExpectToBeVar(_) => 206,
+            ExpectToBeFn(_) => 207,
+            DuplicateDeclaration(_) => 208,
+            BadIdentifier(_) => 209,
+            ConflictingDeclaration(_) => 210,
+            DuplicateParameter(_) => 211,
+            RedefinedParameter(_) => 212,
+            AutoFunction(_) => 213,
+
+            NotMatchFnArguments(..) => 305,
+
+            CustomErr(_) => 900,
+            InternalErr(_) => 999,
+        })
+    }
+
+    /// A note pointing at more of the source, with where it is
+    pub fn note(&self) -> Option<(&'static str, Span)> {
+        match self {
+            ParseErrVariant::UnclosedDelimiter(_, open) => {
+                Some(("unclosed delimiter opened here", *open))
+            }
+            _ => None,
+        }
+    }
+
     pub fn get_err_desc(&self) -> String {
         use self::ParseErrVariant::*;
         match self {
@@ -108,14 +263,22 @@ impl ParseErrVariant {
 
             ExpectToken(expected, found) => format!("Expected {}, found {}", expected, found),
             ExpectTokenOneOf(expected, found) => {
-                format!("Expected to be one of {:?}, found {}", expected, found)
+                format!(
+                    "Expected to be one of [{}], found {}",
+                    expected.join(", "),
+                    found
+                )
             }
             UnexpectedToken(found) => format!("Unexpected token {}", found),
             UnexpectedTokenMsg { typ, msg } => format!("Unexpected token {}: {}", typ, msg),
-            NoConstFns => format!("Functions cannot be constant"),
+            NoConstFns => "Functions cannot be constant".to_string(),
             ConstTypeNeedExplicitInitialization => {
-                format!("Constant values need explicit initialization")
+                "Constant values need explicit initialization".to_string()
+            }
+            ControlFlowInExpr(typ) => {
+                format!("{} is a statement and cannot be used as an expression", typ)
             }
+            BreakWithValue => "Loops do not produce a value; `break` cannot carry one".to_string(),
 
             CannotFindIdent(ident) => format!("Unable to find identifier: {}", ident),
             CannotFindType(ty) => format!("Unable to find type: {}", ty),
@@ -131,31 +294,49 @@ impl ParseErrVariant {
                 typ
             ),
 
+            UnclosedDelimiter(open, _) => format!("Unclosed delimiter {}", open),
+            UnmatchedDirective(name) => format!("'#{}' has no '#if' before it", name),
+            UnclosedIf => "'#if' is not closed by '#endif'".to_string(),
+            BadFeature(found) => format!(
+                "Expected the name of a feature after '#if', found '{}'",
+                found
+            ),
+            DirectiveArgument(name) => format!("'#{}' takes nothing after it", name),
+            DirectiveInStatement => "Directives inside a statement cannot be formatted".to_string(),
+
             DuplicateDeclaration(ident) => format!("Identifier '{}' is declared before", ident),
             BadIdentifier(ident) => format!("Identifier '{}' is invalid", ident),
             ConflictingDeclaration(ident) => {
                 format!("Identifier '{}' has conflicting declarations", ident)
             }
-            EarlyEof => format!("The file unexpectedly ends"),
+            DuplicateParameter(ident) => format!("Parameter '{}' is declared twice", ident),
+            RedefinedParameter(ident) => format!(
+                "Variable '{}' redefines a parameter; declare it in a nested block to shadow it",
+                ident
+            ),
+            AutoFunction(ident) => format!(
+                "Function '{}' cannot be declared `auto`; write its return type",
+                ident
+            ),
+            EarlyEof => "The file unexpectedly ends".to_string(),
 
-            MissingOperandUnary => format!("Unary operator is missing its operand"),
-            MissingOperandL => format!("Binary operator is missing its left operand"),
-            MissingOperandR => format!("Binary operator is missing its right operand"),
+            MissingOperandUnary => "Unary operator is missing its operand".to_string(),
+            MissingOperandL => "Binary operator is missing its left operand".to_string(),
+            MissingOperandR => "Binary operator is missing its right operand".to_string(),
 
             NotMatchFnArguments(expected, found) => format!(
                 "Function arguments mismatch. Expected: {}, found: {}",
                 expected, found
             ),
-            LexerErr(l) => format!("{:?}", l),
-            CustomErr(err) => format!("{}", err),
+            LexerErr(l) => l.to_string(),
+            CustomErr(err) => err.to_string(),
             InternalErr(internal) => format!("Internal error inside compiler: {}", internal),
-            _ => "Unknown Error".to_string(),
         }
     }
 }
 
 impl Display for ParseErrVariant {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.get_err_desc())
     }
 }
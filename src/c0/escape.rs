@@ -0,0 +1,43 @@
+//! Which local variables have their address escape the call they belong to.
+//!
+//! `&x` of a local `x` is the address of its slot on the stack, which is only
+//! valid while the call declaring `x` runs. As long as the address only goes
+//! into other locals and into arguments of calls, which return before the
+//! caller does, `x` stays in its slot. Its address escapes if it may be
+//! returned, stored in a global, or stored through a reference, where it
+//! could outlive the call.
+//!
+//! Codegen boxes the locals whose address escapes: where the variable comes
+//! into scope, `new` allocates its value on the heap, and its slot holds the
+//! address of that instead. The box of a parameter starts with the value
+//! passed. Every use of the variable goes through its slot to the box, and
+//! `&x` is the address of the box, which stays valid to the end of the
+//! program. The VM never frees memory, so each time a boxed variable comes
+//! into scope takes more of the heap.
+//!
+//! The analysis does not look at the order of statements or at which branch
+//! runs, so it may box a variable that does not need it, but never misses
+//! one that does.
+
+use super::alias::Aliases;
+use alloc::collections::BTreeSet;
+use alloc::string::String;
+
+/// A variable, by the id of the scope it is declared in and its name, as in
+/// [`TypedProgram::inferred`](crate::c0::hir::TypedProgram::inferred)
+pub type VarKey = (usize, String);
+
+/// Local variables whose address escapes, by where `aliases` finds
+/// references going, which have to be boxed
+pub fn escaping(aliases: &Aliases) -> BTreeSet<VarKey> {
+    let in_globals = (aliases.points_to.iter())
+        .filter(|((id, _), _)| *id == 0)
+        .flat_map(|(_, addrs)| addrs);
+    let returned = (aliases.returned.iter()).flat_map(|(func, addrs)| {
+        (addrs.iter()).filter(move |a| aliases.owner.get(*a) == Some(func))
+    });
+    (in_globals.chain(returned).chain(&aliases.memory))
+        .filter(|(id, _)| *id != 0)
+        .cloned()
+        .collect()
+}
@@ -0,0 +1,285 @@
+//! Fingerprints of programs, for finding ones copied from each other.
+//!
+//! A program is turned into a stream of symbols by walking its syntax tree:
+//! one for each statement and expression, saying what kind it is. Names and
+//! values of literals are left out, and so are comments and layout, so
+//! renaming variables or changing constants makes no difference. Every `k`
+//! symbols in a row are hashed, and winnowing keeps the smallest hash of
+//! every `window` hashes in a row. Any run of `k + window - 1` symbols two
+//! programs share gives both of them a hash in common, whatever is around it.
+//!
+//! Programs are compared by the hashes they keep, so reordering functions or
+//! statements changes little.
+
+use super::ast::*;
+use super::pretty::{op_str, type_str};
+use crate::prelude::*;
+use alloc::collections::BTreeSet;
+use core::hash::{Hash, Hasher};
+
+/// How programs are fingerprinted
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct FingerprintConfig {
+    /// Symbols hashed together
+    pub k: usize,
+    /// Hashes the smallest one is kept of
+    pub window: usize,
+}
+
+impl Default for FingerprintConfig {
+    fn default() -> Self {
+        FingerprintConfig { k: 6, window: 4 }
+    }
+}
+
+/// A hash kept for a program, and the code it was hashed from
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Mark {
+    pub hash: u64,
+    pub span: Span,
+}
+
+/// The hashes kept for a program, in source order
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Fingerprint {
+    pub marks: Vec<Mark>,
+}
+
+impl Fingerprint {
+    /// Fingerprint `prog` with the default config
+    pub fn new(prog: &Program) -> Fingerprint {
+        Fingerprint::with_config(prog, &FingerprintConfig::default())
+    }
+
+    pub fn with_config(prog: &Program, config: &FingerprintConfig) -> Fingerprint {
+        let mut walker = Walker { syms: vec![] };
+        walker.block(&prog.blk);
+        Fingerprint {
+            marks: winnow(&walker.syms, config),
+        }
+    }
+
+    /// How much of the two programs is the same, from 0 (nothing) to 1:
+    /// the hashes they share out of all of their hashes. Two empty programs
+    /// are the same.
+    pub fn similarity(&self, other: &Fingerprint) -> f64 {
+        let ours = self.hashes();
+        let theirs = other.hashes();
+        let all = ours.union(&theirs).count();
+        if all == 0 {
+            return 1.0;
+        }
+        ours.intersection(&theirs).count() as f64 / all as f64
+    }
+
+    /// Code the two programs share: the span of each mark of `self` with a
+    /// hash `other` has too, and the span of the first such mark of `other`
+    pub fn shared(&self, other: &Fingerprint) -> Vec<(Span, Span)> {
+        (self.marks.iter())
+            .filter_map(|mark| {
+                let theirs = other.marks.iter().find(|m| m.hash == mark.hash)?;
+                Some((mark.span, theirs.span))
+            })
+            .collect()
+    }
+
+    fn hashes(&self) -> BTreeSet<u64> {
+        self.marks.iter().map(|m| m.hash).collect()
+    }
+}
+
+/// Keep the smallest hash of every `window` hashes of `k` symbols in a row,
+/// the rightmost one if there are several, and each of them once
+fn winnow(syms: &[(Sym, Span)], config: &FingerprintConfig) -> Vec<Mark> {
+    if syms.is_empty() {
+        return vec![];
+    }
+    let k = config.k.clamp(1, syms.len());
+    let grams: Vec<Mark> = (syms.windows(k))
+        .map(|gram| {
+            let mut hasher = FnvHasher::default();
+            gram.iter().for_each(|(sym, _)| sym.hash(&mut hasher));
+            let span = gram.iter().skip(1).fold(gram[0].1, |span, s| span + s.1);
+            Mark {
+                hash: hasher.finish(),
+                span,
+            }
+        })
+        .collect();
+
+    let window = config.window.clamp(1, grams.len());
+    let mut marks = vec![];
+    let mut last = None;
+    for (start, hashes) in grams.windows(window).enumerate() {
+        let (idx, _) = (hashes.iter().enumerate())
+            .min_by(|(i, a), (j, b)| a.hash.cmp(&b.hash).then(j.cmp(i)))
+            .unwrap();
+        let idx = start + idx;
+        if last != Some(idx) {
+            marks.push(grams[idx]);
+            last = Some(idx);
+        }
+    }
+    marks
+}
+
+/// What a statement or expression is, leaving out names and values
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+enum Sym {
+    /// A function with this many parameters, followed by its body
+    Function(usize),
+    /// A declaration of a variable of some type
+    Decl {
+        is_const: bool,
+        typ: String,
+    },
+    If,
+    ElseIf,
+    Else,
+    While,
+    Block,
+    /// End of a block or function
+    End,
+    Expr,
+    Print,
+    Scan,
+    Return,
+    Break,
+    Var,
+    Lit,
+    Conv(String),
+    Unary(&'static str),
+    Binary(&'static str),
+    /// A call with this many arguments
+    Call(usize),
+    Field,
+    Index,
+}
+
+/// Turns the syntax tree into symbols, in source order
+struct Walker {
+    syms: Vec<(Sym, Span)>,
+}
+
+impl Walker {
+    fn push(&mut self, sym: Sym, span: Span) {
+        self.syms.push((sym, span));
+    }
+
+    fn block(&mut self, blk: &Block) {
+        for stmt in &blk.stmts {
+            self.stmt(stmt, &blk.scope);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt, scope: &Ptr<Scope>) {
+        let span = stmt.span;
+        maybe_grow(|| match &stmt.var {
+            StmtVariant::If(i) => {
+                self.push(Sym::If, span);
+                self.expr(&i.cond);
+                self.stmt(&i.if_block.borrow(), scope);
+                for (cond, body) in &i.else_ifs {
+                    self.push(Sym::ElseIf, span);
+                    self.expr(cond);
+                    self.stmt(&body.borrow(), scope);
+                }
+                if let Some(body) = &i.else_block {
+                    self.push(Sym::Else, span);
+                    self.stmt(&body.borrow(), scope);
+                }
+            }
+            StmtVariant::While(w) => {
+                self.push(Sym::While, span);
+                self.expr(&w.cond);
+                self.stmt(&w.block.borrow(), scope);
+            }
+            StmtVariant::Block(b) => {
+                self.push(Sym::Block, span);
+                self.block(b);
+                self.push(Sym::End, span);
+            }
+            StmtVariant::Expr(e) => {
+                self.push(Sym::Expr, span);
+                self.expr(e);
+            }
+            StmtVariant::Print(es) => {
+                self.push(Sym::Print, span);
+                es.iter().for_each(|e| self.expr(e));
+            }
+            StmtVariant::ManyExpr(es) => {
+                for (_, def) in scope.borrow().defs_in(span) {
+                    if let SymbolDef::Var { typ, is_const, .. } = &*def.borrow() {
+                        let typ = type_str(&typ.borrow());
+                        let is_const = *is_const;
+                        self.push(Sym::Decl { is_const, typ }, span);
+                    }
+                }
+                es.iter().for_each(|e| self.expr(e));
+            }
+            StmtVariant::Scan(..) => {
+                self.push(Sym::Scan, span);
+                self.push(Sym::Var, span);
+            }
+            StmtVariant::Return(e) => {
+                self.push(Sym::Return, span);
+                e.iter().for_each(|e| self.expr(e));
+            }
+            StmtVariant::Break(_) => self.push(Sym::Break, span),
+            StmtVariant::Empty => {
+                // * Function declarations; their bodies are kept in the scope
+                for (_, def) in scope.borrow().defs_in(span) {
+                    if let SymbolDef::Var { typ, .. } = &*def.borrow() {
+                        if let TypeDef::Function(FunctionType {
+                            body: Some(body),
+                            params,
+                            ..
+                        }) = &*typ.borrow()
+                        {
+                            self.push(Sym::Function(params.len()), span);
+                            self.block(body);
+                            self.push(Sym::End, span);
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    fn expr(&mut self, expr: &Ptr<Expr>) {
+        maybe_grow(|| {
+            let expr = expr.borrow();
+            let span = expr.span;
+            match &expr.var {
+                ExprVariant::Ident(_) => self.push(Sym::Var, span),
+                ExprVariant::Literal(_) => self.push(Sym::Lit, span),
+                ExprVariant::TypeConversion(t) => {
+                    self.push(Sym::Conv(type_str(&t.to.borrow())), span);
+                    self.expr(&t.expr);
+                }
+                ExprVariant::UnaryOp(u) => {
+                    self.push(Sym::Unary(op_str(u.op)), span);
+                    self.expr(&u.val);
+                }
+                ExprVariant::BinaryOp(b) => {
+                    self.push(Sym::Binary(op_str(b.op)), span);
+                    self.expr(&b.lhs);
+                    self.expr(&b.rhs);
+                }
+                ExprVariant::FunctionCall(f) => {
+                    self.push(Sym::Call(f.params.len()), span);
+                    f.params.iter().for_each(|p| self.expr(p));
+                }
+                ExprVariant::StructChild(s) => {
+                    self.push(Sym::Field, span);
+                    self.expr(&s.val);
+                }
+                ExprVariant::ArrayChild(a) => {
+                    self.push(Sym::Index, span);
+                    self.expr(&a.val);
+                    self.expr(&a.idx);
+                }
+            }
+        })
+    }
+}
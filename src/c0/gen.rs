@@ -0,0 +1,393 @@
+//! Random programs for fuzzing backends and benchmarking.
+//!
+//! Programs are generated from a model of what is in scope, so each one is
+//! valid by construction: names are declared before they are used, values
+//! are of types they convert to, and functions end with a `return`. They
+//! also finish quickly and without runtime errors, so every backend should
+//! run them the same way:
+//!
+//! - Functions only call functions declared before them. Each function is
+//!   called from one place at most, never inside a loop or branch, so no
+//!   function runs more than once.
+//! - Loops count to a small bound, with a counter nothing else assigns, and
+//!   nest two deep at most.
+//! - `main` returns 0. Division is only by non-zero literals. Integers may
+//!   overflow, which wraps around.
+
+use crate::prelude::*;
+use core::fmt::Write;
+
+/// What to generate
+#[derive(Debug, Clone)]
+pub struct GenConfig {
+    /// Functions besides `main`
+    pub functions: usize,
+    /// How deep statements, and expressions, nest at most
+    pub depth: usize,
+    pub seed: u64,
+}
+
+impl Default for GenConfig {
+    fn default() -> Self {
+        GenConfig {
+            functions: 10,
+            depth: 4,
+            seed: 0,
+        }
+    }
+}
+
+/// Generate a program. The same config always gives the same program.
+pub fn generate(config: &GenConfig) -> String {
+    let mut gen = Gen {
+        rng: SplitMix64::new(config.seed),
+        depth: config.depth,
+        out: String::new(),
+        indent: 0,
+        scopes: vec![],
+        fns: vec![],
+        loops: 0,
+        counter: 0,
+    };
+    gen.program(config.functions);
+    gen.out
+}
+
+const MAX_LOOPS: usize = 2;
+const MAX_ITERATIONS: usize = 5;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Ty {
+    Int,
+    Double,
+    Char,
+}
+
+impl Ty {
+    fn name(self) -> &'static str {
+        match self {
+            Ty::Int => "int",
+            Ty::Double => "double",
+            Ty::Char => "char",
+        }
+    }
+}
+
+struct Var {
+    name: String,
+    typ: Ty,
+    /// Constants and loop counters are not
+    assignable: bool,
+}
+
+struct Func {
+    name: String,
+    /// `None` for `void`
+    ret: Option<Ty>,
+    params: Vec<Ty>,
+}
+
+struct Gen {
+    rng: SplitMix64,
+    depth: usize,
+    out: String,
+    indent: usize,
+    /// Variables in scope, innermost scope last
+    scopes: Vec<Vec<Var>>,
+    /// Functions declared so far
+    fns: Vec<Func>,
+    /// Loops around the statement being generated
+    loops: usize,
+    /// For fresh names
+    counter: usize,
+}
+
+impl Gen {
+    fn program(&mut self, functions: usize) {
+        // * Function `k` is called by one of the functions after it, `main`
+        // * being the last
+        let mut callees = vec![vec![]; functions + 1];
+        for k in 0..functions {
+            let caller = k + 1 + self.rng.below(functions - k);
+            callees[caller].push(k);
+        }
+
+        self.scopes.push(vec![]);
+        for _ in 0..1 + self.rng.below(3) {
+            self.global();
+        }
+        for (k, callees) in callees.iter().enumerate() {
+            self.out.push('\n');
+            if k == functions {
+                self.function("main".into(), Some(Ty::Int), vec![], callees);
+            } else {
+                let ret = self.pick(&[Some(Ty::Int), Some(Ty::Double), None]);
+                let params = (0..self.rng.below(3))
+                    .map(|_| self.pick(&[Ty::Int, Ty::Double]))
+                    .collect();
+                self.function(format!("f{}", k), ret, params, callees);
+            }
+        }
+    }
+
+    fn global(&mut self) {
+        let typ = self.pick(&[Ty::Int, Ty::Double, Ty::Char]);
+        let is_const = self.rng.one_in(3);
+        let name = self.fresh("g");
+        let val = self.literal(typ);
+        if is_const {
+            self.out += "const ";
+        }
+        let _ = writeln!(self.out, "{} {} = {};", typ.name(), name, val);
+        self.declare(name, typ, !is_const);
+    }
+
+    /// Generate a function calling `callees`, by their index
+    fn function(&mut self, name: String, ret: Option<Ty>, params: Vec<Ty>, callees: &[usize]) {
+        let param_names: Vec<_> = params.iter().map(|_| self.fresh("p")).collect();
+        let decls: Vec<_> = (params.iter().zip(&param_names))
+            .map(|(typ, name)| format!("{} {}", typ.name(), name))
+            .collect();
+        let ret_name = ret.map_or("void", Ty::name);
+        let _ = writeln!(self.out, "{} {}({}) {{", ret_name, name, decls.join(", "));
+
+        self.scopes.push(vec![]);
+        for (typ, name) in params.iter().zip(param_names) {
+            self.declare(name, *typ, true);
+        }
+        self.indent += 1;
+
+        // * Calls go between other statements, at the top level of the body
+        let others = 1 + self.rng.below(4);
+        let mut is_call = vec![false; others];
+        is_call.extend(callees.iter().map(|_| true));
+        for i in (1..is_call.len()).rev() {
+            let j = self.rng.below(i + 1);
+            is_call.swap(i, j);
+        }
+        let mut callees = callees.iter();
+        for is_call in is_call {
+            match is_call {
+                true => self.call_stmt(*callees.next().unwrap()),
+                false => self.stmt(self.depth),
+            }
+        }
+        match ret {
+            _ if name == "main" => self.line("return 0;".into()),
+            Some(ret) => {
+                let val = self.expr(ret, self.depth);
+                self.line(format!("return {};", val));
+            }
+            None => (),
+        }
+
+        self.indent -= 1;
+        self.scopes.pop();
+        self.out += "}\n";
+        self.fns.push(Func { name, ret, params });
+    }
+
+    fn call_stmt(&mut self, callee: usize) {
+        let args: Vec<_> = (self.fns[callee].params.clone().into_iter())
+            .map(|typ| self.expr(typ, self.depth))
+            .collect();
+        let call = format!("{}({})", self.fns[callee].name, args.join(", "));
+        match self.fns[callee].ret {
+            None => self.line(format!("{};", call)),
+            Some(typ) if self.rng.one_in(2) => {
+                let name = self.fresh("v");
+                self.line(format!("{} {} = {};", typ.name(), name, call));
+                self.declare(name, typ, true);
+            }
+            Some(_) => self.line(format!("print({});", call)),
+        }
+    }
+
+    fn stmt(&mut self, depth: usize) {
+        maybe_grow(|| {
+            let compound = depth > 0 && self.rng.one_in(4);
+            if !compound {
+                match self.rng.below(4) {
+                    0 => self.decl(depth),
+                    1 => self.assign(depth),
+                    2 if self.loops > 0 && self.rng.one_in(4) => self.line("break;".into()),
+                    _ => self.print(depth),
+                }
+                return;
+            }
+            match self.rng.below(3) {
+                0 if self.loops < MAX_LOOPS => self.while_loop(depth),
+                1 => {
+                    self.line("{".into());
+                    self.block(depth - 1);
+                    self.line("}".into());
+                }
+                _ => self.if_stmt(depth),
+            }
+        })
+    }
+
+    /// Statements in a scope of their own
+    fn block(&mut self, depth: usize) {
+        self.scopes.push(vec![]);
+        self.indent += 1;
+        for _ in 0..1 + self.rng.below(3) {
+            self.stmt(depth);
+        }
+        self.indent -= 1;
+        self.scopes.pop();
+    }
+
+    fn decl(&mut self, depth: usize) {
+        let typ = self.pick(&[Ty::Int, Ty::Int, Ty::Double, Ty::Char]);
+        let is_const = self.rng.one_in(4);
+        let val = self.expr(typ, depth);
+        let name = self.fresh("v");
+        let konst = if is_const { "const " } else { "" };
+        self.line(format!("{}{} {} = {};", konst, typ.name(), name, val));
+        self.declare(name, typ, !is_const);
+    }
+
+    fn assign(&mut self, depth: usize) {
+        let vars: Vec<_> = (self.scopes.iter().flatten())
+            .filter(|v| v.assignable)
+            .map(|v| (v.name.clone(), v.typ))
+            .collect();
+        if vars.is_empty() {
+            return self.decl(depth);
+        }
+        let (name, typ) = vars[self.rng.below(vars.len())].clone();
+        let val = self.expr(typ, depth);
+        self.line(format!("{} = {};", name, val));
+    }
+
+    fn print(&mut self, depth: usize) {
+        let mut args = vec![];
+        if self.rng.one_in(3) {
+            args.push(format!("\"{}\"", self.fresh("s")));
+        }
+        for _ in 0..1 + self.rng.below(2) {
+            let typ = self.pick(&[Ty::Int, Ty::Double, Ty::Char]);
+            args.push(self.expr(typ, depth));
+        }
+        self.line(format!("print({});", args.join(", ")));
+    }
+
+    fn if_stmt(&mut self, depth: usize) {
+        let cond = self.cond(depth);
+        self.line(format!("if ({}) {{", cond));
+        self.block(depth - 1);
+        for _ in 0..self.rng.below(2) {
+            let cond = self.cond(depth);
+            self.line(format!("}} else if ({}) {{", cond));
+            self.block(depth - 1);
+        }
+        if self.rng.one_in(2) {
+            self.line("} else {".into());
+            self.block(depth - 1);
+        }
+        self.line("}".into());
+    }
+
+    fn while_loop(&mut self, depth: usize) {
+        let counter = self.fresh("i");
+        self.line(format!("int {} = 0;", counter));
+        self.declare(counter.clone(), Ty::Int, false);
+        let bound = 1 + self.rng.below(MAX_ITERATIONS);
+        self.line(format!("while ({} < {}) {{", counter, bound));
+        self.loops += 1;
+        self.block(depth - 1);
+        self.loops -= 1;
+        self.indent += 1;
+        self.line(format!("{0} = {0} + 1;", counter));
+        self.indent -= 1;
+        self.line("}".into());
+    }
+
+    fn cond(&mut self, depth: usize) -> String {
+        let typ = self.pick(&[Ty::Int, Ty::Double]);
+        let op = self.pick(&["<", "<=", ">", ">=", "==", "!="]);
+        let lhs = self.expr(typ, depth.saturating_sub(1));
+        let rhs = self.expr(typ, depth.saturating_sub(1));
+        format!("{} {} {}", lhs, op, rhs)
+    }
+
+    /// An expression of type `typ`
+    fn expr(&mut self, typ: Ty, depth: usize) -> String {
+        maybe_grow(|| {
+            if typ == Ty::Char || depth == 0 || self.rng.one_in(3) {
+                return self.leaf(typ);
+            }
+            let depth = depth - 1;
+            match self.rng.below(5) {
+                0 => format!("-{}", self.leaf(typ)),
+                1 => {
+                    let other = match typ {
+                        Ty::Int => Ty::Double,
+                        _ => Ty::Int,
+                    };
+                    format!("({}) ({})", typ.name(), self.expr(other, depth))
+                }
+                2 => {
+                    let divisor = match typ {
+                        Ty::Int => format!("{}", 1 + self.rng.below(9)),
+                        _ => format!("{}.5", self.rng.below(9)),
+                    };
+                    format!("({} / {})", self.expr(typ, depth), divisor)
+                }
+                _ => {
+                    let op = self.pick(&["+", "-", "*"]);
+                    let lhs = self.expr(typ, depth);
+                    let rhs = self.expr(typ, depth);
+                    format!("({} {} {})", lhs, op, rhs)
+                }
+            }
+        })
+    }
+
+    /// A literal, or a variable of type `typ`
+    fn leaf(&mut self, typ: Ty) -> String {
+        let vars: Vec<_> = (self.scopes.iter().flatten())
+            .filter(|v| v.typ == typ)
+            .map(|v| v.name.clone())
+            .collect();
+        if !vars.is_empty() && !self.rng.one_in(3) {
+            return vars[self.rng.below(vars.len())].clone();
+        }
+        self.literal(typ)
+    }
+
+    fn literal(&mut self, typ: Ty) -> String {
+        match typ {
+            Ty::Int => format!("{}", self.rng.below(100)),
+            Ty::Double => format!("{}.{}", self.rng.below(100), self.rng.below(10)),
+            Ty::Char => format!("'{}'", (b'a' + self.rng.below(26) as u8) as char),
+        }
+    }
+
+    fn pick<T: Clone>(&mut self, items: &[T]) -> T {
+        items[self.rng.below(items.len())].clone()
+    }
+
+    fn fresh(&mut self, prefix: &str) -> String {
+        self.counter += 1;
+        format!("{}{}", prefix, self.counter)
+    }
+
+    fn declare(&mut self, name: String, typ: Ty, assignable: bool) {
+        let var = Var {
+            name,
+            typ,
+            assignable,
+        };
+        self.scopes.last_mut().unwrap().push(var);
+    }
+
+    fn line(&mut self, line: String) {
+        for _ in 0..self.indent {
+            self.out += "    ";
+        }
+        self.out += &line;
+        self.out.push('\n');
+    }
+}
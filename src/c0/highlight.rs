@@ -0,0 +1,90 @@
+//! Token classification for syntax highlighting.
+//!
+//! Only the lexer runs, so code that does not parse is still highlighted, and
+//! it is cheap enough to run again on every keystroke.
+
+use super::lexer::{self, Lexer, TokenType};
+use crate::prelude::*;
+
+/// What a token looks like to a highlighter
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum TokenClass {
+    Keyword,
+    /// Names of built-in types
+    Type,
+    /// Identifiers followed by `(`, which name functions in declarations
+    /// and calls
+    Function,
+    Identifier,
+    Number,
+    String,
+    Char,
+    /// `true` and `false`
+    Boolean,
+    Comment,
+    Operator,
+    /// Brackets, `,`, `;`, `:` and `.`
+    Punctuation,
+    /// Text the lexer could not make sense of
+    Error,
+}
+
+const BUILTIN_TYPES: &[&str] = &["void", "int", "double", "char"];
+
+/// Classify every token and comment of `src`, in source order
+pub fn highlight(src: &str) -> Vec<(Span, TokenClass)> {
+    let mut lexer = Lexer::new(src);
+    let mut out: Vec<(Span, TokenClass)> = vec![];
+    // * Index in `out` of the last identifier, if no token came after it
+    // * other than comments
+    let mut last_ident: Option<usize> = None;
+    while let Some(tok) = lexer.get_next_token() {
+        let class = classify(&tok.var);
+        match (&tok.var, last_ident) {
+            (TokenType::LParenthesis, Some(idx)) => {
+                out[idx].1 = TokenClass::Function;
+                last_ident = None;
+            }
+            (TokenType::Comment(_) | TokenType::DocComment(_), _) => (),
+            _ => last_ident = None,
+        }
+        if class == TokenClass::Identifier {
+            last_ident = Some(out.len());
+        }
+        out.push((tok.span, class));
+    }
+    out
+}
+
+/// Classify a single token. Identifiers are never classified as
+/// [`TokenClass::Function`] here, as that depends on the next token.
+pub fn classify(tok: &TokenType) -> TokenClass {
+    use TokenType::*;
+    match tok {
+        Const | Auto | As | If | Else | While | Break | Continue | Return | Print | Scan => {
+            TokenClass::Keyword
+        }
+
+        Minus | Plus | Multiply | Divide | Not | BinaryAnd | BinaryOr | And | Or | Xor
+        | Increase | Decrease | Equals | NotEquals | LessThan | LessOrEqualThan | GreaterThan
+        | GreaterOrEqualThan | Assign => TokenClass::Operator,
+
+        Semicolon | LParenthesis | RParenthesis | LBracket | RBracket | LCurlyBrace
+        | RCurlyBrace | Comma | Colon | Dot => TokenClass::Punctuation,
+
+        Identifier(name) if BUILTIN_TYPES.contains(&&name[..]) => TokenClass::Type,
+        Identifier(_) => TokenClass::Identifier,
+
+        Literal(lit) => match lit {
+            lexer::Literal::Integer(_) | lexer::Literal::Float(_) => TokenClass::Number,
+            lexer::Literal::String(_) => TokenClass::String,
+            lexer::Literal::Char(_) => TokenClass::Char,
+            lexer::Literal::Boolean(_) => TokenClass::Boolean,
+            lexer::Literal::_Dummy => TokenClass::Error,
+        },
+
+        Comment(_) | DocComment(_) => TokenClass::Comment,
+
+        EndOfFile | Dummy | Error(_) => TokenClass::Error,
+    }
+}
@@ -0,0 +1,444 @@
+//! The typed tree, built from a parsed program by
+//! [`type_checker::lower`](crate::c0::type_checker::lower).
+//!
+//! The parser resolves names but leaves the type of each expression to
+//! whoever walks the tree. Here every expression carries its type and every
+//! name the definition it refers to, so passes reading the tree don't need to
+//! work out either again. Types are resolved: no `NamedType`, `Unknown` or
+//! `TypeErr` is left in them.
+//!
+//! Nodes made up by the type checker, rather than written in the source, are
+//! tagged with a [`SyntheticOrigin`](crate::c0::hir::SyntheticOrigin). They
+//! take the span of what they were made from, so errors and debug info about
+//! them still point at user code.
+
+use super::ast::{Literal, OpVar, PrimitiveTypeVar, Scope, SymbolDef, TypeDef};
+use crate::prelude::*;
+use alloc::collections::BTreeMap;
+use core::fmt;
+
+/// A resolved type
+pub type Type = Ptr<TypeDef>;
+
+/// A program whose types are all known. Its `Debug` output shows types the
+/// way they are written in C0.
+#[derive(Clone)]
+pub struct TypedProgram {
+    /// Global variables, and the statements initializing them
+    pub blk: Block,
+    /// Functions in the order they are declared
+    pub fns: Vec<Function>,
+    /// Types inferred for `auto` variables, by the id of the scope each is
+    /// declared in and its name
+    pub inferred: BTreeMap<(usize, String), Type>,
+}
+
+impl fmt::Debug for TypedProgram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TypedProgram")
+            .field("blk", &self.blk)
+            .field("fns", &self.fns)
+            .finish()
+    }
+}
+
+#[derive(Clone)]
+pub struct Function {
+    pub name: String,
+    pub def: Ptr<SymbolDef>,
+    /// Span of the declaration
+    pub span: Span,
+    pub params: Vec<Var>,
+    pub return_type: Type,
+    /// `None` for functions declared without a body
+    pub body: Option<Block>,
+}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Function")
+            .field("name", &self.name)
+            .field("params", &self.params)
+            .field("return_type", &TypeName(&self.return_type))
+            .field("body", &self.body)
+            .finish()
+    }
+}
+
+/// A variable declared in a block, or a parameter
+#[derive(Clone)]
+pub struct Var {
+    pub name: String,
+    pub typ: Type,
+    pub is_const: bool,
+    /// Span of the declaration
+    pub span: Span,
+}
+
+impl fmt::Debug for Var {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_const {
+            write!(f, "const ")?;
+        }
+        write!(f, "{} {}", type_name(&self.typ.borrow()), self.name)
+    }
+}
+
+/// A use of a variable or function by name
+#[derive(Clone)]
+pub struct NameRef {
+    pub name: String,
+    pub def: Ptr<SymbolDef>,
+}
+
+impl fmt::Debug for NameRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+#[derive(Clone)]
+pub struct Block {
+    pub scope: Ptr<Scope>,
+    /// Variables declared in the block. Parameters of a function are in
+    /// [`Function::params`] instead.
+    pub vars: Vec<Var>,
+    pub stmts: Vec<Stmt>,
+    pub span: Option<Span>,
+}
+
+impl fmt::Debug for Block {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Block")
+            .field("vars", &self.vars)
+            .field("stmts", &self.stmts)
+            .finish()
+    }
+}
+
+#[derive(Clone)]
+pub struct Stmt {
+    pub var: StmtVariant,
+    pub span: Span,
+}
+
+impl Drop for Stmt {
+    fn drop(&mut self) {
+        // * Deeply nested statements would overflow the stack otherwise
+        maybe_grow(|| drop(core::mem::replace(&mut self.var, StmtVariant::Empty)));
+    }
+}
+
+impl fmt::Debug for Stmt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.var.fmt(f)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum StmtVariant {
+    If {
+        cond: Expr,
+        then: Box<Stmt>,
+        else_ifs: Vec<(Expr, Stmt)>,
+        els: Option<Box<Stmt>>,
+    },
+    While {
+        label: Option<String>,
+        cond: Expr,
+        body: Box<Stmt>,
+    },
+    Block(Block),
+    /// Expressions evaluated in order for their effects, like the
+    /// initializers of a declaration
+    Exprs(Vec<Expr>),
+    Print(Vec<Expr>),
+    Scan(NameRef),
+    Return(Option<Expr>),
+    /// Break out of the innermost loop, or the loop with the given label,
+    /// which is known to exist
+    Break(Option<String>),
+    Empty,
+}
+
+#[derive(Clone)]
+pub struct Expr {
+    pub var: ExprVariant,
+    /// Type of the value, `void` for calls of functions returning nothing
+    pub typ: Type,
+    pub span: Span,
+    /// Why the expression was made up, if it is not written in the source
+    pub origin: Option<SyntheticOrigin>,
+}
+
+impl fmt::Debug for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        maybe_grow(|| {
+            let mut s = f.debug_struct("Expr");
+            s.field("typ", &TypeName(&self.typ));
+            if let Some(origin) = &self.origin {
+                s.field("origin", origin);
+            }
+            s.field("var", &self.var).finish()
+        })
+    }
+}
+
+/// What a node not written in the source stands for
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SyntheticOrigin {
+    /// The implicit conversion of a value to the type it is used as. Its
+    /// span is the one of the value.
+    Conversion,
+    /// The assignment of its initial value to a declared variable. Its span
+    /// runs from the name of the variable to the end of the value.
+    Initializer,
+}
+
+impl Drop for Expr {
+    fn drop(&mut self) {
+        // * Deeply nested expressions would overflow the stack otherwise
+        maybe_grow(|| {
+            let placeholder = ExprVariant::Literal(Literal::Boolean { val: false });
+            drop(core::mem::replace(&mut self.var, placeholder));
+        });
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ExprVariant {
+    Var(NameRef),
+    Literal(Literal),
+    /// Conversion of the expression to the type of this one, written with
+    /// `as` or [implicit](SyntheticOrigin::Conversion)
+    Conv(Box<Expr>),
+    Unary(OpVar, Box<Expr>),
+    /// An operator other than assignment. Operands of arithmetic are
+    /// converted to the type of the result, and those of comparisons to the
+    /// wider of the two. Those of `&&` and `||` are left as they are.
+    Binary(OpVar, Box<Expr>, Box<Expr>),
+    /// Assignment to a variable, or its initialization if `is_init`. The
+    /// value is converted to the type of the variable.
+    Assign {
+        to: NameRef,
+        val: Box<Expr>,
+        is_init: bool,
+    },
+    /// Assignment to what the reference `to` refers to, which is a `*`
+    /// [`Unary`](ExprVariant::Unary). The value is converted to the type of
+    /// what is referred to.
+    Store {
+        to: Box<Expr>,
+        val: Box<Expr>,
+    },
+    /// Arguments are converted to the types of the parameters
+    Call {
+        func: NameRef,
+        args: Vec<Expr>,
+    },
+}
+
+/// Shows a type with [`type_name`] in `Debug` output
+struct TypeName<'a>(&'a Type);
+
+impl fmt::Debug for TypeName<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", type_name(&self.0.borrow()))
+    }
+}
+
+/// How a resolved type is written in C0. Comparisons give `bool`, which C0
+/// has no name for.
+pub fn type_name(typ: &TypeDef) -> String {
+    match typ {
+        TypeDef::Primitive(p) => match (p.var, p.occupy_bytes) {
+            (PrimitiveTypeVar::SignedInt, 1) => "bool".into(),
+            (PrimitiveTypeVar::SignedInt, 4) => "int".into(),
+            (PrimitiveTypeVar::UnsignedInt, 1) => "char".into(),
+            (PrimitiveTypeVar::Float, 8) => "double".into(),
+            (var, bytes) => format!("{:?}{}", var, bytes * 8),
+        },
+        TypeDef::Ref(r) => format!("&{}", type_name(&r.target.borrow())),
+        TypeDef::Array(a) => format!("[{}]", type_name(&a.target.borrow())),
+        TypeDef::Function(f) => {
+            let params: Vec<_> = f.params.iter().map(|p| type_name(&p.borrow())).collect();
+            format!(
+                "{}({})",
+                type_name(&f.return_type.borrow()),
+                params.join(", ")
+            )
+        }
+        TypeDef::Unit => "void".into(),
+        other => format!("{:?}", other),
+    }
+}
+
+/// C0-like text of the program, a statement to a line, which
+/// `--emit typed-ast` writes. Declarations give the types of variables and
+/// functions; expressions show only their conversions, `(double)x` as
+/// written and `(x as double)` where the type checker made them up.
+impl fmt::Display for TypedProgram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_block(f, &self.blk, 0)?;
+        // * A blank line before each function, unless it is the first line
+        let mut blank = !self.blk.vars.is_empty()
+            || (self.blk.stmts.iter()).any(|stmt| !matches!(stmt.var, StmtVariant::Empty));
+        for func in &self.fns {
+            if blank {
+                writeln!(f)?;
+            }
+            blank = true;
+            let params: Vec<_> = func.params.iter().map(|p| format!("{:?}", p)).collect();
+            let return_type = type_name(&func.return_type.borrow());
+            write!(f, "{} {}({})", return_type, func.name, params.join(", "))?;
+            match &func.body {
+                Some(body) => {
+                    writeln!(f, " {{")?;
+                    write_block(f, body, 1)?;
+                    writeln!(f, "}}")?;
+                }
+                None => writeln!(f, ";")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        maybe_grow(|| match &self.var {
+            ExprVariant::Var(name) => write!(f, "{}", name.name),
+            ExprVariant::Literal(lit) => {
+                let mut out = String::new();
+                super::pretty::write_literal(lit, &mut out);
+                f.write_str(&out)
+            }
+            ExprVariant::Conv(val) if self.origin == Some(SyntheticOrigin::Conversion) => {
+                f.write_str("(")?;
+                write_operand(f, val)?;
+                write!(f, " as {})", type_name(&self.typ.borrow()))
+            }
+            ExprVariant::Conv(val) => {
+                write!(f, "({})", type_name(&self.typ.borrow()))?;
+                write_operand(f, val)
+            }
+            ExprVariant::Unary(op, val) => {
+                f.write_str(super::pretty::op_str(*op))?;
+                write_operand(f, val)
+            }
+            ExprVariant::Binary(op, lhs, rhs) => {
+                write_operand(f, lhs)?;
+                write!(f, " {} ", super::pretty::op_str(*op))?;
+                write_operand(f, rhs)
+            }
+            ExprVariant::Assign { to, val, .. } => write!(f, "{} = {}", to.name, val),
+            ExprVariant::Store { to, val } => write!(f, "{} = {}", to, val),
+            ExprVariant::Call { func, args } => {
+                write!(f, "{}(", func.name)?;
+                for (idx, arg) in args.iter().enumerate() {
+                    if idx != 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                f.write_str(")")
+            }
+        })
+    }
+}
+
+/// `expr` as an operand, in parentheses if it has operators of its own
+fn write_operand(f: &mut fmt::Formatter<'_>, expr: &Expr) -> fmt::Result {
+    match &expr.var {
+        ExprVariant::Binary(..) | ExprVariant::Assign { .. } | ExprVariant::Store { .. } => {
+            write!(f, "({})", expr)
+        }
+        _ => write!(f, "{}", expr),
+    }
+}
+
+/// The declarations and statements of `blk`, at `depth` levels of
+/// indentation
+fn write_block(f: &mut fmt::Formatter<'_>, blk: &Block, depth: usize) -> fmt::Result {
+    for var in &blk.vars {
+        writeln!(f, "{:indent$}{:?};", "", var, indent = depth * 4)?;
+    }
+    for stmt in &blk.stmts {
+        write_stmt(f, stmt, depth)?;
+    }
+    Ok(())
+}
+
+fn write_stmt(f: &mut fmt::Formatter<'_>, stmt: &Stmt, depth: usize) -> fmt::Result {
+    maybe_grow(|| write_stmt_inner(f, stmt, depth))
+}
+
+fn write_stmt_inner(f: &mut fmt::Formatter<'_>, stmt: &Stmt, depth: usize) -> fmt::Result {
+    let indent = depth * 4;
+    let exprs = |exprs: &[Expr]| -> String {
+        let exprs: Vec<_> = exprs.iter().map(|e| e.to_string()).collect();
+        exprs.join(", ")
+    };
+    match &stmt.var {
+        StmtVariant::If {
+            cond,
+            then,
+            else_ifs,
+            els,
+        } => {
+            write!(f, "{:indent$}if ({}) ", "", cond, indent = indent)?;
+            write_body(f, then, depth)?;
+            for (cond, body) in else_ifs {
+                write!(f, " else if ({}) ", cond)?;
+                write_body(f, body, depth)?;
+            }
+            if let Some(els) = els {
+                f.write_str(" else ")?;
+                write_body(f, els, depth)?;
+            }
+            writeln!(f)
+        }
+        StmtVariant::While { label, cond, body } => {
+            write!(f, "{:indent$}", "", indent = indent)?;
+            if let Some(label) = label {
+                write!(f, "{}: ", label)?;
+            }
+            write!(f, "while ({}) ", cond)?;
+            write_body(f, body, depth)?;
+            writeln!(f)
+        }
+        StmtVariant::Block(blk) => {
+            writeln!(f, "{:indent$}{{", "", indent = indent)?;
+            write_block(f, blk, depth + 1)?;
+            writeln!(f, "{:indent$}}}", "", indent = indent)
+        }
+        StmtVariant::Exprs(es) if es.is_empty() => Ok(()),
+        StmtVariant::Exprs(es) => writeln!(f, "{:indent$}{};", "", exprs(es), indent = indent),
+        StmtVariant::Print(es) => {
+            writeln!(f, "{:indent$}print({});", "", exprs(es), indent = indent)
+        }
+        StmtVariant::Scan(name) => {
+            writeln!(f, "{:indent$}scan({});", "", name.name, indent = indent)
+        }
+        StmtVariant::Return(None) => writeln!(f, "{:indent$}return;", "", indent = indent),
+        StmtVariant::Return(Some(e)) => {
+            writeln!(f, "{:indent$}return {};", "", e, indent = indent)
+        }
+        StmtVariant::Break(None) => writeln!(f, "{:indent$}break;", "", indent = indent),
+        StmtVariant::Break(Some(label)) => {
+            writeln!(f, "{:indent$}break {};", "", label, indent = indent)
+        }
+        StmtVariant::Empty => Ok(()),
+    }
+}
+
+/// The body of an `if` or `while`, in braces whether or not it is a block.
+/// Ends without a line break, for an `else` to follow.
+fn write_body(f: &mut fmt::Formatter<'_>, body: &Stmt, depth: usize) -> fmt::Result {
+    writeln!(f, "{{")?;
+    match &body.var {
+        StmtVariant::Block(blk) => write_block(f, blk, depth + 1)?,
+        _ => write_stmt(f, body, depth + 1)?,
+    }
+    write!(f, "{:indent$}}}", "", indent = depth * 4)
+}
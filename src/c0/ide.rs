@@ -0,0 +1,802 @@
+//! Queries on a parsed program for editor tooling: what is under the cursor,
+//! where it is declared, where it is used, what a file declares, and how to
+//! rename it.
+//!
+//! Positions are [`Pos`](crate::Pos)es whose `index` is set, as the parser compares them
+//! by `index` only. Use [`pos_at`](crate::c0::ide::pos_at) to make one from a line and column.
+
+use super::ast::*;
+use super::hir::{self, type_name, TypedProgram};
+use super::lexer::{Lexer, TokenType};
+use super::pretty::type_str;
+use super::symbol::Symbol;
+use crate::prelude::*;
+use core::fmt;
+use std::collections::BTreeMap;
+
+/// What kind of symbol a declaration introduces
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SymbolKind {
+    Function,
+    Variable,
+    Constant,
+    Parameter,
+}
+
+/// A declared symbol
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SymbolInfo {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// Span of the name in the declaration
+    pub name_span: Span,
+    /// Span of the declaration. Functions listed by [`document_symbols`]
+    /// include their bodies; others only their signature.
+    pub span: Span,
+    /// The declaration as written in C0, like `const int x` or
+    /// `int f(int a)`
+    pub detail: String,
+    /// Its doc comment
+    pub doc: Option<String>,
+    /// Parameters and local variables of functions
+    pub children: Vec<SymbolInfo>,
+}
+
+/// A use or declaration of a symbol found at some position
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SymbolRef {
+    /// Span of the name at that position
+    pub span: Span,
+    /// The declaration it refers to
+    pub def: SymbolInfo,
+}
+
+/// A symbol with everywhere it is used
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct XrefEntry {
+    /// Its declaration. Functions have no children here.
+    pub def: SymbolInfo,
+    /// Spans of the names where it is used, in source order
+    pub uses: Vec<Span>,
+}
+
+/// Cross-reference listing of a program: every symbol it declares with its
+/// uses, in the order they are declared. Functions declared outside the
+/// program are listed when it uses them. Printed with `Display` as text, or
+/// as JSON with [`to_json`](Xref::to_json).
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Xref {
+    pub symbols: Vec<XrefEntry>,
+}
+
+/// A change to the source: the text of `span` is replaced with `text`
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TextEdit {
+    pub span: Span,
+    pub text: String,
+}
+
+/// Why a symbol cannot be renamed
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum RenameError {
+    /// There is no symbol at the position
+    NoSymbol,
+    /// The new name is not an identifier, or is a keyword or a type
+    BadName(String),
+    /// `main` and functions declared outside the program keep their names
+    Fixed(String),
+    /// Another symbol, whose name is at the span, already has the new name
+    /// where it would change what a name refers to
+    Conflict(String, Span),
+}
+
+impl fmt::Display for RenameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenameError::NoSymbol => write!(f, "No symbol to rename here"),
+            RenameError::BadName(name) => write!(f, "`{}` is not a valid name", name),
+            RenameError::Fixed(name) => write!(f, "`{}` cannot be renamed", name),
+            RenameError::Conflict(name, span) => write!(
+                f,
+                "`{}` declared at {}:{} would conflict",
+                name,
+                span.start.ln + 1,
+                span.start.pos + 1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RenameError {}
+
+/// Position of line `ln`, column `col` (both from 0) in `src`, counted the
+/// same way the lexer does. Positions past the end of a line or of the
+/// source are clamped.
+pub fn pos_at(src: &str, ln: usize, col: usize) -> Pos {
+    let mut pos = Pos::zero();
+    let mut chars = src.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if pos.ln == ln && (pos.pos == col || ch == '\r' || ch == '\n') {
+            break;
+        }
+        pos = match ch {
+            '\r' if chars.peek() == Some(&'\n') => {
+                chars.next();
+                pos.lf().bump()
+            }
+            '\r' | '\n' => pos.lf(),
+            _ => pos.inc(),
+        };
+        if pos.ln > ln {
+            break;
+        }
+    }
+    pos
+}
+
+/// Symbols declared at the top level of `prog`, in source order. Functions
+/// list their parameters and local variables as children.
+pub fn document_symbols(prog: &Program) -> Vec<SymbolInfo> {
+    let mut syms = vec![];
+    let scope = prog.blk.scope.borrow();
+    for stmt in &prog.blk.stmts {
+        for (name, def) in scope.defs_in(stmt.span) {
+            syms.extend(symbol_info(&name, &def.borrow(), stmt.span, false));
+        }
+    }
+    syms
+}
+
+/// The symbol used or declared at `pos`, if any
+pub fn symbol_at(prog: &Program, pos: Pos) -> Option<SymbolRef> {
+    let mut finder = Finder {
+        pos,
+        func: None,
+        found: None,
+    };
+    finder.block(&prog.blk);
+    finder.found
+}
+
+impl Xref {
+    pub fn new(prog: &Program) -> Xref {
+        let mut symbols = Collector::new(prog).symbols;
+        symbols.retain(|sym| !(is_extern(&sym.def) && sym.uses.is_empty()));
+        symbols.sort_by_key(|sym| sym.def.name_span.start.index);
+        for sym in &mut symbols {
+            sym.uses.sort_by_key(|span| span.start.index);
+        }
+        Xref { symbols }
+    }
+
+    /// The symbol declared with its name at `name_span`, as in
+    /// [`SymbolInfo::name_span`]
+    pub fn find(&self, name_span: Span) -> Option<&XrefEntry> {
+        self.symbols
+            .iter()
+            .find(|sym| sym.def.name_span == name_span)
+    }
+
+    /// The listing as JSON: an array of symbols with their `name`, `kind`,
+    /// `detail`, the `line` and `col` of their name, counted from 1, and
+    /// `uses` with a `line` and `col` each
+    pub fn to_json(&self) -> String {
+        let at = |span: Span| {
+            serde_json::json!({
+                "line": span.start.ln + 1,
+                "col": span.start.pos + 1,
+            })
+        };
+        let symbols: Vec<_> = (self.symbols.iter())
+            .map(|sym| {
+                let mut entry = at(sym.def.name_span);
+                entry["name"] = sym.def.name.clone().into();
+                entry["kind"] = kind_name(sym.def.kind).into();
+                entry["detail"] = sym.def.detail.clone().into();
+                entry["uses"] = sym.uses.iter().map(|span| at(*span)).collect();
+                entry
+            })
+            .collect();
+        serde_json::to_string_pretty(&symbols).expect("Listings are always valid JSON")
+    }
+}
+
+impl fmt::Display for Xref {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let at = |span: Span| format!("{}:{}", span.start.ln + 1, span.start.pos + 1);
+        for sym in &self.symbols {
+            writeln!(
+                f,
+                "{:<20} {:<9} {:<8} {}",
+                sym.def.name,
+                kind_name(sym.def.kind),
+                match is_extern(&sym.def) {
+                    true => "extern".into(),
+                    false => at(sym.def.name_span),
+                },
+                sym.def.detail
+            )?;
+            match sym.uses.len() {
+                0 => writeln!(f, "    never used")?,
+                _ => {
+                    let uses: Vec<_> = sym.uses.iter().map(|span| at(*span)).collect();
+                    writeln!(f, "    used at {}", uses.join(", "))?
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Edits renaming the symbol declared or used at `pos` to `new_name`, at
+/// its declaration and every use, in source order. Fails if that would make
+/// a name refer to something else: if `new_name` is declared in the same
+/// scope, or in a scope between the symbol and one of its uses, or if
+/// another symbol named `new_name` is used where the renamed one would hide
+/// it.
+pub fn rename(prog: &Program, pos: Pos, new_name: &str) -> Result<Vec<TextEdit>, RenameError> {
+    let def = symbol_at(prog, pos).ok_or(RenameError::NoSymbol)?.def;
+    if is_extern(&def) || (def.kind == SymbolKind::Function && def.name == "main") {
+        return Err(RenameError::Fixed(def.name));
+    }
+    let is_type = (prog.blk.scope.borrow().defs.get(&Symbol::intern(new_name)))
+        .is_some_and(|def| matches!(&*def.borrow(), SymbolDef::Typ { .. }));
+    let tokens: Vec<_> = (Lexer::new(new_name).map(|t| t.var))
+        .filter(|t| *t != TokenType::EndOfFile)
+        .collect();
+    let is_ident = matches!(&tokens[..], [TokenType::Identifier(name)] if name == new_name);
+    if !is_ident || is_type {
+        return Err(RenameError::BadName(new_name.into()));
+    }
+
+    let collector = Collector::new(prog);
+    let target = (collector.symbols.iter())
+        .position(|sym| sym.def.name_span == def.name_span)
+        .ok_or(RenameError::NoSymbol)?;
+    let home = collector.homes[target];
+    let conflict = |def: &SymbolInfo| RenameError::Conflict(def.name.clone(), def.name_span);
+
+    // * Declared in the same scope
+    let same_scope = (collector.symbols.iter().zip(&collector.homes))
+        .find(|(sym, h)| **h == home && sym.def.name == new_name);
+    if let Some((sym, _)) = same_scope {
+        return Err(conflict(&sym.def));
+    }
+    for (idx, span, scope) in &collector.refs {
+        let depth_of_home = scope_depth(scope, home);
+        if *idx == target {
+            // * A use would find another declaration first
+            if let Some((found, other)) = resolve_in(new_name, *span, scope, None) {
+                if scope_depth(scope, found) < depth_of_home {
+                    return Err(conflict(&other));
+                }
+            }
+        } else if collector.symbols[*idx].def.name == new_name {
+            // * A use of another symbol would find the renamed one first
+            let visible = def.kind == SymbolKind::Function || def.name_span.start <= span.start;
+            let depth_of_other = scope_depth(scope, collector.homes[*idx]);
+            if visible && depth_of_home.is_some() && depth_of_home < depth_of_other {
+                return Err(conflict(&collector.symbols[*idx].def));
+            }
+        }
+    }
+
+    let sym = &collector.symbols[target];
+    let mut edits: Vec<_> = (Some(sym.def.name_span).into_iter())
+        .chain(sym.uses.iter().copied())
+        .map(|span| TextEdit {
+            span,
+            text: new_name.into(),
+        })
+        .collect();
+    edits.sort_by_key(|edit| edit.span.start.index);
+    Ok(edits)
+}
+
+fn kind_name(kind: SymbolKind) -> &'static str {
+    match kind {
+        SymbolKind::Function => "function",
+        SymbolKind::Variable => "variable",
+        SymbolKind::Constant => "constant",
+        SymbolKind::Parameter => "parameter",
+    }
+}
+
+/// Whether `def` is a function declared outside the program, which has no
+/// place in the source
+fn is_extern(def: &SymbolInfo) -> bool {
+    def.kind == SymbolKind::Function
+        && def.name_span.start.index == 0
+        && def.name_span.end.index == 0
+}
+
+/// The innermost expression at `pos`, and how its type is written
+pub fn type_at(prog: &TypedProgram, pos: Pos) -> Option<(Span, String)> {
+    let mut found = None;
+    let blocks = (prog.fns.iter().filter_map(|f| f.body.as_ref())).chain(Some(&prog.blk));
+    for blk in blocks {
+        block_type_at(blk, pos, &mut found);
+    }
+    found.map(|e: &hir::Expr| (e.span, type_name(&e.typ.borrow())))
+}
+
+fn block_type_at<'a>(blk: &'a hir::Block, pos: Pos, found: &mut Option<&'a hir::Expr>) {
+    for stmt in &blk.stmts {
+        if stmt.span.start <= pos && pos <= stmt.span.end {
+            stmt_type_at(stmt, pos, found);
+        }
+    }
+}
+
+fn stmt_type_at<'a>(stmt: &'a hir::Stmt, pos: Pos, found: &mut Option<&'a hir::Expr>) {
+    use hir::StmtVariant::*;
+    match &stmt.var {
+        If {
+            cond,
+            then,
+            else_ifs,
+            els,
+        } => {
+            expr_type_at(cond, pos, found);
+            stmt_type_at(then, pos, found);
+            for (cond, blk) in else_ifs {
+                expr_type_at(cond, pos, found);
+                stmt_type_at(blk, pos, found);
+            }
+            if let Some(blk) = els {
+                stmt_type_at(blk, pos, found);
+            }
+        }
+        While { cond, body, .. } => {
+            expr_type_at(cond, pos, found);
+            stmt_type_at(body, pos, found);
+        }
+        Block(b) => block_type_at(b, pos, found),
+        Exprs(es) | Print(es) => es.iter().for_each(|e| expr_type_at(e, pos, found)),
+        Return(Some(e)) => expr_type_at(e, pos, found),
+        Scan(_) | Return(None) | Break(_) | Empty => (),
+    }
+}
+
+fn expr_type_at<'a>(expr: &'a hir::Expr, pos: Pos, found: &mut Option<&'a hir::Expr>) {
+    use hir::ExprVariant::*;
+    maybe_grow(|| {
+        if pos < expr.span.start || expr.span.end < pos {
+            return;
+        }
+        *found = Some(expr);
+        match &expr.var {
+            Var(_) | Literal(_) => (),
+            Conv(e) | Unary(_, e) | Assign { val: e, .. } => expr_type_at(e, pos, found),
+            Binary(_, lhs, rhs) | Store { to: lhs, val: rhs } => {
+                expr_type_at(lhs, pos, found);
+                expr_type_at(rhs, pos, found);
+            }
+            Call { args, .. } => args.iter().for_each(|e| expr_type_at(e, pos, found)),
+        }
+    })
+}
+
+/// Span of `name` if it is written starting at `start`
+fn name_span(name: &str, start: Pos) -> Span {
+    let len = name.chars().count();
+    Span::from(start, start.map_inc(len as isize, 0, len as isize))
+}
+
+/// Describe symbol `name`. Functions use `fn_span` as their span, as their
+/// definitions only know the span of the signature.
+fn symbol_info(name: &str, def: &SymbolDef, fn_span: Span, is_param: bool) -> Option<SymbolInfo> {
+    let (typ, is_const, decl_span, doc) = match def {
+        SymbolDef::Var {
+            typ,
+            is_const,
+            decl_span,
+            doc,
+            ..
+        } => (typ.borrow(), *is_const, *decl_span, doc.clone()),
+        SymbolDef::Typ { .. } => return None,
+    };
+    let name_span = name_span(name, decl_span.start);
+
+    if let TypeDef::Function(func) = &*typ {
+        let mut params = vec![];
+        let mut children = vec![];
+        if let Some(body) = &func.body {
+            let scope = body.scope.borrow();
+            for (idx, (name, def)) in scope.defs.iter().enumerate() {
+                let is_param = idx < func.params.len();
+                if is_param {
+                    params.push(format!("{} {}", type_str(&func.params[idx].borrow()), name));
+                }
+                children.extend(symbol_info(name, &def.borrow(), fn_span, is_param));
+            }
+            for stmt in &body.stmts {
+                locals(stmt, &mut children);
+            }
+        }
+        return Some(SymbolInfo {
+            name: name.into(),
+            kind: SymbolKind::Function,
+            name_span,
+            span: fn_span,
+            detail: format!(
+                "{} {}({})",
+                type_str(&func.return_type.borrow()),
+                name,
+                params.join(", ")
+            ),
+            doc,
+            children,
+        });
+    }
+
+    let (kind, detail) = if is_param {
+        (
+            SymbolKind::Parameter,
+            format!("{} {}", type_str(&typ), name),
+        )
+    } else if is_const {
+        (
+            SymbolKind::Constant,
+            format!("const {} {}", type_str(&typ), name),
+        )
+    } else {
+        (SymbolKind::Variable, format!("{} {}", type_str(&typ), name))
+    };
+    Some(SymbolInfo {
+        name: name.into(),
+        kind,
+        name_span,
+        span: decl_span,
+        detail,
+        doc,
+        children: vec![],
+    })
+}
+
+/// Variables declared in blocks nested in `stmt`
+fn locals(stmt: &Stmt, out: &mut Vec<SymbolInfo>) {
+    match &stmt.var {
+        StmtVariant::Block(b) => {
+            for (name, def) in b.scope.borrow().defs.iter() {
+                out.extend(symbol_info(name, &def.borrow(), stmt.span, false));
+            }
+            for stmt in &b.stmts {
+                locals(stmt, out);
+            }
+        }
+        StmtVariant::If(i) => {
+            locals(&i.if_block.borrow(), out);
+            for (_, blk) in &i.else_ifs {
+                locals(&blk.borrow(), out);
+            }
+            if let Some(blk) = &i.else_block {
+                locals(&blk.borrow(), out);
+            }
+        }
+        StmtVariant::While(w) => locals(&w.block.borrow(), out),
+        _ => (),
+    }
+}
+
+/// Find the declaration `name` used at `at` in `scope` refers to. `func` is
+/// the id of the scope of the function `at` is in, and how many parameters
+/// it has. Variables only come into scope after they are declared, so later
+/// declarations in the same block are skipped.
+fn resolve(
+    name: &str,
+    at: Span,
+    scope: &Ptr<Scope>,
+    func: Option<(usize, usize)>,
+) -> Option<SymbolInfo> {
+    resolve_in(name, at, scope, func).map(|(_, def)| def)
+}
+
+/// [`resolve`], with the id of the scope the declaration is in
+fn resolve_in(
+    name: &str,
+    at: Span,
+    scope: &Ptr<Scope>,
+    func: Option<(usize, usize)>,
+) -> Option<(usize, SymbolInfo)> {
+    let mut scope = Some(scope.cp());
+    while let Some(cur) = scope {
+        let cur = cur.borrow();
+        if let Some((idx, _, def)) = cur.defs.get_full(&Symbol::intern(name)) {
+            let def = def.borrow();
+            if let SymbolDef::Var { typ, decl_span, .. } = &*def {
+                let is_fn = matches!(&*typ.borrow(), TypeDef::Function(_));
+                if is_fn || decl_span.start <= at.start {
+                    let is_param = match func {
+                        Some((id, params)) => id == cur.id && idx < params,
+                        None => false,
+                    };
+                    let info = symbol_info(name, &def, *decl_span, is_param)?;
+                    return Some((cur.id, info));
+                }
+            }
+        }
+        scope = cur.last.as_ref().map(|last| last.cp());
+    }
+    None
+}
+
+/// How many scopes out from `scope` the one with `id` is
+fn scope_depth(scope: &Ptr<Scope>, id: usize) -> Option<usize> {
+    let mut scope = Some(scope.cp());
+    let mut depth = 0;
+    while let Some(cur) = scope {
+        let cur = cur.borrow();
+        if cur.id == id {
+            return Some(depth);
+        }
+        depth += 1;
+        scope = cur.last.as_ref().map(|last| last.cp());
+    }
+    None
+}
+
+/// Walks the program looking for the symbol at `pos`
+struct Finder {
+    pos: Pos,
+    /// Id of the scope of the function being searched, and how many
+    /// parameters it has
+    func: Option<(usize, usize)>,
+    found: Option<SymbolRef>,
+}
+
+impl Finder {
+    fn hits(&self, span: Span) -> bool {
+        span.start <= self.pos && self.pos <= span.end
+    }
+
+    fn found(&mut self, span: Span, name: &str, scope: &Ptr<Scope>) {
+        if self.found.is_none() && self.hits(span) {
+            if let Some(def) = resolve(name, span, scope, self.func) {
+                self.found = Some(SymbolRef { span, def });
+            }
+        }
+    }
+
+    fn block(&mut self, blk: &Block) {
+        let scope = &blk.scope;
+        // * Names in declarations
+        let defs: Vec<_> = scope
+            .borrow()
+            .defs
+            .iter()
+            .map(|(name, def)| (*name, def.cp()))
+            .collect();
+        for (name, def) in defs {
+            if let SymbolDef::Var { typ, decl_span, .. } = &*def.borrow() {
+                self.found(name_span(&name, decl_span.start), &name, scope);
+                if let TypeDef::Function(func) = &*typ.borrow() {
+                    if let Some(body) = &func.body {
+                        let outer = self
+                            .func
+                            .replace((body.scope.borrow().id, func.params.len()));
+                        self.block(body);
+                        self.func = outer;
+                    }
+                }
+            }
+        }
+
+        for stmt in &blk.stmts {
+            if self.found.is_some() {
+                return;
+            }
+            if self.hits(stmt.span) {
+                self.stmt(stmt, scope);
+            }
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt, scope: &Ptr<Scope>) {
+        match &stmt.var {
+            StmtVariant::If(i) => {
+                self.expr(&i.cond, scope);
+                self.stmt(&i.if_block.borrow(), scope);
+                for (cond, blk) in &i.else_ifs {
+                    self.expr(cond, scope);
+                    self.stmt(&blk.borrow(), scope);
+                }
+                if let Some(blk) = &i.else_block {
+                    self.stmt(&blk.borrow(), scope);
+                }
+            }
+            StmtVariant::While(w) => {
+                self.expr(&w.cond, scope);
+                self.stmt(&w.block.borrow(), scope);
+            }
+            StmtVariant::Block(b) => self.block(b),
+            StmtVariant::Expr(e) | StmtVariant::Return(Some(e)) => self.expr(e, scope),
+            StmtVariant::Print(es) | StmtVariant::ManyExpr(es) => {
+                for e in es {
+                    self.expr(e, scope);
+                }
+            }
+            StmtVariant::Scan(i, span) => self.found(*span, &i.name, scope),
+            StmtVariant::Return(None) | StmtVariant::Break(_) | StmtVariant::Empty => (),
+        }
+    }
+
+    fn expr(&mut self, expr: &Ptr<Expr>, scope: &Ptr<Scope>) {
+        maybe_grow(|| {
+            let expr = expr.borrow();
+            if self.found.is_some() || !self.hits(expr.span) {
+                return;
+            }
+            match &expr.var {
+                ExprVariant::Ident(i) => self.found(expr.span, &i.name, scope),
+                ExprVariant::Literal(_) => (),
+                ExprVariant::TypeConversion(t) => self.expr(&t.expr, scope),
+                ExprVariant::UnaryOp(u) => self.expr(&u.val, scope),
+                ExprVariant::BinaryOp(b) => {
+                    self.expr(&b.lhs, scope);
+                    self.expr(&b.rhs, scope);
+                }
+                ExprVariant::FunctionCall(f) => {
+                    self.found(name_span(&f.func, expr.span.start), &f.func, scope);
+                    for p in &f.params {
+                        self.expr(p, scope);
+                    }
+                }
+                ExprVariant::StructChild(s) => self.expr(&s.val, scope),
+                ExprVariant::ArrayChild(a) => {
+                    self.expr(&a.val, scope);
+                    self.expr(&a.idx, scope);
+                }
+            }
+        })
+    }
+}
+
+/// Walks the whole program, collecting declarations and uses for [`Xref`]
+struct Collector {
+    /// Id of the scope of the function being walked, and how many
+    /// parameters it has
+    func: Option<(usize, usize)>,
+    symbols: Vec<XrefEntry>,
+    /// Id of the scope each of `symbols` is declared in
+    homes: Vec<usize>,
+    /// Index in `symbols` of each declaration, by where its name starts and
+    /// the name
+    index: BTreeMap<(usize, String), usize>,
+    /// Every use, with the index in `symbols` of what it refers to and the
+    /// scope it is in
+    refs: Vec<(usize, Span, Ptr<Scope>)>,
+}
+
+impl Collector {
+    fn new(prog: &Program) -> Collector {
+        let mut collector = Collector {
+            func: None,
+            symbols: vec![],
+            homes: vec![],
+            index: BTreeMap::new(),
+            refs: vec![],
+        };
+        collector.block(&prog.blk);
+        collector
+    }
+
+    /// Index in `symbols` of `def`, declared in the scope with id `home`
+    fn entry(&mut self, mut def: SymbolInfo, home: usize) -> usize {
+        def.children.clear();
+        let key = (def.name_span.start.index, def.name.clone());
+        let (symbols, homes) = (&mut self.symbols, &mut self.homes);
+        *self.index.entry(key).or_insert_with(|| {
+            symbols.push(XrefEntry { def, uses: vec![] });
+            homes.push(home);
+            symbols.len() - 1
+        })
+    }
+
+    fn used(&mut self, span: Span, name: &str, scope: &Ptr<Scope>) {
+        if let Some((home, def)) = resolve_in(name, span, scope, self.func) {
+            let idx = self.entry(def, home);
+            self.symbols[idx].uses.push(span);
+            self.refs.push((idx, span, scope.cp()));
+        }
+    }
+
+    fn block(&mut self, blk: &Block) {
+        let scope = &blk.scope;
+        let defs: Vec<_> = (scope.borrow().defs.iter())
+            .map(|(name, def)| (*name, def.cp()))
+            .collect();
+        for (idx, (name, def)) in defs.into_iter().enumerate() {
+            let def = def.borrow();
+            let is_param = match self.func {
+                Some((id, params)) => id == scope.borrow().id && idx < params,
+                None => false,
+            };
+            if let SymbolDef::Var { typ, decl_span, .. } = &*def {
+                if let Some(info) = symbol_info(&name, &def, *decl_span, is_param) {
+                    let home = scope.borrow().id;
+                    self.entry(info, home);
+                }
+                if let TypeDef::Function(func) = &*typ.borrow() {
+                    if let Some(body) = &func.body {
+                        let outer = self
+                            .func
+                            .replace((body.scope.borrow().id, func.params.len()));
+                        self.block(body);
+                        self.func = outer;
+                    }
+                }
+            }
+        }
+
+        for stmt in &blk.stmts {
+            self.stmt(stmt, scope);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt, scope: &Ptr<Scope>) {
+        match &stmt.var {
+            StmtVariant::If(i) => {
+                self.expr(&i.cond, scope);
+                self.stmt(&i.if_block.borrow(), scope);
+                for (cond, blk) in &i.else_ifs {
+                    self.expr(cond, scope);
+                    self.stmt(&blk.borrow(), scope);
+                }
+                if let Some(blk) = &i.else_block {
+                    self.stmt(&blk.borrow(), scope);
+                }
+            }
+            StmtVariant::While(w) => {
+                self.expr(&w.cond, scope);
+                self.stmt(&w.block.borrow(), scope);
+            }
+            StmtVariant::Block(b) => self.block(b),
+            StmtVariant::Expr(e) | StmtVariant::Return(Some(e)) => self.expr(e, scope),
+            StmtVariant::Print(es) => {
+                for e in es {
+                    self.expr(e, scope);
+                }
+            }
+            // * Initializers are assignments to the name declared, which is
+            // * not a use of it
+            StmtVariant::ManyExpr(es) => {
+                for e in es {
+                    match &e.borrow().var {
+                        ExprVariant::BinaryOp(b) => self.expr(&b.rhs, scope),
+                        _ => self.expr(e, scope),
+                    }
+                }
+            }
+            StmtVariant::Scan(i, span) => self.used(*span, &i.name, scope),
+            StmtVariant::Return(None) | StmtVariant::Break(_) | StmtVariant::Empty => (),
+        }
+    }
+
+    fn expr(&mut self, expr: &Ptr<Expr>, scope: &Ptr<Scope>) {
+        maybe_grow(|| {
+            let expr = expr.borrow();
+            match &expr.var {
+                ExprVariant::Ident(i) => self.used(expr.span, &i.name, scope),
+                ExprVariant::Literal(_) => (),
+                ExprVariant::TypeConversion(t) => self.expr(&t.expr, scope),
+                ExprVariant::UnaryOp(u) => self.expr(&u.val, scope),
+                ExprVariant::BinaryOp(b) => {
+                    self.expr(&b.lhs, scope);
+                    self.expr(&b.rhs, scope);
+                }
+                ExprVariant::FunctionCall(f) => {
+                    self.used(name_span(&f.func, expr.span.start), &f.func, scope);
+                    for p in &f.params {
+                        self.expr(p, scope);
+                    }
+                }
+                ExprVariant::StructChild(s) => self.expr(&s.val, scope),
+                ExprVariant::ArrayChild(a) => {
+                    self.expr(&a.val, scope);
+                    self.expr(&a.idx, scope);
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,509 @@
+//! A tree-walking interpreter running C0 programs straight from the AST.
+//!
+//! This is the reference semantics of C0 as this compiler understands it,
+//! written to be obviously correct rather than fast. It shares nothing with
+//! code generation, so running a program both here and on the VM is a cheap
+//! way to catch compiler bugs.
+//!
+//! Differences from C worth knowing:
+//!
+//! - `int` arithmetic wraps on overflow.
+//! - `char` is promoted to `int` in arithmetic, like C.
+//! - Comparisons and `&&`/`||` evaluate to `1` or `0`.
+//! - `double`s are printed like `printf("%f")`.
+
+use super::ast::*;
+use super::symbol::Symbol;
+use super::type_checker;
+use crate::consteval::{self, Kind, Value, ValueError};
+use crate::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Display, Formatter};
+use std::io::{BufRead, Write};
+
+/// Calls nested deeper than this are reported as a stack overflow.
+pub const MAX_CALL_DEPTH: usize = 100_000;
+
+pub type RuntimeResult<T> = Result<T, RuntimeError>;
+
+/// An error that stops the interpreter.
+#[derive(Debug)]
+pub enum RuntimeError {
+    NoMainFunction,
+    CannotFindVar(String),
+    CannotFindFn(String),
+    ArgumentMismatch(String),
+    VoidValue,
+    IntOverflow,
+    DivideByZero,
+    StackOverflow,
+    StepLimitExceeded(u64),
+    Unsupported(String),
+    BadInput(String),
+    UnexpectedEof,
+    Io(std::io::Error),
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        use RuntimeError::*;
+        match self {
+            NoMainFunction => write!(f, "Cannot find function `main`"),
+            CannotFindVar(v) => write!(f, "Unable to find variable: {}", v),
+            CannotFindFn(func) => write!(f, "Unable to find function: {}", func),
+            ArgumentMismatch(func) => write!(f, "Wrong number of arguments to {}", func),
+            VoidValue => write!(f, "A void value is used"),
+            IntOverflow => write!(f, "Integer literal does not fit in `int`"),
+            DivideByZero => write!(f, "Integer division by zero"),
+            StackOverflow => write!(f, "Stack overflow"),
+            StepLimitExceeded(n) => write!(f, "Program did not finish in {} steps", n),
+            Unsupported(what) => write!(f, "{} is not supported", what),
+            BadInput(s) => write!(f, "Bad input: {:?}", s),
+            UnexpectedEof => write!(f, "Input ended unexpectedly"),
+            Io(e) => write!(f, "IO error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+impl From<ValueError> for RuntimeError {
+    fn from(e: ValueError) -> Self {
+        match e {
+            ValueError::DivideByZero => RuntimeError::DivideByZero,
+            ValueError::Overflow => RuntimeError::IntOverflow,
+            ValueError::Void => RuntimeError::VoidValue,
+            ValueError::Unsupported(what) => RuntimeError::Unsupported(what),
+        }
+    }
+}
+
+impl From<std::io::Error> for RuntimeError {
+    fn from(e: std::io::Error) -> Self {
+        RuntimeError::Io(e)
+    }
+}
+
+/// Which kind of value variables of type `typ` hold
+fn kind_of(typ: &TypeDef, scope: &Ptr<Scope>) -> RuntimeResult<Kind> {
+    consteval::kind_of(typ, scope)
+        .ok_or_else(|| RuntimeError::Unsupported(format!("Type {:?}", typ)))
+}
+
+#[derive(Debug)]
+struct Var {
+    val: Value,
+    kind: Kind,
+}
+
+/// What a statement asks its enclosing statements to do next
+enum Flow {
+    Normal,
+    Break(Option<Symbol>),
+    Return(Value),
+}
+
+type Vars = HashMap<Symbol, Var>;
+
+pub struct Interpreter<'a> {
+    prog: &'a Program,
+    /// Types of `auto` variables, by scope id and name
+    inferred: BTreeMap<(usize, String), Ptr<TypeDef>>,
+    globals: Vars,
+    /// Block scopes of every active call, innermost last
+    frames: Vec<Vec<Vars>>,
+    input: &'a mut dyn BufRead,
+    output: &'a mut dyn Write,
+    max_steps: Option<u64>,
+    steps: u64,
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new(
+        prog: &'a Program,
+        input: &'a mut dyn BufRead,
+        output: &'a mut dyn Write,
+    ) -> Interpreter<'a> {
+        Interpreter {
+            prog,
+            inferred: (type_checker::lower(prog).map(|t| t.inferred)).unwrap_or_default(),
+            globals: HashMap::new(),
+            frames: vec![],
+            input,
+            output,
+            max_steps: None,
+            steps: 0,
+        }
+    }
+
+    /// Stop the program after evaluating `steps` statements and expressions.
+    pub fn with_step_limit(mut self, steps: u64) -> Self {
+        self.max_steps = Some(steps);
+        self
+    }
+
+    /// Initialize globals, then run `main`. Returns the value `main` returns,
+    /// or 0 if it returns nothing.
+    pub fn run(&mut self) -> RuntimeResult<i32> {
+        self.globals.clear();
+        self.frames.clear();
+        self.steps = 0;
+
+        let blk = &self.prog.blk;
+        for stmt in &blk.stmts {
+            if let Flow::Return(_) | Flow::Break(_) = self.exec_stmt(stmt, &blk.scope)? {
+                return Err(RuntimeError::Unsupported(
+                    "Control flow outside functions".into(),
+                ));
+            }
+        }
+
+        if blk.scope.borrow().find_def_self("main").is_none() {
+            return Err(RuntimeError::NoMainFunction);
+        }
+        let ret = self.call("main", vec![])?;
+        self.output.flush()?;
+        match ret {
+            Value::Void => Ok(0),
+            val => Ok(val.as_int()?),
+        }
+    }
+
+    fn tick(&mut self) -> RuntimeResult<()> {
+        self.steps += 1;
+        match self.max_steps {
+            Some(max) if self.steps > max => Err(RuntimeError::StepLimitExceeded(max)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Scope that declarations currently go into
+    fn cur_vars(&mut self) -> &mut Vars {
+        match self.frames.last_mut() {
+            Some(frame) => frame.last_mut().unwrap(),
+            None => &mut self.globals,
+        }
+    }
+
+    fn var_mut(&mut self, name: Symbol) -> RuntimeResult<&mut Var> {
+        let local = self
+            .frames
+            .last_mut()
+            .and_then(|frame| frame.iter_mut().rev().find_map(|vars| vars.get_mut(&name)));
+        match local {
+            Some(var) => Ok(var),
+            None => self
+                .globals
+                .get_mut(&name)
+                .ok_or_else(|| RuntimeError::CannotFindVar(name.to_string())),
+        }
+    }
+
+    fn call(&mut self, name: &str, args: Vec<Value>) -> RuntimeResult<Value> {
+        let typ = match self.prog.blk.scope.borrow().find_def_self(name) {
+            Some(def) => def.borrow().get_sym().map(|(typ, _)| typ),
+            None => None,
+        };
+        let typ = typ.ok_or_else(|| RuntimeError::CannotFindFn(name.into()))?;
+        let typ = typ.borrow();
+        let func = match &*typ {
+            TypeDef::Function(f) => f,
+            _ => return Err(RuntimeError::CannotFindFn(name.into())),
+        };
+        let body = func
+            .body
+            .as_ref()
+            .ok_or_else(|| RuntimeError::Unsupported(format!("Function {} without body", name)))?;
+        if args.len() != func.params.len() {
+            return Err(RuntimeError::ArgumentMismatch(name.into()));
+        }
+        if self.frames.len() >= MAX_CALL_DEPTH {
+            return Err(RuntimeError::StackOverflow);
+        }
+
+        // * Parameters are the first symbols declared in the function body
+        let mut params = HashMap::new();
+        let param_names = body.scope.borrow().defs.keys().cloned().collect::<Vec<_>>();
+        for ((name, typ), arg) in param_names.into_iter().zip(&func.params).zip(args) {
+            let kind = kind_of(&typ.borrow(), &body.scope)?;
+            let val = arg.cast(kind)?;
+            params.insert(name, Var { val, kind });
+        }
+        let ret_kind = kind_of(&func.return_type.borrow(), &body.scope)?;
+
+        self.frames.push(vec![params]);
+        let flow = self.exec_stmts(&body.stmts, &body.scope);
+        self.frames.pop();
+
+        match flow? {
+            Flow::Return(val) => Ok(val.cast(ret_kind)?),
+            Flow::Normal if ret_kind == Kind::Void => Ok(Value::Void),
+            Flow::Normal => Err(RuntimeError::Unsupported(format!(
+                "Reaching the end of non-void function {}",
+                name
+            ))),
+            Flow::Break(_) => Err(RuntimeError::Unsupported("`break` outside loops".into())),
+        }
+    }
+
+    fn exec_stmts(&mut self, stmts: &[Stmt], scope: &Ptr<Scope>) -> RuntimeResult<Flow> {
+        for stmt in stmts {
+            match self.exec_stmt(stmt, scope)? {
+                Flow::Normal => (),
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn exec_block(&mut self, block: &Block) -> RuntimeResult<Flow> {
+        self.frames
+            .last_mut()
+            .expect("Blocks only appear in functions")
+            .push(HashMap::new());
+        let flow = self.exec_stmts(&block.stmts, &block.scope);
+        self.frames.last_mut().unwrap().pop();
+        flow
+    }
+
+    fn exec_stmt(&mut self, stmt: &Stmt, scope: &Ptr<Scope>) -> RuntimeResult<Flow> {
+        self.tick()?;
+        match &stmt.var {
+            StmtVariant::Empty => (),
+            StmtVariant::Expr(e) => {
+                self.eval(e, scope)?;
+            }
+            StmtVariant::ManyExpr(inits) => {
+                // * Variables come to life at their declaration, so an
+                // * initializer can still see the shadowed outer variable.
+                for (name, def) in scope.borrow().defs_in(stmt.span) {
+                    let typ = match def.borrow().get_sym() {
+                        Some((typ, _)) => typ,
+                        None => continue,
+                    };
+                    if let TypeDef::Function(_) = &*typ.borrow() {
+                        continue;
+                    }
+                    let kind = match &*typ.borrow() {
+                        TypeDef::Unknown => {
+                            let id = scope.borrow().id;
+                            match self.inferred.get(&(id, name.to_string())) {
+                                Some(typ) => kind_of(&typ.borrow(), scope)?,
+                                None => {
+                                    return Err(RuntimeError::Unsupported(format!(
+                                        "Variable {} of unknown type",
+                                        name
+                                    )))
+                                }
+                            }
+                        }
+                        typ => kind_of(typ, scope)?,
+                    };
+                    let mut val = kind.zero();
+                    let init = inits.iter().find(|init| match &init.borrow().var {
+                        ExprVariant::BinaryOp(b) => match &b.lhs.borrow().var {
+                            ExprVariant::Ident(i) => i.name == name,
+                            _ => false,
+                        },
+                        _ => false,
+                    });
+                    if let Some(init) = init {
+                        if let ExprVariant::BinaryOp(b) = &init.borrow().var {
+                            val = self.eval(&b.rhs, scope)?.cast(kind)?;
+                        }
+                    }
+                    self.cur_vars().insert(name, Var { val, kind });
+                }
+            }
+            StmtVariant::Block(block) => return self.exec_block(block),
+            StmtVariant::If(i) => {
+                if self.eval(&i.cond, scope)?.is_true()? {
+                    return self.exec_stmt(&i.if_block.borrow(), scope);
+                }
+                for (cond, body) in &i.else_ifs {
+                    if self.eval(cond, scope)?.is_true()? {
+                        return self.exec_stmt(&body.borrow(), scope);
+                    }
+                }
+                if let Some(body) = &i.else_block {
+                    return self.exec_stmt(&body.borrow(), scope);
+                }
+            }
+            StmtVariant::While(w) => {
+                let label = w.label.as_ref().map(|l| l.name);
+                while self.eval(&w.cond, scope)?.is_true()? {
+                    match self.exec_stmt(&w.block.borrow(), scope)? {
+                        Flow::Normal => (),
+                        Flow::Break(None) => break,
+                        Flow::Break(Some(l)) if Some(l) == label => break,
+                        flow => return Ok(flow),
+                    }
+                }
+            }
+            StmtVariant::Break(label) => {
+                return Ok(Flow::Break(label.as_ref().map(|l| l.name)));
+            }
+            StmtVariant::Return(val) => {
+                let val = match val {
+                    Some(val) => self.eval(val, scope)?,
+                    None => Value::Void,
+                };
+                return Ok(Flow::Return(val));
+            }
+            StmtVariant::Print(vals) => {
+                for (idx, val) in vals.iter().enumerate() {
+                    if idx != 0 {
+                        write!(self.output, " ")?;
+                    }
+                    // * Strings can only be printed, so they are no values
+                    if let ExprVariant::Literal(Literal::String { val }) = &val.borrow().var {
+                        write!(self.output, "{}", val)?;
+                        continue;
+                    }
+                    let val = self.eval(val, scope)?;
+                    if val == Value::Void {
+                        return Err(RuntimeError::VoidValue);
+                    }
+                    write!(self.output, "{}", val)?;
+                }
+                writeln!(self.output)?;
+            }
+            StmtVariant::Scan(ident, _) => {
+                let kind = self.var_mut(ident.name)?.kind;
+                let val = match kind {
+                    Kind::Char => Value::Char(self.read_char()?),
+                    Kind::Int => {
+                        let token = self.read_token()?;
+                        Value::Int(token.parse().map_err(|_| RuntimeError::BadInput(token))?)
+                    }
+                    Kind::Double => {
+                        let token = self.read_token()?;
+                        Value::Double(token.parse().map_err(|_| RuntimeError::BadInput(token))?)
+                    }
+                    Kind::Void => return Err(RuntimeError::VoidValue),
+                    Kind::UInt | Kind::Bool => {
+                        return Err(RuntimeError::Unsupported(format!("Scanning {:?}", kind)))
+                    }
+                };
+                self.var_mut(ident.name)?.val = val;
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn eval(&mut self, expr: &Ptr<Expr>, scope: &Ptr<Scope>) -> RuntimeResult<Value> {
+        maybe_grow(|| self.eval_inner(&expr.borrow(), scope))
+    }
+
+    fn eval_inner(&mut self, expr: &Expr, scope: &Ptr<Scope>) -> RuntimeResult<Value> {
+        self.tick()?;
+        match &expr.var {
+            ExprVariant::Ident(i) => Ok(self.var_mut(i.name)?.val.clone()),
+            ExprVariant::Literal(lit) => Ok(consteval::literal(lit)?),
+            ExprVariant::TypeConversion(c) => {
+                let kind = kind_of(&c.to.borrow(), scope)?;
+                Ok(self.eval(&c.expr, scope)?.cast(kind)?)
+            }
+            ExprVariant::UnaryOp(u) => {
+                let val = self.eval(&u.val, scope)?;
+                match consteval::un_op(u.op) {
+                    Some(op) => Ok(val.unary(op)?),
+                    None => Err(RuntimeError::Unsupported(format!("Operator {}", u.op))),
+                }
+            }
+            ExprVariant::BinaryOp(b) => self.eval_bin_op(b, scope),
+            ExprVariant::FunctionCall(f) => {
+                let args = f
+                    .params
+                    .iter()
+                    .map(|arg| self.eval(arg, scope))
+                    .collect::<RuntimeResult<Vec<_>>>()?;
+                self.call(&f.func, args)
+            }
+            ExprVariant::StructChild(_) | ExprVariant::ArrayChild(_) => {
+                Err(RuntimeError::Unsupported(format!("Expression {}", expr)))
+            }
+        }
+    }
+
+    fn eval_bin_op(&mut self, b: &BinaryOp, scope: &Ptr<Scope>) -> RuntimeResult<Value> {
+        use OpVar::*;
+        match b.op {
+            _Asn | _Csn => {
+                let name = match &b.lhs.borrow().var {
+                    ExprVariant::Ident(i) => i.name,
+                    _ => {
+                        return Err(RuntimeError::Unsupported(
+                            "Assigning to non-variables".into(),
+                        ))
+                    }
+                };
+                let val = self.eval(&b.rhs, scope)?;
+                let var = self.var_mut(name)?;
+                var.val = val.cast(var.kind)?;
+                // * The value of the assignment, for where it is allowed to
+                // * have one; codegen rejects the rest
+                return Ok(var.val.clone());
+            }
+            _Com => {
+                self.eval(&b.lhs, scope)?;
+                return self.eval(&b.rhs, scope);
+            }
+            And => {
+                let res =
+                    self.eval(&b.lhs, scope)?.is_true()? && self.eval(&b.rhs, scope)?.is_true()?;
+                return Ok(Value::Int(res as i32));
+            }
+            Or => {
+                let res =
+                    self.eval(&b.lhs, scope)?.is_true()? || self.eval(&b.rhs, scope)?.is_true()?;
+                return Ok(Value::Int(res as i32));
+            }
+            _ => (),
+        }
+
+        let lhs = self.eval(&b.lhs, scope)?;
+        let rhs = self.eval(&b.rhs, scope)?;
+        match consteval::bin_op(b.op) {
+            Some(op) => Ok(lhs.binary(op, &rhs)?),
+            None => Err(RuntimeError::Unsupported(format!("Operator {}", b.op))),
+        }
+    }
+
+    /// Read a whitespace-separated token from input
+    fn read_token(&mut self) -> RuntimeResult<String> {
+        let mut token = Vec::new();
+        loop {
+            let buf = self.input.fill_buf()?;
+            let c = match buf.first() {
+                Some(c) => *c,
+                None => break,
+            };
+            if c.is_ascii_whitespace() {
+                if !token.is_empty() {
+                    break;
+                }
+            } else {
+                token.push(c);
+            }
+            self.input.consume(1);
+        }
+        if token.is_empty() {
+            return Err(RuntimeError::UnexpectedEof);
+        }
+        Ok(String::from_utf8_lossy(&token).into_owned())
+    }
+
+    fn read_char(&mut self) -> RuntimeResult<u8> {
+        loop {
+            let c = match self.input.fill_buf()?.first() {
+                Some(c) => *c,
+                None => return Err(RuntimeError::UnexpectedEof),
+            };
+            self.input.consume(1);
+            if !c.is_ascii_whitespace() {
+                return Ok(c);
+            }
+        }
+    }
+}
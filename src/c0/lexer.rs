@@ -1,27 +1,20 @@
 use super::err::*;
+use super::num;
+use super::symbol::Symbol;
 use crate::prelude::*;
-use once_cell::sync::*;
-use ramp;
-use std::collections::HashMap;
-use std::iter::{Iterator, Peekable};
-use std::str::{Chars, FromStr};
-use std::{
-    cell::RefCell,
-    convert::{Into, TryInto},
-    fmt,
-    fmt::Display,
-    fmt::Formatter,
-    hash::Hash,
-    rc::Rc,
-    string::String,
-};
+use alloc::borrow::Cow;
+use core::iter::Iterator;
+use core::str::FromStr;
+use core::{convert::TryInto, fmt, fmt::Display, fmt::Formatter, hash::Hash};
 
 #[derive(Debug, Eq, PartialEq, Hash, Clone)]
 /// This enum defines the variants of token in C0 language. Variants are pretty
-/// self-explanatory.
-pub enum TokenType {
+/// self-explanatory. Text from the source, like comments, is borrowed from it
+/// where it can be, for `'src`.
+pub enum TokenType<'src> {
     // Keywords
     Const,
+    Auto,
     As,
     If,
     Else,
@@ -60,14 +53,18 @@ pub enum TokenType {
     RCurlyBrace,
     Assign,
     Comma,
+    Colon,
     Dot,
 
     // Identifier
-    Identifier(String),
-    Literal(Literal),
+    Identifier(Symbol),
+    Literal(Literal<'src>),
 
     // Comment, will be discarded before handed out
-    Comment(String),
+    Comment(Cow<'src, str>),
+    /// A `///` comment documenting the declaration after it, without the
+    /// `///`. Handed out for the parser to attach to the declaration.
+    DocComment(Cow<'src, str>),
 
     // Special
     EndOfFile,
@@ -75,11 +72,72 @@ pub enum TokenType {
     Error(LexError),
 }
 
-impl Display for TokenType {
+impl TokenType<'_> {
+    /// This token type, owning its text, so that it can outlive the source
+    pub fn into_owned(self) -> TokenType<'static> {
+        use self::TokenType::*;
+        match self {
+            Const => Const,
+            Auto => Auto,
+            As => As,
+            If => If,
+            Else => Else,
+            While => While,
+            Break => Break,
+            Continue => Continue,
+            Return => Return,
+            Print => Print,
+            Scan => Scan,
+
+            Semicolon => Semicolon,
+            Minus => Minus,
+            Plus => Plus,
+            Multiply => Multiply,
+            Divide => Divide,
+            Not => Not,
+            BinaryAnd => BinaryAnd,
+            BinaryOr => BinaryOr,
+            And => And,
+            Or => Or,
+            Xor => Xor,
+            Increase => Increase,
+            Decrease => Decrease,
+            Equals => Equals,
+            NotEquals => NotEquals,
+            LessThan => LessThan,
+            LessOrEqualThan => LessOrEqualThan,
+            GreaterThan => GreaterThan,
+            GreaterOrEqualThan => GreaterOrEqualThan,
+            LParenthesis => LParenthesis,
+            RParenthesis => RParenthesis,
+            LBracket => LBracket,
+            RBracket => RBracket,
+            LCurlyBrace => LCurlyBrace,
+            RCurlyBrace => RCurlyBrace,
+            Assign => Assign,
+            Comma => Comma,
+            Colon => Colon,
+            Dot => Dot,
+
+            Identifier(ident) => Identifier(ident),
+            Literal(lit) => Literal(lit.into_owned()),
+
+            Comment(s) => Comment(Cow::Owned(s.into_owned())),
+            DocComment(s) => DocComment(Cow::Owned(s.into_owned())),
+
+            EndOfFile => EndOfFile,
+            Dummy => Dummy,
+            Error(reason) => Error(reason),
+        }
+    }
+}
+
+impl Display for TokenType<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         use self::TokenType::*;
         match self {
             Const => write!(f, "Const"),
+            Auto => write!(f, "Auto"),
             As => write!(f, "As"),
             If => write!(f, "If"),
             Else => write!(f, "Else"),
@@ -117,12 +175,14 @@ impl Display for TokenType {
             RCurlyBrace => write!(f, "'}}'"),
             Assign => write!(f, "'='"),
             Comma => write!(f, "','"),
+            Colon => write!(f, "':'"),
             Dot => write!(f, "'.'"),
 
             Identifier(ident) => write!(f, "Identifier(\"{}\")", ident),
             Literal(b) => write!(f, "Literal({})", b),
 
             Comment(s) => write!(f, "Comment({})", s),
+            DocComment(s) => write!(f, "DocComment({})", s),
 
             EndOfFile => write!(f, "#EOF"),
             Dummy => write!(f, "<dummy>"),
@@ -133,16 +193,31 @@ impl Display for TokenType {
 }
 
 #[derive(Debug, Eq, PartialEq, Hash, Clone)]
-pub enum Literal {
+pub enum Literal<'src> {
     Char(char),
-    String(String),
+    /// Borrowed from the source, unless it has escapes
+    String(Cow<'src, str>),
     Boolean(bool),
-    Integer(ramp::Int),
-    Float(ramp::rational::Rational),
+    Integer(num::Int),
+    Float(num::Rational),
     _Dummy,
 }
 
-impl Display for Literal {
+impl Literal<'_> {
+    pub fn into_owned(self) -> Literal<'static> {
+        use self::Literal::*;
+        match self {
+            Char(c) => Char(c),
+            String(s) => String(Cow::Owned(s.into_owned())),
+            Boolean(b) => Boolean(b),
+            Integer(i) => Integer(i),
+            Float(f) => Float(f),
+            _Dummy => _Dummy,
+        }
+    }
+}
+
+impl Display for Literal<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         use self::Literal::*;
         match self {
@@ -158,30 +233,30 @@ impl Display for Literal {
 
 /// A single token
 #[derive(Debug, Clone)]
-pub struct Token {
+pub struct Token<'src> {
     /// Its variant
-    pub var: TokenType,
+    pub var: TokenType<'src>,
 
     /// The space the token occupies
     pub span: Span,
 }
 
-impl Token {
-    pub fn get_ident(&self) -> Result<&str, ()> {
+impl<'src> Token<'src> {
+    pub fn get_ident(&self) -> Option<Symbol> {
         match &self.var {
-            TokenType::Identifier(s) => Ok(&s),
-            _ => Err(()),
+            TokenType::Identifier(s) => Some(*s),
+            _ => None,
         }
     }
 
-    pub fn dummy() -> Token {
+    pub fn dummy() -> Token<'src> {
         Token {
             var: TokenType::Dummy,
             span: Span::zero(),
         }
     }
 
-    pub fn eof() -> Token {
+    pub fn eof() -> Token<'src> {
         Token {
             var: TokenType::EndOfFile,
             span: Span::zero(),
@@ -189,108 +264,111 @@ impl Token {
     }
 
     pub fn is_err(&self) -> bool {
-        match self.var {
-            TokenType::Error(_) => true,
-            _ => false,
-        }
+        matches!(self.var, TokenType::Error(_))
     }
 }
 
-impl Display for Token {
+impl Display for Token<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         write!(f, "Token{{var: {}, span: {} }}", self.var, self.span)
     }
 }
 
-static OperatorCombination: Lazy<HashMap<char, Box<Vec<char>>>> = Lazy::new(|| {
-    [
-        ('<', Box::new(vec!['='])),
-        ('>', Box::new(vec!['='])),
-        ('=', Box::new(vec!['='])),
-        ('!', Box::new(vec!['='])),
-        ('+', Box::new(vec!['+'])),
-        ('-', Box::new(vec!['-'])),
-        ('&', Box::new(vec!['&'])),
-        ('|', Box::new(vec!['|'])),
-        ('/', Box::new(vec!['/', '*'])),
-    ]
-    .iter()
-    .cloned()
-    .collect()
-});
-
-pub struct StringPosIter<T>
-where
-    T: Iterator<Item = char>,
-{
-    chars: std::iter::Chain<T, std::iter::Once<char>>,
+/// Largest absolute decimal exponent accepted in a float literal.
+const MAX_FLOAT_EXPONENT: i32 = 4096;
+
+/// Chars that can follow `first` in an operator of two chars
+fn operator_combination(first: char) -> &'static [char] {
+    match first {
+        '<' | '>' | '=' | '!' => &['='],
+        '+' => &['+'],
+        '-' => &['-'],
+        '&' => &['&'],
+        '|' => &['|'],
+        '/' => &['/', '*'],
+        _ => &[],
+    }
+}
+
+/// Chars of a source, with their positions, and a `'\0'` after the last
+#[derive(Clone, Copy)]
+pub struct StringPosIter<'src> {
+    src: &'src str,
+    /// Byte offset of the next char, past the end once the `'\0'` is read
+    offset: usize,
     pos: Pos,
     is_last_cr: bool,
 }
 
-impl<T> StringPosIter<T>
-where
-    T: Iterator<Item = char>,
-{
-    pub fn new(src: T) -> StringPosIter<T> {
-        let chars = src.chain(std::iter::once('\0'));
+impl<'src> StringPosIter<'src> {
+    pub fn new(src: &'src str) -> StringPosIter<'src> {
         StringPosIter {
-            chars,
+            src,
+            offset: 0,
             pos: Pos::zero(),
             is_last_cr: false,
         }
     }
+
+    /// The next char, without reading it
+    pub fn peek(&self) -> Option<(Pos, char)> {
+        let mut iter = *self;
+        iter.next()
+    }
+
+    /// Read the next char if it satisfies `f`
+    pub fn next_if(&mut self, f: impl FnOnce(&(Pos, char)) -> bool) -> Option<(Pos, char)> {
+        self.peek().filter(f).and_then(|_| self.next())
+    }
+
+    /// Byte offset of the next char in the source
+    pub fn offset(&self) -> usize {
+        self.offset.min(self.src.len())
+    }
+
+    /// The source between byte offsets `start` and `end`
+    pub fn slice(&self, start: usize, end: usize) -> &'src str {
+        &self.src[start..end]
+    }
 }
 
-impl<T> Iterator for StringPosIter<T>
-where
-    T: Iterator<Item = char>,
-{
+impl Iterator for StringPosIter<'_> {
     type Item = (Pos, char);
     fn next(&mut self) -> Option<Self::Item> {
-        let next_char = self.chars.next();
-        match next_char {
-            None => None,
-            Some(ch) => {
-                let ret = Some((self.pos, ch));
-                match ch {
-                    '\n' => {
-                        if !self.is_last_cr {
-                            self.pos.lf_self();
-                        } else {
-                            self.pos.bump_self();
-                        }
-                        self.is_last_cr = false;
-                    }
-                    '\r' => {
-                        self.pos.lf_self();
-                        self.is_last_cr = true;
-                    }
-                    _ => {
-                        self.is_last_cr = false;
-                        self.pos.inc_self();
-                    }
-                };
-                ret
+        let ch = self.src.get(self.offset..)?.chars().next().unwrap_or('\0');
+        self.offset += ch.len_utf8();
+        let ret = Some((self.pos, ch));
+        match ch {
+            '\n' => {
+                if !self.is_last_cr {
+                    self.pos.lf_self();
+                } else {
+                    self.pos.bump_self();
+                }
+                self.is_last_cr = false;
             }
-        }
+            '\r' => {
+                self.pos.lf_self();
+                self.is_last_cr = true;
+            }
+            _ => {
+                self.is_last_cr = false;
+                self.pos.inc_self();
+            }
+        };
+        ret
     }
 }
 
-pub struct Lexer<T>
-where
-    T: Iterator<Item = char>,
-{
-    iter: Peekable<StringPosIter<T>>,
+/// Splits a source into [`Token`]s, which borrow from it
+pub struct Lexer<'src> {
+    iter: StringPosIter<'src>,
     err: Option<Vec<super::err::ParseError>>,
 }
 
-impl<T> Iterator for Lexer<T>
-where
-    T: Iterator<Item = char>,
-{
-    type Item = Token;
-    fn next(&mut self) -> Option<Token> {
+impl<'src> Iterator for Lexer<'src> {
+    type Item = Token<'src>;
+    fn next(&mut self) -> Option<Token<'src>> {
         loop {
             let tok = self.get_next_token();
             if let Some(Token {
@@ -305,36 +383,33 @@ where
     }
 }
 
-impl<T> Lexer<T>
-where
-    T: Iterator<Item = char>,
-{
-    pub fn new(iter: T) -> Lexer<T> {
+impl<'src> Lexer<'src> {
+    pub fn new(src: &'src str) -> Lexer<'src> {
         Lexer {
-            iter: StringPosIter::new(iter).peekable(),
+            iter: StringPosIter::new(src),
             err: None,
         }
     }
 
-    pub fn get_next_token(&mut self) -> Option<Token> {
+    pub fn get_next_token(&mut self) -> Option<Token<'src>> {
         Self::skip_spaces(&mut self.iter);
         // the first character of next token
         let (pos, c) = match self.iter.peek() {
             Some((_, '\0')) => return None,
-            Some((pos, c)) => (*pos, *c),
+            Some((pos, c)) => (pos, c),
             // spaces may occur at the end of file
             None => return None,
         };
 
         let tok = match c {
-            '0'..='9' => self.lex_number(),
+            '0'..='9' => return Some(self.lex_number()),
             'a'..='z' | 'A'..='Z' | '_' => self.lex_identifier(),
             '\"' => self.lex_string_literal(),
             '\'' => self.lex_char_literal(),
             '+' | '-' | '*' | '/' | '<' | '>' | '=' | '!' | '|' | '&' | '^' | '(' | ')' | '['
-            | ']' | '{' | '}' | ',' | ';' => self.lex_operator(),
+            | ']' | '{' | '}' | ',' | ':' | ';' => self.lex_operator(),
             // TODO: Add to errors and skip this line
-            c @ _ => Err(LexError::UnexpectedCharacter(c)),
+            c => Err(LexError::UnexpectedCharacter(c)),
         };
 
         let tok = match tok {
@@ -355,58 +430,90 @@ where
         let start_pos = self
             .iter
             .peek()
-            .map(|x| *x)
-            .or(Some((
+            .unwrap_or((
                 Pos {
-                    ln: usize::max_value(),
-                    pos: usize::max_value(),
-                    index: usize::max_value(),
+                    ln: usize::MAX,
+                    pos: usize::MAX,
+                    index: usize::MAX,
                 },
                 '\0',
-            )))
-            .unwrap()
+            ))
             .0;
         let mut end_pos = start_pos;
-        while self.iter.peek().map_or(false, |x| !x.1.is_whitespace()) {
+        while self.iter.peek().is_some_and(|x| !x.1.is_whitespace()) {
             self.iter.next();
             end_pos = end_pos.inc();
         }
         end_pos
     }
 
-    fn lex_int(&mut self, base: u8) -> Result<ramp::Int, ramp::int::ParseIntError> {
+    fn lex_int(&mut self, base: u8) -> Option<num::Int> {
         let mut number = String::new();
 
         while self
             .iter
             .peek()
-            .map_or(false, |ch_ind| (*ch_ind).1.is_digit(10))
+            .is_some_and(|ch_ind| ch_ind.1.is_ascii_digit())
         {
             number.push(self.iter.next().unwrap().1);
         }
 
-        return ramp::Int::from_str_radix(&number, base);
+        num::parse_int(&number, base)
     }
 
-    /// Lex a number
-    fn lex_number(&mut self) -> LexResult<Token> {
+    /// Lex a number. A malformed one is read whole, up to the first char that
+    /// cannot be in a number, into one error token, so that lexing goes on
+    /// right after it.
+    fn lex_number(&mut self) -> Token<'src> {
         let start_pos = self.iter.peek().expect("This value should be valid").0;
+        let res = self.lex_number_value();
+        // * Letters, digits, `_` and `.` right after a number are part of a
+        // * malformed one, like `123abc` or `1.2.3`
+        let rest_start = self.iter.offset();
+        while (self.iter)
+            .next_if(|(_, ch)| ch.is_ascii_alphanumeric() || *ch == '_' || *ch == '.')
+            .is_some()
+        {}
+        let rest = self.iter.slice(rest_start, self.iter.offset());
+        let end_pos = self.iter.peek().map_or(start_pos, |(pos, _)| pos);
+        let var = match (res, rest.chars().next()) {
+            (Err(e), _) => TokenType::Error(e),
+            (Ok((var, _)), None) => var,
+            (Ok(_), Some('.')) => TokenType::Error(LexError::MisplacedPoint),
+            (Ok((_, radix)), Some(digit)) if digit.is_ascii_digit() => {
+                TokenType::Error(LexError::BadDigit(digit, radix))
+            }
+            (Ok(_), Some(_)) => TokenType::Error(LexError::BadSuffix(rest.into())),
+        };
+        Token {
+            var,
+            span: Span::from(start_pos, end_pos),
+        }
+    }
 
+    /// Lex the value of a number, and its base
+    fn lex_number_value(&mut self) -> LexResult<(TokenType<'src>, u32)> {
         // radix check.
-        let (radix, possibly_double) = if self.iter.peek().map_or(false, |ch_ind| ch_ind.1 == '0') {
+        // * `0b`, `0o` or `0x`, which must have digits after it
+        let mut prefix = None;
+        let (radix, possibly_double) = if self.iter.peek().is_some_and(|ch_ind| ch_ind.1 == '0') {
             // this digit is '0'. consume and advance
             self.iter.next();
             match self.iter.peek().map_or('_', |i| i.1) {
                 'b' => {
-                    self.iter.next();
+                    prefix = self.iter.next().map(|(_, ch)| ch);
                     (2, false)
                 }
-                '0' | 'o' => {
+                '0' => {
                     self.iter.next();
                     (8, false)
                 }
+                'o' => {
+                    prefix = self.iter.next().map(|(_, ch)| ch);
+                    (8, false)
+                }
                 'x' => {
-                    self.iter.next();
+                    prefix = self.iter.next().map(|(_, ch)| ch);
                     (16, false)
                 }
                 '1'..='9' => (10, true),
@@ -422,16 +529,19 @@ where
         while self
             .iter
             .peek()
-            .map_or(false, |ch_ind| (*ch_ind).1.is_digit(radix))
+            .is_some_and(|ch_ind| ch_ind.1.is_digit(radix))
         {
             number.push(self.iter.next().unwrap().1);
         }
+        if let Some(prefix) = prefix.filter(|_| number == "0") {
+            Err(LexError::NoDigits(format!("0{}", prefix)))?
+        }
 
         // original * 10 ^ exponent
-        let mut exponent = 0;
+        let mut exponent: i32 = 0;
 
         // Decimal part
-        if possibly_double && self.iter.peek().map_or(false, |x| x.1 == '.') {
+        if possibly_double && self.iter.peek().is_some_and(|x| x.1 == '.') {
             // Consume decimal point
             self.iter.next();
             is_float = true;
@@ -439,7 +549,7 @@ where
             while self
                 .iter
                 .peek()
-                .map_or(false, |ch_ind| (*ch_ind).1.is_digit(10))
+                .is_some_and(|ch_ind| ch_ind.1.is_ascii_digit())
             {
                 exponent -= 1;
                 number.push(self.iter.next().unwrap().1);
@@ -448,7 +558,7 @@ where
 
         // Exponent part
 
-        if possibly_double && self.iter.peek().map_or(false, |x| x.1 == 'e' || x.1 == 'E') {
+        if possibly_double && self.iter.peek().is_some_and(|x| x.1 == 'e' || x.1 == 'E') {
             // Consume exponent `e`
             self.iter.next();
             let mut exp = String::new();
@@ -462,55 +572,55 @@ where
             while self
                 .iter
                 .peek()
-                .map_or(false, |ch_ind| (*ch_ind).1.is_digit(10))
+                .is_some_and(|ch_ind| ch_ind.1.is_ascii_digit())
             {
                 exp.push(self.iter.next().unwrap().1);
             }
 
             let exp = match i32::from_str(&exp) {
                 Ok(i) => i,
-                Err(e) => Err(LexError::BadInteger)?,
+                Err(_) if !exp.ends_with(|ch: char| ch.is_ascii_digit()) => {
+                    Err(LexError::EmptyExponent)?
+                }
+                // * Too many digits for an `i32`
+                Err(_e) => Err(LexError::NumberOutOfRange)?,
             };
 
-            exponent += exp;
+            exponent = exponent
+                .checked_add(exp)
+                .ok_or(LexError::NumberOutOfRange)?;
+        }
+
+        // Anything this far out of `f64` range is a typo, and computing
+        // `10 ^ exponent` for it would take forever.
+        if exponent
+            .checked_abs()
+            .is_none_or(|e| e > MAX_FLOAT_EXPONENT)
+        {
+            Err(LexError::NumberOutOfRange)?
         }
 
-        let number = match ramp::Int::from_str_radix(&number, radix as u8) {
-            Ok(i) => i,
-            Err(e) => Err(LexError::BadInteger)?,
+        let number = match num::parse_int(&number, radix as u8) {
+            Some(i) => i,
+            None => Err(LexError::BadInteger)?,
         };
 
         if is_float {
             let (number, denominator) = if exponent >= 0 {
-                let exp = ramp::Int::from(10).pow(exponent as usize);
-                (number * exp, ramp::Int::one())
+                let exp = num::pow(10, exponent as usize);
+                (number * exp, num::Int::from(1))
             } else {
-                let exp = ramp::Int::from(10).pow((-exponent) as usize);
+                let exp = num::pow(10, (-exponent) as usize);
                 (number, exp)
             };
-
-            let end_pos = self.iter.peek().unwrap().0;
-
-            Ok(Token {
-                var: TokenType::Literal(Literal::Float(ramp::rational::Rational::new(
-                    number,
-                    denominator,
-                ))),
-                // src: &self.src[start..end],
-                span: Span::from(start_pos, end_pos),
-            })
+            let float = Literal::Float(num::rational(number, denominator));
+            Ok((TokenType::Literal(float), radix))
         } else {
-            let end_pos = self.iter.peek().unwrap().0;
-
-            Ok(Token {
-                var: TokenType::Literal(Literal::Integer(number)),
-                // src: &self.src[start..end],
-                span: Span::from(start_pos, end_pos),
-            })
+            Ok((TokenType::Literal(Literal::Integer(number)), radix))
         }
     }
 
-    fn lex_char_literal(&mut self) -> LexResult<Token> {
+    fn lex_char_literal(&mut self) -> LexResult<Token<'src>> {
         let (start, start_quote) = self.iter.next().expect("Should be valid");
         if start_quote != '\'' {
             panic!(
@@ -519,29 +629,28 @@ where
             );
         }
 
-        let ch = match self
-            .iter
-            .next()
-            .expect("A character literal should have a character")
-            .1
-        {
+        let ch = match self.iter.next().ok_or(LexError::UnexpectedEOF)?.1 {
             '\\' => Self::unescape_character(&mut self.iter)?,
-            ch @ _ => ch,
+            '\0' => Err(LexError::UnexpectedEOF)?,
+            ch => ch,
         };
 
-        let (end, end_quote) = self.iter.next().expect("Should be valid");
-        if end_quote != '\'' {
+        let (end, end_quote) = self.iter.next().ok_or(LexError::UnexpectedEOF)?;
+        if end_quote == '\0' {
+            return Err(LexError::UnexpectedEOF);
+        } else if end_quote != '\'' {
             return Err(LexError::UnexpectedCharacter(end_quote));
         }
 
         Ok(Token {
             var: TokenType::Literal(Literal::Char(ch)),
-            span: Span::from(start, end),
+            span: Span::from(start, end.inc()),
         })
     }
 
-    /// Lex a string literal.
-    fn lex_string_literal(&mut self) -> LexResult<Token> {
+    /// Lex a string literal. It is borrowed from the source until an escape
+    /// is read, and copied from there on.
+    fn lex_string_literal(&mut self) -> LexResult<Token<'src>> {
         let (start, start_quotation_mark) = self.iter.next().expect("This value should be valid");
 
         if start_quotation_mark != '"' {
@@ -549,41 +658,56 @@ where
         }
 
         let end: Pos;
-        let mut tgt_string = String::new();
+        let text_start = self.iter.offset();
+        let mut text = Cow::Borrowed("");
 
         loop {
+            let text_end = self.iter.offset();
             let (this_index, this_char) = self.iter.next().ok_or(LexError::UnexpectedEOF)?;
             match this_char {
-                '\\' => tgt_string.push(Self::unescape_character(&mut self.iter)?),
+                '\\' => {
+                    let ch = Self::unescape_character(&mut self.iter)?;
+                    if let Cow::Borrowed(_) = text {
+                        text = Cow::Owned(self.iter.slice(text_start, text_end).into());
+                    }
+                    text.to_mut().push(ch);
+                }
 
                 '"' => {
-                    end = this_index.map_inc(-1, 0, -1);
+                    if let Cow::Borrowed(_) = text {
+                        text = Cow::Borrowed(self.iter.slice(text_start, text_end));
+                    }
+                    end = this_index.inc();
                     break;
                 }
 
                 '\n' | '\r' => Err(LexError::UnexpectedEOL)?,
 
-                _ => tgt_string.push(this_char),
+                _ => {
+                    if let Cow::Owned(text) = &mut text {
+                        text.push(this_char);
+                    }
+                }
             }
         }
 
         Ok(Token {
-            var: TokenType::Literal(Literal::String(tgt_string)),
+            var: TokenType::Literal(Literal::String(text)),
             span: Span::from(start, end),
         })
     }
 
     /// Lex an identifier.
-    fn lex_identifier(&mut self) -> LexResult<Token> {
+    fn lex_identifier(&mut self) -> LexResult<Token<'src>> {
         let start = self.iter.peek().expect("This value should be valid").0;
-        let mut ident = String::new();
-        while self.iter.peek().map_or(false, |ch_ind| {
-            ch_ind.1.is_alphanumeric() || ch_ind.1 == '_'
-        }) {
-            ident.push(self.iter.next().unwrap().1);
-        }
+        let ident_start = self.iter.offset();
+        while (self.iter)
+            .next_if(|ch_ind| ch_ind.1.is_alphanumeric() || ch_ind.1 == '_')
+            .is_some()
+        {}
         let end = self.iter.peek().unwrap().0;
-        let variation = match &ident[..] {
+        let ident = self.iter.slice(ident_start, self.iter.offset());
+        let variation = match ident {
             "if" => TokenType::If,
             "else" => TokenType::Else,
             "while" => TokenType::While,
@@ -591,6 +715,7 @@ where
             "continue" => TokenType::Continue,
             "return" => TokenType::Return,
             "const" => TokenType::Const,
+            "auto" => TokenType::Auto,
             "print" => TokenType::Print,
             "scan" => TokenType::Scan,
             "as" => TokenType::As,
@@ -598,10 +723,10 @@ where
             "false" => TokenType::Literal(Literal::Boolean(false)),
 
             "struct" | "switch" | "case" | "default" | "for" | "do" => {
-                Err(LexError::ReservedWord(ident))?
+                Err(LexError::ReservedWord(ident.into()))?
             }
 
-            _ => TokenType::Identifier(ident),
+            _ => TokenType::Identifier(Symbol::intern(ident)),
         };
 
         Ok(Token {
@@ -611,21 +736,14 @@ where
     }
 
     /// Lex an operator.
-    fn lex_operator(&mut self) -> LexResult<Token> {
+    fn lex_operator(&mut self) -> LexResult<Token<'src>> {
         let (start, first_char) = self.iter.next().expect("This value should be valid");
         let mut end = start.inc();
-        let second_char: Option<char> =
-            OperatorCombination
-                .get(&first_char)
-                .map_or(None, |vec: &Box<Vec<char>>| {
-                    self.iter.peek().map_or(None, |&(_, ch)| {
-                        if vec[..].contains(&ch) {
-                            Some(ch)
-                        } else {
-                            None
-                        }
-                    })
-                });
+        let second_char: Option<char> = self
+            .iter
+            .peek()
+            .map(|(_, ch)| ch)
+            .filter(|ch| operator_combination(first_char).contains(ch));
         if second_char.is_some() {
             self.iter.next();
             end = end.inc();
@@ -645,8 +763,16 @@ where
             '*' => TokenType::Multiply,
             '/' => match second_char {
                 None => TokenType::Divide,
-                Some('*') => self.lex_comments(true)?,
-                Some('/') => self.lex_comments(false)?,
+                Some('*') => self.lex_comments(true, &mut end)?,
+                Some('/') => match self.lex_comments(false, &mut end)? {
+                    // * `///`, but not `////` or longer
+                    TokenType::Comment(Cow::Borrowed(text))
+                        if text.starts_with('/') && !text.starts_with("//") =>
+                    {
+                        TokenType::DocComment(Cow::Borrowed(&text[1..]))
+                    }
+                    comment => comment,
+                },
                 _ => unreachable!(),
             },
             '=' => match second_char {
@@ -687,6 +813,7 @@ where
             '{' => TokenType::LCurlyBrace,
             '}' => TokenType::RCurlyBrace,
             ',' => TokenType::Comma,
+            ':' => TokenType::Colon,
             '.' => TokenType::Dot,
             ';' => TokenType::Semicolon,
             _ => panic!("Unexpected character \'{}\' at {}", first_char, start),
@@ -698,41 +825,53 @@ where
         })
     }
 
-    fn lex_comments(&mut self, multiline: bool) -> LexResult<TokenType> {
-        let mut comment_data = String::new();
+    /// Lex the rest of a comment after its opening `/*` or `//`, moving `end`
+    /// past its last character. Line comments end before their line break.
+    fn lex_comments(&mut self, multiline: bool, end: &mut Pos) -> LexResult<TokenType<'src>> {
+        let text_start = self.iter.offset();
+        let mut text_end = text_start;
         if multiline {
             loop {
                 let c = self.iter.next();
                 match c {
-                    Some((_, '*')) => match self.iter.peek() {
-                        Some((_, '/')) => {
+                    Some((_, '*')) => {
+                        if let Some((pos, '/')) = self.iter.peek() {
+                            text_end = self.iter.offset() - 1;
                             self.iter.next();
+                            *end = pos.inc();
                             break;
                         }
-                        _ => comment_data.push('*'),
-                    },
+                    }
                     None => Err(LexError::UnexpectedEOF)?,
-                    Some((_, c)) => comment_data.push(c),
+                    Some(_) => (),
                 }
             }
         } else {
             loop {
                 let c = self.iter.next();
                 match c {
-                    Some((_, '\r')) | Some((_, '\n')) | Some((_, '\0')) => {
-                        self.iter.next();
+                    Some((_, '\r')) => {
+                        if let Some((_, '\n')) = self.iter.peek() {
+                            self.iter.next();
+                        }
                         break;
                     }
+                    Some((_, '\n')) | Some((_, '\0')) => break,
                     None => break,
-                    Some((_, c)) => comment_data.push(c),
+                    Some((pos, _)) => {
+                        text_end = self.iter.offset();
+                        *end = pos.inc();
+                    }
                 }
             }
         }
-        Ok(TokenType::Comment(comment_data))
+        Ok(TokenType::Comment(Cow::Borrowed(
+            self.iter.slice(text_start, text_end),
+        )))
     }
 
     /// Skip spaces and stop before the next non-space character.
-    fn skip_spaces(iter: &mut Peekable<StringPosIter<T>>) {
+    fn skip_spaces(iter: &mut StringPosIter<'src>) {
         while match iter.peek() {
             None => false,
             Some((_, '\0')) => false,
@@ -769,7 +908,7 @@ where
     /// | `\xNN`   | Character of value `0xNN` |
     /// | `\uNNNN` | Unicode character of value `0xNNNN` |
     /// | `\u{NN...N} | Unicode character of value `0xNN...N` |
-    fn unescape_character(iter: &mut Peekable<StringPosIter<T>>) -> LexResult<char> {
+    fn unescape_character(iter: &mut StringPosIter<'src>) -> LexResult<char> {
         // TODO: Return a result so we can continue to parse
         Ok(match iter.next().ok_or(LexError::BadEscaping)?.1 {
             'n' => '\n',
@@ -800,7 +939,7 @@ where
                 _ => Err(LexError::BadEscaping)?,
             },
 
-            ch @ _ => Err(LexError::BadEscaping)?,
+            _ch => Err(LexError::BadEscaping)?,
         })
     }
 }
@@ -0,0 +1,156 @@
+//! Lint rules a project turns on for itself, like the policies of a course
+//! assignment, checked over [`Query`](crate::Query)s of the syntax tree.
+
+use super::ast::*;
+use super::callgraph::CallGraph;
+use super::query::{Match, Query, QueryError};
+use crate::prelude::*;
+use serde::Deserialize;
+
+/// Which rules to check and how, from the `[lint]` table of `chigusa.toml`:
+///
+/// ```toml
+/// [lint]
+/// max_fn_lines = 40       # lines of a function, from its name to `}`
+/// max_params = 4
+/// forbid_recursion = true
+/// forbid_globals = true   # constants are still allowed
+/// require_doc = true      # a `///` comment on every function
+/// deny = false            # report as errors instead of warnings
+///
+/// [[lint.forbid]]
+/// query = "While"
+/// message = "this assignment is about recursion"
+/// ```
+///
+/// Every rule is off unless given.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LintRules {
+    pub max_fn_lines: Option<usize>,
+    pub max_params: Option<usize>,
+    pub forbid_recursion: bool,
+    pub forbid_globals: bool,
+    pub require_doc: bool,
+    /// Constructs found by a query, which must not be in the program
+    pub forbid: Vec<Forbidden>,
+    pub deny: bool,
+}
+
+/// A construct a program must not have, found by [`Query`] `query`
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Forbidden {
+    pub query: String,
+    /// Why it is forbidden, shown with each place it is found
+    pub message: Option<String>,
+}
+
+/// A rule of [`LintRules`]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum LintRule {
+    MaxFnLines,
+    MaxParams,
+    Recursion,
+    Globals,
+    Doc,
+    Forbidden,
+}
+
+impl LintRule {
+    /// Number of the error code of diagnostics about the rule
+    pub fn code(self) -> u16 {
+        match self {
+            LintRule::MaxFnLines => 502,
+            LintRule::MaxParams => 503,
+            LintRule::Recursion => 504,
+            LintRule::Globals => 505,
+            LintRule::Doc => 506,
+            LintRule::Forbidden => 507,
+        }
+    }
+}
+
+/// A place where a program breaks a rule
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Violation {
+    pub rule: LintRule,
+    pub span: Span,
+    pub message: String,
+}
+
+impl LintRules {
+    /// Every place `prog` breaks the rules, in source order. Fails if a
+    /// forbidden construct is not a valid query.
+    pub fn check(&self, prog: &Program) -> Result<Vec<Violation>, QueryError> {
+        let mut found = vec![];
+        let mut add = |rule, m: &Match, message| {
+            found.push(Violation {
+                rule,
+                span: m.span,
+                message,
+            })
+        };
+
+        let fns = find("Function[extern=false]", prog);
+        for f in &fns {
+            let name = f.attr("name").unwrap_or_default();
+            let lines = f.span.end.ln - f.span.start.ln + 1;
+            if let Some(max) = self.max_fn_lines.filter(|max| lines > *max) {
+                let message = format!("`{}` is {} lines long, more than {}", name, lines, max);
+                add(LintRule::MaxFnLines, f, message);
+            }
+            let params: usize = f.attr("params").and_then(|p| p.parse().ok()).unwrap_or(0);
+            if let Some(max) = self.max_params.filter(|max| params > *max) {
+                let message = format!("`{}` takes {} parameters, more than {}", name, params, max);
+                add(LintRule::MaxParams, f, message);
+            }
+            if self.require_doc && f.attr("doc").is_none() {
+                let message = format!("`{}` has no `///` comment", name);
+                add(LintRule::Doc, f, message);
+            }
+        }
+
+        if self.forbid_recursion {
+            let graph = CallGraph::new(prog);
+            let cycles = graph.cycles();
+            for call in find("FunctionCall", prog) {
+                let from = call.function.as_deref().and_then(|f| graph.find(f));
+                let to = call.attr("name").and_then(|f| graph.find(f));
+                if let (Some(from), Some(to)) = (from, to) {
+                    if graph.is_recursive_call(&cycles, from, to) {
+                        let message = format!("Call to `{}` is recursive", graph.fns[to].name);
+                        add(LintRule::Recursion, &call, message);
+                    }
+                }
+            }
+        }
+
+        if self.forbid_globals {
+            for decl in find("Decl[const=false]", prog) {
+                if decl.function.is_none() {
+                    let name = decl.attr("name").unwrap_or_default();
+                    let message = format!("`{}` is a global variable", name);
+                    add(LintRule::Globals, &decl, message);
+                }
+            }
+        }
+
+        for rule in &self.forbid {
+            for m in Query::parse(&rule.query)?.find_all(prog) {
+                let message = match &rule.message {
+                    Some(why) => format!("{} is forbidden: {}", m.kind, why),
+                    None => format!("{} is forbidden", m.kind),
+                };
+                add(LintRule::Forbidden, &m, message);
+            }
+        }
+
+        found.sort_by_key(|v| v.span.start.index);
+        Ok(found)
+    }
+}
+
+fn find(query: &str, prog: &Program) -> Vec<Match> {
+    (Query::parse(query).expect("Built-in queries are valid")).find_all(prog)
+}
@@ -0,0 +1,235 @@
+//! `chigusa metrics`: how complex each function of a program is.
+
+use super::ast::*;
+use crate::prelude::*;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fmt::{self, Display, Formatter};
+
+/// Metrics of the functions of a program with a body, in the order they are
+/// declared
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Metrics {
+    pub functions: Vec<FnMetrics>,
+}
+
+/// Metrics of one function. Operators and operands are counted as Halstead
+/// does: operators are the operations, calls and statement keywords, and
+/// operands the names and literals they work on.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FnMetrics {
+    pub name: String,
+    /// Line of the declaration, counted from 1
+    pub line: usize,
+    /// Statements, not counting blocks. Each variable declared counts as one.
+    pub statements: usize,
+    /// Paths through the function: 1, plus 1 for each `if`, `else if`,
+    /// `while`, `&&` and `||`
+    pub cyclomatic: usize,
+    /// Most `if`s and `while`s around each other
+    pub max_nesting: usize,
+    pub operators: usize,
+    pub operands: usize,
+    pub distinct_operators: usize,
+    pub distinct_operands: usize,
+    /// Halstead volume, `(operators + operands) * log2(distinct operators +
+    /// distinct operands)`
+    pub volume: f64,
+}
+
+impl Metrics {
+    pub fn new(prog: &Program) -> Metrics {
+        let scope = prog.blk.scope.borrow();
+        let mut functions = vec![];
+        for (name, def) in scope.defs.iter() {
+            let def = def.borrow();
+            let (typ, decl_span) = match &*def {
+                SymbolDef::Var { typ, decl_span, .. } => (typ.borrow(), *decl_span),
+                SymbolDef::Typ { .. } => continue,
+            };
+            let body = match &*typ {
+                TypeDef::Function(FunctionType {
+                    body: Some(body), ..
+                }) => body,
+                _ => continue,
+            };
+            let mut counter = Counter::default();
+            counter.block(body, 0);
+            functions.push(counter.finish(name.to_string(), decl_span.start.ln + 1));
+        }
+        Metrics { functions }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Metrics are always valid JSON")
+    }
+}
+
+#[derive(Default)]
+struct Counter {
+    statements: usize,
+    branches: usize,
+    max_nesting: usize,
+    operators: Vec<String>,
+    operands: Vec<String>,
+}
+
+impl Counter {
+    fn finish(self, name: String, line: usize) -> FnMetrics {
+        let distinct_operators = self.operators.iter().collect::<BTreeSet<_>>().len();
+        let distinct_operands = self.operands.iter().collect::<BTreeSet<_>>().len();
+        let length = self.operators.len() + self.operands.len();
+        let vocabulary = distinct_operators + distinct_operands;
+        let volume = match vocabulary {
+            0 => 0.0,
+            _ => length as f64 * (vocabulary as f64).log2(),
+        };
+        FnMetrics {
+            name,
+            line,
+            statements: self.statements,
+            cyclomatic: self.branches + 1,
+            max_nesting: self.max_nesting,
+            operators: self.operators.len(),
+            operands: self.operands.len(),
+            distinct_operators,
+            distinct_operands,
+            volume,
+        }
+    }
+
+    fn operator(&mut self, op: impl Into<String>) {
+        self.operators.push(op.into());
+    }
+
+    fn block(&mut self, blk: &Block, depth: usize) {
+        for stmt in &blk.stmts {
+            self.stmt(stmt, &blk.scope, depth);
+        }
+    }
+
+    /// Count `stmt`, which is in `scope` inside `depth` `if`s and `while`s
+    fn stmt(&mut self, stmt: &Stmt, scope: &Ptr<Scope>, depth: usize) {
+        maybe_grow(|| match &stmt.var {
+            StmtVariant::If(i) => {
+                self.statements += 1;
+                self.branches += 1 + i.else_ifs.len();
+                self.max_nesting = self.max_nesting.max(depth + 1);
+                self.operator("if");
+                self.expr(&i.cond);
+                self.stmt(&i.if_block.borrow(), scope, depth + 1);
+                for (cond, body) in &i.else_ifs {
+                    self.operator("else if");
+                    self.expr(cond);
+                    self.stmt(&body.borrow(), scope, depth + 1);
+                }
+                if let Some(body) = &i.else_block {
+                    self.operator("else");
+                    self.stmt(&body.borrow(), scope, depth + 1);
+                }
+            }
+            StmtVariant::While(w) => {
+                self.statements += 1;
+                self.branches += 1;
+                self.max_nesting = self.max_nesting.max(depth + 1);
+                self.operator("while");
+                self.expr(&w.cond);
+                self.stmt(&w.block.borrow(), scope, depth + 1);
+            }
+            StmtVariant::Block(b) => self.block(b, depth),
+            StmtVariant::Expr(e) => {
+                self.statements += 1;
+                self.expr(e);
+            }
+            StmtVariant::Print(es) => {
+                self.statements += 1;
+                self.operator("print");
+                es.iter().for_each(|e| self.expr(e));
+            }
+            StmtVariant::ManyExpr(es) => {
+                self.statements += scope.borrow().defs_in(stmt.span).len().max(1);
+                es.iter().for_each(|e| self.expr(e));
+            }
+            StmtVariant::Scan(i, _) => {
+                self.statements += 1;
+                self.operator("scan");
+                self.operands.push(i.name.to_string());
+            }
+            StmtVariant::Return(e) => {
+                self.statements += 1;
+                self.operator("return");
+                e.iter().for_each(|e| self.expr(e));
+            }
+            StmtVariant::Break(label) => {
+                self.statements += 1;
+                self.operator("break");
+                if let Some(label) = label {
+                    self.operands.push(label.name.to_string());
+                }
+            }
+            StmtVariant::Empty => (),
+        })
+    }
+
+    fn expr(&mut self, expr: &Ptr<Expr>) {
+        maybe_grow(|| match &expr.borrow().var {
+            ExprVariant::Ident(i) => self.operands.push(i.name.to_string()),
+            ExprVariant::Literal(lit) => self.operands.push(lit.to_string()),
+            ExprVariant::TypeConversion(t) => {
+                self.operator(format!("({})", super::pretty::type_str(&t.to.borrow())));
+                self.expr(&t.expr);
+            }
+            ExprVariant::UnaryOp(u) => {
+                self.operator(format!("{:?}", u.op));
+                self.expr(&u.val);
+            }
+            ExprVariant::BinaryOp(b) => {
+                if matches!(b.op, OpVar::And | OpVar::Or) {
+                    self.branches += 1;
+                }
+                self.operator(format!("{:?}", b.op));
+                self.expr(&b.lhs);
+                self.expr(&b.rhs);
+            }
+            ExprVariant::FunctionCall(f) => {
+                self.operator(format!("{}()", f.func));
+                f.params.iter().for_each(|e| self.expr(e));
+            }
+            ExprVariant::StructChild(s) => {
+                self.operator(".");
+                self.operands.push(format!(".{}", s.idx));
+                self.expr(&s.val);
+            }
+            ExprVariant::ArrayChild(a) => {
+                self.operator("[]");
+                self.expr(&a.val);
+                self.expr(&a.idx);
+            }
+        })
+    }
+}
+
+impl Display for Metrics {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<20} {:>6} {:>6} {:>10} {:>7} {:>9} {:>8} {:>8}",
+            "function", "line", "stmts", "cyclomatic", "nesting", "operators", "operands", "volume"
+        )?;
+        for m in &self.functions {
+            writeln!(
+                f,
+                "{:<20} {:>6} {:>6} {:>10} {:>7} {:>9} {:>8} {:>8.1}",
+                m.name,
+                m.line,
+                m.statements,
+                m.cyclomatic,
+                m.max_nesting,
+                format!("{}/{}", m.distinct_operators, m.operators),
+                format!("{}/{}", m.distinct_operands, m.operands),
+                m.volume
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -1,10 +1,105 @@
 /// Tokenizer and lexer for C0 Language
 pub mod lexer;
 
+/// Conditional compilation by `#if` directives, before lexing
+pub mod preprocess;
+
 /// Parser
 pub mod parser;
+#[cfg(feature = "std")]
+pub use parser::{parse_no_panic, parse_no_panic_with};
 
 /// Abstract Syntax Tree Components
 pub mod ast;
 
+/// Interned identifiers
+pub mod symbol;
+
+/// Arbitrary-precision numbers for literals
+pub mod num;
+
+/// Pretty printer turning an AST back into source code
+#[cfg(feature = "std")]
+pub mod pretty;
+
+/// Reference interpreter running programs straight from the AST
+#[cfg(feature = "std")]
+pub mod interpreter;
+
+/// Shrinking programs that trigger compiler bugs
+#[cfg(feature = "std")]
+pub mod reduce;
+
+/// Structural differences between two programs
+#[cfg(feature = "std")]
+pub mod ast_diff;
+
+/// Fingerprints for finding programs copied from each other
+#[cfg(feature = "std")]
+pub mod fingerprint;
+
+/// Random valid programs, for fuzzing and benchmarks
+#[cfg(feature = "std")]
+pub mod gen;
+
+/// Renaming symbols and dropping comments, to hide how programs work
+#[cfg(feature = "std")]
+pub mod obfuscate;
+
+/// Putting small bugs into programs, for mutation testing
+#[cfg(feature = "std")]
+pub mod mutate;
+
+/// Sanity checks on tokens and trees
+#[cfg(feature = "std")]
+pub mod validate;
+
+/// The typed tree, where every expression knows its type
+#[cfg(feature = "std")]
+pub mod hir;
+
+/// Type checking, building the typed tree
+#[cfg(feature = "std")]
+pub mod type_checker;
+
+/// Which functions call which
+#[cfg(feature = "std")]
+pub mod callgraph;
+
+/// Which variables references may refer to
+#[cfg(feature = "std")]
+pub mod alias;
+
+/// Which local variables have their address outlive their call
+#[cfg(feature = "std")]
+pub mod escape;
+
+/// Which functions are pure, only computing their result
+#[cfg(feature = "std")]
+pub mod purity;
+
+/// Symbol lookups by position for editor tooling
+#[cfg(feature = "std")]
+pub mod ide;
+
+/// API listings from doc comments
+#[cfg(feature = "std")]
+pub mod doc;
+
+/// Finding nodes of the syntax tree with selectors
+#[cfg(feature = "std")]
+pub mod query;
+
+/// Lint rules configured by a project
+#[cfg(feature = "std")]
+pub mod lint;
+
+/// Complexity of each function
+#[cfg(feature = "std")]
+pub mod metrics;
+
+/// Token classification for syntax highlighting
+pub mod highlight;
+pub use highlight::highlight;
+
 pub mod err;
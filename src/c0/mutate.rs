@@ -0,0 +1,290 @@
+//! Mutation testing: small, deliberate bugs put into a program, to see
+//! whether its tests catch them.
+//!
+//! [`mutations`](crate::c0::mutate::mutations) lists every place a program
+//! can be mutated, in source order:
+//!
+//! - Arithmetic and comparison operators are swapped for a close one: `+`
+//!   and `-`, `*` and `/`, `<` and `<=`, `>` and `>=`, `==` and `!=`
+//! - Integer literals are moved up or down by one
+//! - Conditions of `if` and `while` are negated
+//!
+//! [`mutant`](crate::c0::mutate::mutant) applies one of them and prints the
+//! program back with the pretty printer, so comments are lost.
+//! [`sample`](crate::c0::mutate::sample) picks mutants at random from a
+//! seed, and picks the same ones whenever it is given the same seed and
+//! program. A mutant may not compile, e.g. when a literal moves out of the
+//! range of its type.
+
+use super::ast::*;
+use super::err::ParseResult;
+use super::num;
+use super::parser::parse_no_panic;
+use super::pretty::{op_str, pretty_print};
+use crate::prelude::*;
+use core::fmt;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum MutationKind {
+    /// Replace the operator of a binary expression
+    SwapOperator { from: OpVar, to: OpVar },
+    /// Replace an integer literal with one that is one more or less
+    OffByOne { from: num::Int, to: num::Int },
+    /// Turn `a < b` into `a >= b`, and so on, or `cond` into `cond == 0`
+    NegateCondition,
+}
+
+/// One bug that can be put into a program
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Mutation {
+    /// Index of the mutation in the list of all of them for the program
+    pub id: usize,
+    pub kind: MutationKind,
+    /// Span of the expression changed
+    pub span: Span,
+}
+
+impl fmt::Display for Mutation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            MutationKind::SwapOperator { from, to } => {
+                write!(f, "replace `{}` with `{}`", op_str(*from), op_str(*to))
+            }
+            MutationKind::OffByOne { from, to } => write!(f, "replace {} with {}", from, to),
+            MutationKind::NegateCondition => write!(f, "negate the condition"),
+        }
+    }
+}
+
+/// A program with one mutation applied
+#[derive(Debug)]
+pub struct Mutant {
+    pub mutation: Mutation,
+    /// The mutated program, pretty printed
+    pub src: String,
+    /// `src` parsed, ready to be compiled
+    pub prog: Program,
+}
+
+/// Every mutation of `src`, in source order
+pub fn mutations(src: &str) -> ParseResult<Vec<Mutation>> {
+    let prog = parse_no_panic(src)?;
+    let sites = Collector::default().program(&prog);
+    let mutations = (sites.into_iter().enumerate())
+        .map(|(id, (kind, expr))| Mutation {
+            id,
+            kind,
+            span: expr.borrow().span,
+        })
+        .collect();
+    Ok(mutations)
+}
+
+/// `src` with `mutation` applied.
+///
+/// # Panics
+///
+/// If `mutation` is not one of the [`mutations`] of `src`.
+pub fn mutant(src: &str, mutation: &Mutation) -> ParseResult<Mutant> {
+    let prog = parse_no_panic(src)?;
+    let sites = Collector::default().program(&prog);
+    let (kind, expr) = match sites.get(mutation.id) {
+        Some(site) if site.0 == mutation.kind => site,
+        _ => panic!("{:?} is not a mutation of this program", mutation),
+    };
+    apply(kind, &mut expr.borrow_mut());
+
+    let src = pretty_print(&prog);
+    let prog = parse_no_panic(&src).expect("Mutated program does not parse");
+    Ok(Mutant {
+        mutation: mutation.clone(),
+        src,
+        prog,
+    })
+}
+
+/// Up to `count` different mutants of `src`, picked at random from `seed`,
+/// in source order
+pub fn sample(src: &str, seed: u64, count: usize) -> ParseResult<Vec<Mutant>> {
+    let mut all = mutations(src)?;
+    let mut rng = SplitMix64::new(seed);
+    let count = count.min(all.len());
+    // * A partial Fisher-Yates shuffle, leaving the picks in front
+    for i in 0..count {
+        let j = i + rng.below(all.len() - i);
+        all.swap(i, j);
+    }
+    all.truncate(count);
+    all.sort_by_key(|m| m.id);
+    all.iter().map(|m| mutant(src, m)).collect()
+}
+
+fn apply(kind: &MutationKind, expr: &mut Expr) {
+    match (kind, &mut expr.var) {
+        (MutationKind::SwapOperator { to, .. }, ExprVariant::BinaryOp(b)) => b.op = *to,
+        (MutationKind::OffByOne { to, .. }, ExprVariant::Literal(Literal::Integer { val })) => {
+            *val = to.clone()
+        }
+        (MutationKind::NegateCondition, ExprVariant::BinaryOp(b)) if negated(b.op).is_some() => {
+            b.op = negated(b.op).unwrap()
+        }
+        (MutationKind::NegateCondition, _) => {
+            // * `cond` becomes `(cond) == 0`
+            let zero = ExprVariant::Literal(Literal::Integer {
+                val: num::Int::from(0),
+            });
+            let cond = Expr {
+                var: core::mem::replace(&mut expr.var, zero.clone()),
+                span: expr.span,
+            };
+            expr.var = ExprVariant::BinaryOp(BinaryOp {
+                op: OpVar::Eq,
+                lhs: Ptr::new(cond),
+                rhs: Ptr::new(Expr {
+                    var: zero,
+                    span: expr.span,
+                }),
+                op_span: expr.span,
+            });
+        }
+        (kind, var) => unreachable!("Cannot apply {:?} to {:?}", kind, var),
+    }
+}
+
+/// The operator a mutation swaps `op` for
+fn swapped(op: OpVar) -> Option<OpVar> {
+    use OpVar::*;
+    Some(match op {
+        Add => Sub,
+        Sub => Add,
+        Mul => Div,
+        Div => Mul,
+        Lt => Lte,
+        Lte => Lt,
+        Gt => Gte,
+        Gte => Gt,
+        Eq => Neq,
+        Neq => Eq,
+        _ => return None,
+    })
+}
+
+/// The comparison true exactly when `op` is false
+fn negated(op: OpVar) -> Option<OpVar> {
+    use OpVar::*;
+    Some(match op {
+        Lt => Gte,
+        Gte => Lt,
+        Gt => Lte,
+        Lte => Gt,
+        Eq => Neq,
+        Neq => Eq,
+        _ => return None,
+    })
+}
+
+/// Finds the expressions to mutate, and how, in source order
+#[derive(Default)]
+struct Collector {
+    sites: Vec<(MutationKind, Ptr<Expr>)>,
+}
+
+impl Collector {
+    fn program(mut self, prog: &Program) -> Vec<(MutationKind, Ptr<Expr>)> {
+        self.block(&prog.blk);
+        self.sites
+    }
+
+    fn block(&mut self, blk: &Block) {
+        for stmt in &blk.stmts {
+            self.stmt(stmt, &blk.scope);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt, scope: &Ptr<Scope>) {
+        maybe_grow(|| match &stmt.var {
+            StmtVariant::If(i) => {
+                self.cond(&i.cond);
+                self.stmt(&i.if_block.borrow(), scope);
+                for (cond, body) in &i.else_ifs {
+                    self.cond(cond);
+                    self.stmt(&body.borrow(), scope);
+                }
+                if let Some(body) = &i.else_block {
+                    self.stmt(&body.borrow(), scope);
+                }
+            }
+            StmtVariant::While(w) => {
+                self.cond(&w.cond);
+                self.stmt(&w.block.borrow(), scope);
+            }
+            StmtVariant::Block(b) => self.block(b),
+            StmtVariant::Expr(e) | StmtVariant::Return(Some(e)) => self.expr(e),
+            StmtVariant::Print(es) | StmtVariant::ManyExpr(es) => {
+                es.iter().for_each(|e| self.expr(e))
+            }
+            StmtVariant::Empty => {
+                // * Function declarations; their bodies are kept in the scope
+                for (_, def) in scope.borrow().defs_in(stmt.span) {
+                    if let SymbolDef::Var { typ, .. } = &*def.borrow() {
+                        if let TypeDef::Function(FunctionType {
+                            body: Some(body), ..
+                        }) = &*typ.borrow()
+                        {
+                            self.block(body);
+                        }
+                    }
+                }
+            }
+            StmtVariant::Scan(..) | StmtVariant::Return(None) | StmtVariant::Break(_) => (),
+        })
+    }
+
+    fn cond(&mut self, cond: &Ptr<Expr>) {
+        // * Negating `==` or `!=` is the same as swapping it
+        let is_equality = matches!(
+            &cond.borrow().var,
+            ExprVariant::BinaryOp(b) if b.op == OpVar::Eq || b.op == OpVar::Neq
+        );
+        if !is_equality {
+            self.sites.push((MutationKind::NegateCondition, cond.cp()));
+        }
+        self.expr(cond);
+    }
+
+    fn expr(&mut self, expr: &Ptr<Expr>) {
+        maybe_grow(|| match &expr.borrow().var {
+            ExprVariant::Ident(_) => (),
+            ExprVariant::Literal(Literal::Integer { val }) => {
+                for to in [
+                    val.clone() - num::Int::from(1),
+                    val.clone() + num::Int::from(1),
+                ] {
+                    let from = val.clone();
+                    self.sites
+                        .push((MutationKind::OffByOne { from, to }, expr.cp()));
+                }
+            }
+            ExprVariant::Literal(_) => (),
+            ExprVariant::TypeConversion(t) => self.expr(&t.expr),
+            ExprVariant::UnaryOp(u) => self.expr(&u.val),
+            ExprVariant::BinaryOp(b) => {
+                // * Only the value of an initialization or assignment
+                if b.op != OpVar::_Asn && b.op != OpVar::_Csn {
+                    self.expr(&b.lhs);
+                }
+                if let Some(to) = swapped(b.op) {
+                    let kind = MutationKind::SwapOperator { from: b.op, to };
+                    self.sites.push((kind, expr.cp()));
+                }
+                self.expr(&b.rhs);
+            }
+            ExprVariant::FunctionCall(f) => f.params.iter().for_each(|p| self.expr(p)),
+            ExprVariant::StructChild(s) => self.expr(&s.val),
+            ExprVariant::ArrayChild(a) => {
+                self.expr(&a.val);
+                self.expr(&a.idx);
+            }
+        })
+    }
+}
@@ -0,0 +1,156 @@
+//! Arbitrary-precision numbers backing integer and float literals.
+//!
+//! These come from `num-bigint`, or from `ramp` with the `ramp` feature.
+//! `ramp` is faster but needs nightly Rust and does not build for every
+//! target, WASM included. Everything outside this module only goes through
+//! the functions here, so both backends give the same results.
+
+use alloc::{format, string::ToString};
+
+#[cfg(feature = "ramp")]
+mod imp {
+    use core::convert::TryInto;
+    pub use ramp::rational::Rational;
+    pub use ramp::Int;
+
+    pub fn parse_int(digits: &str, radix: u8) -> Option<Int> {
+        Int::from_str_radix(digits, radix).ok()
+    }
+
+    pub fn pow(base: i32, exp: usize) -> Int {
+        Int::from(base).pow(exp)
+    }
+
+    pub fn rational(numer: Int, denom: Int) -> Rational {
+        Rational::new(numer, denom)
+    }
+
+    pub fn into_parts(val: &Rational) -> (Int, Int) {
+        val.clone().into_parts()
+    }
+
+    pub fn to_i32(val: &Int) -> Option<i32> {
+        val.try_into().ok()
+    }
+
+    pub fn to_i64(val: &Int) -> Option<i64> {
+        val.try_into().ok()
+    }
+
+    pub fn to_f64(val: &Rational) -> f64 {
+        val.to_f64()
+    }
+}
+
+#[cfg(not(feature = "ramp"))]
+mod imp {
+    pub use num_bigint::BigInt as Int;
+    pub use num_rational::BigRational as Rational;
+    use num_traits::{Num, ToPrimitive};
+
+    pub fn parse_int(digits: &str, radix: u8) -> Option<Int> {
+        Int::from_str_radix(digits, radix as u32).ok()
+    }
+
+    pub fn pow(base: i32, exp: usize) -> Int {
+        num_traits::pow(Int::from(base), exp)
+    }
+
+    pub fn rational(numer: Int, denom: Int) -> Rational {
+        Rational::new(numer, denom)
+    }
+
+    pub fn into_parts(val: &Rational) -> (Int, Int) {
+        (val.numer().clone(), val.denom().clone())
+    }
+
+    pub fn to_i32(val: &Int) -> Option<i32> {
+        val.to_i32()
+    }
+
+    pub fn to_i64(val: &Int) -> Option<i64> {
+        val.to_i64()
+    }
+
+    /// Same as `ramp`: divide the parts after converting each of them
+    pub fn to_f64(val: &Rational) -> f64 {
+        let part = |i: &Int| i.to_f64().unwrap_or(f64::NAN);
+        part(val.numer()) / part(val.denom())
+    }
+}
+
+/// An integer of any size
+pub use imp::Int;
+/// An exact fraction of two [`Int`]s
+pub use imp::Rational;
+
+/// Parse `digits` in base `radix`, without sign or prefix
+pub fn parse_int(digits: &str, radix: u8) -> Option<Int> {
+    imp::parse_int(digits, radix)
+}
+
+/// `base` to the power of `exp`
+pub fn pow(base: i32, exp: usize) -> Int {
+    imp::pow(base, exp)
+}
+
+/// `numer / denom`, reduced
+pub fn rational(numer: Int, denom: Int) -> Rational {
+    imp::rational(numer, denom)
+}
+
+/// Numerator and denominator of `val`
+pub fn into_parts(val: &Rational) -> (Int, Int) {
+    imp::into_parts(val)
+}
+
+/// `val` if it fits in an `i32`
+pub fn to_i32(val: &Int) -> Option<i32> {
+    imp::to_i32(val)
+}
+
+/// `val` if it fits in an `i64`
+pub fn to_i64(val: &Int) -> Option<i64> {
+    imp::to_i64(val)
+}
+
+/// The low 32 bits of `val` in two's complement, as C wraps an integer
+/// converted to a narrower type
+pub fn wrap_i32(val: &Int) -> i32 {
+    let modulus = pow(2, 32);
+    let mut low = val.clone() % modulus.clone();
+    if low < Int::from(0) {
+        low += modulus;
+    }
+    to_i64(&low).expect("less than 2^32") as u32 as i32
+}
+
+/// `val` as `mantissa / 10^exp`, if it has a finite decimal expansion, as
+/// every float literal does
+pub fn to_decimal(val: &Rational) -> Option<(Int, usize)> {
+    let (numer, denom) = into_parts(val);
+    // * `denom` divides `10^exp` only if it is `2^a * 5^b`, and then `exp` is
+    // * at most `max(a, b)`, which is less than 4 digits per decimal digit
+    let max_exp = 4 * denom.to_string().len();
+    let zero = Int::from(0);
+    let mut pow = Int::from(1);
+    for exp in 0..=max_exp {
+        if pow.clone() % denom.clone() == zero {
+            return Some((numer * (pow / denom), exp));
+        }
+        pow *= Int::from(10);
+    }
+    None
+}
+
+/// `val` as the nearest `f64`, ties to even, the same as a C compiler reads
+/// the literal. Values too large for an `f64` are infinite.
+pub fn to_f64(val: &Rational) -> f64 {
+    // * Converting the numerator and denominator separately rounds twice,
+    // * and overflows for large denominators. Rust's decimal parsing rounds
+    // * correctly.
+    match to_decimal(val) {
+        Some((mantissa, exp)) => format!("{}e-{}", mantissa, exp).parse().unwrap_or(f64::NAN),
+        None => imp::to_f64(val),
+    }
+}
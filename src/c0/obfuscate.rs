@@ -0,0 +1,210 @@
+//! Obfuscation for distributing programs, such as reference solutions,
+//! without giving away how they work.
+//!
+//! Every variable, parameter, function and loop label is renamed to `v0`,
+//! `v1` and so on, in declaration order: globals first, then what each
+//! function declares. Each use is renamed after the declaration it refers to,
+//! so shadowed names stay apart. `main` keeps its name, as programs start
+//! there, and so do extern functions. The program is then printed with the
+//! pretty printer, which drops comments and normalizes whitespace.
+
+use super::ast::*;
+use super::err::ParseResult;
+use super::parser::parse_no_panic;
+use super::pretty::pretty_print;
+use super::symbol::Symbol;
+use crate::prelude::*;
+use alloc::collections::{BTreeMap, BTreeSet};
+
+/// `src` with its names replaced and its comments and layout dropped
+pub fn obfuscate(src: &str) -> ParseResult<String> {
+    let mut prog = parse_no_panic(src)?;
+    rename(&mut prog);
+    Ok(pretty_print(&prog))
+}
+
+/// Rename the symbols of `prog` to `v0`, `v1` ... as [`obfuscate`] does
+pub fn rename(prog: &mut Program) {
+    let mut renamer = Renamer {
+        kept: BTreeSet::new(),
+        names: BTreeMap::new(),
+        labels: vec![],
+        next: 0,
+    };
+    for (name, def) in prog.blk.scope.borrow().defs.iter() {
+        let is_extern = match &*def.borrow() {
+            SymbolDef::Var { typ, .. } => {
+                matches!(&*typ.borrow(), TypeDef::Function(f) if f.is_extern)
+            }
+            SymbolDef::Typ { .. } => true,
+        };
+        if name == "main" || is_extern {
+            renamer.kept.insert(*name);
+        }
+    }
+    renamer.block(&mut prog.blk);
+}
+
+struct Renamer {
+    /// Names left as they are
+    kept: BTreeSet<Symbol>,
+    /// New name of each symbol, by the id of its scope and its old name
+    names: BTreeMap<(usize, Symbol), Symbol>,
+    /// Old and new names of the labels of the loops around the statement
+    /// being renamed, innermost last
+    labels: Vec<(Symbol, Symbol)>,
+    next: usize,
+}
+
+impl Renamer {
+    fn fresh(&mut self) -> Symbol {
+        loop {
+            let name = Symbol::intern(&format!("v{}", self.next));
+            self.next += 1;
+            if !self.kept.contains(&name) {
+                return name;
+            }
+        }
+    }
+
+    /// New name of the symbol `name` used at `at` refers to. Variables only
+    /// come into scope after they are declared, so later declarations in the
+    /// same block are skipped.
+    fn resolve(&self, name: Symbol, at: Span, scope: &Ptr<Scope>) -> Option<Symbol> {
+        let mut scope = Some(scope.cp());
+        while let Some(cur) = scope {
+            let cur = cur.borrow();
+            if let Some(def) = cur.defs.get(&name) {
+                let visible = match &*def.borrow() {
+                    SymbolDef::Var { typ, decl_span, .. } => {
+                        typ.borrow().is_fn() || decl_span.start <= at.start
+                    }
+                    SymbolDef::Typ { .. } => true,
+                };
+                if visible {
+                    return self.names.get(&(cur.id, name)).copied();
+                }
+            }
+            scope = cur.last.as_ref().map(|last| last.cp());
+        }
+        None
+    }
+
+    fn block(&mut self, blk: &mut Block) {
+        let scope = blk.scope.cp();
+        let id = scope.borrow().id;
+        let defs: Vec<_> = (scope.borrow().defs.iter())
+            .map(|(name, def)| (*name, def.cp()))
+            .collect();
+        for (name, _) in &defs {
+            let new = match self.kept.contains(name) {
+                true => *name,
+                false => self.fresh(),
+            };
+            self.names.insert((id, *name), new);
+        }
+
+        // * Function bodies refer to the names above, and are renamed
+        // * before the block itself. They are taken out meanwhile, as
+        // * recursive calls look at the type of their function.
+        for (_, def) in &defs {
+            if let SymbolDef::Var { typ, .. } = &*def.borrow() {
+                let body = match &mut *typ.borrow_mut() {
+                    TypeDef::Function(f) => f.body.take(),
+                    _ => None,
+                };
+                if let Some(mut body) = body {
+                    self.block(&mut body);
+                    if let TypeDef::Function(f) = &mut *typ.borrow_mut() {
+                        f.body = Some(body);
+                    }
+                }
+            }
+        }
+        for stmt in &mut blk.stmts {
+            self.stmt(stmt, &scope);
+        }
+
+        let old = core::mem::take(&mut scope.borrow_mut().defs);
+        scope.borrow_mut().defs = (old.into_iter())
+            .map(|(name, def)| (self.names[&(id, name)], def))
+            .collect();
+    }
+
+    fn stmt(&mut self, stmt: &mut Stmt, scope: &Ptr<Scope>) {
+        maybe_grow(|| match &mut stmt.var {
+            StmtVariant::If(i) => {
+                self.expr(&i.cond, scope);
+                self.stmt(&mut i.if_block.borrow_mut(), scope);
+                for (cond, body) in &i.else_ifs {
+                    self.expr(cond, scope);
+                    self.stmt(&mut body.borrow_mut(), scope);
+                }
+                if let Some(body) = &i.else_block {
+                    self.stmt(&mut body.borrow_mut(), scope);
+                }
+            }
+            StmtVariant::While(w) => {
+                self.expr(&w.cond, scope);
+                let label = w.label.as_mut().map(|label| {
+                    let new = self.fresh();
+                    let old = core::mem::replace(&mut label.name, new);
+                    self.labels.push((old, new));
+                });
+                self.stmt(&mut w.block.borrow_mut(), scope);
+                if label.is_some() {
+                    self.labels.pop();
+                }
+            }
+            StmtVariant::Block(b) => self.block(b),
+            StmtVariant::Expr(e) | StmtVariant::Return(Some(e)) => self.expr(e, scope),
+            StmtVariant::Print(es) | StmtVariant::ManyExpr(es) => {
+                es.iter().for_each(|e| self.expr(e, scope))
+            }
+            StmtVariant::Scan(ident, _) => {
+                if let Some(new) = self.resolve(ident.name, stmt.span, scope) {
+                    ident.name = new;
+                }
+            }
+            StmtVariant::Break(Some(label)) => {
+                let found = self.labels.iter().rev().find(|(old, _)| *old == label.name);
+                if let Some((_, new)) = found {
+                    label.name = *new;
+                }
+            }
+            StmtVariant::Return(None) | StmtVariant::Break(None) | StmtVariant::Empty => (),
+        })
+    }
+
+    fn expr(&mut self, expr: &Ptr<Expr>, scope: &Ptr<Scope>) {
+        maybe_grow(|| {
+            let mut expr = expr.borrow_mut();
+            let span = expr.span;
+            match &mut expr.var {
+                ExprVariant::Ident(i) => {
+                    if let Some(new) = self.resolve(i.name, span, scope) {
+                        i.name = new;
+                    }
+                }
+                ExprVariant::Literal(_) => (),
+                ExprVariant::TypeConversion(t) => self.expr(&t.expr, scope),
+                ExprVariant::UnaryOp(u) => self.expr(&u.val, scope),
+                ExprVariant::BinaryOp(b) => {
+                    self.expr(&b.lhs, scope);
+                    self.expr(&b.rhs, scope);
+                }
+                ExprVariant::FunctionCall(f) => {
+                    if let Some(new) = self.resolve(f.func, span, scope) {
+                        f.func = new;
+                    }
+                    f.params.iter().for_each(|p| self.expr(p, scope));
+                }
+                ExprVariant::StructChild(s) => self.expr(&s.val, scope),
+                ExprVariant::ArrayChild(a) => {
+                    self.expr(&a.val, scope);
+                    self.expr(&a.idx, scope);
+                }
+            }
+        })
+    }
+}
@@ -1,57 +1,172 @@
 use super::ast::*;
 use super::err::*;
 use super::lexer::*;
+use super::symbol::Symbol;
+use crate::error::Fix;
 use crate::prelude::*;
-use std::iter::Iterator;
+use core::iter::{Iterator, Peekable};
 
-pub trait IntoParser<T>
+pub trait IntoParser<'src, T>
 where
-    T: Iterator<Item = Token>,
+    T: Iterator<Item = Token<'src>>,
 {
-    fn into_parser(self) -> Parser<T>;
+    fn into_parser(self) -> Parser<'src, T>;
 }
 
-impl<L> IntoParser<Lexer<L>> for Lexer<L>
-where
-    L: Iterator<Item = char>,
-{
-    fn into_parser(self) -> Parser<Lexer<L>> {
+impl<'src> IntoParser<'src, Lexer<'src>> for Lexer<'src> {
+    fn into_parser(self) -> Parser<'src, Lexer<'src>> {
         Parser::new(self)
     }
 }
 
-pub struct Parser<T>
+/// Lex and parse `input`, turning any panic inside the front end into a
+/// `ParseError` instead of unwinding into the caller.
+///
+/// Malformed input is supposed to produce an error on its own; this is the
+/// last line of defence for tools (editors, fuzzers) that must never crash.
+#[cfg(feature = "std")]
+pub fn parse_no_panic(input: &str) -> ParseResult<Program> {
+    parse_no_panic_with(input, vec![])
+}
+
+/// [`parse_no_panic`], with `externs` declared as functions defined elsewhere
+#[cfg(feature = "std")]
+pub fn parse_no_panic_with(
+    input: &str,
+    externs: Vec<(String, FunctionType)>,
+) -> ParseResult<Program> {
+    let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        Parser::new(Lexer::new(input)).with_externs(externs).parse()
+    }));
+    res.unwrap_or_else(|payload| {
+        let msg = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".into());
+        Err(parse_err_z(ParseErrVariant::InternalErr(format!(
+            "parser panicked: {}",
+            msg
+        ))))
+    })
+}
+
+/// What [`Parser::parse_decl`] parsed
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Decl {
+    /// A function, with its body
+    Fn(Symbol),
+    /// Anything else, like global variables
+    Other,
+    /// Nothing, as the input has ended
+    End,
+}
+
+pub struct Parser<'src, T>
 where
-    T: Iterator<Item = Token>,
+    T: Iterator<Item = Token<'src>>,
 {
-    lexer: T,
-    cur: Token,
+    lexer: Peekable<T>,
+    cur: Token<'src>,
+    /// Where the token before the current one ends
+    prev_end: Pos,
+    /// Spans of the `(`, `[` and `{` read and not closed yet, innermost last
+    delims: Vec<(TokenType<'static>, Span)>,
+    /// The first malformed number read. It parses as `0`, so that parsing
+    /// goes on after it, but is reported in place of what parsing returns.
+    bad_number: Option<ParseError>,
+    /// Id of the scope of the function being parsed, which its parameters
+    /// share with the outermost block of its body, and their names
+    params: Option<(usize, Vec<Symbol>)>,
+    /// Doc comments read after the current token, before the next one
+    docs: Vec<String>,
+    /// Doc comments right before the current token
+    cur_doc: Option<String>,
+    /// Functions defined outside the program, declared before it
+    externs: Vec<(String, FunctionType)>,
+    /// Skip the bodies of functions, declaring them only
+    skip_bodies: bool,
+    /// Function the last declaration parsed at the top level defined
+    last_fn: Option<Symbol>,
 }
 
-impl<T> Parser<T>
+impl<'src, T> Parser<'src, T>
 where
-    T: Iterator<Item = Token>,
+    T: Iterator<Item = Token<'src>>,
 {
-    pub fn new(lexer: T) -> Parser<T> {
-        log::info!("Created a new parser.");
+    pub fn new(lexer: T) -> Parser<'src, T> {
+        tracing::info!("Created a new parser.");
 
         let mut parser = Parser {
-            lexer,
+            lexer: lexer.peekable(),
             // type_var: TypeVar::new(),
             cur: Token::dummy(),
+            prev_end: Pos::zero(),
+            delims: vec![],
+            bad_number: None,
+            params: None,
+            docs: vec![],
+            cur_doc: None,
+            externs: vec![],
+            skip_bodies: false,
+            last_fn: None,
         };
         parser.bump();
         parser
     }
 
-    fn bump(&mut self) -> Token {
-        let mut next = self.lexer.next().unwrap_or_else(|| Token::eof());
-        std::mem::swap(&mut self.cur, &mut next);
+    /// Declare `externs` in the outermost scope, like functions declared
+    /// without a body. Programs may call them, but not define them.
+    pub fn with_externs(mut self, externs: Vec<(String, FunctionType)>) -> Self {
+        self.externs = externs;
+        self
+    }
+
+    /// Skip the bodies of functions, only checking that their braces are
+    /// balanced, which gives the declarations of a program in a fraction of
+    /// the time and memory
+    pub fn with_bodies_skipped(mut self) -> Self {
+        self.skip_bodies = true;
+        self
+    }
+
+    fn bump(&mut self) -> Token<'src> {
+        self.skip_docs();
+        let mut next = self.lexer.next().unwrap_or_else(Token::eof);
+        core::mem::swap(&mut self.cur, &mut next);
+        self.prev_end = next.span.end;
+        close_delims(&mut self.delims, &next);
+        self.cur_doc = match self.docs.is_empty() {
+            true => None,
+            false => Some(core::mem::take(&mut self.docs).join("\n")),
+        };
 
-        log::trace!("Bump token pointer. Current: {:#}", self.cur);
+        tracing::trace!("Bump token pointer. Current: {:#}", self.cur);
         next
     }
 
+    /// Move doc comments coming next into `docs`, for the token after them
+    fn skip_docs(&mut self) {
+        while let Some(Token {
+            var: TokenType::DocComment(_),
+            ..
+        }) = self.lexer.peek()
+        {
+            if let Some(TokenType::DocComment(text)) = self.lexer.next().map(|tok| tok.var) {
+                let text = text.strip_prefix(' ').unwrap_or(&text);
+                self.docs.push(text.trim_end().into());
+            }
+        }
+    }
+
+    /// Check the token after the current one without consuming anything.
+    fn check_next(&mut self, accept: &TokenType) -> bool {
+        self.skip_docs();
+        self.lexer
+            .peek()
+            .is_some_and(|next| variant_eq(&next.var, accept))
+    }
+
     fn check(&self, accept: &TokenType) -> bool {
         variant_eq(&self.cur.var, accept)
     }
@@ -72,7 +187,7 @@ where
             Err(parse_err(
                 // We used clone here, because once we meet an error we no longer
                 // need to worry about performance. Things're gonna fail anyway.
-                ParseErrVariant::ExpectToken(accept.clone(), self.cur.var.clone()),
+                ParseErrVariant::ExpectToken(accept.to_string(), self.cur.var.to_string()),
                 self.cur.span,
             ))
         }
@@ -82,12 +197,45 @@ where
         if self.expect(accept) {
             Ok(())
         } else {
-            Err(parse_err(
+            let err = parse_err(
                 // We used clone here, because once we meet an error we no longer
                 // need to worry about performance. Things're gonna fail anyway.
-                ParseErrVariant::ExpectToken(accept.clone(), self.cur.var.clone()),
+                ParseErrVariant::ExpectToken(accept.to_string(), self.cur.var.to_string()),
                 self.cur.span,
-            ))
+            );
+            match self.missing_semicolon(core::slice::from_ref(accept)) {
+                Some(fix) => Err(err.with_fix(fix)),
+                None => Err(err),
+            }
+        }
+    }
+
+    /// Inserting `;` after the previous token, if `accept` has it and it is
+    /// surely missing there: the current token starts a new line, closes
+    /// the block or ends the file
+    fn missing_semicolon(&mut self, accept: &[TokenType]) -> Option<Fix> {
+        let line_ended = self.cur.span.start.ln > self.prev_end.ln
+            || self.check_one_of(&[TokenType::RCurlyBrace, TokenType::EndOfFile]);
+        (line_ended && accept.contains(&TokenType::Semicolon))
+            .then(|| Fix::new("insert `;`", Span::point(self.prev_end), ";"))
+    }
+
+    /// Error `var` about `name` at `span` not being found, fixed by the name
+    /// most like it in `scope` that `want` accepts, if there is one
+    fn not_found(
+        var: ParseErrVariant,
+        name: &str,
+        span: Span,
+        scope: &Scope,
+        want: impl Fn(&SymbolDef) -> bool,
+    ) -> ParseError {
+        let err = parse_err(var, span);
+        match scope.similar_name(name, want) {
+            Some(similar) => {
+                let message = format!("did you mean `{}`?", similar);
+                err.with_fix(Fix::new(message, span, similar))
+            }
+            None => err,
         }
     }
 
@@ -111,10 +259,7 @@ where
             Err(parse_err(
                 // We used clone here, because once we meet an error we no longer
                 // need to worry about performance. Things're gonna fail anyway.
-                ParseErrVariant::ExpectTokenOneOf(
-                    accept.iter().map(|x| x.clone()).collect(),
-                    self.cur.var.clone(),
-                ),
+                ParseErrVariant::ExpectTokenOneOf(owned(accept), self.cur.var.to_string()),
                 self.cur.span,
             ))
         }
@@ -127,22 +272,105 @@ where
             Err(parse_err(
                 // We used clone here, because once we meet an error we no longer
                 // need to worry about performance. Things're gonna fail anyway.
-                ParseErrVariant::ExpectTokenOneOf(
-                    accept.iter().map(|x| x.clone()).collect(),
-                    self.cur.var.clone(),
-                ),
+                ParseErrVariant::ExpectTokenOneOf(owned(accept), self.cur.var.to_string()),
                 self.cur.span,
             ))
         }
     }
 
     pub fn parse(&mut self) -> ParseResult<Program> {
-        log::info!("Init parsing");
-        self.p_program()
+        tracing::info!("Init parsing");
+        let res = self.p_program();
+        self.finish(res)
+    }
+
+    /// Start parsing a program one declaration at a time, returning it
+    /// with nothing declared yet for [`parse_decl`](Self::parse_decl) to
+    /// parse declarations into
+    pub fn begin(&mut self) -> ParseResult<Program> {
+        let res = self.p_root();
+        self.finish(res)
+    }
+
+    /// Parse the next declaration at the top level of `prog`, which
+    /// [`begin`](Self::begin) started
+    pub fn parse_decl(&mut self, prog: &mut Program) -> ParseResult<Decl> {
+        if self.cur.var == TokenType::EndOfFile {
+            return Ok(Decl::End);
+        }
+        let res = self.p_decl_stmt(prog.blk.scope.cp());
+        prog.blk.stmts.push(self.finish(res)?);
+        Ok(self.last_fn.take().map_or(Decl::Other, Decl::Fn))
+    }
+
+    /// `res`, with the error of a malformed number found on the way in
+    /// place of it
+    fn finish<R>(&mut self, res: ParseResult<R>) -> ParseResult<R> {
+        let res = res.map_err(|e| match &self.cur.var {
+            // * Nothing can be parsed from a token that is not valid, so say
+            // * what is wrong with it
+            TokenType::Error(lex) if e.span == self.cur.span => {
+                parse_err(ParseErrVariant::LexerErr(lex.clone()), e.span)
+            }
+            _ => self.unclosed_delim().unwrap_or(e),
+        });
+        match self.bad_number.take() {
+            Some(e) => Err(e),
+            None => res,
+        }
+    }
+
+    /// An error about a delimiter open where parsing failed that is never
+    /// closed, found by reading on to the end. Errors after it are only
+    /// confusion about where blocks and calls end, so it takes their place.
+    fn unclosed_delim(&mut self) -> Option<ParseError> {
+        let mut delims = core::mem::take(&mut self.delims);
+        // * Delimiters below this were open where parsing failed, and still are
+        let mut floor = delims.len();
+        let mut last_end = self.prev_end;
+        let mut tok = self.cur.clone();
+        loop {
+            let unclosed = match (delims.last(), &tok.var) {
+                (_, TokenType::EndOfFile) => true,
+                // * Parentheses and brackets only hold expressions
+                (
+                    Some((TokenType::LParenthesis | TokenType::LBracket, _)),
+                    TokenType::Semicolon | TokenType::LCurlyBrace,
+                ) => true,
+                (Some((open, _)), close) => is_closer(close) && !closes(open, close),
+                (None, _) => false,
+            };
+            if unclosed {
+                let (open, open_span) = delims.last().filter(|_| delims.len() <= floor)?;
+                let span = match tok.var {
+                    TokenType::EndOfFile => Span::point(last_end),
+                    _ => tok.span,
+                };
+                let err = parse_err(
+                    ParseErrVariant::UnclosedDelimiter(open.to_string(), *open_span),
+                    span,
+                );
+                // * Closing right before a statement goes on is surely right
+                let close = match open {
+                    TokenType::LParenthesis => ")",
+                    TokenType::LBracket => "]",
+                    _ => return Some(err),
+                };
+                if !matches!(tok.var, TokenType::Semicolon | TokenType::LCurlyBrace) {
+                    return Some(err);
+                }
+                let message = format!("insert `{}`", close);
+                return Some(err.with_fix(Fix::new(message, Span::point(last_end), close)));
+            }
+            close_delims(&mut delims, &tok);
+            floor = floor.min(delims.len());
+            last_end = tok.span.end;
+            tok = self.lexer.next().unwrap_or_else(Token::eof);
+        }
     }
 
     fn inject_std(scope: Ptr<Scope>) {
-        log::info!("Injecting std types");
+        tracing::info!("Injecting std types");
         let mut scope = scope.borrow_mut();
 
         // Declaration of `int`: i32
@@ -196,31 +424,61 @@ where
     }
 
     fn p_program(&mut self) -> ParseResult<Program> {
-        log::info!("Starts parsing program");
+        let mut prog = self.p_root()?;
+        while self.cur.var != TokenType::EndOfFile {
+            let stmt = self.p_decl_stmt(prog.blk.scope.cp())?;
+            prog.blk.stmts.push(stmt);
+        }
+        tracing::info!("Finished parsing program");
+        Ok(prog)
+    }
+
+    /// A program with only what is declared before its source
+    fn p_root(&mut self) -> ParseResult<Program> {
+        tracing::info!("Starts parsing program");
+        Scope::reset_id();
         let root_scope = Ptr::new(Scope::new());
         Self::inject_std(root_scope.cp());
-        let mut stmts = Vec::new();
-        while self.cur.var != TokenType::EndOfFile {
-            stmts.push(self.p_decl_stmt(root_scope.cp())?)
+        for (name, func) in core::mem::take(&mut self.externs) {
+            let def = SymbolDef::Var {
+                typ: Ptr::new(TypeDef::Function(FunctionType {
+                    is_extern: true,
+                    ..func
+                })),
+                is_const: false,
+                decl_span: Span::zero(),
+                value: None,
+                doc: None,
+            };
+            root_scope.borrow_mut().insert_def(&name, def)?;
         }
-        log::info!("Finished parsing program");
         Ok(Program {
             blk: Block {
                 scope: root_scope,
-                stmts,
+                stmts: vec![],
                 span: None,
             },
         })
     }
 
     fn p_stmt(&mut self, scope: Ptr<Scope>) -> ParseResult<Stmt> {
-        log::debug!("Parse statement");
+        // * Every nested statement recurses through here, like expressions
+        // * through `p_base_expr`
+        maybe_grow(|| self.p_stmt_inner(scope))
+    }
+
+    fn p_stmt_inner(&mut self, scope: Ptr<Scope>) -> ParseResult<Stmt> {
+        tracing::debug!("Parse statement");
+
+        if self.check(&TokenType::Identifier(Symbol::EMPTY)) && self.check_next(&TokenType::Colon) {
+            return self.p_labeled_stmt(scope);
+        }
 
         match &self.cur.var {
             TokenType::LCurlyBrace => self.p_block_stmt(scope),
             TokenType::Identifier(..) => self.p_decl_or_expr(scope),
             TokenType::If => self.p_if_stmt(scope),
-            TokenType::While => self.p_while_stmt(scope),
+            TokenType::While => self.p_while_stmt(None, scope),
             TokenType::Scan => self.p_scan_stmt(scope),
             TokenType::Print => self.p_print_stmt(scope),
             TokenType::Break => self.p_break_stmt(scope),
@@ -244,7 +502,8 @@ where
             }
             // TokenType::Do => todo!("Parse do-while loop"),
             // TokenType::For => todo!("Parse for loop"),
-            TokenType::Const => self.p_decl_stmt(scope),
+            // * `&int p;` declares a reference. `&x;` alone would do nothing.
+            TokenType::Const | TokenType::Auto | TokenType::BinaryAnd => self.p_decl_stmt(scope),
             TokenType::LParenthesis
             | TokenType::LBracket
             | TokenType::Literal(..)
@@ -253,7 +512,7 @@ where
             | TokenType::Decrease => self.p_expr_stmt(scope),
             _ => Err(parse_err(
                 ParseErrVariant::UnexpectedTokenMsg {
-                    typ: self.cur.var.clone(),
+                    typ: self.cur.var.to_string(),
                     msg: "This token cannot start a statement",
                 },
                 self.cur.span,
@@ -266,15 +525,18 @@ where
             TokenType::Identifier(ident) => {
                 let entry = scope.borrow().find_def(ident);
                 match entry {
-                    None => Err(parse_err(
-                        ParseErrVariant::CannotFindIdent(ident.into()),
+                    None => Err(Self::not_found(
+                        ParseErrVariant::CannotFindIdent(ident.to_string()),
+                        ident,
                         self.cur.span,
+                        &scope.borrow(),
+                        |_| true,
                     )),
                     Some(entry) => {
                         let entry = entry.borrow();
-                        match &*entry {
-                            &SymbolDef::Typ { .. } => self.p_decl_stmt(scope.cp()),
-                            &SymbolDef::Var { .. } => self.p_expr_stmt(scope.cp()),
+                        match *entry {
+                            SymbolDef::Typ { .. } => self.p_decl_stmt(scope.cp()),
+                            SymbolDef::Var { .. } => self.p_expr_stmt(scope.cp()),
                         }
                     }
                 }
@@ -294,7 +556,7 @@ where
     }
 
     fn p_block_stmt(&mut self, scope: Ptr<Scope>) -> ParseResult<Stmt> {
-        log::trace!("Creating new scope for upcoming block");
+        tracing::trace!("Creating new scope for upcoming block");
 
         let new_scope = Ptr::new(Scope::new_with_parent(scope));
         self.p_block_stmt_no_scope(new_scope)
@@ -307,7 +569,7 @@ where
         })
     }
     fn p_block_no_scope(&mut self, scope: Ptr<Scope>) -> ParseResult<(Block, Span)> {
-        log::debug!("Parsing block");
+        tracing::debug!("Parsing block");
 
         let l_span = self.cur.span;
         self.expect_report(&TokenType::LCurlyBrace)?;
@@ -322,7 +584,7 @@ where
         let r_span = self.cur.span;
         self.expect_report(&TokenType::RCurlyBrace)?;
 
-        log::debug!("Block ends");
+        tracing::debug!("Block ends");
         Ok((
             Block {
                 scope,
@@ -334,7 +596,7 @@ where
     }
 
     fn p_type_name(&mut self, scope: Ptr<Scope>) -> ParseResult<Ptr<TypeDef>> {
-        log::trace!("Parsing type name");
+        tracing::trace!("Parsing type name");
 
         let tok = self.bump();
         match tok.var {
@@ -351,23 +613,28 @@ where
             }
             TokenType::Identifier(ident) => {
                 let span = tok.span;
-                match scope.borrow().find_def(&ident) {
-                    None => Err(parse_err(
-                        ParseErrVariant::CannotFindType(ident.into()),
+                match scope.borrow().find_def(ident) {
+                    None => Err(Self::not_found(
+                        ParseErrVariant::CannotFindType(ident.to_string()),
+                        &ident,
                         span,
+                        &scope.borrow(),
+                        |def| matches!(def, SymbolDef::Typ { .. }),
                     )),
                     Some(def) => match &*def.borrow() {
                         // TODO: Add generics?
-                        SymbolDef::Typ { .. } => Ok(Ptr::new(TypeDef::NamedType(ident.into()))),
+                        SymbolDef::Typ { .. } => {
+                            Ok(Ptr::new(TypeDef::NamedType(ident.to_string())))
+                        }
                         _ => Err(parse_err(
-                            ParseErrVariant::CannotFindType(ident.into()),
+                            ParseErrVariant::CannotFindType(ident.to_string()),
                             span,
                         )),
                     },
                 }
             }
             _ => Err(parse_err(
-                ParseErrVariant::UnexpectedToken(tok.var),
+                ParseErrVariant::UnexpectedToken(tok.var.to_string()),
                 tok.span,
             )),
         }
@@ -376,49 +643,50 @@ where
     /// Parse a function, optionally with its body.
     fn p_fn(
         &mut self,
+        init_span: Span,
         type_decl: Ptr<TypeDef>,
         decl_token: Token,
+        doc: Option<String>,
         scope: Ptr<Scope>,
     ) -> ParseResult<Stmt> {
-        let left_span = self.cur.span;
         self.expect_report(&TokenType::LParenthesis)?;
         // The expressions in function call
         let mut expr_vec = Vec::new();
         let mut inner_scope = Scope::new_with_parent(scope.cp());
 
         if !self.check(&TokenType::RParenthesis) {
-            let param_type = self.p_type_name(scope.cp())?;
-            self.check_report(&TokenType::Identifier(String::new()))?;
-            let ident = self.bump();
-            let ident_str = ident.get_ident().unwrap();
-            inner_scope.insert_def(
-                ident_str,
-                SymbolDef::Var {
-                    typ: param_type.cp(),
-                    is_const: false,
-                    decl_span: ident.span,
-                },
-            )?;
-            expr_vec.push((param_type, ident_str.to_owned()));
-            while self.expect(&TokenType::Comma) {
+            loop {
                 let param_type = self.p_type_name(scope.cp())?;
-                self.check_report(&TokenType::Identifier(String::new()))?;
+                self.check_report(&TokenType::Identifier(Symbol::EMPTY))?;
                 let ident = self.bump();
                 let ident_str = ident.get_ident().unwrap();
-                inner_scope.insert_def(
-                    ident_str,
-                    SymbolDef::Var {
-                        typ: param_type.cp(),
-                        is_const: false,
-                        decl_span: ident.span,
-                    },
-                )?;
+                if expr_vec.iter().any(|(_, name)| *name == ident_str) {
+                    Err(parse_err(
+                        ParseErrVariant::DuplicateParameter(ident_str.to_string()),
+                        ident.span,
+                    ))?;
+                }
+                inner_scope
+                    .insert_def(
+                        ident_str,
+                        SymbolDef::Var {
+                            typ: param_type.cp(),
+                            is_const: false,
+                            decl_span: ident.span,
+                            value: None,
+                            doc: None,
+                        },
+                    )
+                    .with_span(ident.span)?;
                 expr_vec.push((param_type, ident_str.to_owned()));
+                if !self.expect(&TokenType::Comma) {
+                    break;
+                }
             }
         }
         let inner_scope = Ptr::new(inner_scope);
 
-        log::info!(
+        tracing::info!(
             "Parse function \"{}\" with type {:?}, params: {:?}",
             decl_token.get_ident().unwrap(),
             type_decl,
@@ -427,57 +695,109 @@ where
 
         let right_span = self.cur.span;
         self.expect_report(&TokenType::RParenthesis)?;
-        let span = left_span + right_span;
+        let span = decl_token.span + right_span;
 
         // Insert function declaration
-        scope.borrow_mut().insert_def(
-            decl_token.get_ident().unwrap(),
-            SymbolDef::Var {
-                typ: Ptr::new(TypeDef::Function(FunctionType {
-                    return_type: type_decl.cp(),
-                    params: expr_vec.iter().map(|x| x.0.clone()).collect(),
-                    body: None,
-                    is_extern: false,
-                })),
-                is_const: false,
-                decl_span: span,
-            },
-        )?;
+        scope
+            .borrow_mut()
+            .insert_def(
+                decl_token.get_ident().unwrap(),
+                SymbolDef::Var {
+                    typ: Ptr::new(TypeDef::Function(FunctionType {
+                        return_type: type_decl.cp(),
+                        params: expr_vec.iter().map(|x| x.0.clone()).collect(),
+                        body: None,
+                        is_extern: false,
+                    })),
+                    is_const: false,
+                    decl_span: span,
+                    value: None,
+                    doc: doc.clone(),
+                },
+            )
+            .with_span(decl_token.span)?;
+
+        if self.skip_bodies {
+            let body_span = self.skip_block()?;
+            return Ok(Stmt {
+                var: StmtVariant::Empty,
+                span: init_span + body_span,
+            });
+        }
 
-        let (body, body_span) = self.p_block_no_scope(inner_scope.cp())?;
+        let params = expr_vec.iter().map(|(_, name)| *name).collect();
+        let outer_params = self.params.replace((inner_scope.borrow().id, params));
+        let body = self.p_block_no_scope(inner_scope.cp());
+        self.params = outer_params;
+        let (body, body_span) = body?;
 
         // Insert function declaration again with body
-        scope.borrow_mut().insert_def(
-            decl_token.get_ident().unwrap(),
-            SymbolDef::Var {
-                typ: Ptr::new(TypeDef::Function(FunctionType {
-                    return_type: type_decl.cp(),
-                    params: expr_vec.iter().map(|x| x.0.clone()).collect(),
-                    body: Some(body),
-                    is_extern: false,
-                })),
-                is_const: false,
-                decl_span: span,
-            },
-        )?;
+        scope
+            .borrow_mut()
+            .insert_def(
+                decl_token.get_ident().unwrap(),
+                SymbolDef::Var {
+                    typ: Ptr::new(TypeDef::Function(FunctionType {
+                        return_type: type_decl.cp(),
+                        params: expr_vec.iter().map(|x| x.0.clone()).collect(),
+                        body: Some(body),
+                        is_extern: false,
+                    })),
+                    is_const: false,
+                    decl_span: span,
+                    value: None,
+                    doc,
+                },
+            )
+            .with_span(decl_token.span)?;
+        if scope.borrow().last.is_none() {
+            self.last_fn = decl_token.get_ident();
+        }
 
         Ok(Stmt {
             var: StmtVariant::Empty,
-            span: left_span + right_span + body_span,
+            span: init_span + body_span,
         })
     }
 
+    /// Skip a block without parsing what is in it, returning its span
+    fn skip_block(&mut self) -> ParseResult<Span> {
+        let l_span = self.cur.span;
+        self.expect_report(&TokenType::LCurlyBrace)?;
+        let mut depth = 1;
+        loop {
+            match self.cur.var {
+                TokenType::LCurlyBrace => depth += 1,
+                TokenType::RCurlyBrace => depth -= 1,
+                TokenType::EndOfFile => self.expect_report(&TokenType::RCurlyBrace)?,
+                _ => (),
+            }
+            let r_span = self.bump().span;
+            if depth == 0 {
+                return Ok(l_span + r_span);
+            }
+        }
+    }
+
     fn p_decl_stmt(&mut self, scope: Ptr<Scope>) -> ParseResult<Stmt> {
         // This is the identifier token
 
         let init_span = self.cur.span;
+        let doc = self.cur_doc.take();
         let is_const = self.expect(&TokenType::Const);
-        let type_decl = self.p_type_name(scope.cp())?;
+        // * `auto` variables get their type from what is assigned to them,
+        // * which the type checker works out
+        let is_auto = self.expect(&TokenType::Auto);
+        let type_decl = if is_auto {
+            Ptr::new(TypeDef::Unknown)
+        } else {
+            self.p_type_name(scope.cp())?
+        };
         let mut has_next = true;
         let mut exprs = Vec::new();
 
         while has_next {
-            self.check_report(&TokenType::Identifier(String::new()))?;
+            self.check_report(&TokenType::Identifier(Symbol::EMPTY))?;
             let mut span = self.cur.span;
             let ident = self.bump();
 
@@ -486,9 +806,14 @@ where
                 // * immediately end this algorithm and switch to function
                 // * parsing.
                 // TODO: Any possible changes?
-                return self.p_fn(type_decl, ident, scope);
+                if is_auto {
+                    let name = ident.get_ident().unwrap().to_string();
+                    Err(parse_err(ParseErrVariant::AutoFunction(name), ident.span))?;
+                }
+                return self.p_fn(init_span, type_decl, ident, doc, scope);
             }
 
+            let op_span = self.cur.span;
             let init_val = if self.expect(&TokenType::Assign) {
                 let expr =
                     self.p_base_expr(&[TokenType::Comma, TokenType::Semicolon], scope.cp())?;
@@ -505,14 +830,33 @@ where
                 ))?;
             }
 
-            scope.borrow_mut().insert_def(
-                ident.get_ident().unwrap(),
-                SymbolDef::Var {
-                    typ: type_decl.cp(),
-                    is_const,
-                    decl_span: span,
-                },
-            )?;
+            // * C0 puts parameters in the same scope as the outermost block
+            // * of the body, so they cannot be declared again there
+            if let Some((id, params)) = &self.params {
+                let name = ident.get_ident().unwrap();
+                if *id == scope.borrow().id && params.contains(&name) {
+                    Err(parse_err(
+                        ParseErrVariant::RedefinedParameter(name.to_string()),
+                        ident.span,
+                    ))?;
+                }
+            }
+
+            // * Each declarator gets its own span, so errors point at the one
+            // * at fault rather than the start of the declaration
+            scope
+                .borrow_mut()
+                .insert_def(
+                    ident.get_ident().unwrap(),
+                    SymbolDef::Var {
+                        typ: type_decl.cp(),
+                        is_const,
+                        decl_span: span,
+                        value: init_val.as_ref().filter(|_| is_const).map(|v| v.cp()),
+                        doc: doc.clone(),
+                    },
+                )
+                .with_span(ident.span)?;
 
             if let Some(val) = init_val {
                 let span = ident.span + val.borrow().span();
@@ -521,11 +865,12 @@ where
                         op: if is_const { OpVar::_Csn } else { OpVar::_Asn },
                         lhs: Ptr::new(Expr {
                             var: ExprVariant::Ident(Identifier {
-                                name: ident.get_ident().unwrap().into(),
+                                name: ident.get_ident().unwrap(),
                             }),
                             span: ident.span,
                         }),
                         rhs: val,
+                        op_span,
                     }),
                     span,
                 }))
@@ -534,9 +879,9 @@ where
             has_next = self.expect(&TokenType::Comma);
         }
 
-        let span = exprs
-            .iter()
-            .fold(init_span, |last, this| this.borrow().span() + last);
+        // * The span covers the whole declaration, so every variable declared
+        // * here has its `decl_span` inside it.
+        let span = init_span + self.cur.span;
 
         self.expect_report(&TokenType::Semicolon)?;
         Ok(Stmt {
@@ -545,7 +890,22 @@ where
         })
     }
 
-    fn p_while_stmt(&mut self, scope: Ptr<Scope>) -> ParseResult<Stmt> {
+    /// Parse a labeled loop, e.g. `outer: while (...) ...`
+    fn p_labeled_stmt(&mut self, scope: Ptr<Scope>) -> ParseResult<Stmt> {
+        let span = self.cur.span;
+        let label = self.bump();
+        let label = Identifier {
+            name: label.get_ident().unwrap().to_owned(),
+        };
+        self.expect_report(&TokenType::Colon)?;
+        self.check_report(&TokenType::While)?;
+
+        let mut stmt = self.p_while_stmt(Some(label), scope)?;
+        stmt.span = span + stmt.span;
+        Ok(stmt)
+    }
+
+    fn p_while_stmt(&mut self, label: Option<Identifier>, scope: Ptr<Scope>) -> ParseResult<Stmt> {
         let mut span = self.cur.span;
 
         self.expect_report(&TokenType::While)?;
@@ -563,7 +923,7 @@ where
         });
 
         Ok(Stmt {
-            var: StmtVariant::While(WhileConditional { cond, block }),
+            var: StmtVariant::While(WhileConditional { cond, block, label }),
             span,
         })
     }
@@ -585,18 +945,30 @@ where
             stmt
         });
 
-        let else_block = if self.expect(&TokenType::Else) {
-            let stmt = self.p_stmt(scope.cp())?;
-            span = span + stmt.span();
-            Some(Ptr::new(stmt))
-        } else {
-            None
-        };
+        let mut else_ifs = Vec::new();
+        let mut else_block = None;
+        while self.expect(&TokenType::Else) {
+            if self.expect(&TokenType::If) {
+                self.expect_report(&TokenType::LParenthesis)?;
+                let cond = self.p_base_expr(&[TokenType::RParenthesis], scope.cp())?;
+                self.expect_report(&TokenType::RParenthesis)?;
+
+                let stmt = self.p_stmt(scope.cp())?;
+                span = span + stmt.span();
+                else_ifs.push((cond, Ptr::new(stmt)));
+            } else {
+                let stmt = self.p_stmt(scope.cp())?;
+                span = span + stmt.span();
+                else_block = Some(Ptr::new(stmt));
+                break;
+            }
+        }
 
         Ok(Stmt {
             var: StmtVariant::If(IfConditional {
                 cond,
                 if_block,
+                else_ifs,
                 else_block,
             }),
             span,
@@ -625,6 +997,7 @@ where
             span = span + expr.borrow().span();
             exprs.push(expr);
         }
+        let span = span + self.cur.span;
         self.expect_report(&TokenType::Semicolon)?;
 
         Ok(Stmt {
@@ -633,30 +1006,46 @@ where
         })
     }
 
-    fn p_scan_stmt(&mut self, scope: Ptr<Scope>) -> ParseResult<Stmt> {
+    fn p_scan_stmt(&mut self, _scope: Ptr<Scope>) -> ParseResult<Stmt> {
         let span = self.cur.span;
         self.expect_report(&TokenType::Scan)?;
         self.expect_report(&TokenType::LParenthesis)?;
-        self.check_report(&TokenType::Identifier(String::new()))?;
+        self.check_report(&TokenType::Identifier(Symbol::EMPTY))?;
         let ident = self.bump();
+        let name_span = ident.span;
         let ident = ident.get_ident().unwrap().to_owned();
         let ident = Identifier { name: ident };
         self.expect_report(&TokenType::RParenthesis)?;
         let span = span + self.cur.span;
         self.expect_report(&TokenType::Semicolon)?;
         Ok(Stmt {
-            var: StmtVariant::Scan(ident),
+            var: StmtVariant::Scan(ident, name_span),
             span,
         })
     }
 
-    fn p_break_stmt(&mut self, scope: Ptr<Scope>) -> ParseResult<Stmt> {
-        let span = self.cur.span;
+    fn p_break_stmt(&mut self, _scope: Ptr<Scope>) -> ParseResult<Stmt> {
+        let mut span = self.cur.span;
         self.expect_report(&TokenType::Break)?;
+
+        let label = if self.check(&TokenType::Identifier(Symbol::EMPTY)) {
+            let label = self.bump();
+            span = span + label.span;
+            Some(Identifier {
+                name: label.get_ident().unwrap().to_owned(),
+            })
+        } else {
+            None
+        };
+
+        if !self.check(&TokenType::Semicolon) && !self.check(&TokenType::EndOfFile) {
+            // Loops are statements and have no value to break with.
+            return Err(parse_err(ParseErrVariant::BreakWithValue, self.cur.span));
+        }
         self.expect_report(&TokenType::Semicolon)?;
 
         Ok(Stmt {
-            var: StmtVariant::Break,
+            var: StmtVariant::Break(label),
             span,
         })
     }
@@ -685,14 +1074,18 @@ where
         close_delim: &[TokenType],
         scope: Ptr<Scope>,
     ) -> ParseResult<Ptr<Expr>> {
-        let mut expr = None;
-        while !self.check_one_of(close_delim) {
-            expr = Some(self.p_binary_op(expr, 0, close_delim, scope.cp())?);
-        }
-        expr.ok_or_else(|| {
-            parse_err_z(ParseErrVariant::InternalErr(
-                "Invalid branching into expression parsing".into(),
-            ))
+        // * Every nested parenthesis recurses through here, so make sure we
+        // * have enough stack before going deeper.
+        maybe_grow(|| {
+            let mut expr = None;
+            while !self.check_one_of(close_delim) {
+                expr = Some(self.p_binary_op(expr, 0, close_delim, scope.cp())?);
+            }
+            expr.ok_or_else(|| {
+                parse_err_z(ParseErrVariant::InternalErr(
+                    "Invalid branching into expression parsing".into(),
+                ))
+            })
         })
     }
 
@@ -706,29 +1099,35 @@ where
         close_delim: &[TokenType],
         scope: Ptr<Scope>,
     ) -> ParseResult<Ptr<Expr>> {
-        let lhs = if lhs.is_some() {
-            lhs.unwrap()
+        let lhs = if let Some(lhs) = lhs {
+            lhs
         } else {
             self.p_prefix_unary_op(scope.cp())?
         };
 
         // Op should be self.cur
-        if let Some(op) = self.cur.var.into_op(false, false) {
+        if let Some(op) = self.cur.var.to_op(false, false) {
             let mut lhs = lhs;
             let mut op = op;
             while !close_delim.contains(&self.cur.var)
                 && ((op.is_left_associative() && op.priority() > expect_prec)
                     || (op.is_right_associative() && op.priority() >= expect_prec))
             {
+                let op_span = self.cur.span;
                 self.bump();
                 let rhs = self.p_binary_op(None, op.priority(), close_delim, scope.cp())?;
                 let span = { lhs.borrow().span() + rhs.borrow().span() };
                 lhs = Ptr::new(Expr {
-                    var: ExprVariant::BinaryOp(BinaryOp { lhs, rhs, op }),
+                    var: ExprVariant::BinaryOp(BinaryOp {
+                        lhs,
+                        rhs,
+                        op,
+                        op_span,
+                    }),
                     span,
                 });
 
-                if let Some(op_) = self.cur.var.into_op(false, false) {
+                if let Some(op_) = self.cur.var.to_op(false, false) {
                     op = op_;
                 } else {
                     break;
@@ -739,26 +1138,40 @@ where
             if close_delim.contains(&self.cur.var) {
                 Ok(lhs)
             } else {
-                Err(parse_err(
+                let err = parse_err(
                     ParseErrVariant::UnexpectedTokenMsg {
-                        typ: self.cur.var.clone(),
+                        typ: self.cur.var.to_string(),
                         msg: "Token cannot be here in an expression",
                     },
                     self.cur.span,
-                ))
+                );
+                match self.missing_semicolon(close_delim) {
+                    Some(fix) => Err(err.with_fix(fix)),
+                    None => Err(err),
+                }
             }
         }
     }
 
     fn p_prefix_unary_op(&mut self, scope: Ptr<Scope>) -> ParseResult<Ptr<Expr>> {
         let mut op_vec = Vec::new();
-        while let Some(op) = self.cur.var.into_op(true, false) {
+        while let Some(op) = self.cur.var.to_op(true, false) {
             op_vec.push((op, self.cur.span));
             self.bump();
         }
         let mut expr = self.p_postfix_unary_op(scope)?;
         while let Some((op, span)) = op_vec.pop() {
             let span = span + expr.borrow().span();
+            // * `-` is folded into integer literals, so `-2147483648` is one
+            // * literal that fits in an `int`, not a negated one that doesn't
+            if op == OpVar::Neg {
+                let mut item = expr.borrow_mut();
+                if let ExprVariant::Literal(super::ast::Literal::Integer { val }) = &mut item.var {
+                    *val = -val.clone();
+                    item.span = span;
+                    continue;
+                }
+            }
             expr = Ptr::new(Expr {
                 var: ExprVariant::UnaryOp(UnaryOp { op, val: expr }),
                 span,
@@ -770,20 +1183,22 @@ where
     fn p_postfix_unary_op(&mut self, scope: Ptr<Scope>) -> ParseResult<Ptr<Expr>> {
         let mut expr = self.p_item(scope.cp())?;
         loop {
-            if let Some(op) = self.cur.var.into_op(false, true) {
+            if let Some(op) = self.cur.var.to_op(false, true) {
+                let span = expr.borrow().span + self.cur.span;
                 expr = Ptr::new(Expr {
                     var: ExprVariant::UnaryOp(UnaryOp { op, val: expr }),
-                    span: self.cur.span,
+                    span,
                 });
                 self.bump();
             } else if self.cur.var == TokenType::LBracket {
                 // Parse index operator
                 self.bump();
                 let idx = self.p_base_expr(&[TokenType::RBracket], scope.cp())?;
+                let span = expr.borrow().span + self.cur.span;
                 self.expect_report(&TokenType::RBracket)?;
                 expr = Ptr::new(Expr {
                     var: ExprVariant::ArrayChild(ArrayChild { val: expr, idx }),
-                    span: self.cur.span,
+                    span,
                 });
             // TODO: Add parsing for struct child (later)
             // } else if self.cur.var == TokenType::Dot {
@@ -820,15 +1235,7 @@ where
             // * This should be a preceding operator, but it is placed here to avoid backtracking.
             let is_implicit_conv = if let TokenType::Identifier(i) = &self.cur.var {
                 let def = scope.borrow().find_def(i);
-                if let Some(def) = def {
-                    if let SymbolDef::Typ { .. } = &*def.borrow() {
-                        true
-                    } else {
-                        false
-                    }
-                } else {
-                    false
-                }
+                def.is_some_and(|def| matches!(&*def.borrow(), SymbolDef::Typ { .. }))
             } else {
                 false
             };
@@ -854,17 +1261,27 @@ where
         } else {
             if self.check(&TokenType::Literal(super::lexer::Literal::_Dummy)) {
                 self.p_literal()
-            } else if self.check(&TokenType::Identifier(String::new())) {
+            } else if matches!(&self.cur.var, TokenType::Error(e) if e.is_number()) {
+                self.p_bad_number()
+            } else if self.check(&TokenType::Identifier(Symbol::EMPTY)) {
                 self.p_ident_or_fn_call(scope)
+            } else if self.check_one_of(&[TokenType::If, TokenType::While, TokenType::LCurlyBrace])
+            {
+                // * Control flow in C0 never produces a value. Say so instead
+                // * of listing what we expected.
+                Err(parse_err(
+                    ParseErrVariant::ControlFlowInExpr(self.cur.var.to_string()),
+                    self.cur.span,
+                ))
             } else {
                 Err(parse_err(
                     ParseErrVariant::ExpectTokenOneOf(
-                        vec![
+                        owned(&[
                             TokenType::Literal(super::lexer::Literal::_Dummy),
-                            TokenType::Identifier(String::new()),
+                            TokenType::Identifier(Symbol::EMPTY),
                             TokenType::LParenthesis,
-                        ],
-                        self.cur.var.clone(),
+                        ]),
+                        self.cur.var.to_string(),
                     ),
                     self.cur.span,
                 ))
@@ -876,7 +1293,7 @@ where
     ///
     /// This parser accepts a starting state when `self.cur` is the first `Identifier`
     fn p_ident_or_fn_call(&mut self, scope: Ptr<Scope>) -> ParseResult<Ptr<Expr>> {
-        self.check_report(&TokenType::Identifier(String::new()))?;
+        self.check_report(&TokenType::Identifier(Symbol::EMPTY))?;
         let cur = self.bump();
         if self.check(&TokenType::LParenthesis) {
             self.p_fn_call(&cur, scope)
@@ -885,21 +1302,26 @@ where
             let ident = scope
                 .borrow()
                 .find_def(cur.get_ident().unwrap())
-                .ok_or(parse_err(
-                    ParseErrVariant::CannotFindIdent(cur.get_ident().unwrap().to_owned()),
-                    cur.span,
-                ))?;
+                .ok_or_else(|| {
+                    Self::not_found(
+                        ParseErrVariant::CannotFindIdent(cur.get_ident().unwrap().to_string()),
+                        &cur.get_ident().unwrap(),
+                        cur.span,
+                        &scope.borrow(),
+                        |def| matches!(def, SymbolDef::Var { .. }) && !is_fn(def),
+                    )
+                })?;
             let ident = &*ident.borrow();
             match ident {
                 SymbolDef::Typ { .. } => Err(parse_err(
-                    ParseErrVariant::ExpectToBeVar(cur.get_ident().unwrap().into()),
+                    ParseErrVariant::ExpectToBeVar(cur.get_ident().unwrap().to_string()),
                     cur.span,
                 )),
                 SymbolDef::Var { typ, .. } => {
                     let typ = typ.borrow();
                     match &*typ {
                         TypeDef::Function(..) => Err(parse_err(
-                            ParseErrVariant::ExpectToBeVar(cur.get_ident().unwrap().into()),
+                            ParseErrVariant::ExpectToBeVar(cur.get_ident().unwrap().to_string()),
                             cur.span,
                         )),
                         _ => Ok(()),
@@ -922,16 +1344,21 @@ where
         let func = scope
             .borrow()
             .find_def(fn_tok.get_ident().unwrap())
-            .ok_or(parse_err(
-                ParseErrVariant::CannotFindFn(fn_tok.get_ident().unwrap().to_owned()),
-                fn_tok.span,
-            ))?;
+            .ok_or_else(|| {
+                Self::not_found(
+                    ParseErrVariant::CannotFindFn(fn_tok.get_ident().unwrap().to_string()),
+                    &fn_tok.get_ident().unwrap(),
+                    fn_tok.span,
+                    &scope.borrow(),
+                    is_fn,
+                )
+            })?;
 
         // * Check if this is really a function
         let func = &*func.borrow();
         match func {
             SymbolDef::Typ { .. } => Err(parse_err(
-                ParseErrVariant::ExpectToBeFn(fn_tok.get_ident().unwrap().into()),
+                ParseErrVariant::ExpectToBeFn(fn_tok.get_ident().unwrap().to_string()),
                 fn_tok.span,
             )),
             SymbolDef::Var { typ, .. } => {
@@ -939,7 +1366,7 @@ where
                 match &*typ {
                     TypeDef::Function(..) => Ok(()),
                     _ => Err(parse_err(
-                        ParseErrVariant::ExpectToBeFn(fn_tok.get_ident().unwrap().into()),
+                        ParseErrVariant::ExpectToBeFn(fn_tok.get_ident().unwrap().to_string()),
                         fn_tok.span,
                     )),
                 }
@@ -971,6 +1398,20 @@ where
         }))
     }
 
+    /// Parse a malformed number as `0`, keeping its error to report
+    fn p_bad_number(&mut self) -> ParseResult<Ptr<Expr>> {
+        let t = self.bump();
+        if let TokenType::Error(e) = t.var {
+            let err = parse_err(ParseErrVariant::LexerErr(e), t.span);
+            self.bad_number.get_or_insert(err);
+        }
+        let zero = super::lexer::Literal::Integer(super::num::Int::from(0));
+        Ok(Ptr::new(Expr {
+            var: ExprVariant::Literal(zero.into()),
+            span: t.span,
+        }))
+    }
+
     fn p_literal(&mut self) -> ParseResult<Ptr<Expr>> {
         let t = self.bump();
         match t.var {
@@ -978,7 +1419,7 @@ where
                 var: ExprVariant::Literal(i.into()),
                 span: t.span,
             })),
-            v @ _ => Err(parse_err(
+            v => Err(parse_err(
                 ParseErrVariant::InternalErr(format!(
                     "Bad branching into literal parsing while getting a token type of `{}`",
                     v
@@ -989,12 +1430,12 @@ where
     }
 }
 
-trait IntoOperator {
-    fn into_op(&self, suggest_unary: bool) -> Option<OpVar>;
+trait ToOperator {
+    fn to_op(&self, suggest_unary: bool) -> Option<OpVar>;
 }
 
-impl TokenType {
-    fn into_op(&self, unary_prefix: bool, unary_postfix: bool) -> Option<OpVar> {
+impl TokenType<'_> {
+    fn to_op(&self, unary_prefix: bool, unary_postfix: bool) -> Option<OpVar> {
         use OpVar::*;
         use TokenType::*;
         if unary_prefix {
@@ -1055,15 +1496,16 @@ impl Operator for OpVar {
         match self {
             _Dum => 0,
             _Lpr | _Rpr => 2,
-            _Com => 8,
-            _Asn | _Csn => 0,
+            // * The comma binds loosest, so `a = 1, b` is `(a = 1), b`
+            _Com => 1,
+            _Asn | _Csn => 2,
+            Or => 8,
+            And => 9,
+            Bor => 10,
+            Xor => 11,
+            Ban => 12,
             Eq | Neq => 13,
             Gt | Lt | Gte | Lte => 14,
-            Or => 15,
-            And => 16,
-            Bor => 17,
-            Xor => 18,
-            Ban => 19,
             Add | Sub => 20,
             Mul | Div => 30,
             Neg | Pos | Inv | Bin | Ref | Der | Ina | Inb | Dea | Deb => 40,
@@ -1072,17 +1514,58 @@ impl Operator for OpVar {
 
     fn is_unary(&self) -> bool {
         use OpVar::*;
-        match self {
-            Neg | Pos | Inv | Bin | Ref | Der | Ina | Inb | Dea | Deb => true,
-            _ => false,
-        }
+        matches!(
+            self,
+            Neg | Pos | Inv | Bin | Ref | Der | Ina | Inb | Dea | Deb
+        )
     }
 
     fn is_right_associative(&self) -> bool {
         use OpVar::*;
-        match self {
-            Neg | Pos | Inv | Bin | Ref | Der | _Asn | _Lpr | _Rpr => true,
-            _ => false,
+        matches!(self, Neg | Pos | Inv | Bin | Ref | Der | _Asn | _Lpr | _Rpr)
+    }
+}
+
+/// Whether `def` is of a function
+fn is_fn(def: &SymbolDef) -> bool {
+    match def {
+        SymbolDef::Var { typ, .. } => matches!(&*typ.borrow(), TypeDef::Function(..)),
+        SymbolDef::Typ { .. } => false,
+    }
+}
+
+/// Keep track of delimiters opened and closed in `delims` after `tok` is
+/// read. A closer not matching the innermost delimiter closes nothing.
+fn close_delims(delims: &mut Vec<(TokenType<'static>, Span)>, tok: &Token<'_>) {
+    match &tok.var {
+        TokenType::LParenthesis | TokenType::LBracket | TokenType::LCurlyBrace => {
+            delims.push((tok.var.clone().into_owned(), tok.span))
+        }
+        close if delims.last().is_some_and(|(open, _)| closes(open, close)) => {
+            delims.pop();
         }
+        _ => (),
     }
 }
+
+/// `tokens`, owning their text
+fn owned(tokens: &[TokenType<'_>]) -> Vec<String> {
+    tokens.iter().map(|tok| tok.to_string()).collect()
+}
+
+fn is_closer(tok: &TokenType) -> bool {
+    matches!(
+        tok,
+        TokenType::RParenthesis | TokenType::RBracket | TokenType::RCurlyBrace
+    )
+}
+
+/// Whether `close` closes delimiter `open`
+fn closes(open: &TokenType, close: &TokenType) -> bool {
+    matches!(
+        (open, close),
+        (TokenType::LParenthesis, TokenType::RParenthesis)
+            | (TokenType::LBracket, TokenType::RBracket)
+            | (TokenType::LCurlyBrace, TokenType::RCurlyBrace)
+    )
+}
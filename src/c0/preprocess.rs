@@ -0,0 +1,272 @@
+//! Conditional compilation, before lexing.
+//!
+//! A line starting with `#if`, `#else` or `#endif` is a directive picking
+//! which lines are compiled by the features defined, as `-D EXT` defines
+//! `EXT`:
+//!
+//! ```c0
+//! #if EXT
+//! a = b = 3;
+//! #else
+//! b = 3;
+//! a = b;
+//! #endif
+//! ```
+//!
+//! `#if` may nest, and `#else` is optional. `#if 0` leaves lines out, and
+//! `#if 1` keeps them. Lines in `/* */` comments are never directives, and
+//! other lines starting with `#` are left to the lexer. Directives and the
+//! lines left out become spaces, keeping their line breaks, so every token
+//! is at the same line, column and index as in the source, and spans need
+//! no mapping.
+//!
+//! What an error can't tell from its line is why the line was compiled. A
+//! [`SourceMap`](crate::c0::preprocess::SourceMap) keeps which `#if` compiled
+//! each line, so errors there note the directives they are under, innermost
+//! first:
+//!
+//! ```text
+//! note: compiled as `#if EXT` holds
+//! ```
+
+use super::err::*;
+use crate::error::{CompileError, Note};
+use crate::prelude::*;
+use alloc::borrow::Cow;
+use core::ops::Range;
+
+/// Names of the directives. Other lines starting with `#` are not directives.
+const DIRECTIVES: [&str; 3] = ["if", "else", "endif"];
+
+/// An `#if` whose `#endif` is not found yet
+struct Open {
+    span: Span,
+    /// What follows `#if`
+    cond: String,
+    /// Whether lines before its `#else` are compiled
+    holds: bool,
+    /// Whether lines around it are compiled
+    outer: bool,
+    in_else: bool,
+    /// Line the lines before its `#else`, or after it, start at
+    from: usize,
+}
+
+impl Open {
+    /// Whether lines here are compiled
+    fn active(&self) -> bool {
+        self.outer && self.holds != self.in_else
+    }
+
+    /// Lines of the branch ending at line `ln`, if they are compiled
+    fn region(&self, ln: usize) -> Option<Region> {
+        self.active().then(|| Region {
+            directive: self.span,
+            cond: self.cond.clone(),
+            in_else: self.in_else,
+            lines: self.from..ln,
+        })
+    }
+}
+
+/// Lines compiled by one branch of an `#if`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Region {
+    /// The `#if`
+    directive: Span,
+    cond: String,
+    /// Whether the lines are after `#else`
+    in_else: bool,
+    lines: Range<usize>,
+}
+
+/// Which `#if` directives compiled each line of a preprocessed source.
+/// Lines stay where they are, so this is all there is to map.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    /// In the order their branches end
+    regions: Vec<Region>,
+}
+
+impl SourceMap {
+    /// Notes on the `#if` directives compiling the line of `span`, innermost
+    /// first, each at its directive
+    pub fn notes(&self, span: Span) -> Vec<Note> {
+        let mut regions: Vec<&Region> = (self.regions.iter())
+            .filter(|r| r.lines.contains(&span.start.ln))
+            .collect();
+        regions.sort_by_key(|r| core::cmp::Reverse(r.lines.start));
+        (regions.into_iter())
+            .map(|r| Note {
+                message: format!(
+                    "compiled as `#if {}` {}",
+                    r.cond,
+                    if r.in_else { "does not hold" } else { "holds" }
+                ),
+                span: Some(r.directive),
+            })
+            .collect()
+    }
+
+    /// `e` with notes on the `#if` directives compiling where it is
+    pub fn annotate(&self, mut e: CompileError) -> CompileError {
+        if let Some(span) = e.span {
+            e.notes.extend(self.notes(span));
+        }
+        e
+    }
+}
+
+/// `src` with the directives and the lines they leave out without `features`
+/// turned into spaces. Borrowed if `src` has no directives.
+pub fn preprocess<'a, S: AsRef<str>>(src: &'a str, features: &[S]) -> ParseResult<Cow<'a, str>> {
+    preprocess_mapped(src, features).map(|(src, _)| src)
+}
+
+/// [`preprocess`], along with which `#if` compiled each line
+pub fn preprocess_mapped<'a, S: AsRef<str>>(
+    src: &'a str,
+    features: &[S],
+) -> ParseResult<(Cow<'a, str>, SourceMap)> {
+    let mut map = SourceMap::default();
+    if !src.lines().any(|l| l.trim_start().starts_with('#')) {
+        return Ok((Cow::Borrowed(src), map));
+    }
+    let defined = |name: &str| features.iter().any(|f| f.as_ref() == name);
+    let mut out = String::with_capacity(src.len());
+    let mut open: Vec<Open> = vec![];
+    let mut start = Pos::zero();
+    let mut in_comment = false;
+    for line in src.split_inclusive('\n') {
+        let active = open.last().is_none_or(Open::active);
+        let text = line.trim_start();
+        let end = line.chars().fold(start, |pos, ch| match ch {
+            '\n' => pos.lf(),
+            _ => pos.inc(),
+        });
+        // * Other lines starting with `#` are left to the lexer, as are
+        // * directives in comments
+        let directive = (text.strip_prefix('#'))
+            .filter(|_| !in_comment)
+            .map(split_word)
+            .filter(|(directive, _)| DIRECTIVES.contains(directive));
+        in_comment = ends_in_comment(line, in_comment);
+        let (directive, rest) = match directive {
+            Some(directive) => directive,
+            None => {
+                match active {
+                    true => out.push_str(line),
+                    false => out.extend(line.chars().map(blank)),
+                }
+                start = end;
+                continue;
+            }
+        };
+
+        let indent = line.chars().count() - text.chars().count();
+        let at = start.map_inc(indent as isize, 0, indent as isize);
+        let text = text.trim_end();
+        let len = text.chars().count() as isize;
+        let span = Span::from(at, at.map_inc(len, 0, len));
+        let rest = rest.trim();
+        // * Anything after a directive but a comment is a mistake
+        let arg = match rest.find("//") {
+            Some(idx) => rest[..idx].trim_end(),
+            None => rest,
+        };
+        match (directive, arg) {
+            ("if", name) => {
+                if name.is_empty() || !split_word(name).1.is_empty() {
+                    return Err(parse_err(ParseErrVariant::BadFeature(name.into()), span));
+                }
+                // * `#if 0` leaves lines out whatever is defined
+                let holds = match name.parse::<u32>() {
+                    Ok(n) => n != 0,
+                    Err(_) => defined(name),
+                };
+                open.push(Open {
+                    span,
+                    cond: name.into(),
+                    holds,
+                    outer: active,
+                    in_else: false,
+                    from: start.ln + 1,
+                });
+            }
+            ("else", "") => match open.last_mut() {
+                Some(o) if !o.in_else => {
+                    map.regions.extend(o.region(start.ln));
+                    o.in_else = true;
+                    o.from = start.ln + 1;
+                }
+                _ => {
+                    let var = ParseErrVariant::UnmatchedDirective("else".into());
+                    return Err(parse_err(var, span));
+                }
+            },
+            ("endif", "") => match open.pop() {
+                Some(o) => map.regions.extend(o.region(start.ln)),
+                None => {
+                    let var = ParseErrVariant::UnmatchedDirective("endif".into());
+                    return Err(parse_err(var, span));
+                }
+            },
+            (directive, _) => {
+                let var = ParseErrVariant::DirectiveArgument(directive.into());
+                return Err(parse_err(var, span));
+            }
+        }
+        out.extend(line.chars().map(blank));
+        start = end;
+    }
+    match open.pop() {
+        Some(o) => Err(parse_err(ParseErrVariant::UnclosedIf, o.span)),
+        None => Ok((Cow::Owned(out), map)),
+    }
+}
+
+/// Whether a `/* */` comment is open at the end of `line`, given whether one
+/// is at its start
+fn ends_in_comment(line: &str, mut in_comment: bool) -> bool {
+    let mut chars = line.chars();
+    while let Some(ch) = chars.next() {
+        let next = chars.as_str();
+        match (in_comment, ch) {
+            (true, '*') if next.starts_with('/') => {
+                chars.next();
+                in_comment = false;
+            }
+            (false, '/') if next.starts_with('/') => break,
+            (false, '/') if next.starts_with('*') => {
+                chars.next();
+                in_comment = true;
+            }
+            // * Comments don't start in literals, which end on their line
+            (false, quote @ ('"' | '\'')) => {
+                while let Some(ch) = chars.next() {
+                    match ch {
+                        '\\' => drop(chars.next()),
+                        ch if ch == quote => break,
+                        _ => (),
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    in_comment
+}
+
+/// The name at the start of `text`, and what follows it
+fn split_word(text: &str) -> (&str, &str) {
+    let end = (text.find(|c: char| !c.is_ascii_alphanumeric() && c != '_')).unwrap_or(text.len());
+    text.split_at(end)
+}
+
+/// `ch` left out: a space, or itself if it breaks lines
+fn blank(ch: char) -> char {
+    match ch {
+        '\n' | '\r' => ch,
+        _ => ' ',
+    }
+}
@@ -0,0 +1,752 @@
+//! Pretty printer turning an AST back into C0 source code.
+//!
+//! The output parses back into the same AST (see [`ast_eq`](crate::c0::ast::ast_eq)).
+//! Parentheses are only emitted where operator precedence requires them.
+//!
+//! Declarations don't have statements of their own in the AST. The parser
+//! stores them in the enclosing scope and leaves a `ManyExpr` (variables) or
+//! `Empty` (functions) statement behind, whose span covers the declaration.
+//! The printer finds the declared symbols by checking which `decl_span`s lie
+//! inside that statement.
+//!
+//! Comments are not in the AST either. [`format`] collects them from the
+//! lexer and puts each one back between the statements it was between,
+//! by position. Comments in the middle of a statement move down to where the
+//! next line break is printed.
+//!
+//! [`format`] also prints literals as they are written, so `0x7fffffff` and
+//! `1.5e3` stay that way. The printer only has their values otherwise.
+//!
+//! `#if` directives and the lines they leave out are printed as they are
+//! written, the same way as comments. They can only be between statements,
+//! since moving them would change what is compiled.
+
+use super::ast::*;
+use super::err::{parse_err, ParseErrVariant, ParseResult};
+use super::lexer::{Lexer, TokenType};
+use super::num;
+use super::parser::Parser;
+use super::preprocess::preprocess;
+use crate::prelude::*;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Float literals with longer fractions are printed in scientific notation.
+const MAX_FRACTION_DIGITS: usize = 20;
+
+/// Where the opening brace of a block goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BraceStyle {
+    /// At the end of the line starting the block, as in `if (x) {`
+    SameLine,
+    /// On a line of its own
+    NextLine,
+}
+
+/// Layout of printed code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrettyConfig {
+    /// Spaces per level of indentation
+    pub indent_width: usize,
+    pub brace_style: BraceStyle,
+}
+
+impl Default for PrettyConfig {
+    fn default() -> PrettyConfig {
+        PrettyConfig {
+            indent_width: 4,
+            brace_style: BraceStyle::SameLine,
+        }
+    }
+}
+
+/// A comment in the source code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comment {
+    /// The comment as written, including `//` or `/* */`
+    pub text: String,
+    pub span: Span,
+    /// Whether these are whole lines, printed as written rather than
+    /// indented: directives and the lines they leave out
+    pub verbatim: bool,
+}
+
+/// Print `prog` as C0 source code.
+pub fn pretty_print(prog: &Program) -> String {
+    pretty_print_with(prog, &[], &PrettyConfig::default())
+}
+
+/// Print `prog` as C0 source code laid out as `config` says, with
+/// `comments` from its source put back in place.
+pub fn pretty_print_with(prog: &Program, comments: &[Comment], config: &PrettyConfig) -> String {
+    print_program(prog, comments, &BTreeMap::new(), config).out
+}
+
+/// Literals as written, by the index their token ends at, with the index it
+/// starts at
+type Spellings = BTreeMap<usize, (usize, String)>;
+
+fn print_program<'a>(
+    prog: &Program,
+    comments: &'a [Comment],
+    spellings: &'a Spellings,
+    config: &'a PrettyConfig,
+) -> PrettyPrinter<'a> {
+    let mut printer = PrettyPrinter {
+        out: String::new(),
+        indent: 0,
+        config,
+        comments,
+        spellings,
+        last_line: None,
+        blank_next: false,
+        misplaced: None,
+    };
+    for stmt in &prog.blk.stmts {
+        printer.stmt_line(stmt, prog.blk.scope.cp());
+        // * Top level items are always separated by a blank line
+        printer.blank_next = true;
+    }
+    printer.comments_before(Pos::new(usize::MAX, 0, usize::MAX));
+    if !printer.out.is_empty() {
+        printer.out.push('\n');
+    }
+    printer
+}
+
+/// Format C0 source code, keeping its comments. Blank lines between
+/// statements are kept, but never more than one in a row.
+pub fn format(src: &str, config: &PrettyConfig) -> ParseResult<String> {
+    format_with_features(src, &[] as &[&str], config)
+}
+
+/// [`format`] the lines `#if` directives compile with `features`, keeping
+/// the directives and the lines they leave out as they are
+pub fn format_with_features<S: AsRef<str>>(
+    src: &str,
+    features: &[S],
+    config: &PrettyConfig,
+) -> ParseResult<String> {
+    let compiled = preprocess(src, features)?;
+    let chars: Vec<char> = compiled.chars().collect();
+    let mut lexer = Lexer::new(&compiled);
+    let mut tokens = vec![];
+    let mut comments = vec![];
+    let mut spellings = Spellings::new();
+    while let Some(tok) = lexer.get_next_token() {
+        let (start, end) = (tok.span.start.index, tok.span.end.index);
+        let text = || chars[start..end].iter().collect::<String>();
+        match tok.var {
+            TokenType::Comment(_) | TokenType::DocComment(_) => {
+                comments.push(Comment {
+                    text: text().trim_end().into(),
+                    span: tok.span,
+                    verbatim: false,
+                });
+            }
+            TokenType::Literal(_) => {
+                spellings.insert(end, (start, text()));
+                tokens.push(tok);
+            }
+            _ => tokens.push(tok),
+        }
+    }
+    let prog = Parser::new(tokens.into_iter()).parse()?;
+    comments.extend(left_out(src, &compiled));
+    comments.sort_by_key(|c| c.span.start.index);
+    let printer = print_program(&prog, &comments, &spellings, config);
+    match printer.misplaced {
+        Some(span) => Err(parse_err(ParseErrVariant::DirectiveInStatement, span)),
+        None => Ok(printer.out),
+    }
+}
+
+/// Runs of lines of `src` that are blank in `compiled` but not as written:
+/// directives and the lines they leave out
+fn left_out(src: &str, compiled: &str) -> Vec<Comment> {
+    let mut runs: Vec<Comment> = vec![];
+    let mut start = Pos::zero();
+    let lines = src
+        .split_inclusive('\n')
+        .zip(compiled.split_inclusive('\n'));
+    for (ln, (line, kept)) in lines.enumerate() {
+        let len = line.trim_end_matches(['\n', '\r']).chars().count();
+        let end = Pos::new(ln, len, start.index + len);
+        if kept.trim().is_empty() && !line.trim().is_empty() {
+            match runs.last_mut() {
+                Some(run) if run.span.end.ln + 1 == ln => {
+                    run.text.push('\n');
+                    run.text.push_str(line.trim_end());
+                    run.span.end = end;
+                }
+                _ => runs.push(Comment {
+                    text: line.trim_end().into(),
+                    span: Span::from(start, end),
+                    verbatim: true,
+                }),
+            }
+        }
+        start = Pos::new(ln + 1, 0, start.index + line.chars().count());
+    }
+    runs
+}
+
+struct PrettyPrinter<'a> {
+    out: String,
+    indent: usize,
+    config: &'a PrettyConfig,
+    /// Comments not printed yet, in source order
+    comments: &'a [Comment],
+    spellings: &'a Spellings,
+    /// Source line of the end of the last statement or comment printed in
+    /// the current block
+    last_line: Option<usize>,
+    /// Put a blank line before whatever comes next
+    blank_next: bool,
+    /// Where verbatim lines were found inside a statement, and moved
+    misplaced: Option<Span>,
+}
+
+/// How tightly an expression binds, from loosest to tightest. Used to decide
+/// where parentheses are needed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Level {
+    /// A binary operator with the given priority
+    Binary(isize),
+    /// `-x`, `*x`, `++x` ...
+    Prefix,
+    /// `x++`, `x[i]`
+    Postfix,
+    /// Identifiers, literals, calls, conversions and parenthesized expressions
+    Item,
+}
+
+/// Priority of binary operators. Mirrors `Operator::priority` in the parser.
+fn bin_priority(op: OpVar) -> isize {
+    use OpVar::*;
+    match op {
+        _Dum => 0,
+        _Com => 1,
+        _Asn | _Csn => 2,
+        Or => 8,
+        And => 9,
+        Bor => 10,
+        Xor => 11,
+        Ban => 12,
+        Eq | Neq => 13,
+        Gt | Lt | Gte | Lte => 14,
+        Add | Sub => 20,
+        Mul | Div => 30,
+        _ => 40,
+    }
+}
+
+fn is_right_associative(op: OpVar) -> bool {
+    matches!(op, OpVar::_Asn | OpVar::_Csn)
+}
+
+pub(crate) fn op_str(op: OpVar) -> &'static str {
+    use OpVar::*;
+    match op {
+        Add | Pos => "+",
+        Sub | Neg => "-",
+        Mul | Der => "*",
+        Div => "/",
+        And => "&&",
+        Or => "||",
+        Xor => "^",
+        Ban | Ref => "&",
+        Bor => "|",
+        Gt => ">",
+        Lt => "<",
+        Eq => "==",
+        Gte => ">=",
+        Lte => "<=",
+        Neq => "!=",
+        Inv => "!",
+        Bin => "~",
+        Ina | Inb => "++",
+        Dea | Deb => "--",
+        _Com => ",",
+        _Asn | _Csn => "=",
+        _Lpr => "(",
+        _Rpr => ")",
+        _Dum => "",
+    }
+}
+
+fn level(expr: &Expr) -> Level {
+    match &expr.var {
+        ExprVariant::BinaryOp(b) => Level::Binary(bin_priority(b.op)),
+        ExprVariant::UnaryOp(u) => match u.op {
+            OpVar::Ina | OpVar::Dea => Level::Postfix,
+            _ => Level::Prefix,
+        },
+        ExprVariant::ArrayChild(..) | ExprVariant::StructChild(..) => Level::Postfix,
+        // * Printed with a `-` in front, like a negation
+        _ if is_negative_literal(expr) => Level::Prefix,
+        _ => Level::Item,
+    }
+}
+
+/// Does the printed form of `expr` start with a prefix `+`, `-` or `&`?
+/// Expression statements cannot start with those.
+fn starts_with_bad_prefix(expr: &Expr) -> bool {
+    match &expr.var {
+        ExprVariant::BinaryOp(b) => starts_with_bad_prefix(&b.lhs.borrow()),
+        ExprVariant::UnaryOp(u) => match u.op {
+            OpVar::Neg | OpVar::Pos | OpVar::Ref | OpVar::Inv | OpVar::Bin => true,
+            OpVar::Ina | OpVar::Dea => starts_with_bad_prefix(&u.val.borrow()),
+            _ => false,
+        },
+        ExprVariant::ArrayChild(a) => starts_with_bad_prefix(&a.val.borrow()),
+        ExprVariant::StructChild(s) => starts_with_bad_prefix(&s.val.borrow()),
+        _ => is_negative_literal(expr),
+    }
+}
+
+fn is_negative_literal(expr: &Expr) -> bool {
+    match &expr.var {
+        ExprVariant::Literal(Literal::Integer { val }) => *val < num::Int::from(0),
+        _ => false,
+    }
+}
+
+pub(super) fn type_str(typ: &TypeDef) -> String {
+    match typ {
+        TypeDef::NamedType(name) => name.clone(),
+        TypeDef::Ref(r) => format!("&{}", type_str(&r.target.borrow())),
+        TypeDef::Array(a) => format!("[{}]", type_str(&a.target.borrow())),
+        TypeDef::Unit => "void".into(),
+        TypeDef::Unknown => "auto".into(),
+        other => format!("{:?}", other),
+    }
+}
+
+fn escape_char(c: char, quote: char, out: &mut String) {
+    match c {
+        '\n' => out.push_str("\\n"),
+        '\r' => out.push_str("\\r"),
+        '\t' => out.push_str("\\t"),
+        '\\' => out.push_str("\\\\"),
+        c if c == quote => {
+            out.push('\\');
+            out.push(c)
+        }
+        c if c.is_control() => write!(out, "\\u{{{:x}}}", c as u32).unwrap(),
+        c => out.push(c),
+    }
+}
+
+/// Print a float literal exactly. Literals are parsed as `n * 10 ^ exp`, so
+/// the denominator always divides some power of ten.
+fn float_str(val: &num::Rational) -> String {
+    let (numer, denom) = num::into_parts(val);
+    let mut exp = 0;
+    let mut pow = num::Int::from(1);
+    while (numer.clone() * pow.clone()) % denom.clone() != num::Int::from(0) {
+        exp += 1;
+        pow *= num::Int::from(10);
+    }
+    let mantissa = numer * pow / denom;
+    if exp == 0 {
+        format!("{}.0", mantissa)
+    } else if exp <= MAX_FRACTION_DIGITS {
+        let digits = format!("{:0>width$}", mantissa.to_string(), width = exp + 1);
+        let (int, frac) = digits.split_at(digits.len() - exp);
+        format!("{}.{}", int, frac)
+    } else {
+        format!("{}e-{}", mantissa, exp)
+    }
+}
+
+impl<'a> PrettyPrinter<'a> {
+    fn new_line(&mut self) {
+        self.out.push('\n');
+        for _ in 0..self.indent * self.config.indent_width {
+            self.out.push(' ');
+        }
+    }
+
+    /// Start a new line for something found on line `src_line` of the
+    /// source, keeping a blank line before it if there was one
+    fn line_break(&mut self, src_line: usize) {
+        let blank = self.blank_next || matches!(self.last_line, Some(last) if src_line > last + 1);
+        self.blank_next = false;
+        if self.out.is_empty() {
+            return;
+        }
+        if blank {
+            self.out.push('\n');
+        }
+        self.new_line();
+    }
+
+    /// Print the comments before `pos` on lines of their own
+    fn comments_before(&mut self, pos: Pos) {
+        while let Some((comment, rest)) = self.comments.split_first() {
+            if comment.span.start >= pos {
+                break;
+            }
+            if comment.verbatim {
+                // * Directives stay next to the items they enclose
+                let blank_next = core::mem::take(&mut self.blank_next);
+                self.line_break(comment.span.start.ln);
+                self.blank_next = blank_next;
+                self.out.truncate(self.out.trim_end_matches(' ').len());
+            } else {
+                self.line_break(comment.span.start.ln);
+            }
+            self.out.push_str(&comment.text);
+            self.last_line = Some(comment.span.end.ln);
+            self.comments = rest;
+        }
+    }
+
+    /// Print a comment following `end` on the same line
+    fn trailing_comment(&mut self, end: Pos) {
+        if let Some((comment, rest)) = self.comments.split_first() {
+            if comment.span.start.ln == end.ln && comment.span.start >= end && !comment.verbatim {
+                self.out.push(' ');
+                self.out.push_str(&comment.text);
+                self.last_line = Some(comment.span.end.ln);
+                self.comments = rest;
+            }
+        }
+    }
+
+    /// Print a statement of a block on its own line, with its comments
+    fn stmt_line(&mut self, stmt: &Stmt, scope: Ptr<Scope>) {
+        self.comments_before(stmt.span.start);
+        self.line_break(stmt.span.start.ln);
+        self.stmt(stmt, scope);
+        self.last_line = Some(stmt.span.end.ln);
+        self.trailing_comment(stmt.span.end);
+        if let Some(comment) = self.comments.first() {
+            if comment.verbatim && comment.span.start < stmt.span.end {
+                self.misplaced.get_or_insert(comment.span);
+            }
+        }
+    }
+
+    /// Separate an opening brace from what comes before it
+    fn before_brace(&mut self) {
+        match self.config.brace_style {
+            BraceStyle::SameLine => self.out.push(' '),
+            BraceStyle::NextLine => self.new_line(),
+        }
+    }
+
+    /// Print a block of statements in braces. Declarations are looked up in
+    /// `scope`. The block ends at `end` in the source.
+    fn braced(&mut self, stmts: &[Stmt], scope: Ptr<Scope>, end: Pos) {
+        self.out.push('{');
+        self.indent += 1;
+        self.last_line = None;
+        for stmt in stmts {
+            self.stmt_line(stmt, scope.cp());
+        }
+        self.comments_before(end);
+        self.indent -= 1;
+        self.new_line();
+        self.out.push('}');
+    }
+
+    fn body(&mut self, stmt: &Stmt, scope: Ptr<Scope>) {
+        match &stmt.var {
+            StmtVariant::Block(..) => {
+                self.before_brace();
+                self.stmt(stmt, scope);
+            }
+            _ => {
+                self.indent += 1;
+                self.new_line();
+                self.stmt(stmt, scope);
+                self.indent -= 1;
+            }
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt, scope: Ptr<Scope>) {
+        match &stmt.var {
+            StmtVariant::If(i) => {
+                self.out.push_str("if (");
+                self.expr(&i.cond.borrow());
+                self.out.push(')');
+                self.body(&i.if_block.borrow(), scope.cp());
+                let mut last = i.if_block.cp();
+                for (cond, block) in &i.else_ifs {
+                    self.after_body(&last.borrow());
+                    self.out.push_str("else if (");
+                    self.expr(&cond.borrow());
+                    self.out.push(')');
+                    self.body(&block.borrow(), scope.cp());
+                    last = block.cp();
+                }
+                if let Some(else_block) = &i.else_block {
+                    self.after_body(&last.borrow());
+                    self.out.push_str("else");
+                    self.body(&else_block.borrow(), scope);
+                }
+            }
+            StmtVariant::While(w) => {
+                if let Some(label) = &w.label {
+                    write!(self.out, "{}: ", label.name).unwrap();
+                }
+                self.out.push_str("while (");
+                self.expr(&w.cond.borrow());
+                self.out.push(')');
+                self.body(&w.block.borrow(), scope);
+            }
+            StmtVariant::Block(b) => self.braced(&b.stmts, b.scope.cp(), stmt.span.end),
+            StmtVariant::Expr(e) => {
+                let e = e.borrow();
+                if starts_with_bad_prefix(&e) {
+                    self.paren_expr(&e);
+                } else {
+                    self.expr(&e);
+                }
+                self.out.push(';');
+            }
+            StmtVariant::Print(exprs) => {
+                self.out.push_str("print(");
+                self.expr_list(exprs);
+                self.out.push_str(");");
+            }
+            StmtVariant::Scan(ident, _) => write!(self.out, "scan({});", ident.name).unwrap(),
+            StmtVariant::ManyExpr(inits) => self.var_decl(stmt.span, inits, &scope),
+            StmtVariant::Return(None) => self.out.push_str("return;"),
+            StmtVariant::Return(Some(e)) => {
+                self.out.push_str("return ");
+                self.expr(&e.borrow());
+                self.out.push(';');
+            }
+            StmtVariant::Break(None) => self.out.push_str("break;"),
+            StmtVariant::Break(Some(label)) => write!(self.out, "break {};", label.name).unwrap(),
+            StmtVariant::Empty => self.fn_decl(stmt.span, &scope),
+        }
+    }
+
+    /// Separate a body from the following `else`
+    fn after_body(&mut self, body: &Stmt) {
+        match (&body.var, self.config.brace_style) {
+            (StmtVariant::Block(..), BraceStyle::SameLine) => self.out.push(' '),
+            _ => self.new_line(),
+        }
+    }
+
+    fn var_decl(&mut self, span: Span, inits: &[Ptr<Expr>], scope: &Ptr<Scope>) {
+        let decls = scope.borrow().defs_in(span);
+        if let Some((_, first)) = decls.first() {
+            if let SymbolDef::Var { typ, is_const, .. } = &*first.borrow() {
+                if *is_const {
+                    self.out.push_str("const ");
+                }
+                self.out.push_str(&type_str(&typ.borrow()));
+                self.out.push(' ');
+            }
+        }
+        for (idx, (name, _)) in decls.iter().enumerate() {
+            if idx != 0 {
+                self.out.push_str(", ");
+            }
+            self.out.push_str(name);
+            let init = inits.iter().find(|init| match &init.borrow().var {
+                ExprVariant::BinaryOp(b) => match &b.lhs.borrow().var {
+                    ExprVariant::Ident(i) => &i.name == name,
+                    _ => false,
+                },
+                _ => false,
+            });
+            if let Some(init) = init {
+                if let ExprVariant::BinaryOp(b) = &init.borrow().var {
+                    self.out.push_str(" = ");
+                    self.comma_safe_expr(&b.rhs.borrow());
+                }
+            }
+        }
+        self.out.push(';');
+    }
+
+    fn fn_decl(&mut self, span: Span, scope: &Ptr<Scope>) {
+        for (name, def) in scope.borrow().defs_in(span) {
+            let def = def.borrow();
+            let typ = match &*def {
+                SymbolDef::Var { typ, .. } => typ.borrow(),
+                _ => continue,
+            };
+            let func = match &*typ {
+                TypeDef::Function(f) => f,
+                _ => continue,
+            };
+            let body = match &func.body {
+                Some(body) => body,
+                None => continue,
+            };
+
+            // * Parameters are the first symbols of the function's scope
+            let param_names: Vec<String> = body
+                .scope
+                .borrow()
+                .defs
+                .keys()
+                .take(func.params.len())
+                .map(|name| name.to_string())
+                .collect();
+
+            write!(
+                self.out,
+                "{} {}(",
+                type_str(&func.return_type.borrow()),
+                name
+            )
+            .unwrap();
+            for (idx, (typ, name)) in func.params.iter().zip(&param_names).enumerate() {
+                if idx != 0 {
+                    self.out.push_str(", ");
+                }
+                write!(self.out, "{} {}", type_str(&typ.borrow()), name).unwrap();
+            }
+            self.out.push(')');
+            self.before_brace();
+            self.braced(&body.stmts, body.scope.cp(), span.end);
+        }
+    }
+
+    fn expr_list(&mut self, exprs: &[Ptr<Expr>]) {
+        for (idx, expr) in exprs.iter().enumerate() {
+            if idx != 0 {
+                self.out.push_str(", ");
+            }
+            self.comma_safe_expr(&expr.borrow());
+        }
+    }
+
+    /// Print an expression in a comma-separated context
+    fn comma_safe_expr(&mut self, expr: &Expr) {
+        if level(expr) <= Level::Binary(bin_priority(OpVar::_Com)) {
+            self.paren_expr(expr)
+        } else {
+            self.expr(expr)
+        }
+    }
+
+    fn paren_expr(&mut self, expr: &Expr) {
+        self.out.push('(');
+        self.expr(expr);
+        self.out.push(')');
+    }
+
+    /// Print `expr`, wrapping it in parentheses if it binds looser than `min`
+    fn expr_at_least(&mut self, expr: &Expr, min: Level) {
+        if level(expr) < min {
+            self.paren_expr(expr)
+        } else {
+            self.expr(expr)
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        maybe_grow(|| match &expr.var {
+            ExprVariant::Ident(i) => self.out.push_str(&i.name),
+            ExprVariant::Literal(lit) => match self.spelling(expr) {
+                Some(text) => self.out.push_str(&text),
+                None => self.literal(lit),
+            },
+            ExprVariant::TypeConversion(conv) => {
+                write!(self.out, "({})", type_str(&conv.to.borrow())).unwrap();
+                self.expr_at_least(&conv.expr.borrow(), Level::Item);
+            }
+            ExprVariant::UnaryOp(u) => match u.op {
+                OpVar::Ina | OpVar::Dea => {
+                    self.expr_at_least(&u.val.borrow(), Level::Postfix);
+                    self.out.push_str(op_str(u.op));
+                }
+                _ => {
+                    self.out.push_str(op_str(u.op));
+                    // * `- -x` must not become `--x`, so nested prefix
+                    // * operators are parenthesized.
+                    self.expr_at_least(&u.val.borrow(), Level::Postfix);
+                }
+            },
+            ExprVariant::BinaryOp(b) => {
+                let prio = bin_priority(b.op);
+                let right = is_right_associative(b.op);
+                let lhs = b.lhs.borrow();
+                let rhs = b.rhs.borrow();
+                if level(&lhs) < Level::Binary(prio)
+                    || (right && level(&lhs) == Level::Binary(prio))
+                {
+                    self.paren_expr(&lhs);
+                } else {
+                    self.expr(&lhs);
+                }
+                match b.op {
+                    OpVar::_Com => self.out.push_str(", "),
+                    op => write!(self.out, " {} ", op_str(op)).unwrap(),
+                }
+                if level(&rhs) < Level::Binary(prio)
+                    || (!right && level(&rhs) == Level::Binary(prio))
+                {
+                    self.paren_expr(&rhs);
+                } else {
+                    self.expr(&rhs);
+                }
+            }
+            ExprVariant::FunctionCall(call) => {
+                write!(self.out, "{}(", call.func).unwrap();
+                self.expr_list(&call.params);
+                self.out.push(')');
+            }
+            ExprVariant::StructChild(s) => {
+                self.expr_at_least(&s.val.borrow(), Level::Postfix);
+                write!(self.out, ".{}", s.idx).unwrap();
+            }
+            ExprVariant::ArrayChild(a) => {
+                self.expr_at_least(&a.val.borrow(), Level::Postfix);
+                self.out.push('[');
+                self.expr(&a.idx.borrow());
+                self.out.push(']');
+            }
+        })
+    }
+
+    /// `expr`, a literal, as written. Negative integers are written as `-`
+    /// and the literal it is folded into.
+    fn spelling(&self, expr: &Expr) -> Option<String> {
+        let (start, text) = self.spellings.get(&expr.span.end.index)?;
+        if *start == expr.span.start.index {
+            Some(text.clone())
+        } else if is_negative_literal(expr) {
+            Some(format!("-{}", text))
+        } else {
+            None
+        }
+    }
+
+    fn literal(&mut self, lit: &Literal) {
+        write_literal(lit, &mut self.out);
+    }
+}
+
+/// Write `lit` the way C0 writes it, in its plainest spelling
+pub(super) fn write_literal(lit: &Literal, out: &mut String) {
+    match lit {
+        Literal::Char { val } => {
+            out.push('\'');
+            escape_char(*val, '\'', out);
+            out.push('\'');
+        }
+        Literal::Integer { val } => write!(out, "{}", val).unwrap(),
+        Literal::Float { val } => out.push_str(&float_str(val)),
+        Literal::Boolean { val } => write!(out, "{}", val).unwrap(),
+        Literal::String { val } => {
+            out.push('"');
+            for c in val.chars() {
+                escape_char(c, '"', out);
+            }
+            out.push('"');
+        }
+        Literal::Struct { .. } => write!(out, "{}", lit).unwrap(),
+    }
+}
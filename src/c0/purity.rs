@@ -0,0 +1,339 @@
+//! Which functions of a program are pure.
+//!
+//! A function is pure if calling it does nothing but compute its result: it
+//! does no input or output, writes no global variables, takes and returns no
+//! references through which memory of its caller could change, and calls
+//! only pure functions. It may read globals, so two calls with the same
+//! arguments give the same result as long as nothing is written in between.
+//! Functions without a body are never pure, as nothing is known of them.
+//!
+//! This is less than [`consteval`](crate::consteval) asks of functions it
+//! runs, which may not read variables either.
+
+use super::ast::*;
+use crate::prelude::*;
+use alloc::collections::BTreeMap;
+use core::fmt;
+
+/// What keeps a function from being pure, the first thing found
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Impurity {
+    /// Reads input or writes output, at this statement
+    Io(Span),
+    /// Writes this global variable, at this expression
+    WritesGlobal(String, Span),
+    /// Writes through a reference, at this expression
+    WritesThrough(Span),
+    /// Takes or returns a reference or array
+    Reference,
+    /// Has no body
+    Extern,
+    /// Calls this function, which is not pure, at this expression
+    Calls(String, Span),
+}
+
+impl fmt::Display for Impurity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Impurity::Io(_) => write!(f, "does input or output"),
+            Impurity::WritesGlobal(name, _) => write!(f, "writes global variable `{}`", name),
+            Impurity::WritesThrough(_) => write!(f, "writes through a reference"),
+            Impurity::Reference => write!(f, "takes or returns a reference"),
+            Impurity::Extern => write!(f, "has no body"),
+            Impurity::Calls(name, _) => write!(f, "calls `{}`, which is not pure", name),
+        }
+    }
+}
+
+/// Which functions of a program are pure, and why the others are not
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Purity {
+    /// Each function, with what keeps it from being pure if anything does
+    pub fns: BTreeMap<String, Option<Impurity>>,
+}
+
+impl Purity {
+    pub fn new(prog: &Program) -> Purity {
+        let scope = prog.blk.scope.borrow();
+        let mut fns = BTreeMap::new();
+        let mut calls = BTreeMap::new();
+        for (name, def) in scope.defs.iter() {
+            let typ = match &*def.borrow() {
+                SymbolDef::Var { typ, .. } => typ.cp(),
+                _ => continue,
+            };
+            let typ = typ.borrow();
+            let func = match &*typ {
+                TypeDef::Function(func) => func,
+                _ => continue,
+            };
+            let mut walk = Walk {
+                impurity: None,
+                calls: vec![],
+            };
+            let is_ref = |t: &Ptr<TypeDef>| {
+                matches!(
+                    &*resolve(t, &scope).borrow(),
+                    TypeDef::Ref(_) | TypeDef::Array(_)
+                )
+            };
+            match &func.body {
+                None => walk.impurity = Some(Impurity::Extern),
+                Some(_) if func.params.iter().any(is_ref) || is_ref(&func.return_type) => {
+                    walk.impurity = Some(Impurity::Reference)
+                }
+                Some(body) => walk.block(body),
+            }
+            fns.insert(name.to_string(), walk.impurity);
+            calls.insert(name.to_string(), walk.calls);
+        }
+
+        // * A function calling one that is not pure is not pure either, which
+        // * spreads to its callers in turn
+        loop {
+            let mut changed = false;
+            for (name, calls) in &calls {
+                if fns[name].is_some() {
+                    continue;
+                }
+                let impure = calls
+                    .iter()
+                    .find(|(callee, _)| fns.get(callee).is_none_or(|i| i.is_some()));
+                if let Some((callee, span)) = impure {
+                    fns.insert(name.clone(), Some(Impurity::Calls(callee.clone(), *span)));
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+        Purity { fns }
+    }
+
+    /// Whether function `name` is pure
+    pub fn is_pure(&self, name: &str) -> bool {
+        self.fns.get(name).is_some_and(|i| i.is_none())
+    }
+
+    /// Whether evaluating `expr` does nothing but compute its value: it
+    /// assigns nothing and calls only pure functions
+    pub fn is_pure_expr(&self, expr: &Expr) -> bool {
+        maybe_grow(|| match &expr.var {
+            ExprVariant::Ident(_) | ExprVariant::Literal(_) => true,
+            ExprVariant::TypeConversion(t) => self.is_pure_expr(&t.expr.borrow()),
+            ExprVariant::UnaryOp(u) => !is_write(u.op) && self.is_pure_expr(&u.val.borrow()),
+            ExprVariant::BinaryOp(b) => {
+                !is_write(b.op)
+                    && self.is_pure_expr(&b.lhs.borrow())
+                    && self.is_pure_expr(&b.rhs.borrow())
+            }
+            ExprVariant::FunctionCall(f) => {
+                self.is_pure(&f.func) && f.params.iter().all(|p| self.is_pure_expr(&p.borrow()))
+            }
+            ExprVariant::StructChild(_) | ExprVariant::ArrayChild(_) => false,
+        })
+    }
+}
+
+/// The body of one function, looked through for what it does
+struct Walk {
+    impurity: Option<Impurity>,
+    /// Functions it calls, and where
+    calls: Vec<(String, Span)>,
+}
+
+impl Walk {
+    fn found(&mut self, impurity: Impurity) {
+        if self.impurity.is_none() {
+            self.impurity = Some(impurity);
+        }
+    }
+
+    /// Note a write to `target` at `span`, which is impure if it is a global
+    /// or what a reference refers to, which may be one
+    fn write(&mut self, target: &Ptr<Expr>, span: Span, scope: &Ptr<Scope>) {
+        match &target.borrow().var {
+            ExprVariant::Ident(i) => {
+                let is_global =
+                    (scope.borrow().find_def_depth(i.name)).is_some_and(|(_, depth)| depth == 0);
+                if is_global {
+                    self.found(Impurity::WritesGlobal(i.name.to_string(), span));
+                }
+            }
+            ExprVariant::UnaryOp(u) if u.op == OpVar::Der => {
+                self.found(Impurity::WritesThrough(span))
+            }
+            _ => (),
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        for stmt in &block.stmts {
+            self.stmt(stmt, &block.scope);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt, scope: &Ptr<Scope>) {
+        maybe_grow(|| match &stmt.var {
+            StmtVariant::If(i) => {
+                self.expr(&i.cond, scope);
+                self.stmt(&i.if_block.borrow(), scope);
+                for (cond, blk) in &i.else_ifs {
+                    self.expr(cond, scope);
+                    self.stmt(&blk.borrow(), scope);
+                }
+                if let Some(blk) = &i.else_block {
+                    self.stmt(&blk.borrow(), scope);
+                }
+            }
+            StmtVariant::While(w) => {
+                self.expr(&w.cond, scope);
+                self.stmt(&w.block.borrow(), scope);
+            }
+            StmtVariant::Block(b) => self.block(b),
+            StmtVariant::Expr(e) | StmtVariant::Return(Some(e)) => self.expr(e, scope),
+            StmtVariant::ManyExpr(es) => es.iter().for_each(|e| self.expr(e, scope)),
+            StmtVariant::Print(_) | StmtVariant::Scan(..) => self.found(Impurity::Io(stmt.span)),
+            StmtVariant::Return(None) | StmtVariant::Break(_) | StmtVariant::Empty => (),
+        })
+    }
+
+    fn expr(&mut self, expr: &Ptr<Expr>, scope: &Ptr<Scope>) {
+        maybe_grow(|| {
+            let expr = expr.borrow();
+            match &expr.var {
+                ExprVariant::Ident(_) | ExprVariant::Literal(_) => (),
+                ExprVariant::TypeConversion(t) => self.expr(&t.expr, scope),
+                ExprVariant::UnaryOp(u) => {
+                    if is_write(u.op) {
+                        self.write(&u.val, expr.span, scope);
+                    }
+                    self.expr(&u.val, scope);
+                }
+                ExprVariant::BinaryOp(b) => {
+                    if is_write(b.op) {
+                        self.write(&b.lhs, expr.span, scope);
+                    }
+                    self.expr(&b.lhs, scope);
+                    self.expr(&b.rhs, scope);
+                }
+                ExprVariant::FunctionCall(f) => {
+                    self.calls.push((f.func.to_string(), expr.span));
+                    f.params.iter().for_each(|p| self.expr(p, scope));
+                }
+                ExprVariant::StructChild(s) => self.expr(&s.val, scope),
+                ExprVariant::ArrayChild(a) => {
+                    self.expr(&a.val, scope);
+                    self.expr(&a.idx, scope);
+                }
+            }
+        })
+    }
+}
+
+/// `typ` with type names followed to what they name
+fn resolve(typ: &Ptr<TypeDef>, scope: &Scope) -> Ptr<TypeDef> {
+    let mut typ = typ.cp();
+    loop {
+        let named = match &*typ.borrow() {
+            TypeDef::NamedType(name) => scope.find_def(name).and_then(|d| d.borrow().get_typ()),
+            _ => None,
+        };
+        match named {
+            Some(named) => typ = named,
+            None => return typ,
+        }
+    }
+}
+
+/// Whether `op` writes to its operand
+fn is_write(op: OpVar) -> bool {
+    use OpVar::*;
+    matches!(op, _Asn | _Csn | Ina | Inb | Dea | Deb)
+}
+
+/// Calls to pure functions in `prog` whose result is thrown away, which do
+/// nothing at all, with the name of the function called. Calls to functions
+/// returning `void` are left out, as they have no result to keep.
+pub fn unused_results(prog: &Program, purity: &Purity) -> Vec<(String, Span)> {
+    let mut found = vec![];
+    unused_in_block(&prog.blk, prog, purity, &mut found);
+    for def in prog.blk.scope.borrow().defs.values() {
+        let typ = match &*def.borrow() {
+            SymbolDef::Var { typ, .. } => typ.cp(),
+            _ => continue,
+        };
+        let typ = typ.borrow();
+        if let TypeDef::Function(FunctionType {
+            body: Some(body), ..
+        }) = &*typ
+        {
+            unused_in_block(body, prog, purity, &mut found);
+        }
+    }
+    found.sort_by_key(|(_, span)| span.start);
+    found
+}
+
+fn unused_in_block(
+    block: &Block,
+    prog: &Program,
+    purity: &Purity,
+    found: &mut Vec<(String, Span)>,
+) {
+    for stmt in &block.stmts {
+        unused_in_stmt(stmt, prog, purity, found);
+    }
+}
+
+fn unused_in_stmt(stmt: &Stmt, prog: &Program, purity: &Purity, found: &mut Vec<(String, Span)>) {
+    maybe_grow(|| match &stmt.var {
+        StmtVariant::If(i) => {
+            unused_in_stmt(&i.if_block.borrow(), prog, purity, found);
+            for (_, blk) in &i.else_ifs {
+                unused_in_stmt(&blk.borrow(), prog, purity, found);
+            }
+            if let Some(blk) = &i.else_block {
+                unused_in_stmt(&blk.borrow(), prog, purity, found);
+            }
+        }
+        StmtVariant::While(w) => unused_in_stmt(&w.block.borrow(), prog, purity, found),
+        StmtVariant::Block(b) => unused_in_block(b, prog, purity, found),
+        StmtVariant::Expr(e) => unused_in_expr(&e.borrow(), prog, purity, found),
+        StmtVariant::ManyExpr(es) => {
+            for e in es {
+                unused_in_expr(&e.borrow(), prog, purity, found);
+            }
+        }
+        _ => (),
+    })
+}
+
+/// Look at `expr`, whose value is thrown away
+fn unused_in_expr(expr: &Expr, prog: &Program, purity: &Purity, found: &mut Vec<(String, Span)>) {
+    match &expr.var {
+        // * Of `a, b` the value of `a` is thrown away too
+        ExprVariant::BinaryOp(b) if b.op == OpVar::_Com => {
+            unused_in_expr(&b.lhs.borrow(), prog, purity, found);
+            unused_in_expr(&b.rhs.borrow(), prog, purity, found);
+        }
+        ExprVariant::FunctionCall(f) if purity.is_pure(&f.func) => {
+            let scope = prog.blk.scope.borrow();
+            let def = scope.find_def_self(f.func);
+            let returns_value = def.is_some_and(|def| match &*def.borrow() {
+                SymbolDef::Var { typ, .. } => match &*typ.borrow() {
+                    TypeDef::Function(func) => {
+                        !resolve(&func.return_type, &scope).borrow().is_unit()
+                    }
+                    _ => false,
+                },
+                _ => false,
+            });
+            if returns_value {
+                found.push((f.func.to_string(), expr.span));
+            }
+        }
+        _ => (),
+    }
+}
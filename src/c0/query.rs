@@ -0,0 +1,663 @@
+//! Finding nodes of the syntax tree with selectors, as CSS finds elements,
+//! for lints and tools that would otherwise walk the tree themselves.
+
+use super::ast::*;
+use super::pretty::{op_str, type_str};
+use crate::prelude::*;
+use core::fmt;
+
+/// A kind of node of the syntax tree
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, PartialOrd, Ord)]
+pub enum NodeKind {
+    Function,
+    Decl,
+    If,
+    While,
+    Block,
+    Print,
+    Scan,
+    Return,
+    Break,
+    Ident,
+    Literal,
+    Conversion,
+    UnaryOp,
+    BinaryOp,
+    Assign,
+    FunctionCall,
+    Field,
+    Index,
+}
+
+impl NodeKind {
+    pub const ALL: [NodeKind; 18] = [
+        NodeKind::Function,
+        NodeKind::Decl,
+        NodeKind::If,
+        NodeKind::While,
+        NodeKind::Block,
+        NodeKind::Print,
+        NodeKind::Scan,
+        NodeKind::Return,
+        NodeKind::Break,
+        NodeKind::Ident,
+        NodeKind::Literal,
+        NodeKind::Conversion,
+        NodeKind::UnaryOp,
+        NodeKind::BinaryOp,
+        NodeKind::Assign,
+        NodeKind::FunctionCall,
+        NodeKind::Field,
+        NodeKind::Index,
+    ];
+
+    /// Name of the kind in selectors
+    pub fn name(self) -> &'static str {
+        match self {
+            NodeKind::Function => "Function",
+            NodeKind::Decl => "Decl",
+            NodeKind::If => "If",
+            NodeKind::While => "While",
+            NodeKind::Block => "Block",
+            NodeKind::Print => "Print",
+            NodeKind::Scan => "Scan",
+            NodeKind::Return => "Return",
+            NodeKind::Break => "Break",
+            NodeKind::Ident => "Ident",
+            NodeKind::Literal => "Literal",
+            NodeKind::Conversion => "Conversion",
+            NodeKind::UnaryOp => "UnaryOp",
+            NodeKind::BinaryOp => "BinaryOp",
+            NodeKind::Assign => "Assign",
+            NodeKind::FunctionCall => "FunctionCall",
+            NodeKind::Field => "Field",
+            NodeKind::Index => "Index",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<NodeKind> {
+        NodeKind::ALL.iter().copied().find(|k| k.name() == name)
+    }
+
+    /// Attributes nodes of this kind may have
+    pub fn attrs(self) -> &'static [&'static str] {
+        match self {
+            NodeKind::Function => &["line", "name", "params", "returns", "extern", "doc"],
+            NodeKind::Decl => &["line", "name", "type", "const"],
+            NodeKind::While | NodeKind::Break => &["line", "label"],
+            NodeKind::Scan | NodeKind::Ident => &["line", "name"],
+            NodeKind::Literal => &["line", "value"],
+            NodeKind::Conversion => &["line", "type"],
+            NodeKind::UnaryOp | NodeKind::BinaryOp => &["line", "op"],
+            NodeKind::Assign => &["line", "op", "name"],
+            NodeKind::FunctionCall => &["line", "name", "args"],
+            NodeKind::Field => &["line", "index"],
+            _ => &["line"],
+        }
+    }
+}
+
+impl fmt::Display for NodeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// A node found by a query
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Match {
+    pub kind: NodeKind,
+    pub span: Span,
+    /// Function the node is in, or `None` at the top level
+    pub function: Option<String>,
+    /// Attributes of the node, with `line` first
+    pub attrs: Vec<(&'static str, String)>,
+}
+
+impl Match {
+    /// Value of attribute `name`, if the node has it
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        let (_, val) = self.attrs.iter().find(|(attr, _)| *attr == name)?;
+        Some(val)
+    }
+}
+
+/// A condition on an attribute
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Cond {
+    /// `[attr]`: the node has it
+    Has(String),
+    /// `[attr=value]`
+    Eq(String, String),
+    /// `[attr!=value]`: the node does not have it, or it is something else
+    Ne(String, String),
+}
+
+/// One node to find: its kind, if any, and conditions on its attributes
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Selector {
+    pub kind: Option<NodeKind>,
+    pub conds: Vec<Cond>,
+}
+
+impl Selector {
+    /// Nodes of any kind, `*`
+    pub fn any() -> Selector {
+        Selector::default()
+    }
+
+    /// Nodes of kind `kind`
+    pub fn of(kind: NodeKind) -> Selector {
+        Selector {
+            kind: Some(kind),
+            conds: vec![],
+        }
+    }
+
+    /// Only nodes whose attribute `name` is `value`
+    pub fn attr(mut self, name: &str, value: &str) -> Selector {
+        self.conds.push(Cond::Eq(name.into(), value.into()));
+        self
+    }
+
+    /// Only nodes whose attribute `name` is not `value`
+    pub fn attr_ne(mut self, name: &str, value: &str) -> Selector {
+        self.conds.push(Cond::Ne(name.into(), value.into()));
+        self
+    }
+
+    /// Only nodes with attribute `name`
+    pub fn has(mut self, name: &str) -> Selector {
+        self.conds.push(Cond::Has(name.into()));
+        self
+    }
+
+    fn matches(&self, node: &Node) -> bool {
+        if self.kind.is_some_and(|kind| kind != node.kind) {
+            return false;
+        }
+        self.conds.iter().all(|cond| match cond {
+            Cond::Has(name) => node.attr(name).is_some(),
+            Cond::Eq(name, val) => node.attr(name) == Some(val),
+            Cond::Ne(name, val) => node.attr(name) != Some(val),
+        })
+    }
+
+    fn check(&self) -> Result<(), QueryError> {
+        let known = |name: &str| match self.kind {
+            Some(kind) => kind.attrs().contains(&name),
+            None => NodeKind::ALL.iter().any(|k| k.attrs().contains(&name)),
+        };
+        for cond in &self.conds {
+            let (Cond::Has(name) | Cond::Eq(name, _) | Cond::Ne(name, _)) = cond;
+            if !known(name) {
+                let kind = self.kind.map_or("*", NodeKind::name);
+                return Err(QueryError::UnknownAttr(kind.into(), name.clone()));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How a selector relates to the one after it
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Combinator {
+    /// Anywhere around it, a space
+    Descendant,
+    /// Right around it, `>`
+    Child,
+}
+
+/// What to find in a program, found with selectors as CSS finds elements.
+///
+/// A selector names a kind of node, or `*` for any, followed by conditions
+/// on its attributes in brackets: `FunctionCall[name=foo]` finds calls to
+/// `foo`, `BinaryOp[op=/][line=3]` divisions on line 3, and `Decl[type]`
+/// declarations with a type written out. `[attr!=value]` matches nodes
+/// whose attribute is anything else, or that have none. Selectors separated
+/// by spaces find the last one inside the others, as in `While
+/// FunctionCall`; `>` between them only looks at the node right around it,
+/// as in `Function[name=main] > Return`. Queries separated by commas find
+/// what any of them finds.
+///
+/// Every node has a `line`, counted from 1. Kinds and their other
+/// attributes:
+///
+/// | Kind | Attributes |
+/// |------|------------|
+/// | `Function` | `name`, `params`, `returns`, `extern`, `doc` if it has `///` comments |
+/// | `Decl` | `name`, `type`, `const` |
+/// | `If`, `While`, `Block`, `Print`, `Return` | `label` for `While` |
+/// | `Scan` | `name` |
+/// | `Break` | `label` |
+/// | `Ident` | `name` |
+/// | `Literal` | `value` |
+/// | `Conversion` | `type` |
+/// | `UnaryOp`, `BinaryOp` | `op` |
+/// | `Assign` | `op`, `name` if a variable is assigned |
+/// | `FunctionCall` | `name`, `args` |
+/// | `Field` | `index` |
+/// | `Index` | |
+///
+/// The body of a function and the initializer of a declaration are inside
+/// them.
+///
+/// Queries can be built from [`Selector`]s too, without parsing:
+/// `Function[name=main] > Return` is
+/// `Query::select(Selector::of(NodeKind::Return))` made
+/// `.child_of(Selector::of(NodeKind::Function).attr("name", "main"))`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Query {
+    paths: Vec<Vec<(Combinator, Selector)>>,
+}
+
+impl From<Selector> for Query {
+    fn from(sel: Selector) -> Query {
+        Query {
+            paths: vec![vec![(Combinator::Descendant, sel)]],
+        }
+    }
+}
+
+impl Query {
+    /// Nodes `sel` matches
+    pub fn select(sel: Selector) -> Query {
+        Query::from(sel)
+    }
+
+    /// Only nodes anywhere inside a node `outer` matches
+    pub fn inside(self, outer: Selector) -> Query {
+        self.around(Combinator::Descendant, outer)
+    }
+
+    /// Only nodes right inside a node `parent` matches
+    pub fn child_of(self, parent: Selector) -> Query {
+        self.around(Combinator::Child, parent)
+    }
+
+    /// Nodes either query finds
+    pub fn or(mut self, other: Query) -> Query {
+        self.paths.extend(other.paths);
+        self
+    }
+
+    fn around(mut self, comb: Combinator, outer: Selector) -> Query {
+        for path in &mut self.paths {
+            path[0].0 = comb;
+            path.insert(0, (Combinator::Descendant, outer.clone()));
+        }
+        self
+    }
+
+    /// Parse a query written as described [above](Query)
+    pub fn parse(src: &str) -> Result<Query, QueryError> {
+        let mut paths = vec![];
+        for alt in src.split(',') {
+            let mut path = vec![];
+            let mut comb = Combinator::Descendant;
+            let mut rest = alt.trim_start();
+            while !rest.is_empty() {
+                if let Some(after) = rest.strip_prefix('>') {
+                    if path.is_empty() || comb == Combinator::Child {
+                        return Err(QueryError::Syntax(rest.into()));
+                    }
+                    comb = Combinator::Child;
+                    rest = after.trim_start();
+                    continue;
+                }
+                let (sel, after) = parse_selector(rest)?;
+                path.push((comb, sel));
+                comb = Combinator::Descendant;
+                rest = after.trim_start();
+            }
+            if path.is_empty() || comb == Combinator::Child {
+                return Err(QueryError::Syntax(alt.trim().into()));
+            }
+            paths.push(path);
+        }
+        Ok(Query { paths })
+    }
+
+    /// Nodes of `prog` the query finds, in source order
+    pub fn find_all(&self, prog: &Program) -> Vec<Match> {
+        let mut walker = Walker {
+            query: self,
+            stack: vec![],
+            function: None,
+            found: vec![],
+        };
+        walker.block(&prog.blk);
+        walker.found.sort_by_key(|m| m.span.start.index);
+        walker.found
+    }
+
+    fn matches(&self, stack: &[Node]) -> bool {
+        self.paths.iter().any(|path| path_matches(path, stack))
+    }
+}
+
+/// Whether the last node of `stack` matches the last selector of `path`,
+/// and the nodes around it the ones before
+fn path_matches(path: &[(Combinator, Selector)], stack: &[Node]) -> bool {
+    let ((comb, sel), outer) = match path.split_last() {
+        Some(last) => last,
+        None => return true,
+    };
+    let (node, around) = match stack.split_last() {
+        Some(last) => last,
+        None => return false,
+    };
+    if !sel.matches(node) {
+        return false;
+    }
+    if outer.is_empty() {
+        return true;
+    }
+    match comb {
+        Combinator::Child => path_matches(outer, around),
+        Combinator::Descendant => (1..=around.len()).any(|len| path_matches(outer, &around[..len])),
+    }
+}
+
+fn parse_selector(src: &str) -> Result<(Selector, &str), QueryError> {
+    let end =
+        (src.find(|c: char| !c.is_alphanumeric() && c != '_' && c != '*')).unwrap_or(src.len());
+    let (name, mut rest) = src.split_at(end);
+    let mut sel = match name {
+        "*" => Selector::any(),
+        "" => return Err(QueryError::Syntax(src.into())),
+        _ => Selector::of(
+            NodeKind::from_name(name).ok_or_else(|| QueryError::UnknownKind(name.into()))?,
+        ),
+    };
+    while let Some(after) = rest.strip_prefix('[') {
+        let close = after
+            .find(']')
+            .ok_or_else(|| QueryError::Syntax(rest.into()))?;
+        let cond = &after[..close];
+        let cond = match cond.split_once('=') {
+            Some((name, val)) => match name.strip_suffix('!') {
+                Some(name) => Cond::Ne(name.trim().into(), val.trim().into()),
+                None => Cond::Eq(name.trim().into(), val.trim().into()),
+            },
+            None => Cond::Has(cond.trim().into()),
+        };
+        sel.conds.push(cond);
+        rest = &after[close + 1..];
+    }
+    sel.check()?;
+    Ok((sel, rest))
+}
+
+/// Why a query could not be parsed
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum QueryError {
+    /// Query text that is not a selector, from where it went wrong
+    Syntax(String),
+    UnknownKind(String),
+    /// An attribute that nodes of the kind, given first, never have
+    UnknownAttr(String, String),
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryError::Syntax(at) => write!(f, "Expected a selector at `{}`", at),
+            QueryError::UnknownKind(kind) => {
+                let kinds: Vec<_> = NodeKind::ALL.iter().map(|k| k.name()).collect();
+                write!(
+                    f,
+                    "Unknown kind `{}`, expected one of {}",
+                    kind,
+                    kinds.join(", ")
+                )
+            }
+            QueryError::UnknownAttr(kind, attr) => {
+                write!(f, "`{}` nodes have no attribute `{}`", kind, attr)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for QueryError {}
+
+/// A node being walked
+struct Node {
+    kind: NodeKind,
+    span: Span,
+    attrs: Vec<(&'static str, String)>,
+}
+
+impl Node {
+    fn new(kind: NodeKind, span: Span) -> Node {
+        Node {
+            kind,
+            span,
+            attrs: vec![("line", (span.start.ln + 1).to_string())],
+        }
+    }
+
+    fn with(mut self, name: &'static str, val: impl ToString) -> Node {
+        self.attrs.push((name, val.to_string()));
+        self
+    }
+
+    fn attr(&self, name: &str) -> Option<&String> {
+        self.attrs.iter().find(|(n, _)| *n == name).map(|(_, v)| v)
+    }
+}
+
+/// Walks the tree with the nodes around the current one, keeping those the
+/// query finds
+struct Walker<'q> {
+    query: &'q Query,
+    stack: Vec<Node>,
+    function: Option<String>,
+    found: Vec<Match>,
+}
+
+impl<'q> Walker<'q> {
+    /// Visit `node`, then what `inside` visits inside it
+    fn node(&mut self, node: Node, inside: impl FnOnce(&mut Self)) {
+        self.stack.push(node);
+        if self.query.matches(&self.stack) {
+            let node = self.stack.last().unwrap();
+            self.found.push(Match {
+                kind: node.kind,
+                span: node.span,
+                function: self.function.clone(),
+                attrs: node.attrs.clone(),
+            });
+        }
+        inside(self);
+        self.stack.pop();
+    }
+
+    fn block(&mut self, blk: &Block) {
+        for stmt in &blk.stmts {
+            self.stmt(stmt, &blk.scope);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt, scope: &Ptr<Scope>) {
+        let span = stmt.span;
+        maybe_grow(|| match &stmt.var {
+            StmtVariant::If(i) => self.node(Node::new(NodeKind::If, span), |w| {
+                w.expr(&i.cond);
+                w.stmt(&i.if_block.borrow(), scope);
+                for (cond, body) in &i.else_ifs {
+                    w.expr(cond);
+                    w.stmt(&body.borrow(), scope);
+                }
+                if let Some(body) = &i.else_block {
+                    w.stmt(&body.borrow(), scope);
+                }
+            }),
+            StmtVariant::While(wh) => {
+                let mut node = Node::new(NodeKind::While, span);
+                if let Some(label) = &wh.label {
+                    node = node.with("label", label.name);
+                }
+                self.node(node, |w| {
+                    w.expr(&wh.cond);
+                    w.stmt(&wh.block.borrow(), scope);
+                })
+            }
+            StmtVariant::Block(b) => self.node(Node::new(NodeKind::Block, span), |w| w.block(b)),
+            StmtVariant::Expr(e) => self.expr(e),
+            StmtVariant::Print(es) => self.node(Node::new(NodeKind::Print, span), |w| {
+                es.iter().for_each(|e| w.expr(e))
+            }),
+            StmtVariant::ManyExpr(es) => self.decls(es, scope, span),
+            StmtVariant::Scan(i, _) => {
+                self.node(Node::new(NodeKind::Scan, span).with("name", i.name), |_| ())
+            }
+            StmtVariant::Return(e) => self.node(Node::new(NodeKind::Return, span), |w| {
+                e.iter().for_each(|e| w.expr(e))
+            }),
+            StmtVariant::Break(label) => {
+                let mut node = Node::new(NodeKind::Break, span);
+                if let Some(label) = label {
+                    node = node.with("label", label.name);
+                }
+                self.node(node, |_| ())
+            }
+            StmtVariant::Empty => self.functions(scope, span),
+        })
+    }
+
+    /// Declarations of variables in `span`, each with its initializer in
+    /// `inits` inside it
+    fn decls(&mut self, inits: &[Ptr<Expr>], scope: &Ptr<Scope>, span: Span) {
+        for (name, def) in scope.borrow().defs_in(span) {
+            let def = def.borrow();
+            let (typ, is_const, decl_span) = match &*def {
+                SymbolDef::Var {
+                    typ,
+                    is_const,
+                    decl_span,
+                    ..
+                } => (typ, *is_const, *decl_span),
+                SymbolDef::Typ { .. } => continue,
+            };
+            let mut node = Node::new(NodeKind::Decl, decl_span).with("name", name);
+            if !matches!(&*typ.borrow(), TypeDef::Unknown) {
+                node = node.with("type", type_str(&typ.borrow()));
+            }
+            let node = node.with("const", is_const);
+            self.node(node, |w| {
+                let init = (inits.iter()).find(|e| decl_span.contains(e.borrow().span));
+                if let Some(init) = init {
+                    if let ExprVariant::BinaryOp(b) = &init.borrow().var {
+                        w.expr(&b.rhs);
+                    }
+                }
+            });
+        }
+    }
+
+    /// Functions declared in `span`, with their bodies inside them
+    fn functions(&mut self, scope: &Ptr<Scope>, span: Span) {
+        for (name, def) in scope.borrow().defs_in(span) {
+            let def = def.borrow();
+            let (typ, decl_span, doc) = match &*def {
+                SymbolDef::Var {
+                    typ,
+                    decl_span,
+                    doc,
+                    ..
+                } => (typ.borrow(), *decl_span, doc),
+                SymbolDef::Typ { .. } => continue,
+            };
+            let f = match &*typ {
+                TypeDef::Function(f) => f,
+                _ => continue,
+            };
+            let body_span = f.body.as_ref().and_then(|b| b.span);
+            let node = Node::new(
+                NodeKind::Function,
+                decl_span + body_span.unwrap_or(decl_span),
+            )
+            .with("name", name)
+            .with("params", f.params.len())
+            .with("returns", type_str(&f.return_type.borrow()))
+            .with("extern", f.is_extern);
+            let node = match doc {
+                Some(doc) => node.with("doc", doc),
+                None => node,
+            };
+            let outer = self.function.replace(name.to_string());
+            self.node(node, |w| {
+                if let Some(body) = &f.body {
+                    w.block(body);
+                }
+            });
+            self.function = outer;
+        }
+    }
+
+    fn expr(&mut self, expr: &Ptr<Expr>) {
+        maybe_grow(|| {
+            let expr = expr.borrow();
+            let span = expr.span;
+            match &expr.var {
+                ExprVariant::Ident(i) => self.node(
+                    Node::new(NodeKind::Ident, span).with("name", i.name),
+                    |_| (),
+                ),
+                ExprVariant::Literal(lit) => {
+                    let val = match lit {
+                        Literal::Char { val } => val.to_string(),
+                        Literal::String { val } => val.clone(),
+                        lit => lit.to_string(),
+                    };
+                    self.node(
+                        Node::new(NodeKind::Literal, span).with("value", val),
+                        |_| (),
+                    )
+                }
+                ExprVariant::TypeConversion(t) => {
+                    let node = Node::new(NodeKind::Conversion, span)
+                        .with("type", type_str(&t.to.borrow()));
+                    self.node(node, |w| w.expr(&t.expr))
+                }
+                ExprVariant::UnaryOp(u) => {
+                    let node = Node::new(NodeKind::UnaryOp, span).with("op", op_str(u.op));
+                    self.node(node, |w| w.expr(&u.val))
+                }
+                ExprVariant::BinaryOp(b) if matches!(b.op, OpVar::_Asn | OpVar::_Csn) => {
+                    let mut node = Node::new(NodeKind::Assign, span).with("op", op_str(b.op));
+                    if let ExprVariant::Ident(i) = &b.lhs.borrow().var {
+                        node = node.with("name", i.name);
+                    }
+                    self.node(node, |w| {
+                        w.expr(&b.lhs);
+                        w.expr(&b.rhs);
+                    })
+                }
+                ExprVariant::BinaryOp(b) => {
+                    let node = Node::new(NodeKind::BinaryOp, span).with("op", op_str(b.op));
+                    self.node(node, |w| {
+                        w.expr(&b.lhs);
+                        w.expr(&b.rhs);
+                    })
+                }
+                ExprVariant::FunctionCall(f) => {
+                    let node = Node::new(NodeKind::FunctionCall, span)
+                        .with("name", f.func)
+                        .with("args", f.params.len());
+                    self.node(node, |w| f.params.iter().for_each(|p| w.expr(p)))
+                }
+                ExprVariant::StructChild(s) => {
+                    let node = Node::new(NodeKind::Field, span).with("index", s.idx);
+                    self.node(node, |w| w.expr(&s.val))
+                }
+                ExprVariant::ArrayChild(a) => self.node(Node::new(NodeKind::Index, span), |w| {
+                    w.expr(&a.val);
+                    w.expr(&a.idx);
+                }),
+            }
+        })
+    }
+}
@@ -0,0 +1,234 @@
+//! Test case reduction: shrink a program while it keeps failing the same way.
+//!
+//! Candidates are small edits on the source text, found by walking the AST:
+//! removing runs of statements, replacing an `if` or `while` with one of its
+//! bodies, and replacing expressions with an operand or `0`. Larger edits are
+//! tried first, and every success starts over on the smaller program, until
+//! no edit is left that keeps it failing. Only candidates that parse are
+//! tried, so the result is always a valid program.
+
+use super::ast::*;
+use super::lexer::Lexer;
+use super::parser::Parser;
+use super::pretty::pretty_print;
+use crate::prelude::*;
+
+/// Replace the text of `start..end`, in chars, with `text`
+#[derive(Debug, Clone)]
+struct Edit {
+    start: usize,
+    end: usize,
+    text: String,
+}
+
+impl Edit {
+    /// Chars removed by the edit
+    fn removed(&self) -> usize {
+        (self.end - self.start).saturating_sub(self.text.chars().count())
+    }
+
+    fn apply(&self, src: &[char]) -> String {
+        let mut out: String = src[..self.start].iter().collect();
+        out.push_str(&self.text);
+        out.extend(&src[self.end..]);
+        out
+    }
+}
+
+/// Shrink `src` for as long as it stays `interesting`. The result is pretty
+/// printed if that keeps it interesting. `None` if `src` doesn't parse or is
+/// not interesting to begin with.
+pub fn reduce(src: &str, mut interesting: impl FnMut(&str) -> bool) -> Option<String> {
+    if !interesting(src) {
+        return None;
+    }
+    let mut cur = normalize(src, &mut interesting)?;
+
+    'restart: loop {
+        let prog = match parse(&cur) {
+            Some(prog) => prog,
+            None => break,
+        };
+        let chars: Vec<char> = cur.chars().collect();
+        let mut edits = Collector::new(&chars).program(&prog);
+        // * Stable, so equally large edits go in source order
+        edits.sort_by_key(|e| core::cmp::Reverse(e.removed()));
+        for edit in edits {
+            if edit.removed() == 0 {
+                break;
+            }
+            let candidate = edit.apply(&chars);
+            if parse(&candidate).is_some() && interesting(&candidate) {
+                cur = candidate;
+                continue 'restart;
+            }
+        }
+        break;
+    }
+    normalize(&cur, &mut interesting).or(Some(cur))
+}
+
+/// `src` pretty printed, or as it is if that makes it uninteresting
+fn normalize(src: &str, interesting: &mut impl FnMut(&str) -> bool) -> Option<String> {
+    let printed = pretty_print(&parse(src)?);
+    if printed == src || !interesting(&printed) {
+        return Some(src.into());
+    }
+    Some(printed)
+}
+
+fn parse(src: &str) -> Option<Program> {
+    Parser::new(Lexer::new(src)).parse().ok()
+}
+
+/// Finds candidate edits in a program
+struct Collector<'a> {
+    src: &'a [char],
+    edits: Vec<Edit>,
+}
+
+impl<'a> Collector<'a> {
+    fn new(src: &'a [char]) -> Collector<'a> {
+        Collector { src, edits: vec![] }
+    }
+
+    fn program(mut self, prog: &Program) -> Vec<Edit> {
+        self.block(&prog.blk.stmts, &prog.blk.scope);
+        self.edits
+    }
+
+    fn text(&self, span: Span) -> String {
+        self.src[span.start.index..span.end.index].iter().collect()
+    }
+
+    fn replace(&mut self, span: Span, text: String) {
+        self.edits.push(Edit {
+            start: span.start.index,
+            end: span.end.index,
+            text,
+        });
+    }
+
+    /// Span of all of `stmt`. Statement spans can stop at their last
+    /// expression, leaving out `)` and `;`, and for `return` they cover only
+    /// the value.
+    fn stmt_span(&self, stmt: &Stmt) -> Span {
+        let mut span = stmt.span;
+        if let StmtVariant::Return(Some(_)) = stmt.var {
+            let before: String = self.src[..span.start.index].iter().collect();
+            if let Some(keyword) = before.trim_end().strip_suffix("return") {
+                span.start.index = keyword.chars().count();
+            }
+        }
+        let end = (span.end.index..self.src.len())
+            .find(|&i| !self.src[i].is_whitespace() && self.src[i] != ')')
+            .unwrap_or(span.end.index);
+        if self.src.get(end) == Some(&';') {
+            span.end.index = end + 1;
+        }
+        span
+    }
+
+    /// Remove runs of statements: halves, then quarters, down to each one
+    fn block(&mut self, stmts: &[Stmt], scope: &Ptr<Scope>) {
+        let mut chunk = stmts.len();
+        while chunk > 0 {
+            for run in stmts.chunks(chunk) {
+                let (first, last) = (&run[0], &run[run.len() - 1]);
+                let span = Span::from(self.stmt_span(first).start, self.stmt_span(last).end);
+                self.replace(span, "".into());
+            }
+            chunk /= 2;
+        }
+        for stmt in stmts {
+            self.stmt(stmt, scope);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt, scope: &Ptr<Scope>) {
+        match &stmt.var {
+            StmtVariant::If(i) => {
+                let bodies = core::iter::once(&i.if_block)
+                    .chain(i.else_ifs.iter().map(|(_, body)| body))
+                    .chain(&i.else_block);
+                for body in bodies {
+                    let text = self.text(self.stmt_span(&body.borrow()));
+                    self.replace(self.stmt_span(stmt), text);
+                    self.stmt(&body.borrow(), scope);
+                }
+                self.expr(&i.cond.borrow());
+                for (cond, _) in &i.else_ifs {
+                    self.expr(&cond.borrow());
+                }
+            }
+            StmtVariant::While(w) => {
+                let text = self.text(self.stmt_span(&w.block.borrow()));
+                self.replace(self.stmt_span(stmt), text);
+                self.expr(&w.cond.borrow());
+                self.stmt(&w.block.borrow(), scope);
+            }
+            StmtVariant::Block(b) => {
+                if let (Some(first), Some(last)) = (b.stmts.first(), b.stmts.last()) {
+                    let span = Span::from(self.stmt_span(first).start, self.stmt_span(last).end);
+                    let text = self.text(span);
+                    self.replace(stmt.span, text);
+                }
+                self.block(&b.stmts, &b.scope);
+            }
+            StmtVariant::Expr(e) => self.expr(&e.borrow()),
+            StmtVariant::Print(exprs) => exprs.iter().for_each(|e| self.expr(&e.borrow())),
+            StmtVariant::ManyExpr(inits) => {
+                for init in inits {
+                    if let ExprVariant::BinaryOp(b) = &init.borrow().var {
+                        self.expr(&b.rhs.borrow());
+                    }
+                }
+            }
+            StmtVariant::Return(Some(e)) => self.expr(&e.borrow()),
+            StmtVariant::Empty => {
+                // * Function declarations; their bodies are kept in the scope
+                for (_, def) in scope.borrow().defs_in(stmt.span) {
+                    if let SymbolDef::Var { typ, .. } = &*def.borrow() {
+                        if let TypeDef::Function(FunctionType {
+                            body: Some(body), ..
+                        }) = &*typ.borrow()
+                        {
+                            self.block(&body.stmts, &body.scope);
+                        }
+                    }
+                }
+            }
+            StmtVariant::Scan(..) | StmtVariant::Return(None) | StmtVariant::Break(_) => {}
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        let operands: Vec<Ptr<Expr>> = match &expr.var {
+            ExprVariant::Ident(_) => return,
+            ExprVariant::Literal(_) => {
+                self.replace(expr.span, "0".into());
+                return;
+            }
+            ExprVariant::BinaryOp(b) if b.op == OpVar::_Asn => {
+                self.expr(&b.rhs.borrow());
+                return;
+            }
+            ExprVariant::TypeConversion(t) => vec![t.expr.cp()],
+            ExprVariant::UnaryOp(u) => vec![u.val.cp()],
+            ExprVariant::BinaryOp(b) => vec![b.lhs.cp(), b.rhs.cp()],
+            ExprVariant::FunctionCall(f) => f.params.clone(),
+            ExprVariant::StructChild(s) => vec![s.val.cp()],
+            ExprVariant::ArrayChild(a) => vec![a.val.cp(), a.idx.cp()],
+        };
+        self.replace(expr.span, "0".into());
+        for operand in &operands {
+            let operand = operand.borrow();
+            let text = match operand.var {
+                ExprVariant::Ident(_) | ExprVariant::Literal(_) => self.text(operand.span),
+                _ => format!("({})", self.text(operand.span)),
+            };
+            self.replace(expr.span, text);
+            self.expr(&operand);
+        }
+    }
+}
@@ -0,0 +1,197 @@
+//! Interned identifiers. Every name read is kept once, and tokens, the
+//! syntax tree and scopes hold a `Symbol` numbering it, so names are copied
+//! and compared as integers instead of allocated and compared as strings.
+
+use super::ast::DefsHasher;
+use alloc::{boxed::Box, string::String};
+use core::cmp::Ordering;
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::Deref;
+use indexmap::IndexSet;
+
+/// A name, interned. Two symbols are equal exactly when their names are.
+///
+/// Names are interned for the thread they are read on, and live as long as
+/// it does, so a symbol only means something on the thread that made it,
+/// and can't be sent to another. That is the thread parsing a program,
+/// which the syntax tree cannot leave anyway. Long sessions compile each
+/// program with [`with_fresh_names`], so names no longer read are freed.
+#[derive(Clone, Copy, Eq, PartialEq, Hash)]
+pub struct Symbol(u32, PhantomData<*const ()>);
+
+struct Interner {
+    /// Names are boxed so they stay where they are as the set grows
+    names: IndexSet<Box<str>, DefsHasher>,
+}
+
+impl Default for Interner {
+    fn default() -> Self {
+        let mut names = IndexSet::default();
+        names.insert("".into());
+        Interner { names }
+    }
+}
+
+impl Interner {
+    fn intern(&mut self, name: &str) -> Symbol {
+        if let Some((idx, _)) = self.names.get_full(name) {
+            return Symbol::at(idx);
+        }
+        let (idx, _) = self.names.insert_full(name.into());
+        Symbol::at(idx)
+    }
+}
+
+#[cfg(feature = "std")]
+thread_local! {
+    static INTERNER: core::cell::RefCell<Interner> = core::cell::RefCell::default();
+}
+
+#[cfg(feature = "std")]
+fn with_interner<R>(f: impl FnOnce(&mut Interner) -> R) -> R {
+    INTERNER.with(|interner| f(&mut interner.borrow_mut()))
+}
+
+// * Without `std` there are no thread locals, so one interner is shared,
+// * behind a spin lock
+#[cfg(not(feature = "std"))]
+struct Global {
+    locked: core::sync::atomic::AtomicBool,
+    interner: core::cell::UnsafeCell<Option<Interner>>,
+}
+
+// SAFETY: `interner` is only touched by whoever holds `locked`
+#[cfg(not(feature = "std"))]
+unsafe impl Sync for Global {}
+
+#[cfg(not(feature = "std"))]
+static INTERNER: Global = Global {
+    locked: core::sync::atomic::AtomicBool::new(false),
+    interner: core::cell::UnsafeCell::new(None),
+};
+
+#[cfg(not(feature = "std"))]
+fn with_interner<R>(f: impl FnOnce(&mut Interner) -> R) -> R {
+    use core::sync::atomic::Ordering;
+    while (INTERNER.locked)
+        .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+        .is_err()
+    {
+        core::hint::spin_loop();
+    }
+    // SAFETY: the lock is held until `f` returns
+    let interner = unsafe { &mut *INTERNER.interner.get() };
+    let res = f(interner.get_or_insert_with(Interner::default));
+    INTERNER.locked.store(false, Ordering::Release);
+    res
+}
+
+impl Symbol {
+    /// The empty name, which no identifier has. Token types are compared by
+    /// variant with it, like `TokenType::Identifier(Symbol::EMPTY)`.
+    pub const EMPTY: Symbol = Symbol(0, PhantomData);
+
+    fn at(idx: usize) -> Symbol {
+        Symbol(idx as u32, PhantomData)
+    }
+
+    pub fn intern(name: &str) -> Symbol {
+        with_interner(|interner| interner.intern(name))
+    }
+
+    pub fn as_str(&self) -> &str {
+        let name = with_interner(|interner| {
+            let name = interner.names.get_index(self.0 as usize);
+            &**name.expect("Symbols are made by interning") as *const str
+        });
+        // SAFETY: names are boxed, and none is dropped before the interner
+        // is, when the thread ends. `self` can't leave the thread, so the
+        // name outlives the borrow of it.
+        unsafe { &*name }
+    }
+}
+
+/// Run `f` on a thread of its own, whose names are freed once it returns.
+/// Symbols made by `f` can't be returned, so nothing refers to them after.
+#[cfg(feature = "std")]
+pub fn with_fresh_names<R: Send>(f: impl FnOnce() -> R + Send) -> R {
+    std::thread::scope(|scope| match scope.spawn(f).join() {
+        Ok(res) => res,
+        Err(panic) => std::panic::resume_unwind(panic),
+    })
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Symbol {
+        Symbol::intern(name)
+    }
+}
+
+impl From<&String> for Symbol {
+    fn from(name: &String) -> Symbol {
+        Symbol::intern(name)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(name: String) -> Symbol {
+        Symbol::intern(&name)
+    }
+}
+
+impl From<&Symbol> for Symbol {
+    fn from(sym: &Symbol) -> Symbol {
+        *sym
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+// * Ordered by name, not by when they were interned, so sorted names come out
+// * the same whatever order they were read in
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Symbol) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Symbol {
+    fn cmp(&self, other: &Symbol) -> Ordering {
+        if self == other {
+            Ordering::Equal
+        } else {
+            self.as_str().cmp(other.as_str())
+        }
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
@@ -0,0 +1,626 @@
+//! Working out the type of every expression, building the
+//! [typed tree](crate::c0::hir).
+//!
+//! The rules are the ones code generation follows: arithmetic on an integer
+//! and a `double` gives a `double`, otherwise the type of the left operand,
+//! and comparisons give `bool`. Values are converted implicitly between
+//! primitive types, and between references, but never from one to the
+//! other. A program rejected here fails to compile with the same error.
+//! Implicit conversions are made explicit in the typed tree, as
+//! [`Conversion`](crate::c0::hir::SyntheticOrigin::Conversion) nodes.
+//!
+//! Variables declared `auto` take the type of the first value assigned to
+//! them, usually their initializer. Using one before anything is assigned to
+//! it leaves its type unknown, which is an error.
+
+use super::ast::{self, OpVar, PrimitiveType, PrimitiveTypeVar, Scope, SymbolDef, TypeDef};
+use super::hir::*;
+use crate::minivm::err::*;
+use crate::prelude::*;
+use alloc::collections::BTreeMap;
+
+/// Type check `prog` and build its typed tree
+pub fn lower(prog: &ast::Program) -> CompileResult<TypedProgram> {
+    let mut lower = Lower {
+        ret: Ptr::new(TypeDef::Unit),
+        loops: vec![],
+        inferred: BTreeMap::new(),
+    };
+    // * Globals come first, so that functions see the types inferred for them
+    let blk = lower.block(&prog.blk)?;
+    let mut fns = vec![];
+    let defs: Vec<_> = (prog.blk.scope.borrow().defs.iter())
+        .map(|(name, def)| (*name, def.cp()))
+        .collect();
+    for (name, def) in defs {
+        if let SymbolDef::Var { typ, decl_span, .. } = &*def.borrow() {
+            if let TypeDef::Function(func) = &*typ.borrow() {
+                let f = lower
+                    .function(&name, def.cp(), func, &prog.blk.scope)
+                    .with_span(*decl_span)?;
+                fns.push(f);
+            }
+        }
+    }
+    Ok(TypedProgram {
+        blk,
+        fns,
+        inferred: lower.inferred,
+    })
+}
+
+/// Type check function `name`, defined by `def` in `scope`, the global scope
+/// of a program, on its own. `globals` are the types [`lower`] inferred for
+/// the global variables of the program.
+pub fn lower_fn(
+    name: &str,
+    def: Ptr<SymbolDef>,
+    scope: &Ptr<Scope>,
+    globals: BTreeMap<(usize, String), Type>,
+) -> CompileResult<TypedProgram> {
+    let mut lower = Lower {
+        ret: Ptr::new(TypeDef::Unit),
+        loops: vec![],
+        inferred: globals,
+    };
+    let f = match &*def.borrow() {
+        SymbolDef::Var { typ, decl_span, .. } => match &*typ.borrow() {
+            TypeDef::Function(func) => Some(
+                lower
+                    .function(name, def.cp(), func, scope)
+                    .with_span(*decl_span)?,
+            ),
+            _ => None,
+        },
+        SymbolDef::Typ { .. } => None,
+    };
+    let f =
+        f.ok_or_else(|| CompileErrorVar::InternalError(format!("`{}` is not a function", name)))?;
+    Ok(TypedProgram {
+        blk: Block {
+            scope: scope.cp(),
+            vars: vec![],
+            stmts: vec![],
+            span: None,
+        },
+        fns: vec![f],
+        inferred: lower.inferred,
+    })
+}
+
+fn prim(var: PrimitiveTypeVar, occupy_bytes: usize) -> Type {
+    Ptr::new(TypeDef::Primitive(PrimitiveType { var, occupy_bytes }))
+}
+
+fn bool_type() -> Type {
+    prim(PrimitiveTypeVar::SignedInt, 1)
+}
+
+/// `typ` with every named type replaced by its definition, seen from `scope`
+fn resolve(typ: &TypeDef, scope: &Ptr<Scope>) -> CompileResult<Type> {
+    let resolved = match typ {
+        TypeDef::NamedType(name) => {
+            let def = scope.borrow().find_def(name);
+            let def = def
+                .and_then(|def| def.borrow().get_typ())
+                .ok_or_else(|| CompileErrorVar::Error(format!("Unknown type {}", name)))?;
+            return resolve(&def.borrow(), scope);
+        }
+        // * `auto`, which is left for inference
+        TypeDef::Primitive(_) | TypeDef::Unit | TypeDef::Unknown => typ.clone(),
+        TypeDef::Ref(r) => TypeDef::Ref(ast::RefType {
+            target: resolve(&r.target.borrow(), scope)?,
+        }),
+        TypeDef::Array(a) => TypeDef::Array(ast::ArrayType {
+            target: resolve(&a.target.borrow(), scope)?,
+            length: a.length,
+        }),
+        TypeDef::Function(f) => TypeDef::Function(ast::FunctionType {
+            params: (f.params.iter())
+                .map(|p| resolve(&p.borrow(), scope))
+                .collect::<CompileResult<_>>()?,
+            return_type: resolve(&f.return_type.borrow(), scope)?,
+            body: None,
+            is_extern: f.is_extern,
+        }),
+        TypeDef::TypeErr => return Err(CompileErrorVar::ErrorType.into()),
+        TypeDef::Struct(_) | TypeDef::VariableArgs(_) => {
+            return Err(CompileErrorVar::UnsupportedType.into())
+        }
+    };
+    Ok(Ptr::new(resolved))
+}
+
+/// Fail unless a value of type `from` converts implicitly to `to`
+fn check_conv(from: &Type, to: &Type) -> Result<(), CompileErrorVar> {
+    match (&*from.borrow(), &*to.borrow()) {
+        (_, TypeDef::Unit) => Ok(()),
+        (_, TypeDef::Unknown) | (_, TypeDef::TypeErr) => Err(CompileErrorVar::ErrorType),
+        (TypeDef::Unit, _) => Err(CompileErrorVar::AssignVoid),
+        (TypeDef::Primitive(_), TypeDef::Primitive(_)) | (TypeDef::Ref(_), TypeDef::Ref(_)) => {
+            Ok(())
+        }
+        (TypeDef::Ref(_), TypeDef::Primitive(_)) => Err(CompileErrorVar::MakePrimitiveFromRef),
+        (_, TypeDef::Ref(_)) => Err(CompileErrorVar::MakeRefFromPrimitive),
+        _ => Err(CompileErrorVar::UnsupportedType),
+    }
+}
+
+/// `val` converted implicitly to `to`, if it is not of that type already
+fn convert(val: Expr, to: &Type) -> Expr {
+    if *val.typ.borrow() == *to.borrow() || to.borrow().is_unit() {
+        return val;
+    }
+    Expr {
+        span: val.span,
+        typ: to.cp(),
+        var: ExprVariant::Conv(Box::new(val)),
+        origin: Some(SyntheticOrigin::Conversion),
+    }
+}
+
+/// Type both operands of arithmetic on `a` and `b` are converted to
+fn unify(a: &Type, b: &Type) -> Result<Type, CompileErrorVar> {
+    if a.borrow().is_unit() || b.borrow().is_unit() {
+        return Err(CompileErrorVar::AssignVoid);
+    }
+    if let (TypeDef::Primitive(p), TypeDef::Primitive(q)) = (&*a.borrow(), &*b.borrow()) {
+        let is_float = |p: &PrimitiveType| p.var == PrimitiveTypeVar::Float;
+        if !is_float(p) && is_float(q) && p.occupy_bytes <= q.occupy_bytes {
+            return Ok(b.cp());
+        }
+        return Ok(a.cp());
+    }
+    check_conv(b, a)?;
+    Ok(a.cp())
+}
+
+struct Lower {
+    /// Return type of the function being lowered
+    ret: Type,
+    /// Labels of the loops around the statement being lowered, innermost last
+    loops: Vec<Option<String>>,
+    /// Types of `auto` variables found so far
+    inferred: BTreeMap<(usize, String), Type>,
+}
+
+impl Lower {
+    fn function(
+        &mut self,
+        name: &str,
+        def: Ptr<SymbolDef>,
+        func: &ast::FunctionType,
+        scope: &Ptr<Scope>,
+    ) -> CompileResult<Function> {
+        let typ = resolve(&TypeDef::Function(func.clone()), scope)?;
+        let return_type = match &*typ.borrow() {
+            TypeDef::Function(f) => f.return_type.cp(),
+            _ => unreachable!(),
+        };
+        let span = match &*def.borrow() {
+            SymbolDef::Var { decl_span, .. } => *decl_span,
+            SymbolDef::Typ { .. } => unreachable!(),
+        };
+        let (params, body) = match &func.body {
+            Some(body) => {
+                self.ret = return_type.cp();
+                let mut body = self.block(body)?;
+                self.ret = Ptr::new(TypeDef::Unit);
+                let vars = body.vars.split_off(func.params.len());
+                (core::mem::replace(&mut body.vars, vars), Some(body))
+            }
+            None => (vec![], None),
+        };
+        Ok(Function {
+            name: name.into(),
+            def,
+            span,
+            params,
+            return_type,
+            body,
+        })
+    }
+
+    fn block(&mut self, blk: &ast::Block) -> CompileResult<Block> {
+        let scope = &blk.scope;
+        let is_global = scope.borrow().last.is_none();
+        let defs: Vec<_> = (scope.borrow().defs.iter())
+            .map(|(name, def)| (name.to_string(), def.cp()))
+            .collect();
+        let mut vars = vec![];
+        for (name, def) in defs {
+            if let SymbolDef::Var {
+                typ,
+                is_const,
+                decl_span,
+                ..
+            } = &*def.borrow()
+            {
+                if typ.borrow().is_fn() {
+                    if is_global {
+                        continue;
+                    }
+                    return Err(compile_err(
+                        CompileErrorVar::NestedFunctions(name),
+                        Some(*decl_span),
+                    ));
+                }
+                let typ = resolve(&typ.borrow(), scope).with_span(*decl_span)?;
+                if typ.borrow().is_unit() {
+                    return Err(compile_err(
+                        CompileErrorVar::VoidVariable(name),
+                        Some(*decl_span),
+                    ));
+                }
+                vars.push(Var {
+                    name,
+                    typ,
+                    is_const: *is_const,
+                    span: *decl_span,
+                });
+            }
+        }
+        let stmts = (blk.stmts.iter())
+            .map(|stmt| self.stmt(stmt, scope))
+            .collect::<CompileResult<_>>()?;
+        let id = scope.borrow().id;
+        for var in &mut vars {
+            if *var.typ.borrow() == TypeDef::Unknown {
+                var.typ =
+                    (self.inferred.get(&(id, var.name.clone())).cloned()).ok_or_else(|| {
+                        compile_err(
+                            CompileErrorVar::CannotInferType(var.name.clone()),
+                            Some(var.span),
+                        )
+                    })?;
+            }
+        }
+        Ok(Block {
+            scope: scope.cp(),
+            vars,
+            stmts,
+            span: blk.span,
+        })
+    }
+
+    fn stmt(&mut self, stmt: &ast::Stmt, scope: &Ptr<Scope>) -> CompileResult<Stmt> {
+        let var = maybe_grow(|| self.stmt_var(stmt, scope)).with_span(stmt.span)?;
+        Ok(Stmt {
+            var,
+            span: stmt.span,
+        })
+    }
+
+    fn stmt_var(&mut self, stmt: &ast::Stmt, scope: &Ptr<Scope>) -> CompileResult<StmtVariant> {
+        use ast::StmtVariant as S;
+        Ok(match &stmt.var {
+            S::If(i) => StmtVariant::If {
+                cond: self.cond(&i.cond, scope)?,
+                then: Box::new(self.stmt(&i.if_block.borrow(), scope)?),
+                else_ifs: (i.else_ifs.iter())
+                    .map(|(cond, blk)| {
+                        Ok((self.cond(cond, scope)?, self.stmt(&blk.borrow(), scope)?))
+                    })
+                    .collect::<CompileResult<_>>()?,
+                els: match &i.else_block {
+                    Some(blk) => Some(Box::new(self.stmt(&blk.borrow(), scope)?)),
+                    None => None,
+                },
+            },
+            S::While(w) => {
+                let cond = self.cond(&w.cond, scope)?;
+                let label = w.label.as_ref().map(|l| l.name.to_string());
+                self.loops.push(label.clone());
+                let body = self.stmt(&w.block.borrow(), scope);
+                self.loops.pop();
+                StmtVariant::While {
+                    label,
+                    cond,
+                    body: Box::new(body?),
+                }
+            }
+            S::Block(b) => StmtVariant::Block(self.block(b)?),
+            S::Expr(e) => StmtVariant::Exprs(vec![self.expr(e, scope)?]),
+            // * The parser turns initializers into assignments
+            S::ManyExpr(es) => StmtVariant::Exprs(
+                es.iter()
+                    .map(|e| {
+                        let mut e = self.expr(e, scope)?;
+                        e.origin = Some(SyntheticOrigin::Initializer);
+                        Ok(e)
+                    })
+                    .collect::<CompileResult<_>>()?,
+            ),
+            S::Print(es) => {
+                let es = (es.iter())
+                    .map(|e| self.expr(e, scope))
+                    .collect::<CompileResult<Vec<_>>>()?;
+                for e in &es {
+                    if !matches!(&*e.typ.borrow(), TypeDef::Primitive(_) | TypeDef::Ref(_)) {
+                        return Err(compile_err(
+                            CompileErrorVar::RequirePrintable(format!("{:?}", e.typ)),
+                            Some(e.span),
+                        ));
+                    }
+                }
+                StmtVariant::Print(es)
+            }
+            S::Scan(ident, _) => {
+                let (name, typ) = self.used_var(&ident.name, scope)?;
+                if !typ.borrow().is_primitive() {
+                    return Err(CompileErrorVar::RequireScannable(format!("{:?}", typ)).into());
+                }
+                StmtVariant::Scan(name)
+            }
+            S::Return(e) => {
+                let e = match e {
+                    Some(e) => Some(self.expr(e, scope)?),
+                    None => None,
+                };
+                let is_unit = self.ret.borrow().is_unit();
+                match e {
+                    Some(e) if !is_unit => {
+                        check_conv(&e.typ, &self.ret).with_span(e.span)?;
+                        StmtVariant::Return(Some(convert(e, &self.ret)))
+                    }
+                    None if is_unit => StmtVariant::Return(None),
+                    _ => {
+                        let ret = format!("{:?}", self.ret.borrow());
+                        return Err(CompileErrorVar::ReturnTypeMismatch(ret).into());
+                    }
+                }
+            }
+            S::Break(label) => {
+                match label {
+                    None if self.loops.is_empty() => Err(CompileErrorVar::NoTargetToBreak)?,
+                    Some(l) if !self.loops.contains(&Some(l.name.to_string())) => {
+                        Err(CompileErrorVar::NoLoopLabel(l.name.to_string()))?
+                    }
+                    _ => (),
+                }
+                StmtVariant::Break(label.as_ref().map(|l| l.name.to_string()))
+            }
+            S::Empty => StmtVariant::Empty,
+        })
+    }
+
+    /// Lower the condition of an `if` or `while`
+    fn cond(&mut self, expr: &Ptr<ast::Expr>, scope: &Ptr<Scope>) -> CompileResult<Expr> {
+        let cond = self.expr(expr, scope)?;
+        check_conv(&cond.typ, &bool_type()).with_span(cond.span)?;
+        Ok(cond)
+    }
+
+    /// Variable `name` seen from `scope`, with its type and whether it is
+    /// constant. The type is unknown for `auto` variables nothing has been
+    /// assigned to yet.
+    fn var(&self, name: &str, scope: &Ptr<Scope>) -> CompileResult<(NameRef, Type, bool)> {
+        let def = scope.borrow().find_def_depth(name);
+        let (def, id) = def.ok_or_else(|| CompileErrorVar::NonExistVar(name.into()))?;
+        let (mut typ, is_const) = match &*def.borrow() {
+            SymbolDef::Var { typ, is_const, .. } if !typ.borrow().is_fn() => {
+                (resolve(&typ.borrow(), scope)?, *is_const)
+            }
+            _ => return Err(CompileErrorVar::NonExistVar(name.into()).into()),
+        };
+        if *typ.borrow() == TypeDef::Unknown {
+            if let Some(inferred) = self.inferred.get(&(id, name.into())) {
+                typ = inferred.cp();
+            }
+        }
+        let name = NameRef {
+            name: name.into(),
+            def,
+        };
+        Ok((name, typ, is_const))
+    }
+
+    /// Variable `name` used for its value, which needs its type to be known
+    fn used_var(&self, name: &str, scope: &Ptr<Scope>) -> CompileResult<(NameRef, Type)> {
+        let (name, typ, _) = self.var(name, scope)?;
+        if *typ.borrow() == TypeDef::Unknown {
+            return Err(CompileErrorVar::CannotInferType(name.name).into());
+        }
+        Ok((name, typ))
+    }
+
+    fn expr(&mut self, expr: &Ptr<ast::Expr>, scope: &Ptr<Scope>) -> CompileResult<Expr> {
+        maybe_grow(|| {
+            let expr = expr.borrow();
+            let (var, typ) = self.expr_var(&expr, scope).with_span(expr.span)?;
+            Ok(Expr {
+                var,
+                typ,
+                span: expr.span,
+                origin: None,
+            })
+        })
+    }
+
+    fn expr_var(
+        &mut self,
+        expr: &ast::Expr,
+        scope: &Ptr<Scope>,
+    ) -> CompileResult<(ExprVariant, Type)> {
+        use ast::ExprVariant as E;
+        match &expr.var {
+            E::Ident(i) => {
+                let (name, typ) = self.used_var(&i.name, scope)?;
+                Ok((ExprVariant::Var(name), typ))
+            }
+            E::Literal(lit) => {
+                let typ = match lit {
+                    ast::Literal::Boolean { .. } => bool_type(),
+                    ast::Literal::Integer { .. } => prim(PrimitiveTypeVar::SignedInt, 4),
+                    ast::Literal::Char { .. } => prim(PrimitiveTypeVar::UnsignedInt, 1),
+                    ast::Literal::Float { .. } => prim(PrimitiveTypeVar::Float, 8),
+                    ast::Literal::String { .. } => Ptr::new(TypeDef::Ref(ast::RefType {
+                        target: prim(PrimitiveTypeVar::UnsignedInt, 1),
+                    })),
+                    ast::Literal::Struct { .. } => {
+                        return Err(CompileErrorVar::NotImplemented(
+                            "Structs are not yet supported".into(),
+                        )
+                        .into())
+                    }
+                };
+                Ok((ExprVariant::Literal(lit.clone()), typ))
+            }
+            E::TypeConversion(t) => {
+                let to = resolve(&t.to.borrow(), scope)?;
+                let val = self.expr(&t.expr, scope)?;
+                check_conv(&val.typ, &to)?;
+                Ok((ExprVariant::Conv(Box::new(val)), to))
+            }
+            E::UnaryOp(u) if u.op == OpVar::Ref => {
+                let name = match &u.val.borrow().var {
+                    E::Ident(i) => i.name,
+                    _ => {
+                        let val = format!("{}", u.val.borrow());
+                        return Err(CompileErrorVar::NotAddressable(val).into());
+                    }
+                };
+                let (name, typ, is_const) = self.var(&name, scope)?;
+                if *typ.borrow() == TypeDef::Unknown {
+                    return Err(CompileErrorVar::CannotInferType(name.name).into());
+                }
+                if is_const {
+                    return Err(CompileErrorVar::AddressOfConst(name.name).into());
+                }
+                let val = Expr {
+                    var: ExprVariant::Var(name),
+                    typ: typ.cp(),
+                    span: u.val.borrow().span,
+                    origin: None,
+                };
+                let typ = Ptr::new(TypeDef::Ref(ast::RefType { target: typ }));
+                Ok((ExprVariant::Unary(OpVar::Ref, Box::new(val)), typ))
+            }
+            E::UnaryOp(u) if u.op == OpVar::Der => {
+                let val = self.expr(&u.val, scope)?;
+                let typ = match &*val.typ.borrow() {
+                    TypeDef::Ref(r) => r.target.cp(),
+                    typ => return Err(CompileErrorVar::NotAReference(format!("{:?}", typ)).into()),
+                };
+                Ok((ExprVariant::Unary(OpVar::Der, Box::new(val)), typ))
+            }
+            E::UnaryOp(u) => {
+                let val = self.expr(&u.val, scope)?;
+                if val.typ.borrow().is_unit() {
+                    return Err(CompileErrorVar::AssignVoid.into());
+                }
+                if !matches!(u.op, OpVar::Neg | OpVar::Pos) {
+                    return Err(CompileErrorVar::UnsupportedOp.into());
+                }
+                let typ = val.typ.cp();
+                Ok((ExprVariant::Unary(u.op, Box::new(val)), typ))
+            }
+            E::BinaryOp(b) if b.op == OpVar::_Asn || b.op == OpVar::_Csn => {
+                let to = match &b.lhs.borrow().var {
+                    E::Ident(i) => i.name,
+                    E::UnaryOp(u) if u.op == OpVar::Der => {
+                        let to = self.expr(&b.lhs, scope)?;
+                        let typ = to.typ.cp();
+                        let val = self.expr(&b.rhs, scope)?;
+                        check_conv(&val.typ, &typ)?;
+                        let var = ExprVariant::Store {
+                            to: Box::new(to),
+                            val: Box::new(convert(val, &typ)),
+                        };
+                        return Ok((var, typ));
+                    }
+                    _ => {
+                        let lhs = format!("{}", b.lhs.borrow());
+                        return Err(CompileErrorVar::NotLValue(lhs)).with_span(b.lhs.borrow().span);
+                    }
+                };
+                let (to, mut typ, is_const) = self.var(&to, scope)?;
+                let is_init = b.op == OpVar::_Csn;
+                if is_const && !is_init {
+                    return Err(CompileErrorVar::AssignConst.into());
+                }
+                let val = self.expr(&b.rhs, scope)?;
+                if *typ.borrow() == TypeDef::Unknown {
+                    // * The first value assigned decides the type
+                    if val.typ.borrow().is_unit() {
+                        return Err(CompileErrorVar::VoidVariable(to.name).into());
+                    }
+                    let (_, id) = scope.borrow().find_def_depth(&to.name).unwrap();
+                    self.inferred.insert((id, to.name.clone()), val.typ.cp());
+                    typ = val.typ.cp();
+                }
+                check_conv(&val.typ, &typ)?;
+                let var = ExprVariant::Assign {
+                    to,
+                    val: Box::new(convert(val, &typ)),
+                    is_init,
+                };
+                Ok((var, typ))
+            }
+            E::BinaryOp(b) => {
+                let lhs = self.expr(&b.lhs, scope)?;
+                let rhs = self.expr(&b.rhs, scope)?;
+                let (operands, typ) = match b.op {
+                    OpVar::_Com => (None, rhs.typ.cp()),
+                    OpVar::Add | OpVar::Sub | OpVar::Mul | OpVar::Div => {
+                        let typ = unify(&lhs.typ, &rhs.typ)?;
+                        (Some(typ.cp()), typ)
+                    }
+                    OpVar::Gt | OpVar::Gte | OpVar::Lt | OpVar::Lte | OpVar::Eq | OpVar::Neq => {
+                        (Some(unify(&lhs.typ, &rhs.typ)?), bool_type())
+                    }
+                    OpVar::And | OpVar::Or => {
+                        if lhs.typ.borrow().is_unit() || rhs.typ.borrow().is_unit() {
+                            return Err(CompileErrorVar::AssignVoid.into());
+                        }
+                        (None, bool_type())
+                    }
+                    _ => return Err(CompileErrorVar::UnsupportedOp.into()),
+                };
+                let (lhs, rhs) = match operands {
+                    Some(to) => (convert(lhs, &to), convert(rhs, &to)),
+                    None => (lhs, rhs),
+                };
+                let var = ExprVariant::Binary(b.op, Box::new(lhs), Box::new(rhs));
+                Ok((var, typ))
+            }
+            E::FunctionCall(f) => {
+                let def = f
+                    .def(scope)
+                    .ok_or_else(|| CompileErrorVar::NonExistFunc(f.func.to_string()))?;
+                let typ = match &*def.borrow() {
+                    SymbolDef::Var { typ, .. } => resolve(&typ.borrow(), scope)?,
+                    SymbolDef::Typ { .. } => unreachable!(),
+                };
+                let typ = typ.borrow();
+                let func = match &*typ {
+                    TypeDef::Function(func) => func,
+                    _ => unreachable!(),
+                };
+                if f.params.len() != func.params.len() {
+                    return Err(CompileErrorVar::ParamLengthMismatch.into());
+                }
+                let args = (f.params.iter().zip(&func.params))
+                    .map(|(arg, param)| {
+                        let arg = self.expr(arg, scope)?;
+                        check_conv(&arg.typ, param).with_span(arg.span)?;
+                        Ok(convert(arg, param))
+                    })
+                    .collect::<CompileResult<_>>()?;
+                let func_ref = NameRef {
+                    name: f.func.to_string(),
+                    def: def.cp(),
+                };
+                let var = ExprVariant::Call {
+                    func: func_ref,
+                    args,
+                };
+                Ok((var, func.return_type.cp()))
+            }
+            E::StructChild(_) | E::ArrayChild(_) => Err(CompileErrorVar::NotImplemented(
+                "Implement other expression variants".into(),
+            )
+            .into()),
+        }
+    }
+}
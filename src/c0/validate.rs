@@ -0,0 +1,202 @@
+//! Structural checks on tokens and trees, for `--verify-each`.
+//!
+//! These hold for anything the lexer and parser produce, whatever the input,
+//! so a failure is a bug in the compiler. Checking right after each phase
+//! catches it there, instead of when a later phase trips over it.
+//!
+//! - Every span starts no later than it ends
+//! - Tokens, and statements of a block, come in source order
+//! - Expressions lie within the statement they are in, and parts of an
+//!   expression within it
+//! - Each block's scope has the scope around it as parent, and no scope is
+//!   used by two blocks
+//! - Every name used resolves to a variable, and every call to a function
+
+use super::ast::*;
+use super::lexer::Token;
+use crate::prelude::*;
+use core::fmt;
+
+/// A broken invariant
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Invalid {
+    pub span: Span,
+    pub what: String,
+}
+
+impl fmt::Display for Invalid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}", self.what, self.span)
+    }
+}
+
+type ValidateResult = Result<(), Invalid>;
+
+fn invalid(span: Span, what: impl Into<String>) -> ValidateResult {
+    Err(Invalid {
+        span,
+        what: what.into(),
+    })
+}
+
+fn check_span(span: Span) -> ValidateResult {
+    if span.start > span.end {
+        return invalid(span, "Span ends before it starts");
+    }
+    Ok(())
+}
+
+/// Check tokens from the lexer
+pub fn validate_tokens(tokens: &[Token]) -> ValidateResult {
+    let mut last: Option<Span> = None;
+    for tok in tokens {
+        check_span(tok.span)?;
+        if let Some(last) = last {
+            if tok.span.start < last.end {
+                return invalid(
+                    tok.span,
+                    format!("Token {:?} overlaps the one before", tok.var),
+                );
+            }
+        }
+        last = Some(tok.span);
+    }
+    Ok(())
+}
+
+/// Check a tree from the parser
+pub fn validate(prog: &Program) -> ValidateResult {
+    let mut v = Validator { scopes: vec![] };
+    if prog.blk.scope.borrow().last.is_some() {
+        return invalid(Span::zero(), "Global scope has a parent");
+    }
+    v.block(&prog.blk, None)
+}
+
+struct Validator {
+    /// Ids of scopes seen so far
+    scopes: Vec<usize>,
+}
+
+impl Validator {
+    /// Check `blk`, which is inside `parent`
+    fn block(&mut self, blk: &Block, parent: Option<&Ptr<Scope>>) -> ValidateResult {
+        let span = blk.span.unwrap_or_else(Span::zero);
+        let scope = &blk.scope;
+        let id = scope.borrow().id;
+        if self.scopes.contains(&id) {
+            return invalid(
+                span,
+                format!("Scope #{} is used by more than one block", id),
+            );
+        }
+        self.scopes.push(id);
+        let last = scope.borrow().last.as_ref().map(|last| last.borrow().id);
+        if let Some(parent) = parent {
+            if last != Some(parent.borrow().id) {
+                return invalid(
+                    span,
+                    format!("Scope #{} is not a child of the scope around it", id),
+                );
+            }
+        }
+
+        let defs: Vec<_> = scope.borrow().defs.values().map(|def| def.cp()).collect();
+        for def in defs {
+            if let SymbolDef::Var { typ, decl_span, .. } = &*def.borrow() {
+                check_span(*decl_span)?;
+                if let TypeDef::Function(func) = &*typ.borrow() {
+                    if let Some(body) = &func.body {
+                        self.block(body, Some(scope))?;
+                    }
+                }
+            }
+        }
+
+        let mut last: Option<Span> = None;
+        for stmt in &blk.stmts {
+            if let Some(last) = last {
+                if stmt.span.start < last.start {
+                    return invalid(stmt.span, "Statement comes before the one before it");
+                }
+            }
+            last = Some(stmt.span);
+            self.stmt(stmt, scope)?;
+        }
+        Ok(())
+    }
+
+    fn stmt(&mut self, stmt: &Stmt, scope: &Ptr<Scope>) -> ValidateResult {
+        check_span(stmt.span)?;
+        let span = stmt.span;
+        match &stmt.var {
+            StmtVariant::If(i) => {
+                self.expr(&i.cond, span, scope)?;
+                self.stmt(&i.if_block.borrow(), scope)?;
+                for (cond, blk) in &i.else_ifs {
+                    self.expr(cond, span, scope)?;
+                    self.stmt(&blk.borrow(), scope)?;
+                }
+                if let Some(blk) = &i.else_block {
+                    self.stmt(&blk.borrow(), scope)?;
+                }
+                Ok(())
+            }
+            StmtVariant::While(w) => {
+                self.expr(&w.cond, span, scope)?;
+                self.stmt(&w.block.borrow(), scope)
+            }
+            StmtVariant::Block(b) => self.block(b, Some(scope)),
+            StmtVariant::Expr(e) | StmtVariant::Return(Some(e)) => self.expr(e, span, scope),
+            StmtVariant::Print(es) | StmtVariant::ManyExpr(es) => {
+                es.iter().try_for_each(|e| self.expr(e, span, scope))
+            }
+            StmtVariant::Scan(ident, _) => self.var(&ident.name, span, scope),
+            StmtVariant::Return(None) | StmtVariant::Break(_) | StmtVariant::Empty => Ok(()),
+        }
+    }
+
+    /// Check `expr`, which is inside `outer`
+    fn expr(&mut self, expr: &Ptr<Expr>, outer: Span, scope: &Ptr<Scope>) -> ValidateResult {
+        maybe_grow(|| {
+            let expr = expr.borrow();
+            let span = expr.span;
+            check_span(span)?;
+            if !outer.contains(span) {
+                return invalid(span, format!("Expression is outside of {}", outer));
+            }
+            match &expr.var {
+                ExprVariant::Ident(i) => self.var(&i.name, span, scope),
+                ExprVariant::Literal(_) => Ok(()),
+                ExprVariant::TypeConversion(t) => self.expr(&t.expr, span, scope),
+                ExprVariant::UnaryOp(u) => self.expr(&u.val, span, scope),
+                ExprVariant::BinaryOp(b) => {
+                    self.expr(&b.lhs, span, scope)?;
+                    self.expr(&b.rhs, span, scope)
+                }
+                ExprVariant::FunctionCall(f) => {
+                    if f.def(scope).is_none() {
+                        return invalid(
+                            span,
+                            format!("Call to '{}', which is no function", f.func),
+                        );
+                    }
+                    f.params.iter().try_for_each(|p| self.expr(p, span, scope))
+                }
+                ExprVariant::StructChild(s) => self.expr(&s.val, span, scope),
+                ExprVariant::ArrayChild(a) => {
+                    self.expr(&a.val, span, scope)?;
+                    self.expr(&a.idx, span, scope)
+                }
+            }
+        })
+    }
+
+    /// Check that `name` is a variable seen from `scope`
+    fn var(&self, name: &str, span: Span, scope: &Ptr<Scope>) -> ValidateResult {
+        match scope.borrow().find_def(name) {
+            Some(def) if matches!(&*def.borrow(), SymbolDef::Var { .. }) => Ok(()),
+            _ => invalid(span, format!("Name '{}' is not a declared variable", name)),
+        }
+    }
+}
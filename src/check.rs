@@ -0,0 +1,151 @@
+//! `chigusa check`: report errors without generating any output.
+
+use crate::config;
+use crate::err_disp;
+use crate::exit::Exit;
+use crate::ice;
+use crate::opt::ParserConfig;
+use crate::source::{LoadError, Source};
+use chigusa::minivm::Codegen;
+use chigusa::{CompileError, Diagnostic, LintRules, Severity, Standard, Target};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Check `files`, or stdin if there are none, printing every diagnostic
+/// unless `--quiet` is given. Stops after `--max-errors` errors.
+pub fn check(files: &[PathBuf], target: Target, standard: Standard, opt: &ParserConfig) -> Exit {
+    let cwd = std::env::current_dir().unwrap_or_default();
+    let rules = match config::lint_rules(&cwd) {
+        Ok(rules) => rules,
+        Err(e) => {
+            eprintln!("{}", e);
+            return Exit::CompileError;
+        }
+    };
+    if files.is_empty() {
+        let mut src = String::new();
+        if let Err(e) = std::io::stdin().read_to_string(&mut src) {
+            if !opt.quiet {
+                eprintln!("<stdin>: cannot read: {}", e);
+            }
+            return Exit::IoError;
+        }
+        let mut errors = 0;
+        ice::set_source(None, &src);
+        return report(
+            Path::new("<stdin>"),
+            &check_src(&src, target, standard, &rules, opt),
+            opt,
+            &mut errors,
+        );
+    }
+
+    let mut worst = Exit::Success;
+    let mut errors = 0;
+    for file in files {
+        let exit = match std::fs::read_to_string(file) {
+            Ok(src) => {
+                ice::set_source(Some(file), &src);
+                report(
+                    file,
+                    &check_src(&src, target, standard, &rules, opt),
+                    opt,
+                    &mut errors,
+                )
+            }
+            Err(e) => {
+                if !opt.quiet {
+                    eprintln!("{}: cannot read file: {}", file.display(), e);
+                }
+                errors += 1;
+                Exit::IoError
+            }
+        };
+        worst = worst.max(exit);
+        if opt.max_errors.is_some_and(|max| errors >= max) {
+            break;
+        }
+    }
+    worst
+}
+
+/// Every diagnostic of `src`: the first error stopping it from compiling,
+/// if any, and the warnings of lints
+pub fn check_src(
+    src: &str,
+    target: Target,
+    standard: Standard,
+    rules: &LintRules,
+    opt: &ParserConfig,
+) -> Vec<Diagnostic> {
+    ice::set_phase("parse");
+    let src = match Source::new(src.into(), &opt.defines) {
+        Ok(src) => src,
+        Err(e) => return vec![e.into()],
+    };
+    let mut diags = match chigusa::parse(src.compiled()) {
+        Ok(prog) => {
+            ice::set_phase("check");
+            let codegen = Codegen::new(&prog)
+                .with_target(target)
+                .with_standard(standard)
+                .with_allow_overflow(opt.allow_overflow);
+            let mut diags = match codegen.check() {
+                Ok(()) => vec![],
+                Err(e) => vec![CompileError::from(e).into()],
+            };
+            diags.extend(chigusa::lint_with(&prog, rules).expect("Rules are checked when loaded"));
+            diags
+        }
+        Err(e) => vec![e.into()],
+    };
+    for d in &mut diags {
+        if let Some(span) = d.span {
+            d.notes.extend(src.map.notes(span));
+        }
+    }
+    diags
+}
+
+/// Print `diags` and their notes until there have been `--max-errors` in all,
+/// counting them in `errors`. Warnings are printed but not counted, and do not
+/// fail the check.
+pub fn report(file: &Path, diags: &[Diagnostic], opt: &ParserConfig, errors: &mut usize) -> Exit {
+    let mut worst = Exit::Success;
+    for d in diags {
+        if opt.max_errors.is_some_and(|max| *errors >= max) {
+            break;
+        }
+        if !opt.quiet {
+            eprintln!("{}", err_disp::concise(file, d));
+            for note in &d.notes {
+                eprintln!("{}", err_disp::concise_note(file, note));
+            }
+        }
+        if d.severity == Severity::Warning {
+            continue;
+        }
+        *errors += 1;
+        worst = worst.max(Exit::of(d.code));
+    }
+    worst
+}
+
+/// Print why `file` could not be loaded, as [`report`] prints diagnostics
+pub fn load_failed(file: &Path, e: LoadError, opt: &ParserConfig, errors: &mut usize) -> Exit {
+    match e {
+        LoadError::Io(e) => {
+            *errors += 1;
+            io_error(file, "cannot read file", &e, opt)
+        }
+        LoadError::Compile(e) => report(file, &[e.into()], opt, errors),
+    }
+}
+
+/// Print that `path` could not be read or written, unless `--quiet` is given
+pub fn io_error(path: &Path, doing: &str, e: &std::io::Error, opt: &ParserConfig) -> Exit {
+    if !opt.quiet {
+        eprintln!("{}: {}: {}", path.display(), doing, e);
+    }
+    Exit::IoError
+}
@@ -0,0 +1,169 @@
+//! `chigusa.toml`: settings for a whole project.
+//!
+//! The file closest to the current directory or its ancestors is used:
+//!
+//! ```toml
+//! sources = ["main.c0", "lib_test.c0"] # compiled when no file is given
+//! output = "out"      # output file, with one source
+//! out_dir = "build"   # output directory; files are named after sources
+//! target = "o0"
+//! opt_level = 1       # 0 turns off optimizations, 2 adds value numbering
+//! passes = "fold,dce" # passes to run instead of those of `opt_level`
+//! unroll_factor = 8   # copies of a loop body at most, below 2 for none
+//! unroll_budget = 64  # statements and expressions of all copies at most
+//! debug_info = false
+//! max_stack_depth = 64
+//! max_frame_size = 1024
+//!
+//! [lint]              # rules `chigusa check` enforces, see `LintRules`
+//! forbid_recursion = true
+//! ```
+//!
+//! Paths are relative to the file. Flags given on the command line win over
+//! the file. C0 has no `#include`, so there are no settings for include
+//! paths.
+
+use crate::opt::ParserConfig;
+use chigusa::{LintRules, Query};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+const CONFIG_FILE: &str = "chigusa.toml";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default)]
+    sources: Vec<PathBuf>,
+    output: Option<PathBuf>,
+    out_dir: Option<PathBuf>,
+    target: Option<String>,
+    opt_level: Option<u8>,
+    passes: Option<String>,
+    unroll_factor: Option<u32>,
+    unroll_budget: Option<usize>,
+    debug_info: Option<bool>,
+    max_stack_depth: Option<usize>,
+    max_frame_size: Option<usize>,
+    #[serde(default)]
+    lint: LintRules,
+}
+
+/// A file to compile, and where its output goes. No input means stdin.
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub input: Option<PathBuf>,
+    pub output: PathBuf,
+}
+
+/// Fill in settings not given on the command line from the config file
+/// closest to `dir`, and list what to compile
+pub fn merge(opt: &mut ParserConfig, dir: &Path) -> Result<Vec<Job>, String> {
+    let (root, file) = match load(dir)? {
+        Some((root, file)) => (root, file),
+        None => (PathBuf::new(), ConfigFile::default()),
+    };
+
+    if opt.target.is_none() {
+        opt.target = file.target.clone();
+    }
+    if opt.opt_level.is_none() {
+        opt.opt_level = file.opt_level;
+    }
+    if opt.passes.is_none() {
+        opt.passes = file.passes.clone();
+    }
+    if opt.unroll_factor.is_none() {
+        opt.unroll_factor = file.unroll_factor;
+    }
+    if opt.unroll_budget.is_none() {
+        opt.unroll_budget = file.unroll_budget;
+    }
+    if opt.max_stack_depth.is_none() {
+        opt.max_stack_depth = file.max_stack_depth;
+    }
+    if opt.max_frame_size.is_none() {
+        opt.max_frame_size = file.max_frame_size;
+    }
+    opt.debug_info |= file.debug_info.unwrap_or(false);
+
+    // * A file on the command line replaces the project's sources
+    let sources: Vec<_> = match &opt.input_file {
+        Some(input) => vec![input.clone()],
+        None => file.sources.iter().map(|s| root.join(s)).collect(),
+    };
+    let out_dir = match (&opt.output_file, &file.out_dir) {
+        (Some(out), _) if sources.len() > 1 => Some(out.clone()),
+        (Some(_), _) => None,
+        (None, Some(dir)) => Some(root.join(dir)),
+        (None, None) if sources.len() > 1 => Some(root.clone()),
+        (None, None) => None,
+    };
+
+    let output = (opt.output_file.clone())
+        .or_else(|| file.output.as_ref().map(|out| root.join(out)))
+        .unwrap_or_else(|| opt.output_path().to_owned());
+
+    if sources.is_empty() {
+        return Ok(vec![Job {
+            input: None,
+            output,
+        }]);
+    }
+    if let Some(dir) = &out_dir {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+    }
+    let ext = opt.emit.extension();
+    Ok(sources
+        .into_iter()
+        .map(|input| {
+            let output = match &out_dir {
+                Some(dir) => {
+                    let stem = input.file_stem().unwrap_or_else(|| input.as_os_str());
+                    dir.join(stem).with_extension(ext)
+                }
+                None => output.clone(),
+            };
+            Job {
+                input: Some(input),
+                output,
+            }
+        })
+        .collect())
+}
+
+/// Lint rules of the config file closest to `dir`, none if there is no file.
+/// Fails if a construct they forbid is not a valid query.
+pub fn lint_rules(dir: &Path) -> Result<LintRules, String> {
+    let (root, file) = match load(dir)? {
+        Some(found) => found,
+        None => return Ok(LintRules::default()),
+    };
+    for forbidden in &file.lint.forbid {
+        Query::parse(&forbidden.query).map_err(|e| {
+            let path = root.join(CONFIG_FILE);
+            format!("bad config file {}: {}", path.display(), e)
+        })?;
+    }
+    Ok(file.lint)
+}
+
+/// Read the config file closest to `dir`, with the directory it is in
+fn load(dir: &Path) -> Result<Option<(PathBuf, ConfigFile)>, String> {
+    let path = match dir
+        .ancestors()
+        .map(|dir| dir.join(CONFIG_FILE))
+        .find(|path| path.is_file())
+    {
+        Some(path) => path,
+        None => return Ok(None),
+    };
+
+    let file = std::fs::read_to_string(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|s| toml::from_str::<ConfigFile>(&s).map_err(|e| e.to_string()))
+        .map_err(|e| format!("bad config file {}: {}", path.display(), e))?;
+    let root = path.parent().map(Path::to_owned).unwrap_or_default();
+    Ok(Some((root, file)))
+}
@@ -0,0 +1,456 @@
+//! Evaluating constant expressions at compile time.
+//!
+//! An expression is constant if it is made of literals, `const` variables,
+//! operators, type conversions and calls to pure functions, so it has no
+//! side effects. A function is pure if it reads no variables but its own and
+//! constants, writes none but its own, and does no input or output; calls
+//! are run, so it only needs to be pure on the paths taken. Operators work as
+//! in the VM, through the same [`Value`](crate::consteval::Value)s. Where the
+//! VM would wrap around, evaluation fails with
+//! [`EvalError::Overflow`](crate::consteval::EvalError::Overflow) instead, as a
+//! constant that silently changed is never what was meant.
+//!
+//! Evaluation has [`FUEL`](crate::consteval::FUEL) steps to finish in, so a
+//! constant that loops forever can't hang the compiler.
+
+use crate::c0::ast::*;
+use crate::c0::num;
+use crate::c0::symbol::Symbol;
+use crate::error::{CompileError, ErrorCode, Stage};
+use crate::prelude::*;
+use alloc::collections::BTreeMap;
+pub use chigusa_minivm::value::{BinOp, Kind, UnOp, Value, ValueError};
+use core::fmt;
+
+/// Why an expression has no constant value
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum EvalError {
+    /// Part of the expression is only known when the program runs, like a
+    /// variable or a function call
+    NotConstant(Span),
+    /// The value does not fit in its type
+    Overflow(Span),
+    DivideByZero(Span),
+    /// Evaluation ran out of fuel or calls nested too deep, as in a loop
+    /// that never ends
+    OutOfFuel(Span),
+}
+
+/// Statements and expressions [`eval`] evaluates at most
+pub const FUEL: u64 = 1_000_000;
+
+/// Calls [`eval`] nests at most
+pub const MAX_CALL_DEPTH: usize = 256;
+
+impl EvalError {
+    pub fn code(&self) -> ErrorCode {
+        ErrorCode(match self {
+            EvalError::NotConstant(_) => 316,
+            EvalError::Overflow(_) => 317,
+            EvalError::DivideByZero(_) => 318,
+            EvalError::OutOfFuel(_) => 323,
+        })
+    }
+
+    /// The part of the expression at fault
+    pub fn span(&self) -> Span {
+        match self {
+            EvalError::NotConstant(span)
+            | EvalError::Overflow(span)
+            | EvalError::DivideByZero(span)
+            | EvalError::OutOfFuel(span) => *span,
+        }
+    }
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::NotConstant(_) => write!(f, "Expression is not a constant"),
+            EvalError::Overflow(_) => write!(f, "Constant expression overflows its type"),
+            EvalError::DivideByZero(_) => write!(f, "Division by zero in a constant expression"),
+            EvalError::OutOfFuel(_) => {
+                write!(f, "Constant expression takes too long to evaluate")
+            }
+        }
+    }
+}
+
+impl EvalError {
+    fn of(e: ValueError, span: Span) -> EvalError {
+        match e {
+            ValueError::Overflow => EvalError::Overflow(span),
+            ValueError::DivideByZero => EvalError::DivideByZero(span),
+            ValueError::Void | ValueError::Unsupported(_) => EvalError::NotConstant(span),
+        }
+    }
+}
+
+impl From<EvalError> for CompileError {
+    fn from(e: EvalError) -> Self {
+        CompileError::new(e.code(), Stage::Compile, e.to_string()).with_span(e.span())
+    }
+}
+
+/// Evaluate `expr`, looking up the names it uses in `scope`
+pub fn eval(expr: &Expr, scope: &Ptr<Scope>) -> Result<Value, EvalError> {
+    eval_with_fuel(expr, scope, FUEL)
+}
+
+/// Evaluate `expr` as [`eval`] does, in at most `fuel` steps
+pub fn eval_with_fuel(expr: &Expr, scope: &Ptr<Scope>, fuel: u64) -> Result<Value, EvalError> {
+    Evaluator {
+        fuel,
+        frames: vec![],
+    }
+    .eval(expr, scope)
+}
+
+/// A variable of a function being called
+struct Local {
+    val: Value,
+    kind: Kind,
+}
+
+/// What a statement asks its enclosing statements to do next
+enum Flow {
+    Normal,
+    Break(Option<Symbol>),
+    Return(Value),
+}
+
+struct Evaluator {
+    fuel: u64,
+    /// Block scopes of every active call, innermost last
+    frames: Vec<Vec<BTreeMap<Symbol, Local>>>,
+}
+
+impl Evaluator {
+    fn tick(&mut self, span: Span) -> Result<(), EvalError> {
+        self.fuel = self.fuel.checked_sub(1).ok_or(EvalError::OutOfFuel(span))?;
+        Ok(())
+    }
+
+    fn local(&mut self, name: Symbol) -> Option<&mut Local> {
+        let frame = self.frames.last_mut()?;
+        frame.iter_mut().rev().find_map(|vars| vars.get_mut(&name))
+    }
+
+    fn eval(&mut self, expr: &Expr, scope: &Ptr<Scope>) -> Result<Value, EvalError> {
+        maybe_grow(|| self.eval_inner(expr, scope))
+    }
+
+    fn eval_inner(&mut self, expr: &Expr, scope: &Ptr<Scope>) -> Result<Value, EvalError> {
+        let span = expr.span;
+        let fail = |e| EvalError::of(e, span);
+        self.tick(span)?;
+        match &expr.var {
+            ExprVariant::Literal(lit) => literal(lit).map_err(fail),
+            ExprVariant::Ident(ident) => {
+                if let Some(local) = self.local(ident.name) {
+                    return Ok(local.val.clone());
+                }
+                let (def, def_scope) =
+                    find(scope, &ident.name).ok_or(EvalError::NotConstant(span))?;
+                let value = match &*def.borrow() {
+                    SymbolDef::Var {
+                        is_const: true,
+                        value: Some(value),
+                        ..
+                    } => value.cp(),
+                    _ => return Err(EvalError::NotConstant(span)),
+                };
+                // * A constant is evaluated where it is declared, outside any
+                // * call
+                let frames = core::mem::take(&mut self.frames);
+                let val = self.eval(&value.borrow(), &def_scope);
+                self.frames = frames;
+                val
+            }
+            ExprVariant::TypeConversion(conv) => {
+                let val = self.eval(&conv.expr.borrow(), scope)?;
+                let kind = kind_of(&conv.to.borrow(), scope).ok_or(EvalError::NotConstant(span))?;
+                val.checked_cast(kind).map_err(fail)
+            }
+            ExprVariant::UnaryOp(op) => {
+                let val = self.eval(&op.val.borrow(), scope)?;
+                let op = un_op(op.op).ok_or(EvalError::NotConstant(span))?;
+                val.checked_unary(op).map_err(fail)
+            }
+            ExprVariant::BinaryOp(op) => self.eval_bin_op(op, span, scope),
+            ExprVariant::FunctionCall(call) => {
+                let args = (call.params.iter())
+                    .map(|arg| self.eval(&arg.borrow(), scope))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.call(&call.func, args, span, scope)
+            }
+            _ => Err(EvalError::NotConstant(span)),
+        }
+    }
+
+    fn eval_bin_op(
+        &mut self,
+        op: &BinaryOp,
+        span: Span,
+        scope: &Ptr<Scope>,
+    ) -> Result<Value, EvalError> {
+        let fail = |e| EvalError::of(e, span);
+        match op.op {
+            // * Only variables of the function being called can change
+            OpVar::_Asn | OpVar::_Csn => {
+                let name = match &op.lhs.borrow().var {
+                    ExprVariant::Ident(ident) => ident.name,
+                    _ => return Err(EvalError::NotConstant(span)),
+                };
+                let val = self.eval(&op.rhs.borrow(), scope)?;
+                let local = self.local(name).ok_or(EvalError::NotConstant(span))?;
+                local.val = val.checked_cast(local.kind).map_err(fail)?;
+                return Ok(local.val.clone());
+            }
+            OpVar::_Com => {
+                self.eval(&op.lhs.borrow(), scope)?;
+                return self.eval(&op.rhs.borrow(), scope);
+            }
+            _ => (),
+        }
+
+        let lhs = self.eval(&op.lhs.borrow(), scope)?;
+        // * The right side of `&&` and `||` is not run, so it may be anything
+        let short_circuit = match op.op {
+            OpVar::And => Some(false),
+            OpVar::Or => Some(true),
+            _ => None,
+        };
+        if let Some(stop) = short_circuit {
+            if lhs.is_true().map_err(fail)? == stop {
+                return Ok(Value::Int(stop as i32));
+            }
+            let rhs = self.eval(&op.rhs.borrow(), scope)?;
+            return Ok(Value::Int(rhs.is_true().map_err(fail)? as i32));
+        }
+        let rhs = self.eval(&op.rhs.borrow(), scope)?;
+        let op = bin_op(op.op).ok_or(EvalError::NotConstant(span))?;
+        lhs.checked_binary(op, &rhs).map_err(fail)
+    }
+
+    /// Run function `name` on `args`
+    fn call(
+        &mut self,
+        name: &str,
+        args: Vec<Value>,
+        span: Span,
+        scope: &Ptr<Scope>,
+    ) -> Result<Value, EvalError> {
+        let not_constant = EvalError::NotConstant(span);
+        let (def, _) = find(scope, name).ok_or(not_constant.clone())?;
+        let typ = def.borrow().get_sym().ok_or(not_constant.clone())?.0;
+        let typ = typ.borrow();
+        let (func, body) = match &*typ {
+            TypeDef::Function(func) => match &func.body {
+                Some(body) if args.len() == func.params.len() => (func, body),
+                _ => return Err(not_constant),
+            },
+            _ => return Err(not_constant),
+        };
+        if self.frames.len() >= MAX_CALL_DEPTH {
+            return Err(EvalError::OutOfFuel(span));
+        }
+
+        // * Parameters are the first symbols declared in the function body
+        let mut params = BTreeMap::new();
+        let names = body.scope.borrow().defs.keys().cloned().collect::<Vec<_>>();
+        for ((name, typ), arg) in names.into_iter().zip(&func.params).zip(args) {
+            let kind = kind_of(&typ.borrow(), &body.scope).ok_or(not_constant.clone())?;
+            let val = arg.checked_cast(kind).map_err(|e| EvalError::of(e, span))?;
+            params.insert(name, Local { val, kind });
+        }
+        let ret = kind_of(&func.return_type.borrow(), &body.scope).ok_or(not_constant.clone())?;
+
+        self.frames.push(vec![params]);
+        let flow = self.exec_stmts(&body.stmts, &body.scope);
+        self.frames.pop();
+
+        match flow? {
+            Flow::Return(val) => val.checked_cast(ret).map_err(|e| EvalError::of(e, span)),
+            Flow::Normal if ret == Kind::Void => Ok(Value::Void),
+            _ => Err(not_constant),
+        }
+    }
+
+    fn exec_stmts(&mut self, stmts: &[Stmt], scope: &Ptr<Scope>) -> Result<Flow, EvalError> {
+        for stmt in stmts {
+            match self.exec_stmt(stmt, scope)? {
+                Flow::Normal => (),
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn exec_stmt(&mut self, stmt: &Stmt, scope: &Ptr<Scope>) -> Result<Flow, EvalError> {
+        maybe_grow(|| self.exec_stmt_inner(stmt, scope))
+    }
+
+    fn exec_stmt_inner(&mut self, stmt: &Stmt, scope: &Ptr<Scope>) -> Result<Flow, EvalError> {
+        let span = stmt.span;
+        self.tick(span)?;
+        match &stmt.var {
+            StmtVariant::Empty => (),
+            StmtVariant::Expr(expr) => {
+                self.eval(&expr.borrow(), scope)?;
+            }
+            StmtVariant::ManyExpr(inits) => {
+                // * Variables come to life at their declaration, so an
+                // * initializer can still see the shadowed outer variable
+                for (name, def) in scope.borrow().defs_in(span) {
+                    let typ = def
+                        .borrow()
+                        .get_sym()
+                        .ok_or(EvalError::NotConstant(span))?
+                        .0;
+                    let kind = kind_of(&typ.borrow(), scope).ok_or(EvalError::NotConstant(span))?;
+                    let init = inits.iter().find_map(|init| match &init.borrow().var {
+                        ExprVariant::BinaryOp(op) => match &op.lhs.borrow().var {
+                            ExprVariant::Ident(ident) if ident.name == name => Some(op.rhs.cp()),
+                            _ => None,
+                        },
+                        _ => None,
+                    });
+                    let val = match init {
+                        Some(init) => {
+                            let val = self.eval(&init.borrow(), scope)?;
+                            val.checked_cast(kind).map_err(|e| EvalError::of(e, span))?
+                        }
+                        None => kind.zero(),
+                    };
+                    let frame = self.frames.last_mut().ok_or(EvalError::NotConstant(span))?;
+                    frame.last_mut().unwrap().insert(name, Local { val, kind });
+                }
+            }
+            StmtVariant::Block(block) => {
+                self.frames.last_mut().unwrap().push(BTreeMap::new());
+                let flow = self.exec_stmts(&block.stmts, &block.scope);
+                self.frames.last_mut().unwrap().pop();
+                return flow;
+            }
+            StmtVariant::If(cond) => {
+                if self.is_true(&cond.cond, scope)? {
+                    return self.exec_stmt(&cond.if_block.borrow(), scope);
+                }
+                for (cond, body) in &cond.else_ifs {
+                    if self.is_true(cond, scope)? {
+                        return self.exec_stmt(&body.borrow(), scope);
+                    }
+                }
+                if let Some(body) = &cond.else_block {
+                    return self.exec_stmt(&body.borrow(), scope);
+                }
+            }
+            StmtVariant::While(w) => {
+                let label = w.label.as_ref().map(|l| l.name);
+                while self.is_true(&w.cond, scope)? {
+                    match self.exec_stmt(&w.block.borrow(), scope)? {
+                        Flow::Normal => (),
+                        Flow::Break(None) => break,
+                        Flow::Break(Some(l)) if Some(l) == label => break,
+                        flow => return Ok(flow),
+                    }
+                }
+            }
+            StmtVariant::Break(label) => {
+                return Ok(Flow::Break(label.as_ref().map(|l| l.name)));
+            }
+            StmtVariant::Return(val) => {
+                let val = match val {
+                    Some(val) => self.eval(&val.borrow(), scope)?,
+                    None => Value::Void,
+                };
+                return Ok(Flow::Return(val));
+            }
+            StmtVariant::Print(_) | StmtVariant::Scan(..) => {
+                return Err(EvalError::NotConstant(span))
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn is_true(&mut self, cond: &Ptr<Expr>, scope: &Ptr<Scope>) -> Result<bool, EvalError> {
+        let cond = cond.borrow();
+        let val = self.eval(&cond, scope)?;
+        val.is_true().map_err(|e| EvalError::of(e, cond.span))
+    }
+}
+
+/// Where `name` is defined, and the scope it is defined in
+fn find(scope: &Ptr<Scope>, name: &str) -> Option<(Ptr<SymbolDef>, Ptr<Scope>)> {
+    let mut scope = scope.cp();
+    loop {
+        if let Some(def) = scope.borrow().find_def_self(name) {
+            return Some((def, scope.cp()));
+        }
+        let last = scope.borrow().last.as_ref()?.cp();
+        scope = last;
+    }
+}
+
+/// The value of `lit`. Strings and structs are not values.
+pub(crate) fn literal(lit: &Literal) -> Result<Value, ValueError> {
+    match lit {
+        Literal::Integer { val } => num::to_i32(val).map(Value::Int).ok_or(ValueError::Overflow),
+        Literal::Float { val } => Ok(Value::Double(num::to_f64(val))),
+        Literal::Char { val } => Ok(Value::Char(*val as u32 as u8)),
+        Literal::Boolean { val } => Ok(Value::Bool(*val)),
+        Literal::String { .. } => Err(ValueError::Unsupported("String".into())),
+        Literal::Struct { .. } => Err(ValueError::Unsupported("Struct".into())),
+    }
+}
+
+/// Which kind of value `typ` holds, if it is a primitive type or `void`
+pub(crate) fn kind_of(typ: &TypeDef, scope: &Ptr<Scope>) -> Option<Kind> {
+    match typ {
+        TypeDef::Unit => Some(Kind::Void),
+        TypeDef::Primitive(p) => Some(match p.var {
+            PrimitiveTypeVar::Float => Kind::Double,
+            PrimitiveTypeVar::UnsignedInt if p.occupy_bytes == 1 => Kind::Char,
+            PrimitiveTypeVar::UnsignedInt => Kind::UInt,
+            PrimitiveTypeVar::SignedInt => Kind::Int,
+        }),
+        TypeDef::NamedType(name) => {
+            let def = scope.borrow().find_def(name)?.borrow().get_typ()?;
+            let typ = def.borrow();
+            kind_of(&typ, scope)
+        }
+        _ => None,
+    }
+}
+
+/// The operator computed by binary `op`, if it computes a value
+pub(crate) fn bin_op(op: OpVar) -> Option<BinOp> {
+    Some(match op {
+        OpVar::Add => BinOp::Add,
+        OpVar::Sub => BinOp::Sub,
+        OpVar::Mul => BinOp::Mul,
+        OpVar::Div => BinOp::Div,
+        OpVar::Ban => BinOp::BitAnd,
+        OpVar::Bor => BinOp::BitOr,
+        OpVar::Xor => BinOp::BitXor,
+        OpVar::Lt => BinOp::Lt,
+        OpVar::Gt => BinOp::Gt,
+        OpVar::Lte => BinOp::Le,
+        OpVar::Gte => BinOp::Ge,
+        OpVar::Eq => BinOp::Eq,
+        OpVar::Neq => BinOp::Ne,
+        _ => return None,
+    })
+}
+
+/// The operator computed by unary `op`, if it computes a value
+pub(crate) fn un_op(op: OpVar) -> Option<UnOp> {
+    Some(match op {
+        OpVar::Neg => UnOp::Neg,
+        OpVar::Pos => UnOp::Pos,
+        OpVar::Inv => UnOp::Not,
+        OpVar::Bin => UnOp::BitNot,
+        _ => return None,
+    })
+}
@@ -0,0 +1,130 @@
+//! `chigusa cov`: line coverage of a program over its test inputs, and
+//! `chigusa cov-report`: the same from counts an instrumented build printed.
+
+use crate::check::{io_error, load_failed, report};
+use crate::exit::Exit;
+use crate::opt::ParserConfig;
+use crate::source;
+use chigusa::minivm::vm::{intrinsic_sigs, Coverage, Intrinsics, MiniVM};
+use chigusa::minivm::{render_coverage, Codegen, CounterMap, CoverageMap};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// Print the coverage of `file`, compiled with the features `-D` defines,
+/// run on each of `inputs`. Errors are reported as `chigusa check` reports
+/// them.
+pub fn cov(file: &Path, inputs: &[PathBuf], steps: u64, opt: &ParserConfig) -> Exit {
+    match cov_file(file, inputs, steps, opt) {
+        Ok(report) => {
+            print!("{}", report);
+            Exit::Success
+        }
+        Err(exit) => exit,
+    }
+}
+
+fn cov_file(
+    file: &Path,
+    inputs: &[PathBuf],
+    steps: u64,
+    opt: &ParserConfig,
+) -> Result<String, Exit> {
+    let mut errors = 0;
+    let src =
+        source::load(file, &opt.defines).map_err(|e| load_failed(file, e, opt, &mut errors))?;
+    let inputs = if inputs.is_empty() {
+        vec![std::fs::read(file.with_extension("in")).unwrap_or_default()]
+    } else {
+        (inputs.iter())
+            .map(|path| {
+                std::fs::read(path).map_err(|e| io_error(path, "cannot read input", &e, opt))
+            })
+            .collect::<Result<_, _>>()?
+    };
+
+    let o0 = match chigusa::parse_with_host(src.compiled(), &intrinsic_sigs()) {
+        Ok(prog) => Codegen::new(&prog)
+            .with_debug_info(true)
+            .compile()
+            .map_err(|e| src.annotate(e)),
+        Err(e) => Err(src.annotate(e)),
+    };
+    let o0 = o0.map_err(|e| report(file, &[e.into()], opt, &mut errors))?;
+    let map = CoverageMap::new(&o0).expect("Compiled with debug info");
+
+    let mut coverage = Coverage::new(&o0);
+    for (idx, input) in inputs.iter().enumerate() {
+        let mut input = input.as_slice();
+        let mut output = vec![];
+        let mut vm = MiniVM::new(&o0, &mut input, &mut output)
+            .with_step_limit(steps)
+            .with_coverage()
+            // * The same lines every time, whatever `rand` and `clock` give
+            .with_intrinsics(Intrinsics {
+                seed: Some(0),
+                virtual_clock: true,
+            });
+        // * Failing tests still tell which lines they ran
+        match vm.run() {
+            Err(e) if !opt.quiet => eprintln!("run {}: runtime error: {}", idx + 1, e),
+            _ => {}
+        }
+        coverage.merge(vm.coverage().expect("Coverage was asked for"));
+    }
+
+    Ok(render_coverage(&src.text, &map.line_counts(&coverage)))
+}
+
+/// Print the coverage of `file` from the counts printed in each of
+/// `outputs`, or stdin, by a build whose counters `counters` maps. Files that
+/// cannot be read are reported as `chigusa check` reports them.
+pub fn cov_report(file: &Path, counters: &Path, outputs: &[PathBuf], opt: &ParserConfig) -> Exit {
+    match report_file(file, counters, outputs, opt) {
+        Ok(report) => {
+            print!("{}", report);
+            Exit::Success
+        }
+        Err(exit) => exit,
+    }
+}
+
+fn report_file(
+    file: &Path,
+    counters: &Path,
+    outputs: &[PathBuf],
+    opt: &ParserConfig,
+) -> Result<String, Exit> {
+    let src =
+        std::fs::read_to_string(file).map_err(|e| io_error(file, "cannot read file", &e, opt))?;
+    let map = std::fs::read_to_string(counters).and_then(|m| {
+        CounterMap::from_json(&m)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+    });
+    let map = map.map_err(|e| io_error(counters, "cannot read counters", &e, opt))?;
+
+    let outputs = if outputs.is_empty() {
+        let mut output = String::new();
+        (io::stdin().read_to_string(&mut output))
+            .map_err(|e| io_error(Path::new("<stdin>"), "cannot read", &e, opt))?;
+        vec![("stdin".to_string(), output)]
+    } else {
+        (outputs.iter())
+            .map(|path| {
+                std::fs::read_to_string(path)
+                    .map(|output| (path.display().to_string(), output))
+                    .map_err(|e| io_error(path, "cannot read output", &e, opt))
+            })
+            .collect::<Result<_, _>>()?
+    };
+
+    let mut counts = vec![0; map.counters.len()];
+    for (name, output) in outputs {
+        // * A run that stopped before `main` returned printed no counts
+        match map.parse_dump(&output) {
+            Some(run) => counts.iter_mut().zip(run).for_each(|(c, r)| *c += r),
+            None if !opt.quiet => eprintln!("{}: no counts of this program", name),
+            None => {}
+        }
+    }
+    Ok(render_coverage(&src, &map.line_counts(&counts)))
+}
@@ -275,7 +275,7 @@ where
             }
             ast::StmtVariant::Return(e) => todo!("Generate code for return"),
             ast::StmtVariant::Block(e) => todo!("Generate code for block"),
-            ast::StmtVariant::Break => todo!("Generate code for return"),
+            ast::StmtVariant::Break(..) => todo!("Generate code for return"),
             ast::StmtVariant::If(e) => todo!("Generate code for return`"),
             ast::StmtVariant::While(e) => todo!("Generate code for ret`urn"),
             ast::StmtVariant::Empty => (),
@@ -0,0 +1,642 @@
+//! `chigusa dap`: a debug adapter speaking the Debug Adapter Protocol over
+//! stdio, debugging programs on the built-in VM.
+//!
+//! There is one thread, and one source file. Breakpoints and steps stop at
+//! the start of statements, which are the instructions whose line differs
+//! from the one before. Lines and columns start at 1 unless the client says
+//! otherwise. `attach` is not supported, as programs only run inside the
+//! adapter.
+
+use crate::source;
+use chigusa::minivm::vm::{function_name, intrinsic_sigs, MiniVM, VmError};
+use chigusa::minivm::{Codegen, O0};
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::collections::{BTreeSet, VecDeque};
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+
+/// Steps between looks for `pause` requests while running
+const POLL_STEPS: usize = 1 << 12;
+
+/// Variables reference of globals. Locals of frame `id` are `id + 1`.
+const GLOBALS_REF: i64 = 1;
+
+/// The only thread there is
+const THREAD_ID: i64 = 1;
+
+/// Serve a client on stdin and stdout until it disconnects, compiling the
+/// program with `defines`
+pub fn serve(defines: &[String]) -> io::Result<()> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let stdin = io::stdin();
+        let mut stdin = stdin.lock();
+        while let Ok(Some(msg)) = read_message(&mut stdin) {
+            if tx.send(msg).is_err() {
+                break;
+            }
+        }
+    });
+
+    let client = Rc::new(RefCell::new(Client {
+        seq: 0,
+        output: vec![],
+    }));
+    let mut pending = VecDeque::new();
+    let mut config = Config {
+        lines_from_1: true,
+        columns_from_1: true,
+        breakpoints: BTreeSet::new(),
+    };
+
+    // * Until `launch`, only configuration is done
+    let launch = loop {
+        let req = match next_message(&rx, &mut pending) {
+            Some(req) => req,
+            None => return Ok(()),
+        };
+        let mut client = client.borrow_mut();
+        match command(&req) {
+            "initialize" => {
+                let args = &req["arguments"];
+                config.lines_from_1 = args["linesStartAt1"].as_bool().unwrap_or(true);
+                config.columns_from_1 = args["columnsStartAt1"].as_bool().unwrap_or(true);
+                let capabilities = json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsTerminateRequest": true,
+                });
+                client.respond(&req, Ok(capabilities))?;
+            }
+            "launch" => break req,
+            "attach" => client.respond(&req, Err(ATTACH.into()))?,
+            "disconnect" | "terminate" => {
+                client.respond(&req, Ok(Value::Null))?;
+                return Ok(());
+            }
+            _ => client.respond(&req, Err(format!("`{}` before `launch`", command(&req))))?,
+        }
+    };
+
+    let program = match launch["arguments"]["program"].as_str() {
+        Some(program) => PathBuf::from(program),
+        None => {
+            let err = "`program` is missing from the launch arguments".into();
+            return client.borrow_mut().respond(&launch, Err(err));
+        }
+    };
+    let o0 = match compile(&program, defines) {
+        Ok(o0) => o0,
+        Err(e) => {
+            let err = format!("{}: {}", program.display(), e);
+            return client.borrow_mut().respond(&launch, Err(err));
+        }
+    };
+    let input = match launch["arguments"]["stdinFile"].as_str() {
+        Some(path) => match std::fs::read(path) {
+            Ok(input) => input,
+            Err(e) => {
+                let err = format!("cannot read input {}: {}", path, e);
+                return client.borrow_mut().respond(&launch, Err(err));
+            }
+        },
+        None => std::fs::read(program.with_extension("in")).unwrap_or_default(),
+    };
+    let stop_on_entry = launch["arguments"]["stopOnEntry"]
+        .as_bool()
+        .unwrap_or(false);
+    client.borrow_mut().respond(&launch, Ok(Value::Null))?;
+    client.borrow_mut().event("initialized", Value::Null)?;
+
+    let mut input = input.as_slice();
+    let mut output = ProgramOutput(client.clone());
+    let mut session = Session {
+        vm: MiniVM::new(&o0, &mut input, &mut output).with_intrinsics(Default::default()),
+        o0: &o0,
+        program,
+        client,
+        rx,
+        pending,
+        config,
+        stop_on_entry,
+        state: State::Configuring,
+    };
+    session.serve()
+}
+
+const ATTACH: &str = "`attach` is not supported; use `launch`";
+
+/// Compile `path` with debug info and `defines`
+fn compile(path: &std::path::Path, defines: &[String]) -> Result<O0, String> {
+    let src = source::load(path, defines).map_err(|e| e.to_string())?;
+    let prog = chigusa::parse_with_host(src.compiled(), &intrinsic_sigs())
+        .map_err(|e| format!("parse error: {}", src.annotate(e)))?;
+    Codegen::new(&prog)
+        .with_debug_info(true)
+        .compile()
+        .map_err(|e| format!("compile error: {}", e.var))
+}
+
+/// Read one message, or `None` at the end of input
+fn read_message(r: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut len = None;
+    loop {
+        let mut line = String::new();
+        if r.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(val) = line.strip_prefix("Content-Length:") {
+            len = val.trim().parse().ok();
+        }
+    }
+    let len = len.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "no Content-Length"))?;
+    let mut body = vec![0; len];
+    r.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn command(req: &Value) -> &str {
+    req["command"].as_str().unwrap_or("")
+}
+
+/// A message put aside while running, or the next one from the client
+fn next_message(rx: &Receiver<Value>, pending: &mut VecDeque<Value>) -> Option<Value> {
+    pending.pop_front().or_else(|| rx.recv().ok())
+}
+
+/// Writes messages to the client
+struct Client {
+    seq: i64,
+    /// Output of the program not sent yet
+    output: Vec<u8>,
+}
+
+impl Client {
+    fn send(&mut self, mut msg: Value) -> io::Result<()> {
+        self.seq += 1;
+        msg["seq"] = json!(self.seq);
+        let body = msg.to_string();
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        write!(stdout, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+        stdout.flush()
+    }
+
+    fn respond(&mut self, req: &Value, body: Result<Value, String>) -> io::Result<()> {
+        let mut msg = json!({
+            "type": "response",
+            "request_seq": req["seq"],
+            "command": req["command"],
+            "success": body.is_ok(),
+        });
+        match body {
+            Ok(Value::Null) => (),
+            Ok(body) => msg["body"] = body,
+            Err(e) => msg["message"] = json!(e),
+        }
+        self.send(msg)
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        let mut msg = json!({ "type": "event", "event": event });
+        if !body.is_null() {
+            msg["body"] = body;
+        }
+        self.send(msg)
+    }
+
+    /// Send output of the program up to its last newline, or all of it
+    fn send_output(&mut self, all: bool) -> io::Result<()> {
+        let end = match all {
+            true => self.output.len(),
+            false => self
+                .output
+                .iter()
+                .rposition(|&c| c == b'\n')
+                .map_or(0, |i| i + 1),
+        };
+        if end == 0 {
+            return Ok(());
+        }
+        let text: Vec<_> = self.output.drain(..end).collect();
+        let text = String::from_utf8_lossy(&text).into_owned();
+        self.event("output", json!({ "category": "stdout", "output": text }))
+    }
+}
+
+/// Output of the program, sent to the client a line at a time
+struct ProgramOutput(Rc<RefCell<Client>>);
+
+impl Write for ProgramOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut client = self.0.borrow_mut();
+        client.output.extend_from_slice(buf);
+        if buf.contains(&b'\n') {
+            client.send_output(false)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.borrow_mut().send_output(true)
+    }
+}
+
+struct Config {
+    lines_from_1: bool,
+    columns_from_1: bool,
+    /// Lines to stop at, counted from 0
+    breakpoints: BTreeSet<u32>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum State {
+    /// Waiting for `configurationDone`
+    Configuring,
+    Stopped,
+    /// Failed at runtime, and can only be looked at
+    Failed,
+    Exited,
+}
+
+/// How far to run
+#[derive(Debug, Clone, Copy)]
+enum Until {
+    /// A breakpoint
+    Breakpoint,
+    /// The start of any statement
+    StepIn,
+    /// The start of a statement in a call this deep or less
+    Next(usize),
+    /// Leaving calls this deep
+    StepOut(usize),
+}
+
+/// Why running stopped
+enum Stop {
+    At(&'static str),
+    Exited(i32),
+    Failed(VmError),
+    Disconnect(Value),
+}
+
+struct Session<'a> {
+    vm: MiniVM<'a>,
+    o0: &'a O0,
+    program: PathBuf,
+    client: Rc<RefCell<Client>>,
+    rx: Receiver<Value>,
+    /// Requests that came while running
+    pending: VecDeque<Value>,
+    config: Config,
+    stop_on_entry: bool,
+    state: State,
+}
+
+impl<'a> Session<'a> {
+    fn serve(&mut self) -> io::Result<()> {
+        while let Some(req) = next_message(&self.rx, &mut self.pending) {
+            if !self.request(req)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Answer `req`. Returns whether to carry on.
+    fn request(&mut self, req: Value) -> io::Result<bool> {
+        let running = matches!(self.state, State::Stopped | State::Configuring);
+        let body = match command(&req) {
+            "setBreakpoints" => Ok(self.set_breakpoints(&req["arguments"])),
+            "setExceptionBreakpoints" => Ok(json!({ "breakpoints": [] })),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] })),
+            "stackTrace" => Ok(self.stack_trace()),
+            "scopes" => Ok(self.scopes(&req["arguments"])),
+            "variables" => Ok(self.variables(&req["arguments"])),
+            "configurationDone" => {
+                self.respond(&req, Ok(Value::Null))?;
+                self.vm.start();
+                return match self.stop_on_entry {
+                    true => self.go(Until::StepIn, true),
+                    false => self.go(Until::Breakpoint, true),
+                };
+            }
+            "continue" | "next" | "stepIn" | "stepOut" if running => {
+                let depth = self.vm.depth();
+                let until = match command(&req) {
+                    "continue" => Until::Breakpoint,
+                    "next" => Until::Next(depth),
+                    "stepIn" => Until::StepIn,
+                    _ => Until::StepOut(depth),
+                };
+                let body = match until {
+                    Until::Breakpoint => json!({ "allThreadsContinued": true }),
+                    _ => Value::Null,
+                };
+                self.respond(&req, Ok(body))?;
+                return self.go(until, false);
+            }
+            "continue" | "next" | "stepIn" | "stepOut" => {
+                self.respond(&req, Ok(Value::Null))?;
+                self.finish(None)?;
+                return Ok(true);
+            }
+            // * Nothing runs between requests, so there is nothing to pause
+            "pause" => Ok(Value::Null),
+            "disconnect" | "terminate" => {
+                self.respond(&req, Ok(Value::Null))?;
+                return Ok(false);
+            }
+            "attach" => Err(ATTACH.into()),
+            other => Err(format!("`{}` is not supported", other)),
+        };
+        self.respond(&req, body)?;
+        Ok(true)
+    }
+
+    fn respond(&mut self, req: &Value, body: Result<Value, String>) -> io::Result<()> {
+        self.client.borrow_mut().respond(req, body)
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        self.client.borrow_mut().event(event, body)
+    }
+
+    /// Run until `until`, telling the client why it stopped. Returns whether
+    /// to carry on.
+    fn go(&mut self, until: Until, check_first: bool) -> io::Result<bool> {
+        let stop = self.run(until, check_first);
+        self.client.borrow_mut().send_output(true)?;
+        match stop {
+            Stop::At(reason) => {
+                self.state = State::Stopped;
+                let body = json!({
+                    "reason": reason,
+                    "threadId": THREAD_ID,
+                    "allThreadsStopped": true,
+                });
+                self.event("stopped", body)?;
+            }
+            Stop::Failed(e) => {
+                self.state = State::Failed;
+                let text = format!("runtime error: {}", e);
+                let output = json!({ "category": "stderr", "output": format!("{}\n", text) });
+                self.event("output", output)?;
+                let body = json!({
+                    "reason": "exception",
+                    "description": text,
+                    "text": text,
+                    "threadId": THREAD_ID,
+                    "allThreadsStopped": true,
+                });
+                self.event("stopped", body)?;
+            }
+            Stop::Exited(code) => self.finish(Some(code))?,
+            Stop::Disconnect(req) => {
+                self.respond(&req, Ok(Value::Null))?;
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Tell the client the program is over
+    fn finish(&mut self, code: Option<i32>) -> io::Result<()> {
+        if self.state != State::Exited {
+            let code = code.unwrap_or(1);
+            self.event("exited", json!({ "exitCode": code }))?;
+            self.event("terminated", Value::Null)?;
+        }
+        self.state = State::Exited;
+        Ok(())
+    }
+
+    fn run(&mut self, until: Until, check_first: bool) -> Stop {
+        let mut steps = 0;
+        if check_first {
+            if let Some(stop) = self.should_stop(until) {
+                return stop;
+            }
+        }
+        loop {
+            match self.vm.step() {
+                Ok(None) => (),
+                Ok(Some(code)) => return Stop::Exited(code),
+                Err(e) => return Stop::Failed(e),
+            }
+            if let Some(stop) = self.should_stop(until) {
+                return stop;
+            }
+            steps += 1;
+            if steps % POLL_STEPS == 0 {
+                loop {
+                    match self.rx.try_recv() {
+                        Ok(req) if command(&req) == "pause" => {
+                            let _ = self.respond(&req, Ok(Value::Null));
+                            return Stop::At("pause");
+                        }
+                        Ok(req) if matches!(command(&req), "disconnect" | "terminate") => {
+                            return Stop::Disconnect(req);
+                        }
+                        Ok(req) => self.pending.push_back(req),
+                        Err(TryRecvError::Empty) => break,
+                        Err(TryRecvError::Disconnected) => return Stop::Disconnect(json!({})),
+                    }
+                }
+            }
+        }
+    }
+
+    fn should_stop(&self, until: Until) -> Option<Stop> {
+        if let Until::StepOut(depth) = until {
+            if self.vm.depth() < depth {
+                return Some(Stop::At("step"));
+            }
+        }
+        let line = self.statement_start()?;
+        let stop = match until {
+            Until::StepIn => true,
+            Until::Next(depth) => self.vm.depth() <= depth,
+            Until::Breakpoint | Until::StepOut(_) => false,
+        };
+        if stop {
+            Some(Stop::At("step"))
+        } else if self.config.breakpoints.contains(&line) {
+            Some(Stop::At("breakpoint"))
+        } else {
+            None
+        }
+    }
+
+    /// Line of the statement the program is at the start of, if any
+    fn statement_start(&self) -> Option<u32> {
+        let frame = self.vm.current()?;
+        let lines = self.lines(frame.func)?;
+        let line = (*lines.get(frame.ip)?)?;
+        match frame.ip {
+            0 => Some(line),
+            ip if lines[ip - 1] != Some(line) => Some(line),
+            _ => None,
+        }
+    }
+
+    /// Lines of the instructions of `func`, or of start code
+    fn lines(&self, func: Option<u16>) -> Option<&'a [Option<u32>]> {
+        let debug = self.o0.debug.as_ref()?;
+        match func {
+            Some(f) => debug.fn_lines.get(f as usize).map(Vec::as_slice),
+            None => Some(&debug.start_lines),
+        }
+    }
+
+    fn line_of(&self, func: Option<u16>, ip: usize) -> Option<u32> {
+        *self.lines(func)?.get(ip)?
+    }
+
+    fn to_client_line(&self, line: u32) -> i64 {
+        line as i64 + self.config.lines_from_1 as i64
+    }
+
+    fn source(&self) -> Value {
+        let path = std::fs::canonicalize(&self.program).unwrap_or_else(|_| self.program.clone());
+        let name = self
+            .program
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned());
+        json!({ "name": name, "path": path.to_string_lossy() })
+    }
+
+    /// Take the breakpoints in `args`, moving each to the next line with
+    /// a statement
+    fn set_breakpoints(&mut self, args: &Value) -> Value {
+        let starts: BTreeSet<u32> = (self.o0.debug.iter())
+            .flat_map(|debug| {
+                debug
+                    .fn_lines
+                    .iter()
+                    .chain(std::iter::once(&debug.start_lines))
+            })
+            .flat_map(|lines| lines.iter().flatten().copied())
+            .collect();
+        let first = !self.config.lines_from_1 as i64;
+        self.config.breakpoints.clear();
+        let mut breakpoints = vec![];
+        for bp in args["breakpoints"].as_array().into_iter().flatten() {
+            let line = bp["line"].as_i64().unwrap_or(0) + first - 1;
+            match starts.range(line.max(0) as u32..).next() {
+                Some(&line) => {
+                    self.config.breakpoints.insert(line);
+                    breakpoints.push(json!({
+                        "verified": true,
+                        "line": self.to_client_line(line),
+                    }));
+                }
+                None => breakpoints.push(json!({
+                    "verified": false,
+                    "message": "No code on or after this line",
+                })),
+            }
+        }
+        json!({ "breakpoints": breakpoints })
+    }
+
+    /// Calls being run, innermost first. Frame ids count from 1 for start
+    /// code.
+    fn stack_trace(&self) -> Value {
+        let snapshot = self.vm.snapshot();
+        let column = self.config.columns_from_1 as i64;
+        let frames: Vec<_> = (snapshot.frames.iter().enumerate().rev())
+            .map(|(idx, frame)| {
+                // * Frames besides the innermost have moved past their call
+                let ip = match idx + 1 == snapshot.frames.len() {
+                    true => frame.ip,
+                    false => frame.ip.saturating_sub(1),
+                };
+                let mut json = json!({
+                    "id": idx + 1,
+                    "name": function_name(self.o0, frame.func),
+                    "line": 0,
+                    "column": 0,
+                });
+                if let Some(line) = self.line_of(frame.func, ip) {
+                    json["line"] = json!(self.to_client_line(line));
+                    json["column"] = json!(column);
+                    json["source"] = self.source();
+                }
+                json
+            })
+            .collect();
+        json!({ "stackFrames": frames, "totalFrames": frames.len() })
+    }
+
+    fn scopes(&self, args: &Value) -> Value {
+        let id = args["frameId"].as_i64().unwrap_or(0);
+        let mut scopes = vec![];
+        // * Start code has globals for locals
+        if id > 1 {
+            scopes.push(json!({
+                "name": "Locals",
+                "presentationHint": "locals",
+                "variablesReference": id + 1,
+                "expensive": false,
+            }));
+        }
+        scopes.push(json!({
+            "name": "Globals",
+            "variablesReference": GLOBALS_REF,
+            "expensive": false,
+        }));
+        json!({ "scopes": scopes })
+    }
+
+    fn variables(&self, args: &Value) -> Value {
+        let reference = args["variablesReference"].as_i64().unwrap_or(0);
+        // * Start code, the outermost frame, has the globals
+        let depth = match reference {
+            GLOBALS_REF => self.vm.depth().checked_sub(1),
+            _ => self.vm.depth().checked_sub((reference - 1) as usize),
+        };
+        let frame = match depth.and_then(|depth| self.vm.read_stack_frame(depth)) {
+            Some(frame) => frame,
+            None => return json!({ "variables": [] }),
+        };
+        let variables: Vec<_> = (frame.vars.iter())
+            .map(|var| {
+                json!({
+                    "name": var.name,
+                    "value": show_value(var.value.as_ref()),
+                    "variablesReference": 0,
+                })
+            })
+            .collect();
+        json!({ "variables": variables })
+    }
+}
+
+/// A value of a variable as shown to the client
+fn show_value(value: Option<&chigusa::minivm::Value>) -> String {
+    use chigusa::minivm::Value::*;
+    match value {
+        None => "<not allocated>".into(),
+        Some(Char(c)) => format!("{:?}", *c as char),
+        Some(Double(d)) => format!("{:?}", d),
+        Some(Array(slots)) => {
+            let slots: Vec<_> = (slots.iter())
+                .map(|s| match s {
+                    UInt(s) => format!("{:#010x}", s),
+                    s => s.to_string(),
+                })
+                .collect();
+            format!("[{}]", slots.join(", "))
+        }
+        Some(val) => val.to_string(),
+    }
+}
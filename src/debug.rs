@@ -0,0 +1,191 @@
+//! `chigusa debug`: step through a program on the VM, forwards and back.
+
+use crate::check::{io_error, load_failed, report};
+use crate::exit::Exit;
+use crate::opt::ParserConfig;
+use crate::source;
+use chigusa::minivm::vm::{function_name, intrinsic_sigs, MiniVM, Snapshot};
+use chigusa::minivm::{Codegen, O0};
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+const HELP: &str = "\
+step [n]      execute n instructions (default 1)
+back [n]      go back n instructions (default 1)
+continue      run until the program ends or fails
+frame         show the slots of the current call
+where         show the calls being run
+help          show this message
+quit          stop debugging";
+
+/// Debug `file`, compiled with the features `-D` defines, interactively,
+/// reading commands from stdin, with `input` as the program's input and the
+/// last `history` instructions kept to go back to. Errors are reported as
+/// `chigusa check` reports them.
+pub fn debug(file: &Path, input: Option<&Path>, history: usize, opt: &ParserConfig) -> Exit {
+    match debug_file(file, input, history, opt) {
+        Ok(()) => Exit::Success,
+        Err(exit) => exit,
+    }
+}
+
+fn debug_file(
+    file: &Path,
+    input: Option<&Path>,
+    history: usize,
+    opt: &ParserConfig,
+) -> Result<(), Exit> {
+    let mut errors = 0;
+    let src =
+        source::load(file, &opt.defines).map_err(|e| load_failed(file, e, opt, &mut errors))?;
+    let input = match input {
+        Some(path) => {
+            std::fs::read(path).map_err(|e| io_error(path, "cannot read input", &e, opt))?
+        }
+        None => std::fs::read(file.with_extension("in")).unwrap_or_default(),
+    };
+    let o0 = match chigusa::parse_with_host(src.compiled(), &intrinsic_sigs()) {
+        Ok(prog) => Codegen::new(&prog)
+            .with_debug_info(true)
+            .compile()
+            .map_err(|e| src.annotate(e)),
+        Err(e) => Err(src.annotate(e)),
+    };
+    let o0 = o0.map_err(|e| report(file, &[e.into()], opt, &mut errors))?;
+
+    let mut input = input.as_slice();
+    let mut output = std::io::stdout();
+    let mut vm = MiniVM::new(&o0, &mut input, &mut output)
+        .with_history(history)
+        .with_intrinsics(Default::default());
+    let lines: Vec<_> = src.text.lines().collect();
+    let mut finished = None;
+
+    println!("Type `help` for commands.");
+    let stdin = std::io::stdin();
+    let mut commands = stdin.lock().lines();
+    loop {
+        print!("(debug) ");
+        std::io::stdout().flush().ok();
+        let command = match commands.next() {
+            Some(Ok(command)) => command,
+            _ => break,
+        };
+        let mut words = command.split_whitespace();
+        let name = match words.next() {
+            Some(name) => name,
+            None => continue,
+        };
+        let count = match words.next().map(str::parse::<usize>) {
+            None => 1,
+            Some(Ok(count)) => count,
+            Some(Err(_)) => {
+                println!("expected a number of instructions");
+                continue;
+            }
+        };
+
+        match name {
+            "s" | "step" | "c" | "continue" => {
+                let count = match name {
+                    "c" | "continue" => usize::MAX,
+                    _ => count,
+                };
+                for _ in 0..count {
+                    match vm.step() {
+                        Ok(None) => (),
+                        Ok(Some(code)) => {
+                            finished = Some(code);
+                            break;
+                        }
+                        Err(e) => {
+                            println!("runtime error: {}", e);
+                            println!("use `back` to see how it came to this");
+                            break;
+                        }
+                    }
+                }
+                match finished {
+                    Some(code) => println!("program returned {}", code),
+                    None => location(&o0, &lines, &vm.snapshot()),
+                }
+            }
+            "b" | "back" => {
+                let went = vm.step_back(count);
+                if went < count {
+                    println!("went back {} instructions, as far as history goes", went);
+                }
+                finished = None;
+                location(&o0, &lines, &vm.snapshot());
+            }
+            "f" | "frame" => frame(&vm.snapshot()),
+            "w" | "where" | "bt" => backtrace(&o0, &lines, &vm.snapshot()),
+            "h" | "help" => println!("{}", HELP),
+            "q" | "quit" => break,
+            _ => println!("unknown command `{}`, type `help` for commands", name),
+        }
+    }
+    Ok(())
+}
+
+/// Source line, counted from 0, of instruction `ip` of `func`
+fn line_of(o0: &O0, func: Option<u16>, ip: usize) -> Option<u32> {
+    let debug = o0.debug.as_ref()?;
+    let lines = match func {
+        Some(f) => debug.fn_lines.get(f as usize)?,
+        None => &debug.start_lines,
+    };
+    *lines.get(ip)?
+}
+
+/// Show the line and instruction the program is at
+fn location(o0: &O0, lines: &[&str], snapshot: &Snapshot) {
+    let frame = match snapshot.frames.last() {
+        Some(frame) => frame,
+        None => return println!("program has not started"),
+    };
+    let code = match frame.func {
+        Some(f) => &o0.functions[f as usize].ins,
+        None => &o0.start_code.ins,
+    };
+    let name = function_name(o0, frame.func);
+    match line_of(o0, frame.func, frame.ip) {
+        Some(line) => {
+            let text = lines.get(line as usize).map_or("", |l| l.trim());
+            println!("{} line {}: {}", name, line + 1, text);
+        }
+        None => println!("{}", name),
+    }
+    match code.get(frame.ip) {
+        Some(inst) => println!("    {:4} {}", frame.ip, inst),
+        None => println!("    {:4} (end)", frame.ip),
+    }
+}
+
+fn frame(snapshot: &Snapshot) {
+    let base = snapshot.frames.last().map_or(0, |frame| frame.base);
+    if base == snapshot.stack.len() {
+        println!("no slots");
+    }
+    for (addr, slot) in snapshot.stack.iter().enumerate().skip(base) {
+        println!("    [{}] = {} ({:#010x})", addr, *slot as i32, slot);
+    }
+}
+
+fn backtrace(o0: &O0, lines: &[&str], snapshot: &Snapshot) {
+    for (depth, frame) in snapshot.frames.iter().rev().enumerate() {
+        let name = function_name(o0, frame.func);
+        // * Frames besides the innermost have moved past their call already
+        let ip = match depth {
+            0 => frame.ip,
+            _ => frame.ip.saturating_sub(1),
+        };
+        match line_of(o0, frame.func, ip) {
+            Some(line) => {
+                let text = lines.get(line as usize).map_or("", |l| l.trim());
+                println!("#{} {} line {}: {}", depth, name, line + 1, text);
+            }
+            None => println!("#{} {}", depth, name),
+        }
+    }
+}
@@ -0,0 +1,185 @@
+//! `chigusa difftest`: run programs on every backend and compare.
+
+use crate::check::{self, io_error, load_failed};
+use crate::exit::Exit;
+use crate::opt::ParserConfig;
+use crate::source;
+use chigusa::c0::interpreter::{Interpreter, RuntimeError};
+use chigusa::minivm::{vm::MiniVM, Codegen};
+use std::path::{Path, PathBuf};
+
+/// What happened when one backend ran a program
+#[derive(Debug)]
+struct Outcome {
+    backend: &'static str,
+    /// Exit code, or a description of why the program did not finish
+    status: Result<i32, String>,
+    output: Vec<u8>,
+}
+
+impl Outcome {
+    fn status_str(&self) -> String {
+        match &self.status {
+            Ok(code) => format!("exit code {}", code),
+            Err(e) => e.clone(),
+        }
+    }
+
+    /// Runtime errors are backend-specific in wording, so any two failures
+    /// count as the same.
+    fn agrees_with(&self, other: &Outcome) -> bool {
+        self.output == other.output && self.status.as_ref().ok() == other.status.as_ref().ok()
+    }
+}
+
+/// How a program ran on all backends
+enum Verdict {
+    Agreed,
+    Diverged,
+    /// The interpreter does not support something the program uses, so there
+    /// is nothing to compare with
+    Skipped(RuntimeError),
+}
+
+/// Run every file, compiled with the features `-D` defines, and report
+/// divergences. Files that cannot be tested are reported as `chigusa check`
+/// reports errors. Returns 1 if backends diverged on a file.
+pub fn difftest(files: &[PathBuf], input: Option<&Path>, steps: u64, opt: &ParserConfig) -> Exit {
+    let mut diverged = vec![];
+    let mut skipped = 0;
+    let mut failed = vec![];
+    let mut worst = Exit::Success;
+    let mut errors = 0;
+    for file in files {
+        match difftest_file(file, input, steps, opt, &mut errors) {
+            Ok(Verdict::Agreed) => println!("{}: ok", file.display()),
+            Ok(Verdict::Diverged) => diverged.push(file),
+            Ok(Verdict::Skipped(e)) => {
+                println!("{}: skipped: {}", file.display(), e);
+                skipped += 1;
+            }
+            Err(exit) => {
+                worst = worst.max(exit);
+                failed.push(file);
+            }
+        }
+        if opt.max_errors.is_some_and(|max| errors >= max) {
+            break;
+        }
+    }
+
+    if files.len() > 1 {
+        println!(
+            "\n{} programs: {} agreed, {} diverged, {} skipped, {} could not be tested",
+            files.len(),
+            files.len() - diverged.len() - skipped - failed.len(),
+            diverged.len(),
+            skipped,
+            failed.len()
+        );
+        for file in &diverged {
+            println!("  diverged: {}", file.display());
+        }
+    }
+    match diverged.is_empty() {
+        true => worst,
+        false => worst.max(Exit::CompileError),
+    }
+}
+
+/// Run one file, counting why it could not be run in `errors`
+fn difftest_file(
+    file: &Path,
+    input: Option<&Path>,
+    steps: u64,
+    opt: &ParserConfig,
+    errors: &mut usize,
+) -> Result<Verdict, Exit> {
+    let src = source::load(file, &opt.defines).map_err(|e| load_failed(file, e, opt, errors))?;
+    let input = match input {
+        Some(path) => std::fs::read(path).map_err(|e| {
+            *errors += 1;
+            io_error(path, "cannot read input", &e, opt)
+        })?,
+        None => std::fs::read(file.with_extension("in")).unwrap_or_default(),
+    };
+
+    let prog = chigusa::parse(src.compiled())
+        .map_err(|e| check::report(file, &[src.annotate(e).into()], opt, errors))?;
+
+    let mut outcomes = vec![];
+
+    {
+        let mut input = input.as_slice();
+        let mut output = vec![];
+        let status = match Interpreter::new(&prog, &mut input, &mut output)
+            .with_step_limit(steps)
+            .run()
+        {
+            Err(e @ RuntimeError::Unsupported(_)) => return Ok(Verdict::Skipped(e)),
+            status => status.map_err(|e| format!("runtime error: {}", e)),
+        };
+        outcomes.push(Outcome {
+            backend: "interpreter",
+            status,
+            output,
+        });
+    }
+
+    {
+        let mut input = input.as_slice();
+        let mut output = vec![];
+        let status = match Codegen::new(&prog).compile() {
+            Ok(o0) => MiniVM::new(&o0, &mut input, &mut output)
+                .with_step_limit(steps)
+                .run()
+                .map_err(|e| format!("runtime error: {}", e)),
+            Err(e) => Err(format!("compile error: {}", e.var)),
+        };
+        outcomes.push(Outcome {
+            backend: "minivm",
+            status,
+            output,
+        });
+    }
+
+    let reference = &outcomes[0];
+    let mut verdict = Verdict::Agreed;
+    for other in &outcomes[1..] {
+        if !reference.agrees_with(other) {
+            verdict = Verdict::Diverged;
+            report(file, reference, other);
+        }
+    }
+    Ok(verdict)
+}
+
+fn report(file: &Path, a: &Outcome, b: &Outcome) {
+    println!(
+        "{}: {} and {} diverged",
+        file.display(),
+        a.backend,
+        b.backend
+    );
+    println!("  {:>11}: {}", a.backend, a.status_str());
+    println!("  {:>11}: {}", b.backend, b.status_str());
+
+    if a.output != b.output {
+        let a_out = String::from_utf8_lossy(&a.output);
+        let b_out = String::from_utf8_lossy(&b.output);
+        let mut a_lines = a_out.lines();
+        let mut b_lines = b_out.lines();
+        let mut ln = 1;
+        loop {
+            match (a_lines.next(), b_lines.next()) {
+                (Some(x), Some(y)) if x == y => ln += 1,
+                (x, y) => {
+                    println!("  output differs at line {}:", ln);
+                    println!("  {:>11}: {}", a.backend, x.unwrap_or("<end of output>"));
+                    println!("  {:>11}: {}", b.backend, y.unwrap_or("<end of output>"));
+                    break;
+                }
+            }
+        }
+    }
+}
@@ -1,8 +1,6 @@
-use chigusa::c0::err::ParseError;
 use chigusa::prelude::Span;
-use std::fs::*;
-use std::io::*;
-use std::path::PathBuf;
+use chigusa::{Diagnostic, Fix, Note};
+use std::path::Path;
 
 /// Lines to display around error line
 const ERR_CONTEXT_LINES: usize = 3;
@@ -58,3 +56,54 @@ pub fn pretty_print_error(
 
     println!("{}", err_desc);
 }
+
+/// Print the notes attached to an error, showing the source around each
+pub fn print_notes(src: &str, notes: &[Note]) {
+    for note in notes {
+        let desc = format!("note: {}", note.message);
+        match note.span {
+            Some(span) => pretty_print_error(&mut src.lines(), span, &desc),
+            None => println!("{}", desc),
+        }
+    }
+}
+
+/// Print the fixes suggested for an error, which `chigusa fix` makes
+pub fn print_fixes(fixes: &[Fix]) {
+    for fix in fixes {
+        let at = fix.span.start;
+        println!("help: {} at {}:{}", fix.message, at.ln + 1, at.pos + 1);
+    }
+}
+
+/// `file:line:col: error[code]: message` on one line, which editors can jump
+/// to. Warnings say `warning[code]` instead.
+pub fn concise(file: &Path, d: &Diagnostic) -> String {
+    format!(
+        "{}{}: {}[{}]: {}",
+        file.display(),
+        at(d.span),
+        d.severity,
+        d.code,
+        d.message
+    )
+}
+
+/// `file:line:col: note: message`, for a note of a diagnostic shown with
+/// [`concise`]
+pub fn concise_note(file: &Path, note: &Note) -> String {
+    format!(
+        "{}{}: note: {}",
+        file.display(),
+        at(note.span),
+        note.message
+    )
+}
+
+/// `:line:col` of `span`, if known
+fn at(span: Option<Span>) -> String {
+    match span {
+        Some(span) => format!(":{}:{}", span.start.ln + 1, span.start.pos + 1),
+        None => String::new(),
+    }
+}
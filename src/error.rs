@@ -0,0 +1,175 @@
+//! The one error type every stage of the compiler reports through.
+//!
+//! Each kind of error has a stable [`ErrorCode`]; the full list is in
+//! `docs/errors.md`.
+
+use crate::c0::err::{LexError, ParseError};
+use crate::prelude::*;
+use core::fmt;
+
+/// Which step of compilation found a problem
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Stage {
+    Parse,
+    Compile,
+}
+
+/// How bad a problem is. Errors stop a program from compiling, warnings
+/// only point at code that is likely wrong.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A stable number identifying a kind of error, shown as `E0101`
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct ErrorCode(pub u16);
+
+impl ErrorCode {
+    /// Code of errors that are bugs in the compiler rather than the program
+    pub const INTERNAL: ErrorCode = ErrorCode(999);
+
+    pub fn is_internal(&self) -> bool {
+        *self == ErrorCode::INTERNAL
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "E{:04}", self.0)
+    }
+}
+
+/// Extra information attached to an error, like where a conflicting name was
+/// declared first
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Note {
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+/// A change to the source that fixes an error, safe to make without looking
+/// at it, like inserting a missing `;`
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Fix {
+    /// What the change does, like "insert `;`"
+    pub message: String,
+    /// Text to replace. It is empty when the fix only inserts.
+    pub span: Span,
+    pub replacement: String,
+}
+
+impl Fix {
+    pub fn new(message: impl Into<String>, span: Span, replacement: impl Into<String>) -> Fix {
+        Fix {
+            message: message.into(),
+            span,
+            replacement: replacement.into(),
+        }
+    }
+}
+
+/// An error stopping a program from compiling
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CompileError {
+    pub code: ErrorCode,
+    pub stage: Stage,
+    pub message: String,
+    /// Where the problem is in the source, if known
+    pub span: Option<Span>,
+    pub notes: Vec<Note>,
+    pub fixes: Vec<Fix>,
+}
+
+impl CompileError {
+    pub fn new(code: ErrorCode, stage: Stage, message: impl Into<String>) -> CompileError {
+        CompileError {
+            code,
+            stage,
+            message: message.into(),
+            span: None,
+            notes: vec![],
+            fixes: vec![],
+        }
+    }
+
+    pub fn with_span(mut self, span: Span) -> CompileError {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_note(mut self, message: impl Into<String>, span: Option<Span>) -> CompileError {
+        self.notes.push(Note {
+            message: message.into(),
+            span,
+        });
+        self
+    }
+
+    pub fn with_fix(mut self, fix: Fix) -> CompileError {
+        self.fixes.push(fix);
+        self
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "error[{}]: {}", self.code, self.message)?;
+        if let Some(span) = self.span {
+            write!(f, " at {}", span)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CompileError {}
+
+impl From<LexError> for CompileError {
+    fn from(e: LexError) -> Self {
+        CompileError::new(e.code(), Stage::Parse, e.to_string())
+    }
+}
+
+impl From<ParseError> for CompileError {
+    fn from(e: ParseError) -> Self {
+        let err = CompileError::new(e.var.code(), Stage::Parse, e.var.to_string());
+        let err = err.with_span(e.span);
+        let err = match e.var.note() {
+            Some((note, span)) => err.with_note(note, Some(span)),
+            None => err,
+        };
+        match e.fix {
+            Some(fix) => err.with_fix(fix),
+            None => err,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<crate::minivm::CompileError> for CompileError {
+    fn from(e: crate::minivm::CompileError) -> Self {
+        let err = CompileError {
+            span: e.span,
+            ..CompileError::new(e.var.code(), Stage::Compile, e.var.to_string())
+        };
+        let err = match e.var.note() {
+            Some(note) => err.with_note(note, e.span),
+            None => err,
+        };
+        match e.span.and_then(|span| e.var.fix(span)) {
+            Some(fix) => err.with_fix(fix),
+            None => err,
+        }
+    }
+}
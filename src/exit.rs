@@ -0,0 +1,31 @@
+//! Exit codes of the command line. Scripts may rely on these, so they must
+//! not change.
+
+use chigusa::ErrorCode;
+
+/// How a command ended, from best to worst
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Exit {
+    Success = 0,
+    /// Errors in the program, or on the command line
+    CompileError = 1,
+    /// A bug in the compiler
+    InternalError = 2,
+    /// A file could not be read or written
+    IoError = 3,
+}
+
+impl Exit {
+    /// The code for failing to compile with an error of `code`
+    pub fn of(code: ErrorCode) -> Exit {
+        if code.is_internal() {
+            Exit::InternalError
+        } else {
+            Exit::CompileError
+        }
+    }
+
+    pub fn exit(self) -> ! {
+        std::process::exit(self as i32)
+    }
+}
@@ -0,0 +1,96 @@
+//! `chigusa fix`: make the fixes suggested with errors, in place.
+
+use crate::check::{check_src, report};
+use crate::config;
+use crate::exit::Exit;
+use crate::opt::ParserConfig;
+use chigusa::{Fix, LintRules, Standard, Target};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Times a source is checked again after fixing it. The parser stops at the
+/// first error, so each round usually finds one more fix; this only stops
+/// fixes that undo each other from going on forever.
+const MAX_ROUNDS: usize = 100;
+
+/// Fix `files` in place, or stdin to stdout if there are none, printing how
+/// many fixes were made to each file and the errors left unless `--quiet`
+/// is given
+pub fn fix(files: &[PathBuf], target: Target, standard: Standard, opt: &ParserConfig) -> Exit {
+    let cwd = std::env::current_dir().unwrap_or_default();
+    let rules = match config::lint_rules(&cwd) {
+        Ok(rules) => rules,
+        Err(e) => {
+            eprintln!("{}", e);
+            return Exit::CompileError;
+        }
+    };
+    let mut errors = 0;
+    if files.is_empty() {
+        let mut src = String::new();
+        if let Err(e) = std::io::stdin().read_to_string(&mut src) {
+            if !opt.quiet {
+                eprintln!("<stdin>: cannot read: {}", e);
+            }
+            return Exit::IoError;
+        }
+        let (fixed, _) = fix_src(src, target, standard, &rules, opt);
+        print!("{}", fixed);
+        let diags = check_src(&fixed, target, standard, &rules, opt);
+        return report(Path::new("<stdin>"), &diags, opt, &mut errors);
+    }
+
+    let mut worst = Exit::Success;
+    for file in files {
+        let src = match std::fs::read_to_string(file) {
+            Ok(src) => src,
+            Err(e) => {
+                if !opt.quiet {
+                    eprintln!("{}: cannot read file: {}", file.display(), e);
+                }
+                worst = Exit::IoError;
+                continue;
+            }
+        };
+        let (fixed, made) = fix_src(src, target, standard, &rules, opt);
+        if made > 0 {
+            if let Err(e) = std::fs::write(file, &fixed) {
+                if !opt.quiet {
+                    eprintln!("{}: cannot write file: {}", file.display(), e);
+                }
+                worst = Exit::IoError;
+                continue;
+            }
+            if !opt.quiet {
+                let fixes = if made == 1 { "fix" } else { "fixes" };
+                eprintln!("{}: made {} {}", file.display(), made, fixes);
+            }
+        }
+        let diags = check_src(&fixed, target, standard, &rules, opt);
+        worst = worst.max(report(file, &diags, opt, &mut errors));
+    }
+    worst
+}
+
+/// `src` with the fixes of its diagnostics made, round after round until
+/// there are none, and how many were made
+fn fix_src(
+    mut src: String,
+    target: Target,
+    standard: Standard,
+    rules: &LintRules,
+    opt: &ParserConfig,
+) -> (String, usize) {
+    let mut made = 0;
+    for _ in 0..MAX_ROUNDS {
+        let diags = check_src(&src, target, standard, rules, opt);
+        let fixes: Vec<Fix> = diags.into_iter().flat_map(|d| d.fixes).collect();
+        if fixes.is_empty() {
+            break;
+        }
+        let (fixed, n) = chigusa::apply_fixes(&src, &fixes);
+        src = fixed;
+        made += n;
+    }
+    (src, made)
+}
@@ -0,0 +1,191 @@
+//! `chigusa fmt`: format source files.
+//!
+//! Layout is read from the nearest `.chigusafmt.toml` in the directory of
+//! each file or its ancestors:
+//!
+//! ```toml
+//! indent_width = 4          # spaces per level of indentation
+//! brace_style = "same_line" # or "next_line"
+//! ```
+//!
+//! Lines `#if` directives leave out with the features `-D` defines are kept
+//! as they are, along with the directives.
+
+use crate::check::{io_error, report};
+use crate::exit::Exit;
+use crate::opt::ParserConfig;
+use crate::source::Source;
+use chigusa::c0::ast::ast_eq;
+use chigusa::c0::parse_no_panic;
+use chigusa::c0::pretty::{format_with_features, BraceStyle, PrettyConfig};
+use chigusa::{CompileError, ErrorCode, Stage};
+use serde::Deserialize;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+const CONFIG_FILE: &str = ".chigusafmt.toml";
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    indent_width: Option<usize>,
+    brace_style: Option<BraceStyleName>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum BraceStyleName {
+    SameLine,
+    NextLine,
+}
+
+/// Format `files` in place, compiled with the features `-D` defines, or only
+/// report unformatted ones if `check` is set. Without files, formats stdin to
+/// stdout. Errors are printed as `chigusa check` prints them. Returns 1 if a
+/// file was not formatted when checking.
+pub fn fmt(files: &[PathBuf], check: bool, opt: &ParserConfig) -> Exit {
+    let mut errors = 0;
+    if files.is_empty() {
+        let stdin = Path::new("<stdin>");
+        let mut src = String::new();
+        if let Err(e) = std::io::stdin().read_to_string(&mut src) {
+            return io_error(stdin, "cannot read", &e, opt);
+        }
+        let config = std::env::current_dir()
+            .map_err(|e| e.to_string())
+            .and_then(|dir| load_config(&dir));
+        let config = match config {
+            Ok(config) => config,
+            Err(e) => return config_error(&e, opt),
+        };
+        return match format_checked(&src, &config, &opt.defines) {
+            Ok(formatted) if check => {
+                if formatted != src {
+                    println!("<stdin>: not formatted");
+                    return Exit::CompileError;
+                }
+                Exit::Success
+            }
+            Ok(formatted) => {
+                print!("{}", formatted);
+                Exit::Success
+            }
+            Err(e) => report(stdin, &[e.into()], opt, &mut errors),
+        };
+    }
+
+    let mut worst = Exit::Success;
+    for file in files {
+        let exit = match fmt_file(file, check, opt, &mut errors) {
+            Ok(true) => Exit::Success,
+            Ok(false) => {
+                println!("{}: not formatted", file.display());
+                Exit::CompileError
+            }
+            Err(exit) => exit,
+        };
+        worst = worst.max(exit);
+        if opt.max_errors.is_some_and(|max| errors >= max) {
+            break;
+        }
+    }
+    worst
+}
+
+/// Format one file, counting errors in `errors`. Returns whether it was
+/// formatted already.
+fn fmt_file(
+    file: &Path,
+    check: bool,
+    opt: &ParserConfig,
+    errors: &mut usize,
+) -> Result<bool, Exit> {
+    let mut read = |e| {
+        *errors += 1;
+        io_error(file, "cannot read file", &e, opt)
+    };
+    let src = std::fs::read_to_string(file).map_err(&mut read)?;
+    let dir = file.canonicalize().map_err(read)?;
+    let config = load_config(dir.parent().unwrap_or(&dir)).map_err(|e| {
+        *errors += 1;
+        config_error(&e, opt)
+    })?;
+    let formatted = format_checked(&src, &config, &opt.defines)
+        .map_err(|e| report(file, &[e.into()], opt, errors))?;
+    if formatted == src {
+        return Ok(true);
+    }
+    if !check {
+        std::fs::write(file, formatted).map_err(|e| {
+            *errors += 1;
+            io_error(file, "cannot write file", &e, opt)
+        })?;
+    }
+    Ok(!check)
+}
+
+/// Format `src`, making sure the program compiled with `defines` means the
+/// same afterwards and that formatting again changes nothing
+fn format_checked(
+    src: &str,
+    config: &PrettyConfig,
+    defines: &[String],
+) -> Result<String, CompileError> {
+    let source = Source::new(src.into(), defines)?;
+    let formatted = format_with_features(src, defines, config).map_err(|e| source.annotate(e))?;
+
+    let before = parse_no_panic(source.compiled()).map_err(|e| source.annotate(e))?;
+    let after = Source::new(formatted.clone(), defines)
+        .ok()
+        .and_then(|after| parse_no_panic(after.compiled()).ok())
+        .ok_or_else(|| internal_error("formatted code does not parse"))?;
+    if !ast_eq(&before, &after) {
+        return Err(internal_error("formatting changed the program"));
+    }
+    match format_with_features(&formatted, defines, config) {
+        Ok(again) if again == formatted => Ok(formatted),
+        _ => Err(internal_error("formatting is not idempotent")),
+    }
+}
+
+/// A bug in the formatter
+fn internal_error(message: &str) -> CompileError {
+    CompileError::new(ErrorCode::INTERNAL, Stage::Parse, message)
+}
+
+/// Print that the config file is wrong, unless `--quiet` is given
+fn config_error(e: &str, opt: &ParserConfig) -> Exit {
+    if !opt.quiet {
+        eprintln!("{}", e);
+    }
+    Exit::CompileError
+}
+
+/// Read the config file closest to `dir`, or use the defaults if there is
+/// none
+fn load_config(dir: &Path) -> Result<PrettyConfig, String> {
+    let mut config = PrettyConfig::default();
+    let path = match dir
+        .ancestors()
+        .map(|dir| dir.join(CONFIG_FILE))
+        .find(|path| path.is_file())
+    {
+        Some(path) => path,
+        None => return Ok(config),
+    };
+
+    let file = std::fs::read_to_string(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|s| toml::from_str::<ConfigFile>(&s).map_err(|e| e.to_string()))
+        .map_err(|e| format!("bad config file {}: {}", path.display(), e))?;
+    if let Some(width) = file.indent_width {
+        config.indent_width = width;
+    }
+    if let Some(style) = file.brace_style {
+        config.brace_style = match style {
+            BraceStyleName::SameLine => BraceStyle::SameLine,
+            BraceStyleName::NextLine => BraceStyle::NextLine,
+        };
+    }
+    Ok(config)
+}
@@ -0,0 +1,160 @@
+//! Internal compiler errors. A panic is a bug in the compiler, so instead of
+//! a backtrace the user gets told so, along with where compilation was, and
+//! a small program crashing the same way is saved for the bug report.
+
+use chigusa::c0::{lexer::Lexer, parser::Parser};
+use chigusa::minivm::{current_span, Codegen};
+use std::cell::{Cell, RefCell};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
+/// Where reproductions are saved, in the current directory
+const REPRODUCTION_FILE: &str = "chigusa-ice.c0";
+
+/// Compiles tried while minimizing, so a slow compile can't stall a crash
+const MAX_ATTEMPTS: usize = 500;
+
+thread_local! {
+    static PHASE: Cell<Option<&'static str>> = const { Cell::new(None) };
+    static SOURCE: RefCell<Option<(Option<PathBuf>, String)>> = const { RefCell::new(None) };
+    /// Features `#if` directives of the source test for
+    static DEFINES: RefCell<Vec<String>> = const { RefCell::new(vec![]) };
+    /// Location in the compiler of the last panic
+    static LOCATION: RefCell<Option<String>> = const { RefCell::new(None) };
+    /// Set while looking for crashes, where panics are expected
+    static SILENT: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Replace the panic message with an explanation for users. Set
+/// `RUST_BACKTRACE` to also get the usual message and backtrace.
+pub fn install() {
+    let default = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let location = info.location().map(|l| l.to_string());
+        LOCATION.with(|l| *l.borrow_mut() = location.clone());
+        if SILENT.with(Cell::get) {
+            return;
+        }
+
+        let payload = info.payload();
+        let message = (payload.downcast_ref::<&str>().copied())
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("unknown panic");
+        eprintln!("error: internal compiler error: {}", message);
+        if let Some(location) = location {
+            eprintln!("  --> {}", location);
+        }
+        let mut doing = format!("chigusa {} crashed", env!("CARGO_PKG_VERSION"));
+        if let Some(phase) = PHASE.with(Cell::get) {
+            doing += &format!(" in pass `{}`", phase);
+        }
+        SOURCE.with(|s| {
+            if let Some((Some(file), _)) = &*s.borrow() {
+                doing += &format!(" on {}", file.display());
+            }
+        });
+        eprintln!("note: {}", doing);
+        if let Some(span) = current_span() {
+            eprintln!(
+                "note: while compiling line {}, column {}",
+                span.start.ln + 1,
+                span.start.pos + 1
+            );
+        }
+        eprintln!("note: this is a bug in the compiler, not in your program");
+        if std::env::var_os("RUST_BACKTRACE").is_some() {
+            default(info);
+        }
+    }));
+}
+
+/// Note that compilation is now in pass `phase`
+pub fn set_phase(phase: &'static str) {
+    PHASE.with(|p| p.set(Some(phase)));
+}
+
+/// Note that the source being compiled is `src`, read from `file`
+pub fn set_source(file: Option<&Path>, src: &str) {
+    SOURCE.with(|s| *s.borrow_mut() = Some((file.map(Path::to_owned), src.into())));
+}
+
+/// Note that sources are compiled with features `defines`
+pub fn set_defines(defines: &[String]) {
+    DEFINES.with(|d| *d.borrow_mut() = defines.to_vec());
+}
+
+/// After a crash, save the smallest program found that crashes at the same
+/// place, and tell the user where it is
+pub fn save_reproduction() {
+    let src = match SOURCE.with(|s| s.borrow().as_ref().map(|(_, src)| src.clone())) {
+        Some(src) => src,
+        None => return,
+    };
+    let location = LOCATION.with(|l| l.borrow().clone());
+
+    let reproduction = minimize(&src, |src| crash_location(src) == location);
+    // * If compiling alone doesn't crash, the crash needs something else
+    // * from the command line; the whole program is the best we have
+    let reproduction = reproduction.unwrap_or(src);
+
+    match std::fs::write(REPRODUCTION_FILE, &reproduction) {
+        Ok(()) => eprintln!(
+            "note: a {} line program crashing the same way was saved to {}; please attach it to \
+             a bug report",
+            reproduction.lines().count(),
+            REPRODUCTION_FILE
+        ),
+        Err(e) => eprintln!("note: cannot save {}: {}", REPRODUCTION_FILE, e),
+    }
+}
+
+/// Where compiling `src` with the features defined panics, or `None` if it
+/// doesn't. The panic is not reported.
+pub fn crash_location(src: &str) -> Option<String> {
+    let src = DEFINES.with(|d| chigusa::preprocess(src, &d.borrow()).ok())?;
+    LOCATION.with(|l| *l.borrow_mut() = None);
+    SILENT.with(|s| s.set(true));
+    let res = panic::catch_unwind(AssertUnwindSafe(|| {
+        if let Ok(prog) = Parser::new(Lexer::new(&src)).parse() {
+            let _ = Codegen::new(&prog).compile();
+        }
+    }));
+    SILENT.with(|s| s.set(false));
+    match res {
+        Ok(()) => None,
+        Err(_) => LOCATION.with(|l| l.borrow().clone()),
+    }
+}
+
+/// Remove lines from `src` for as long as it stays `interesting`: first
+/// large runs, then smaller ones. `None` if `src` itself is not interesting.
+fn minimize(src: &str, mut interesting: impl FnMut(&str) -> bool) -> Option<String> {
+    if !interesting(src) {
+        return None;
+    }
+    let mut lines: Vec<&str> = src.lines().collect();
+    let mut attempts = 0;
+    let mut chunk = lines.len().div_ceil(2).max(1);
+    loop {
+        let mut start = 0;
+        while start < lines.len() && attempts < MAX_ATTEMPTS {
+            let end = (start + chunk).min(lines.len());
+            let candidate: Vec<_> = lines[..start]
+                .iter()
+                .chain(&lines[end..])
+                .copied()
+                .collect();
+            attempts += 1;
+            if interesting(&candidate.join("\n")) {
+                lines = candidate;
+            } else {
+                start = end;
+            }
+        }
+        if chunk == 1 || attempts >= MAX_ATTEMPTS {
+            break;
+        }
+        chunk = chunk.div_ceil(2);
+    }
+    Some(lines.join("\n") + "\n")
+}
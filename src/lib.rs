@@ -1,25 +1,88 @@
-// #![feature(try_trait)]
+//! A compiler from C0 to O0, the bytecode of the C0 virtual machine.
+//!
+//! Use the functions and types at the root of the crate; see [`parse`] to
+//! get started. Everything in modules is an internal and may change.
+//!
+//! Without the `std` feature only [`lex`] and [`parse`] are built, and they
+//! need no more than `alloc`.
+
+#![cfg_attr(not(feature = "std"), no_std)]
 #![allow(dead_code)]
+// * Errors carry spans and messages, and only travel the failure path
+#![allow(clippy::result_large_err)]
+// * `failure`'s derive puts impls inside a constant
+#![allow(non_local_definitions)]
+
+extern crate alloc;
+
+mod api;
+pub use api::*;
+mod error;
+#[cfg(feature = "std")]
+pub use c0::alias::{Aliases, Place};
+pub use c0::ast::Program;
+#[cfg(feature = "std")]
+pub use c0::callgraph::CallGraph;
+#[cfg(feature = "std")]
+pub use c0::fingerprint::Fingerprint;
+#[cfg(feature = "std")]
+pub use c0::hir::TypedProgram;
+#[cfg(feature = "std")]
+pub use c0::ide::{RenameError, TextEdit};
+pub use c0::lexer::{Token, TokenType};
+#[cfg(feature = "std")]
+pub use c0::lint::{Forbidden, LintRule, LintRules, Violation};
+#[cfg(feature = "std")]
+pub use c0::metrics::{FnMetrics, Metrics};
+#[cfg(feature = "std")]
+pub use c0::mutate::{Mutant, Mutation, MutationKind};
+pub use c0::preprocess::SourceMap;
+#[cfg(feature = "std")]
+pub use c0::purity::{Impurity, Purity};
+#[cfg(feature = "std")]
+pub use c0::query::{Match, NodeKind, Query, QueryError, Selector};
+pub use c0::symbol::Symbol;
+pub use error::*;
+#[cfg(feature = "std")]
+pub use minivm::{
+    CompilerPass, HostSig, Inst, InstSink, PassError, PassManager, Remark, RemarkKind, Remarks,
+    Standard, Target, O0,
+};
+pub use prelude::{Pos, Span};
 
 /// C0 is the main library hosting tools to tokenize, generate AST from and
 /// compile C0.
+#[doc(hidden)]
 pub mod c0;
 
+#[doc(hidden)]
+#[cfg(feature = "std")]
 pub mod minivm;
 
 /// Kurumi is a simple virtual machine for this project.
 // #[cfg(kurumi)]
 // pub mod kurumi;
 
-#[cfg(cranelift_codegen)]
+#[doc(hidden)]
+#[cfg(feature = "cranelift_codegen")]
 /// x86 codegen using Cranelift
 pub mod cranelift;
 
+/// Evaluating constant expressions at compile time
+#[cfg(feature = "std")]
+pub mod consteval;
+
+/// Compiling from JSON-speaking hosts like a browser playground
+#[cfg(feature = "std")]
+pub mod playground;
+
 /// Essencial stuff
+#[doc(hidden)]
 pub mod prelude;
 
 /// Stuff for binary program
+#[cfg(feature = "std")]
 pub(crate) mod opt;
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests;
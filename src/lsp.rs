@@ -0,0 +1,443 @@
+//! `chigusa lsp`: a language server speaking LSP over stdio.
+//!
+//! Files are kept in memory and parsed again on every request; C0 programs
+//! are small enough for this to be instant. Columns are counted in chars,
+//! which is what LSP expects for everything but astral-plane characters.
+
+use chigusa::c0::highlight::{highlight, TokenClass};
+use chigusa::c0::ide::{self, SymbolInfo, SymbolKind};
+use chigusa::c0::symbol::with_fresh_names;
+use chigusa::Span;
+use lsp_server::{Connection, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _,
+    PublishDiagnostics,
+};
+use lsp_types::request::{
+    DocumentSymbolRequest, GotoDefinition, HoverRequest, References, Rename, Request as _,
+    SemanticTokensFullRequest,
+};
+use lsp_types::*;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::error::Error;
+
+type LspResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// Semantic token types we send, indexed by `token_type`
+const TOKEN_TYPES: &[SemanticTokenType] = &[
+    SemanticTokenType::KEYWORD,
+    SemanticTokenType::TYPE,
+    SemanticTokenType::FUNCTION,
+    SemanticTokenType::VARIABLE,
+    SemanticTokenType::NUMBER,
+    SemanticTokenType::STRING,
+    SemanticTokenType::COMMENT,
+    SemanticTokenType::OPERATOR,
+];
+
+/// Serve the client on stdin and stdout until it shuts us down
+pub fn serve() -> LspResult<()> {
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        rename_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        semantic_tokens_provider: Some(
+            SemanticTokensOptions {
+                legend: SemanticTokensLegend {
+                    token_types: TOKEN_TYPES.to_vec(),
+                    token_modifiers: vec![],
+                },
+                full: Some(SemanticTokensFullOptions::Bool(true)),
+                ..Default::default()
+            }
+            .into(),
+        ),
+        ..Default::default()
+    };
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+
+    let mut server = Server {
+        connection: &connection,
+        files: HashMap::new(),
+    };
+    // * Each message is handled with names of its own, so a long session
+    // * doesn't keep every name ever typed
+    for msg in &connection.receiver {
+        match msg {
+            Message::Request(req) => {
+                if connection.handle_shutdown(&req)? {
+                    break;
+                }
+                with_fresh_names(|| server.request(req))?;
+            }
+            Message::Notification(not) => with_fresh_names(|| server.notification(not))?,
+            Message::Response(_) => (),
+        }
+    }
+
+    // * The writer thread only exits once the connection is gone
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+struct Server<'a> {
+    connection: &'a Connection,
+    /// Contents of open files
+    files: HashMap<Url, String>,
+}
+
+impl<'a> Server<'a> {
+    fn request(&mut self, req: Request) -> LspResult<()> {
+        let result = match &req.method[..] {
+            GotoDefinition::METHOD => {
+                let params: GotoDefinitionParams = serde_json::from_value(req.params)?;
+                let pos = params.text_document_position_params;
+                let uri = pos.text_document.uri;
+                let res = self.symbol_at(&uri, pos.position).map(|sym| {
+                    GotoDefinitionResponse::Scalar(Location::new(
+                        uri.clone(),
+                        range(sym.def.name_span),
+                    ))
+                });
+                serde_json::to_value(res)?
+            }
+            References::METHOD => {
+                let params: ReferenceParams = serde_json::from_value(req.params)?;
+                let pos = params.text_document_position;
+                let uri = pos.text_document.uri;
+                let with_decl = params.context.include_declaration;
+                let res = self.references(&uri, pos.position).map(|(decl, uses)| {
+                    (with_decl.then_some(decl).into_iter())
+                        .chain(uses)
+                        .map(|span| Location::new(uri.clone(), range(span)))
+                        .collect::<Vec<_>>()
+                });
+                serde_json::to_value(res)?
+            }
+            Rename::METHOD => {
+                let params: RenameParams = serde_json::from_value(req.params)?;
+                let pos = params.text_document_position;
+                let uri = pos.text_document.uri;
+                // * Renames that would change what a name refers to are
+                // * refused with the reason, for the editor to show
+                let edits = match self.rename(&uri, pos.position, &params.new_name) {
+                    Some(Ok(edits)) => edits,
+                    Some(Err(e)) => {
+                        let resp = Response::new_err(
+                            req.id,
+                            lsp_server::ErrorCode::RequestFailed as i32,
+                            e.to_string(),
+                        );
+                        self.connection.sender.send(resp.into())?;
+                        return Ok(());
+                    }
+                    None => vec![],
+                };
+                let edits = (edits.into_iter())
+                    .map(|edit| TextEdit::new(range(edit.span), edit.text))
+                    .collect();
+                let res = WorkspaceEdit::new(HashMap::from([(uri, edits)]));
+                serde_json::to_value(res)?
+            }
+            HoverRequest::METHOD => {
+                let params: HoverParams = serde_json::from_value(req.params)?;
+                let pos = params.text_document_position_params;
+                let uri = &pos.text_document.uri;
+                // * Names show their declaration and doc comment, other
+                // * expressions their type
+                let res = self
+                    .symbol_at(uri, pos.position)
+                    .map(|sym| (sym.span, sym.def.detail, sym.def.doc))
+                    .or_else(|| {
+                        let (span, typ) = self.type_at(uri, pos.position)?;
+                        Some((span, typ, None))
+                    })
+                    .map(|(span, detail, doc)| Hover {
+                        contents: HoverContents::Markup(MarkupContent {
+                            kind: MarkupKind::Markdown,
+                            value: match doc {
+                                Some(doc) => format!("```c\n{}\n```\n\n{}", detail, doc),
+                                None => format!("```c\n{}\n```", detail),
+                            },
+                        }),
+                        range: Some(range(span)),
+                    });
+                serde_json::to_value(res)?
+            }
+            DocumentSymbolRequest::METHOD => {
+                let params: DocumentSymbolParams = serde_json::from_value(req.params)?;
+                let res = self.parse(&params.text_document.uri).map(|prog| {
+                    let syms = ide::document_symbols(&prog);
+                    DocumentSymbolResponse::Nested(syms.iter().map(document_symbol).collect())
+                });
+                serde_json::to_value(res)?
+            }
+            SemanticTokensFullRequest::METHOD => {
+                let params: SemanticTokensParams = serde_json::from_value(req.params)?;
+                // * Lines left out by `#if` are not highlighted, unless the
+                // * directives are wrong
+                let res = self.files.get(&params.text_document.uri).map(|src| {
+                    SemanticTokensResult::Tokens(SemanticTokens {
+                        result_id: None,
+                        data: semantic_tokens(&preprocess(src).unwrap_or(Cow::Borrowed(src))),
+                    })
+                });
+                serde_json::to_value(res)?
+            }
+            _ => {
+                let resp = Response::new_err(
+                    req.id,
+                    lsp_server::ErrorCode::MethodNotFound as i32,
+                    format!("Unsupported request: {}", req.method),
+                );
+                self.connection.sender.send(resp.into())?;
+                return Ok(());
+            }
+        };
+        self.respond(req.id, result)
+    }
+
+    fn notification(&mut self, not: Notification) -> LspResult<()> {
+        match &not.method[..] {
+            DidOpenTextDocument::METHOD => {
+                let params: DidOpenTextDocumentParams = serde_json::from_value(not.params)?;
+                let doc = params.text_document;
+                self.files.insert(doc.uri.clone(), doc.text);
+                self.publish_diagnostics(doc.uri)
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: DidChangeTextDocumentParams = serde_json::from_value(not.params)?;
+                // * We asked for full syncs, so the last change has the whole text
+                if let Some(change) = params.content_changes.into_iter().last() {
+                    let uri = params.text_document.uri;
+                    self.files.insert(uri.clone(), change.text);
+                    self.publish_diagnostics(uri)?;
+                }
+                Ok(())
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: DidCloseTextDocumentParams = serde_json::from_value(not.params)?;
+                let uri = params.text_document.uri;
+                self.files.remove(&uri);
+                self.publish_diagnostics(uri)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn respond(&self, id: RequestId, result: serde_json::Value) -> LspResult<()> {
+        let resp = Response {
+            id,
+            result: Some(result),
+            error: None,
+        };
+        self.connection.sender.send(resp.into())?;
+        Ok(())
+    }
+
+    /// Open file `uri`, parsed, if it has no errors stopping the parser
+    fn parse(&self, uri: &Url) -> Option<chigusa::Program> {
+        chigusa::parse(&preprocess(self.files.get(uri)?).ok()?).ok()
+    }
+
+    fn symbol_at(&self, uri: &Url, pos: Position) -> Option<ide::SymbolRef> {
+        let src = self.files.get(uri)?;
+        let prog = self.parse(uri)?;
+        let pos = ide::pos_at(src, pos.line as usize, pos.character as usize);
+        ide::symbol_at(&prog, pos)
+    }
+
+    /// Span of the name in the declaration of the symbol at `pos`, and of
+    /// everywhere it is used
+    fn references(&self, uri: &Url, pos: Position) -> Option<(Span, Vec<Span>)> {
+        let src = self.files.get(uri)?;
+        let prog = self.parse(uri)?;
+        let pos = ide::pos_at(src, pos.line as usize, pos.character as usize);
+        let decl = ide::symbol_at(&prog, pos)?.def.name_span;
+        let xref = ide::Xref::new(&prog);
+        Some((decl, xref.find(decl)?.uses.clone()))
+    }
+
+    /// Edits renaming the symbol at `pos`, or `None` if the file does not
+    /// parse
+    fn rename(
+        &self,
+        uri: &Url,
+        pos: Position,
+        new_name: &str,
+    ) -> Option<Result<Vec<ide::TextEdit>, ide::RenameError>> {
+        let src = self.files.get(uri)?;
+        let prog = self.parse(uri)?;
+        let pos = ide::pos_at(src, pos.line as usize, pos.character as usize);
+        Some(ide::rename(&prog, pos, new_name))
+    }
+
+    fn type_at(&self, uri: &Url, pos: Position) -> Option<(Span, String)> {
+        let src = self.files.get(uri)?;
+        let prog = chigusa::typed(&self.parse(uri)?).ok()?;
+        let pos = ide::pos_at(src, pos.line as usize, pos.character as usize);
+        ide::type_at(&prog, pos)
+    }
+
+    /// Check the file and send what is wrong with it. Closed files get their
+    /// diagnostics cleared.
+    fn publish_diagnostics(&self, uri: Url) -> LspResult<()> {
+        let diagnostics = self
+            .files
+            .get(&uri)
+            .map(|src| check(&uri, src))
+            .unwrap_or_default();
+        let params = PublishDiagnosticsParams::new(uri, diagnostics, None);
+        let not = Notification::new(PublishDiagnostics::METHOD.into(), params);
+        self.connection.sender.send(not.into())?;
+        Ok(())
+    }
+}
+
+/// `src` with its `#if` directives applied. No features are defined.
+fn preprocess(src: &str) -> Result<Cow<'_, str>, chigusa::CompileError> {
+    chigusa::preprocess(src, &[] as &[&str])
+}
+
+/// Parse and compile `src`, collecting errors
+fn check(uri: &Url, src: &str) -> Vec<Diagnostic> {
+    let (src, map) = match chigusa::preprocess_mapped(src, &[] as &[&str]) {
+        Ok(preprocessed) => preprocessed,
+        Err(e) => return vec![diagnostic(uri, e.into())],
+    };
+    // * The parser stops at the first error, so there is at most one for now
+    let prog = match chigusa::parse(&src) {
+        Ok(prog) => prog,
+        Err(e) => return vec![diagnostic(uri, map.annotate(e).into())],
+    };
+    let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| chigusa::check(&prog)));
+    let diags = res.unwrap_or_else(|_| {
+        let e = chigusa::CompileError::new(
+            chigusa::ErrorCode(999),
+            chigusa::Stage::Compile,
+            "Internal error inside compiler: compiler panicked",
+        );
+        vec![e.into()]
+    });
+    (diags.into_iter())
+        .map(|mut d| {
+            d.notes
+                .extend(d.span.map(|span| map.notes(span)).unwrap_or_default());
+            diagnostic(uri, d)
+        })
+        .collect()
+}
+
+fn diagnostic(uri: &Url, diag: chigusa::Diagnostic) -> Diagnostic {
+    let span = diag.span.unwrap_or_else(Span::zero);
+    let related = diag
+        .notes
+        .into_iter()
+        .map(|note| DiagnosticRelatedInformation {
+            location: Location::new(uri.clone(), range(note.span.unwrap_or(span))),
+            message: note.message,
+        })
+        .collect::<Vec<_>>();
+    Diagnostic {
+        range: range(span),
+        severity: Some(match diag.severity {
+            chigusa::Severity::Error => DiagnosticSeverity::ERROR,
+            chigusa::Severity::Warning => DiagnosticSeverity::WARNING,
+        }),
+        code: Some(NumberOrString::String(diag.code.to_string())),
+        source: Some("chigusa".into()),
+        message: diag.message,
+        related_information: if related.is_empty() {
+            None
+        } else {
+            Some(related)
+        },
+        ..Default::default()
+    }
+}
+
+#[allow(deprecated)]
+fn document_symbol(sym: &SymbolInfo) -> DocumentSymbol {
+    DocumentSymbol {
+        name: sym.name.clone(),
+        detail: Some(sym.detail.clone()),
+        kind: match sym.kind {
+            SymbolKind::Function => lsp_types::SymbolKind::FUNCTION,
+            SymbolKind::Variable | SymbolKind::Parameter => lsp_types::SymbolKind::VARIABLE,
+            SymbolKind::Constant => lsp_types::SymbolKind::CONSTANT,
+        },
+        tags: None,
+        deprecated: None,
+        range: range(sym.span),
+        selection_range: range(sym.name_span),
+        children: if sym.children.is_empty() {
+            None
+        } else {
+            Some(sym.children.iter().map(document_symbol).collect())
+        },
+    }
+}
+
+/// Highlight `src` as LSP semantic tokens. Tokens spanning several lines,
+/// like block comments, are split into one token per line.
+fn semantic_tokens(src: &str) -> Vec<SemanticToken> {
+    let line_lens: Vec<usize> = src.lines().map(|l| l.chars().count()).collect();
+    let mut tokens = vec![];
+    let (mut last_ln, mut last_start) = (0, 0);
+    for (span, class) in highlight(src) {
+        let token_type = match class {
+            TokenClass::Keyword | TokenClass::Boolean => 0,
+            TokenClass::Type => 1,
+            TokenClass::Function => 2,
+            TokenClass::Identifier => 3,
+            TokenClass::Number => 4,
+            TokenClass::String | TokenClass::Char => 5,
+            TokenClass::Comment => 6,
+            TokenClass::Operator => 7,
+            TokenClass::Punctuation | TokenClass::Error => continue,
+        };
+        for ln in span.start.ln..=span.end.ln {
+            let start = if ln == span.start.ln {
+                span.start.pos
+            } else {
+                0
+            };
+            let end = if ln == span.end.ln {
+                span.end.pos
+            } else {
+                line_lens.get(ln).copied().unwrap_or(0)
+            };
+            if end <= start {
+                continue;
+            }
+            let delta_start = if ln == last_ln {
+                start - last_start
+            } else {
+                start
+            };
+            tokens.push(SemanticToken {
+                delta_line: (ln - last_ln) as u32,
+                delta_start: delta_start as u32,
+                length: (end - start) as u32,
+                token_type,
+                token_modifiers_bitset: 0,
+            });
+            last_ln = ln;
+            last_start = start;
+        }
+    }
+    tokens
+}
+
+fn range(span: Span) -> Range {
+    Range::new(
+        Position::new(span.start.ln as u32, span.start.pos as u32),
+        Position::new(span.end.ln as u32, span.end.pos as u32),
+    )
+}
@@ -1,17 +1,302 @@
+// * Errors carry spans and messages, and only travel the failure path
+#![allow(clippy::result_large_err)]
+
+mod ast_diff;
+mod check;
+mod config;
+mod cov;
+mod dap;
+mod debug;
+mod difftest;
 mod err_disp;
+mod exit;
+mod fix;
+mod fmt;
+mod ice;
+mod lsp;
 mod opt;
-use chigusa::c0::lexer;
-use failure::Fail;
-use opt::{EmitOption, ParserConfig};
+mod reduce;
+mod run;
+mod source;
+mod stats;
+mod time_passes;
+mod watch;
+use chigusa::c0::gen::{generate, GenConfig};
+use chigusa::c0::ide::Xref;
+use chigusa::c0::obfuscate::obfuscate;
+use chigusa::c0::{doc, parse_no_panic};
+use chigusa::c0::{lexer, validate};
+use chigusa::minivm::passes::{compile_order, PassError};
+use chigusa::minivm::vm::{Intrinsics, TraceKind};
+use chigusa::minivm::{
+    binfmt, disassemble, Codegen, CounterMap, CoverageMap, ExecProfile, OptFilter, Pass,
+    PassManager, Remarks, SizeReport, O0,
+};
+use exit::Exit;
+use opt::{Command, EmitOption, ParserConfig};
+use source::Source;
+use stats::Stats;
 use std::fs::*;
-use std::io::{Read, Write};
-use std::path::PathBuf;
-use structopt;
+use std::io::{BufReader, Read, Write};
+use std::path::{Path, PathBuf};
 use structopt::StructOpt;
+use time_passes::{CountingAlloc, PassTimes};
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
 
 fn main() {
+    ice::install();
+    // * The panic itself has been printed already
+    if std::panic::catch_unwind(cli).is_err() {
+        ice::save_reproduction();
+        Exit::InternalError.exit();
+    }
+}
+
+fn cli() {
     let mut opt: ParserConfig = ParserConfig::from_args();
-    cute_log::init_with_max_level(opt.verbosity).unwrap();
+    let filter = match opt.log_filter() {
+        Ok(filter) => filter,
+        Err(e) => {
+            eprintln!("Bad log filter: {}", e);
+            std::process::exit(1);
+        }
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .init();
+
+    if let Some(bad) = (opt.defines.iter()).find(|d| !is_feature(d)) {
+        eprintln!("Bad feature `{}`: features are names, like `EXT`", bad);
+        std::process::exit(1);
+    }
+    ice::set_defines(&opt.defines);
+
+    if let Some(Command::Difftest {
+        files,
+        input,
+        steps,
+    }) = &opt.cmd
+    {
+        difftest::difftest(files, input.as_deref(), *steps, &opt).exit();
+    }
+
+    if let Some(Command::Check { files }) = &opt.cmd {
+        check::check(
+            files,
+            target(opt.target.as_deref()),
+            standard(opt.std.as_deref()),
+            &opt,
+        )
+        .exit();
+    }
+
+    if let Some(Command::Fix { files }) = &opt.cmd {
+        fix::fix(
+            files,
+            target(opt.target.as_deref()),
+            standard(opt.std.as_deref()),
+            &opt,
+        )
+        .exit();
+    }
+
+    if let Some(Command::Fmt { files, check }) = &opt.cmd {
+        fmt::fmt(files, *check, &opt).exit();
+    }
+
+    if let Some(Command::AstDiff { old, new }) = &opt.cmd {
+        ast_diff::ast_diff(old, new, &opt).exit();
+    }
+
+    if let Some(Command::Cov { file, input, steps }) = &opt.cmd {
+        cov::cov(file, input, *steps, &opt).exit();
+    }
+
+    if let Some(Command::CovReport {
+        file,
+        counters,
+        outputs,
+    }) = &opt.cmd
+    {
+        let counters = counters.as_deref().unwrap_or(Path::new("out.counters"));
+        cov::cov_report(file, counters, outputs, &opt).exit();
+    }
+
+    if let Some(Command::Debug {
+        file,
+        input,
+        history,
+    }) = &opt.cmd
+    {
+        debug::debug(file, input.as_deref(), *history, &opt).exit();
+    }
+
+    if let Some(Command::Run {
+        file,
+        profile,
+        folded,
+        profile_out,
+        stdin_file,
+        expect_output,
+        steps,
+        timeout,
+        max_heap,
+        max_call_depth,
+        trace,
+        trace_file,
+        trace_fn,
+        seed,
+        virtual_clock,
+        sanitize,
+    }) = &opt.cmd
+    {
+        let trace = trace.as_ref().map(|kind| run::TraceOptions {
+            kind: match kind.as_deref() {
+                Some("calls") => TraceKind::Calls,
+                Some("jumps") => TraceKind::Jumps,
+                _ => TraceKind::All,
+            },
+            file: (trace_file.clone()).unwrap_or_else(|| file.with_extension("trace")),
+            functions: trace_fn.clone(),
+        });
+        let opts = run::RunOptions {
+            profile: *profile,
+            folded: folded.clone(),
+            profile_out: profile_out.clone(),
+            stdin_file: stdin_file.clone(),
+            expect_output: expect_output.clone(),
+            trace,
+            limits: run::Limits {
+                steps: *steps,
+                timeout: *timeout,
+                heap_bytes: *max_heap,
+                call_depth: *max_call_depth,
+            },
+            intrinsics: Intrinsics {
+                seed: *seed,
+                virtual_clock: *virtual_clock,
+            },
+            ub_checks: sanitize.is_some(),
+            defines: opt.defines.clone(),
+        };
+        std::process::exit(run::run(file, &opts, &opt));
+    }
+
+    if let Some(Command::Watch { file, args }) = &opt.cmd {
+        if let Err(e) = watch::watch(file, args, &opt.defines) {
+            eprintln!("Cannot watch {}: {}", file.display(), e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(Command::Reduce {
+        file,
+        crashcmd,
+        output,
+    }) = &opt.cmd
+    {
+        reduce::reduce(file, crashcmd.as_deref(), output.as_deref(), &opt).exit();
+    }
+
+    if let Some(Command::Doc {
+        file,
+        format,
+        output,
+    }) = &opt.cmd
+    {
+        let prog = load_program(file, &opt).unwrap_or_else(|e| e.exit());
+        let title = file.file_stem().unwrap_or_default().to_string_lossy();
+        let format = match &format[..] {
+            "html" => doc::DocFormat::Html,
+            _ => doc::DocFormat::Markdown,
+        };
+        let listing = doc::render(&prog, &title, format);
+        match output {
+            Some(output) => {
+                let res = std::fs::write(output, listing);
+                write_failed(&opt, output, res).unwrap_or_else(|e| e.exit());
+            }
+            None => print!("{}", listing),
+        }
+        return;
+    }
+
+    if let Some(Command::Metrics { file, json, output }) = &opt.cmd {
+        let prog = load_program(file, &opt).unwrap_or_else(|e| e.exit());
+        let metrics = chigusa::metrics(&prog);
+        let report = match json {
+            true => metrics.to_json() + "\n",
+            false => metrics.to_string(),
+        };
+        match output {
+            Some(output) => {
+                let res = std::fs::write(output, report);
+                write_failed(&opt, output, res).unwrap_or_else(|e| e.exit());
+            }
+            None => print!("{}", report),
+        }
+        return;
+    }
+
+    if let Some(Command::Obfuscate { file, output }) = &opt.cmd {
+        let mut errors = 0;
+        let src = source::load(file, &opt.defines)
+            .map_err(|e| check::load_failed(file, e, &opt, &mut errors))
+            .and_then(|src| {
+                obfuscate(src.compiled())
+                    .map_err(|e| check::report(file, &[src.annotate(e).into()], &opt, &mut errors))
+            });
+        let src = src.unwrap_or_else(|e| e.exit());
+        match output {
+            Some(output) => {
+                let res = std::fs::write(output, src);
+                write_failed(&opt, output, res).unwrap_or_else(|e| e.exit());
+            }
+            None => print!("{}", src),
+        }
+        return;
+    }
+
+    if let Some(Command::Gen {
+        functions,
+        depth,
+        seed,
+        output,
+    }) = &opt.cmd
+    {
+        let config = GenConfig {
+            functions: *functions,
+            depth: *depth,
+            seed: *seed,
+        };
+        let src = generate(&config);
+        match output {
+            Some(output) => {
+                let res = std::fs::write(output, src);
+                write_failed(&opt, output, res).unwrap_or_else(|e| e.exit());
+            }
+            None => print!("{}", src),
+        }
+        return;
+    }
+
+    if let Some(Command::Disasm { file }) = &opt.cmd {
+        disasm(file, &opt).unwrap_or_else(|e| e.exit());
+        return;
+    }
+
+    if let Some(Command::Dap) = &opt.cmd {
+        let res = dap::serve(&opt.defines);
+        served(&opt, "Debug adapter", res.map_err(Into::into)).exit();
+    }
+
+    if let Some(Command::Lsp) = &opt.cmd {
+        served(&opt, "Language server", lsp::serve()).exit();
+    }
 
     if opt.output_assembly {
         opt.emit = EmitOption::S0;
@@ -19,79 +304,555 @@ fn main() {
     if opt.output_binary {
         opt.emit = EmitOption::O0;
     }
+    let cwd = std::env::current_dir().unwrap_or_default();
+    let jobs = match config::merge(&mut opt, &cwd) {
+        Ok(jobs) => jobs,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let mut worst = Exit::Success;
+    let mut errors = 0;
+    for job in jobs {
+        opt.input_file = job.input;
+        opt.output_file = Some(job.output);
+        if let Err(exit) = compile(&opt) {
+            worst = worst.max(exit);
+            errors += 1;
+            if opt.max_errors.is_some_and(|max| errors >= max) {
+                break;
+            }
+        }
+    }
+    worst.exit();
+}
 
-    let mut input = String::new();
-    if let Some(f) = &opt.input_file {
-        std::fs::File::open(f)
-            .expect("File does not exist!")
-            .read_to_string(&mut input)
-            .expect("Failed to read");
-    } else {
-        std::io::stdin()
-            .read_to_string(&mut input)
-            .expect("Failed to read");
+/// Compile the input file, or stdin, as the command line says. Errors are
+/// printed unless `--quiet` is given, and returned as the exit code.
+fn compile(opt: &ParserConfig) -> Result<(), Exit> {
+    let target = target(opt.target.as_deref());
+    let pipeline = match PassManager::for_level(opt.opt_level.unwrap_or(1)) {
+        Some(pipeline) => pipeline,
+        None => {
+            eprintln!("Unknown optimization level. Allowed are: 0, 1, 2");
+            return Err(Exit::CompileError);
+        }
+    };
+    let pipeline = match &opt.passes {
+        Some(names) => match pipeline.with_pipeline(names) {
+            Ok(pipeline) => pipeline,
+            Err(e) => {
+                eprintln!("{}", e);
+                return Err(Exit::CompileError);
+            }
+        },
+        None => pipeline,
+    };
+    let optimize = !pipeline.pipeline().is_empty();
+
+    let profile = match &opt.profile_use {
+        Some(path) => {
+            let profile = std::fs::read_to_string(path).map_err(|e| e.to_string());
+            match profile.and_then(|p| ExecProfile::from_json(&p).map_err(|e| e.to_string())) {
+                Ok(profile) => Some(profile),
+                Err(e) => {
+                    if !opt.quiet {
+                        eprintln!("{}: cannot read profile: {}", path.display(), e);
+                    }
+                    return Err(Exit::IoError);
+                }
+            }
+        }
+        None => None,
     };
 
-    let token = lexer::Lexer::new(Box::new(input.chars())).into_iter();
+    let mut opt_filter = OptFilter {
+        limit: opt.opt_bisect,
+        functions: Some(opt.opt_fn.clone()).filter(|fns| !fns.is_empty()),
+        ..OptFilter::default()
+    };
+    if !opt.opt_pass.is_empty() {
+        opt_filter.passes = vec![];
+        for name in &opt.opt_pass {
+            match Pass::from_name(name) {
+                Some(pass) => opt_filter.passes.push(pass),
+                None => {
+                    eprintln!("{}", PassError::Unknown(name.clone(), pipeline.names()));
+                    return Err(Exit::CompileError);
+                }
+            }
+        }
+    }
+
+    let mut remarked = vec![];
+    for name in opt.remarks.iter().flat_map(|names| names.split(',')) {
+        match Pass::from_name(name.trim()) {
+            Some(pass) => remarked.push(pass),
+            None => {
+                eprintln!("{}", PassError::Unknown(name.into(), pipeline.names()));
+                return Err(Exit::CompileError);
+            }
+        }
+    }
+    let remarks = Remarks::new(remarked);
+
+    let mut passes = PassTimes::new(opt.time_passes || opt.stats);
+    let mut stats = Stats::default();
+
+    let input = passes.time("read", || {
+        let mut input = String::new();
+        match &opt.input_file {
+            Some(f) => File::open(f).and_then(|mut f| f.read_to_string(&mut input)),
+            None => std::io::stdin().read_to_string(&mut input),
+        }
+        .map(|_| input)
+    });
+    let input = match input {
+        Ok(input) => input,
+        Err(e) => {
+            if !opt.quiet {
+                let name = (opt.input_file.as_deref()).unwrap_or_else(|| Path::new("<stdin>"));
+                eprintln!("{}: cannot read: {}", name.display(), e);
+            }
+            return Err(Exit::IoError);
+        }
+    };
+    ice::set_source(opt.input_file.as_deref(), &input);
+    // * Spans into the source compiled are spans into `input`, which errors
+    // * are shown in, with notes on the `#if` directives compiling them
+    let source = passes.time("preprocess", || Source::new(input.clone(), &opt.defines));
+    let source = match source {
+        Ok(source) => source,
+        Err(e) => {
+            report(opt, &passes, &mut stats);
+            return Err(compile_error(opt, &input, e));
+        }
+    };
+    drop(input);
+    let input = &source.text;
+
+    if opt.stream {
+        let emits_code = matches!(
+            opt.emit,
+            EmitOption::S0 | EmitOption::O0 | EmitOption::SizeReport | EmitOption::SizeReportJson
+        );
+        if !emits_code || opt.instrument_coverage {
+            eprintln!("--stream only compiles to code, without --instrument-coverage");
+            return Err(Exit::CompileError);
+        }
+        let s0 = passes.time("codegen", || {
+            chigusa::codegen_streamed(source.compiled(), |codegen| {
+                configure(
+                    codegen, opt, target, optimize, profile, pipeline, opt_filter,
+                )
+                .with_remarks(remarks.clone())
+            })
+        });
+        print_remarks(opt, &remarks);
+        return emit_code(
+            opt,
+            input,
+            &mut passes,
+            &mut stats,
+            s0.map(|o0| (o0, None)).map_err(|e| source.annotate(e)),
+        );
+    }
+
+    let tokens: Vec<_> = passes.time("lex", || lexer::Lexer::new(source.compiled()).collect());
+    stats.tokens = Some(tokens.len());
+    if opt.verify_each {
+        let res = passes.time("verify", || validate::validate_tokens(&tokens));
+        res.unwrap_or_else(|e| panic!("Tokens are invalid after lexing: {}", e));
+    }
 
     if opt.emit == EmitOption::Token {
-        let tokens: Vec<_> = token.collect();
-        write_output(&opt, tokens);
-        return;
+        let res = passes.time("emit", || write_output(opt, tokens));
+        report(opt, &passes, &mut stats);
+        return res;
     }
 
-    let tree = chigusa::c0::parser::Parser::new(token).parse();
+    let tree = passes.time("parse", || {
+        chigusa::c0::parser::Parser::new(tokens.into_iter()).parse()
+    });
 
     let tree = match tree {
         Ok(t) => t,
         Err(e) => {
-            let mut input_lines = input.lines();
-            let err_des = format!("Parsing error: {}", &e.var);
-            let span = e.span;
-            err_disp::pretty_print_error(&mut input_lines, span, &err_des);
-            std::process::exit(1);
+            report(opt, &passes, &mut stats);
+            let e = source.annotate(e);
+            if !opt.quiet {
+                let mut input_lines = input.lines();
+                let err_des = format!("Parsing error[{}]: {}", e.code, e.message);
+                err_disp::pretty_print_error(&mut input_lines, e.span.unwrap(), &err_des);
+                err_disp::print_notes(input, &e.notes);
+                err_disp::print_fixes(&e.fixes);
+            }
+            return Err(Exit::of(e.code));
         }
     };
 
+    if opt.verify_each {
+        let res = passes.time("verify", || validate::validate(&tree));
+        res.unwrap_or_else(|e| panic!("Syntax tree is invalid after parsing: {}", e));
+    }
+
+    if opt.stats {
+        stats.count_ast(&tree);
+    }
+
     if opt.emit == EmitOption::Ast {
-        write_output(&opt, tree);
-        return;
+        let res = passes.time("emit", || write_output(opt, tree));
+        report(opt, &passes, &mut stats);
+        return res;
     }
 
-    let s0 = chigusa::minivm::Codegen::new(&tree).compile();
-    let s0 = match s0 {
-        Ok(t) => t,
-        Err(e) => {
-            let mut input_lines = input.lines();
-            let err_des = format!("Compile error: {}", &e.var);
+    if opt.emit == EmitOption::TypedAst {
+        let typed = passes.time("typeck", || chigusa::typed(&tree));
+        report(opt, &passes, &mut stats);
+        return match typed {
+            Ok(program) => passes.time("emit", || {
+                let aliases = chigusa::aliases(&program);
+                write_output(opt, TypedAst { program, aliases })
+            }),
+            Err(e) => Err(compile_error(opt, input, source.annotate(e))),
+        };
+    }
 
-            if let Some(span) = e.span {
-                err_disp::pretty_print_error(&mut input_lines, span, &err_des);
+    if matches!(
+        opt.emit,
+        EmitOption::CallGraph | EmitOption::Xref | EmitOption::XrefJson
+    ) {
+        let res = passes.time("emit", || {
+            let listing = match opt.emit {
+                EmitOption::CallGraph => chigusa::CallGraph::new(&tree).to_dot(),
+                EmitOption::Xref => Xref::new(&tree).to_string(),
+                _ => Xref::new(&tree).to_json() + "\n",
+            };
+            if opt.stdout {
+                print!("{}", listing);
+                Ok(())
             } else {
-                log::error!("{}", err_des);
+                let res =
+                    File::create(opt.output_path()).and_then(|mut f| write!(f, "{}", listing));
+                write_failed(opt, opt.output_path(), res)
             }
-            std::process::exit(1);
+        });
+        report(opt, &passes, &mut stats);
+        return res;
+    }
+
+    if optimize && !opt.quiet && (opt_filter.limit.is_some() || opt_filter.functions.is_some()) {
+        for (idx, name) in compile_order(&tree).iter().enumerate() {
+            let verb = match opt_filter.optimizes(idx, name) {
+                true => "optimizing",
+                false => "NOT optimizing",
+            };
+            eprintln!("opt-bisect: {} ({}) {}", verb, idx + 1, name);
+        }
+    }
+
+    let s0 = passes.time("codegen", || {
+        let codegen = Codegen::new(&tree);
+        let codegen = configure(
+            codegen, opt, target, optimize, profile, pipeline, opt_filter,
+        )
+        .with_remarks(remarks.clone());
+        match opt.instrument_coverage {
+            true => (codegen.compile_instrumented()).map(|(o0, map)| (o0, Some(map))),
+            false => codegen.compile().map(|o0| (o0, None)),
+        }
+    });
+    print_remarks(opt, &remarks);
+    let s0 = s0.map_err(|e| source.annotate(e));
+    emit_code(opt, input, &mut passes, &mut stats, s0)
+}
+
+/// `codegen` with the options of `opt`
+fn configure<'p>(
+    codegen: Codegen<'p>,
+    opt: &ParserConfig,
+    target: chigusa::Target,
+    optimize: bool,
+    profile: Option<ExecProfile>,
+    pipeline: PassManager,
+    opt_filter: OptFilter,
+) -> Codegen<'p> {
+    codegen
+        .with_target(target)
+        .with_debug_info(opt.debug_info || opt.emit == EmitOption::CoverageMap)
+        .with_max_stack_depth(opt.max_stack_depth)
+        .with_max_frame_size(opt.max_frame_size)
+        .with_allow_overflow(opt.allow_overflow)
+        .with_ub_checks(opt.sanitize.is_some())
+        .with_standard(standard(opt.std.as_deref()))
+        .with_peephole(optimize)
+        .with_cse(optimize)
+        .with_unroll(
+            if optimize {
+                opt.unroll_factor.unwrap_or(8)
+            } else {
+                0
+            },
+            opt.unroll_budget.unwrap_or(64),
+        )
+        .with_profile(profile.filter(|_| optimize))
+        .with_passes(pipeline)
+        .with_opt_filter(opt_filter)
+}
+
+/// Emit `s0`, with the counters of its blocks if they are counted, or show
+/// the error compiling `input` into it
+fn emit_code(
+    opt: &ParserConfig,
+    input: &str,
+    passes: &mut PassTimes,
+    stats: &mut Stats,
+    s0: Result<(O0, Option<CounterMap>), chigusa::CompileError>,
+) -> Result<(), Exit> {
+    let (s0, counters) = match s0 {
+        Ok(t) => t,
+        Err(e) => {
+            report(opt, passes, stats);
+            return Err(compile_error(opt, input, e));
         }
     };
 
-    if opt.emit == EmitOption::S0 {
-        let mut f = File::create(&opt.output_file).expect("Failed to create output file");
-        write!(f, "{}", s0).expect("Failed to write");
+    stats.count_instructions(&s0);
+
+    let res = passes.time("emit", || {
+        emit(opt, &s0)?;
+        match &counters {
+            Some(map) => {
+                let path = counters_path(opt.output_path());
+                let res = File::create(&path).and_then(|mut f| writeln!(f, "{}", map.to_json()));
+                write_failed(opt, &path, res)
+            }
+            None => Ok(()),
+        }
+    });
+    report(opt, passes, stats);
+    res
+}
+
+/// Print the remarks of passes asked for, one a line, each starting with
+/// where it is as `file:line:col` for editors to jump to
+fn print_remarks(opt: &ParserConfig, remarks: &Remarks) {
+    let file = (opt.input_file.as_deref()).unwrap_or_else(|| Path::new("<stdin>"));
+    for remark in remarks.take() {
+        match remark.span {
+            Some(span) => eprintln!(
+                "{}:{}:{}: {}",
+                file.display(),
+                span.start.ln + 1,
+                span.start.pos + 1,
+                remark
+            ),
+            None => eprintln!("{}: {}", file.display(), remark),
+        }
+    }
+}
+
+/// Show compile error `e` in `input`, unless asked to be quiet
+fn compile_error(opt: &ParserConfig, input: &str, e: chigusa::CompileError) -> Exit {
+    if !opt.quiet {
+        let mut input_lines = input.lines();
+        let stage = match e.stage {
+            chigusa::Stage::Parse => "Parsing",
+            chigusa::Stage::Compile => "Compile",
+        };
+        let err_des = format!("{} error[{}]: {}", stage, e.code, e.message);
+        if let Some(span) = e.span {
+            err_disp::pretty_print_error(&mut input_lines, span, &err_des);
+        } else {
+            tracing::error!("{}", err_des);
+        }
+        err_disp::print_notes(input, &e.notes);
+        err_disp::print_fixes(&e.fixes);
+    }
+    Exit::of(e.code)
+}
+
+/// Parse `file` with the features `-D` defines, reporting why it could not be
+/// parsed as `check` does
+fn load_program(file: &Path, opt: &ParserConfig) -> Result<chigusa::Program, Exit> {
+    let mut errors = 0;
+    let src = source::load(file, &opt.defines)
+        .map_err(|e| check::load_failed(file, e, opt, &mut errors))?;
+    parse_no_panic(src.compiled())
+        .map_err(|e| check::report(file, &[src.annotate(e).into()], opt, &mut errors))
+}
+
+/// Write `s0` out in the format asked for
+fn emit(opt: &ParserConfig, s0: &O0) -> Result<(), Exit> {
+    let output = opt.output_path();
+    let res = if opt.emit == EmitOption::S0 {
+        File::create(output).and_then(|mut f| write!(f, "{}", s0))
+    } else if opt.emit == EmitOption::SizeReport
+        || opt.emit == EmitOption::SizeReportJson
+        || opt.emit == EmitOption::CoverageMap
+    {
+        let report = match opt.emit {
+            EmitOption::SizeReport => SizeReport::new(s0).to_string(),
+            EmitOption::SizeReportJson => SizeReport::new(s0).to_json() + "\n",
+            _ => {
+                let map = CoverageMap::new(s0).expect("Compiled with debug info");
+                map.to_json() + "\n"
+            }
+        };
+        if opt.stdout {
+            print!("{}", report);
+            Ok(())
+        } else {
+            File::create(output).and_then(|mut f| write!(f, "{}", report))
+        }
     } else {
         // Emit O0
-        let mut f = File::create(&opt.output_file).expect("Failed to create output file");
-        s0.write_binary(&mut f).expect("Failed to write");
+        File::create(output).and_then(|mut f| s0.write_binary(&mut f))
+    };
+    write_failed(opt, output, res)?;
+
+    if let Some(debug) = &s0.debug {
+        let mut debug = debug.clone();
+        if let Some(path) = &opt.input_file {
+            let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.clone());
+            debug.source = path.to_string_lossy().into_owned();
+        }
+        let path = debug_path(output);
+        let res = File::create(&path).and_then(|mut f| binfmt::write_debug(&debug, &mut f));
+        write_failed(opt, &path, res)?;
+    }
+    Ok(())
+}
+
+/// Report a failure to write `path`
+fn write_failed(opt: &ParserConfig, path: &Path, res: std::io::Result<()>) -> Result<(), Exit> {
+    res.map_err(|e| {
+        if !opt.quiet {
+            eprintln!("{}: cannot write: {}", path.display(), e);
+        }
+        Exit::IoError
+    })
+}
+
+/// How a server on stdio ended: failures are those of the connection, so are
+/// I/O errors
+fn served(
+    opt: &ParserConfig,
+    what: &str,
+    res: Result<(), Box<dyn std::error::Error + Send + Sync>>,
+) -> Exit {
+    match res {
+        Ok(()) => Exit::Success,
+        Err(e) => {
+            if !opt.quiet {
+                eprintln!("{} failed: {}", what, e);
+            }
+            Exit::IoError
+        }
+    }
+}
+
+/// Whether `name` can be defined as a feature with `-D`
+fn is_feature(name: &str) -> bool {
+    let mut chars = name.chars();
+    (chars.next()).is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// The target called `name`, or the default one. Exits if there is none.
+fn target(name: Option<&str>) -> chigusa::Target {
+    match chigusa::Target::from_name(name.unwrap_or("o0")) {
+        Some(target) => target,
+        None => {
+            let names: Vec<_> = chigusa::Target::ALL.iter().map(|t| t.name).collect();
+            eprintln!("Unknown target. Allowed are: {}", names.join(", "));
+            std::process::exit(1);
+        }
+    }
+}
+
+/// The standard called `name`, or C0. Exits if there is none.
+fn standard(name: Option<&str>) -> chigusa::Standard {
+    match chigusa::Standard::from_name(name.unwrap_or("c0")) {
+        Some(standard) => standard,
+        None => {
+            let names: Vec<_> = chigusa::Standard::ALL.iter().map(|s| s.name).collect();
+            eprintln!("Unknown standard. Allowed are: {}", names.join(", "));
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Where debug info for the binary at `path` is kept
+fn debug_path(path: &Path) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".dbg");
+    path.into()
+}
+
+/// Where the map of counters of the binary at `path` is kept
+fn counters_path(path: &Path) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".counters");
+    path.into()
+}
+
+/// Print the binary at `path` as annotated assembly
+fn disasm(path: &Path, opt: &ParserConfig) -> Result<(), Exit> {
+    let bad = |e: binfmt::BinError| {
+        let e = match e {
+            binfmt::BinError::Io(e) => e,
+            e => std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()),
+        };
+        check::io_error(path, "cannot disassemble", &e, opt)
+    };
+    let f = File::open(path).map_err(|e| check::io_error(path, "cannot read file", &e, opt))?;
+    let mut o0 = O0::read_binary(&mut BufReader::new(f)).map_err(bad)?;
+    let mut source = None;
+    if let Ok(f) = File::open(debug_path(path)) {
+        let debug = binfmt::read_debug(&mut BufReader::new(f)).map_err(bad)?;
+        if !debug.source.is_empty() {
+            source = std::fs::read_to_string(&debug.source).ok();
+        }
+        o0.debug = Some(debug);
+    }
+    print!("{}", disassemble(&o0, source.as_deref()));
+    Ok(())
+}
+
+/// Print statistics asked for on the command line
+fn report(opt: &ParserConfig, passes: &PassTimes, stats: &mut Stats) {
+    passes.report();
+    if opt.stats {
+        stats.peak_heap = Some(passes.peak());
+        stats.report();
+    }
+}
+
+/// What `--emit typed-ast` writes: the typed tree, then which variables
+/// each reference in it may refer to
+struct TypedAst {
+    program: chigusa::TypedProgram,
+    aliases: chigusa::Aliases,
+}
+
+impl std::fmt::Debug for TypedAst {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", self.program)?;
+        self.aliases.fmt(f)
     }
 }
 
-fn write_output<T>(opt: &ParserConfig, val: T)
+fn write_output<T>(opt: &ParserConfig, val: T) -> Result<(), Exit>
 where
     T: std::fmt::Debug,
 {
     if opt.stdout {
         print!("{:?}", val);
+        Ok(())
     } else {
-        let mut f = File::create(&opt.output_file).expect("Failed to create output file");
-        write!(f, "{:#?}", val).expect("Failed to write file");
+        let res = File::create(opt.output_path()).and_then(|mut f| write!(f, "{:#?}", val));
+        write_failed(opt, opt.output_path(), res)
     }
 }
@@ -1,13 +1,33 @@
+use super::cse::{self, Reuse};
 use super::err::*;
+use super::gvn;
 use super::instgen::*;
+use super::schedule::Scheduler;
+use super::unroll::{self, Unrolled};
 use super::*;
+use crate::c0::alias::Aliases;
 use crate::c0::ast::{self, *};
+use crate::c0::escape::{self, VarKey};
+use crate::c0::num;
+use crate::c0::purity::Purity;
+use crate::c0::type_checker;
+use crate::consteval::{self, Value};
 use crate::prelude::*;
 use either::Either;
-use indexmap::{map::Entry, IndexMap, IndexSet};
-use std::convert::TryInto;
+use indexmap::IndexMap;
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::iter::Iterator;
-const bytes_per_slot: u16 = 4;
+
+thread_local! {
+    static CURRENT_SPAN: Cell<Option<Span>> = const { Cell::new(None) };
+}
+
+/// Span of the innermost statement or expression being compiled on this
+/// thread, if any. After a panic in code generation, this is where it was.
+pub fn current_span() -> Option<Span> {
+    CURRENT_SPAN.with(Cell::get)
+}
 
 #[derive(Debug, Clone)]
 struct Data {
@@ -19,7 +39,9 @@ struct Data {
     is_const: bool,
 }
 
-/// A sink of global data
+/// A sink of global data. Constants are numbered in the order they are
+/// first put: names of functions in declaration order, then literals in the
+/// order code using them is generated.
 #[derive(Debug, Clone)]
 struct DataSink {
     map: IndexMap<String, Data>,
@@ -34,7 +56,7 @@ impl DataSink {
     }
 
     pub fn put_data(&mut self, name: &str, val: Data) -> Option<u16> {
-        if self.map.len() < u16::max_value() as usize {
+        if self.map.len() < u16::MAX as usize {
             if self.map.contains_key(name) {
                 None
             } else {
@@ -46,8 +68,8 @@ impl DataSink {
         }
     }
 
-    fn put_str(&mut self, name: &str, val: String, is_const: bool) -> Option<u16> {
-        let str_val: Vec<_> = val.as_bytes().iter().map(|x| *x).collect();
+    fn put_str(&mut self, name: &str, val: String, _is_const: bool) -> Option<u16> {
+        let str_val: Vec<_> = val.as_bytes().to_vec();
         // let str_val = std::ffi::CString::new(str_val).unwrap();
         // let str_val = str_val.into_bytes_with_nul();
 
@@ -75,13 +97,17 @@ impl DataSink {
         self.map.get(name)
     }
 
-    pub fn unwrap(mut self) -> Vec<Data> {
-        self.map.into_iter().map(|(s, d)| d).collect()
+    pub fn unwrap(self) -> Vec<Data> {
+        self.map.into_iter().map(|(_s, d)| d).collect()
     }
 }
 
 type BB = Ptr<BasicBlock>;
 
+/// A block ending a condition, and where it goes when its test holds and when
+/// it does not, unless to where the whole condition goes
+type CondExit = (BB, Option<usize>, Option<usize>);
+
 #[derive(Debug, Clone)]
 pub(super) struct BasicBlock {
     pub id: usize,
@@ -124,25 +150,66 @@ pub(super) struct FunctionType {
     pub param_siz: u32,
     pub is_extern: bool,
     pub name_idx: u16,
+    pub span: Option<Span>,
+    /// Parameters and local variables, for debug info
+    pub vars: Vec<VarInfo>,
 }
 
-impl Into<FnInfo> for FunctionType {
-    fn into(self) -> FnInfo {
+impl From<FunctionType> for FnInfo {
+    fn from(val: FunctionType) -> Self {
         FnInfo {
-            name_idx: self.name_idx,
-            ins: self.body.unwrap().unwrap(),
+            name_idx: val.name_idx,
+            ins: val.body.unwrap().unwrap(),
             lvl: 1,
             // TODO
-            param_siz: self.param_siz as u16,
+            param_siz: val.param_siz as u16,
         }
     }
 }
 
+// * Everything that decides the order of anything in the binary is an
+// * `IndexMap` or a `Vec`, so compiling the same source twice gives the same
+// * bytes. Don't iterate over a `HashMap` here.
 #[derive(Debug, Clone)]
 struct GlobalData {
     pub vars: LocalVars,
     pub consts: DataSink,
+    /// Functions in declaration order, which is their order in the binary
     pub fns: IndexMap<String, FunctionType>,
+    /// Functions of the host, which join `fns` once compiling finds them
+    /// called
+    pub host_fns: IndexMap<String, FunctionType>,
+    /// Functions called, which calls name until relocation
+    pub relocator: Relocator,
+    /// Types of `auto` variables, from the type checker
+    pub inferred: BTreeMap<(usize, String), Type>,
+    /// Which functions are pure, to compute the same call only once. Empty
+    /// unless common subexpressions are eliminated.
+    pub purity: Purity,
+    /// Where references may go, to tell which writes change what a call
+    /// reads. `None` unless common subexpressions are eliminated.
+    pub aliases: Option<Aliases>,
+    /// Local variables whose address escapes their call, which live on the
+    /// heap with their slot holding the address
+    pub boxed: BTreeSet<VarKey>,
+    /// Global constants known when compiling, with their type and value.
+    /// The type checker lets no address of a constant be taken, so they
+    /// get no slot, and each use pushes the value instead.
+    pub folded: BTreeMap<String, (Type, Value)>,
+    /// Values of global initializers calling functions, evaluated before
+    /// compiling a stream, whose declarations have no bodies to call
+    pub initial: BTreeMap<String, Value>,
+    /// Global variables, for debug info
+    pub globals: Vec<VarInfo>,
+    /// Counts of an earlier run, to inline hot calls and lay out hot
+    /// blocks first
+    pub profile: Option<ExecProfile>,
+    /// Blocks counted so far, when instrumenting code to count them
+    pub counters: Option<CounterMap>,
+    /// Slot of start code holding the first counter
+    pub counter_base: usize,
+    /// Remarks of the passes asked for
+    pub remarks: Remarks,
 }
 
 impl GlobalData {
@@ -151,58 +218,148 @@ impl GlobalData {
             vars: LocalVars::new(),
             consts: DataSink::new(),
             fns: IndexMap::new(),
+            host_fns: IndexMap::new(),
+            relocator: Relocator::new(),
+            inferred: BTreeMap::new(),
+            purity: Purity::default(),
+            aliases: None,
+            boxed: BTreeSet::new(),
+            folded: BTreeMap::new(),
+            initial: BTreeMap::new(),
+            globals: vec![],
+            profile: None,
+            counters: None,
+            counter_base: 0,
+            remarks: Remarks::default(),
         }
     }
 }
 
 pub type Type = Ptr<ast::TypeDef>;
 
-/// An opaque sink of instructions
-#[derive(Debug, Clone)]
-pub(super) struct InstSink(pub Vec<Inst>);
+/// An opaque sink of instructions, remembering the source line of each
+#[derive(Debug, Clone, Default)]
+pub struct InstSink {
+    inst: Vec<Inst>,
+    lines: Vec<Option<u32>>,
+    /// Basic blocks laid out here, and the instruction each starts at
+    blocks: Vec<(usize, usize)>,
+    /// Line given to instructions pushed from now on
+    pub line: Option<u32>,
+}
 
 impl InstSink {
     pub fn new() -> InstSink {
-        InstSink(Vec::new())
+        InstSink {
+            inst: Vec::new(),
+            lines: Vec::new(),
+            blocks: Vec::new(),
+            line: None,
+        }
     }
 
     pub fn inner(&self) -> &Vec<Inst> {
-        &self.0
+        &self.inst
+    }
+
+    pub fn inner_mut(&mut self) -> &mut Vec<Inst> {
+        &mut self.inst
+    }
+
+    pub fn lines(&self) -> &Vec<Option<u32>> {
+        &self.lines
+    }
+
+    pub fn blocks(&self) -> &Vec<(usize, usize)> {
+        &self.blocks
+    }
+
+    /// Note that basic block `id` starts at the next instruction
+    pub fn mark_block(&mut self, id: usize) {
+        self.blocks.push((id, self.inst.len()));
+    }
+
+    /// Instructions and their lines, which must be kept the same length
+    pub fn parts_mut(&mut self) -> (&mut Vec<Inst>, &mut Vec<Option<u32>>) {
+        (&mut self.inst, &mut self.lines)
+    }
+
+    pub fn blocks_mut(&mut self) -> &mut Vec<(usize, usize)> {
+        &mut self.blocks
     }
 
     pub fn unwrap(self) -> Vec<Inst> {
-        self.0
+        self.inst
     }
 
-    /// Append all instruction from the other InstSink
+    /// Append all instruction from the other InstSink. Instructions without
+    /// a line get the current line of `self`.
     pub fn append_all(&mut self, other: &mut InstSink) {
-        self.0.append(&mut other.0);
+        self.inst.append(&mut other.inst);
+        let line = self.line;
+        self.lines.extend(other.lines.drain(..).map(|l| l.or(line)));
+    }
+
+    /// Insert all instruction from the other InstSink before `idx`
+    pub fn insert_all(&mut self, idx: usize, other: &mut InstSink) {
+        self.inst.splice(idx..idx, other.inst.drain(..));
+        let line = self.line;
+        self.lines
+            .splice(idx..idx, other.lines.drain(..).map(|l| l.or(line)));
     }
 
     pub fn push(&mut self, inst: Inst) {
-        self.0.push(inst)
+        self.inst.push(inst);
+        self.lines.push(self.line);
     }
 
     pub fn push_many(&mut self, inst: &[Inst]) {
         for i in inst {
-            self.0.push(*i)
+            self.push(*i)
+        }
+    }
+
+    /// Insert `ins` before instruction `at` of laid out code, where jumps
+    /// hold instruction indices. Jumps to `at` and blocks starting there
+    /// now go to the first instruction inserted, and later ones move along.
+    pub fn insert_before(&mut self, at: usize, ins: &[Inst]) {
+        let len = ins.len();
+        for inst in self.inst.iter_mut() {
+            if let Some(to) = jump_target(inst).filter(|to| *to as usize > at) {
+                set_jump_target(inst, to + len as u16);
+            }
         }
+        for (_, start) in self.blocks.iter_mut().filter(|(_, start)| *start > at) {
+            *start += len;
+        }
+        let line = self.lines.get(at).copied().flatten();
+        self.inst.splice(at..at, ins.iter().copied());
+        self.lines.splice(at..at, std::iter::repeat_n(line, len));
     }
 
     pub fn prepend(&mut self, inst: Inst) {
-        self.0.insert(0, inst);
+        self.inst.insert(0, inst);
+        self.lines.insert(0, self.lines.first().copied().flatten());
     }
 
     pub fn pop(&mut self) -> Option<Inst> {
-        self.0.pop()
+        self.lines.pop();
+        self.inst.pop()
     }
 
     pub fn len(&self) -> usize {
-        self.0.len()
+        self.inst.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inst.is_empty()
     }
 
     pub fn reset(&mut self) {
-        self.0.clear()
+        self.inst.clear();
+        self.lines.clear();
+        self.blocks.clear();
+        self.line = None;
     }
 
     pub fn reset_self(mut self) -> Self {
@@ -215,6 +372,24 @@ impl InstSink {
 pub struct Codegen<'a> {
     prog: &'a ast::Program,
     glob: GlobalData,
+    target: Target,
+    debug_info: bool,
+    peephole: bool,
+    cse: bool,
+    /// Copies of a loop body at most, and statements and expressions they
+    /// may add up to, when unrolling loops
+    unroll: (u32, usize),
+    /// Passes to run
+    passes: PassManager,
+    /// Which functions get which of the passes turned on
+    opt: OptFilter,
+    /// Functions in the order they are compiled, start code first
+    order: Vec<String>,
+    max_stack_depth: Option<usize>,
+    max_frame_size: Option<usize>,
+    allow_overflow: bool,
+    ub_checks: bool,
+    standard: Standard,
 }
 
 impl<'a> Codegen<'a> {
@@ -222,40 +397,246 @@ impl<'a> Codegen<'a> {
         Codegen {
             prog,
             glob: GlobalData::new(),
+            target: Target::default(),
+            debug_info: false,
+            peephole: true,
+            cse: true,
+            unroll: (8, 64),
+            passes: PassManager::default(),
+            opt: OptFilter::default(),
+            order: vec![],
+            max_stack_depth: None,
+            max_frame_size: None,
+            allow_overflow: false,
+            ub_checks: false,
+            standard: Standard::default(),
         }
     }
 
-    pub fn compile(mut self) -> CompileResult<O0> {
-        let decls = &self.prog.blk.scope;
-        let decls = &*decls.borrow();
+    /// Run the peephole optimizer over each function, and lay out blocks so
+    /// that where a condition holds falls through, skipping blocks that only
+    /// jump elsewhere. On by default.
+    pub fn with_peephole(mut self, peephole: bool) -> Codegen<'a> {
+        self.peephole = peephole;
+        self
+    }
 
-        for item in decls.defs.iter() {
-            let name = item.0;
-            let def = item.1.borrow();
-            if let ast::SymbolDef::Var { typ, .. } = &*def {
-                let typ = typ.borrow();
-                if let ast::TypeDef::Function(f) = &*typ {
-                    self.add_fn(f, name)?;
-                } else {
-                    // ...
-                }
-            }
+    /// Compute both sides of `a op b` only once if they are the same and
+    /// pure, as in `f(x) * f(x)` with `f` pure, and a pure call made again
+    /// in a later statement only once if nothing it reads may be written in
+    /// between. On by default.
+    pub fn with_cse(mut self, cse: bool) -> Codegen<'a> {
+        self.cse = cse;
+        self
+    }
+
+    /// Unroll loops running a number of times known when compiling into at
+    /// most `factor` copies of their body, together at most `budget`
+    /// statements and expressions of the source. A factor below 2 turns
+    /// unrolling off. On by default, with a factor of 8 and a budget of 64.
+    pub fn with_unroll(mut self, factor: u32, budget: usize) -> Codegen<'a> {
+        self.unroll = (factor, budget);
+        self
+    }
+
+    /// Use the counts of a run of the same program, compiled with the same
+    /// options, to inline small functions called often and to lay out the
+    /// blocks run most so that they fall through to each other, with blocks
+    /// that never ran moved to the end of their function
+    pub fn with_profile(mut self, profile: Option<ExecProfile>) -> Codegen<'a> {
+        self.glob.profile = profile;
+        self
+    }
+
+    /// Run only the passes in the pipeline of `passes`, and only those
+    /// turned on of them. Every pass by default.
+    pub fn with_passes(mut self, passes: PassManager) -> Codegen<'a> {
+        self.passes = passes;
+        self
+    }
+
+    /// Only optimize the functions `opt` picks, with the passes it picks of
+    /// those turned on, to find which one miscompiles a program
+    pub fn with_opt_filter(mut self, opt: OptFilter) -> Codegen<'a> {
+        self.opt = opt;
+        self
+    }
+
+    /// Note what the passes `remarks` asks for did to each function, and why
+    /// they left code as it was, in `remarks`
+    pub fn with_remarks(mut self, remarks: Remarks) -> Codegen<'a> {
+        self.glob.remarks = remarks;
+        self
+    }
+
+    /// Record the source line of every instruction in [`O0::debug`]
+    pub fn with_debug_info(mut self, debug_info: bool) -> Codegen<'a> {
+        self.debug_info = debug_info;
+        self
+    }
+
+    /// Fail if any function needs more operand stack slots than `max`
+    pub fn with_max_stack_depth(mut self, max: Option<usize>) -> Codegen<'a> {
+        self.max_stack_depth = max;
+        self
+    }
+
+    /// Fail if the frame of any function, its parameters, local variables
+    /// and operand stack, takes more than `max` slots
+    pub fn with_max_frame_size(mut self, max: Option<usize>) -> Codegen<'a> {
+        self.max_frame_size = max;
+        self
+    }
+
+    /// Let integer literals out of the range of the type they are used as
+    /// wrap around, as in C, instead of failing
+    pub fn with_allow_overflow(mut self, allow_overflow: bool) -> Codegen<'a> {
+        self.allow_overflow = allow_overflow;
+        self
+    }
+
+    /// Check for undefined behavior when the program runs: arithmetic on
+    /// `int`s overflowing or dividing by zero, references that are null
+    /// dereferenced, and local variables read before they are assigned,
+    /// which the VM stops the program at with the line and column. Local
+    /// variables get a shadow slot telling whether they were assigned.
+    pub fn with_ub_checks(mut self, ub_checks: bool) -> Codegen<'a> {
+        self.ub_checks = ub_checks;
+        self
+    }
+
+    /// Accept the language of `standard` instead of plain C0
+    pub fn with_standard(mut self, standard: Standard) -> Codegen<'a> {
+        self.standard = standard;
+        self
+    }
+
+    /// Generate code for `target` instead of the standard O0 VM
+    pub fn with_target(mut self, target: Target) -> Codegen<'a> {
+        self.target = target;
+        self
+    }
+
+    /// Initialize the globals of `initial` with their values there, for
+    /// initializers calling functions whose bodies `self` does not have
+    pub(crate) fn with_initial_values(mut self, initial: BTreeMap<String, Value>) -> Codegen<'a> {
+        self.glob.initial = initial;
+        self
+    }
+
+    /// Fail if `inst` uses anything the target does not have
+    fn check_target(&self, inst: &InstSink, span: Option<Span>) -> CompileResult<()> {
+        match inst.inner().iter().find(|i| !self.target.supports(i)) {
+            Some(i) => Err(compile_err(
+                CompileErrorVar::NotOnTarget(*i, self.target.name),
+                span,
+            )),
+            None => Ok(()),
         }
+    }
 
+    /// Report the first error in the program, without optimizing, checking
+    /// the generated code or building an [`O0`]. Stack depth and frame size
+    /// are not checked.
+    pub fn check(mut self) -> CompileResult<()> {
+        self.peephole = false;
+        self.cse = false;
+        self.unroll = (0, 0);
+        self.gen_all().map(|_| ())
+    }
+
+    pub fn compile(self) -> CompileResult<O0> {
+        self.build().map(|(o0, _)| o0)
+    }
+
+    /// Compile with code added to count how many times each block runs,
+    /// which `main` prints before it returns. Also returns where the code of
+    /// each counter came from.
+    pub fn compile_instrumented(mut self) -> CompileResult<(O0, CounterMap)> {
+        self.glob.counters = Some(CounterMap::default());
+        let (o0, map) = self.build()?;
+        Ok((o0, map.unwrap_or_default()))
+    }
+
+    /// Start compiling a program one function at a time, for programs too
+    /// large to keep the syntax tree of whole. `self` is made from the
+    /// declarations of the program alone, parsed with
+    /// [`with_bodies_skipped`](crate::c0::parser::Parser::with_bodies_skipped),
+    /// and global variables are compiled right away. Nothing is known about
+    /// other functions when compiling one, so no call is computed only
+    /// once. Globals initialized by calls are initialized when the program
+    /// starts, unless given values with `with_initial_values`.
+    pub fn stream(mut self) -> CompileResult<StreamedCodegen<'a>> {
+        let typed = type_checker::lower(self.prog)?;
+        self.target.check_types(&typed)?;
+        self.order = passes::compile_order(self.prog);
+        self.glob.boxed = escape::escaping(&Aliases::new(&typed));
+        self.glob.folded = fold_consts(self.prog, &self.glob.initial);
+        self.glob.inferred = typed.inferred.clone();
+        self.add_fns()?;
+        self.remark_no_profile();
         let start_code = self.make_start()?;
+        Ok(StreamedCodegen {
+            codegen: self,
+            start_code,
+            globals: typed.inferred,
+        })
+    }
 
-        for item in decls.defs.iter() {
-            let name = item.0;
-            let def = item.1.borrow();
-            if let ast::SymbolDef::Var { typ, .. } = &*def {
-                let typ = typ.borrow();
-                if let ast::TypeDef::Function(f) = &*typ {
-                    self.compile_fn(f, name)?;
-                }
+    fn build(mut self) -> CompileResult<(O0, Option<CounterMap>)> {
+        let start_code = self.gen_all()?;
+        self.assemble(start_code)
+    }
+
+    /// Put start code `start_code` and the functions compiled together
+    fn assemble(mut self, mut start_code: InstSink) -> CompileResult<(O0, Option<CounterMap>)> {
+        let counters = self.glob.counters.take();
+        if let Some(map) = &counters {
+            let (base, count) = (self.glob.counter_base, map.counters.len());
+            instrument::add_counters(&mut start_code, count);
+            if let Some(main) = self.glob.fns.get_mut("main").and_then(|f| f.body.as_mut()) {
+                instrument::dump_counts(main, base, count);
             }
         }
 
-        Ok(O0 {
+        let debug = if self.debug_info {
+            Some(DebugInfo {
+                source: String::new(),
+                start_lines: start_code.lines().clone(),
+                fn_lines: (self.glob.fns.values())
+                    .map(|f| f.body.as_ref().map_or(vec![], |b| b.lines().clone()))
+                    .collect(),
+                globals: self.glob.globals.clone(),
+                fn_vars: (self.glob.fns.values()).map(|f| f.vars.clone()).collect(),
+                fn_blocks: (self.glob.fns.values())
+                    .map(|f| {
+                        let blocks = f.body.as_ref().map_or(&[][..], |b| &b.blocks()[..]);
+                        (blocks.iter())
+                            .map(|(id, at)| (*id as u32, *at as u32))
+                            .collect()
+                    })
+                    .collect(),
+            })
+        } else {
+            None
+        };
+
+        let fn_spans: Vec<_> = (self.glob.fns.iter())
+            .map(|(name, f)| (name.clone(), f.span))
+            .collect();
+        let host_fns = (self.glob.fns.iter().enumerate())
+            .filter(|(_, (_, f))| f.is_extern)
+            .map(|(idx, (name, f))| {
+                let sig = HostSig {
+                    name: name.clone(),
+                    params: f.params.iter().map(|p| host_kind(&p.borrow())).collect(),
+                    ret: host_kind(&f.return_type.borrow()),
+                };
+                (idx as u16, sig)
+            })
+            .collect();
+        let relocator = std::mem::take(&mut self.glob.relocator);
+        let mut o0 = O0 {
             version: 1,
             constants: self
                 .glob
@@ -271,76 +652,343 @@ impl<'a> Codegen<'a> {
                 ins: start_code.unwrap(),
             },
             functions: self.glob.fns.into_iter().map(|f| f.1.into()).collect(),
-        })
+            endian: self.target.endian,
+            debug,
+            relocs: vec![],
+            host_fns,
+        };
+        relocator.relocate(&mut o0, |name| {
+            (fn_spans.iter())
+                .position(|(f, _)| f == name)
+                .map(|idx| idx as u16)
+        })?;
+        let depth = stack_depth(&o0).map_err(|e| {
+            CompileErrorVar::InternalError(format!("Generated code is invalid: {}", e))
+        })?;
+        let prog_span = self.prog.blk.span;
+        let fn_at = |func: Option<usize>| match func {
+            Some(idx) => fn_spans[idx].clone(),
+            None => ("global variables".into(), prog_span),
+        };
+        if let Some(max) = self.max_stack_depth {
+            let (func, depth) = depth.max();
+            if depth > max {
+                let (name, span) = fn_at(func);
+                return Err(compile_err(
+                    CompileErrorVar::StackTooDeep(name, depth, max),
+                    span,
+                ));
+            }
+        }
+        if let Some(max) = self.max_frame_size {
+            let (start, functions) = depth.frame_slots(&o0);
+            let too_large = (functions.into_iter().enumerate())
+                .map(|(idx, slots)| (Some(idx), slots))
+                .chain(std::iter::once((None, start)))
+                .find(|(_, slots)| *slots > max);
+            if let Some((func, slots)) = too_large {
+                let (name, span) = fn_at(func);
+                return Err(compile_err(
+                    CompileErrorVar::FrameTooLarge(name, slots, max),
+                    span,
+                ));
+            }
+        }
+        Ok((o0, counters))
+    }
+
+    /// Generate code for start code and every function, returning start code
+    fn gen_all(&mut self) -> CompileResult<InstSink> {
+        let typed = type_checker::lower(self.prog)?;
+        self.target.check_types(&typed)?;
+        self.order = passes::compile_order(self.prog);
+        let aliases = Aliases::new(&typed);
+        self.glob.boxed = escape::escaping(&aliases);
+        self.glob.folded = fold_consts(self.prog, &self.glob.initial);
+        self.glob.inferred = typed.inferred;
+        if self.cse && self.passes.runs(Pass::Purity) {
+            self.glob.purity = Purity::new(self.prog);
+        }
+        if self.cse && self.passes.runs(Pass::Alias) {
+            self.glob.aliases = Some(aliases);
+        }
+        self.add_fns()?;
+        self.remark_no_profile();
+        let start_code = self.make_start()?;
+
+        let decls = &self.prog.blk.scope;
+        let decls = &*decls.borrow();
+        for item in decls.defs.iter() {
+            let name = item.0;
+            let def = item.1.borrow();
+            if let ast::SymbolDef::Var { typ, .. } = &*def {
+                let typ = typ.borrow();
+                if let ast::TypeDef::Function(f) = &*typ {
+                    self.compile_fn(f, name)?;
+                }
+            }
+        }
+        self.add_host_fns();
+        Ok(start_code)
+    }
+
+    /// Add the signature of every function to `self.glob`, with those of
+    /// the checks for undefined behavior if asked for
+    fn add_fns(&mut self) -> CompileResult<()> {
+        let decls = &self.prog.blk.scope;
+        let decls = &*decls.borrow();
+
+        for item in decls.defs.iter() {
+            let name = item.0;
+            let def = item.1.borrow();
+            if let ast::SymbolDef::Var { typ, .. } = &*def {
+                let typ = typ.borrow();
+                if let ast::TypeDef::Function(f) = &*typ {
+                    self.add_fn(f, name)?;
+                } else {
+                    // ...
+                }
+            }
+        }
+
+        if self.ub_checks {
+            self.add_checks()?;
+        }
+        Ok(())
+    }
+
+    /// Add the stubs of functions of the host called, once every other
+    /// function is compiled
+    fn add_host_fns(&mut self) {
+        // * Stubs of functions of the host go last, and only for those called,
+        // * so they change nothing about programs that don't call them
+        for (name, mut func) in std::mem::take(&mut self.glob.host_fns) {
+            if self.glob.relocator.is_called(&name) {
+                func.name_idx = self.name_const(&name);
+                self.glob.fns.insert(name, func);
+            }
+        }
     }
 
     fn make_start(&mut self) -> CompileResult<InstSink> {
         let prog = &self.prog.blk;
-        let name = "_start";
+        let name = passes::START;
         let ret = Ptr::new(ast::TypeDef::Unit);
         let params = Vec::new();
         let mut fnc = FnCodegen::new(prog, name, self, ret, params);
 
         fnc.gen()?;
+        let vars = std::mem::take(&mut fnc.vars);
+        let passes = std::mem::take(&mut fnc.passes);
         let (mut start_code, loc) = fnc.finish_with_loc()?;
+        self.glob.globals = vars;
+        let done = locals::optimize(&mut start_code, &passes, true, |inst| {
+            self.stack_effect(inst)
+        });
+        self.remark_fn(name, prog.span, done);
+        peephole::optimize(&mut start_code, &passes);
+        self.glob.counter_base = instrument::counter_base(&start_code);
+        self.run_custom(name, &mut start_code, prog.span)?;
+        self.check_target(&start_code, prog.span)?;
         self.glob.vars = loc;
         start_code.pop();
         Ok(start_code)
     }
 
-    /// Add the signature of a function to `self.glob`, but does not compile it.
-    fn add_fn(&mut self, func: &ast::FunctionType, name: &str) -> CompileResult<()> {
-        if !func.is_extern {
-            let fn_name = format!("`function_name`{}", name);
-            // ** The `fn_name` variable is only for identifying the string name!
-            let name_idx = self
-                .glob
-                .consts
-                .put_str(&fn_name, name.into(), true)
-                .unwrap();
-
-            let ret = Ptr::new(resolve_ty(
-                &*func.return_type.borrow(),
-                self.prog.blk.scope.cp(),
-            ));
+    /// Note that nothing is inlined, if inlining is asked for without a
+    /// profile to tell which calls are made often
+    fn remark_no_profile(&self) {
+        if self.glob.profile.is_none() && self.passes.runs(Pass::Inline) {
+            self.glob.remarks.add(Remark {
+                pass: Pass::Inline,
+                kind: RemarkKind::Missed,
+                func: passes::START.into(),
+                span: None,
+                message: "nothing inlined: calls are inlined by a profile of an earlier run".into(),
+            });
+        }
+    }
 
-            let params: Vec<_> = func
-                .params
-                .iter()
-                .map(|i| Ptr::new(resolve_ty(&*i.borrow(), self.prog.blk.scope.cp())))
-                .collect();
+    /// Note what passes over the instructions of function `name`, with its
+    /// body at `span`, did to it
+    fn remark_fn(&self, name: &str, span: Option<Span>, done: Vec<(Pass, String)>) {
+        for (pass, message) in done {
+            self.glob.remarks.add(Remark {
+                pass,
+                kind: RemarkKind::Applied,
+                func: name.into(),
+                span,
+                message: format!("in `{}`: {}", name, message),
+            });
+        }
+    }
 
-            let param_siz =
-                params
-                    .iter()
-                    .try_fold::<u32, _, CompileResult<u32>>(0, |sum, item| {
-                        let item_size = item
-                            .borrow()
-                            .occupy_slots()
-                            .ok_or(compile_err_n(CompileErrorVar::RequireSized("".into())))?;
-                        Ok(item_size + sum)
-                    })?;
+    /// Slots `inst` pops and pushes, if it is a call or loads a constant
+    fn stack_effect(&self, inst: &Inst) -> Option<(usize, usize)> {
+        match *inst {
+            Inst::LoadC(idx) => match &self.glob.consts.map.get_index(idx as usize)?.1.init_val {
+                Either::Left(Constant::Float(_)) => Some((0, 2)),
+                _ => Some((0, 1)),
+            },
+            Inst::_Call(symbol) => {
+                let name = self.glob.relocator.name(symbol)?;
+                let f = (self.glob.fns.get(name)).or_else(|| self.glob.host_fns.get(name))?;
+                let ret = self.target.slots_of(&f.return_type.borrow())?;
+                Some((f.param_siz as usize, ret as usize))
+            }
+            _ => None,
+        }
+    }
 
+    /// Declare the checks of [`sanitizer_sigs`](vm::sanitizer_sigs) as
+    /// functions of the host, for code checking for undefined behavior to
+    /// call
+    fn add_checks(&mut self) -> CompileResult<()> {
+        for sig in vm::sanitizer_sigs() {
+            let ret = match sig.ret {
+                value::Kind::Void => Ptr::new(ast::TypeDef::Unit),
+                _ => FnCodegen::int_type(4),
+            };
+            let mut stub = InstSink::new();
+            if sig.ret != value::Kind::Void {
+                stub.push(Inst::IPush(0));
+            }
+            instgen::ret(ret.cp(), &self.target, &mut stub)?;
             let func = FunctionType {
-                name_idx,
-                param_siz,
-                params,
+                name_idx: 0,
+                param_siz: sig.params.len() as u32,
+                params: sig.params.iter().map(|_| FnCodegen::int_type(4)).collect(),
                 return_type: ret,
-                body: None,
-                is_extern: false,
+                body: Some(stub),
+                is_extern: true,
+                span: None,
+                vars: vec![],
             };
+            self.glob.host_fns.insert(sig.name, func);
+        }
+        Ok(())
+    }
+
+    /// Add the signature of a function to `self.glob`, but does not compile it.
+    fn add_fn(&mut self, func: &ast::FunctionType, name: &str) -> CompileResult<()> {
+        let name_idx = match func.is_extern {
+            true => 0,
+            false => self.name_const(name),
+        };
 
-            // ** We insert the original name to global function registry
-            self.glob.fns.insert(name.into(), func);
+        let ret = Ptr::new(resolve_ty(
+            &func.return_type.borrow(),
+            self.prog.blk.scope.cp(),
+        ));
 
-            Ok(())
-        } else {
-            Err(CompileErrorVar::NoExternFunction(name.into()).into())
+        let params: Vec<_> = func
+            .params
+            .iter()
+            .map(|i| Ptr::new(resolve_ty(&i.borrow(), self.prog.blk.scope.cp())))
+            .collect();
+
+        let param_siz = params
+            .iter()
+            .try_fold::<u32, _, CompileResult<u32>>(0, |sum, item| {
+                let item_size = self
+                    .target
+                    .slots_of(&item.borrow())
+                    .ok_or_else(|| compile_err_n(CompileErrorVar::RequireSized("".into())))?;
+                Ok(item_size + sum)
+            })?;
+
+        // * Functions of the host get a stub returning zero, which the VM
+        // * runs the host's function for instead
+        let body = match func.is_extern {
+            true => {
+                let mut stub = InstSink::new();
+                match self.target.slots_of(&ret.borrow()) {
+                    Some(0) => (),
+                    Some(1) => stub.push(Inst::IPush(0)),
+                    _ => stub.push_many(&[Inst::IPush(0), Inst::I2D]),
+                }
+                instgen::ret(ret.cp(), &self.target, &mut stub)?;
+                Some(stub)
+            }
+            false => None,
+        };
+
+        let func = FunctionType {
+            name_idx,
+            param_siz,
+            params,
+            return_type: ret,
+            body,
+            is_extern: func.is_extern,
+            span: None,
+            vars: vec![],
+        };
+
+        // ** We insert the original name to global function registry
+        match func.is_extern {
+            true => self.glob.host_fns.insert(name.into(), func),
+            false => self.glob.fns.insert(name.into(), func),
+        };
+
+        Ok(())
+    }
+
+    /// Passes to run on function `name`: those of the pipeline turned on
+    /// that the filter picks for it
+    fn passes_for(&self, name: &str) -> Vec<Pass> {
+        let idx = self.order.iter().position(|f| f == name);
+        let mut passes = self.opt.passes_for(idx.unwrap_or(usize::MAX), name);
+        passes.retain(|pass| {
+            self.passes.runs(*pass)
+                && match pass {
+                    Pass::Alias | Pass::Purity | Pass::Cse | Pass::Gvn => self.cse,
+                    Pass::Unroll => self.unroll.0 >= 2,
+                    Pass::Inline => self.glob.profile.is_some(),
+                    // * Debuggers read locals, so their stores stay
+                    Pass::Dse => self.peephole && !self.debug_info,
+                    Pass::Layout
+                    | Pass::Sccp
+                    | Pass::CopyProp
+                    | Pass::Fold
+                    | Pass::Dce
+                    | Pass::Peephole => self.peephole,
+                }
+        });
+        passes
+    }
+
+    /// Run the passes of other crates over the code of function `name`
+    fn run_custom(&self, name: &str, code: &mut InstSink, span: Option<Span>) -> CompileResult<()> {
+        for pass in self.passes.custom_pipeline() {
+            let e = match pass.run_on_function(name, code) {
+                Ok(()) => continue,
+                Err(e) => passes::failed(pass, e),
+            };
+            return Err(match span {
+                Some(span) => e.with_span(span),
+                None => e,
+            });
         }
+        Ok(())
+    }
+
+    /// Index of the constant holding the name of function `name`
+    fn name_const(&mut self, name: &str) -> u16 {
+        let fn_name = format!("`function_name`{}", name);
+        // ** The `fn_name` variable is only for identifying the string name!
+        self.glob
+            .consts
+            .put_str(&fn_name, name.into(), true)
+            .unwrap()
     }
 
     /// Compile and repair the function declaration in `self.glob`
     fn compile_fn(&mut self, func: &ast::FunctionType, name: &str) -> CompileResult<()> {
+        let _span = tracing::debug_span!("compile_fn", name).entered();
+        if func.is_extern {
+            return Ok(());
+        }
+
         // Get the function. Things can't go wrong here right?
         let fn_ref = self.glob.fns.get(name).unwrap();
 
@@ -352,12 +1000,24 @@ impl<'a> Codegen<'a> {
             let mut fnc = FnCodegen::new(b, name, self, ret, params);
 
             fnc.gen()?;
-            let inst = fnc.finish()?;
+            let mut inst = fnc.finish()?;
+            let vars = std::mem::take(&mut fnc.vars);
+            let passes = std::mem::take(&mut fnc.passes);
+            let done = locals::optimize(&mut inst, &passes, false, |inst| self.stack_effect(inst));
+            self.remark_fn(name, b.span, done);
+            peephole::optimize(&mut inst, &passes);
+            if let Some(map) = &mut self.glob.counters {
+                instrument::count_blocks(&mut inst, name, self.glob.counter_base, map);
+            }
+            self.run_custom(name, &mut inst, b.span)?;
+            self.check_target(&inst, b.span)?;
 
             // * We're done here. Add the instructions
             let fn_ref = self.glob.fns.get_mut(name).unwrap();
 
             fn_ref.body = Some(inst);
+            fn_ref.span = b.span;
+            fn_ref.vars = vars;
 
             Ok(())
         } else {
@@ -366,6 +1026,81 @@ impl<'a> Codegen<'a> {
     }
 }
 
+/// Code generation of a program one function at a time, which
+/// [`Codegen::stream`] starts
+#[derive(Debug)]
+pub struct StreamedCodegen<'a> {
+    codegen: Codegen<'a>,
+    start_code: InstSink,
+    /// Types inferred for `auto` global variables
+    globals: BTreeMap<(usize, String), Type>,
+}
+
+impl StreamedCodegen<'_> {
+    /// Compile function `name`, defined by `def` in `scope`, the global
+    /// scope of the program parsed again, with bodies. Nothing of its syntax
+    /// tree is kept once this returns.
+    pub fn compile_fn(
+        &mut self,
+        name: &str,
+        def: &Ptr<ast::SymbolDef>,
+        scope: &Ptr<ast::Scope>,
+    ) -> CompileResult<()> {
+        let typed = type_checker::lower_fn(name, def.cp(), scope, self.globals.clone())?;
+        let codegen = &mut self.codegen;
+        codegen.target.check_types(&typed)?;
+        codegen.glob.boxed = escape::escaping(&Aliases::new(&typed));
+        codegen.glob.inferred = typed.inferred;
+        if !codegen.glob.fns.contains_key(name) {
+            return Err(CompileErrorVar::InternalError(format!(
+                "Function `{}` was not declared before it was compiled",
+                name
+            ))
+            .into());
+        }
+        let def = def.borrow();
+        if let ast::SymbolDef::Var { typ, .. } = &*def {
+            if let ast::TypeDef::Function(f) = &*typ.borrow() {
+                codegen.compile_fn(f, name)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Finish compiling, once every function is compiled
+    pub fn finish(mut self) -> CompileResult<O0> {
+        let glob = &self.codegen.glob;
+        if let Some((name, _)) = (glob.fns.iter()).find(|(_, f)| f.body.is_none()) {
+            return Err(CompileErrorVar::FunctionMissingBody(name.clone()).into());
+        }
+        self.codegen.add_host_fns();
+        let (o0, _) = self.codegen.assemble(self.start_code)?;
+        Ok(o0)
+    }
+}
+
+/// How debuggers show variables of type `typ`
+fn var_kind(typ: &ast::TypeDef) -> VarKind {
+    match typ {
+        ast::TypeDef::Primitive(p) => match p.var {
+            ast::PrimitiveTypeVar::Float => VarKind::Double,
+            _ if p.occupy_bytes == 1 => VarKind::Char,
+            _ => VarKind::Int,
+        },
+        _ => VarKind::Other,
+    }
+}
+
+/// Kind of values of `typ` passed to and from host functions
+fn host_kind(typ: &ast::TypeDef) -> value::Kind {
+    match var_kind(typ) {
+        VarKind::Double => value::Kind::Double,
+        VarKind::Char => value::Kind::Char,
+        _ if typ.is_unit() => value::Kind::Void,
+        _ => value::Kind::Int,
+    }
+}
+
 /// Resolve all named types into their definitions, and strip function types' bodies
 fn resolve_ty(ty: &ast::TypeDef, scope: Ptr<ast::Scope>) -> ast::TypeDef {
     match ty {
@@ -376,12 +1111,12 @@ fn resolve_ty(ty: &ast::TypeDef, scope: Ptr<ast::Scope>) -> ast::TypeDef {
             let sty = sty.borrow().get_typ().unwrap();
             let sty = sty.borrow();
 
-            resolve_ty(&*sty, scope.cp())
+            resolve_ty(&sty, scope.cp())
         }
         prim @ ast::TypeDef::Primitive(..) => prim.clone(),
         ast::TypeDef::Ref(r) => {
             let src = r.target.borrow();
-            let res = Ptr::new(resolve_ty(&*src, scope.cp()));
+            let res = Ptr::new(resolve_ty(&src, scope.cp()));
             ast::TypeDef::Ref(ast::RefType { target: res })
         }
         ast::TypeDef::Function(f) => {
@@ -390,10 +1125,10 @@ fn resolve_ty(ty: &ast::TypeDef, scope: Ptr<ast::Scope>) -> ast::TypeDef {
                 .iter()
                 .map(|a| {
                     let a = a.borrow();
-                    Ptr::new(resolve_ty(&*a, scope.cp()))
+                    Ptr::new(resolve_ty(&a, scope.cp()))
                 })
                 .collect();
-            let ret = Ptr::new(resolve_ty(&*f.return_type.borrow(), scope.cp()));
+            let ret = Ptr::new(resolve_ty(&f.return_type.borrow(), scope.cp()));
             ast::TypeDef::Function(ast::FunctionType {
                 params,
                 return_type: ret,
@@ -406,6 +1141,60 @@ fn resolve_ty(ty: &ast::TypeDef, scope: Ptr<ast::Scope>) -> ast::TypeDef {
     }
 }
 
+/// The value of `expr` if it is an integer literal, maybe with signs in
+/// front
+fn int_literal(expr: &ast::Expr) -> Option<num::Int> {
+    match &expr.var {
+        ast::ExprVariant::Literal(ast::Literal::Integer { val }) => Some(val.clone()),
+        ast::ExprVariant::UnaryOp(u) if u.op == ast::OpVar::Neg => {
+            int_literal(&u.val.borrow()).map(|val| -val)
+        }
+        ast::ExprVariant::UnaryOp(u) if u.op == ast::OpVar::Pos => int_literal(&u.val.borrow()),
+        _ => None,
+    }
+}
+
+/// Whether `expr` calls a function anywhere
+pub(crate) fn has_call(expr: &ast::Expr) -> bool {
+    maybe_grow(|| match &expr.var {
+        ast::ExprVariant::FunctionCall(_) => true,
+        ast::ExprVariant::BinaryOp(b) => has_call(&b.lhs.borrow()) || has_call(&b.rhs.borrow()),
+        ast::ExprVariant::UnaryOp(u) => has_call(&u.val.borrow()),
+        ast::ExprVariant::TypeConversion(c) => has_call(&c.expr.borrow()),
+        _ => false,
+    })
+}
+
+/// Global constants of `prog` known when compiling, with their type and
+/// value, taken from `initial` if their initializer cannot be evaluated
+fn fold_consts(
+    prog: &ast::Program,
+    initial: &BTreeMap<String, Value>,
+) -> BTreeMap<String, (Type, Value)> {
+    let scope = &prog.blk.scope;
+    let defs = scope.borrow();
+    (defs.defs.iter())
+        .filter_map(|(name, def)| match &*def.borrow() {
+            ast::SymbolDef::Var {
+                typ,
+                is_const: true,
+                value: Some(value),
+                ..
+            } => {
+                let kind = consteval::kind_of(&typ.borrow(), scope)?;
+                let val = (consteval::eval(&value.borrow(), scope).ok())
+                    .or_else(|| initial.get(&name.to_string()).cloned())?;
+                // * Out of range for its type, it wraps around as stored
+                let val = val.checked_cast(kind).ok()?;
+                let typ = Ptr::new(resolve_ty(&typ.borrow(), scope.cp()));
+                Some((name.to_string(), (typ, val)))
+            }
+            _ => None,
+        })
+        .filter(|(_, (_, val))| !matches!(val, Value::Void))
+        .collect()
+}
+
 /// Calculate the bits needed for a type to contain a value
 fn type_bits(len: u32) -> Option<u16> {
     if len > 128 {
@@ -433,6 +1222,7 @@ pub(super) struct LocalVar {
 
 #[derive(Clone, Eq, PartialEq, Debug)]
 pub(super) struct LocalVars {
+    /// Variables get slots in declaration order
     def_map: IndexMap<String, LocalVar>,
     size_stack: Vec<u32>,
     max_stack_size: u32,
@@ -461,23 +1251,19 @@ impl LocalVars {
             is_const,
             typ: typ.cp(),
         };
-        log::trace!(
+        tracing::trace!(
             "Inserting local variable: {}, size {}, offset {}",
             name,
             size,
             cur_stack_size
         );
-        self.def_map.insert(name.into(), loc).map_or_else(
-            || Ok(()),
-            |_| {
-                Err(CompileErrorVar::InternalError(
-                    "Name conflict on local variable declaration".into(),
-                ))
-            },
-        )?;
+        // * A block generated again, as a copy of the body of an unrolled
+        // * loop or a function inlined again, declares its variables again,
+        // * in slots that only differ if it is not nested the same
+        self.def_map.insert(name.into(), loc);
         {
             let last = self.size_stack.last_mut().unwrap();
-            *last = *last + size;
+            *last += size;
         }
         if cur_stack_size + size > self.max_stack_size {
             self.max_stack_size = cur_stack_size + size;
@@ -552,6 +1338,14 @@ where
     }
 }
 
+/// The body of a function inlined into its callers
+struct InlineBody {
+    /// What it returns
+    expr: Ptr<ast::Expr>,
+    scope: Ptr<ast::Scope>,
+    params: Vec<String>,
+}
+
 /// A function code generator. Responsible for generating
 #[derive(Debug)]
 pub(super) struct FnCodegen<'a, 'b> {
@@ -562,15 +1356,42 @@ pub(super) struct FnCodegen<'a, 'b> {
 
     name: &'b str,
 
-    break_tgt: Vec<usize>,
+    /// Enclosing loops as `(label, block to jump to on break)`, innermost last
+    break_tgt: Vec<(Option<String>, usize)>,
 
     /// Data count, only for naming usage
     data_cnt: u32,
     data: &'b mut GlobalData,
+    target: Target,
+    allow_overflow: bool,
+    ub_checks: bool,
+    standard: Standard,
+    unroll: (u32, usize),
+    /// Loops to unroll, and how
+    unrolled: HashMap<*const ast::WhileConditional, Unrolled>,
     loc: LocalVars,
+    /// Variables declared so far, for debug info
+    vars: Vec<VarInfo>,
+    /// Last line of the block whose variables are being declared
+    scope_end: u32,
+    /// Line of the statement being compiled
+    line: Option<u32>,
 
     inst: Option<&'a mut InstSink>,
     sink_pool: DeqPool<'a, InstSink>,
+    scheduler: Scheduler,
+    /// Pure calls and values computed only once, by the temporary keeping
+    /// the result
+    reuse: HashMap<*const ast::Expr, Reuse>,
+    /// Offset and type of each temporary keeping a result. Values of
+    /// [`gvn`] get their type once computed.
+    temps: Vec<(i32, Option<Type>)>,
+    /// Functions whose calls are being inlined, innermost last
+    inlining: Vec<String>,
+    /// Whether to lay out blocks so that branches fall through
+    branch_layout: bool,
+    /// Optimizations to run on the function
+    passes: Vec<Pass>,
 
     start_bb: BB,
     bbs: Vec<BB>,
@@ -590,6 +1411,7 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
             inst: InstSink::new(),
             end: BlockEndJump::Unknown,
         });
+        let passes = ctx.passes_for(name);
 
         FnCodegen {
             f,
@@ -600,10 +1422,28 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
             data_cnt: 0,
             break_tgt: vec![],
             data: &mut ctx.glob,
+            target: ctx.target,
+            allow_overflow: ctx.allow_overflow,
+            ub_checks: ctx.ub_checks,
+            standard: ctx.standard,
+            unroll: match passes.contains(&Pass::Unroll) {
+                true => ctx.unroll,
+                false => (0, 0),
+            },
+            unrolled: HashMap::new(),
+            line: None,
             loc: LocalVars::new(),
+            vars: vec![],
+            scope_end: 0,
             // module: &mut ctx.module,,
             inst: None,
             sink_pool: DeqPool::new_with_reset(&InstSink::new, &InstSink::reset),
+            scheduler: Scheduler::new(),
+            reuse: HashMap::new(),
+            temps: vec![],
+            inlining: vec![],
+            branch_layout: passes.contains(&Pass::Layout),
+            passes,
             start_bb: start_bb.cp(),
             bbs: vec![start_bb],
         }
@@ -619,20 +1459,25 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
             self.params
                 .iter()
                 .try_fold::<u32, _, CompileResult<u32>>(0, |sum, item| {
-                    Ok(item
-                        .borrow()
-                        .occupy_slots()
+                    Ok(self
+                        .target
+                        .slots_of(&item.borrow())
                         .ok_or(CompileErrorVar::RequireSized("".into()))?
                         + sum)
                 })?;
 
+        // * Address 0 is null, so no variable may be there
+        if self.ub_checks && b.scope.borrow().id == 0 {
+            self.loc.dive_into_scope();
+            self.loc.add_var("`null", 1, true, Self::int_type(4))?;
+        }
         self.gen_scope(b, self.start_bb.cp(), b.scope.cp())?;
 
         // Calculate local variable size
         {
             let stack_size = self.loc.max_stack_size();
 
-            log::info!(
+            tracing::info!(
                 "The function has max stack size of {} slots, of which {} are params.",
                 stack_size,
                 self.param_siz
@@ -659,132 +1504,153 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
         Ok((inst, loc))
     }
 
+    /// Lay out basic blocks, each followed by jumps to the blocks it goes
+    /// to. Jumps target the labels of blocks until all are laid out.
+    ///
+    /// With a profile, a block is followed by the block it went to most that
+    /// is not laid out yet, and blocks that never ran come last.
     pub fn finish(&mut self) -> CompileResult<InstSink> {
-        log::debug!("Finished compiling. function is {:#?}", &self.bbs);
+        tracing::debug!("Finished compiling. function is {:#?}", &self.bbs);
 
-        let mut bb_start: IndexMap<usize, usize> = IndexMap::new();
-        let mut bb_length: IndexMap<usize, usize> = IndexMap::new();
-        let mut finished_bb: IndexSet<usize> = IndexSet::new();
+        let to = self.skip_empty_blocks();
+
+        let counts = (self.data.profile.as_ref())
+            .filter(|_| self.branch_layout)
+            .and_then(|p| p.blocks(self.name, self.bbs.len()))
+            .map(<[u64]>::to_vec);
+        let mut labels = Labels::new();
+        let bb_labels: Vec<_> = self.bbs.iter().map(|_| labels.new_label()).collect();
         let mut inst = InstSink::new();
-        let mut pending_bb = std::collections::VecDeque::new();
-        pending_bb.push_back(0);
-
-        while pending_bb.len() != 0 {
-            let bb_id = pending_bb.pop_back().unwrap();
-            let bb = self.bbs.get(bb_id).unwrap();
-            let mut bb_mut = bb.borrow_mut();
-
-            log::info!("Parsing BB {}", bb_id);
-            if !bb_start.contains_key(&bb_id) {
-                log::debug!("BB is not seen before");
-                // * Brand new basic block
-                bb_start.insert(bb_mut.id, inst.len());
-                bb_length.insert(bb_mut.id, bb_mut.len());
-                inst.append_all(&mut bb_mut.inst);
-                match bb_mut.end {
-                    BlockEndJump::Conditional { z, nz } => {
-                        log::debug!("BB: Conditional z {} nz {}", z, nz);
-                        // * To be replaced with `JNz(nz)`
-                        inst.push(Inst::Nop);
-                        // * To be replaced with `Jmp(z)`
-                        inst.push(Inst::Nop);
-
-                        pending_bb.push_back(bb_id);
-                        pending_bb.push_back(z);
-                        pending_bb.push_back(nz);
-                    }
-                    BlockEndJump::Unconditional(z) => {
-                        // * To be replaced with `Jmp(z)`
-                        log::info!("BB: Unconditional z {}", z);
-                        inst.push(Inst::Nop);
+        // * Depth first, visiting where a conditional jump goes when its
+        // * condition holds before where it goes otherwise
+        let mut pending_bb = vec![0];
+        let mut cold_bb = vec![];
+
+        while let Some(bb_id) = pending_bb.pop().or_else(|| cold_bb.pop()) {
+            let label = bb_labels[bb_id];
+            if labels.place_of(label).is_some() {
+                tracing::debug!("BB {} is laid out already", bb_id);
+                continue;
+            }
+            tracing::info!("Laying out BB {}", bb_id);
+            labels.place(label, inst.len());
+            inst.mark_block(bb_id);
 
-                        pending_bb.push_back(bb_id);
-                        pending_bb.push_back(z);
-                    }
-                    BlockEndJump::Return => {
-                        // * Already finished because BB does not link to another
-                        log::info!("BB: Return",);
-                        finished_bb.insert(bb_id);
+            let mut bb_mut = self.bbs[bb_id].borrow_mut();
+            // * Jumps at the end belong to the last statement of the block
+            inst.line = bb_mut
+                .inst
+                .line
+                .or(bb_mut.inst.lines().last().copied().flatten());
+            inst.append_all(&mut bb_mut.inst);
+            let is_placed = |bb: usize| labels.place_of(bb_labels[bb]).is_some();
+            // * Where to go after a block that ran, unless it is the next
+            let mut then = |bb: usize, pending: &mut Vec<usize>| match &counts {
+                Some(c) if c[bb] == 0 && c[bb_id] > 0 => cold_bb.push(bb),
+                _ => pending.push(bb),
+            };
+            match bb_mut.end {
+                BlockEndJump::Conditional { z, nz } => {
+                    tracing::debug!("BB: Conditional z {} nz {}", z, nz);
+                    let (z, nz) = (to[z], to[nz]);
+                    let z_first = !is_placed(z)
+                        && match &counts {
+                            Some(c) => is_placed(nz) || c[z] > c[nz],
+                            None => self.branch_layout && is_placed(nz),
+                        };
+                    if z_first {
+                        inst.push(Inst::JNe(bb_labels[nz].0));
+                        inst.push(Inst::Jmp(bb_labels[z].0));
+                        then(nz, &mut pending_bb);
+                        pending_bb.push(z);
+                    } else if counts.is_some() || self.branch_layout {
+                        inst.push(Inst::JE(bb_labels[z].0));
+                        inst.push(Inst::Jmp(bb_labels[nz].0));
+                        then(z, &mut pending_bb);
+                        pending_bb.push(nz);
+                    } else {
+                        inst.push(Inst::JNe(bb_labels[nz].0));
+                        inst.push(Inst::Jmp(bb_labels[z].0));
+                        pending_bb.push(z);
+                        pending_bb.push(nz);
                     }
-                    BlockEndJump::Unknown => {
-                        log::info!("BB: Unknown",);
-                        if self.ret_type.borrow().is_unit() {
-                            // * Unit return type. Manually add `ret` here. Same as above.
-                            inst.push(Inst::Ret);
-                            finished_bb.insert(bb_id);
-                        } else {
-                            // * Hey, your favorite error message!
-                            return Err(compile_err(
-                                CompileErrorVar::ControlReachesEndOfNonVoidFunction,
-                                self.f.span,
-                            ));
-                        }
+                }
+                BlockEndJump::Unconditional(z) => {
+                    tracing::info!("BB: Unconditional z {}", z);
+                    let z = to[z];
+                    inst.push(Inst::Jmp(bb_labels[z].0));
+                    then(z, &mut pending_bb);
+                }
+                BlockEndJump::Return => {
+                    // * Already finished because BB does not link to another
+                    tracing::info!("BB: Return",);
+                }
+                BlockEndJump::Unknown => {
+                    tracing::info!("BB: Unknown",);
+                    if self.ret_type.borrow().is_unit() {
+                        // * Unit return type. Manually add `ret` here.
+                        inst.push(Inst::Ret);
+                    } else {
+                        // * Hey, your favorite error message!
+                        return Err(compile_err(
+                            CompileErrorVar::ControlReachesEndOfNonVoidFunction,
+                            self.f.span,
+                        ));
                     }
                 }
-            } else if !finished_bb.contains(&bb_id) {
-                // * Basic block that has its decendants resolved
-                log::debug!("BB has seen before");
-                match bb_mut.end {
-                    BlockEndJump::Conditional { z, nz } => {
-                        let nz_place =
-                            *bb_start.get(&bb_id).unwrap() + *bb_length.get(&bb_id).unwrap();
-
-                        let mut not_finished = false;
-                        // Replace nop with `JNz(nz)`
-                        if bb_start.contains_key(&nz) {
-                            let replace_nz = inst.0.get_mut(nz_place).unwrap();
-                            *replace_nz = Inst::JNe(*bb_start.get(&nz).unwrap() as u16);
-                        } else {
-                            // No luck. Try again later!
-                            not_finished = not_finished || true;
-                            pending_bb.push_front(bb_id);
-                            pending_bb.push_back(nz);
-                            log::debug!("BB has no nz. Waiting.");
-                        }
+            }
+        }
+        // * Blocks nothing goes to start at the end, so profiles know of
+        // * every block
+        for (bb_id, label) in bb_labels.iter().enumerate() {
+            if labels.place_of(*label).is_none() {
+                inst.mark_block(bb_id);
+            }
+        }
 
-                        // Replace nop with `Jmp(z)`
-                        if bb_start.contains_key(&z) {
-                            let replace_z = inst.0.get_mut(nz_place + 1).unwrap();
-                            *replace_z = Inst::Jmp(*bb_start.get(&z).unwrap() as u16);
-                        } else {
-                            not_finished = not_finished || true;
-                            pending_bb.push_front(bb_id);
-                            pending_bb.push_back(z);
-                            log::debug!("BB has no z. Waiting.");
-                        }
+        labels.resolve(inst.inner_mut()).map_err(|label| {
+            CompileErrorVar::InternalError(format!("Jump to BB {} never laid out", label.0))
+        })?;
+        Ok(inst)
+    }
 
-                        if !not_finished {
-                            finished_bb.insert(bb_id);
-                        }
-                    }
-                    BlockEndJump::Unconditional(z) => {
-                        let z_place =
-                            *bb_start.get(&bb_id).unwrap() + *bb_length.get(&bb_id).unwrap();
-
-                        if bb_start.contains_key(&z) {
-                            // Replace nop with `Jmp(nz)`
-                            let replace_nz = inst.0.get_mut(z_place).unwrap();
-                            *replace_nz = Inst::Jmp(*bb_start.get(&z).unwrap() as u16);
-                            finished_bb.insert(bb_id);
-                        } else {
-                            pending_bb.push_front(bb_id);
-                        }
+    /// Where jumps to each block go: past blocks with no instructions that
+    /// only jump elsewhere, if blocks are laid out for branches
+    fn skip_empty_blocks(&self) -> Vec<usize> {
+        let mut to: Vec<_> = (0..self.bbs.len()).collect();
+        if !self.branch_layout {
+            return to;
+        }
+        let next = |bb: usize| {
+            let bb = self.bbs[bb].borrow();
+            match bb.end {
+                BlockEndJump::Unconditional(z) if bb.inst.is_empty() => Some(z),
+                _ => None,
+            }
+        };
+        for (bb, to) in to.iter_mut().enumerate().skip(1) {
+            // * Empty blocks jumping around in a loop are left as they are
+            let mut at = bb;
+            for _ in 0..self.bbs.len() {
+                match next(at) {
+                    Some(z) => at = z,
+                    None => {
+                        *to = at;
+                        break;
                     }
-                    _ => {} // Already finished!
                 }
-            } else {
-                log::debug!("BB is finished");
             }
         }
-
-        Ok(inst)
+        to
     }
 
     pub(super) fn new_bb(&mut self) -> (usize, BB) {
         let bb_id = self.bbs.len();
+        let mut inst = InstSink::new();
+        inst.line = self.line;
         let bb = Ptr::new(BasicBlock {
             id: bb_id,
-            inst: InstSink::new(),
+            inst,
             end: BlockEndJump::Unknown,
         });
         self.bbs.push(bb.cp());
@@ -808,20 +1674,46 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
                 typ,
                 is_const,
                 decl_span,
+                ..
             } => {
                 // if id != 0 {
                 // Who cares about constants?
                 // * This function does not care about where this variable is declared
                 let var_name = format!("{}`{}", name, id);
 
-                let typ = resolve_ty(&*typ.borrow(), scope);
-                if !typ.is_fn() && !typ.is_unit() {
-                    let occupy_slots = typ
-                        .occupy_slots()
+                let typ = match &*typ.borrow() {
+                    ast::TypeDef::Unknown => {
+                        let inferred = self.data.inferred.get(&(id, name.into()));
+                        let inferred = inferred.ok_or_else(|| {
+                            let var = CompileErrorVar::CannotInferType(name.into());
+                            compile_err(var, Some(*decl_span))
+                        })?;
+                        inferred.borrow().clone()
+                    }
+                    typ => resolve_ty(typ, scope),
+                };
+                if id == 0 && self.data.folded.contains_key(name) {
+                    Ok(())
+                } else if !typ.is_fn() && !typ.is_unit() {
+                    let occupy_slots = self
+                        .target
+                        .slots_of(&typ)
                         .ok_or(CompileErrorVar::RequireSized(format!("{:?}", typ)))?;
 
+                    let kind = var_kind(&typ);
                     self.loc
                         .add_var(&var_name, occupy_slots, *is_const, Ptr::new(typ))?;
+                    let first_line = decl_span.start.ln as u32;
+                    let info = VarInfo {
+                        name: name.into(),
+                        kind,
+                        offset: self.loc.get_var(&var_name).unwrap().offset,
+                        slots: occupy_slots,
+                        lines: (first_line, self.scope_end.max(first_line)),
+                    };
+                    if !self.vars.contains(&info) {
+                        self.vars.push(info);
+                    }
 
                     Ok(())
                 } else if typ.is_unit() {
@@ -845,79 +1737,300 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
     }
 
     fn gen_stmt(&mut self, stmt: &ast::Stmt, bb: BB, scope: Ptr<ast::Scope>) -> CompileResult<BB> {
-        match &stmt.var {
+        // * Instructions get the line of the innermost statement they are for
+        let outer_line = self.line;
+        self.line = Some(stmt.span.start.ln as u32);
+        bb.borrow_mut().inst.line = self.line;
+        let outer_span = CURRENT_SPAN.with(|s| s.replace(Some(stmt.span)));
+
+        // * Nested statements recurse through here
+        let res = maybe_grow(|| match &stmt.var {
             ast::StmtVariant::Expr(e) => {
-                {
-                    let inst = &mut bb.borrow_mut().inst;
-
-                    let typ = self.gen_expr(e.cp(), inst, scope.cp())?;
-                    if !typ.borrow().is_unit() {
-                        pop(typ.cp(), inst)?;
-                    }
-                }
+                self.gen_expr_stmt(e.cp(), &mut bb.borrow_mut().inst, scope)?;
                 Ok(bb)
             }
             ast::StmtVariant::ManyExpr(e) => {
-                {
-                    let inst = &mut bb.borrow_mut().inst;
-
-                    for e in e {
-                        let typ = self.gen_expr(e.cp(), inst, scope.cp())?;
-                        if !typ.borrow().is_unit() {
-                            pop(typ.cp(), inst)?;
-                        }
-                    }
+                for e in e {
+                    self.gen_expr_stmt(e.cp(), &mut bb.borrow_mut().inst, scope.cp())?;
                 }
-
                 Ok(bb)
             }
             ast::StmtVariant::Return(e) => self.gen_return(e, bb, scope),
             ast::StmtVariant::Block(e) => self.gen_scope(e, bb, scope),
             ast::StmtVariant::Print(e) => self.gen_print(e, bb, scope),
-            ast::StmtVariant::Scan(e) => self.gen_scan(e, bb, scope),
-            ast::StmtVariant::Break => self.gen_break(bb, scope),
+            ast::StmtVariant::Scan(e, _) => self.gen_scan(e, bb, scope),
+            ast::StmtVariant::Break(label) => self.gen_break(label.as_ref(), bb, scope),
             ast::StmtVariant::If(e) => self.gen_if(e, bb, scope),
             ast::StmtVariant::While(e) => self.gen_while(e, bb, scope),
             ast::StmtVariant::Empty => Ok(bb),
+        });
+
+        CURRENT_SPAN.with(|s| s.set(outer_span));
+        self.line = outer_line;
+        if let Ok(bb) = &res {
+            bb.borrow_mut().inst.line = outer_line;
+        }
+        res.with_span(stmt.span)
+    }
+
+    /// Generate `expr` for its side effects, dropping its value
+    fn gen_expr_stmt(
+        &mut self,
+        expr: Ptr<ast::Expr>,
+        inst: &mut InstSink,
+        scope: Ptr<ast::Scope>,
+    ) -> CompileResult<()> {
+        // * An assignment statement needs no value, even where it has one
+        if let ast::ExprVariant::BinaryOp(b) = &expr.borrow().var {
+            if b.op == ast::OpVar::_Asn || b.op == ast::OpVar::_Csn {
+                return self
+                    .gen_assign(b, false, inst, scope)
+                    .map(|_| ())
+                    .with_span(expr.borrow().span);
+            }
+        }
+        let typ = self.gen_expr(expr, inst, scope)?;
+        if !typ.borrow().is_unit() {
+            pop(typ.cp(), &self.target, inst)?;
         }
-        .with_span(stmt.span)
+        Ok(())
+    }
+
+    /// Generate `expr` as a test in the condition of an `if` or `while`
+    fn gen_cond(
+        &mut self,
+        expr: Ptr<ast::Expr>,
+        inst: &mut InstSink,
+        scope: Ptr<ast::Scope>,
+    ) -> CompileResult<()> {
+        if !self.standard.assign_expr {
+            if let ast::ExprVariant::BinaryOp(b) = &expr.borrow().var {
+                if b.op == ast::OpVar::_Asn {
+                    return Err(compile_err(
+                        CompileErrorVar::AssignInCondition,
+                        Some(b.op_span),
+                    ));
+                }
+            }
+        }
+        let typ = self.gen_expr(expr, inst, scope)?;
+        conv(typ, Self::int_type(1), &self.target, inst)?;
+        Ok(())
+    }
+
+    /// Generate `expr` as the condition of an `if` or `while`, starting in
+    /// `bb`. The right side of `&&` and `||` gets a block of its own, run
+    /// only when the left side does not decide the condition. The blocks
+    /// ending the condition go to `exits`, to be ended by [`Self::end_cond`]
+    /// once its targets are known.
+    fn gen_branch(
+        &mut self,
+        expr: Ptr<ast::Expr>,
+        bb: BB,
+        targets: (Option<usize>, Option<usize>),
+        exits: &mut Vec<CondExit>,
+        scope: Ptr<ast::Scope>,
+    ) -> CompileResult<()> {
+        maybe_grow(|| {
+            let logic = match &expr.borrow().var {
+                ast::ExprVariant::BinaryOp(b) if matches!(b.op, OpVar::And | OpVar::Or) => {
+                    Some((b.op, b.lhs.cp(), b.rhs.cp()))
+                }
+                _ => None,
+            };
+            let (op, lhs, rhs) = match logic {
+                Some(logic) => logic,
+                None => {
+                    self.gen_cond(expr, &mut bb.borrow_mut().inst, scope)?;
+                    exits.push((bb, targets.0, targets.1));
+                    return Ok(());
+                }
+            };
+            let (rhs_bb_id, rhs_bb) = self.new_bb();
+            let lhs_targets = match op {
+                OpVar::And => (Some(rhs_bb_id), targets.1),
+                _ => (targets.0, Some(rhs_bb_id)),
+            };
+            self.gen_branch(lhs, bb, lhs_targets, exits, scope.cp())?;
+            self.gen_branch(rhs, rhs_bb, targets, exits, scope)
+        })
+    }
+
+    /// End the blocks of a condition, going to `nz` when it holds and to `z`
+    /// when it does not
+    fn end_cond(exits: &[CondExit], nz: usize, z: usize) {
+        for (bb, exit_nz, exit_z) in exits {
+            bb.borrow_mut().end = BlockEndJump::Conditional {
+                z: exit_z.unwrap_or(z),
+                nz: exit_nz.unwrap_or(nz),
+            };
+        }
+    }
+
+    fn gen_expr(
+        &mut self,
+        expr: Ptr<ast::Expr>,
+        inst: &mut InstSink,
+        scope: Ptr<ast::Scope>,
+    ) -> CompileResult<Type> {
+        maybe_grow(|| {
+            let expr = expr.borrow();
+            let expr = &*expr;
+            if let Some(reuse) = self.reuse.get(&(expr as *const ast::Expr)) {
+                return self.gen_reuse(*reuse, expr, inst, scope);
+            }
+            self.gen_expr_uncached(expr, inst, scope)
+        })
+    }
+
+    /// Generate a pure call or value computed only once, by `reuse`
+    fn gen_reuse(
+        &mut self,
+        reuse: Reuse,
+        expr: &ast::Expr,
+        inst: &mut InstSink,
+        scope: Ptr<ast::Scope>,
+    ) -> CompileResult<Type> {
+        let (temp, save) = match reuse {
+            Reuse::Save(temp) => (temp, true),
+            Reuse::Load(temp) => (temp, false),
+        };
+        let (offset, typ) = self.temps[temp].clone();
+        let typ = if save {
+            inst.push(Inst::LoadA(0, offset));
+            let res = self.gen_expr_uncached(expr, inst, scope)?;
+            let typ = match typ {
+                Some(typ) => {
+                    conv(res, typ.cp(), &self.target, inst)?;
+                    typ
+                }
+                None => res,
+            };
+            store(typ.cp(), &self.target, inst)?;
+            self.temps[temp].1 = Some(typ.cp());
+            typ
+        } else {
+            let pass = match self.passes.contains(&Pass::Gvn) {
+                true => Pass::Gvn,
+                false => Pass::Cse,
+            };
+            let message = match &expr.var {
+                ast::ExprVariant::FunctionCall(f) => {
+                    format!("call to `{}` not made again, its result reused", f.func)
+                }
+                _ => "value computed before reused".into(),
+            };
+            self.remark(pass, RemarkKind::Applied, Some(expr.span), message);
+            typ.ok_or_else(|| {
+                CompileErrorVar::InternalError(format!("Temporary {} used before set", temp))
+            })?
+        };
+        inst.push(Inst::LoadA(0, offset));
+        load(typ.cp(), &self.target, inst)?;
+        Ok(typ)
     }
 
-    fn gen_expr(
+    fn gen_expr_uncached(
         &mut self,
-        expr: Ptr<ast::Expr>,
+        expr: &ast::Expr,
         inst: &mut InstSink,
         scope: Ptr<ast::Scope>,
     ) -> CompileResult<Type> {
-        let expr = expr.borrow();
-        let expr = &*expr;
-        match &expr.var {
-            ast::ExprVariant::BinaryOp(b) => self.gen_bin_op(b, inst, scope),
-            ast::ExprVariant::UnaryOp(u) => self.gen_una_op(u, inst, scope),
-            ast::ExprVariant::Ident(i) => self.gen_ident_expr(i, inst, scope),
-            ast::ExprVariant::FunctionCall(f) => self.gen_func_call(f, inst, scope),
-            ast::ExprVariant::Literal(lit) => self.gen_literal(lit, inst, scope),
-            ast::ExprVariant::TypeConversion(ty) => self.gen_ty_conversion(ty, inst, scope),
-            _ => Err(
-                CompileErrorVar::NotImplemented("Implement other expression variants".into())
-                    .into(),
-            ),
+        {
+            let outer_span = CURRENT_SPAN.with(|s| s.replace(Some(expr.span)));
+            let res = match &expr.var {
+                ast::ExprVariant::BinaryOp(b) => self.gen_bin_op(b, inst, scope),
+                ast::ExprVariant::UnaryOp(u) => self.gen_una_op(u, inst, scope),
+                ast::ExprVariant::Ident(i) => self.gen_ident_expr(i, inst, scope),
+                ast::ExprVariant::FunctionCall(f) => self.gen_func_call(f, inst, scope),
+                ast::ExprVariant::Literal(lit) => self.gen_literal(lit, inst, scope),
+                ast::ExprVariant::TypeConversion(ty) => self.gen_ty_conversion(ty, inst, scope),
+                _ => Err(CompileErrorVar::NotImplemented(
+                    "Implement other expression variants".into(),
+                )
+                .into()),
+            };
+            CURRENT_SPAN.with(|s| s.set(outer_span));
+            res.with_span(expr.span)
         }
-        .with_span(expr.span)
     }
 
     fn gen_scope(
         &mut self,
         block: &ast::Block,
         bb: BB,
-        scope: Ptr<ast::Scope>,
+        _scope: Ptr<ast::Scope>,
     ) -> CompileResult<BB> {
         self.loc.dive_into_scope();
+        // * The program has no span, and its globals are in scope to the end
+        self.scope_end = block.span.map_or(u32::MAX, |span| span.end.ln as u32);
 
         let scope = block.scope.cp();
         let defs = scope.borrow();
         for local in &defs.defs {
-            self.add_local(&local.0, &*local.1.borrow(), defs.id, scope.cp())?;
+            self.add_local(local.0, &local.1.borrow(), defs.id, scope.cp())?;
+        }
+        // * Parameters are the first variables of the function's block
+        let params = match std::ptr::eq(block, self.f) {
+            true => self.params.len(),
+            false => 0,
+        };
+        for (idx, name) in defs.defs.keys().enumerate() {
+            if self.data.boxed.contains(&(defs.id, name.to_string())) {
+                self.gen_box(name, defs.id, idx < params, &mut bb.borrow_mut().inst)?;
+            } else if self.ub_checks && defs.id != 0 && idx >= params {
+                self.add_shadow(name, defs.id, &mut bb.borrow_mut().inst)?;
+            }
+        }
+
+        // * Start code runs once, and global initializers may be folded
+        let gvn = self.passes.contains(&Pass::Gvn);
+        let cse = self.passes.contains(&Pass::Cse) && !gvn;
+        let body = std::ptr::eq(block, self.f);
+        if let (Some(aliases), false, true, true) = (&self.data.aliases, defs.id == 0, gvn, body) {
+            let plan = gvn::plan(block, &self.data.purity, aliases);
+            let first = self.temps.len();
+            for idx in 0..plan.temps {
+                self.add_value_temp(first + idx, &scope)?;
+            }
+            let reuse = plan.reuse.into_iter();
+            (self.reuse).extend(reuse.map(|(expr, reuse)| match reuse {
+                Reuse::Save(temp) => (expr, Reuse::Save(first + temp)),
+                Reuse::Load(temp) => (expr, Reuse::Load(first + temp)),
+            }));
+        }
+        if let (Some(aliases), false, true) = (&self.data.aliases, defs.id == 0, cse) {
+            let plan = cse::plan(block, &self.data.purity, aliases);
+            let first = self.temps.len();
+            for (idx, func) in plan.temps.iter().enumerate() {
+                self.add_temp(first + idx, func, &scope)?;
+            }
+            let reuse = plan.reuse.into_iter();
+            (self.reuse).extend(reuse.map(|(expr, reuse)| match reuse {
+                Reuse::Save(temp) => (expr, Reuse::Save(first + temp)),
+                Reuse::Load(temp) => (expr, Reuse::Load(first + temp)),
+            }));
+        }
+
+        if self.unroll.0 >= 2 {
+            for (stmt, unrolled) in unroll::plan(block, self.f, self.unroll.0, self.unroll.1) {
+                let (kind, message) = match &unrolled {
+                    Ok(Unrolled::Full(copies)) => (
+                        RemarkKind::Applied,
+                        format!("loop unrolled fully, into {} copies of its body", copies),
+                    ),
+                    Ok(Unrolled::Partial(copies)) => (
+                        RemarkKind::Applied,
+                        format!("loop unrolled into {} copies of its body", copies),
+                    ),
+                    Err(why) => (RemarkKind::Missed, format!("loop not unrolled: {}", why)),
+                };
+                self.remark(Pass::Unroll, kind, Some(stmt.span), message);
+                if let (StmtVariant::While(w), Ok(unrolled)) = (&stmt.var, unrolled) {
+                    self.unrolled
+                        .insert(w as *const ast::WhileConditional, unrolled);
+                }
+            }
         }
 
         let stmts = &block.stmts;
@@ -930,13 +2043,139 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
         Ok(bb)
     }
 
+    /// Add temporary `temp`, keeping the result of a call to `func`, to
+    /// `scope`
+    fn add_temp(&mut self, temp: usize, func: &str, scope: &Ptr<ast::Scope>) -> CompileResult<()> {
+        let ret = (self.data.fns.get(func))
+            .map(|f| f.return_type.cp())
+            .ok_or_else(|| CompileErrorVar::InternalError(format!("No function {}", func)))?;
+        let typ = resolve_ty(&ret.borrow(), scope.cp());
+        let slots = (self.target.slots_of(&typ))
+            .ok_or_else(|| CompileErrorVar::RequireSized(format!("{:?}", typ)))?;
+        let name = format!("`{}`{}", temp, scope.borrow().id);
+        self.loc
+            .add_var(&name, slots, false, Ptr::new(typ.clone()))?;
+        let offset = self.loc.get_var(&name).unwrap().offset as i32;
+        self.temps.push((offset, Some(Ptr::new(typ))));
+        Ok(())
+    }
+
+    /// Add temporary `temp`, keeping a value of any type, to `scope`
+    fn add_value_temp(&mut self, temp: usize, scope: &Ptr<ast::Scope>) -> CompileResult<()> {
+        let typ = Self::float_type(8);
+        let slots = (self.target.slots_of(&typ.borrow()))
+            .max(self.target.slots_of(&Self::ref_type(typ.cp()).borrow()))
+            .ok_or_else(|| CompileErrorVar::RequireSized(format!("{:?}", typ)))?;
+        let name = format!("`{}`{}", temp, scope.borrow().id);
+        self.loc.add_var(&name, slots, false, typ)?;
+        let offset = self.loc.get_var(&name).unwrap().offset as i32;
+        self.temps.push((offset, None));
+        Ok(())
+    }
+
+    /// Give local variable `name` of the scope `id` a shadow slot, set to 0
+    /// until it is assigned. Entering the scope again, as a loop does, sets
+    /// it to 0 again.
+    fn add_shadow(&mut self, name: &str, id: usize, inst: &mut InstSink) -> CompileResult<()> {
+        if self.loc.get_var(&format!("{}`{}", name, id)).is_none() {
+            return Ok(());
+        }
+        let shadow = format!("{}`{}`init", name, id);
+        self.loc.add_var(&shadow, 1, false, Self::int_type(4))?;
+        let offset = self.loc.get_var(&shadow).unwrap().offset as i32;
+        inst.push_many(&[Inst::LoadA(0, offset), Inst::IPush(0), Inst::IStore]);
+        Ok(())
+    }
+
+    /// Offset of the shadow slot of variable `name`, if it has one
+    fn shadow_of(&self, name: &str, scope: &Ptr<ast::Scope>) -> Option<i32> {
+        let (_, id) = scope.borrow().find_def_depth(name)?;
+        let shadow = self.loc.get_var(&format!("{}`{}`init", name, id))?;
+        Some(shadow.offset as i32)
+    }
+
+    /// Set the shadow slot of variable `expr`, if it has one, as it is
+    /// assigned
+    fn gen_init(&mut self, expr: &ast::Expr, inst: &mut InstSink, scope: &Ptr<ast::Scope>) {
+        let shadow = match &expr.var {
+            ast::ExprVariant::Ident(i) => self.shadow_of(&i.name, scope),
+            _ => None,
+        };
+        if let Some(shadow) = shadow {
+            inst.push_many(&[Inst::LoadA(0, shadow), Inst::IPush(1), Inst::IStore]);
+        }
+    }
+
+    /// Call check `name` of [`sanitizer_sigs`](vm::sanitizer_sigs) with the
+    /// values on the stack and where the expression being compiled starts
+    fn gen_check(&mut self, name: &str, inst: &mut InstSink) {
+        let (line, col) = current_span().map_or((0, 0), |span| {
+            (span.start.ln as i32 + 1, span.start.pos as i32 + 1)
+        });
+        inst.push_many(&[
+            Inst::IPush(line),
+            Inst::IPush(col),
+            Inst::_Call(self.data.relocator.symbol(name)),
+        ]);
+    }
+
+    /// Apply `op` to values of type `typ`, checking `int` arithmetic for
+    /// overflow with checks for undefined behavior
+    fn gen_op(&mut self, op: ast::OpVar, typ: Type, inst: &mut InstSink) -> CompileResult<()> {
+        let signed = matches!(
+            &*typ.borrow(),
+            ast::TypeDef::Primitive(p) if p.var == ast::PrimitiveTypeVar::SignedInt
+        );
+        let check = match op {
+            ast::OpVar::Add => "__ub_add",
+            ast::OpVar::Sub => "__ub_sub",
+            ast::OpVar::Mul => "__ub_mul",
+            ast::OpVar::Div => "__ub_div",
+            ast::OpVar::Neg => "__ub_neg",
+            _ => "",
+        };
+        if self.ub_checks && signed && !check.is_empty() {
+            self.gen_check(check, inst);
+            return Ok(());
+        }
+        op.inst(inst, typ)
+    }
+
+    /// Move local variable `name` of the scope `id` to a new box on the
+    /// heap, leaving the address of the box in its slot. A parameter takes
+    /// the value passed along.
+    fn gen_box(
+        &mut self,
+        name: &str,
+        id: usize,
+        is_param: bool,
+        inst: &mut InstSink,
+    ) -> CompileResult<()> {
+        let loc = self
+            .loc
+            .get_var(&format!("{}`{}", name, id))
+            .ok_or_else(|| {
+                CompileErrorVar::InternalError(format!("Unable to find local identifier {}", name))
+            })?;
+        let (typ, offset, size) = (loc.typ.cp(), loc.offset as i32, loc.size);
+        inst.push(Inst::LoadA(0, offset));
+        inst.push_many(&[Inst::IPush(size as i32), Inst::New]);
+        if is_param {
+            inst.push_many(&[Inst::Dup, Inst::LoadA(0, offset)]);
+            load(typ.cp(), &self.target, inst)?;
+            store(typ, &self.target, inst)?;
+        }
+        inst.push(Inst::AStore);
+        Ok(())
+    }
+
     fn gen_ident_address_and_const(
         &mut self,
         i: &ast::Identifier,
         inst: &mut InstSink,
         scope: Ptr<ast::Scope>,
     ) -> CompileResult<(Type, bool)> {
-        let def = scope.borrow().find_def_depth(&i.name).unwrap();
+        let def = scope.borrow().find_def_depth(i.name).unwrap();
 
         // Global var in global scope is also local var
         let global_scope = self.f.scope.borrow().id == 0;
@@ -950,6 +2189,9 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
             let typ = loc.typ.cp();
             let offset = loc.offset as i32;
             inst.push(Inst::LoadA(0, offset));
+            if self.data.boxed.contains(&(def.1, i.name.to_string())) {
+                inst.push(Inst::ALoad);
+            }
             Ok((typ, loc.is_const))
         } else {
             // Global variable
@@ -994,6 +2236,9 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
 
         match &expr.var {
             ast::ExprVariant::Ident(i) => self.gen_ident_address_and_const(i, inst, scope),
+            ast::ExprVariant::UnaryOp(u) if u.op == ast::OpVar::Der => {
+                Ok((self.gen_deref_address(u, inst, scope)?, false))
+            }
             _ => Err(CompileErrorVar::NotLValue(format!("{}", expr))).with_span(expr.span),
         }
     }
@@ -1005,41 +2250,94 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
         scope: Ptr<ast::Scope>,
     ) -> CompileResult<Type> {
         if b.op == ast::OpVar::_Asn || b.op == ast::OpVar::_Csn {
-            // * This generates address for lhs.
-            let (lhs, constance) =
-                self.gen_l_value_address_and_const(b.lhs.cp(), inst, scope.cp())?;
-
-            if constance && b.op != ast::OpVar::_Csn {
-                return Err(compile_err_n(CompileErrorVar::AssignConst));
+            // * Assignment statements don't get here; this one is a value
+            if !self.standard.assign_expr {
+                return Err(CompileErrorVar::AssignAsValue.into());
             }
-
-            let rhs = self.gen_expr(b.rhs.cp(), inst, scope.cp())?;
-
-            conv(rhs, lhs.cp(), inst)?;
-
-            // store lhs
-            store(lhs, inst)?;
-
-            // * Assignment evaluates as unit type!
-            Ok(Ptr::new(ast::TypeDef::Unit))
+            self.gen_assign(b, true, inst, scope)
+        } else if b.op == ast::OpVar::And || b.op == ast::OpVar::Or {
+            // * Conditions don't get here; a value would need its own blocks
+            Err(CompileErrorVar::LogicAsValue.into())
+        } else if b.op == ast::OpVar::_Com {
+            // * Only the value on the right is kept
+            self.gen_expr_stmt(b.lhs.cp(), inst, scope.cp())?;
+            self.gen_expr(b.rhs.cp(), inst, scope)
+        } else if self.is_common(b) {
+            // * Both sides give the same value, so compute it once
+            let message = "both sides of the operator the same pure value, computed once";
+            self.remark(
+                Pass::Cse,
+                RemarkKind::Applied,
+                current_span(),
+                message.into(),
+            );
+            let val = self.gen_expr(b.lhs.cp(), inst, scope)?;
+            let mut conv = self.sink_pool.get();
+            let mut other_conv = self.sink_pool.get();
+            let typ = flatten_ty(val.cp(), &mut conv, val.cp(), &mut other_conv, &self.target)?;
+            inst.append_all(&mut conv);
+            match self.target.slots_of(&typ.borrow()) {
+                Some(1) => inst.push(Inst::Dup),
+                _ => inst.push(Inst::Dup2),
+            }
+            self.gen_op(b.op, typ.cp(), inst)?;
+            self.sink_pool.put(conv);
+            self.sink_pool.put(other_conv);
+            Ok(match b.op {
+                ast::OpVar::Gt
+                | ast::OpVar::Gte
+                | ast::OpVar::Lt
+                | ast::OpVar::Lte
+                | ast::OpVar::Eq
+                | ast::OpVar::Neq => Self::int_type(1),
+                _ => typ,
+            })
         } else {
             // Normal expressions
-            let mut lhs_op = self.sink_pool.get();
-
-            let lhs = self.gen_expr(b.lhs.cp(), &mut lhs_op, scope.cp())?;
-
-            let mut rhs_op = self.sink_pool.get();
-            let rhs = self.gen_expr(b.rhs.cp(), &mut rhs_op, scope.cp())?;
+            // * Both operands go straight into `inst`; only the implicit
+            // * conversions are collected separately. Moving whole operands
+            // * around would copy deeply nested expressions once per level.
+            // * Commutative operators may run their deeper side first, which
+            // * keeps the stack shallower. Types are still unified in source
+            // * order.
+            let swap = self.scheduler.swap(b);
+            let (first, second) = if swap.is_some() {
+                (&b.rhs, &b.lhs)
+            } else {
+                (&b.lhs, &b.rhs)
+            };
+            let first_ty = self.gen_expr(first.cp(), inst, scope.cp())?;
+            let first_end = inst.len();
+            let second_ty = self.gen_expr(second.cp(), inst, scope.cp())?;
+            let (lhs, rhs) = if swap.is_some() {
+                (second_ty, first_ty)
+            } else {
+                (first_ty, second_ty)
+            };
 
-            let typ = flatten_ty(lhs, &mut lhs_op, rhs, &mut rhs_op)?;
+            let mut lhs_conv = self.sink_pool.get();
+            let mut rhs_conv = self.sink_pool.get();
+            let typ = flatten_ty(
+                lhs.cp(),
+                &mut lhs_conv,
+                rhs.cp(),
+                &mut rhs_conv,
+                &self.target,
+            )?;
+            tracing::debug!("Unify {:?} and {:?} into {:?}", lhs, rhs, typ);
 
-            inst.append_all(&mut lhs_op);
-            inst.append_all(&mut rhs_op);
+            if swap.is_some() {
+                inst.insert_all(first_end, &mut rhs_conv);
+                inst.append_all(&mut lhs_conv);
+            } else {
+                inst.insert_all(first_end, &mut lhs_conv);
+                inst.append_all(&mut rhs_conv);
+            }
 
-            b.op.inst(inst, typ.cp())?;
+            self.gen_op(swap.unwrap_or(b.op), typ.cp(), inst)?;
 
-            self.sink_pool.put(lhs_op);
-            self.sink_pool.put(rhs_op);
+            self.sink_pool.put(lhs_conv);
+            self.sink_pool.put(rhs_conv);
 
             match b.op {
                 ast::OpVar::Gt
@@ -1053,30 +2351,201 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
         }
     }
 
+    /// Whether both sides of `b` are the same pure call, worth computing only
+    /// once. Values of one or two slots can be copied.
+    fn is_common(&self, b: &ast::BinaryOp) -> bool {
+        use ast::OpVar::*;
+        let arith = matches!(
+            b.op,
+            Add | Sub | Mul | Div | Xor | Ban | Bor | Gt | Lt | Eq | Gte | Lte | Neq
+        );
+        let lhs = b.lhs.borrow();
+        // * Sides of different shapes differ at their roots, so comparing
+        // * them first keeps long chains of operators linear
+        arith
+            && self.passes.contains(&Pass::Cse)
+            && lhs.spanless_eq(&b.rhs.borrow())
+            && has_call(&lhs)
+            && self.data.purity.is_pure_expr(&lhs)
+    }
+
+    /// Assign the value of `b.rhs` to `b.lhs`. With `value`, the value
+    /// assigned is left on the stack afterwards, as the value of `b`;
+    /// otherwise `b` has type void.
+    fn gen_assign(
+        &mut self,
+        b: &ast::BinaryOp,
+        value: bool,
+        inst: &mut InstSink,
+        scope: Ptr<ast::Scope>,
+    ) -> CompileResult<Type> {
+        // * A constant without a slot has nothing to store when declared
+        if let ast::ExprVariant::Ident(i) = &b.lhs.borrow().var {
+            if self.folded(i, &scope).is_some() {
+                return match b.op {
+                    ast::OpVar::_Csn => Ok(Ptr::new(ast::TypeDef::Unit)),
+                    _ => Err(compile_err_n(CompileErrorVar::AssignConst)),
+                };
+            }
+        }
+        // * This generates address for lhs.
+        let (lhs, constance) = self.gen_l_value_address_and_const(b.lhs.cp(), inst, scope.cp())?;
+
+        if constance && b.op != ast::OpVar::_Csn {
+            return Err(compile_err_n(CompileErrorVar::AssignConst));
+        }
+
+        self.check_literal(&b.rhs.borrow(), &lhs)?;
+        let rhs = match self.gen_const_init(b, inst, scope.cp()) {
+            Some(rhs) => rhs,
+            None => self.gen_expr(b.rhs.cp(), inst, scope.cp())?,
+        };
+
+        conv(rhs, lhs.cp(), &self.target, inst)?;
+
+        // store lhs
+        store(lhs.cp(), &self.target, inst)?;
+        self.gen_init(&b.lhs.borrow(), inst, &scope);
+
+        if !value {
+            return Ok(Ptr::new(ast::TypeDef::Unit));
+        }
+        // * O0 can't keep a copy of the value under the address it is stored
+        // * to, so it is loaded back. Only variables are assigned to, so
+        // * finding the address again has no side effects.
+        self.gen_l_value_address(b.lhs.cp(), inst, scope)?;
+        load(lhs.cp(), &self.target, inst)?;
+        Ok(lhs)
+    }
+
+    /// Generate the initializer of a global that `b` assigns, if it is not
+    /// a literal, as the value it evaluates to, so start code doesn't
+    /// compute it or run the calls in it. Does nothing and returns `None` if
+    /// it is not constant.
+    fn gen_const_init(
+        &mut self,
+        b: &ast::BinaryOp,
+        inst: &mut InstSink,
+        scope: Ptr<ast::Scope>,
+    ) -> Option<Type> {
+        let expr = b.rhs.borrow();
+        if self.f.scope.borrow().id != 0 || matches!(expr.var, ast::ExprVariant::Literal(_)) {
+            return None;
+        }
+        let val = match consteval::eval(&expr, &scope) {
+            Ok(val) => val,
+            Err(_) => match &b.lhs.borrow().var {
+                ast::ExprVariant::Ident(i) => self.data.initial.get(&i.name.to_string())?.clone(),
+                _ => return None,
+            },
+        };
+        self.gen_value(&val, inst)
+    }
+
+    /// Push `val`, returning its type, or return `None` if it is not a
+    /// number
+    fn gen_value(&mut self, val: &Value, inst: &mut InstSink) -> Option<Type> {
+        let typ = match *val {
+            Value::Int(val) => {
+                inst.push(Inst::IPush(val));
+                Self::int_type(4)
+            }
+            Value::UInt(val) => {
+                inst.push(Inst::IPush(val as i32));
+                Self::uint_type(4)
+            }
+            Value::Bool(val) => {
+                inst.push(Inst::IPush(val as i32));
+                Self::int_type(1)
+            }
+            Value::Char(val) => {
+                inst.push(Inst::IPush(val as i32));
+                Self::uint_type(1)
+            }
+            Value::Double(val) => self.gen_double(val, inst),
+            _ => return None,
+        };
+        Some(typ)
+    }
+
     fn gen_una_op(
         &mut self,
         u: &ast::UnaryOp,
         inst: &mut InstSink,
         scope: Ptr<ast::Scope>,
     ) -> CompileResult<Type> {
+        match u.op {
+            ast::OpVar::Ref => {
+                // * What is written through the reference is not tracked
+                self.gen_init(&u.val.borrow(), inst, &scope);
+                let typ = self.gen_l_value_address(u.val.cp(), inst, scope)?;
+                return Ok(Self::ref_type(typ));
+            }
+            ast::OpVar::Der => {
+                let typ = self.gen_deref_address(u, inst, scope)?;
+                load(typ.cp(), &self.target, inst)?;
+                return Ok(typ);
+            }
+            _ => (),
+        }
+
         // Calculate expression body
         // self.inst.push(self.sink_pool.get());
         let lhs = self.gen_expr(u.val.cp(), inst, scope.cp())?;
         // let mut lhs_op = self.inst.pop().unwrap();
 
-        u.op.inst(inst, lhs.cp())?;
+        self.gen_op(u.op, lhs.cp(), inst)?;
 
         Ok(lhs)
     }
 
+    /// Generate the reference `*u` goes through, returning the type of
+    /// what it refers to
+    fn gen_deref_address(
+        &mut self,
+        u: &ast::UnaryOp,
+        inst: &mut InstSink,
+        scope: Ptr<ast::Scope>,
+    ) -> CompileResult<Type> {
+        let typ = self.gen_expr(u.val.cp(), inst, scope)?;
+        if self.ub_checks {
+            self.gen_check("__ub_null", inst);
+        }
+        let target = match &*typ.borrow() {
+            ast::TypeDef::Ref(r) => r.target.cp(),
+            typ => Err(CompileErrorVar::NotAReference(format!("{:?}", typ)))?,
+        };
+        Ok(target)
+    }
+
+    /// Type and value of `i`, if it is a global constant without a slot
+    fn folded(&self, i: &ast::Identifier, scope: &Ptr<ast::Scope>) -> Option<(Type, Value)> {
+        match scope.borrow().find_def_depth(i.name)? {
+            (_, 0) => self.data.folded.get(&i.name.to_string()).cloned(),
+            _ => None,
+        }
+    }
+
     fn gen_ident_expr(
         &mut self,
         i: &ast::Identifier,
         inst: &mut InstSink,
         scope: Ptr<ast::Scope>,
     ) -> CompileResult<Type> {
+        let shadow = match self.ub_checks {
+            true => self.shadow_of(&i.name, &scope),
+            false => None,
+        };
+        if let Some(shadow) = shadow {
+            inst.push_many(&[Inst::LoadA(0, shadow), Inst::ILoad]);
+            self.gen_check("__ub_uninit", inst);
+        }
+        if let Some((typ, val)) = self.folded(i, &scope) {
+            self.gen_value(&val, inst);
+            return Ok(typ);
+        }
         let typ = self.gen_ident_address_and_const(i, inst, scope)?.0;
-        load(typ.cp(), inst)?;
+        load(typ.cp(), &self.target, inst)?;
         Ok(typ)
     }
 
@@ -1086,20 +2555,17 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
         inst: &mut InstSink,
         scope: Ptr<ast::Scope>,
     ) -> CompileResult<Type> {
-        let func = &f.func;
-        let func_entry = self
-            .data
-            .fns
-            .get_full(func)
-            .ok_or_else(|| CompileErrorVar::NonExistFunc("Function does not exist".into()))?;
+        let func = &f.func.to_string();
+        let func_entry = (self.data.fns.get(func))
+            .or_else(|| self.data.host_fns.get(func))
+            .ok_or_else(|| CompileErrorVar::NonExistFunc(func.clone()))?;
 
-        let params = &func_entry.2.params;
+        let params = &func_entry.params;
 
         if f.params.len() != params.len() {
             return Err(CompileErrorVar::ParamLengthMismatch.into());
         }
-        let f_idx = func_entry.0 as u16;
-        let f_ret_typ = func_entry.2.return_type.cp();
+        let f_ret_typ = func_entry.return_type.cp();
 
         // Push each param into stack
         // * Rust complains about lifetimes here, so we'll just move everything into
@@ -1110,16 +2576,157 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
             .zip(params.iter().map(|param| param.cp()))
             .collect();
 
+        if let Some(body) = self.inline_body(func, &scope) {
+            return self.gen_inline(func, body, params_pair_iter, inst, scope);
+        }
+
         for param in params_pair_iter {
+            self.check_literal(&param.0.borrow(), &param.1)?;
             let res = self.gen_expr(param.0.cp(), inst, scope.cp())?;
-            conv(res, param.1.cp(), inst)?;
+            conv(res, param.1.cp(), &self.target, inst)?;
         }
 
-        inst.push(Inst::Call(f_idx));
+        inst.push(Inst::_Call(self.data.relocator.symbol(func)));
 
         Ok(f_ret_typ)
     }
 
+    /// What calls to `func` are replaced by, if they are to be inlined. Only
+    /// functions doing nothing but return a small expression, and called
+    /// often in the profile, are inlined.
+    fn inline_body(&self, func: &str, scope: &Ptr<ast::Scope>) -> Option<InlineBody> {
+        let profile = self.data.profile.as_ref()?;
+        if !self.passes.contains(&Pass::Inline) || self.f.scope.borrow().id == 0 {
+            return None;
+        }
+        let body = self.inlinable(func, scope, profile);
+        let (kind, message) = match &body {
+            Ok(_) => (
+                RemarkKind::Applied,
+                format!("call to `{}` inlined into `{}`", func, self.name),
+            ),
+            Err(why) => (
+                RemarkKind::Missed,
+                format!("call to `{}` not inlined: {}", func, why),
+            ),
+        };
+        self.remark(Pass::Inline, kind, current_span(), message);
+        body.ok()
+    }
+
+    /// The body of `func` to inline, or why calls to it are not inlined
+    fn inlinable(
+        &self,
+        func: &str,
+        scope: &Ptr<ast::Scope>,
+        profile: &ExecProfile,
+    ) -> Result<InlineBody, String> {
+        if func == self.name || self.inlining.iter().any(|f| f == func) {
+            return Err("callee is recursive".into());
+        }
+        if profile.calls(self.name) == 0 {
+            return Err("caller never ran in the profile".into());
+        }
+        let calls = profile.calls(func);
+        if calls < INLINE_CALLS {
+            return Err(format!(
+                "callee called {} times in the profile, fewer than {}",
+                calls, INLINE_CALLS
+            ));
+        }
+        let def = (scope.borrow().find_def(func)).ok_or("callee is not declared")?;
+        let def = def.borrow();
+        let typ = match &*def {
+            ast::SymbolDef::Var { typ, .. } => typ.cp(),
+            _ => return Err("callee is not a function".into()),
+        };
+        let typ = typ.borrow();
+        let (body, params) = match &*typ {
+            ast::TypeDef::Function(f) if !f.is_extern => match &f.body {
+                Some(body) => (body, f.params.len()),
+                None => return Err("callee has no body".into()),
+            },
+            _ => return Err("callee is a function of the host".into()),
+        };
+        let expr = match &body.stmts[..] {
+            [stmt @ ast::Stmt {
+                var: ast::StmtVariant::Return(Some(e)),
+                ..
+            }] => match unroll::size(stmt, &body.scope) {
+                size if size <= INLINE_SIZE => e.cp(),
+                size => {
+                    return Err(format!(
+                        "callee too large, with {} statements and expressions, more than {}",
+                        size, INLINE_SIZE
+                    ))
+                }
+            },
+            _ => return Err("callee does more than return a value".into()),
+        };
+        let body_scope = body.scope.borrow();
+        // * Parameters are all the body declares, and live in slots
+        if body_scope.defs.len() != params {
+            return Err("callee declares variables".into());
+        }
+        if (body_scope.defs.keys())
+            .any(|n| self.data.boxed.contains(&(body_scope.id, n.to_string())))
+        {
+            return Err("callee takes the address of a parameter".into());
+        }
+        Ok(InlineBody {
+            expr,
+            scope: body.scope.cp(),
+            params: body_scope.defs.keys().map(|n| n.to_string()).collect(),
+        })
+    }
+
+    /// Note what `pass` did at `span`, if remarks of it are asked for
+    fn remark(&self, pass: Pass, kind: RemarkKind, span: Option<Span>, message: String) {
+        self.data.remarks.add(Remark {
+            pass,
+            kind,
+            func: self.name.into(),
+            span,
+            message,
+        });
+    }
+
+    /// Compute the arguments of a call to `func` into slots for its
+    /// parameters, then the expression its body returns
+    fn gen_inline(
+        &mut self,
+        func: &str,
+        body: InlineBody,
+        args: Vec<(&Ptr<ast::Expr>, Type)>,
+        inst: &mut InstSink,
+        scope: Ptr<ast::Scope>,
+    ) -> CompileResult<Type> {
+        tracing::debug!("Inlining a call to {} into {}", func, self.name);
+        let ret = self.data.fns[func].return_type.cp();
+        let id = body.scope.borrow().id;
+        self.loc.dive_into_scope();
+        // * An argument calling the same function again calls it for real,
+        // * so its parameters stay in these slots
+        self.inlining.push(func.into());
+        for (name, (arg, typ)) in body.params.iter().zip(args) {
+            let var_name = format!("{}`{}", name, id);
+            let slots = (self.target.slots_of(&typ.borrow()))
+                .ok_or_else(|| CompileErrorVar::RequireSized(format!("{:?}", typ)))?;
+            self.loc.add_var(&var_name, slots, false, typ.cp())?;
+            let offset = self.loc.get_var(&var_name).unwrap().offset as i32;
+            self.check_literal(&arg.borrow(), &typ)?;
+            inst.push(Inst::LoadA(0, offset));
+            let res = self.gen_expr(arg.cp(), inst, scope.cp())?;
+            conv(res, typ.cp(), &self.target, inst)?;
+            store(typ, &self.target, inst)?;
+        }
+        let res = self.gen_expr(body.expr, inst, body.scope);
+        self.inlining.pop();
+        self.loc.pop_scope();
+        conv(res?, ret.cp(), &self.target, inst)?;
+        Ok(ret)
+    }
+
     fn uint_type(bytes: usize) -> Type {
         Ptr::new(ast::TypeDef::Primitive(ast::PrimitiveType {
             var: ast::PrimitiveTypeVar::UnsignedInt,
@@ -1149,7 +2756,7 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
         &mut self,
         lit: &ast::Literal,
         inst: &mut InstSink,
-        scope: Ptr<ast::Scope>,
+        _scope: Ptr<ast::Scope>,
     ) -> CompileResult<Type> {
         match lit {
             ast::Literal::Boolean { val } => {
@@ -1159,8 +2766,7 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
             }
 
             ast::Literal::Integer { val } => {
-                let val: i32 = val.try_into().map_err(|_| CompileErrorVar::IntOverflow)?;
-                inst.push(Inst::IPush(val));
+                inst.push(Inst::IPush(self.int_const(val)?));
 
                 let typ = Self::int_type(4);
                 Ok(typ)
@@ -1174,28 +2780,7 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
                 Ok(typ)
             }
 
-            ast::Literal::Float { val } => {
-                let typ = Self::float_type(8);
-
-                let val: f64 = val.to_f64();
-                let idx = self
-                    .data
-                    .consts
-                    .put_data(
-                        &format!("`{}``double{}", self.name, self.data_cnt),
-                        Data {
-                            typ: typ.cp(),
-                            init_val: Either::Left(Constant::Float(val)),
-                            is_const: true,
-                        },
-                    )
-                    .expect("Unable to add double data");
-                inst.push(Inst::LoadC(idx));
-                self.data_cnt += 1;
-
-                // let val = self.builder.ins().f64const(val.to_f64());
-                Ok(typ)
-            }
+            ast::Literal::Float { val } => Ok(self.gen_double(num::to_f64(val), inst)),
 
             ast::Literal::String { val } => {
                 let offset = self
@@ -1219,6 +2804,62 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
         }
     }
 
+    /// Push `val`, kept among the constants
+    fn gen_double(&mut self, val: f64, inst: &mut InstSink) -> Type {
+        let typ = Self::float_type(8);
+        let idx = self
+            .data
+            .consts
+            .put_data(
+                &format!("`{}``double{}", self.name, self.data_cnt),
+                Data {
+                    typ: typ.cp(),
+                    init_val: Either::Left(Constant::Float(val)),
+                    is_const: true,
+                },
+            )
+            .expect("Unable to add double data");
+        inst.push(Inst::LoadC(idx));
+        self.data_cnt += 1;
+        typ
+    }
+
+    /// `val` as an `int`, wrapped around if it doesn't fit and overflow is
+    /// allowed
+    fn int_const(&self, val: &num::Int) -> CompileResult<i32> {
+        match num::to_i32(val) {
+            Some(val) => Ok(val),
+            None if self.allow_overflow => Ok(num::wrap_i32(val)),
+            None => Err(CompileErrorVar::IntOverflow.into()),
+        }
+    }
+
+    /// Fail if `expr` is an integer literal that `to` cannot hold, unless
+    /// overflow is allowed. Converting it would silently change its value.
+    fn check_literal(&self, expr: &ast::Expr, to: &Type) -> CompileResult<()> {
+        let val = match int_literal(expr) {
+            Some(val) if !self.allow_overflow => val,
+            _ => return Ok(()),
+        };
+        let (name, min, max) = match &*to.borrow() {
+            ast::TypeDef::Primitive(p) => match p.var {
+                ast::PrimitiveTypeVar::SignedInt => ("int", i32::MIN as i64, i32::MAX as i64),
+                ast::PrimitiveTypeVar::UnsignedInt if p.occupy_bytes == 1 => {
+                    ("char", 0, u8::MAX as i64)
+                }
+                _ => return Ok(()),
+            },
+            _ => return Ok(()),
+        };
+        if num::to_i64(&val).is_some_and(|v| (min..=max).contains(&v)) {
+            return Ok(());
+        }
+        Err(compile_err(
+            CompileErrorVar::LiteralOutOfRange(val.to_string(), name.into(), min, max),
+            Some(expr.span),
+        ))
+    }
+
     fn gen_ty_conversion(
         &mut self,
         i: &ast::TypeConversion,
@@ -1227,11 +2868,11 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
     ) -> CompileResult<Type> {
         let expr = i.expr.cp();
         let ty = i.to.cp();
-        let ty = Ptr::new(resolve_ty(&*ty.borrow(), scope.cp()));
+        let ty = Ptr::new(resolve_ty(&ty.borrow(), scope.cp()));
 
         let expr_ty = self.gen_expr(expr, inst, scope)?;
 
-        conv(expr_ty, ty, inst)
+        conv(expr_ty, ty, &self.target, inst)
     }
 
     fn gen_if(
@@ -1240,42 +2881,36 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
         bb: BB,
         scope: Ptr<ast::Scope>,
     ) -> CompileResult<BB> {
-        {
-            // Condition
-            let cond = i.cond.cp();
-            let inst = &mut bb.borrow_mut().inst;
-            let cond_ty = self.gen_expr(cond, inst, scope.cp())?;
-            conv(cond_ty, Self::int_type(1), inst)?;
-        }
-        // * True branch
-        let (true_bb_id, true_bb) = self.new_bb();
-        let true_bb = self.gen_stmt(&*i.if_block.borrow(), true_bb, scope.cp())?;
-
-        if let Some(else_br) = &i.else_block {
-            let (else_bb_id, else_bb) = self.new_bb();
-            let else_bb = self.gen_stmt(&*else_br.borrow(), else_bb, scope.cp())?;
-
-            let (final_bb_id, final_bb) = self.new_bb();
+        let (final_bb_id, final_bb) = self.new_bb();
 
-            bb.borrow_mut().end = BlockEndJump::Conditional {
-                z: else_bb_id,
-                nz: true_bb_id,
-            };
+        // * `else if` arms are flattened by the parser, so walk them in a loop
+        // * instead of recursing once per arm.
+        let arms = std::iter::once((&i.cond, &i.if_block))
+            .chain(i.else_ifs.iter().map(|(cond, block)| (cond, block)));
+        let mut cond_bb = bb;
+        for (cond, block) in arms {
+            // Condition
+            let mut exits = vec![];
+            self.gen_branch(cond.cp(), cond_bb, (None, None), &mut exits, scope.cp())?;
+            // * True branch
+            let (true_bb_id, true_bb) = self.new_bb();
+            let true_bb = self.gen_stmt(&block.borrow(), true_bb, scope.cp())?;
             true_bb.borrow_mut().end = BlockEndJump::Unconditional(final_bb_id);
-            else_bb.borrow_mut().end = BlockEndJump::Unconditional(final_bb_id);
 
-            Ok(final_bb)
-        } else {
-            let (final_bb_id, final_bb) = self.new_bb();
+            // * False branch falls into the next arm
+            let (next_bb_id, next_bb) = self.new_bb();
+            Self::end_cond(&exits, true_bb_id, next_bb_id);
+            cond_bb = next_bb;
+        }
 
-            bb.borrow_mut().end = BlockEndJump::Conditional {
-                z: final_bb_id,
-                nz: true_bb_id,
-            };
-            true_bb.borrow_mut().end = BlockEndJump::Unconditional(final_bb_id);
+        // * Every condition failed
+        let else_bb = match &i.else_block {
+            Some(else_br) => self.gen_stmt(&else_br.borrow(), cond_bb, scope.cp())?,
+            None => cond_bb,
+        };
+        else_bb.borrow_mut().end = BlockEndJump::Unconditional(final_bb_id);
 
-            Ok(final_bb)
-        }
+        Ok(final_bb)
     }
 
     fn gen_while(
@@ -1284,43 +2919,72 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
         bb: BB,
         scope: Ptr<ast::Scope>,
     ) -> CompileResult<BB> {
-        {
-            // Condition
-            let cond = i.cond.cp();
-            let inst = &mut bb.borrow_mut().inst;
-            let cond_ty = self.gen_expr(cond, inst, scope.cp())?;
-            conv(cond_ty, Self::int_type(1), inst)?;
-        }
+        let unrolled = self.unrolled.get(&(i as *const ast::WhileConditional));
+        let mut entry_exits = vec![];
+        let copies = match unrolled.copied() {
+            Some(Unrolled::Full(copies)) => {
+                let mut bb = bb;
+                for _ in 0..copies {
+                    bb = self.gen_stmt(&i.block.borrow(), bb, scope.cp())?;
+                }
+                return Ok(bb);
+            }
+            // * The loop runs at least once, so it is entered without a check
+            Some(Unrolled::Partial(copies)) => copies,
+            None => {
+                // Condition
+                self.gen_branch(
+                    i.cond.cp(),
+                    bb.cp(),
+                    (None, None),
+                    &mut entry_exits,
+                    scope.cp(),
+                )?;
+                1
+            }
+        };
         let (while_bb_id, while_bb) = self.new_bb();
         let (final_bb_id, final_bb) = self.new_bb();
-        self.break_tgt.push(final_bb_id);
-        let while_bb = self.gen_stmt(&*i.block.borrow(), while_bb, scope.cp())?;
-        {
-            // Condition
-            let cond = i.cond.cp();
-            let inst = &mut while_bb.borrow_mut().inst;
-            let cond_ty = self.gen_expr(cond, inst, scope.cp())?;
-            conv(cond_ty, Self::int_type(1), inst)?;
+        self.break_tgt
+            .push((i.label.as_ref().map(|l| l.name.to_string()), final_bb_id));
+        let mut while_bb = while_bb;
+        for _ in 0..copies {
+            while_bb = self.gen_stmt(&i.block.borrow(), while_bb, scope.cp())?;
         }
+        // Condition
+        let mut exits = vec![];
+        self.gen_branch(i.cond.cp(), while_bb, (None, None), &mut exits, scope.cp())?;
         self.break_tgt.pop();
-        {
-            bb.borrow_mut().end = BlockEndJump::Conditional {
-                z: final_bb_id,
-                nz: while_bb_id,
-            };
-            while_bb.borrow_mut().end = BlockEndJump::Conditional {
-                z: final_bb_id,
-                nz: while_bb_id,
-            };
+        match copies {
+            1 => Self::end_cond(&entry_exits, while_bb_id, final_bb_id),
+            _ => bb.borrow_mut().end = BlockEndJump::Unconditional(while_bb_id),
         }
+        Self::end_cond(&exits, while_bb_id, final_bb_id);
         Ok(final_bb)
     }
 
-    fn gen_break(&mut self, bb: BB, _: Ptr<ast::Scope>) -> CompileResult<BB> {
-        let break_tgt = *self
-            .break_tgt
-            .last()
-            .ok_or(CompileErrorVar::NoTargetToBreak)?;
+    fn gen_break(
+        &mut self,
+        label: Option<&ast::Identifier>,
+        bb: BB,
+        _: Ptr<ast::Scope>,
+    ) -> CompileResult<BB> {
+        let break_tgt = match label {
+            None => {
+                self.break_tgt
+                    .last()
+                    .ok_or(CompileErrorVar::NoTargetToBreak)?
+                    .1
+            }
+            Some(label) => {
+                self.break_tgt
+                    .iter()
+                    .rev()
+                    .find(|(l, _)| l.as_deref() == Some(label.name.as_str()))
+                    .ok_or_else(|| CompileErrorVar::NoLoopLabel(label.name.to_string()))?
+                    .1
+            }
+        };
         let (_, dummy_bb) = self.new_bb();
         bb.borrow_mut().end = BlockEndJump::Unconditional(break_tgt);
         Ok(dummy_bb)
@@ -1355,6 +3019,9 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
                     &*typ.borrow()
                 )))?,
             }
+            if let Some(shadow) = self.shadow_of(&scan.name, &scope) {
+                inst.push_many(&[Inst::LoadA(0, shadow), Inst::IPush(1), Inst::IStore]);
+            }
         }
         Ok(bb)
     }
@@ -1423,9 +3090,10 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
             let mut bb = bb.borrow_mut();
             let inst = &mut bb.inst;
 
+            self.check_literal(&e.borrow(), &self.ret_type)?;
             let expr_typ = self.gen_expr(e.cp(), inst, scope.cp())?;
-            let typ = conv(expr_typ, self.ret_type.cp(), inst)?;
-            ret(typ, inst)?;
+            let typ = conv(expr_typ, self.ret_type.cp(), &self.target, inst)?;
+            ret(typ, &self.target, inst)?;
             bb.end = BlockEndJump::Return;
 
             let (_, dummy_bb) = self.new_bb();
@@ -1455,10 +3123,7 @@ impl ast::OpVar {
         use Inst::*;
 
         let emit_double_inst = match &*typ.borrow() {
-            ast::TypeDef::Primitive(p) => match p.var {
-                ast::PrimitiveTypeVar::Float => true,
-                _ => false,
-            },
+            ast::TypeDef::Primitive(p) => matches!(p.var, ast::PrimitiveTypeVar::Float),
             _ => false,
         };
 
@@ -1500,7 +3165,7 @@ impl ast::OpVar {
                     "Assign operators should be spotted early".into(),
                 ))?,
 
-                Ina | Inb | Dea | Deb | _ => Err(CompileErrorVar::UnsupportedOp)?,
+                _ => Err(CompileErrorVar::UnsupportedOp)?,
             }
         } else {
             // Double instructions
@@ -1511,12 +3176,21 @@ impl ast::OpVar {
                 Mul => sink.push(DMul),
                 Div => sink.push(DDiv),
 
-                Eq => sink.push_many(&[DCmp, IPush(2), ISub]),
+                /*
+                 * DCmp gives 2 for a NaN, which only Neq accepts:
+                 *
+                 * Eq: sign is 0
+                 * Gt: compared to 1 is 0
+                 * Lt: plus 1, sign, compared to 1 is not 0
+                 * Gte: compared to its square is 0, so it is 0 or 1
+                 * Lte: sign is not 1
+                 */
+                Eq => sink.push_many(&[DCmp, IPush(0), ICmp, Dup, IMul, IPush(1), ICmp]),
                 Neq => sink.push_many(&[DCmp]),
-                Gt => sink.push_many(&[DCmp, IPush(1), ISub, IPush(0), ICmp, IPush(-1), ICmp]),
+                Gt => sink.push_many(&[DCmp, IPush(1), ICmp, Dup, IMul, IPush(1), ICmp]),
                 Lt => sink.push_many(&[DCmp, IPush(1), IAdd, IPush(0), ICmp, IPush(1), ICmp]),
-                Gte => sink.push_many(&[DCmp, IPush(1), IAdd]),
-                Lte => sink.push_many(&[DCmp, IPush(1), ISub]),
+                Gte => sink.push_many(&[DCmp, Dup, Dup, IMul, ICmp, IPush(1), IAdd]),
+                Lte => sink.push_many(&[DCmp, IPush(0), ICmp, IPush(1), ICmp]),
 
                 Neg => sink.push(DNeg),
                 Pos => (),
@@ -1528,31 +3202,9 @@ impl ast::OpVar {
                     "Assign operators should be spotted early".into(),
                 ))?,
 
-                Ina | Inb | Dea | Deb | _ => Err(CompileErrorVar::UnsupportedOp)?,
+                _ => Err(CompileErrorVar::UnsupportedOp)?,
             }
         }
         Ok(())
     }
 }
-
-impl ast::TypeDef {
-    /// Calculate the bytes one type occupy
-    ///
-    /// We don't have Sized trait, but we can still calculate the bytes types occupy
-    pub fn occupy_slots(&self) -> Option<u32> {
-        match self {
-            ast::TypeDef::Unit => Some(0),
-            ast::TypeDef::Ref(..) => Some(1),
-            ast::TypeDef::Array(a) => a.length.and_then(|l| {
-                (a.target
-                    .borrow()
-                    .occupy_slots()
-                    .map(|s| (s * l as u32) as u32))
-            }),
-            ast::TypeDef::Function(..) => None,
-            ast::TypeDef::NamedType(..) => None,
-            ast::TypeDef::Primitive(p) => Some(((p.occupy_bytes + 3) / 4) as u32),
-            _ => None,
-        }
-    }
-}
@@ -0,0 +1,114 @@
+//! Mapping source lines to instructions, and instruction counts back to
+//! line coverage.
+
+use super::vm::Coverage;
+use super::{Constant, O0};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Which instructions each source line compiled into
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct CoverageMap {
+    /// Runs of instructions from the same line, in code order
+    pub ranges: Vec<CodeRange>,
+}
+
+/// Instructions `start..end` of a function, all compiled from one line
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct CodeRange {
+    /// Index of the function, or `None` for start code
+    pub function: Option<usize>,
+    /// Name of the function, or `.start` for start code
+    pub name: String,
+    pub start: usize,
+    pub end: usize,
+    /// Source line, counted from 1
+    pub line: u32,
+}
+
+impl CoverageMap {
+    /// Map the code of `o0` to lines, or `None` if it has no debug info
+    pub fn new(o0: &O0) -> Option<CoverageMap> {
+        let debug = o0.debug.as_ref()?;
+        let mut ranges = vec![];
+        let code = std::iter::once((None, ".start".to_string(), &debug.start_lines)).chain(
+            (debug.fn_lines.iter().enumerate()).map(|(idx, lines)| {
+                let name = o0.functions.get(idx).and_then(|f| {
+                    match o0.constants.get(f.name_idx as usize) {
+                        Some(Constant::String(s)) => Some(String::from_utf8_lossy(s).into_owned()),
+                        _ => None,
+                    }
+                });
+                (
+                    Some(idx),
+                    name.unwrap_or_else(|| format!(".F{}", idx)),
+                    lines,
+                )
+            }),
+        );
+        for (function, name, lines) in code {
+            let mut last: Option<CodeRange> = None;
+            for (idx, line) in lines.iter().enumerate() {
+                match (&mut last, line) {
+                    (Some(r), Some(line)) if r.line == line + 1 && r.end == idx => r.end += 1,
+                    (_, Some(line)) => {
+                        ranges.extend(last.take());
+                        last = Some(CodeRange {
+                            function,
+                            name: name.clone(),
+                            start: idx,
+                            end: idx + 1,
+                            line: line + 1,
+                        });
+                    }
+                    (_, None) => ranges.extend(last.take()),
+                }
+            }
+            ranges.extend(last);
+        }
+        Some(CoverageMap { ranges })
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Maps are always valid JSON")
+    }
+
+    /// How many times each line with code ran, counting a line as run as
+    /// often as its most run instruction
+    pub fn line_counts(&self, coverage: &Coverage) -> BTreeMap<u32, u64> {
+        let mut lines = BTreeMap::new();
+        for r in &self.ranges {
+            let counts = coverage.counts(r.function);
+            let hits = (counts.get(r.start..r.end))
+                .and_then(|c| c.iter().max().copied())
+                .unwrap_or(0);
+            let line = lines.entry(r.line).or_insert(0);
+            *line = hits.max(*line);
+        }
+        lines
+    }
+}
+
+/// Annotate each line of `src` with how many times it ran: `-` for lines
+/// without code, and `#####` for lines that never ran
+pub fn render_coverage(src: &str, counts: &BTreeMap<u32, u64>) -> String {
+    let mut out = String::new();
+    for (idx, line) in src.lines().enumerate() {
+        let count = match counts.get(&(idx as u32 + 1)) {
+            Some(0) => "#####".to_string(),
+            Some(n) => n.to_string(),
+            None => "-".to_string(),
+        };
+        out += &format!("{:>9}:{:>5}:{}\n", count, idx + 1, line);
+    }
+
+    let run = counts.values().filter(|c| **c > 0).count();
+    let total = counts.len();
+    let percent = if total == 0 {
+        100.0
+    } else {
+        run as f64 * 100.0 / total as f64
+    };
+    out += &format!("\nLines executed: {:.2}% of {}\n", percent, total);
+    out
+}
@@ -0,0 +1,254 @@
+//! Computing the same pure call only once across statements.
+//!
+//! A pure function gives the same result for the same arguments as long as
+//! nothing it reads is written in between. In statements running one after
+//! another, with no branch or loop between them, a pure call made again can
+//! take the result of the first one, kept in a local slot the source does
+//! not name.
+//!
+//! Whether a statement writes something a call reads is up to
+//! [alias analysis](crate::c0::alias): `*p = 1` only ends the reuse of calls
+//! reading a variable `p` may refer to. Pure functions may read any global,
+//! so a write that may be to a global ends the reuse of every call. Writes
+//! through references nothing is known of, and calls to functions that are
+//! not pure, end the reuse of everything.
+
+use crate::c0::alias::{Aliases, Place};
+use crate::c0::ast::{self, ExprVariant, OpVar, Scope, SpanlessEq};
+use crate::c0::purity::Purity;
+use crate::prelude::*;
+use std::collections::HashMap;
+
+/// What to do at a pure call
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(super) enum Reuse {
+    /// Keep the result in this temporary, for calls after it
+    Save(usize),
+    /// Take the result from this temporary instead of calling again
+    Load(usize),
+}
+
+/// Calls to compute only once in a block
+#[derive(Debug, Default)]
+pub(super) struct Plan {
+    pub reuse: HashMap<*const ast::Expr, Reuse>,
+    /// The function each temporary keeps the result of
+    pub temps: Vec<String>,
+}
+
+/// What a statement may write
+#[derive(Debug, Clone)]
+pub(super) enum Write {
+    Place(Place),
+    /// Anything at all
+    All,
+}
+
+/// A call whose result is around to take
+struct Available {
+    expr: Ptr<ast::Expr>,
+    /// Variables and memory it reads
+    reads: Vec<Place>,
+    /// Whether it reads through references nothing is known of
+    reads_any: bool,
+    temp: Option<usize>,
+}
+
+/// Plan which pure calls in the statements of `block` to compute once
+pub(super) fn plan(block: &ast::Block, purity: &Purity, aliases: &Aliases) -> Plan {
+    let mut plan = Plan::default();
+    let mut available: Vec<Available> = vec![];
+    let scope = &block.scope;
+    for stmt in &block.stmts {
+        let exprs: Vec<&Ptr<ast::Expr>> = match &stmt.var {
+            ast::StmtVariant::Expr(e) | ast::StmtVariant::Return(Some(e)) => vec![e],
+            ast::StmtVariant::ManyExpr(es) | ast::StmtVariant::Print(es) => es.iter().collect(),
+            ast::StmtVariant::Scan(name, _) => {
+                let write = Write::Place(Place::Var(key(&name.name, scope)));
+                available.retain(|a| !kills(&write, a, aliases));
+                continue;
+            }
+            ast::StmtVariant::Empty => continue,
+            _ => {
+                available.clear();
+                continue;
+            }
+        };
+
+        let mut writes = vec![];
+        for e in &exprs {
+            find_writes(e, purity, scope, &mut writes);
+        }
+        available.retain(|a| !writes.iter().any(|w| kills(w, a, aliases)));
+
+        let mut calls = vec![];
+        for e in &exprs {
+            find_calls(e, purity, &mut calls);
+        }
+        let mut fresh: Vec<Available> = vec![];
+        for call in calls {
+            let key = &*call.borrow() as *const ast::Expr;
+            if let Some(a) = available.iter_mut().find(|a| a.expr.spanless_eq(&call)) {
+                let temp = match a.temp {
+                    Some(temp) => temp,
+                    None => {
+                        let temp = plan.temps.len();
+                        plan.temps.push(callee(&a.expr));
+                        let first = &*a.expr.borrow() as *const ast::Expr;
+                        plan.reuse.insert(first, Reuse::Save(temp));
+                        a.temp = Some(temp);
+                        temp
+                    }
+                };
+                plan.reuse.insert(key, Reuse::Load(temp));
+            } else if !fresh.iter().any(|a| a.expr.spanless_eq(&call)) {
+                let mut a = Available {
+                    expr: call.cp(),
+                    reads: vec![],
+                    reads_any: false,
+                    temp: None,
+                };
+                find_reads(&call.borrow(), scope, &mut a);
+                fresh.push(a);
+            }
+        }
+        // * A call this statement may change what it reads of, before or
+        // * after it is made, is not kept
+        fresh.retain(|a| !writes.iter().any(|w| kills(w, a, aliases)));
+        available.extend(fresh);
+
+        if matches!(stmt.var, ast::StmtVariant::Return(_)) {
+            available.clear();
+        }
+    }
+    plan
+}
+
+/// Whether `write` may change the result of `call`
+fn kills(write: &Write, call: &Available, aliases: &Aliases) -> bool {
+    let place = match write {
+        Write::All => return true,
+        Write::Place(place) => place,
+    };
+    let targets = aliases.targets(place);
+    // * Pure functions may read any global
+    call.reads_any
+        || targets.iter().any(|(id, _)| *id == 0)
+        || call.reads.iter().any(|r| aliases.may_alias(place, r))
+}
+
+/// Name of the function `expr` calls
+fn callee(expr: &Ptr<ast::Expr>) -> String {
+    match &expr.borrow().var {
+        ExprVariant::FunctionCall(f) => f.func.to_string(),
+        _ => String::new(),
+    }
+}
+
+/// Key of the variable `name` refers to, seen from `scope`
+pub(super) fn key(name: &str, scope: &Ptr<Scope>) -> (usize, String) {
+    let id = (scope.borrow().find_def_depth(name)).map_or(0, |(_, id)| id);
+    (id, name.into())
+}
+
+/// Calls to pure functions in `expr` that always run when it does, outermost
+/// first and in the order they are written
+fn find_calls(expr: &Ptr<ast::Expr>, purity: &Purity, calls: &mut Vec<Ptr<ast::Expr>>) {
+    maybe_grow(|| match &expr.borrow().var {
+        ExprVariant::FunctionCall(f) => {
+            if purity.is_pure_expr(&expr.borrow()) {
+                calls.push(expr.cp());
+            } else {
+                f.params.iter().for_each(|p| find_calls(p, purity, calls));
+            }
+        }
+        // * The right side of `&&` and `||` may not run
+        ExprVariant::BinaryOp(b) if matches!(b.op, OpVar::And | OpVar::Or) => {
+            find_calls(&b.lhs, purity, calls)
+        }
+        ExprVariant::BinaryOp(b) => {
+            find_calls(&b.lhs, purity, calls);
+            find_calls(&b.rhs, purity, calls);
+        }
+        ExprVariant::UnaryOp(u) => find_calls(&u.val, purity, calls),
+        ExprVariant::TypeConversion(t) => find_calls(&t.expr, purity, calls),
+        _ => (),
+    })
+}
+
+/// What `expr` may write
+pub(super) fn find_writes(
+    expr: &Ptr<ast::Expr>,
+    purity: &Purity,
+    scope: &Ptr<Scope>,
+    writes: &mut Vec<Write>,
+) {
+    maybe_grow(|| match &expr.borrow().var {
+        ExprVariant::FunctionCall(f) => {
+            if !purity.is_pure(&f.func) {
+                writes.push(Write::All);
+            }
+            f.params
+                .iter()
+                .for_each(|p| find_writes(p, purity, scope, writes));
+        }
+        ExprVariant::BinaryOp(b) => {
+            if matches!(b.op, OpVar::_Asn | OpVar::_Csn) {
+                writes.push(written(&b.lhs, scope));
+            }
+            find_writes(&b.lhs, purity, scope, writes);
+            find_writes(&b.rhs, purity, scope, writes);
+        }
+        ExprVariant::UnaryOp(u) => {
+            if matches!(u.op, OpVar::Ina | OpVar::Inb | OpVar::Dea | OpVar::Deb) {
+                writes.push(written(&u.val, scope));
+            }
+            find_writes(&u.val, purity, scope, writes);
+        }
+        ExprVariant::TypeConversion(t) => find_writes(&t.expr, purity, scope, writes),
+        ExprVariant::Ident(_) | ExprVariant::Literal(_) => (),
+        ExprVariant::StructChild(_) | ExprVariant::ArrayChild(_) => writes.push(Write::All),
+    })
+}
+
+/// What assigning to `target` writes
+pub(super) fn written(target: &Ptr<ast::Expr>, scope: &Ptr<Scope>) -> Write {
+    match &target.borrow().var {
+        ExprVariant::Ident(i) => Write::Place(Place::Var(key(&i.name, scope))),
+        ExprVariant::UnaryOp(u) if u.op == OpVar::Der => match &u.val.borrow().var {
+            ExprVariant::Ident(i) => Write::Place(Place::Through(key(&i.name, scope))),
+            _ => Write::All,
+        },
+        _ => Write::All,
+    }
+}
+
+/// Note what `expr` reads in `call`
+fn find_reads(expr: &ast::Expr, scope: &Ptr<Scope>, call: &mut Available) {
+    maybe_grow(|| match &expr.var {
+        ExprVariant::Ident(i) => call.reads.push(Place::Var(key(&i.name, scope))),
+        ExprVariant::UnaryOp(u) if u.op == OpVar::Der => match &u.val.borrow().var {
+            ExprVariant::Ident(i) => {
+                let key = key(&i.name, scope);
+                call.reads.push(Place::Through(key.clone()));
+                call.reads.push(Place::Var(key));
+            }
+            _ => {
+                call.reads_any = true;
+                find_reads(&u.val.borrow(), scope, call);
+            }
+        },
+        ExprVariant::UnaryOp(u) => find_reads(&u.val.borrow(), scope, call),
+        ExprVariant::BinaryOp(b) => {
+            find_reads(&b.lhs.borrow(), scope, call);
+            find_reads(&b.rhs.borrow(), scope, call);
+        }
+        ExprVariant::FunctionCall(f) => f
+            .params
+            .iter()
+            .for_each(|p| find_reads(&p.borrow(), scope, call)),
+        ExprVariant::TypeConversion(t) => find_reads(&t.expr.borrow(), scope, call),
+        ExprVariant::Literal(_) => (),
+        ExprVariant::StructChild(_) | ExprVariant::ArrayChild(_) => call.reads_any = true,
+    })
+}
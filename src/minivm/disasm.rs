@@ -0,0 +1,138 @@
+use super::{Constant, Inst, O0};
+use std::fmt::Write;
+
+/// Width instructions are padded to before their comments
+const COMMENT_COLUMN: usize = 20;
+
+/// Print `o0` as S0 assembly annotated with comments.
+///
+/// Constants list the instructions using them, and instructions using
+/// constants or calling functions show what they refer to. If `o0` has debug
+/// info and `source` is given, the source line of each run of instructions
+/// is shown before them.
+pub fn disassemble(o0: &O0, source: Option<&str>) -> String {
+    let d = Disasm::new(o0, source);
+    let mut out = String::new();
+    d.write(&mut out).expect("Writing to a String cannot fail");
+    out
+}
+
+struct Disasm<'a> {
+    o0: &'a O0,
+    source: Vec<&'a str>,
+    /// Where each constant is used
+    refs: Vec<Vec<String>>,
+}
+
+impl<'a> Disasm<'a> {
+    fn new(o0: &'a O0, source: Option<&'a str>) -> Disasm<'a> {
+        let mut refs = vec![vec![]; o0.constants.len()];
+        let mut add_ref = |idx: u16, place: String| {
+            if let Some(r) = refs.get_mut(idx as usize) {
+                r.push(place);
+            }
+        };
+        for (f_idx, f) in o0.functions.iter().enumerate() {
+            add_ref(f.name_idx, format!("name of .F{}", f_idx));
+        }
+        let code = std::iter::once((".start".to_string(), &o0.start_code.ins)).chain(
+            (o0.functions.iter().enumerate()).map(|(idx, f)| (format!(".F{}", idx), &f.ins)),
+        );
+        for (name, ins) in code {
+            for (i, inst) in ins.iter().enumerate() {
+                if let Inst::LoadC(idx) = inst {
+                    add_ref(*idx, format!("{}:{}", name, i));
+                }
+            }
+        }
+
+        Disasm {
+            o0,
+            source: source.map_or(vec![], |s| s.lines().collect()),
+            refs,
+        }
+    }
+
+    fn fn_name(&self, idx: u16) -> Option<String> {
+        let f = self.o0.functions.get(idx as usize)?;
+        match self.o0.constants.get(f.name_idx as usize)? {
+            Constant::String(s) => Some(String::from_utf8_lossy(s).into_owned()),
+            _ => None,
+        }
+    }
+
+    fn write(&self, out: &mut String) -> std::fmt::Result {
+        writeln!(out, ".constants:")?;
+        for (idx, c) in self.o0.constants.iter().enumerate() {
+            let refs = &self.refs[idx];
+            let comment = if refs.is_empty() {
+                "unused".to_string()
+            } else {
+                refs.join(", ")
+            };
+            line(out, format!("{} {}", idx, c), &comment)?;
+        }
+
+        let debug = self.o0.debug.as_ref();
+        writeln!(out, ".start:")?;
+        self.write_insts(
+            out,
+            &self.o0.start_code.ins,
+            debug.map(|d| &d.start_lines[..]),
+        )?;
+
+        writeln!(out, ".functions:")?;
+        for (idx, f) in self.o0.functions.iter().enumerate() {
+            let text = format!("{} {} {} {}", idx, f.name_idx, f.param_siz, f.lvl);
+            line(out, text, &self.fn_name(idx as u16).unwrap_or_default())?;
+        }
+        for (idx, f) in self.o0.functions.iter().enumerate() {
+            let text = format!(".F{}:", idx);
+            line(out, text, &self.fn_name(idx as u16).unwrap_or_default())?;
+            let lines = debug.and_then(|d| d.fn_lines.get(idx)).map(|l| &l[..]);
+            self.write_insts(out, &f.ins, lines)?;
+        }
+        Ok(())
+    }
+
+    fn write_insts(
+        &self,
+        out: &mut String,
+        ins: &[Inst],
+        lines: Option<&[Option<u32>]>,
+    ) -> std::fmt::Result {
+        let mut last_line = None;
+        for (idx, inst) in ins.iter().enumerate() {
+            let ln = lines.and_then(|l| l.get(idx).copied().flatten());
+            if let Some(ln) = ln.filter(|_| ln != last_line) {
+                if let Some(src) = self.source.get(ln as usize) {
+                    writeln!(out, "# {:>4} | {}", ln + 1, src.trim_end())?;
+                }
+                last_line = Some(ln);
+            }
+
+            let comment = match inst {
+                Inst::LoadC(c) => self
+                    .o0
+                    .constants
+                    .get(*c as usize)
+                    .map_or("no such constant".into(), |c| c.to_string()),
+                Inst::Call(f) => self
+                    .fn_name(*f)
+                    .unwrap_or_else(|| "no such function".into()),
+                _ => String::new(),
+            };
+            line(out, format!("{} {}", idx, inst), &comment)?;
+        }
+        Ok(())
+    }
+}
+
+/// Write `text` and a `# comment` after it, if there is one
+fn line(out: &mut String, text: String, comment: &str) -> std::fmt::Result {
+    if comment.is_empty() {
+        writeln!(out, "{}", text)
+    } else {
+        writeln!(out, "{:width$} # {}", text, comment, width = COMMENT_COLUMN)
+    }
+}
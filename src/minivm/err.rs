@@ -1,5 +1,6 @@
-use crate::c0::ast;
+use crate::error::{ErrorCode, Fix};
 use crate::prelude::*;
+use chigusa_minivm::Inst;
 use failure::*;
 use std::fmt;
 
@@ -27,7 +28,10 @@ pub fn compile_err(v: CompileErrorVar, span: Option<Span>) -> CompileError {
 
 impl fmt::Display for CompileError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}", self)
+        match self.span {
+            Some(span) => write!(f, "{} at {}", self.var, span),
+            None => write!(f, "{}", self.var),
+        }
     }
 }
 
@@ -71,9 +75,22 @@ pub enum CompileErrorVar {
     Unknown,
     AssignVoid,
     AssignConst,
+    /// An assignment used as a value, under a standard where it has none
+    AssignAsValue,
+    /// An assignment used as the condition of an `if` or `while`, under a
+    /// standard where it has no value
+    AssignInCondition,
+    /// `&&` or `||` used other than in the condition of an `if` or `while`
+    LogicAsValue,
     VoidVariable(String),
+    /// A variable declared `auto` that is used before anything is assigned
+    /// to it, or never assigned
+    CannotInferType(String),
     UnsupportedType,
     UnsupportedOp,
+    NotOnTarget(Inst, &'static str),
+    /// A type, as written in C0, and the target lacking it
+    TypeNotOnTarget(String, &'static str),
     NoExternFunction(String),
 
     ErrorType,
@@ -84,6 +101,8 @@ pub enum CompileErrorVar {
     RequireScannable(String),
 
     IntOverflow,
+    /// An integer literal, the type it is used as, and the range of that type
+    LiteralOutOfRange(String, String, i64, i64),
     ParamLengthMismatch,
     ReturnTypeMismatch(String),
     NonExistFunc(String),
@@ -91,19 +110,185 @@ pub enum CompileErrorVar {
 
     ControlReachesEndOfNonVoidFunction,
     NoTargetToBreak,
+    NoLoopLabel(String),
     FunctionMissingBody(String),
     NestedFunctions(String),
+    StackTooDeep(String, usize, usize),
+    FrameTooLarge(String, usize, usize),
 
     NotLValue(String),
+    /// `*` applied to a value of this type, which is not a reference
+    NotAReference(String),
+    /// `&` applied to this constant
+    AddressOfConst(String),
+    /// `&` applied to this expression, which is not a variable
+    NotAddressable(String),
     NotImplemented(String),
 
     Error(String),
     InternalError(String),
 }
 
+impl CompileErrorVar {
+    pub fn code(&self) -> ErrorCode {
+        use self::CompileErrorVar::*;
+        ErrorCode(match self {
+            NonExistVar(_) => 203,
+            NonExistFunc(_) => 204,
+
+            AssignVoid => 301,
+            AssignConst => 302,
+            VoidVariable(_) => 303,
+            UnsupportedType => 304,
+            ParamLengthMismatch => 305,
+            ReturnTypeMismatch(_) => 306,
+            ErrorType => 307,
+            MakeRefFromPrimitive | MakePrimitiveFromRef => 308,
+            RequireSized(_) => 309,
+            RequirePrintable(_) => 310,
+            RequireScannable(_) => 311,
+            IntOverflow => 312,
+            NotLValue(_) => 313,
+            UnsupportedOp => 314,
+            NotOnTarget(..) => 315,
+            LiteralOutOfRange(..) => 319,
+            AssignAsValue => 320,
+            AssignInCondition => 321,
+            CannotInferType(_) => 322,
+            NotAReference(_) => 324,
+            AddressOfConst(_) => 325,
+            NotAddressable(_) => 326,
+            TypeNotOnTarget(..) => 327,
+            LogicAsValue => 328,
+
+            ControlReachesEndOfNonVoidFunction => 401,
+            NoTargetToBreak => 402,
+            NoLoopLabel(_) => 403,
+            FunctionMissingBody(_) => 404,
+            NestedFunctions(_) => 405,
+            NoExternFunction(_) => 406,
+            StackTooDeep(..) => 407,
+            FrameTooLarge(..) => 408,
+
+            Unknown | Error(_) => 900,
+            NotImplemented(_) => 901,
+            InternalError(_) => 999,
+        })
+    }
+}
+
+impl CompileErrorVar {
+    /// A hint on how to fix the error
+    pub fn note(&self) -> Option<&'static str> {
+        match self {
+            CompileErrorVar::AssignInCondition => Some("did you mean `==`?"),
+            CompileErrorVar::LogicAsValue => Some("use it in the condition of an `if` instead"),
+            CompileErrorVar::CannotInferType(_) => {
+                Some("give it an initializer, or declare it with a type")
+            }
+            _ => None,
+        }
+    }
+
+    /// A change fixing the error at `span`, if one is safe to make
+    pub fn fix(&self, span: Span) -> Option<Fix> {
+        match self {
+            // * Reported at the `=`
+            CompileErrorVar::AssignInCondition => Some(Fix::new("compare with `==`", span, "==")),
+            CompileErrorVar::LiteralOutOfRange(_, ty, ..) => Some(Fix::new(
+                format!("cast to `{}`", ty),
+                Span::point(span.start),
+                format!("({})", ty),
+            )),
+            _ => None,
+        }
+    }
+}
+
 impl fmt::Display for CompileErrorVar {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}", self)
+        use self::CompileErrorVar::*;
+        match self {
+            Unknown => write!(f, "Unknown error"),
+            AssignVoid => write!(f, "Cannot assign a void value"),
+            AssignConst => write!(f, "Cannot assign to a constant"),
+            AssignAsValue => write!(
+                f,
+                "An assignment has no value in C0; use `--std c0-ext` to allow this"
+            ),
+            AssignInCondition => write!(f, "An assignment cannot be a condition in C0"),
+            LogicAsValue => write!(
+                f,
+                "`&&` and `||` are only supported in the condition of an `if` or `while`"
+            ),
+            VoidVariable(name) => write!(f, "Variable '{}' cannot be void", name),
+            CannotInferType(name) => write!(f, "Cannot infer the type of '{}'", name),
+            UnsupportedType => write!(f, "This type is not supported"),
+            UnsupportedOp => write!(f, "This operator is not supported for these types"),
+            NotOnTarget(inst, target) => {
+                write!(
+                    f,
+                    "Instruction `{}` is not available on target {}",
+                    inst, target
+                )
+            }
+            TypeNotOnTarget(typ, target) => {
+                write!(f, "Type `{}` is not supported on target {}", typ, target)
+            }
+            NoExternFunction(name) => write!(f, "No external function named '{}'", name),
+
+            ErrorType => write!(f, "Expression has an invalid type"),
+            MakeRefFromPrimitive => write!(f, "Cannot make a reference from a primitive value"),
+            MakePrimitiveFromRef => write!(f, "Cannot make a primitive value from a reference"),
+            RequireSized(ty) if ty.is_empty() => write!(f, "Expected a sized type"),
+            RequireSized(ty) => write!(f, "Expected a sized type, found {}", ty),
+            RequirePrintable(ty) => write!(f, "Values of type {} cannot be printed", ty),
+            RequireScannable(ty) => write!(f, "Values of type {} cannot be scanned", ty),
+
+            IntOverflow => write!(f, "Integer literal does not fit in 32 bits"),
+            LiteralOutOfRange(val, ty, min, max) => write!(
+                f,
+                "Integer literal {} is out of range for {}, which holds {} to {}",
+                val, ty, min, max
+            ),
+            ParamLengthMismatch => write!(f, "Wrong number of arguments"),
+            ReturnTypeMismatch(ty) => {
+                write!(f, "Return value does not match the return type {}", ty)
+            }
+            NonExistFunc(name) => write!(f, "Unable to find function: {}", name),
+            NonExistVar(name) => write!(f, "Unable to find variable: {}", name),
+
+            ControlReachesEndOfNonVoidFunction => {
+                write!(f, "Control reaches the end of a non-void function")
+            }
+            NoTargetToBreak => write!(f, "`break` and `continue` can only be used in loops"),
+            NoLoopLabel(label) => write!(f, "No enclosing loop is labeled '{}'", label),
+            FunctionMissingBody(name) => write!(f, "Function '{}' has no body", name),
+            NestedFunctions(name) => write!(
+                f,
+                "Function '{}' cannot be declared inside another function",
+                name
+            ),
+            StackTooDeep(name, depth, max) => write!(
+                f,
+                "Code of {} needs {} stack slots, more than the maximum {}",
+                name, depth, max
+            ),
+            FrameTooLarge(name, slots, max) => write!(
+                f,
+                "Frame of {} needs {} slots, more than the maximum {}",
+                name, slots, max
+            ),
+
+            NotLValue(expr) => write!(f, "'{}' cannot be assigned to", expr),
+            NotAReference(ty) => write!(f, "Values of type {} cannot be dereferenced", ty),
+            AddressOfConst(name) => write!(f, "Cannot take the address of constant '{}'", name),
+            NotAddressable(expr) => write!(f, "Cannot take the address of '{}'", expr),
+            NotImplemented(what) => write!(f, "Not implemented: {}", what),
+
+            Error(msg) => write!(f, "{}", msg),
+            InternalError(msg) => write!(f, "Internal error inside compiler: {}", msg),
+        }
     }
 }
 
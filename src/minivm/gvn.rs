@@ -0,0 +1,413 @@
+//! Computing the same value only once across the blocks of a function.
+//!
+//! Every expression gets a number, the same for two expressions only where
+//! they surely compute the same value: the same operator on operands of the
+//! same numbers, or the same variable with nothing written to it in between.
+//! A write gives a variable a new version, so what reads it afterwards gets
+//! new numbers. An expression whose number was computed before it on every
+//! path to it takes the value kept in a local slot the source does not name,
+//! instead of computing it again.
+//!
+//! In structured code, an expression is computed on every path to what
+//! follows it in its block, nested blocks included, and the condition of an
+//! `if` on every path to its arms and to what follows the `if`, less the
+//! right sides of `&&` and `||`, which may not run. The condition of a loop
+//! is not, as an unrolled loop runs its body without checking it first.
+//! Variables written anywhere in a loop get new versions before it, as its
+//! body may run again after any of them is written.
+//!
+//! Writes are found as for [pure calls](super::cse): a write through a
+//! reference gives new versions to the variables it may refer to, and a call
+//! to a function that is not pure to every global and every variable whose
+//! address is taken.
+
+use super::cse::{self, Reuse, Write};
+use super::schedule::Scheduler;
+use crate::c0::alias::{Aliases, Place};
+use crate::c0::ast::{self, ExprVariant, OpVar, Scope, StmtVariant};
+use crate::c0::escape::VarKey;
+use crate::c0::purity::Purity;
+use crate::prelude::*;
+use std::collections::HashMap;
+
+/// Values to compute only once in a function
+#[derive(Debug, Default)]
+pub(super) struct Plan {
+    pub reuse: HashMap<*const ast::Expr, Reuse>,
+    /// How many temporaries keep values
+    pub temps: usize,
+}
+
+/// What a value is computed from, by the numbers of its operands
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+enum Key {
+    /// A variable, its version, and how many unknown writes there were
+    /// before it if those may change it
+    Var(VarKey, u32, u32),
+    Lit(String),
+    Op(OpVar, Vec<u32>),
+    Conv(String, u32),
+    /// A call to a pure function, and how many writes there were before it
+    /// to what functions may read
+    Call(String, Vec<u32>, u32),
+}
+
+/// An expression computed before, and the temporary keeping its value once
+/// it is needed again
+struct Available {
+    expr: *const ast::Expr,
+    temp: Option<usize>,
+}
+
+/// Plan which values in the function with body `body` to compute once
+pub(super) fn plan(body: &ast::Block, purity: &Purity, aliases: &Aliases) -> Plan {
+    let mut numbering = Numbering {
+        purity,
+        aliases,
+        scheduler: Scheduler::new(),
+        plan: Plan::default(),
+        numbers: HashMap::new(),
+        memo: HashMap::new(),
+        versions: HashMap::new(),
+        unknown: 0,
+        memory: 0,
+        next: 0,
+        available: HashMap::new(),
+        scopes: vec![],
+    };
+    numbering.block(body);
+    numbering.plan
+}
+
+struct Numbering<'a> {
+    purity: &'a Purity,
+    aliases: &'a Aliases,
+    /// Which operand of each operator is computed first, as code generation
+    /// picks it
+    scheduler: Scheduler,
+    plan: Plan,
+    numbers: HashMap<Key, u32>,
+    /// Numbers of expressions since the last write, `None` for those that
+    /// are not computed once
+    memo: HashMap<*const ast::Expr, Option<(u32, bool)>>,
+    /// Version of each variable written
+    versions: HashMap<VarKey, u32>,
+    /// Version of everything unknown writes may change
+    unknown: u32,
+    /// Version of what pure functions may read
+    memory: u32,
+    /// Last number or version given out
+    next: u32,
+    available: HashMap<u32, Available>,
+    /// Numbers made available in each scope entered, innermost last
+    scopes: Vec<Vec<u32>>,
+}
+
+impl Numbering<'_> {
+    fn fresh(&mut self) -> u32 {
+        self.next += 1;
+        self.next
+    }
+
+    fn enter(&mut self) {
+        self.scopes.push(vec![]);
+    }
+
+    /// Forget the values computed since the scope was entered
+    fn leave(&mut self) {
+        for number in self.scopes.pop().unwrap_or_default() {
+            self.available.remove(&number);
+        }
+    }
+
+    fn block(&mut self, block: &ast::Block) {
+        self.enter();
+        for stmt in &block.stmts {
+            self.stmt(stmt, &block.scope);
+        }
+        self.leave();
+    }
+
+    /// `stmt`, alone in a scope of its own
+    fn arm(&mut self, stmt: &Ptr<ast::Stmt>, scope: &Ptr<Scope>) {
+        self.enter();
+        self.stmt(&stmt.borrow(), scope);
+        self.leave();
+    }
+
+    fn stmt(&mut self, stmt: &ast::Stmt, scope: &Ptr<Scope>) {
+        maybe_grow(|| match &stmt.var {
+            StmtVariant::Expr(e) => self.expr_stmt(e, scope),
+            StmtVariant::ManyExpr(es) => es.iter().for_each(|e| self.expr_stmt(e, scope)),
+            StmtVariant::Print(es) => es.iter().for_each(|e| self.value(e, scope)),
+            StmtVariant::Return(Some(e)) => self.value(e, scope),
+            StmtVariant::Scan(name, _) => {
+                let key = cse::key(&name.name, scope);
+                self.write(&Write::Place(Place::Var(key)));
+            }
+            StmtVariant::Block(b) => self.block(b),
+            StmtVariant::If(i) => {
+                // * The first condition is checked whichever arm runs
+                self.value(&i.cond, scope);
+                self.enter();
+                self.arm(&i.if_block, scope);
+                for (cond, arm) in &i.else_ifs {
+                    self.value(cond, scope);
+                    self.arm(arm, scope);
+                }
+                if let Some(arm) = &i.else_block {
+                    self.arm(arm, scope);
+                }
+                self.leave();
+            }
+            StmtVariant::While(w) => {
+                let mut writes = vec![];
+                cse::find_writes(&w.cond, self.purity, scope, &mut writes);
+                self.writes_in(&w.block.borrow(), scope, &mut writes);
+                writes.iter().for_each(|write| self.write(write));
+                self.enter();
+                self.value(&w.cond, scope);
+                self.leave();
+                self.arm(&w.block, scope);
+            }
+            StmtVariant::Return(None) | StmtVariant::Break(_) | StmtVariant::Empty => (),
+        })
+    }
+
+    /// `expr`, whose value is dropped, or assigned if it is an assignment
+    fn expr_stmt(&mut self, expr: &Ptr<ast::Expr>, scope: &Ptr<Scope>) {
+        let e = expr.borrow();
+        let b = match &e.var {
+            ExprVariant::BinaryOp(b) if matches!(b.op, OpVar::_Asn | OpVar::_Csn) => b,
+            _ => {
+                if self.writes_nothing(expr, scope) {
+                    self.operands(expr, scope);
+                }
+                return;
+            }
+        };
+        // * The value is computed before it is assigned
+        let mut writes = vec![];
+        cse::find_writes(&b.lhs, self.purity, scope, &mut writes);
+        if writes.is_empty() {
+            self.value(&b.rhs, scope);
+        } else {
+            cse::find_writes(&b.rhs, self.purity, scope, &mut writes);
+            writes.iter().for_each(|write| self.write(write));
+        }
+        self.write(&cse::written(&b.lhs, scope));
+    }
+
+    /// Make the writes of `expr`, returning whether there are none
+    fn writes_nothing(&mut self, expr: &Ptr<ast::Expr>, scope: &Ptr<Scope>) -> bool {
+        let mut writes = vec![];
+        cse::find_writes(expr, self.purity, scope, &mut writes);
+        writes.iter().for_each(|write| self.write(write));
+        writes.is_empty()
+    }
+
+    /// `expr`, whose value is used
+    fn value(&mut self, expr: &Ptr<ast::Expr>, scope: &Ptr<Scope>) {
+        // * Values computed alongside writes may be computed before or after
+        // * them, so they are left alone
+        if self.writes_nothing(expr, scope) {
+            self.visit(expr, scope);
+        }
+    }
+
+    /// Plan computing `expr`, and what it is computed from, in the order
+    /// code generation computes them
+    fn visit(&mut self, expr: &Ptr<ast::Expr>, scope: &Ptr<Scope>) {
+        let at = &*expr.borrow() as *const ast::Expr;
+        // * A variable loads as fast from its own slot as from a temporary
+        let computed = !matches!(expr.borrow().var, ExprVariant::Ident(_));
+        let number = self
+            .number(expr, scope)
+            .filter(|(_, reads)| *reads && computed);
+        let number = number.map(|(number, _)| number);
+        let available = &mut self.available;
+        if let Some(a) = number.and_then(|number| available.get_mut(&number)) {
+            let temp = match a.temp {
+                Some(temp) => temp,
+                None => {
+                    let temp = self.plan.temps;
+                    self.plan.temps += 1;
+                    self.plan.reuse.insert(a.expr, Reuse::Save(temp));
+                    a.temp = Some(temp);
+                    temp
+                }
+            };
+            self.plan.reuse.insert(at, Reuse::Load(temp));
+            return;
+        }
+        self.operands(expr, scope);
+        if let Some(number) = number {
+            self.available.insert(
+                number,
+                Available {
+                    expr: at,
+                    temp: None,
+                },
+            );
+            if let Some(scope) = self.scopes.last_mut() {
+                scope.push(number);
+            }
+        }
+    }
+
+    fn operands(&mut self, expr: &Ptr<ast::Expr>, scope: &Ptr<Scope>) {
+        maybe_grow(|| match &expr.borrow().var {
+            // * The right side of `&&` and `||` may not run
+            ExprVariant::BinaryOp(b) if matches!(b.op, OpVar::And | OpVar::Or) => {
+                self.visit(&b.lhs, scope);
+                self.enter();
+                self.visit(&b.rhs, scope);
+                self.leave();
+            }
+            ExprVariant::BinaryOp(b) => {
+                let (first, second) = match self.scheduler.swap(b) {
+                    Some(_) => (&b.rhs, &b.lhs),
+                    None => (&b.lhs, &b.rhs),
+                };
+                self.visit(first, scope);
+                self.visit(second, scope);
+            }
+            // * What a reference is taken of is not computed
+            ExprVariant::UnaryOp(u) if u.op != OpVar::Ref => self.visit(&u.val, scope),
+            ExprVariant::TypeConversion(t) => self.visit(&t.expr, scope),
+            ExprVariant::FunctionCall(f) => f.params.iter().for_each(|p| self.visit(p, scope)),
+            _ => (),
+        })
+    }
+
+    /// Number of the value of `expr`, if it is computed only once, and
+    /// whether computing it reads a variable
+    fn number(&mut self, expr: &Ptr<ast::Expr>, scope: &Ptr<Scope>) -> Option<(u32, bool)> {
+        let at = &*expr.borrow() as *const ast::Expr;
+        if let Some(number) = self.memo.get(&at) {
+            return *number;
+        }
+        let number = maybe_grow(|| self.compute(&expr.borrow(), scope));
+        self.memo.insert(at, number);
+        number
+    }
+
+    fn compute(&mut self, expr: &ast::Expr, scope: &Ptr<Scope>) -> Option<(u32, bool)> {
+        let (key, reads) = match &expr.var {
+            ExprVariant::Ident(i) => {
+                let var = cse::key(&i.name, scope);
+                let version = self.versions.get(&var).copied().unwrap_or(0);
+                let unknown = match self.unknown_writes(&var) {
+                    true => self.unknown,
+                    false => 0,
+                };
+                (Key::Var(var, version, unknown), true)
+            }
+            ExprVariant::Literal(lit) => (Key::Lit(format!("{:?}", lit)), false),
+            ExprVariant::BinaryOp(b) => {
+                use OpVar::*;
+                if !matches!(b.op, Add | Sub | Mul | Div | Gt | Lt | Eq | Gte | Lte | Neq) {
+                    return None;
+                }
+                let (lhs, lhs_reads) = self.number(&b.lhs, scope)?;
+                let (rhs, rhs_reads) = self.number(&b.rhs, scope)?;
+                (Key::Op(b.op, vec![lhs, rhs]), lhs_reads || rhs_reads)
+            }
+            ExprVariant::UnaryOp(u) if u.op == OpVar::Neg => {
+                let (val, reads) = self.number(&u.val, scope)?;
+                (Key::Op(u.op, vec![val]), reads)
+            }
+            ExprVariant::TypeConversion(t) => {
+                let (val, reads) = self.number(&t.expr, scope)?;
+                (Key::Conv(format!("{:?}", t.to.borrow()), val), reads)
+            }
+            ExprVariant::FunctionCall(f) if self.purity.is_pure(&f.func) => {
+                let mut args = vec![];
+                for param in &f.params {
+                    args.push(self.number(param, scope)?.0);
+                }
+                (Key::Call(f.func.to_string(), args, self.memory), true)
+            }
+            _ => return None,
+        };
+        let number = match self.numbers.get(&key) {
+            Some(number) => *number,
+            None => {
+                let number = self.fresh();
+                self.numbers.insert(key, number);
+                number
+            }
+        };
+        Some((number, reads))
+    }
+
+    /// Whether calls to functions that are not pure may change `var`
+    fn unknown_writes(&self, var: &VarKey) -> bool {
+        var.0 == 0 || self.aliases.taken.contains(var)
+    }
+
+    fn write(&mut self, write: &Write) {
+        self.memo.clear();
+        let place = match write {
+            Write::Place(place) => place,
+            Write::All => {
+                self.unknown = self.fresh();
+                self.memory = self.fresh();
+                return;
+            }
+        };
+        let targets = self.aliases.targets(place);
+        // * A reference that may refer to nothing known may refer to anything
+        if targets.is_empty() {
+            return self.write(&Write::All);
+        }
+        for var in targets {
+            if self.unknown_writes(&var) {
+                self.memory = self.fresh();
+            }
+            let version = self.fresh();
+            self.versions.insert(var, version);
+        }
+        if matches!(place, Place::Through(_)) {
+            self.memory = self.fresh();
+        }
+    }
+
+    /// Add what `stmt` may write to `writes`
+    fn writes_in(&self, stmt: &ast::Stmt, scope: &Ptr<Scope>, writes: &mut Vec<Write>) {
+        maybe_grow(|| match &stmt.var {
+            StmtVariant::Expr(e) | StmtVariant::Return(Some(e)) => {
+                cse::find_writes(e, self.purity, scope, writes)
+            }
+            StmtVariant::ManyExpr(es) | StmtVariant::Print(es) => {
+                for e in es {
+                    cse::find_writes(e, self.purity, scope, writes);
+                }
+            }
+            StmtVariant::Scan(name, _) => {
+                writes.push(Write::Place(Place::Var(cse::key(&name.name, scope))))
+            }
+            StmtVariant::Block(b) => {
+                for stmt in &b.stmts {
+                    self.writes_in(stmt, &b.scope, writes);
+                }
+            }
+            StmtVariant::If(i) => {
+                cse::find_writes(&i.cond, self.purity, scope, writes);
+                self.writes_in(&i.if_block.borrow(), scope, writes);
+                for (cond, arm) in &i.else_ifs {
+                    cse::find_writes(cond, self.purity, scope, writes);
+                    self.writes_in(&arm.borrow(), scope, writes);
+                }
+                if let Some(arm) = &i.else_block {
+                    self.writes_in(&arm.borrow(), scope, writes);
+                }
+            }
+            StmtVariant::While(w) => {
+                cse::find_writes(&w.cond, self.purity, scope, writes);
+                self.writes_in(&w.block.borrow(), scope, writes);
+            }
+            StmtVariant::Return(None) | StmtVariant::Break(_) | StmtVariant::Empty => (),
+        })
+    }
+}
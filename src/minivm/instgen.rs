@@ -2,8 +2,6 @@ use super::codegen::*;
 use super::err::*;
 use super::*;
 use crate::c0::ast::{self, *};
-use crate::prelude::*;
-use indexmap::{map::Entry, IndexMap};
 
 /// Generate type conversion for `a` and `b` to match their types.
 ///
@@ -21,6 +19,7 @@ pub(super) fn flatten_ty(
     a_sink: &mut InstSink,
     b: Type,
     b_sink: &mut InstSink,
+    target: &Target,
 ) -> CompileResult<Type> {
     use TypeDef::*;
 
@@ -33,35 +32,40 @@ pub(super) fn flatten_ty(
             use ast::PrimitiveTypeVar::*;
             if p.var == Float {
                 if q.var != Float {
-                    conv(b.cp(), a.cp(), b_sink)
+                    conv(b.cp(), a.cp(), target, b_sink)
                 } else {
                     Ok(a.cp())
                 }
             } else {
                 if q.var != Float {
-                    conv(b.cp(), a.cp(), b_sink)
+                    conv(b.cp(), a.cp(), target, b_sink)
                 } else {
                     if p.occupy_bytes > q.occupy_bytes {
-                        conv(b.cp(), a.cp(), a_sink)
+                        conv(b.cp(), a.cp(), target, a_sink)
                     } else {
-                        conv(a.cp(), b.cp(), a_sink)
+                        conv(a.cp(), b.cp(), target, a_sink)
                     }
                 }
             }
         } else {
-            conv(b.cp(), a.cp(), b_sink)
+            conv(b.cp(), a.cp(), target, b_sink)
         }
     } else {
-        conv(b.cp(), a.cp(), b_sink)
+        conv(b.cp(), a.cp(), target, b_sink)
     }
 }
 
 /// Generate implicit conversion for `val` to match `tgt` type
-pub(super) fn conv(from: Type, to: Type, sink: &mut InstSink) -> CompileResult<Type> {
+pub(super) fn conv(
+    from: Type,
+    to: Type,
+    target: &Target,
+    sink: &mut InstSink,
+) -> CompileResult<Type> {
     use TypeDef::*;
     match &*to.borrow() {
         Unit => {
-            pop(from, sink)?;
+            pop(from, target, sink)?;
             Ok(to.cp())
         }
         Unknown | TypeErr => Err(CompileErrorVar::ErrorType.into()),
@@ -86,7 +90,7 @@ pub(super) fn conv(from: Type, to: Type, sink: &mut InstSink) -> CompileResult<T
                 if r == r1 {
                     Ok(to.cp())
                 } else {
-                    log::warn!("Implicit ref type change: {:?} -> {:?}", from, to);
+                    tracing::warn!("Implicit ref type change: {:?} -> {:?}", from, to);
                     Ok(to.cp())
                 }
             }
@@ -101,58 +105,62 @@ pub(super) fn conv(from: Type, to: Type, sink: &mut InstSink) -> CompileResult<T
     }
 }
 
-pub(super) fn pop(ty: Type, sink: &mut InstSink) -> CompileResult<()> {
-    let slots = ty
-        .borrow()
-        .occupy_slots()
+/// Whether values of `ty` are addresses, loaded and stored as such
+fn is_ref(ty: &Type) -> bool {
+    matches!(&*ty.borrow(), TypeDef::Ref(..))
+}
+
+pub(super) fn pop(ty: Type, target: &Target, sink: &mut InstSink) -> CompileResult<()> {
+    let slots = target
+        .slots_of(&ty.borrow())
         .ok_or(CompileErrorVar::RequireSized(format!("{:?}", ty.cp())))?;
     match slots {
         0 => (),
         1 => sink.push(Inst::Pop1),
         2 => sink.push(Inst::Pop2),
-        n @ _ => sink.push(Inst::PopN(n as u32)),
+        n => sink.push(Inst::PopN(n)),
     }
     Ok(())
 }
 
-pub(super) fn ret(ty: Type, sink: &mut InstSink) -> CompileResult<()> {
-    let slots = ty
-        .borrow()
-        .occupy_slots()
+pub(super) fn ret(ty: Type, target: &Target, sink: &mut InstSink) -> CompileResult<()> {
+    let slots = target
+        .slots_of(&ty.borrow())
         .ok_or(CompileErrorVar::RequireSized(format!("{:?}", ty.cp())))?;
     match slots {
         0 => sink.push(Inst::Ret),
+        1 if is_ref(&ty) => sink.push(Inst::ARet),
         1 => sink.push(Inst::IRet),
         2 => sink.push(Inst::DRet),
-        _n @ _ => Err(CompileErrorVar::UnsupportedType)?,
+        _n => Err(CompileErrorVar::UnsupportedType)?,
     }
     Ok(())
 }
 
-pub(super) fn load(ty: Type, sink: &mut InstSink) -> CompileResult<()> {
-    let slots = ty
-        .borrow()
-        .occupy_slots()
+pub(super) fn load(ty: Type, target: &Target, sink: &mut InstSink) -> CompileResult<()> {
+    let slots = target
+        .slots_of(&ty.borrow())
         .ok_or(CompileErrorVar::RequireSized(format!("{:?}", ty.cp())))?;
     match slots {
         0 => Err(CompileErrorVar::AssignVoid)?,
+        1 if is_ref(&ty) => sink.push(Inst::ALoad),
         1 => sink.push(Inst::ILoad),
         2 => sink.push(Inst::DLoad),
-        _n @ _ => Err(CompileErrorVar::UnsupportedType)?,
+        _n => Err(CompileErrorVar::UnsupportedType)?,
     }
     Ok(())
 }
 
-pub(super) fn store(ty: Type, sink: &mut InstSink) -> CompileResult<()> {
-    let slots = ty
-        .borrow()
-        .occupy_slots()
+pub(super) fn store(ty: Type, target: &Target, sink: &mut InstSink) -> CompileResult<()> {
+    let slots = target
+        .slots_of(&ty.borrow())
         .ok_or(CompileErrorVar::RequireSized(format!("{:?}", ty.cp())))?;
     match slots {
         0 => Err(CompileErrorVar::AssignVoid)?,
+        1 if is_ref(&ty) => sink.push(Inst::AStore),
         1 => sink.push(Inst::IStore),
         2 => sink.push(Inst::DStore),
-        _n @ _ => Err(CompileErrorVar::UnsupportedType)?,
+        _n => Err(CompileErrorVar::UnsupportedType)?,
     }
     Ok(())
 }
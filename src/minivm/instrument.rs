@@ -0,0 +1,182 @@
+//! Counting in the program itself how many times each block of its code
+//! runs.
+//!
+//! Code is instrumented once it is optimized and laid out. Every instruction
+//! starting a block, as the entry of a function, the target of a jump or
+//! the one after a jump, first adds one to a counter of its own. Counters
+//! are slots of start code right after the global variables, and
+//! `main` prints them before it returns, on a line starting with
+//! [`DUMP_MARK`]. Any VM running the program prints the counts, and a
+//! [`CounterMap`] written when compiling maps them back to source lines.
+//! Global initializers are not counted.
+
+use super::codegen::InstSink;
+use super::label::{jump_target, set_jump_target};
+use super::Inst;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// What the line of counts a program prints starts with
+pub const DUMP_MARK: &str = "#counts";
+
+/// Where the code of each counter came from, in the order the counts are
+/// printed
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CounterMap {
+    pub counters: Vec<Counter>,
+}
+
+/// A block of code counted
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Counter {
+    /// Function the block is in
+    pub function: String,
+    /// Source lines the block has code of, counted from 1
+    pub lines: Vec<u32>,
+}
+
+impl CounterMap {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Maps are always valid JSON")
+    }
+
+    pub fn from_json(json: &str) -> Result<CounterMap, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// Counts printed in `output`, a run of the program, if it printed as
+    /// many as there are counters. The last line of counts wins, as `main`
+    /// may return more than once.
+    pub fn parse_dump(&self, output: &str) -> Option<Vec<u64>> {
+        let line = output.lines().rev().find(|l| l.starts_with(DUMP_MARK))?;
+        let counts = line[DUMP_MARK.len()..].split_whitespace();
+        // * Counters wrap around like any `int`
+        let counts = counts.map(|c| c.parse::<i32>().ok().map(|c| c as u32 as u64));
+        let counts = counts.collect::<Option<Vec<_>>>()?;
+        (counts.len() == self.counters.len()).then_some(counts)
+    }
+
+    /// How many times each line with counted code ran, counting a line as
+    /// run as often as its most run block
+    pub fn line_counts(&self, counts: &[u64]) -> BTreeMap<u32, u64> {
+        let mut lines = BTreeMap::new();
+        for (counter, count) in self.counters.iter().zip(counts) {
+            for line in &counter.lines {
+                let hits = lines.entry(*line).or_insert(0);
+                *hits = (*count).max(*hits);
+            }
+        }
+        lines
+    }
+}
+
+fn is_ret(inst: &Inst) -> bool {
+    matches!(inst, Inst::Ret | Inst::IRet | Inst::DRet | Inst::ARet)
+}
+
+/// Instructions starting a block of `ins`. The first block starts after
+/// the `snew` making room for local variables, which must come first.
+fn leaders(ins: &[Inst]) -> Vec<usize> {
+    let entry = usize::from(matches!(ins.first(), Some(Inst::SNew(_))));
+    let mut leaders = vec![entry];
+    for (idx, inst) in ins.iter().enumerate() {
+        leaders.extend(jump_target(inst).map(|to| (to as usize).max(entry)));
+        if jump_target(inst).is_some() || is_ret(inst) {
+            leaders.push(idx + 1);
+        }
+    }
+    leaders.retain(|at| *at < ins.len());
+    leaders.sort_unstable();
+    leaders.dedup();
+    leaders
+}
+
+/// Count each block of `code`, the code of `function`, in counters after
+/// those already in `map`. Counters start at slot `base` of start code.
+pub(super) fn count_blocks(code: &mut InstSink, function: &str, base: usize, map: &mut CounterMap) {
+    let leaders = leaders(code.inner());
+    let first = map.counters.len();
+    let ends = leaders.iter().skip(1).copied().chain(Some(code.len()));
+    for (start, end) in leaders.iter().zip(ends) {
+        let mut lines: Vec<_> = code.lines()[*start..end]
+            .iter()
+            .flatten()
+            .map(|l| l + 1)
+            .collect();
+        lines.sort_unstable();
+        lines.dedup();
+        map.counters.push(Counter {
+            function: function.into(),
+            lines,
+        });
+    }
+    for (idx, at) in leaders.iter().enumerate().rev() {
+        let slot = (base + first + idx) as i32;
+        code.insert_before(
+            *at,
+            &[
+                Inst::LoadA(1, slot),
+                Inst::LoadA(1, slot),
+                Inst::ILoad,
+                Inst::IPush(1),
+                Inst::IAdd,
+                Inst::IStore,
+            ],
+        );
+    }
+}
+
+/// The slot of start code `start` the first counter goes in: the one after
+/// its variables
+pub(super) fn counter_base(start: &InstSink) -> usize {
+    match start.inner().first() {
+        Some(Inst::SNew(n)) => *n as usize,
+        _ => 0,
+    }
+}
+
+/// Make start code `start` make room for `count` counters after its
+/// variables, and set them to zero
+pub(super) fn add_counters(start: &mut InstSink, count: usize) {
+    let base = counter_base(start);
+    let zero = (base..base + count)
+        .flat_map(|slot| [Inst::LoadA(0, slot as i32), Inst::IPush(0), Inst::IStore]);
+    let zero: Vec<_> = zero.collect();
+    match start.inner_mut().first_mut() {
+        Some(Inst::SNew(n)) => *n += count as u32,
+        _ => start.insert_before(0, &[Inst::SNew(count as u32)]),
+    }
+    start.insert_before(1, &zero);
+    // * Jumps there skip setting the counters, which is only done once
+    for inst in start.inner_mut().iter_mut() {
+        if jump_target(inst) == Some(1) {
+            set_jump_target(inst, 1 + zero.len() as u16);
+        }
+    }
+}
+
+/// Make `code` print the `count` counters from slot `base` before each of
+/// its returns
+pub(super) fn dump_counts(code: &mut InstSink, base: usize, count: usize) {
+    let mark = DUMP_MARK
+        .bytes()
+        .map(|c| [Inst::IPush(c as i32), Inst::CPrint]);
+    let mut dump: Vec<_> = mark.flatten().collect();
+    for slot in base..base + count {
+        dump.extend([
+            Inst::IPush(b' ' as i32),
+            Inst::CPrint,
+            Inst::LoadA(1, slot as i32),
+            Inst::ILoad,
+            Inst::IPrint,
+        ]);
+    }
+    dump.push(Inst::PrintLn);
+    let rets: Vec<_> = (code.inner().iter().enumerate())
+        .filter(|(_, inst)| is_ret(inst))
+        .map(|(at, _)| at)
+        .collect();
+    for at in rets.into_iter().rev() {
+        code.insert_before(at, &dump);
+    }
+}
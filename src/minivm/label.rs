@@ -0,0 +1,102 @@
+//! Jumps to labels, resolved to instruction indices in a fixup pass.
+//!
+//! Code is emitted with the target of every jump being a [`Label`], before
+//! it is known where the label will be. Labels are then placed at
+//! instructions, and [`Labels::resolve`] replaces each target with the index
+//! its label is at. A pass inserting or removing instructions can turn the
+//! targets of laid out code back into labels with [`Labels::of_jumps`], move
+//! the labels along with the instructions, and resolve again.
+
+use super::Inst;
+
+/// A place in code that jumps can go to
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Label(pub u16);
+
+/// Where each label is placed, if it is yet
+#[derive(Debug, Clone, Default)]
+pub struct Labels {
+    places: Vec<Option<usize>>,
+}
+
+impl Labels {
+    pub fn new() -> Labels {
+        Labels::default()
+    }
+
+    /// Label every jump target of `ins`, placing the labels where the
+    /// targets are, and make the jumps target the labels
+    pub fn of_jumps(ins: &mut [Inst]) -> Labels {
+        let mut labels = Labels::new();
+        let mut label_at = std::collections::BTreeMap::new();
+        for inst in ins.iter_mut() {
+            if let Some(to) = jump_target(inst) {
+                let label = *label_at.entry(to).or_insert_with(|| {
+                    let label = labels.new_label();
+                    labels.place(label, to as usize);
+                    label
+                });
+                set_jump_target(inst, label.0);
+            }
+        }
+        labels
+    }
+
+    /// A label that is not placed yet
+    pub fn new_label(&mut self) -> Label {
+        self.places.push(None);
+        Label((self.places.len() - 1) as u16)
+    }
+
+    /// Put `label` at instruction `at`
+    pub fn place(&mut self, label: Label, at: usize) {
+        self.places[label.0 as usize] = Some(at);
+    }
+
+    /// Where `label` is, if it is placed
+    pub fn place_of(&self, label: Label) -> Option<usize> {
+        self.places.get(label.0 as usize).copied().flatten()
+    }
+
+    /// Labels that are placed, and where
+    pub fn placed(&self) -> impl Iterator<Item = (Label, usize)> + '_ {
+        (self.places.iter().enumerate()).filter_map(|(idx, at)| Some((Label(idx as u16), (*at)?)))
+    }
+
+    /// Move every placed label from instruction `at` to `to(at)`
+    pub fn move_all(&mut self, to: impl Fn(usize) -> usize) {
+        for at in self.places.iter_mut().flatten() {
+            *at = to(*at);
+        }
+    }
+
+    /// Replace the label each jump of `ins` targets with where it is.
+    /// Fails with the first label that is not placed.
+    pub fn resolve(&self, ins: &mut [Inst]) -> Result<(), Label> {
+        for inst in ins.iter_mut() {
+            if let Some(label) = jump_target(inst).map(Label) {
+                let at = self.place_of(label).ok_or(label)?;
+                set_jump_target(inst, at as u16);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Where `inst` may jump to, if it is a jump
+pub fn jump_target(inst: &Inst) -> Option<u16> {
+    use Inst::*;
+    match *inst {
+        Jmp(to) | JE(to) | JNe(to) | JL(to) | JGe(to) | JG(to) | JLe(to) => Some(to),
+        _ => None,
+    }
+}
+
+/// Make jump `inst` go to `to`. Other instructions are left alone.
+pub fn set_jump_target(inst: &mut Inst, to: u16) {
+    use Inst::*;
+    match inst {
+        Jmp(t) | JE(t) | JNe(t) | JL(t) | JGe(t) | JG(t) | JLe(t) => *t = to,
+        _ => (),
+    }
+}
@@ -0,0 +1,632 @@
+//! Dataflow over the locals of one function: constants, copies and dead
+//! stores.
+//!
+//! Runs after basic blocks are laid out, before [peephole](super::peephole)
+//! rewrites. Starting from the first instruction, it works out what each
+//! local holds before each instruction, merging what each path into it
+//! knows: a constant, the same value as another local, or nothing.
+//!
+//! - Sparse conditional constant propagation follows only the jumps that can
+//!   go: a conditional jump on a condition known to be a constant goes one
+//!   way. So a local set to the same constant on every path into a test
+//!   makes the test known, and the arm it never takes is never followed.
+//!   Loads of locals known to be constant push the constant instead, tests
+//!   known to go one way jump or fall through without testing, and
+//!   instructions never reached are dropped.
+//! - Copy propagation loads a local holding a copy of another, as `b` does
+//!   after `b = a;`, from the local it was copied from, as long as neither
+//!   is written in between.
+//! - Dead store elimination drops stores to locals never read again before
+//!   they are written or the function returns, keeping only what computing
+//!   the value does besides. Loads turned into loads of another local leave
+//!   many of those.
+//!
+//! A local whose address is used other than to load or store it, such as
+//! passed to a function, may change anywhere, and is never known or dropped.
+
+use super::codegen::InstSink;
+use super::label::{jump_target, set_jump_target, Labels};
+use super::passes::Pass;
+use super::peephole::{drops_unreached, fold, taken};
+use super::value::{UnOp, Value};
+use super::Inst;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Which rewrites to make, by the pass they belong to
+#[derive(Debug, Clone, Copy)]
+struct Rules {
+    sccp: bool,
+    copies: bool,
+    dse: bool,
+}
+
+/// What is known of a value on the stack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Val {
+    Const(i32),
+    /// Address of the local at this offset, pushed by the instruction at
+    /// this index
+    Addr(i32, usize),
+    /// What the local at this offset holds now
+    Local(i32),
+    Any,
+}
+
+/// What is known before an instruction runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct State {
+    stack: Vec<Val>,
+    /// Locals known to hold a constant, by offset
+    consts: BTreeMap<i32, i32>,
+    /// Locals known to hold the same as another, by offset
+    copies: BTreeMap<i32, i32>,
+}
+
+/// What was changed, to remark on
+#[derive(Debug, Clone, Copy, Default)]
+struct Changes {
+    /// Loads of locals replaced by the constant they hold
+    consts: usize,
+    /// Tests known to go one way
+    tests: usize,
+    /// Instructions dropped as never reached
+    unreached: usize,
+    /// Loads of copies made from the locals they copy
+    copies: usize,
+    /// Stores dropped as never read
+    stores: usize,
+}
+
+/// Where control goes after an instruction
+enum Next {
+    Fall,
+    Jump(u16),
+    /// A conditional jump, and whether it goes if that is known
+    Branch(u16, Option<bool>),
+    Return,
+}
+
+/// Optimize `sink` with those of `passes` working on locals, returning what
+/// each pass that changed something did. `effect` gives how many slots a
+/// call or a constant pops and pushes; where it doesn't know, nothing
+/// changes. With `calls_write`, calls may write every local, as in start
+/// code, whose locals are the globals functions read.
+pub(super) fn optimize(
+    sink: &mut InstSink,
+    passes: &[Pass],
+    calls_write: bool,
+    effect: impl Fn(&Inst) -> Option<(usize, usize)>,
+) -> Vec<(Pass, String)> {
+    let rules = Rules {
+        sccp: passes.contains(&Pass::Sccp),
+        copies: passes.contains(&Pass::CopyProp),
+        dse: passes.contains(&Pass::Dse) && !calls_write,
+    };
+    let mut changes = Changes::default();
+    if rules.sccp || rules.copies {
+        if let Some(analysis) = Analysis::of(sink.inner(), &effect, calls_write, rules.sccp) {
+            let edits = propagate(&analysis, rules, &mut changes);
+            apply(sink, edits);
+        }
+    }
+    // * Loads propagation took from other locals read those instead
+    if rules.dse {
+        if let Some(analysis) = Analysis::of(sink.inner(), &effect, calls_write, false) {
+            let mut edits = vec![None; analysis.ins.len()];
+            for (store, addr) in analysis.dead_stores() {
+                // * The value stored is still computed, and dropped
+                edits[store] = Some(match analysis.ins[store] {
+                    Inst::DStore => vec![Inst::Pop2],
+                    _ => vec![Inst::Pop1],
+                });
+                edits[addr] = Some(vec![]);
+                changes.stores += 1;
+            }
+            apply(sink, edits);
+        }
+    }
+
+    let sccp = [
+        (
+            changes.consts,
+            "load of a constant local",
+            "loads of constant locals",
+            "folded",
+        ),
+        (changes.tests, "test", "tests", "decided when compiling"),
+        (
+            changes.unreached,
+            "instruction",
+            "instructions",
+            "never reached dropped",
+        ),
+    ];
+    let sccp: Vec<_> = (sccp.iter())
+        .filter(|(n, ..)| *n > 0)
+        .map(|&(n, one, many, done)| count(n, one, many, done))
+        .collect();
+    let mut done = vec![];
+    if !sccp.is_empty() {
+        done.push((Pass::Sccp, sccp.join(", ")));
+    }
+    if changes.copies > 0 {
+        let copies = "made from the local copied";
+        let message = count(changes.copies, "load of a copy", "loads of copies", copies);
+        done.push((Pass::CopyProp, message));
+    }
+    if changes.stores > 0 {
+        let stores = "never read again dropped";
+        done.push((Pass::Dse, count(changes.stores, "store", "stores", stores)));
+    }
+    done
+}
+
+/// `n` things, called `one` or `many`, that were `done`
+fn count(n: usize, one: &str, many: &str, done: &str) -> String {
+    format!("{} {} {}", n, if n == 1 { one } else { many }, done)
+}
+
+/// What to replace each instruction with to propagate constants and copies,
+/// `None` to keep it
+fn propagate<F>(
+    analysis: &Analysis<F>,
+    rules: Rules,
+    changes: &mut Changes,
+) -> Vec<Option<Vec<Inst>>> {
+    let ins = analysis.ins;
+    let mut is_target = vec![false; ins.len() + 1];
+    for to in ins.iter().filter_map(jump_target) {
+        if let Some(t) = is_target.get_mut(to as usize) {
+            *t = true;
+        }
+    }
+    let drop_unreached = drops_unreached(ins, |idx| analysis.states[idx].is_some());
+    let mut edits = vec![None; ins.len()];
+    for (idx, &inst) in ins.iter().enumerate() {
+        let state = match &analysis.states[idx] {
+            Some(state) => state,
+            None if rules.sccp && drop_unreached => {
+                edits[idx] = Some(vec![]);
+                changes.unreached += 1;
+                continue;
+            }
+            None => continue,
+        };
+        edits[idx] = match (inst, state.stack.last()) {
+            // * Only a load right after its address, with no jump between
+            (Inst::LoadA(0, off), _)
+                if ins.get(idx + 1) == Some(&Inst::ILoad) && !is_target[idx + 1] =>
+            {
+                match (state.consts.get(&off), state.copies.get(&off)) {
+                    (Some(val), _) if rules.sccp => {
+                        edits[idx + 1] = Some(vec![]);
+                        changes.consts += 1;
+                        Some(vec![Inst::IPush(*val)])
+                    }
+                    (_, Some(from)) if rules.copies => {
+                        changes.copies += 1;
+                        Some(vec![Inst::LoadA(0, *from)])
+                    }
+                    _ => None,
+                }
+            }
+            (Inst::ILoad, _) if edits[idx].is_some() => continue,
+            (_, Some(Val::Const(v))) if rules.sccp => {
+                let taken = taken(&inst, *v);
+                changes.tests += taken.is_some() as usize;
+                match taken {
+                    Some(true) => Some(vec![Inst::Pop1, Inst::Jmp(jump_target(&inst).unwrap())]),
+                    Some(false) => Some(vec![Inst::Pop1]),
+                    None => None,
+                }
+            }
+            _ => None,
+        };
+    }
+    edits
+}
+
+/// Replace each instruction of `sink` with its edit, if it has one, moving
+/// jumps along. Jumps in edits go where the jumps they replace do.
+fn apply(sink: &mut InstSink, edits: Vec<Option<Vec<Inst>>>) {
+    if edits.iter().all(Option::is_none) {
+        return;
+    }
+    let (ins, lines) = sink.parts_mut();
+    let mut labels = Labels::of_jumps(ins);
+    let mut out = Vec::with_capacity(ins.len());
+    let mut out_lines = Vec::with_capacity(ins.len());
+    let mut new_idx = vec![0; ins.len() + 1];
+    for (idx, edit) in edits.into_iter().enumerate() {
+        new_idx[idx] = out.len();
+        let mut new = edit.unwrap_or_else(|| vec![ins[idx]]);
+        // * Jumps of `ins` now target labels
+        if let Some(label) = jump_target(&ins[idx]) {
+            new.iter_mut().for_each(|inst| set_jump_target(inst, label));
+        }
+        out_lines.extend(std::iter::repeat_n(lines[idx], new.len()));
+        out.extend(new);
+    }
+    new_idx[ins.len()] = out.len();
+
+    labels.move_all(|at| new_idx[at]);
+    labels
+        .resolve(&mut out)
+        .expect("Every jump target is labeled");
+    *ins = out;
+    *lines = out_lines;
+    // * A block with nothing left starts where the next one does
+    for (_, at) in sink.blocks_mut() {
+        *at = new_idx[*at];
+    }
+}
+
+struct Analysis<'a, F> {
+    ins: &'a [Inst],
+    effect: &'a F,
+    calls_write: bool,
+    /// Whether to follow only the way a test known when compiling goes
+    prune: bool,
+    /// Locals whose address escapes
+    escaped: BTreeSet<i32>,
+    /// Instructions pushing an address that is copied on the stack
+    shared: BTreeSet<usize>,
+    /// Instructions using each address pushed, by where it is pushed
+    uses: BTreeMap<usize, BTreeSet<usize>>,
+    /// What is known before each instruction, `None` for those never
+    /// reached
+    states: Vec<Option<State>>,
+    /// Where control may go after each instruction
+    succs: Vec<Vec<usize>>,
+}
+
+impl<'a, F: Fn(&Inst) -> Option<(usize, usize)>> Analysis<'a, F> {
+    /// Find what is known before each instruction of `ins`, following only
+    /// the way tests known when compiling go with `prune`. `None` if the
+    /// code is not understood.
+    fn of(ins: &'a [Inst], effect: &'a F, calls_write: bool, prune: bool) -> Option<Self> {
+        let mut escaped = BTreeSet::new();
+        loop {
+            let mut analysis = Analysis {
+                ins,
+                effect,
+                calls_write,
+                prune,
+                escaped: escaped.clone(),
+                shared: BTreeSet::new(),
+                uses: BTreeMap::new(),
+                states: vec![None; ins.len()],
+                succs: vec![vec![]; ins.len()],
+            };
+            analysis.run()?;
+            // * Locals found to escape on the way may have been written before
+            if analysis.escaped == escaped {
+                return Some(analysis);
+            }
+            escaped = analysis.escaped;
+        }
+    }
+
+    fn run(&mut self) -> Option<()> {
+        let mut pending = vec![];
+        let entry = State {
+            stack: vec![],
+            consts: BTreeMap::new(),
+            copies: BTreeMap::new(),
+        };
+        self.reach(&mut pending, 0, entry)?;
+        while let Some(idx) = pending.pop() {
+            let mut state = self.states[idx].clone()?;
+            let succs = match self.step(idx, &mut state)? {
+                Next::Fall => vec![idx + 1],
+                Next::Jump(to) => vec![to as usize],
+                Next::Branch(to, Some(true)) if self.prune => vec![to as usize],
+                Next::Branch(_, Some(false)) if self.prune => vec![idx + 1],
+                Next::Branch(to, _) => vec![idx + 1, to as usize],
+                Next::Return => vec![],
+            };
+            for &succ in &succs {
+                self.reach(&mut pending, succ, state.clone())?;
+            }
+            self.succs[idx] = succs;
+        }
+        Some(())
+    }
+
+    /// Merge `state` into what is known before instruction `idx`, and look
+    /// at it again if that changes. `None` if stacks of different sizes
+    /// meet.
+    fn reach(&mut self, pending: &mut Vec<usize>, idx: usize, state: State) -> Option<()> {
+        // * Falling off the end is left for the verifier to report
+        let known = match self.states.get(idx) {
+            Some(known) => known.clone(),
+            None => return Some(()),
+        };
+        let merged = match known {
+            None => state,
+            Some(mut merged) => {
+                if merged.stack.len() != state.stack.len() {
+                    return None;
+                }
+                for (a, b) in merged.stack.iter_mut().zip(&state.stack) {
+                    if a != b {
+                        for val in [*a, *b] {
+                            self.consume(val, idx);
+                        }
+                        *a = Val::Any;
+                    }
+                }
+                (merged.consts).retain(|off, val| state.consts.get(off) == Some(val));
+                (merged.copies).retain(|off, from| state.copies.get(off) == Some(from));
+                merged
+            }
+        };
+        if self.states[idx].as_ref() != Some(&merged) {
+            self.states[idx] = Some(merged);
+            pending.push(idx);
+        }
+        Some(())
+    }
+
+    /// Note that `val` is used by instruction `at` other than to load or
+    /// store through it
+    fn consume(&mut self, val: Val, at: usize) {
+        if let Val::Addr(off, from) = val {
+            self.escaped.insert(off);
+            self.uses.entry(from).or_default().insert(at);
+        }
+    }
+
+    /// Pop `n` values used up by instruction `at`
+    fn pop(&mut self, stack: &mut Vec<Val>, n: usize, at: usize) -> Option<Vec<Val>> {
+        let vals = stack.split_off(stack.len().checked_sub(n)?);
+        vals.iter().for_each(|val| self.consume(*val, at));
+        Some(vals)
+    }
+
+    /// Pop the address instruction `at` loads or stores through, and the
+    /// local it is of
+    fn pop_addr(&mut self, stack: &mut Vec<Val>, at: usize) -> Option<Option<i32>> {
+        Some(match stack.pop()? {
+            Val::Addr(off, from) => {
+                self.uses.entry(from).or_default().insert(at);
+                Some(off).filter(|off| !self.escaped.contains(off))
+            }
+            _ => None,
+        })
+    }
+
+    /// Apply instruction `idx` to `state`. `None` if the code is not
+    /// understood.
+    fn step(&mut self, idx: usize, state: &mut State) -> Option<Next> {
+        use Inst::*;
+        let inst = self.ins[idx];
+        let any = |stack: &mut Vec<Val>, n: usize| stack.extend(std::iter::repeat_n(Val::Any, n));
+        match inst {
+            Nop => (),
+            IPush(v) => state.stack.push(Val::Const(v)),
+            CPush(v) => state.stack.push(Val::Const(v as i32)),
+            // * Values dropped don't escape
+            Pop1 | Pop2 | PopN(_) => {
+                let n = match inst {
+                    PopN(n) => n as usize,
+                    Pop2 => 2,
+                    _ => 1,
+                };
+                let at = state.stack.len().checked_sub(n)?;
+                for val in state.stack.split_off(at) {
+                    if let Val::Addr(_, from) = val {
+                        self.uses.entry(from).or_default().insert(idx);
+                    }
+                }
+            }
+            Dup | Dup2 => {
+                let n = if inst == Dup { 1 } else { 2 };
+                let at = state.stack.len().checked_sub(n)?;
+                for i in at..at + n {
+                    let val = state.stack[i];
+                    if let Val::Addr(_, from) = val {
+                        self.shared.insert(from);
+                    }
+                    state.stack.push(val);
+                }
+            }
+            LoadA(0, off) => state.stack.push(Val::Addr(off, idx)),
+            LoadA(..) => state.stack.push(Val::Any),
+            SNew(n) => any(&mut state.stack, n as usize),
+            ILoad => {
+                let val = match self.pop_addr(&mut state.stack, idx)? {
+                    Some(off) => match (state.consts.get(&off), state.copies.get(&off)) {
+                        (Some(v), _) => Val::Const(*v),
+                        (_, Some(from)) => Val::Local(*from),
+                        _ => Val::Local(off),
+                    },
+                    None => Val::Any,
+                };
+                state.stack.push(val);
+            }
+            DLoad | ALoad => {
+                self.pop_addr(&mut state.stack, idx)?;
+                any(&mut state.stack, if inst == DLoad { 2 } else { 1 });
+            }
+            IStore | DStore | AStore => {
+                let size = if inst == DStore { 2 } else { 1 };
+                let val = self.pop(&mut state.stack, size, idx)?;
+                if let Some(off) = self.pop_addr(&mut state.stack, idx)? {
+                    forget(state, off);
+                    if inst == DStore {
+                        forget(state, off + 1);
+                    }
+                    match (inst, &val[..]) {
+                        (IStore, [Val::Const(v)]) => {
+                            state.consts.insert(off, *v);
+                        }
+                        (IStore, [Val::Local(from)]) if *from != off => {
+                            state.copies.insert(off, *from);
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            IAdd | ISub | IMul | IDiv | ICmp => {
+                let val = match self.pop(&mut state.stack, 2, idx)?[..] {
+                    [Val::Const(a), Val::Const(b)] => fold(a, b, inst).map_or(Val::Any, Val::Const),
+                    _ => Val::Any,
+                };
+                state.stack.push(val);
+            }
+            INeg => {
+                let val = match self.pop(&mut state.stack, 1, idx)?[..] {
+                    [Val::Const(a)] => match Value::Int(a).unary(UnOp::Neg) {
+                        Ok(Value::Int(v)) => Val::Const(v),
+                        _ => Val::Any,
+                    },
+                    _ => Val::Any,
+                };
+                state.stack.push(val);
+            }
+            Jmp(to) => return Some(Next::Jump(to)),
+            JE(to) | JNe(to) | JL(to) | JGe(to) | JG(to) | JLe(to) => {
+                let goes = match self.pop(&mut state.stack, 1, idx)?[..] {
+                    [Val::Const(v)] => taken(&inst, v),
+                    _ => None,
+                };
+                return Some(Next::Branch(to, goes));
+            }
+            Ret | IRet | DRet | ARet => return Some(Next::Return),
+            LoadC(_) | _Call(_) => {
+                let (pops, pushes) = (self.effect)(&inst)?;
+                self.pop(&mut state.stack, pops, idx)?;
+                any(&mut state.stack, pushes);
+                if matches!(inst, _Call(_)) && self.calls_write {
+                    state.consts.clear();
+                    state.copies.clear();
+                    for val in state.stack.iter_mut() {
+                        if let Val::Local(_) = val {
+                            *val = Val::Any;
+                        }
+                    }
+                }
+            }
+            _Gt | _Lt | _Eq | _Gte | _Lte | _Neq | Call(_) => return None,
+            inst => {
+                let (pops, pushes) = effect_of(inst)?;
+                self.pop(&mut state.stack, pops, idx)?;
+                any(&mut state.stack, pushes);
+            }
+        }
+        Some(Next::Fall)
+    }
+
+    /// Locals instruction `idx` reads, and those it writes
+    fn reads_writes(&self, idx: usize) -> (Vec<i32>, Vec<i32>) {
+        use Inst::*;
+        let stack = match &self.states[idx] {
+            Some(state) => &state.stack,
+            None => return (vec![], vec![]),
+        };
+        // * Where the address is on the stack, and how many slots go through it
+        let (depth, slots, writes) = match self.ins[idx] {
+            ILoad | ALoad => (1, 1, false),
+            DLoad => (1, 2, false),
+            IStore | AStore => (2, 1, true),
+            DStore => (3, 2, true),
+            _ => return (vec![], vec![]),
+        };
+        let locals = match stack.len().checked_sub(depth).map(|at| stack[at]) {
+            Some(Val::Addr(off, _)) => (off..off + slots).collect(),
+            _ => vec![],
+        };
+        match writes {
+            true => (vec![], locals),
+            false => (locals, vec![]),
+        }
+    }
+
+    /// Stores to locals never read afterwards, and where the address each
+    /// stores through is pushed
+    fn dead_stores(&self) -> BTreeMap<usize, usize> {
+        let len = self.ins.len();
+        let mut preds = vec![vec![]; len];
+        for (idx, succs) in self.succs.iter().enumerate() {
+            for &succ in succs.iter().filter(|succ| **succ < len) {
+                preds[succ].push(idx);
+            }
+        }
+        // * Live before each instruction; locals die when the function returns
+        let mut live: Vec<BTreeSet<i32>> = vec![BTreeSet::new(); len];
+        let live_after = |live: &[BTreeSet<i32>], idx: usize| -> BTreeSet<i32> {
+            let succs = self.succs[idx].iter().filter(|succ| **succ < len);
+            succs.flat_map(|succ| live[*succ].iter().copied()).collect()
+        };
+        let mut pending: Vec<usize> = (0..len).collect();
+        while let Some(idx) = pending.pop() {
+            let (reads, writes) = self.reads_writes(idx);
+            let mut before = live_after(&live, idx);
+            writes.iter().for_each(|off| {
+                before.remove(off);
+            });
+            before.extend(reads);
+            if before != live[idx] {
+                live[idx] = before;
+                pending.extend(&preds[idx]);
+            }
+        }
+
+        let mut dead = BTreeMap::new();
+        for idx in 0..len {
+            let (_, writes) = self.reads_writes(idx);
+            // * A local whose address escapes may be read through it anywhere
+            let after = live_after(&live, idx);
+            let read = |off: &i32| after.contains(off) || self.escaped.contains(off);
+            if writes.is_empty() || writes.iter().any(read) {
+                continue;
+            }
+            let stack = &self.states[idx].as_ref().unwrap().stack;
+            let depth = if self.ins[idx] == Inst::DStore { 3 } else { 2 };
+            // * Only an address pushed for this store alone is dropped along
+            if let Val::Addr(off, from) = stack[stack.len() - depth] {
+                let alone = self.uses.get(&from).is_some_and(|uses| uses.len() == 1);
+                if alone && !self.shared.contains(&from) && self.ins[from] == Inst::LoadA(0, off) {
+                    dead.insert(idx, from);
+                }
+            }
+        }
+        dead
+    }
+}
+
+/// Forget what is known of the local at `off`, which is written
+fn forget(state: &mut State, off: i32) {
+    state.consts.remove(&off);
+    state.copies.retain(|to, from| *to != off && *from != off);
+    for val in state.stack.iter_mut() {
+        if *val == Val::Local(off) {
+            *val = Val::Any;
+        }
+    }
+}
+
+/// Slots the other instructions pop and push, none of whose results are
+/// known
+fn effect_of(inst: Inst) -> Option<(usize, usize)> {
+    use Inst::*;
+    Some(match inst {
+        New | I2C => (1, 1),
+        IALoad | AALoad => (2, 1),
+        DALoad => (2, 2),
+        IAStore | AAStore => (3, 0),
+        DAStore => (4, 0),
+        DAdd | DSub | DMul | DDiv => (4, 2),
+        DNeg => (2, 2),
+        DCmp => (4, 1),
+        I2D => (1, 2),
+        D2I => (2, 1),
+        IPrint | CPrint | SPrint => (1, 0),
+        DPrint => (2, 0),
+        PrintLn => (0, 0),
+        IScan | CScan => (0, 1),
+        DScan => (0, 2),
+        _ => return None,
+    })
+}
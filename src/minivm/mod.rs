@@ -1,7 +1,37 @@
 pub mod codegen;
+pub mod coverage;
+mod cse;
+pub mod disasm;
 pub mod err;
+mod gvn;
 mod instgen;
+pub mod instrument;
+pub mod label;
+mod locals;
+pub mod passes;
+mod peephole;
+pub mod pgo;
+pub mod reloc;
+pub mod remarks;
+mod schedule;
+pub mod size;
+pub mod standard;
+pub mod target;
+mod unroll;
+pub mod verify;
 
 pub use chigusa_minivm::*;
 pub use codegen::*;
+pub use coverage::*;
+pub use disasm::*;
 pub use err::*;
+pub use instrument::{CounterMap, DUMP_MARK};
+pub use label::*;
+pub use passes::{CompilerPass, OptFilter, Pass, PassError, PassManager};
+pub use pgo::*;
+pub use reloc::*;
+pub use remarks::*;
+pub use size::*;
+pub use standard::*;
+pub use target::*;
+pub use verify::*;
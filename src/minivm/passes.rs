@@ -0,0 +1,473 @@
+//! Which optimizations run, and on which functions.
+//!
+//! Passes are known to a [`PassManager`] by name, as analyses computing facts
+//! about the program or transforms changing its code. A pipeline picks the
+//! passes to run, by `-O` level or by name, along with the analyses they
+//! need. Each pass runs where it fits in code generation, so the order of a
+//! pipeline is the order passes are registered in, not the order they are
+//! named in. Crates embedding the compiler add passes of their own as
+//! [`CompilerPass`]es.
+//!
+//! Code is optimized one function at a time: start code first, then
+//! functions in the order they are declared. Optimizing only the first `n`
+//! of them, and halving the range of `n` until the program works again,
+//! finds the first function whose optimized code is wrong. Running one pass
+//! at a time on it then finds the pass.
+
+use super::codegen::InstSink;
+use super::err::{CompileError, CompileErrorVar, CompileResult};
+use crate::c0::ast;
+use std::fmt;
+use std::rc::Rc;
+
+/// Name start code goes by
+pub const START: &str = ".start";
+
+/// An analysis or optimization of the code, in the order they run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Pass {
+    /// Which variables each reference may refer to
+    Alias,
+    /// Which functions have no side effects
+    Purity,
+    /// Computing the same pure call only once
+    Cse,
+    /// Computing the same value once across the blocks of a function
+    Gvn,
+    /// Unrolling loops running a known number of times
+    Unroll,
+    /// Inlining functions called often in a profile
+    Inline,
+    /// Laying out blocks so that branches fall through
+    Layout,
+    /// Propagating constants through locals along the paths that can run
+    Sccp,
+    /// Loading copies of locals from the locals they were copied from
+    CopyProp,
+    /// Dropping stores to locals never read afterwards
+    Dse,
+    /// Computing arithmetic on constants, and jumps on constant conditions
+    Fold,
+    /// Dropping code that never runs and values never used
+    Dce,
+    /// Rewriting short sequences of instructions, and threading jumps
+    Peephole,
+}
+
+impl Pass {
+    pub const ALL: [Pass; 13] = [
+        Pass::Alias,
+        Pass::Purity,
+        Pass::Cse,
+        Pass::Gvn,
+        Pass::Unroll,
+        Pass::Inline,
+        Pass::Layout,
+        Pass::Sccp,
+        Pass::CopyProp,
+        Pass::Dse,
+        Pass::Fold,
+        Pass::Dce,
+        Pass::Peephole,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Pass::Alias => "alias",
+            Pass::Purity => "purity",
+            Pass::Cse => "cse",
+            Pass::Gvn => "gvn",
+            Pass::Unroll => "unroll",
+            Pass::Inline => "inline",
+            Pass::Layout => "layout",
+            Pass::Sccp => "sccp",
+            Pass::CopyProp => "copyprop",
+            Pass::Dse => "dse",
+            Pass::Fold => "fold",
+            Pass::Dce => "dce",
+            Pass::Peephole => "peephole",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Pass> {
+        Pass::ALL.iter().copied().find(|p| p.name() == name)
+    }
+}
+
+/// Whether a pass changes code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PassKind {
+    /// Computes facts about the program for other passes
+    Analysis,
+    /// Changes the code generated
+    Transform,
+}
+
+/// A pass known to a [`PassManager`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PassInfo {
+    pub pass: Pass,
+    pub kind: PassKind,
+    /// Analyses run whenever this pass is
+    pub requires: Vec<Pass>,
+    /// What the pass does, in a line
+    pub about: &'static str,
+}
+
+impl PassInfo {
+    pub fn name(&self) -> &'static str {
+        self.pass.name()
+    }
+}
+
+/// A pass of a crate embedding the compiler, such as one instrumenting
+/// code for a research project.
+///
+/// Registered with [`PassManager::with_pass`], it runs after the built-in
+/// passes when its name is in the pipeline. Code it changes is checked like
+/// all generated code, so a pass leaving the stack wrong fails compiling
+/// instead of producing a broken binary.
+pub trait CompilerPass {
+    /// Name the pass goes by in pipelines
+    fn name(&self) -> &str;
+
+    /// What the pass does, in a line
+    fn about(&self) -> &str {
+        ""
+    }
+
+    /// Look at or change `prog` before code is generated for it, when
+    /// [`PassManager::run_on_program`] is called. An error stops compiling.
+    fn run_on_program(&self, _prog: &mut ast::Program) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Look at or change the code of function `name`, or of start code as
+    /// [`START`], once the built-in passes are done with it. Jumps hold
+    /// instruction indices; [`InstSink::insert_before`] moves them along.
+    /// An error stops compiling.
+    fn run_on_function(&self, _name: &str, _code: &mut InstSink) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Why passes can't be registered or put in a pipeline
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum PassError {
+    /// A pass that is not registered, and the names of those that are
+    Unknown(String, Vec<String>),
+    /// A pass registered under the name of another
+    Duplicate(String),
+}
+
+impl fmt::Display for PassError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PassError::Unknown(name, known) => write!(
+                f,
+                "Unknown pass `{}`. Allowed are: {}",
+                name,
+                known.join(", ")
+            ),
+            PassError::Duplicate(name) => write!(f, "A pass named `{}` exists already", name),
+        }
+    }
+}
+
+/// The error of `pass` failing with `msg`
+pub(super) fn failed(pass: &dyn CompilerPass, msg: String) -> CompileError {
+    CompileErrorVar::Error(format!("Pass `{}` failed: {}", pass.name(), msg)).into()
+}
+
+/// A pass of another crate, and whether the pipeline has it
+#[derive(Clone)]
+struct Custom {
+    pass: Rc<dyn CompilerPass>,
+    runs: bool,
+}
+
+/// Passes known by name, and the pipeline of those to run
+#[derive(Clone)]
+pub struct PassManager {
+    registry: Vec<PassInfo>,
+    pipeline: Vec<Pass>,
+    /// Passes of other crates, in the order they run
+    custom: Vec<Custom>,
+}
+
+impl fmt::Debug for PassManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let custom: Vec<_> = self.custom_pipeline().map(|p| p.name()).collect();
+        f.debug_struct("PassManager")
+            .field("pipeline", &self.pipeline)
+            .field("custom", &custom)
+            .finish()
+    }
+}
+
+impl Default for PassManager {
+    /// Every pass of `-O1`
+    fn default() -> PassManager {
+        PassManager::for_level(1).expect("-O1 exists")
+    }
+}
+
+impl PassManager {
+    /// A manager knowing every built-in pass, running none
+    pub fn new() -> PassManager {
+        use Pass::*;
+        use PassKind::*;
+        let mut pm = PassManager {
+            registry: vec![],
+            pipeline: vec![],
+            custom: vec![],
+        };
+        let builtin: [(Pass, PassKind, &[Pass], &str); 13] = [
+            (
+                Alias,
+                Analysis,
+                &[],
+                "which variables each reference may refer to",
+            ),
+            (
+                Purity,
+                Analysis,
+                &[],
+                "which functions have no side effects",
+            ),
+            (
+                Cse,
+                Transform,
+                &[Alias, Purity],
+                "compute the same pure call once",
+            ),
+            (
+                Gvn,
+                Transform,
+                &[Alias, Purity],
+                "compute the same value once across blocks",
+            ),
+            (
+                Unroll,
+                Transform,
+                &[],
+                "unroll loops running a known number of times",
+            ),
+            (
+                Inline,
+                Transform,
+                &[],
+                "inline small functions called often in a profile",
+            ),
+            (
+                Layout,
+                Transform,
+                &[],
+                "lay out blocks so that branches fall through",
+            ),
+            (
+                Sccp,
+                Transform,
+                &[],
+                "propagate constants and drop branches never taken",
+            ),
+            (
+                CopyProp,
+                Transform,
+                &[],
+                "load copies of locals from where they were copied",
+            ),
+            (
+                Dse,
+                Transform,
+                &[],
+                "drop stores to locals never read again",
+            ),
+            (
+                Fold,
+                Transform,
+                &[],
+                "compute arithmetic and conditions on constants",
+            ),
+            (
+                Dce,
+                Transform,
+                &[],
+                "drop code that never runs and values never used",
+            ),
+            (
+                Peephole,
+                Transform,
+                &[],
+                "rewrite short sequences of instructions",
+            ),
+        ];
+        for (pass, kind, requires, about) in builtin {
+            pm.register(PassInfo {
+                pass,
+                kind,
+                requires: requires.to_vec(),
+                about,
+            });
+        }
+        pm
+    }
+
+    /// Make `info` known, replacing a pass of the same name
+    fn register(&mut self, info: PassInfo) {
+        match self.registry.iter_mut().find(|p| p.pass == info.pass) {
+            Some(known) => *known = info,
+            None => self.registry.push(info),
+        }
+    }
+
+    /// The pipeline of optimization level `level`, if there is one: `0` runs
+    /// nothing, `1` every pass but value numbering, `2` every pass
+    pub fn for_level(level: u8) -> Option<PassManager> {
+        let mut pm = PassManager::new();
+        let passes = pm.registry.iter().map(|p| p.pass);
+        match level {
+            0 => (),
+            1 => pm.pipeline = passes.filter(|p| *p != Pass::Gvn).collect(),
+            2 => pm.pipeline = passes.collect(),
+            _ => return None,
+        }
+        Some(pm)
+    }
+
+    /// Register `pass`, running it after the built-in passes and those
+    /// registered before it
+    pub fn with_pass(
+        mut self,
+        pass: impl CompilerPass + 'static,
+    ) -> Result<PassManager, PassError> {
+        if self.names().iter().any(|name| name == pass.name()) {
+            return Err(PassError::Duplicate(pass.name().into()));
+        }
+        self.custom.push(Custom {
+            pass: Rc::new(pass),
+            runs: true,
+        });
+        Ok(self)
+    }
+
+    /// Run only the passes named in `names`, separated by commas, as in
+    /// `"fold,dce,cse"`, and the analyses they need
+    pub fn with_pipeline(mut self, names: &str) -> Result<PassManager, PassError> {
+        let mut pipeline = vec![];
+        let mut custom = vec![];
+        for name in names.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            if let Some(info) = self.get(name) {
+                pipeline.extend(info.requires.iter().copied());
+                pipeline.push(info.pass);
+            } else if self.custom.iter().any(|c| c.pass.name() == name) {
+                custom.push(name);
+            } else {
+                return Err(PassError::Unknown(name.into(), self.names()));
+            }
+        }
+        let registry = &self.registry;
+        self.pipeline = registry
+            .iter()
+            .map(|p| p.pass)
+            .filter(|p| pipeline.contains(p))
+            .collect();
+        for c in self.custom.iter_mut() {
+            c.runs = custom.contains(&c.pass.name());
+        }
+        Ok(self)
+    }
+
+    /// Names of every pass known, built-in passes first
+    pub fn names(&self) -> Vec<String> {
+        let builtin = self.registry.iter().map(|p| p.name().to_string());
+        builtin
+            .chain(self.custom.iter().map(|c| c.pass.name().to_string()))
+            .collect()
+    }
+
+    /// Every pass known, in the order they run
+    pub fn passes(&self) -> &[PassInfo] {
+        &self.registry
+    }
+
+    /// The pass called `name`
+    pub fn get(&self, name: &str) -> Option<&PassInfo> {
+        self.registry.iter().find(|p| p.name() == name)
+    }
+
+    /// Passes to run, in the order they run
+    pub fn pipeline(&self) -> &[Pass] {
+        &self.pipeline
+    }
+
+    pub fn runs(&self, pass: Pass) -> bool {
+        self.pipeline.contains(&pass)
+    }
+
+    /// Passes of other crates to run, in the order they run
+    pub fn custom_pipeline(&self) -> impl Iterator<Item = &dyn CompilerPass> + '_ {
+        (self.custom.iter().filter(|c| c.runs)).map(|c| &*c.pass)
+    }
+
+    /// Run the passes of other crates over `prog`, before it is given to
+    /// [`Codegen`](super::Codegen)
+    pub fn run_on_program(&self, prog: &mut ast::Program) -> CompileResult<()> {
+        for pass in self.custom_pipeline() {
+            pass.run_on_program(prog).map_err(|e| failed(pass, e))?;
+        }
+        Ok(())
+    }
+}
+
+/// Which functions to optimize, and with what passes. By default, all of
+/// them with every pass.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct OptFilter {
+    /// Optimize only this many functions, in the order they are compiled
+    pub limit: Option<usize>,
+    /// Optimize only functions of these names, with [`START`] for start code
+    pub functions: Option<Vec<String>>,
+    pub passes: Vec<Pass>,
+}
+
+impl Default for OptFilter {
+    fn default() -> OptFilter {
+        OptFilter {
+            limit: None,
+            functions: None,
+            passes: Pass::ALL.to_vec(),
+        }
+    }
+}
+
+impl OptFilter {
+    /// Whether the function `name`, compiled `idx`th counting from 0, is
+    /// optimized at all
+    pub fn optimizes(&self, idx: usize, name: &str) -> bool {
+        self.limit.is_none_or(|limit| idx < limit)
+            && (self.functions.as_ref()).is_none_or(|fns| fns.iter().any(|f| f == name))
+    }
+
+    /// Passes to run on the function `name`, compiled `idx`th
+    pub fn passes_for(&self, idx: usize, name: &str) -> Vec<Pass> {
+        match self.optimizes(idx, name) {
+            true => self.passes.clone(),
+            false => vec![],
+        }
+    }
+}
+
+/// Start code and the functions of `prog` with a body, in the order they
+/// are compiled
+pub fn compile_order(prog: &ast::Program) -> Vec<String> {
+    let scope = prog.blk.scope.borrow();
+    let fns = scope.defs.iter().filter(|(_, def)| match &*def.borrow() {
+        ast::SymbolDef::Var { typ, .. } => {
+            matches!(&*typ.borrow(), ast::TypeDef::Function(f) if !f.is_extern)
+        }
+        _ => false,
+    });
+    let fns = fns.map(|(name, _)| name.to_string());
+    std::iter::once(START.to_string()).chain(fns).collect()
+}
@@ -0,0 +1,313 @@
+//! Peephole optimization over the instructions of one function.
+//!
+//! Runs after basic blocks are laid out, so jumps hold instruction indices.
+//! Every rewrite keeps the stack the same at each jump target, which is
+//! where instructions can be reached from more than one place.
+
+use super::codegen::InstSink;
+use super::label::{jump_target, set_jump_target, Label, Labels};
+use super::passes::Pass;
+use super::value::{BinOp, UnOp, Value};
+use super::Inst;
+
+/// Which rewrites to make, by the pass they belong to
+#[derive(Debug, Clone, Copy)]
+struct Rules {
+    fold: bool,
+    dce: bool,
+    peephole: bool,
+}
+
+/// Optimize `sink` with those of `passes` that rewrite instructions, until
+/// nothing changes
+pub(super) fn optimize(sink: &mut InstSink, passes: &[Pass]) {
+    let rules = Rules {
+        fold: passes.contains(&Pass::Fold),
+        dce: passes.contains(&Pass::Dce),
+        peephole: passes.contains(&Pass::Peephole),
+    };
+    let (ins, lines) = sink.parts_mut();
+    let mut moves = vec![];
+    loop {
+        let len = ins.len();
+        if rules.peephole {
+            thread_jumps(ins);
+        }
+        let (new_ins, new_lines, new_idx) = combine(ins, lines, rules);
+        *ins = new_ins;
+        *lines = new_lines;
+        moves.push(new_idx);
+        if ins.len() == len {
+            break;
+        }
+    }
+    // * A block with nothing left starts where the next one does
+    for (_, at) in sink.blocks_mut() {
+        *at = moves.iter().fold(*at, |at, new_idx| new_idx[at]);
+    }
+}
+
+fn ends_flow(inst: &Inst) -> bool {
+    matches!(
+        inst,
+        Inst::Jmp(_) | Inst::Ret | Inst::IRet | Inst::DRet | Inst::ARet
+    )
+}
+
+/// Point jumps to `jmp` at where that jumps to, and jumps to a test whose
+/// outcome is known coming from the jump at where the test goes
+fn thread_jumps(ins: &mut [Inst]) {
+    let mut is_target = vec![false; ins.len() + 1];
+    for to in ins.iter().filter_map(jump_target) {
+        if let Some(t) = is_target.get_mut(to as usize) {
+            *t = true;
+        }
+    }
+    for idx in 0..ins.len() {
+        let mut to = match jump_target(&ins[idx]) {
+            Some(to) => to,
+            None => continue,
+        };
+        // * A constant stored right before an unconditional jump, with
+        // * nothing else reaching the jump, is what the target loads
+        if let (Inst::Jmp(_), Some([Inst::LoadA(a, o), Inst::IPush(v), Inst::IStore])) =
+            (ins[idx], idx.checked_sub(3).map(|at| &ins[at..idx]))
+        {
+            if !is_target[idx - 2..=idx].contains(&true) {
+                if let Some(dest) = known_test(ins, to as usize, ((*a, *o), *v)) {
+                    to = dest as u16;
+                }
+            }
+        }
+        // * Chains longer than the function are loops of jumps; leave them
+        for _ in 0..ins.len() {
+            match ins.get(to as usize) {
+                Some(Inst::Jmp(next)) if *next != to => to = *next,
+                _ => break,
+            }
+        }
+        set_jump_target(&mut ins[idx], to);
+    }
+}
+
+/// Where the test at `at` goes when `slot` holds `val`, if it only computes
+/// a condition from constants and that slot before jumping on it
+fn known_test(ins: &[Inst], at: usize, (slot, val): ((u16, i32), i32)) -> Option<usize> {
+    let mut stack = vec![];
+    let mut idx = at;
+    // * Tests are short, and loops of jumps never end
+    while idx < ins.len() && idx < at + 16 {
+        match ins[idx] {
+            Inst::LoadA(a, o) if (a, o) == slot && ins.get(idx + 1) == Some(&Inst::ILoad) => {
+                stack.push(val);
+                idx += 1;
+            }
+            Inst::IPush(v) => stack.push(v),
+            Inst::Dup => stack.push(*stack.last()?),
+            Inst::INeg => {
+                let v = stack.pop()?;
+                stack.push(v.checked_neg()?);
+            }
+            op @ (Inst::IAdd | Inst::ISub | Inst::IMul | Inst::IDiv | Inst::ICmp) => {
+                let b = stack.pop()?;
+                let a = stack.pop()?;
+                stack.push(fold(a, b, op)?);
+            }
+            jump => {
+                return match (taken(&jump, stack.pop()?), stack.is_empty()) {
+                    (Some(true), true) => jump_target(&jump).map(|to| to as usize),
+                    (Some(false), true) => Some(idx + 1),
+                    _ => None,
+                };
+            }
+        }
+        idx += 1;
+    }
+    None
+}
+
+/// Whether conditional jump `jump` goes when the condition is `v`, or
+/// `None` if it is not a conditional jump
+pub(super) fn taken(jump: &Inst, v: i32) -> Option<bool> {
+    match jump {
+        Inst::JE(_) => Some(v == 0),
+        Inst::JNe(_) => Some(v != 0),
+        Inst::JL(_) => Some(v < 0),
+        Inst::JGe(_) => Some(v >= 0),
+        Inst::JG(_) => Some(v > 0),
+        Inst::JLe(_) => Some(v <= 0),
+        _ => None,
+    }
+}
+
+/// Which instructions can run at all
+fn reachable(ins: &[Inst]) -> Vec<bool> {
+    let mut seen = vec![false; ins.len()];
+    let mut pending = vec![0];
+    while let Some(idx) = pending.pop() {
+        if idx >= ins.len() || seen[idx] {
+            continue;
+        }
+        seen[idx] = true;
+        if let Some(to) = jump_target(&ins[idx]) {
+            pending.push(to as usize);
+        }
+        if !ends_flow(&ins[idx]) {
+            pending.push(idx + 1);
+        }
+    }
+    seen
+}
+
+/// Whether instructions of `ins` never `reached` may be dropped. The verifier
+/// counts the slots a function returns from its returns, so they stay in a
+/// function that never returns.
+pub(super) fn drops_unreached(ins: &[Inst], reached: impl Fn(usize) -> bool) -> bool {
+    let is_return = |inst: &Inst| ends_flow(inst) && !matches!(inst, Inst::Jmp(_));
+    let mut returns = (ins.iter().enumerate()).filter(|(_, inst)| is_return(inst));
+    returns.clone().next().is_none() || returns.any(|(idx, _)| reached(idx))
+}
+
+/// Drop unreachable instructions and rewrite short sequences, moving the
+/// targets of jumps along. Also returns where each instruction went.
+fn combine(
+    ins: &[Inst],
+    lines: &[Option<u32>],
+    rules: Rules,
+) -> (Vec<Inst>, Vec<Option<u32>>, Vec<usize>) {
+    let reachable = match rules.dce {
+        true => Some(reachable(ins)).filter(|seen| drops_unreached(ins, |idx| seen[idx])),
+        false => None,
+    };
+    let reachable = reachable.unwrap_or_else(|| vec![true; ins.len()]);
+    let mut ins = ins.to_vec();
+    let mut labels = Labels::of_jumps(&mut ins);
+    let mut is_target = vec![false; ins.len() + 1];
+    for (_, at) in labels.placed() {
+        if let Some(t) = is_target.get_mut(at) {
+            *t = true;
+        }
+    }
+
+    let mut out: Vec<Inst> = Vec::with_capacity(ins.len());
+    let mut out_lines = Vec::with_capacity(ins.len());
+    let mut new_idx = vec![0; ins.len() + 1];
+    // * Instructions before this may be reached with other stacks, so no
+    // * rewrite may reach back past it
+    let mut barrier = 0;
+
+    for (idx, &inst) in ins.iter().enumerate() {
+        new_idx[idx] = out.len();
+        if !reachable[idx] {
+            continue;
+        }
+        if is_target[idx] {
+            barrier = out.len();
+        }
+        let inst = match inst {
+            // * Jumping to the next instruction only pops the condition
+            Inst::Jmp(to) if rules.peephole && labels.place_of(Label(to)) == Some(idx + 1) => {
+                continue
+            }
+            Inst::JE(to)
+            | Inst::JNe(to)
+            | Inst::JL(to)
+            | Inst::JGe(to)
+            | Inst::JG(to)
+            | Inst::JLe(to)
+                if rules.peephole && labels.place_of(Label(to)) == Some(idx + 1) =>
+            {
+                Inst::Pop1
+            }
+            inst => inst,
+        };
+        out.push(inst);
+        out_lines.push(lines[idx]);
+        while simplify(&mut out, &mut out_lines, barrier, rules) {}
+    }
+    new_idx[ins.len()] = out.len();
+
+    labels.move_all(|at| new_idx[at]);
+    labels
+        .resolve(&mut out)
+        .expect("Every jump target is labeled");
+    (out, out_lines, new_idx)
+}
+
+/// Rewrite the instructions at the end of `out` after `barrier` once.
+/// Returns whether anything changed.
+fn simplify(
+    out: &mut Vec<Inst>,
+    lines: &mut Vec<Option<u32>>,
+    barrier: usize,
+    rules: Rules,
+) -> bool {
+    use Inst::*;
+    let tail = &out[barrier..];
+    let Rules {
+        fold,
+        dce,
+        peephole,
+    } = rules;
+    let (drop, replace) = match tail {
+        // * Values pushed only to be popped
+        [.., IPush(_) | CPush(_) | LoadA(..) | Dup, Pop1] if dce => (2, None),
+        [.., Dup2, Pop2] if dce => (2, None),
+        [.., IPush(_) | CPush(_) | LoadA(..) | Dup, IPush(_) | CPush(_) | LoadA(..) | Dup, Pop2]
+            if dce =>
+        {
+            (3, None)
+        }
+        [.., IPush(_) | CPush(_) | LoadA(..) | Dup, Pop2] if dce => (2, Some(Pop1)),
+        // * Loads of locals and globals, and arithmetic that can't fail,
+        // * computed only to be popped
+        [.., LoadA(..), ILoad | ALoad, Pop1] | [.., LoadA(..), DLoad, Pop2] if dce => (3, None),
+        [.., LoadA(..), ILoad | ALoad, Pop2] if dce => (3, Some(Pop1)),
+        [.., IAdd | ISub | IMul | ICmp | D2I, Pop1] if dce => (2, Some(Pop2)),
+        [.., INeg | I2C, Pop1] | [.., I2D, Pop2] if dce => (2, Some(Pop1)),
+        [.., DNeg, Pop2] if dce => (2, Some(Pop2)),
+        // * Arithmetic on constants
+        [.., IPush(a), Dup] if fold => (1, Some(IPush(*a))),
+        [.., IPush(a), IPush(b), op] if fold => match self::fold(*a, *b, *op) {
+            Some(val) => (3, Some(IPush(val))),
+            None => return false,
+        },
+        [.., IPush(a), INeg] if fold => match Value::Int(*a).unary(UnOp::Neg) {
+            Ok(Value::Int(val)) => (2, Some(IPush(val))),
+            _ => return false,
+        },
+        // * Jumps on a constant condition always or never go
+        [.., IPush(v), jump] if fold && taken(jump, *v).is_some() => match taken(jump, *v) {
+            Some(true) => (2, jump_target(jump).map(Jmp)),
+            _ => (2, None),
+        },
+        // * A constant stored and loaded right back
+        [.., LoadA(a, o), IPush(v), IStore, LoadA(b, p), ILoad] if peephole && (a, o) == (b, p) => {
+            (2, Some(IPush(*v)))
+        }
+        _ => return false,
+    };
+    let line = lines.last().copied().flatten();
+    out.truncate(out.len() - drop);
+    lines.truncate(lines.len() - drop);
+    if let Some(inst) = replace {
+        out.push(inst);
+        lines.push(line);
+    }
+    true
+}
+
+/// Compute `a op b` the way the VM does
+pub(super) fn fold(a: i32, b: i32, op: Inst) -> Option<i32> {
+    let (a, b) = (Value::Int(a), Value::Int(b));
+    let op = match op {
+        Inst::IAdd => BinOp::Add,
+        Inst::ISub => BinOp::Sub,
+        Inst::IMul => BinOp::Mul,
+        Inst::IDiv => BinOp::Div,
+        Inst::ICmp => return a.compare(&b).ok()?.map(|ord| ord as i32),
+        _ => return None,
+    };
+    // * Division by zero is left for the VM to report
+    a.binary(op, &b).ok()?.as_int().ok()
+}
@@ -0,0 +1,74 @@
+//! Profiles of runs on the VM, for compiling the same program again with
+//! what they tell about where time goes.
+//!
+//! A profile counts how many times each basic block of each function ran.
+//! Blocks are known by the number the compiler gives them, so a profile
+//! only fits code compiled from the same source with the same options, and
+//! counts of a function whose blocks came out differently are ignored.
+
+use super::vm::{function_name, Coverage};
+use super::O0;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Calls made to a function at least, for calls to it to be inlined
+pub const INLINE_CALLS: u64 = 16;
+
+/// Statements and expressions the body of a function may have at most, for
+/// calls to it to be inlined
+pub const INLINE_SIZE: usize = 16;
+
+/// Counts of one run, by function name
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ExecProfile {
+    pub functions: BTreeMap<String, FnCounts>,
+}
+
+/// How many times a function was called, and each of its blocks ran
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct FnCounts {
+    pub calls: u64,
+    /// Runs of each basic block, by its number
+    pub blocks: Vec<u64>,
+}
+
+impl ExecProfile {
+    /// Counts of blocks of `o0` from the counts of its instructions, or
+    /// `None` if it has no debug info
+    pub fn new(o0: &O0, coverage: &Coverage) -> Option<ExecProfile> {
+        let debug = o0.debug.as_ref()?;
+        let mut functions = BTreeMap::new();
+        for (idx, blocks) in debug.fn_blocks.iter().enumerate() {
+            let counts = coverage.counts(Some(idx));
+            let len = blocks.iter().map(|(id, _)| *id as usize + 1).max();
+            let mut fn_counts = FnCounts {
+                calls: counts.first().copied().unwrap_or(0),
+                blocks: vec![0; len.unwrap_or(0)],
+            };
+            for (id, start) in blocks {
+                fn_counts.blocks[*id as usize] = counts.get(*start as usize).copied().unwrap_or(0);
+            }
+            functions.insert(function_name(o0, Some(idx as u16)), fn_counts);
+        }
+        Some(ExecProfile { functions })
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Profiles are always valid JSON")
+    }
+
+    pub fn from_json(json: &str) -> Result<ExecProfile, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// Times function `name` was called
+    pub fn calls(&self, name: &str) -> u64 {
+        self.functions.get(name).map_or(0, |f| f.calls)
+    }
+
+    /// Runs of each block of function `name`, if it has `blocks` blocks
+    pub fn blocks(&self, name: &str, blocks: usize) -> Option<&[u64]> {
+        let counts = &self.functions.get(name)?.blocks;
+        (counts.len() == blocks).then(|| &counts[..])
+    }
+}
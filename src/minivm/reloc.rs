@@ -0,0 +1,71 @@
+//! Resolving calls to functions by name.
+//!
+//! Code generation doesn't decide where functions go in the binary, so it
+//! emits calls as `_Call` of a symbol, the name of the function called. Once
+//! every function has its place, the [`Relocator`] turns each into a `Call`
+//! of the function's index and lists it in [`O0::relocs`], where a linker or
+//! [`O0::patch_calls`] can find it later.
+
+use super::err::*;
+use super::{Inst, Reloc, O0};
+use indexmap::IndexSet;
+
+/// Names of the functions called by the code being generated
+#[derive(Debug, Clone, Default)]
+pub struct Relocator {
+    symbols: IndexSet<String>,
+}
+
+impl Relocator {
+    pub fn new() -> Relocator {
+        Relocator::default()
+    }
+
+    /// The symbol standing for function `name` in `_Call`
+    pub fn symbol(&mut self, name: &str) -> u16 {
+        match self.symbols.get_full(name) {
+            Some((idx, _)) => idx as u16,
+            None => self.symbols.insert_full(name.to_owned()).0 as u16,
+        }
+    }
+
+    /// Whether function `name` is called
+    pub fn is_called(&self, name: &str) -> bool {
+        self.symbols.contains(name)
+    }
+
+    /// Name of function `symbol` stands for
+    pub fn name(&self, symbol: u16) -> Option<&str> {
+        self.symbols.get_index(symbol as usize).map(|s| s.as_str())
+    }
+
+    /// Turn every `_Call` in `o0` into a `Call` of the function `index_of`
+    /// gives for its name, recording it in `o0.relocs`
+    pub fn relocate(
+        &self,
+        o0: &mut O0,
+        index_of: impl Fn(&str) -> Option<u16>,
+    ) -> CompileResult<()> {
+        let code = std::iter::once((None, &mut o0.start_code.ins)).chain(
+            (o0.functions.iter_mut().enumerate()).map(|(idx, f)| (Some(idx as u16), &mut f.ins)),
+        );
+        for (func, ins) in code {
+            for (idx, inst) in ins.iter_mut().enumerate() {
+                if let Inst::_Call(symbol) = *inst {
+                    let name = self.name(symbol).ok_or_else(|| {
+                        CompileErrorVar::InternalError(format!("Unknown symbol #{}", symbol))
+                    })?;
+                    let to = index_of(name)
+                        .ok_or_else(|| CompileErrorVar::NonExistFunc(name.to_owned()))?;
+                    *inst = Inst::Call(to);
+                    o0.relocs.push(Reloc {
+                        func,
+                        idx,
+                        symbol: name.to_owned(),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,90 @@
+//! Remarks on what optimizations did, and on why they left code as it was.
+//!
+//! Passes working on the syntax tree remark where in the source they apply:
+//! `inline` on each call, `unroll` on each loop, `cse` and `gvn` on each
+//! value they reuse. Passes working on instructions remark once for each
+//! function, at its body. Other passes make no remarks.
+
+use super::passes::Pass;
+use crate::prelude::*;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+/// Whether a pass did what a remark tells of
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RemarkKind {
+    /// The pass changed the code
+    Applied,
+    /// The pass left the code as it was, and the remark tells why
+    Missed,
+}
+
+/// What a pass did, or didn't, to a piece of code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Remark {
+    pub pass: Pass,
+    pub kind: RemarkKind,
+    /// Function the code is in, [`START`](super::passes::START) for start code
+    pub func: String,
+    /// Where the code is in the source, if known
+    pub span: Option<Span>,
+    pub message: String,
+}
+
+impl fmt::Display for Remark {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "remark[{}]: {}", self.pass.name(), self.message)
+    }
+}
+
+/// Remarks of the passes asked for, collected while compiling.
+///
+/// Clones share the remarks collected, so one given to
+/// [`Codegen::with_remarks`](super::Codegen::with_remarks) can be kept to
+/// [`take`](Remarks::take) them once compiling is done.
+#[derive(Debug, Clone, Default)]
+pub struct Remarks {
+    passes: Vec<Pass>,
+    remarks: Rc<RefCell<Vec<Remark>>>,
+}
+
+impl Remarks {
+    /// Collect the remarks of `passes`
+    pub fn new(passes: Vec<Pass>) -> Remarks {
+        Remarks {
+            passes,
+            remarks: Rc::default(),
+        }
+    }
+
+    /// Whether remarks of `pass` are collected
+    pub fn wants(&self, pass: Pass) -> bool {
+        self.passes.contains(&pass)
+    }
+
+    /// Note `remark`, if remarks of its pass are collected
+    pub(super) fn add(&self, remark: Remark) {
+        if self.wants(remark.pass) {
+            self.remarks.borrow_mut().push(remark);
+        }
+    }
+
+    /// Remarks collected so far, in the order of the source. Code compiled
+    /// more than once, like the body of an unrolled loop, is remarked on
+    /// once.
+    pub fn take(&self) -> Vec<Remark> {
+        let mut remarks = std::mem::take(&mut *self.remarks.borrow_mut());
+        // * Stable, so remarks at the same place keep the order passes ran in
+        remarks.sort_by_key(|r| r.span.map(|s| (s.start.ln, s.start.pos)));
+        let mut seen: Vec<Remark> = vec![];
+        remarks.retain(|r| {
+            // * Only remarks at the same place can be the same
+            let same_place = seen.iter().rev().take_while(|s| s.span == r.span);
+            let first = !same_place.into_iter().any(|s| s == r);
+            seen.push(r.clone());
+            first
+        });
+        remarks
+    }
+}
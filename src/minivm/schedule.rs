@@ -0,0 +1,113 @@
+//! Choosing the order operands are evaluated in.
+//!
+//! Each operand of a binary operator stays on the stack while the other one
+//! is computed, so starting with the operand needing more stack keeps the
+//! stack shallower. `a + (b + (c + d))` needs four slots left to right, but
+//! only two when every right side goes first.
+
+use crate::c0::ast::{self, ExprVariant, OpVar};
+use crate::prelude::*;
+use std::collections::HashMap;
+
+/// What evaluating an expression takes
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct Need {
+    /// Estimated stack slots, counting every value as one
+    slots: u32,
+    /// Whether it has no side effects and reads nothing that others write
+    pure: bool,
+}
+
+/// Estimates of expressions' stack needs, remembered so each expression is
+/// only looked at once
+#[derive(Debug, Default)]
+pub(super) struct Scheduler {
+    needs: HashMap<*const ast::Expr, Need>,
+}
+
+impl Scheduler {
+    pub fn new() -> Scheduler {
+        Scheduler::default()
+    }
+
+    /// If `b` needs less stack with its right side evaluated first, the
+    /// operator to apply to the swapped operands
+    pub fn swap(&mut self, b: &ast::BinaryOp) -> Option<OpVar> {
+        let op = mirror(b.op)?;
+        let lhs = self.need(&b.lhs);
+        let rhs = self.need(&b.rhs);
+        // * Operands with side effects run in source order
+        if lhs.pure && rhs.pure && rhs.slots > lhs.slots {
+            Some(op)
+        } else {
+            None
+        }
+    }
+
+    fn need(&mut self, expr: &Ptr<ast::Expr>) -> Need {
+        let expr = expr.borrow();
+        let key = &*expr as *const ast::Expr;
+        if let Some(need) = self.needs.get(&key) {
+            return *need;
+        }
+        let need = maybe_grow(|| self.compute(&expr.var));
+        self.needs.insert(key, need);
+        need
+    }
+
+    fn compute(&mut self, var: &ExprVariant) -> Need {
+        match var {
+            ExprVariant::Ident(_) | ExprVariant::Literal(_) => Need {
+                slots: 1,
+                pure: true,
+            },
+            ExprVariant::TypeConversion(c) => self.need(&c.expr),
+            ExprVariant::UnaryOp(u) => self.need(&u.val),
+            ExprVariant::BinaryOp(b) if b.op == OpVar::_Asn || b.op == OpVar::_Csn => Need {
+                // * The address waits for the value
+                slots: 1 + self.need(&b.rhs).slots,
+                pure: false,
+            },
+            ExprVariant::BinaryOp(b) => {
+                let lhs = self.need(&b.lhs);
+                let rhs = self.need(&b.rhs);
+                let pure = lhs.pure && rhs.pure;
+                let in_order = lhs.slots.max(rhs.slots + 1);
+                let swapped = rhs.slots.max(lhs.slots + 1);
+                let slots = if pure && mirror(b.op).is_some() {
+                    in_order.min(swapped)
+                } else {
+                    in_order
+                };
+                Need { slots, pure }
+            }
+            ExprVariant::FunctionCall(f) => {
+                let slots = (f.params.iter().enumerate())
+                    .map(|(idx, p)| idx as u32 + self.need(p).slots)
+                    .max()
+                    .unwrap_or(0);
+                Need {
+                    slots: slots.max(1),
+                    pure: false,
+                }
+            }
+            ExprVariant::StructChild(_) | ExprVariant::ArrayChild(_) => Need {
+                slots: 1,
+                pure: false,
+            },
+        }
+    }
+}
+
+/// The operator giving the same result with its operands swapped
+fn mirror(op: OpVar) -> Option<OpVar> {
+    use OpVar::*;
+    match op {
+        Add | Mul | Eq | Neq => Some(op),
+        Gt => Some(Lt),
+        Lt => Some(Gt),
+        Gte => Some(Lte),
+        Lte => Some(Gte),
+        _ => None,
+    }
+}
@@ -0,0 +1,164 @@
+//! `--emit size-report`: where the bytes of a binary go.
+
+use super::binfmt::{constant_size, inst_size, FN_HEADER_SIZE};
+use super::{stack_depth, Constant, Inst, O0};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fmt::{self, Display, Formatter};
+
+/// Sizes of each function of a binary
+#[derive(Debug, Clone, Serialize)]
+pub struct SizeReport {
+    /// Bytes of the whole binary file
+    pub total_bytes: usize,
+    /// Bytes of the constant table
+    pub constant_bytes: usize,
+    pub start: FnSize,
+    /// Functions, largest first
+    pub functions: Vec<FnSize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FnSize {
+    pub name: String,
+    pub instructions: usize,
+    /// Bytes in the binary, counting the function's header and instruction
+    /// count
+    pub bytes: usize,
+    /// Distinct constants loaded
+    pub constants: usize,
+    /// Bytes of the constants loaded, which other functions may share
+    pub constant_bytes: usize,
+    pub param_slots: usize,
+    pub local_slots: usize,
+    /// Most operand stack slots used at once
+    pub stack_slots: usize,
+    /// Slots of parameters, local variables and the operand stack together
+    pub frame_slots: usize,
+}
+
+impl SizeReport {
+    /// Report on `o0`. Operand stack slots are only counted if the code
+    /// passes [`verify`](super::verify), and are 0 otherwise.
+    pub fn new(o0: &O0) -> SizeReport {
+        let mut binary = vec![];
+        o0.write_binary(&mut binary)
+            .expect("Writing to a Vec cannot fail");
+        let depth = stack_depth(o0).ok();
+        let depth_of = |func: Option<usize>| match (&depth, func) {
+            (Some(d), Some(idx)) => d.functions[idx],
+            (Some(d), None) => d.start,
+            (None, _) => 0,
+        };
+
+        let start = FnSize::new(
+            o0,
+            ".start".into(),
+            &o0.start_code.ins,
+            0,
+            depth_of(None),
+            2,
+        );
+        let mut functions: Vec<_> = (o0.functions.iter().enumerate())
+            .map(|(idx, f)| {
+                let name = match o0.constants.get(f.name_idx as usize) {
+                    Some(Constant::String(s)) => String::from_utf8_lossy(s).into_owned(),
+                    _ => format!(".F{}", idx),
+                };
+                let params = f.param_siz as usize;
+                FnSize::new(
+                    o0,
+                    name,
+                    &f.ins,
+                    params,
+                    depth_of(Some(idx)),
+                    FN_HEADER_SIZE,
+                )
+            })
+            .collect();
+        functions.sort_by_key(|f| std::cmp::Reverse(f.bytes));
+
+        SizeReport {
+            total_bytes: binary.len(),
+            constant_bytes: o0.constants.iter().map(constant_size).sum(),
+            start,
+            functions,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Reports are always valid JSON")
+    }
+}
+
+impl FnSize {
+    fn new(
+        o0: &O0,
+        name: String,
+        ins: &[Inst],
+        params: usize,
+        stack: usize,
+        header: usize,
+    ) -> FnSize {
+        let constants: BTreeSet<_> = (ins.iter())
+            .filter_map(|i| match i {
+                Inst::LoadC(c) => Some(*c as usize),
+                _ => None,
+            })
+            .collect();
+        let locals = match ins.first() {
+            Some(Inst::SNew(n)) => *n as usize,
+            _ => 0,
+        };
+        FnSize {
+            name,
+            instructions: ins.len(),
+            bytes: header + ins.iter().map(inst_size).sum::<usize>(),
+            constants: constants.len(),
+            constant_bytes: (constants.iter())
+                .filter_map(|c| o0.constants.get(*c))
+                .map(constant_size)
+                .sum(),
+            param_slots: params,
+            local_slots: locals,
+            stack_slots: stack,
+            frame_slots: params + locals + stack,
+        }
+    }
+}
+
+impl Display for SizeReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<20} {:>8} {:>8} {:>8} {:>11} {:>8} {:>8} {:>8} {:>8}",
+            "function",
+            "insts",
+            "bytes",
+            "consts",
+            "const bytes",
+            "params",
+            "locals",
+            "stack",
+            "frame"
+        )?;
+        for s in std::iter::once(&self.start).chain(&self.functions) {
+            writeln!(
+                f,
+                "{:<20} {:>8} {:>8} {:>8} {:>11} {:>8} {:>8} {:>8} {:>8}",
+                s.name,
+                s.instructions,
+                s.bytes,
+                s.constants,
+                s.constant_bytes,
+                s.param_slots,
+                s.local_slots,
+                s.stack_slots,
+                s.frame_slots
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(f, "constant table: {} bytes", self.constant_bytes)?;
+        writeln!(f, "total:          {} bytes", self.total_bytes)
+    }
+}
@@ -0,0 +1,39 @@
+/// A version of the C0 language. Chigusa accepts some extensions to C0; a
+/// standard says which of them are allowed, so programs can be kept to what
+/// other C0 compilers accept.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct Standard {
+    /// Name used to pick this standard with `--std`
+    pub name: &'static str,
+    /// Whether an assignment is an expression with the assigned value, so
+    /// `a = b = 3` and `if (a = 3)` are allowed. Otherwise an assignment can
+    /// only be a statement of its own.
+    pub assign_expr: bool,
+}
+
+impl Standard {
+    /// C0 as specified
+    pub const C0: Standard = Standard {
+        name: "c0",
+        assign_expr: false,
+    };
+
+    /// C0 with assignments as expressions, as in C
+    pub const C0_EXT: Standard = Standard {
+        name: "c0-ext",
+        assign_expr: true,
+    };
+
+    /// All standards, in the order they are listed to users
+    pub const ALL: &'static [Standard] = &[Standard::C0, Standard::C0_EXT];
+
+    pub fn from_name(name: &str) -> Option<Standard> {
+        Standard::ALL.iter().find(|s| s.name == name).copied()
+    }
+}
+
+impl Default for Standard {
+    fn default() -> Self {
+        Standard::C0
+    }
+}
@@ -0,0 +1,180 @@
+use super::err::{compile_err, CompileErrorVar, CompileResult};
+use super::{Endian, Inst};
+use crate::c0::ast;
+use crate::c0::hir::{self, type_name};
+use crate::prelude::*;
+
+/// Bytes in one stack slot of the VM
+pub const BYTES_PER_SLOT: u32 = 4;
+
+/// Description of a machine code is generated for. Layout of values and the
+/// set of instructions codegen may use are read from here instead of being
+/// hardcoded.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct Target {
+    /// Name used to pick this target with `--target`
+    pub name: &'static str,
+    /// Bytes in a reference
+    pub pointer_bytes: u32,
+    /// Bytes in an `int`
+    pub int_bytes: u32,
+    /// Byte order of the emitted binary
+    pub endian: Endian,
+    /// Whether `double` values and their instructions are available
+    pub float: bool,
+    /// Whether print and scan instructions are available
+    pub io: bool,
+}
+
+impl Target {
+    /// The standard O0 virtual machine
+    pub const O0: Target = Target {
+        name: "o0",
+        pointer_bytes: 4,
+        int_bytes: 4,
+        endian: Endian::Big,
+        float: true,
+        io: true,
+    };
+
+    /// An O0 machine with only 32-bit values, lacking `double`
+    pub const O0_32: Target = Target {
+        name: "o0-32",
+        float: false,
+        ..Target::O0
+    };
+
+    /// All targets, in the order they are listed to users
+    pub const ALL: &'static [Target] = &[Target::O0, Target::O0_32];
+
+    pub fn from_name(name: &str) -> Option<Target> {
+        Target::ALL.iter().find(|t| t.name == name).copied()
+    }
+
+    /// Bytes a value of this primitive type occupies
+    pub fn primitive_bytes(&self, p: &ast::PrimitiveType) -> u32 {
+        match p.var {
+            ast::PrimitiveTypeVar::Float => p.occupy_bytes as u32,
+            _ if p.occupy_bytes == 1 => 1,
+            _ => self.int_bytes,
+        }
+    }
+
+    /// Calculate the slots one type occupy, or `None` if it has no size
+    pub fn slots_of(&self, ty: &ast::TypeDef) -> Option<u32> {
+        match ty {
+            ast::TypeDef::Unit => Some(0),
+            ast::TypeDef::Ref(..) => Some(self.pointer_bytes.div_ceil(BYTES_PER_SLOT)),
+            ast::TypeDef::Array(a) => a
+                .length
+                .and_then(|l| self.slots_of(&a.target.borrow()).map(|s| s * l as u32)),
+            ast::TypeDef::Primitive(p) => Some(self.primitive_bytes(p).div_ceil(BYTES_PER_SLOT)),
+            _ => None,
+        }
+    }
+
+    /// Whether this target can run `inst`
+    pub fn supports(&self, inst: &Inst) -> bool {
+        use Inst::*;
+        match inst {
+            DPrint | DScan => self.float && self.io,
+            DLoad | DALoad | DStore | DAStore | DAdd | DSub | DMul | DDiv | DNeg | DCmp | I2D
+            | D2I | DRet => self.float,
+            IPrint | CPrint | SPrint | PrintLn | IScan | CScan => self.io,
+            _ => true,
+        }
+    }
+
+    /// Whether this target has values of type `typ`
+    pub fn has_type(&self, typ: &ast::TypeDef) -> bool {
+        match typ {
+            ast::TypeDef::Primitive(p) => p.var != ast::PrimitiveTypeVar::Float || self.float,
+            ast::TypeDef::Ref(r) => self.has_type(&r.target.borrow()),
+            ast::TypeDef::Array(a) => self.has_type(&a.target.borrow()),
+            _ => true,
+        }
+    }
+
+    /// Fail at the first declaration of a type this target does not have,
+    /// or the first expression of one, before any code is generated for it
+    pub fn check_types(&self, prog: &hir::TypedProgram) -> CompileResult<()> {
+        self.check_block(&prog.blk)?;
+        for f in &prog.fns {
+            for param in &f.params {
+                self.check_type(&param.typ, param.span)?;
+            }
+            self.check_type(&f.return_type, f.span)?;
+            if let Some(body) = &f.body {
+                self.check_block(body)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn check_type(&self, typ: &hir::Type, span: Span) -> CompileResult<()> {
+        match self.has_type(&typ.borrow()) {
+            true => Ok(()),
+            false => Err(compile_err(
+                CompileErrorVar::TypeNotOnTarget(type_name(&typ.borrow()), self.name),
+                Some(span),
+            )),
+        }
+    }
+
+    fn check_block(&self, blk: &hir::Block) -> CompileResult<()> {
+        for var in &blk.vars {
+            self.check_type(&var.typ, var.span)?;
+        }
+        blk.stmts.iter().try_for_each(|s| self.check_stmt(s))
+    }
+
+    fn check_stmt(&self, stmt: &hir::Stmt) -> CompileResult<()> {
+        use hir::StmtVariant::*;
+        maybe_grow(|| match &stmt.var {
+            If {
+                cond,
+                then,
+                else_ifs,
+                els,
+            } => {
+                self.check_expr(cond)?;
+                self.check_stmt(then)?;
+                for (cond, body) in else_ifs {
+                    self.check_expr(cond)?;
+                    self.check_stmt(body)?;
+                }
+                els.iter().try_for_each(|s| self.check_stmt(s))
+            }
+            While { cond, body, .. } => {
+                self.check_expr(cond)?;
+                self.check_stmt(body)
+            }
+            Block(blk) => self.check_block(blk),
+            Exprs(exprs) | Print(exprs) => exprs.iter().try_for_each(|e| self.check_expr(e)),
+            Return(val) => val.iter().try_for_each(|e| self.check_expr(e)),
+            Scan(_) | Break(_) | Empty => Ok(()),
+        })
+    }
+
+    fn check_expr(&self, expr: &hir::Expr) -> CompileResult<()> {
+        use hir::ExprVariant::*;
+        maybe_grow(|| {
+            self.check_type(&expr.typ, expr.span)?;
+            match &expr.var {
+                Var(_) | Literal(_) => Ok(()),
+                Conv(e) | Unary(_, e) | Assign { val: e, .. } => self.check_expr(e),
+                Binary(_, a, b) | Store { to: a, val: b } => {
+                    self.check_expr(a)?;
+                    self.check_expr(b)
+                }
+                Call { args, .. } => args.iter().try_for_each(|e| self.check_expr(e)),
+            }
+        })
+    }
+}
+
+impl Default for Target {
+    fn default() -> Self {
+        Target::O0
+    }
+}
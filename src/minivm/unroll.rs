@@ -0,0 +1,360 @@
+//! Unrolling loops that run a small number of times known when compiling.
+//!
+//! Each time round a `while` loop, the VM checks its condition and jumps
+//! back, which costs as much as a few instructions of the body. A loop
+//! counting a variable from a constant to a constant, like
+//!
+//! ```c0
+//! int i = 0;
+//! while (i < 4) {
+//!     s = s + i;
+//!     i = i + 1;
+//! }
+//! ```
+//!
+//! runs its body a number of times known when compiling. If that is at most
+//! the unrolling factor, the body is generated that many times in a row,
+//! with no condition and no jumps. Otherwise, if a number of copies up to
+//! the factor divides it, the loop runs that many copies of its body each
+//! time round, checking its condition that much less often. The copies have
+//! to fit in the size budget, counted in statements and expressions of the
+//! source.
+//!
+//! The counter has to be a local `int` whose address is never taken, set to
+//! a constant earlier in the block the loop is in with nothing writing it in
+//! between, changed only by adding or subtracting a constant as the last
+//! statement of the body, and compared with a constant by the condition. The body may not `break` out of the
+//! loop.
+
+use crate::c0::ast::{self, ExprVariant, OpVar, Scope, StmtVariant};
+use crate::consteval::{self, Value};
+use crate::prelude::*;
+
+/// How to generate a loop
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub(super) enum Unrolled {
+    /// The body this many times in a row, with no loop left
+    Full(u32),
+    /// A loop running this many copies of the body each time round
+    Partial(u32),
+}
+
+/// Plan how to unroll the loops directly in `block`, a block of the
+/// function with body `func`, to at most `factor` copies of a body and
+/// `budget` statements and expressions. Each loop comes with how it is
+/// unrolled, or why it isn't.
+pub(super) fn plan<'a>(
+    block: &'a ast::Block,
+    func: &ast::Block,
+    factor: u32,
+    budget: usize,
+) -> Vec<(&'a ast::Stmt, Result<Unrolled, String>)> {
+    let mut plan = vec![];
+    for (idx, stmt) in block.stmts.iter().enumerate() {
+        if let StmtVariant::While(w) = &stmt.var {
+            let trips = trip_count(&block.stmts[..idx], w, &block.scope, func);
+            let size = size(&w.block.borrow(), &block.scope);
+            let fits = |copies: u32| copies as usize * size <= budget;
+            let unrolled = match trips {
+                Some(trips) if trips <= factor && fits(trips) => Ok(Unrolled::Full(trips)),
+                Some(trips) => {
+                    let copies = (2..=factor.min(trips)).rev();
+                    match copies.into_iter().find(|k| trips % k == 0 && fits(*k)) {
+                        Some(k) => Ok(Unrolled::Partial(k)),
+                        None => Err(format!(
+                            "no number of copies up to {} dividing the {} times it runs \
+                             adds up to at most {} statements and expressions",
+                            factor, trips, budget
+                        )),
+                    }
+                }
+                None => Err("the times it runs are not known when compiling".into()),
+            };
+            plan.push((stmt, unrolled));
+        }
+    }
+    plan
+}
+
+/// Times the loop `w` runs, right after the statements `before` it in its
+/// block, if it is a counting loop
+fn trip_count(
+    before: &[ast::Stmt],
+    w: &ast::WhileConditional,
+    scope: &Ptr<Scope>,
+    func: &ast::Block,
+) -> Option<u32> {
+    // * The condition compares the counter with a constant
+    let cond = w.cond.borrow();
+    let (op, counter, end) = match &cond.var {
+        ExprVariant::BinaryOp(b) => match &b.lhs.borrow().var {
+            ExprVariant::Ident(i) => (b.op, key(&i.name, scope)?, constant(&b.rhs, scope)?),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    if counter.0 == 0 || !is_int(&counter.1, scope) || address_taken(func, &counter) {
+        return None;
+    }
+
+    // * Set to a constant by the last statement writing it
+    let init = before
+        .iter()
+        .rev()
+        .find(|s| stmt_writes(s, &counter, scope))?;
+    let inits: Vec<&Ptr<ast::Expr>> = match &init.var {
+        StmtVariant::Expr(e) => vec![e],
+        StmtVariant::ManyExpr(es) => es.iter().collect(),
+        _ => return None,
+    };
+    let last = inits.iter().rposition(|e| writes(e, &counter, scope))?;
+    let start = match &inits[last].borrow().var {
+        ExprVariant::BinaryOp(b) if is_assign(b.op) && is_var(&b.lhs, &counter, scope) => {
+            constant(&b.rhs, scope)?
+        }
+        _ => return None,
+    };
+
+    // * Stepped by a constant at the end of the body, and nowhere else
+    let body = w.block.borrow();
+    let (stmts, body_scope) = match &body.var {
+        StmtVariant::Block(b) => (&b.stmts[..], &b.scope),
+        _ => return None,
+    };
+    let (step, rest) = stmts.split_last()?;
+    let step = match &step.var {
+        StmtVariant::Expr(e) => step_of(e, &counter, body_scope)?,
+        _ => return None,
+    };
+    if (rest.iter()).any(|s| stmt_writes(s, &counter, body_scope) || breaks(s, w, 0)) {
+        return None;
+    }
+
+    // * Every value the counter takes has to fit, as the VM would wrap
+    // * around or trap otherwise
+    let trips = match op {
+        OpVar::Lt if step > 0 => (end - start + step - 1).div_euclid(step),
+        OpVar::Lte if step > 0 => (end - start).div_euclid(step) + 1,
+        OpVar::Gt if step < 0 => (start - end - step - 1).div_euclid(-step),
+        OpVar::Gte if step < 0 => (start - end).div_euclid(-step) + 1,
+        OpVar::Neq if (end - start) % step == 0 && (end - start) / step >= 0 => {
+            (end - start) / step
+        }
+        _ => return None,
+    }
+    .max(0);
+    let last = start + trips * step;
+    if last < i32::MIN as i64 || last > i32::MAX as i64 || trips > u32::MAX as i64 {
+        return None;
+    }
+    Some(trips as u32)
+}
+
+/// The constant `e`, as in `i = i + 2` or `i = i - 2`, adds to `counter`
+fn step_of(e: &Ptr<ast::Expr>, counter: &(usize, String), scope: &Ptr<Scope>) -> Option<i64> {
+    let e = e.borrow();
+    let b = match &e.var {
+        ExprVariant::BinaryOp(b) if b.op == OpVar::_Asn && is_var(&b.lhs, counter, scope) => b,
+        _ => return None,
+    };
+    let rhs = b.rhs.borrow();
+    let step = match &rhs.var {
+        ExprVariant::BinaryOp(s)
+            if is_var(&s.lhs, counter, scope) && !writes(&s.rhs, counter, scope) =>
+        {
+            match s.op {
+                OpVar::Add => constant(&s.rhs, scope)?,
+                OpVar::Sub => -constant(&s.rhs, scope)?,
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+    (step != 0).then_some(step)
+}
+
+/// Statements and expressions in `stmt`, what its copies are counted in
+pub(super) fn size(stmt: &ast::Stmt, scope: &Ptr<Scope>) -> usize {
+    let (mut stmts, mut exprs) = (0, 0);
+    visit_stmt(stmt, scope, &mut |_, _| stmts += 1, &mut |e, _| {
+        visit_expr(e, &mut |_| exprs += 1)
+    });
+    stmts + exprs
+}
+
+/// Value of the constant `int` expression `e`
+fn constant(e: &Ptr<ast::Expr>, scope: &Ptr<Scope>) -> Option<i64> {
+    match consteval::eval(&e.borrow(), scope) {
+        Ok(Value::Int(val)) => Some(val as i64),
+        _ => None,
+    }
+}
+
+/// Key of the variable `name` refers to, seen from `scope`
+fn key(name: &str, scope: &Ptr<Scope>) -> Option<(usize, String)> {
+    let (_, id) = scope.borrow().find_def_depth(name)?;
+    Some((id, name.into()))
+}
+
+fn is_var(e: &Ptr<ast::Expr>, var: &(usize, String), scope: &Ptr<Scope>) -> bool {
+    match &e.borrow().var {
+        ExprVariant::Ident(i) => key(&i.name, scope).as_ref() == Some(var),
+        _ => false,
+    }
+}
+
+fn is_assign(op: OpVar) -> bool {
+    matches!(op, OpVar::_Asn | OpVar::_Csn)
+}
+
+/// Whether the variable `name` is an `int`
+fn is_int(name: &str, scope: &Ptr<Scope>) -> bool {
+    let def = match scope.borrow().find_def(name) {
+        Some(def) => def,
+        None => return false,
+    };
+    let typ = match &*def.borrow() {
+        ast::SymbolDef::Var { typ, .. } => typ.cp(),
+        _ => return false,
+    };
+    let typ = typ.borrow();
+    match &*typ {
+        ast::TypeDef::NamedType(name) => name == "int",
+        ast::TypeDef::Primitive(p) => {
+            p.var == ast::PrimitiveTypeVar::SignedInt && p.occupy_bytes == 4
+        }
+        _ => false,
+    }
+}
+
+/// Whether `e` may write `var`
+fn writes(e: &Ptr<ast::Expr>, var: &(usize, String), scope: &Ptr<Scope>) -> bool {
+    let mut found = false;
+    visit_expr(e, &mut |e| {
+        found |= match &e.var {
+            ExprVariant::BinaryOp(b) => is_assign(b.op) && is_var(&b.lhs, var, scope),
+            ExprVariant::UnaryOp(u) => {
+                matches!(u.op, OpVar::Ina | OpVar::Inb | OpVar::Dea | OpVar::Deb)
+                    && is_var(&u.val, var, scope)
+            }
+            _ => false,
+        }
+    });
+    found
+}
+
+/// Whether `stmt`, in `scope`, may write `var`
+fn stmt_writes(stmt: &ast::Stmt, var: &(usize, String), scope: &Ptr<Scope>) -> bool {
+    let (mut scanned, mut assigned) = (false, false);
+    visit_stmt(
+        stmt,
+        scope,
+        &mut |s, scope| {
+            if let StmtVariant::Scan(i, _) = &s.var {
+                scanned |= key(&i.name, scope).as_ref() == Some(var);
+            }
+        },
+        &mut |e, scope| assigned |= writes(e, var, scope),
+    );
+    scanned || assigned
+}
+
+/// Whether `&var` appears anywhere in `func`
+fn address_taken(func: &ast::Block, var: &(usize, String)) -> bool {
+    let mut found = false;
+    for stmt in &func.stmts {
+        visit_stmt(stmt, &func.scope, &mut |_, _| (), &mut |e, scope| {
+            visit_expr(e, &mut |e| {
+                if let ExprVariant::UnaryOp(u) = &e.var {
+                    found |= u.op == OpVar::Ref && is_var(&u.val, var, scope);
+                }
+            })
+        });
+    }
+    found
+}
+
+/// Whether `stmt`, inside `depth` loops nested in `w`, may break out of `w`
+fn breaks(stmt: &ast::Stmt, w: &ast::WhileConditional, depth: usize) -> bool {
+    maybe_grow(|| match &stmt.var {
+        StmtVariant::Break(None) => depth == 0,
+        StmtVariant::Break(Some(label)) => w.label.as_ref().is_some_and(|l| l.name == label.name),
+        StmtVariant::While(inner) => breaks(&inner.block.borrow(), w, depth + 1),
+        StmtVariant::If(i) => {
+            breaks(&i.if_block.borrow(), w, depth)
+                || i.else_ifs
+                    .iter()
+                    .any(|(_, s)| breaks(&s.borrow(), w, depth))
+                || (i.else_block.as_ref()).is_some_and(|s| breaks(&s.borrow(), w, depth))
+        }
+        StmtVariant::Block(b) => b.stmts.iter().any(|s| breaks(s, w, depth)),
+        _ => false,
+    })
+}
+
+/// Call `on_stmt` on every statement in `stmt`, which is in `scope`, and
+/// `on_expr` on every expression directly in one, each with the scope it is
+/// in
+fn visit_stmt(
+    stmt: &ast::Stmt,
+    scope: &Ptr<Scope>,
+    on_stmt: &mut dyn FnMut(&ast::Stmt, &Ptr<Scope>),
+    on_expr: &mut dyn FnMut(&Ptr<ast::Expr>, &Ptr<Scope>),
+) {
+    maybe_grow(|| {
+        on_stmt(stmt, scope);
+        match &stmt.var {
+            StmtVariant::If(i) => {
+                on_expr(&i.cond, scope);
+                visit_stmt(&i.if_block.borrow(), scope, on_stmt, on_expr);
+                for (cond, s) in &i.else_ifs {
+                    on_expr(cond, scope);
+                    visit_stmt(&s.borrow(), scope, on_stmt, on_expr);
+                }
+                if let Some(s) = &i.else_block {
+                    visit_stmt(&s.borrow(), scope, on_stmt, on_expr);
+                }
+            }
+            StmtVariant::While(w) => {
+                on_expr(&w.cond, scope);
+                visit_stmt(&w.block.borrow(), scope, on_stmt, on_expr);
+            }
+            StmtVariant::Block(b) => {
+                for s in &b.stmts {
+                    visit_stmt(s, &b.scope, on_stmt, on_expr);
+                }
+            }
+            StmtVariant::Expr(e) | StmtVariant::Return(Some(e)) => on_expr(e, scope),
+            StmtVariant::ManyExpr(es) | StmtVariant::Print(es) => {
+                es.iter().for_each(|e| on_expr(e, scope))
+            }
+            StmtVariant::Scan(..)
+            | StmtVariant::Return(None)
+            | StmtVariant::Break(_)
+            | StmtVariant::Empty => (),
+        }
+    })
+}
+
+/// Call `f` on `e` and every expression in it
+fn visit_expr(e: &Ptr<ast::Expr>, f: &mut dyn FnMut(&ast::Expr)) {
+    maybe_grow(|| {
+        let e = e.borrow();
+        f(&e);
+        match &e.var {
+            ExprVariant::BinaryOp(b) => {
+                visit_expr(&b.lhs, f);
+                visit_expr(&b.rhs, f);
+            }
+            ExprVariant::UnaryOp(u) => visit_expr(&u.val, f),
+            ExprVariant::FunctionCall(c) => c.params.iter().for_each(|p| visit_expr(p, f)),
+            ExprVariant::TypeConversion(t) => visit_expr(&t.expr, f),
+            ExprVariant::StructChild(s) => visit_expr(&s.val, f),
+            ExprVariant::ArrayChild(a) => {
+                visit_expr(&a.val, f);
+                visit_expr(&a.idx, f);
+            }
+            ExprVariant::Ident(_) | ExprVariant::Literal(_) => (),
+        }
+    })
+}
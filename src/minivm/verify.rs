@@ -0,0 +1,471 @@
+//! Checks on generated code, catching codegen bugs before they reach the VM.
+//!
+//! Every instruction is checked to refer to existing constants, functions
+//! and frame slots, and to jump inside its function. The types of values on
+//! the operand stack are followed along every path, so each instruction is
+//! known to find what it expects there, and every path reaching an
+//! instruction agrees on the stack.
+
+use super::{Constant, Inst, O0};
+use std::fmt::{self, Display, Formatter};
+
+/// What is known about one slot of the operand stack
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Slot {
+    /// Anything, like a local variable slot
+    Any,
+    Int,
+    /// Half of a double
+    Double,
+    Addr,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum VerifyErrorKind {
+    BadConstant(u16),
+    BadFunction(u16),
+    BadFrame(u16, i32),
+    BadJump(u16),
+    PseudoInstruction,
+    StackUnderflow,
+    StackMismatch(usize, usize),
+    TypeMismatch { expected: Slot, found: Slot },
+    ReturnMismatch,
+    FallsOffEnd,
+}
+
+/// Something wrong with generated code
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct VerifyError {
+    /// Index of the function, or `None` for start code
+    pub func: Option<usize>,
+    /// Index of the instruction
+    pub idx: usize,
+    pub kind: VerifyErrorKind,
+}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        use VerifyErrorKind::*;
+        match self.func {
+            Some(func) => write!(f, ".F{}:{}: ", func, self.idx)?,
+            None => write!(f, ".start:{}: ", self.idx)?,
+        }
+        match &self.kind {
+            BadConstant(c) => write!(f, "Constant #{} does not exist or has wrong type", c),
+            BadFunction(func) => write!(f, "Function #{} does not exist", func),
+            BadFrame(lvl, off) => {
+                write!(f, "Slot {} of frame {} levels out does not exist", off, lvl)
+            }
+            BadJump(to) => write!(f, "Jump to {} is out of the function", to),
+            PseudoInstruction => write!(f, "Compiler-use instruction left in code"),
+            StackUnderflow => write!(f, "Stack underflow"),
+            StackMismatch(a, b) => write!(f, "Paths reach here with {} and {} stack slots", a, b),
+            TypeMismatch { expected, found } => {
+                write!(f, "Expected {:?} on stack, found {:?}", expected, found)
+            }
+            ReturnMismatch => write!(f, "Function returns values of different sizes"),
+            FallsOffEnd => write!(f, "Control reaches the end of function without returning"),
+        }
+    }
+}
+
+type VerifyResult<T> = Result<T, VerifyError>;
+
+/// Most operand stack slots used at once, not counting parameters and
+/// local variables
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct StackDepth {
+    pub start: usize,
+    pub functions: Vec<usize>,
+}
+
+impl StackDepth {
+    /// The deepest function, with `None` for start code
+    pub fn max(&self) -> (Option<usize>, usize) {
+        (self.functions.iter().copied().enumerate())
+            .map(|(idx, d)| (Some(idx), d))
+            .fold((None, self.start), |a, b| if b.1 > a.1 { b } else { a })
+    }
+
+    /// Slots of the frame of start code and of each function: parameters,
+    /// local variables and the operand stack on top of them
+    pub fn frame_slots(&self, o0: &O0) -> (usize, Vec<usize>) {
+        let start = frame_size(0, &o0.start_code.ins) + self.start;
+        let functions = (o0.functions.iter().zip(&self.functions))
+            .map(|(f, depth)| frame_size(f.param_siz as usize, &f.ins) + depth)
+            .collect();
+        (start, functions)
+    }
+}
+
+/// Check all code in `o0`
+pub fn verify(o0: &O0) -> VerifyResult<()> {
+    stack_depth(o0).map(|_| ())
+}
+
+/// Check all code in `o0`, and find how deep each function's stack gets
+pub fn stack_depth(o0: &O0) -> VerifyResult<StackDepth> {
+    let ret_slots = o0
+        .functions
+        .iter()
+        .enumerate()
+        .map(|(idx, f)| {
+            ret_slots(&f.ins).ok_or_else(|| err(Some(idx), 0, VerifyErrorKind::ReturnMismatch))
+        })
+        .collect::<VerifyResult<Vec<_>>>()?;
+
+    let globals = frame_size(0, &o0.start_code.ins);
+    let start = Function {
+        o0,
+        ret_slots: &ret_slots,
+        func: None,
+        ins: &o0.start_code.ins,
+        lvl: 0,
+        frames: vec![globals],
+    };
+    let start = start.verify(0)?;
+
+    let mut functions = vec![];
+    for (idx, f) in o0.functions.iter().enumerate() {
+        let f = Function {
+            o0,
+            ret_slots: &ret_slots,
+            func: Some(idx),
+            ins: &f.ins,
+            lvl: f.lvl,
+            frames: vec![frame_size(f.param_siz as usize, &f.ins), globals],
+        };
+        functions.push(f.verify(o0.functions[idx].param_siz as usize)?);
+    }
+    Ok(StackDepth { start, functions })
+}
+
+fn err(func: Option<usize>, idx: usize, kind: VerifyErrorKind) -> VerifyError {
+    VerifyError { func, idx, kind }
+}
+
+/// Slots a function returns, or `None` if its returns disagree
+fn ret_slots(ins: &[Inst]) -> Option<usize> {
+    let mut slots = ins.iter().filter_map(|i| match i {
+        Inst::Ret => Some(0),
+        Inst::IRet | Inst::ARet => Some(1),
+        Inst::DRet => Some(2),
+        _ => None,
+    });
+    let first = slots.next().unwrap_or(0);
+    if slots.all(|s| s == first) {
+        Some(first)
+    } else {
+        None
+    }
+}
+
+/// Slots in a frame: parameters and what the first `snew` makes
+fn frame_size(params: usize, ins: &[Inst]) -> usize {
+    match ins.first() {
+        Some(Inst::SNew(n)) => params + *n as usize,
+        _ => params,
+    }
+}
+
+struct Function<'a> {
+    o0: &'a O0,
+    ret_slots: &'a [usize],
+    func: Option<usize>,
+    ins: &'a [Inst],
+    lvl: u16,
+    /// Sizes of reachable frames, the current one first
+    frames: Vec<usize>,
+}
+
+impl Function<'_> {
+    /// Check the function, returning its operand stack depth
+    fn verify(&self, params: usize) -> VerifyResult<usize> {
+        let len = self.ins.len();
+        // * Stacks are only kept where basic blocks start, which keeps long
+        // * straight runs of code with deep stacks cheap to check
+        let mut leaders = vec![false; len + 1];
+        leaders[0] = true;
+        leaders[len] = true;
+        for (idx, inst) in self.ins.iter().enumerate() {
+            if let Some(to) = jump_target(inst) {
+                if let Some(l) = leaders.get_mut(to as usize) {
+                    *l = true;
+                }
+                leaders[idx + 1] = true;
+            } else if is_return(inst) {
+                leaders[idx + 1] = true;
+            }
+        }
+
+        let mut entry: Vec<Option<Vec<Slot>>> = vec![None; len + 1];
+        let mut pending = vec![];
+        self.enter(0, vec![Slot::Any; params], &mut entry, &mut pending)?;
+        let mut depth = 0;
+
+        while let Some(start) = pending.pop() {
+            let mut stack = entry[start].clone().unwrap_or_default();
+            let mut idx = start;
+            loop {
+                let next = self
+                    .step(self.ins[idx], &mut stack)
+                    .map_err(|kind| err(self.func, idx, kind))?;
+                depth = depth.max(stack.len());
+                match next {
+                    Next::Fall if !leaders[idx + 1] => idx += 1,
+                    _ => {
+                        for to in next.targets(idx) {
+                            self.enter(to, stack.clone(), &mut entry, &mut pending)?;
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(depth.saturating_sub(self.frames[0]))
+    }
+
+    /// Record that control reaches the block at `idx` with `stack`, queueing
+    /// it to be checked if that tells anything new
+    fn enter(
+        &self,
+        idx: usize,
+        stack: Vec<Slot>,
+        entry: &mut [Option<Vec<Slot>>],
+        pending: &mut Vec<usize>,
+    ) -> VerifyResult<()> {
+        if idx >= self.ins.len() {
+            // * Start code ends by falling through; functions must return
+            if self.func.is_some() {
+                return Err(err(self.func, idx, VerifyErrorKind::FallsOffEnd));
+            }
+            return Ok(());
+        }
+        let merged = match &entry[idx] {
+            None => stack,
+            Some(prev) if prev.len() != stack.len() => {
+                let kind = VerifyErrorKind::StackMismatch(prev.len(), stack.len());
+                return Err(err(self.func, idx, kind));
+            }
+            Some(prev) => {
+                let merged: Vec<_> = (prev.iter().zip(&stack))
+                    .map(|(a, b)| if a == b { *a } else { Slot::Any })
+                    .collect();
+                if merged == *prev {
+                    return Ok(());
+                }
+                merged
+            }
+        };
+        entry[idx] = Some(merged);
+        pending.push(idx);
+        Ok(())
+    }
+
+    /// Apply `inst` to `stack`, returning where control goes next
+    fn step(&self, inst: Inst, stack: &mut Vec<Slot>) -> Result<Next, VerifyErrorKind> {
+        use Inst::*;
+        use Slot::*;
+        match inst {
+            Nop => (),
+            CPush(_) | IPush(_) => stack.push(Int),
+            Pop1 => pop_any(stack, 1)?,
+            Pop2 => pop_any(stack, 2)?,
+            PopN(n) => pop_any(stack, n as usize)?,
+            Dup => {
+                let top = *stack.last().ok_or(VerifyErrorKind::StackUnderflow)?;
+                stack.push(top);
+            }
+            Dup2 => {
+                let len = stack.len();
+                if len < 2 {
+                    return Err(VerifyErrorKind::StackUnderflow);
+                }
+                stack.extend_from_within(len - 2..);
+            }
+            LoadC(c) => match self.o0.constants.get(c as usize) {
+                Some(Constant::Number(_)) => stack.push(Int),
+                Some(Constant::Float(_)) => stack.extend([Double, Double]),
+                Some(Constant::String(_)) => stack.push(Addr),
+                None => return Err(VerifyErrorKind::BadConstant(c)),
+            },
+            LoadA(lvl, off) => {
+                let size = if lvl <= self.lvl {
+                    self.frames.get(lvl as usize).copied()
+                } else {
+                    None
+                };
+                match size {
+                    Some(size) if off >= 0 && (off as usize) < size => stack.push(Addr),
+                    _ => return Err(VerifyErrorKind::BadFrame(lvl, off)),
+                }
+            }
+            New => {
+                pop(stack, &[Int])?;
+                stack.push(Addr);
+            }
+            SNew(n) => stack.extend(std::iter::repeat_n(Any, n as usize)),
+
+            ILoad => load(stack, &[Int])?,
+            DLoad => load(stack, &[Double, Double])?,
+            ALoad => load(stack, &[Addr])?,
+            IALoad => array_load(stack, &[Int])?,
+            DALoad => array_load(stack, &[Double, Double])?,
+            AALoad => array_load(stack, &[Addr])?,
+            IStore => pop(stack, &[Addr, Int])?,
+            DStore => pop(stack, &[Addr, Double, Double])?,
+            AStore => pop(stack, &[Addr, Addr])?,
+            IAStore => pop(stack, &[Addr, Int, Int])?,
+            DAStore => pop(stack, &[Addr, Int, Double, Double])?,
+            AAStore => pop(stack, &[Addr, Int, Addr])?,
+
+            IAdd | ISub | IMul | IDiv | ICmp => {
+                pop(stack, &[Int, Int])?;
+                stack.push(Int);
+            }
+            DAdd | DSub | DMul | DDiv => {
+                pop(stack, &[Double, Double, Double, Double])?;
+                stack.extend([Double, Double]);
+            }
+            INeg | I2C => {
+                pop(stack, &[Int])?;
+                stack.push(Int);
+            }
+            DNeg => {
+                pop(stack, &[Double, Double])?;
+                stack.extend([Double, Double]);
+            }
+            DCmp => {
+                pop(stack, &[Double, Double, Double, Double])?;
+                stack.push(Int);
+            }
+            I2D => {
+                pop(stack, &[Int])?;
+                stack.extend([Double, Double]);
+            }
+            D2I => {
+                pop(stack, &[Double, Double])?;
+                stack.push(Int);
+            }
+
+            Jmp(to) => return self.jump(to).map(|_| Next::Jump(to)),
+            JE(to) | JNe(to) | JL(to) | JGe(to) | JG(to) | JLe(to) => {
+                pop(stack, &[Int])?;
+                self.jump(to)?;
+                return Ok(Next::Branch(to));
+            }
+            Call(f) => {
+                let callee =
+                    (self.o0.functions.get(f as usize)).ok_or(VerifyErrorKind::BadFunction(f))?;
+                pop_any(stack, callee.param_siz as usize)?;
+                match self.ret_slots[f as usize] {
+                    0 => (),
+                    1 => stack.push(Any),
+                    n => stack.extend(std::iter::repeat_n(Double, n)),
+                }
+            }
+            Ret => return Ok(Next::Return),
+            IRet => {
+                pop(stack, &[Int])?;
+                return Ok(Next::Return);
+            }
+            ARet => {
+                pop(stack, &[Addr])?;
+                return Ok(Next::Return);
+            }
+            DRet => {
+                pop(stack, &[Double, Double])?;
+                return Ok(Next::Return);
+            }
+
+            IPrint | CPrint => pop(stack, &[Int])?,
+            DPrint => pop(stack, &[Double, Double])?,
+            SPrint => pop(stack, &[Addr])?,
+            PrintLn => (),
+            IScan | CScan => stack.push(Int),
+            DScan => stack.extend([Double, Double]),
+
+            _Gt | _Lt | _Eq | _Gte | _Lte | _Neq | _Call(_) => {
+                return Err(VerifyErrorKind::PseudoInstruction)
+            }
+        }
+        Ok(Next::Fall)
+    }
+
+    fn jump(&self, to: u16) -> Result<(), VerifyErrorKind> {
+        if (to as usize) < self.ins.len() {
+            Ok(())
+        } else {
+            Err(VerifyErrorKind::BadJump(to))
+        }
+    }
+}
+
+/// Where control goes after an instruction
+enum Next {
+    Fall,
+    Jump(u16),
+    Branch(u16),
+    Return,
+}
+
+impl Next {
+    fn targets(&self, idx: usize) -> Vec<usize> {
+        match *self {
+            Next::Fall => vec![idx + 1],
+            Next::Jump(to) => vec![to as usize],
+            Next::Branch(to) => vec![idx + 1, to as usize],
+            Next::Return => vec![],
+        }
+    }
+}
+
+fn jump_target(inst: &Inst) -> Option<u16> {
+    use Inst::*;
+    match *inst {
+        Jmp(to) | JE(to) | JNe(to) | JL(to) | JGe(to) | JG(to) | JLe(to) => Some(to),
+        _ => None,
+    }
+}
+
+fn is_return(inst: &Inst) -> bool {
+    matches!(inst, Inst::Ret | Inst::IRet | Inst::DRet | Inst::ARet)
+}
+
+/// Pop slots of any type
+fn pop_any(stack: &mut Vec<Slot>, n: usize) -> Result<(), VerifyErrorKind> {
+    let len = stack.len();
+    if len < n {
+        return Err(VerifyErrorKind::StackUnderflow);
+    }
+    stack.truncate(len - n);
+    Ok(())
+}
+
+/// Pop slots expected to be `expected`, listed bottom first
+fn pop(stack: &mut Vec<Slot>, expected: &[Slot]) -> Result<(), VerifyErrorKind> {
+    let len = stack.len();
+    if len < expected.len() {
+        return Err(VerifyErrorKind::StackUnderflow);
+    }
+    for (&found, &expected) in stack[len - expected.len()..].iter().zip(expected) {
+        if found != expected && found != Slot::Any {
+            return Err(VerifyErrorKind::TypeMismatch { expected, found });
+        }
+    }
+    stack.truncate(len - expected.len());
+    Ok(())
+}
+
+fn load(stack: &mut Vec<Slot>, val: &[Slot]) -> Result<(), VerifyErrorKind> {
+    pop(stack, &[Slot::Addr])?;
+    stack.extend_from_slice(val);
+    Ok(())
+}
+
+fn array_load(stack: &mut Vec<Slot>, val: &[Slot]) -> Result<(), VerifyErrorKind> {
+    pop(stack, &[Slot::Addr, Slot::Int])?;
+    stack.extend_from_slice(val);
+    Ok(())
+}
@@ -1,21 +1,7 @@
-use std::fs::*;
-use std::io::{Read, Write};
-use std::path::PathBuf;
-use structopt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use structopt::StructOpt;
-
-fn parse_verbosity(input: &str) -> Result<log::LevelFilter, &'static str> {
-    match input {
-        "info" => Ok(log::LevelFilter::Info),
-        "warn" => Ok(log::LevelFilter::Warn),
-        "error" => Ok(log::LevelFilter::Error),
-        "trace" => Ok(log::LevelFilter::Trace),
-        "debug" => Ok(log::LevelFilter::Debug),
-        "off" => Ok(log::LevelFilter::Off),
-        _ => Err("Bad verbosity level. Allowed values are: debug, trace, info, warn, error, off"),
-    }
-    // .map(|t| Some(Some(t)))
-}
+use tracing_subscriber::filter::{EnvFilter, ParseError};
 
 #[derive(StructOpt, Debug)]
 #[structopt(
@@ -28,32 +14,75 @@ O0: https://github.com/BUAA-SE-Compiling/c0-vm-standards
 "
 )]
 pub struct ParserConfig {
-    /// Input file. Defaults to stdin if no file were supplied.
+    /// Input file. Defaults to the `sources` of `chigusa.toml`, or stdin if
+    /// there is none.
     #[structopt(name = "file", parse(from_os_str))]
     pub input_file: Option<PathBuf>,
 
-    /// Output file.
-    #[structopt(short, long = "out", default_value = "out", parse(from_os_str))]
-    pub output_file: PathBuf,
+    /// Output file, or directory when compiling several sources. Defaults to
+    /// `out`.
+    #[structopt(short, long = "out", parse(from_os_str))]
+    pub output_file: Option<PathBuf>,
 
-    /// Verbossity. Allowed values are: debug, trace, info, warn, error, off.
-    #[structopt(short, long, default_value = "warn", parse(try_from_str = parse_verbosity))]
-    pub verbosity: log::LevelFilter,
+    /// Log more. `-v` logs compiler passes, `-vv` adds scopes and types,
+    /// `-vvv` logs everything.
+    #[structopt(short, long, parse(from_occurrences))]
+    pub verbose: u8,
 
-    /// Write result to stdout. Overwrites `output-file`. Only for `token`, `ast` and `s0` targets.
+    /// Log filter in `tracing-subscriber` syntax, e.g.
+    /// `chigusa::minivm=debug,chigusa::c0::parser=trace`. Overrides `-v`.
+    #[structopt(long)]
+    pub log_filter: Option<String>,
+
+    /// Print no errors. The exit code still tells what went wrong: 1 for
+    /// errors in the program, 2 for internal errors and 3 for I/O errors.
+    #[structopt(short, long, global = true)]
+    pub quiet: bool,
+
+    /// Stop after this many errors.
+    #[structopt(long, global = true)]
+    pub max_errors: Option<usize>,
+
+    /// Write result to stdout. Overwrites `output-file`. Only for `token`, `ast`, `s0`, size report and coverage map targets.
     #[structopt(long)]
     pub stdout: bool,
 
+    /// Print wall time and heap memory used by each compiler pass to stderr.
+    #[structopt(long)]
+    pub time_passes: bool,
+
+    /// Check that tokens and the syntax tree are well formed after each
+    /// pass, stopping with an internal compiler error where one is not. For
+    /// debugging the compiler; generated code is always checked.
+    #[structopt(long)]
+    pub verify_each: bool,
+
+    /// Print pass timings and counters of tokens, AST nodes, symbols,
+    /// instructions and peak heap memory to stderr.
+    #[structopt(long)]
+    pub stats: bool,
+
     // /// Use JIT compilation and run immediately.
     // #[structopt(long)]
     // pub jit: bool,
-    /// The type of code to emit. Allowed are: token, ast, s0, o0
+    /// The type of code to emit. Allowed are: token, ast, s0, o0,
+    /// size-report, size-report-json, coverage-map, callgraph, xref,
+    /// xref-json
     ///
     /// Emit result explanation:
     /// - Token: Direct result from lexer (tokenizer)
     /// - AST: Abstract Syntax Tree, direct result from parser (analyzer)
     /// - s0: C0 assembly file
     /// - o0: C0 binary file
+    /// - size-report: Instructions, bytes, constants and frame slots of each
+    ///   function in the binary, as a table
+    /// - size-report-json: The same report as JSON
+    /// - coverage-map: JSON mapping source lines to the instructions compiled
+    ///   from them
+    /// - callgraph: Graphviz DOT graph of which functions call which, with
+    ///   functions never called dashed and recursive calls red
+    /// - xref: Every symbol with where it is declared and used
+    /// - xref-json: The same listing as JSON
     #[structopt(long, default_value = "o0", parse(try_from_str = EmitOption::parse))]
     pub emit: EmitOption,
 
@@ -64,24 +93,585 @@ pub struct ParserConfig {
     /// Emit C0 binary file, same as `--emit o0`
     #[structopt(short = "c", long = "o0")]
     pub output_binary: bool,
+
+    /// Also write the source line of each instruction to `<output>.dbg`,
+    /// which `disasm` reads.
+    #[structopt(short = "g", long = "debug-info")]
+    pub debug_info: bool,
+
+    /// The machine to generate code for. Allowed are: o0, o0-32. Defaults
+    /// to o0.
+    ///
+    /// - o0: The standard C0 virtual machine
+    /// - o0-32: O0 with only 32-bit values, without `double`
+    #[structopt(long)]
+    pub target: Option<String>,
+
+    /// The version of C0 to accept. Allowed are: c0, c0-ext. Defaults to c0.
+    ///
+    /// - c0: C0 as specified; an assignment is a statement, with no value
+    /// - c0-ext: Assignments are expressions as in C, so `a = b = 3` and
+    ///   `if (a = 3)` are allowed
+    #[structopt(long)]
+    pub std: Option<String>,
+
+    /// Define this feature, compiling the lines between `#if FEATURE` and
+    /// its `#else` or `#endif` instead of those between `#else` and
+    /// `#endif`. Can be given more than once.
+    #[structopt(short = "D", long = "define", number_of_values = 1)]
+    pub defines: Vec<String>,
+
+    /// Optimization level. 0 turns off optimizations; 1, the default, turns
+    /// them on; 2 also computes values used again in later blocks only once.
+    #[structopt(short = "O", long)]
+    pub opt_level: Option<u8>,
+
+    /// Run these passes instead of those of the optimization level,
+    /// separated by commas, e.g. `fold,dce,cse`. Analyses they need run
+    /// too. Known are: alias, purity, cse, gvn, unroll, inline, layout, sccp,
+    /// copyprop, dse, fold, dce and peephole.
+    #[structopt(long)]
+    pub passes: Option<String>,
+
+    /// Unroll loops running a number of times known when compiling into at
+    /// most this many copies of their body. Below 2 turns unrolling off.
+    /// Defaults to 8.
+    #[structopt(long)]
+    pub unroll_factor: Option<u32>,
+
+    /// Statements and expressions the copies of an unrolled loop body may
+    /// add up to. Defaults to 64.
+    #[structopt(long)]
+    pub unroll_budget: Option<usize>,
+
+    /// Use the counts of a run written by `chigusa run --profile-out` to
+    /// inline small functions called often, and to lay out code so that
+    /// what ran most falls through and what never ran comes last. The
+    /// counts fit code compiled from the same source with the default
+    /// options, and are ignored for functions that changed.
+    #[structopt(long, parse(from_os_str))]
+    pub profile_use: Option<PathBuf>,
+
+    /// Optimize only this many functions: start code first, then functions
+    /// in the order they are declared. Which are optimized is printed to
+    /// stderr. Halving the number until the output works again finds the
+    /// function an optimization gets wrong.
+    #[structopt(long)]
+    pub opt_bisect: Option<usize>,
+
+    /// Only optimize this function. Can be given more than once; start code
+    /// is called `.start`.
+    #[structopt(long, number_of_values = 1)]
+    pub opt_fn: Vec<String>,
+
+    /// Only run this pass on the functions optimized, out of those the
+    /// optimization level or `--passes` runs. Can be given more than once.
+    #[structopt(long, number_of_values = 1)]
+    pub opt_pass: Vec<String>,
+
+    /// Tell on stderr what these passes did, separated by commas, e.g.
+    /// `inline,unroll`, and why they left code as it was. `inline`,
+    /// `unroll`, `cse` and `gvn` remark where in the source they apply;
+    /// `sccp`, `copyprop` and `dse` once for each function they change.
+    #[structopt(long)]
+    pub remarks: Option<String>,
+
+    /// Fail if any function needs more operand stack slots than this, not
+    /// counting its parameters and local variables.
+    #[structopt(long)]
+    pub max_stack_depth: Option<usize>,
+
+    /// Fail if the frame of any function, counting its parameters, local
+    /// variables and operand stack, needs more slots than this, instead of
+    /// producing a binary that runs out of stack.
+    #[structopt(long)]
+    pub max_frame_size: Option<usize>,
+
+    /// Parse, check and compile one function at a time, dropping its syntax
+    /// tree once its code is generated, for inputs too large to hold the
+    /// tree of whole. Calls are not optimized across functions. Only for
+    /// `s0`, `o0` and size report targets, without `--instrument-coverage`.
+    #[structopt(long)]
+    pub stream: bool,
+
+    /// Let integer literals too large for the type they are assigned,
+    /// passed or returned as wrap around, as in C, instead of reporting an
+    /// error.
+    #[structopt(long)]
+    pub allow_overflow: bool,
+
+    /// Add code counting how many times each block runs, which `main`
+    /// prints on a line starting with `#counts` before it returns, on any
+    /// VM. `<output>.counters` maps the counts to source lines for
+    /// `cov-report`.
+    #[structopt(long)]
+    pub instrument_coverage: bool,
+
+    /// Add checks for undefined behavior, which stop the program where it
+    /// happens, with its line and column, on the built-in VM. `undefined`
+    /// checks `int` arithmetic for overflow and division by zero,
+    /// dereferences of null references, and reads of local variables
+    /// never assigned. C0 has no shifts to check.
+    #[structopt(long, require_equals = true, parse(try_from_str = parse_sanitizer))]
+    pub sanitize: Option<Sanitizer>,
+
+    #[structopt(subcommand)]
+    pub cmd: Option<Command>,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum Command {
+    /// Run programs on every backend and report where they disagree.
+    ///
+    /// The AST interpreter is the reference; the other backend is the
+    /// bytecode VM running compiled code.
+    Difftest {
+        /// Source files to test.
+        #[structopt(name = "files", parse(from_os_str), required = true)]
+        files: Vec<PathBuf>,
+
+        /// File to use as stdin for every program. Defaults to `<file>.in`
+        /// next to each source file, or no input if that does not exist.
+        #[structopt(short, long, parse(from_os_str))]
+        input: Option<PathBuf>,
+
+        /// Treat programs running longer than this many steps as stuck.
+        #[structopt(long, default_value = "100000000")]
+        steps: u64,
+    },
+
+    /// Report errors in source files without generating any output.
+    ///
+    /// Stops at code generation, so it is quicker than compiling. Errors are
+    /// printed one per line as `file:line:col: error[code]: message`. Use
+    /// `--target` before `check` to check for another target.
+    Check {
+        /// Files to check. Checks stdin if there are none.
+        #[structopt(name = "files", parse(from_os_str))]
+        files: Vec<PathBuf>,
+    },
+
+    /// Make the fixes suggested with errors in source files, in place.
+    ///
+    /// Fixes are made only where they are sure to be right: a missing `;`
+    /// at the end of a line, a `)` or `]` missing before a `;` or `{`, a name
+    /// misspelled as one nearly like it, and a cast on an integer literal
+    /// out of the range of its type. Each file is
+    /// checked again after fixing it until no more fixes are found, and the
+    /// errors left are printed like `check` does.
+    Fix {
+        /// Files to fix. Fixes stdin to stdout if there are none.
+        #[structopt(name = "files", parse(from_os_str))]
+        files: Vec<PathBuf>,
+    },
+
+    /// Format source files in place.
+    ///
+    /// Layout is configured by the nearest `.chigusafmt.toml`, with
+    /// `indent_width` and `brace_style` ("same_line" or "next_line").
+    Fmt {
+        /// Files to format. Formats stdin to stdout if there are none.
+        #[structopt(name = "files", parse(from_os_str))]
+        files: Vec<PathBuf>,
+
+        /// Don't write anything; fail if any file is not formatted.
+        #[structopt(long)]
+        check: bool,
+    },
+
+    /// Compare two programs by their syntax trees.
+    ///
+    /// Formatting, comments and redundant parentheses are ignored. Each
+    /// difference is printed as `-` (removed), `+` (added) or `~` (changed),
+    /// with where it is and its source. Exits with 1 if the programs differ
+    /// or either does not parse, and 3 if either cannot be read.
+    AstDiff {
+        /// The old program.
+        #[structopt(name = "old", parse(from_os_str))]
+        old: PathBuf,
+
+        /// The new program.
+        #[structopt(name = "new", parse(from_os_str))]
+        new: PathBuf,
+    },
+
+    /// Print an O0 binary as annotated assembly.
+    ///
+    /// If `<file>.dbg` exists, as written by `-g`, source lines are shown
+    /// before the instructions compiled from them.
+    Disasm {
+        /// The binary to disassemble.
+        #[structopt(name = "file", parse(from_os_str))]
+        file: PathBuf,
+    },
+
+    /// Show which lines of a program its tests run.
+    ///
+    /// The program is compiled and run on the VM once for each input file,
+    /// then printed with how many times each line ran. Lines that never ran
+    /// are marked `#####`.
+    Cov {
+        /// Source file to measure.
+        #[structopt(name = "file", parse(from_os_str))]
+        file: PathBuf,
+
+        /// Files to use as stdin, one run each. Defaults to `<file>.in`
+        /// next to the source file, or one run without input.
+        #[structopt(short, long, parse(from_os_str))]
+        input: Vec<PathBuf>,
+
+        /// Treat runs longer than this many steps as stuck.
+        #[structopt(long, default_value = "100000000")]
+        steps: u64,
+    },
+
+    /// Show which lines of a program ran, from the counts a build with
+    /// `--instrument-coverage` printed.
+    ///
+    /// Counts of every output given are added up, and the program is printed
+    /// with how many times each line ran, as `cov` does.
+    CovReport {
+        /// Source file the program was compiled from.
+        #[structopt(name = "file", parse(from_os_str))]
+        file: PathBuf,
+
+        /// Map of counters, written next to the binary. Defaults to
+        /// `out.counters`.
+        #[structopt(long, parse(from_os_str))]
+        counters: Option<PathBuf>,
+
+        /// Output of each run of the program. Reads stdin if there are none.
+        #[structopt(name = "outputs", parse(from_os_str))]
+        outputs: Vec<PathBuf>,
+    },
+
+    /// Step through a program on the built-in VM, forwards and back.
+    ///
+    /// Commands are read from stdin; type `help` for a list. `back` goes back
+    /// over the instructions executed last, as many as `--history` keeps, to
+    /// see how a value came to be. Input read and output written stay as
+    /// they are when going back.
+    Debug {
+        /// Source file to debug.
+        #[structopt(name = "file", parse(from_os_str))]
+        file: PathBuf,
+
+        /// File to use as the program's stdin. Defaults to `<file>.in` next
+        /// to the source file, or no input.
+        #[structopt(short, long, parse(from_os_str))]
+        input: Option<PathBuf>,
+
+        /// Instructions to keep for going back.
+        #[structopt(long, default_value = "10000")]
+        history: usize,
+    },
+
+    /// Compile a program and run it on the built-in VM.
+    ///
+    /// The program reads stdin and writes stdout, and its return value is the
+    /// exit code. With `--expect-output`, output is compared with a file
+    /// instead, and the exit code tells whether they match.
+    Run {
+        /// Source file to run.
+        #[structopt(name = "file", parse(from_os_str))]
+        file: PathBuf,
+
+        /// Print instructions run and calls of each function to stderr.
+        #[structopt(long)]
+        profile: bool,
+
+        /// Write instructions run by each call stack to this file, in the
+        /// folded format flamegraph tools read.
+        #[structopt(long, parse(from_os_str))]
+        folded: Option<PathBuf>,
+
+        /// Write how many times each block of each function ran to this
+        /// file, for compiling with `--profile-use`.
+        #[structopt(long, parse(from_os_str))]
+        profile_out: Option<PathBuf>,
+
+        /// Read input from this file instead of stdin.
+        #[structopt(long, parse(from_os_str))]
+        stdin_file: Option<PathBuf>,
+
+        /// Compare output with this file instead of printing it. Whitespace
+        /// at the end of lines and of the output is ignored.
+        #[structopt(long, parse(from_os_str))]
+        expect_output: Option<PathBuf>,
+
+        /// Stop programs running longer than this many steps.
+        #[structopt(long, default_value = "100000000")]
+        steps: u64,
+
+        /// Stop programs running longer than this many seconds.
+        #[structopt(long, parse(try_from_str = parse_seconds))]
+        timeout: Option<Duration>,
+
+        /// Stop programs allocating more than this many bytes with `new`.
+        #[structopt(long)]
+        max_heap: Option<usize>,
+
+        /// Stop programs nesting more calls than this.
+        #[structopt(long)]
+        max_call_depth: Option<usize>,
+
+        /// Log instructions executed, with the values they take and their
+        /// source lines: only calls and returns, only jumps, or all of them,
+        /// the default.
+        #[structopt(
+            long,
+            name = "kind",
+            require_equals = true,
+            possible_values = &["calls", "jumps", "all"]
+        )]
+        trace: Option<Option<String>>,
+
+        /// Write the trace here. Defaults to `<file>.trace` next to the
+        /// source file.
+        #[structopt(long, parse(from_os_str))]
+        trace_file: Option<PathBuf>,
+
+        /// Only trace instructions in this function. Can be given more than
+        /// once; start code is called `.start`.
+        #[structopt(long, name = "function")]
+        trace_fn: Vec<String>,
+
+        /// Seed `rand()` with this instead of at random, so it gives the same
+        /// numbers every run.
+        #[structopt(long)]
+        seed: Option<u64>,
+
+        /// Make `clock()` count a millisecond every 1000 instructions run
+        /// instead of real time, so it gives the same times every run.
+        #[structopt(long)]
+        virtual_clock: bool,
+
+        /// Stop the program at undefined behavior, as `--sanitize` compiles
+        /// it to.
+        #[structopt(long, require_equals = true, parse(try_from_str = parse_sanitizer))]
+        sanitize: Option<Sanitizer>,
+    },
+
+    /// Recompile a program and rerun it every time it changes.
+    ///
+    /// Arguments after `--` are a chigusa command line to run on the file,
+    /// e.g. `chigusa watch prog.c0 -- run --stdin-file prog.in`. The file is
+    /// added after the subcommand. Without them, the file is only checked.
+    Watch {
+        /// Source file to watch.
+        #[structopt(name = "file", parse(from_os_str))]
+        file: PathBuf,
+
+        /// Command to run on each change.
+        #[structopt(last = true)]
+        args: Vec<String>,
+    },
+
+    /// Shrink a program while it keeps triggering a compiler bug.
+    ///
+    /// Statements are removed and expressions simplified for as long as the
+    /// program stays interesting. Without `--crashcmd`, interesting means
+    /// crashing the compiler at the same place. The result goes to stdout.
+    Reduce {
+        /// Source file to reduce.
+        #[structopt(name = "file", parse(from_os_str))]
+        file: PathBuf,
+
+        /// Shell command exiting with 0 on interesting programs, e.g. to
+        /// find miscompiles. `{}` is replaced by the file to test, which is
+        /// otherwise added at the end.
+        #[structopt(long)]
+        crashcmd: Option<String>,
+
+        /// Write the result to this file instead of stdout.
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+
+    /// List the constants, globals and functions of a program with their
+    /// doc comments.
+    ///
+    /// Doc comments are `///` lines right before a declaration. Each
+    /// declaration is listed with its signature, as Markdown or as an HTML
+    /// page.
+    Doc {
+        /// Source file to document.
+        #[structopt(name = "file", parse(from_os_str))]
+        file: PathBuf,
+
+        /// Output format: markdown or html.
+        #[structopt(long, default_value = "markdown", possible_values = &["markdown", "html"])]
+        format: String,
+
+        /// Write the listing to this file instead of stdout.
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+
+    /// Print how complex each function of a program is, for grading code
+    /// quality.
+    ///
+    /// For each function: its statements, cyclomatic complexity (1 plus
+    /// each `if`, `else if`, `while`, `&&` and `||`), most `if`s and
+    /// `while`s nested, and Halstead counts of operators and operands,
+    /// distinct and in all, with the volume they give.
+    Metrics {
+        /// Source file to measure.
+        #[structopt(name = "file", parse(from_os_str))]
+        file: PathBuf,
+
+        /// Print the metrics as JSON instead of a table.
+        #[structopt(long)]
+        json: bool,
+
+        /// Write the metrics to this file instead of stdout.
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+
+    /// Print a program with its names replaced, for distributing it.
+    ///
+    /// Variables, parameters, functions and loop labels are renamed to
+    /// `v0`, `v1` and so on, except `main`. Comments are dropped and the
+    /// layout is the one of `fmt`. The program still runs the same way.
+    Obfuscate {
+        /// Source file to obfuscate.
+        #[structopt(name = "file", parse(from_os_str))]
+        file: PathBuf,
+
+        /// Write the result to this file instead of stdout.
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+
+    /// Generate a random program, for fuzzing backends and benchmarking.
+    ///
+    /// Programs are valid by construction, finish quickly and have no
+    /// runtime errors, so every backend should run them the same way. The
+    /// same options always give the same program.
+    Gen {
+        /// Functions to generate besides `main`.
+        #[structopt(long, default_value = "10")]
+        functions: usize,
+
+        /// How deep statements and expressions nest at most.
+        #[structopt(long, default_value = "4")]
+        depth: usize,
+
+        /// Seed of the random choices.
+        #[structopt(long, default_value = "0")]
+        seed: u64,
+
+        /// Write the program to this file instead of stdout.
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+
+    /// Run a language server on stdin and stdout.
+    ///
+    /// Supports diagnostics, go to definition, hover and document symbols.
+    Lsp,
+
+    /// Run a debug adapter on stdin and stdout.
+    ///
+    /// Debugs programs on the built-in VM over the Debug Adapter Protocol:
+    /// breakpoints, stepping, call stacks and variables. Launch arguments are
+    /// `program`, `stdinFile` and `stopOnEntry`.
+    Dap,
 }
 
 #[derive(Debug, Eq, PartialEq)]
 pub enum EmitOption {
     Token,
     Ast,
+    /// The syntax tree after type checking, with the type of every
+    /// expression, and which variables each reference may refer to
+    TypedAst,
     S0,
     O0,
+    SizeReport,
+    SizeReportJson,
+    CoverageMap,
+    CallGraph,
+    Xref,
+    XrefJson,
+}
+
+/// Checks `--sanitize` adds
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Sanitizer {
+    Undefined,
+}
+
+impl ParserConfig {
+    /// Where output goes
+    pub fn output_path(&self) -> &Path {
+        self.output_file
+            .as_deref()
+            .unwrap_or_else(|| Path::new("out"))
+    }
+
+    /// The log filter asked for on the command line
+    pub fn log_filter(&self) -> Result<EnvFilter, ParseError> {
+        match &self.log_filter {
+            Some(filter) => EnvFilter::try_new(filter),
+            None => Ok(EnvFilter::new(match self.verbose {
+                0 => "warn",
+                1 => "info",
+                2 => "debug",
+                _ => "trace",
+            })),
+        }
+    }
+}
+
+/// Parse a non-negative number of seconds, like `1.5`
+fn parse_seconds(s: &str) -> Result<Duration, &'static str> {
+    match s.parse::<f64>() {
+        Ok(secs) if secs.is_finite() && secs >= 0.0 => Ok(Duration::from_secs_f64(secs)),
+        _ => Err("Expected a non-negative number of seconds"),
+    }
+}
+
+fn parse_sanitizer(s: &str) -> Result<Sanitizer, &'static str> {
+    match s {
+        "undefined" => Ok(Sanitizer::Undefined),
+        _ => Err("Expected `undefined`"),
+    }
 }
 
 impl EmitOption {
+    /// Extension of files holding this kind of output
+    pub fn extension(&self) -> &'static str {
+        match self {
+            EmitOption::Token => "tokens",
+            EmitOption::Ast => "ast",
+            EmitOption::TypedAst => "tast",
+            EmitOption::S0 => "s0",
+            EmitOption::O0 => "o0",
+            EmitOption::SizeReport | EmitOption::Xref => "txt",
+            EmitOption::SizeReportJson | EmitOption::CoverageMap | EmitOption::XrefJson => "json",
+            EmitOption::CallGraph => "dot",
+        }
+    }
+
     pub fn parse(s: &str) -> Result<Self, &'static str> {
         match s {
             "token" => Ok(EmitOption::Token),
             "ast" => Ok(EmitOption::Ast),
+            "typed-ast" => Ok(EmitOption::TypedAst),
             "s0" => Ok(EmitOption::S0),
             "o0" => Ok(EmitOption::O0),
-            _ => Err("Bad emit option. Allowed are: token, ast, s0, o0"),
+            "size-report" => Ok(EmitOption::SizeReport),
+            "size-report-json" => Ok(EmitOption::SizeReportJson),
+            "coverage-map" => Ok(EmitOption::CoverageMap),
+            "callgraph" => Ok(EmitOption::CallGraph),
+            "xref" => Ok(EmitOption::Xref),
+            "xref-json" => Ok(EmitOption::XrefJson),
+            _ => Err(
+                "Bad emit option. Allowed are: token, ast, typed-ast, s0, o0, \
+                 size-report, size-report-json, coverage-map, callgraph, xref, xref-json",
+            ),
         }
     }
 }
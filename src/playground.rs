@@ -0,0 +1,123 @@
+//! Entry point for running the compiler in a browser playground.
+//!
+//! With the `wasm` feature, [`compile_to_json`](crate::playground::compile_to_json) is exported to JavaScript
+//! through `wasm-bindgen`, and so is [`Runner`](crate::playground::Runner),
+//! for running programs a slice at a time.
+
+use crate::minivm::vm::{MiniVM, RunState, Snapshot};
+use crate::minivm::O0;
+use crate::prelude::Pos;
+use crate::{codegen, parse, CompileError, Stage};
+use serde_json::{json, Value};
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+/// Compile C0 `source` and describe the result as JSON.
+///
+/// On success this is
+/// `{"ok": true, "assembly": "<s0 text>", "binary": [<o0 bytes>]}`.
+/// Otherwise it is
+/// `{"ok": false, "error": {"kind", "code", "message", "span"}}`, where
+/// `kind` is `"parse"` or `"compile"`, `code` is like `"E0201"` and `span` is
+/// `null` or
+/// `{"start": {"line", "column"}, "end": {"line", "column"}}`, counted from
+/// 0 in chars.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn compile_to_json(source: &str) -> String {
+    let o0 = match parse(source).and_then(|prog| codegen(&prog)) {
+        Ok(o0) => o0,
+        Err(e) => return error(e),
+    };
+
+    let mut binary = vec![];
+    o0.write_binary(&mut binary)
+        .expect("Writing to a Vec cannot fail");
+    json!({
+        "ok": true,
+        "assembly": o0.to_string(),
+        "binary": binary,
+    })
+    .to_string()
+}
+
+fn error(e: CompileError) -> String {
+    let kind = match e.stage {
+        Stage::Parse => "parse",
+        Stage::Compile => "compile",
+    };
+    let pos = |p: Pos| json!({ "line": p.ln, "column": p.pos });
+    let span = e.span.map_or(
+        Value::Null,
+        |s| json!({ "start": pos(s.start), "end": pos(s.end) }),
+    );
+    json!({
+        "ok": false,
+        "error": {
+            "kind": kind,
+            "code": e.code.to_string(),
+            "message": e.message,
+            "span": span,
+        },
+    })
+    .to_string()
+}
+
+/// A program running on the VM that owns all it needs, so a host without
+/// threads can keep it between calls and run it in slices, updating its UI
+/// in between.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct Runner {
+    o0: O0,
+    input: Vec<u8>,
+    /// Bytes of input read so far
+    read: usize,
+    state: Snapshot,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl Runner {
+    /// Load an O0 `binary`, as made by [`compile_to_json`], to run on
+    /// `input`
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(binary: &[u8], input: &str) -> Result<Runner, String> {
+        let o0 = O0::read_binary(&mut &binary[..]).map_err(|e| e.to_string())?;
+        Ok(Runner {
+            o0,
+            input: input.as_bytes().to_vec(),
+            read: 0,
+            state: Snapshot::default(),
+        })
+    }
+
+    /// Execute at most `steps` more instructions and describe where that
+    /// left the program as JSON:
+    /// `{"state": "paused" | "finished" | "trapped", "output": "<written in
+    /// this slice>"}`, with `"code"` once finished and `"error"` once
+    /// trapped.
+    pub fn run_steps(&mut self, steps: u32) -> String {
+        let mut input = &self.input[self.read..];
+        let mut output = vec![];
+        let mut vm = MiniVM::new(&self.o0, &mut input, &mut output);
+        vm.restore(&self.state)
+            .expect("Snapshots taken of this program restore");
+        let res = vm.run_steps(steps as u64);
+        self.state = vm.snapshot();
+        drop(vm);
+        self.read = self.input.len() - input.len();
+
+        let mut json = json!({ "output": String::from_utf8_lossy(&output) });
+        match res {
+            RunState::Paused => json["state"] = json!("paused"),
+            RunState::Finished(code) => {
+                json["state"] = json!("finished");
+                json["code"] = json!(code);
+            }
+            RunState::Trapped(e) => {
+                json["state"] = json!("trapped");
+                json["error"] = json!(e.to_string());
+            }
+        }
+        json.to_string()
+    }
+}
@@ -1,15 +1,21 @@
-use crate::c0::lexer::TokenType;
-use std::str::{Chars, FromStr};
-use std::{
+use alloc::rc::{Rc, Weak};
+use core::{
     cell::{Ref, RefCell, RefMut},
     cmp::PartialOrd,
     fmt,
     fmt::Display,
     fmt::Formatter,
-    hash::Hash,
-    // ops::Try,
-    rc::{Rc, Weak},
-    string::String,
+};
+
+// * What the `std` prelude would bring in
+#[cfg(not(feature = "std"))]
+pub use alloc::{
+    borrow::ToOwned,
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
 };
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
@@ -96,7 +102,7 @@ impl Pos {
 }
 
 impl Display for Pos {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         write!(
             f,
             "Pos(idx {}, ln {} col {})",
@@ -106,13 +112,13 @@ impl Display for Pos {
 }
 
 impl PartialOrd for Pos {
-    fn partial_cmp(&self, other: &Pos) -> Option<std::cmp::Ordering> {
-        Some(self.index.cmp(&other.index))
+    fn partial_cmp(&self, other: &Pos) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
     }
 }
 
 impl Ord for Pos {
-    fn cmp(&self, other: &Pos) -> std::cmp::Ordering {
+    fn cmp(&self, other: &Pos) -> core::cmp::Ordering {
         self.index.cmp(&other.index)
     }
 }
@@ -136,15 +142,20 @@ impl Span {
     pub fn zero() -> Span {
         Span::from(Pos::zero(), Pos::zero())
     }
+
+    /// Does this span fully cover `other`?
+    pub fn contains(&self, other: Span) -> bool {
+        self.start <= other.start && other.end <= self.end
+    }
 }
 
 impl Display for Span {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         write!(f, "{}->{}", self.start, self.end)
     }
 }
 
-impl std::ops::Add for Span {
+impl core::ops::Add for Span {
     type Output = Span;
 
     /// An add between spans is combining them together, and filling the center
@@ -195,8 +206,8 @@ impl std::ops::Add for Span {
     /// });
     /// ```
     fn add(self, rhs: Span) -> Self::Output {
-        let smaller_start = std::cmp::min(self.start, rhs.start);
-        let larger_end = std::cmp::max(self.end, rhs.end);
+        let smaller_start = core::cmp::min(self.start, rhs.start);
+        let larger_end = core::cmp::max(self.end, rhs.end);
         Span {
             start: smaller_start,
             end: larger_end,
@@ -212,16 +223,16 @@ impl<T> Ptr<T> {
         Ptr(Rc::new(RefCell::new(val)))
     }
 
-    pub fn borrow(&self) -> Ref<T> {
+    pub fn borrow(&self) -> Ref<'_, T> {
         self.0.borrow()
     }
 
-    pub fn borrow_mut(&self) -> RefMut<T> {
+    pub fn borrow_mut(&self) -> RefMut<'_, T> {
         self.0.borrow_mut()
     }
 
     pub fn downgrade(self) -> Weak<RefCell<T>> {
-        std::rc::Rc::downgrade(&self.0)
+        Rc::downgrade(&self.0)
     }
 
     /// Copy pointer
@@ -233,7 +244,7 @@ impl<T> Ptr<T> {
 impl<T> Clone for Ptr<T> {
     /// Deep clone
     fn clone(&self) -> Self {
-        Ptr((&self.0).clone())
+        Ptr(self.0.clone())
     }
 }
 
@@ -270,9 +281,55 @@ pub trait AstNode {
     // fn return_type(&self, scope: &super::ast::Scope) -> Option<&str>;
 }
 
+/// Remaining stack below which recursive passes over the AST switch to a
+/// freshly allocated stack segment.
+pub const STACK_RED_ZONE: usize = 64 * 1024;
+
+/// Size of each stack segment allocated when [`STACK_RED_ZONE`] is hit.
+pub const STACK_GROW_SIZE: usize = 1024 * 1024;
+
+/// Run `f`, on a new stack segment if less than [`STACK_RED_ZONE`] is left.
+/// Without `std` the stack cannot grow, and `f` just runs.
+#[inline]
+pub fn maybe_grow<R>(f: impl FnOnce() -> R) -> R {
+    #[cfg(feature = "std")]
+    return stacker::maybe_grow(STACK_RED_ZONE, STACK_GROW_SIZE, f);
+    #[cfg(not(feature = "std"))]
+    f()
+}
+
+/// SplitMix64, a small seeded random number generator. Random enough for
+/// picking test programs, and gives the same numbers on every platform.
+#[derive(Debug, Clone)]
+pub struct SplitMix64(u64);
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> SplitMix64 {
+        SplitMix64(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number below `n`, which must not be 0
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// True once in `n` times
+    pub fn one_in(&mut self, n: usize) -> bool {
+        self.below(n) == 0
+    }
+}
+
 #[inline]
 pub fn variant_eq<T>(a: &T, b: &T) -> bool {
-    std::mem::discriminant(a) == std::mem::discriminant(b)
+    core::mem::discriminant(a) == core::mem::discriminant(b)
 }
 
 #[macro_export]
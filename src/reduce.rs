@@ -0,0 +1,124 @@
+//! `chigusa reduce`: shrink a program that crashes or miscompiles, for a bug
+//! report.
+
+use crate::check::{io_error, load_failed};
+use crate::exit::Exit;
+use crate::ice;
+use crate::opt::ParserConfig;
+use crate::source;
+use chigusa::c0::reduce::reduce as reduce_src;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Reduce `file`, compiled with the features `-D` defines, for as long as it
+/// stays interesting, and print the result or write it to `output`. The lines
+/// `#if` directives leave out are left out of the result too. Returns 1 if
+/// `file` is not interesting, and reports files that cannot be read or
+/// written as `chigusa check` reports them.
+///
+/// With `crashcmd`, a program is interesting if the shell command exits with
+/// 0. `{}` in it is replaced by the file to test, or the file is added at the
+/// end. Without it, a program is interesting if compiling it crashes at the
+/// same place in the compiler as `file` does.
+pub fn reduce(
+    file: &Path,
+    crashcmd: Option<&str>,
+    output: Option<&Path>,
+    opt: &ParserConfig,
+) -> Exit {
+    let src = match source::load(file, &opt.defines) {
+        Ok(src) => src.compiled().to_owned(),
+        Err(e) => return load_failed(file, e, opt, &mut 0),
+    };
+    let say = |msg: String| {
+        if !opt.quiet {
+            eprintln!("{}", msg);
+        }
+    };
+
+    let scratch = scratch_file(file);
+    let mut tests = 0;
+    let reduced = match crashcmd {
+        Some(cmd) => reduce_src(&src, |candidate| {
+            tests += 1;
+            passes(cmd, &scratch, candidate)
+        }),
+        None => match ice::crash_location(&src) {
+            Some(location) => {
+                say(format!(
+                    "{} crashes the compiler at {}",
+                    file.display(),
+                    location
+                ));
+                reduce_src(&src, |candidate| {
+                    tests += 1;
+                    ice::crash_location(candidate).as_ref() == Some(&location)
+                })
+            }
+            None => {
+                say(format!(
+                    "{} doesn't crash the compiler; use --crashcmd to say what to look for",
+                    file.display()
+                ));
+                return Exit::CompileError;
+            }
+        },
+    };
+    let _ = std::fs::remove_file(&scratch);
+
+    let reduced = match reduced {
+        Some(reduced) => reduced,
+        None => {
+            say(format!(
+                "{} is not interesting: it doesn't parse, or the command fails on it",
+                file.display()
+            ));
+            return Exit::CompileError;
+        }
+    };
+    say(format!(
+        "Reduced {} lines to {} in {} tests",
+        src.lines().count(),
+        reduced.lines().count(),
+        tests
+    ));
+    match output {
+        Some(output) => match std::fs::write(output, &reduced) {
+            Ok(()) => Exit::Success,
+            Err(e) => io_error(output, "cannot write", &e, opt),
+        },
+        None => {
+            print!("{}", reduced);
+            Exit::Success
+        }
+    }
+}
+
+/// Where candidates are written for the command to read, named after `file`
+fn scratch_file(file: &Path) -> PathBuf {
+    let stem = file.file_stem().unwrap_or(file.as_os_str());
+    let mut name = stem.to_owned();
+    name.push(format!(".reduce-{}.c0", std::process::id()));
+    std::env::temp_dir().join(name)
+}
+
+/// Does `cmd` exit with 0 on `candidate`?
+fn passes(cmd: &str, scratch: &Path, candidate: &str) -> bool {
+    if std::fs::write(scratch, candidate).is_err() {
+        return false;
+    }
+    let path = scratch.to_string_lossy();
+    let cmd = if cmd.contains("{}") {
+        cmd.replace("{}", &path)
+    } else {
+        format!("{} {}", cmd, path)
+    };
+    Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
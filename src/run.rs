@@ -0,0 +1,255 @@
+//! `chigusa run`: compile a program and run it on the built-in VM.
+
+use crate::check::{io_error, load_failed, report};
+use crate::opt::ParserConfig;
+use crate::source;
+use chigusa::minivm::vm::{function_name, intrinsic_sigs, Intrinsics, MiniVM, Profile, TraceKind};
+use chigusa::minivm::{Codegen, ExecProfile, O0};
+use std::fs::File;
+use std::io::{BufRead, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How to run a program, and what to do with the run
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+    /// Print a flat profile to stderr
+    pub profile: bool,
+    /// Write folded call stacks here
+    pub folded: Option<PathBuf>,
+    /// Write counts of blocks run here
+    pub profile_out: Option<PathBuf>,
+    /// Read input from this file instead of stdin
+    pub stdin_file: Option<PathBuf>,
+    /// Compare output with this file instead of printing it
+    pub expect_output: Option<PathBuf>,
+    pub trace: Option<TraceOptions>,
+    pub limits: Limits,
+    /// Where `rand` and `clock` get their values from
+    pub intrinsics: Intrinsics,
+    /// Check for undefined behavior
+    pub ub_checks: bool,
+    /// Features `#if` directives test for
+    pub defines: Vec<String>,
+}
+
+/// What instructions to log, and where
+#[derive(Debug, Clone)]
+pub struct TraceOptions {
+    pub kind: TraceKind,
+    pub file: PathBuf,
+    /// Functions to trace, or all if empty
+    pub functions: Vec<String>,
+}
+
+/// What a program may use before it is stopped
+#[derive(Debug, Clone)]
+pub struct Limits {
+    pub steps: u64,
+    pub timeout: Option<Duration>,
+    pub heap_bytes: Option<usize>,
+    pub call_depth: Option<usize>,
+}
+
+/// Run `file` with the process's stdin and stdout, or the files in `opts`.
+/// Returns the program's exit code, 1 if it could not be compiled or failed
+/// at runtime, or 3 if a file could not be read or written.
+///
+/// With an expected output, returns 0 if the program finished and printed
+/// it, and 1 otherwise.
+///
+/// Errors are printed as `chigusa check` prints them, unless `--quiet` is
+/// given in `opt`.
+pub fn run(file: &Path, opts: &RunOptions, opt: &ParserConfig) -> i32 {
+    let mut errors = 0;
+    let src = match source::load(file, &opts.defines) {
+        Ok(src) => src,
+        Err(e) => return load_failed(file, e, opt, &mut errors) as i32,
+    };
+    let stdin_data = match &opts.stdin_file {
+        Some(path) => match std::fs::read(path) {
+            Ok(data) => Some(data),
+            Err(e) => return io_error(path, "cannot read input", &e, opt) as i32,
+        },
+        None => None,
+    };
+    let expected = match &opts.expect_output {
+        Some(path) => match std::fs::read(path) {
+            Ok(data) => Some(data),
+            Err(e) => return io_error(path, "cannot read expected output", &e, opt) as i32,
+        },
+        None => None,
+    };
+    let o0 = match chigusa::parse_with_host(src.compiled(), &intrinsic_sigs()) {
+        // * Traces show source lines, and profiles need where blocks start
+        Ok(prog) => Codegen::new(&prog)
+            .with_debug_info(opts.trace.is_some() || opts.profile_out.is_some())
+            .with_ub_checks(opts.ub_checks)
+            .compile()
+            .map_err(|e| src.annotate(e)),
+        Err(e) => Err(src.annotate(e)),
+    };
+    let o0 = match o0 {
+        Ok(o0) => o0,
+        Err(e) => return report(file, &[e.into()], opt, &mut errors) as i32,
+    };
+
+    let limits = &opts.limits;
+    let stdin = std::io::stdin();
+    let mut stdin_lock;
+    let mut stdin_slice;
+    let input: &mut dyn BufRead = match &stdin_data {
+        Some(data) => {
+            stdin_slice = data.as_slice();
+            &mut stdin_slice
+        }
+        None => {
+            stdin_lock = stdin.lock();
+            &mut stdin_lock
+        }
+    };
+    let stdout = std::io::stdout();
+    let mut stdout_lock;
+    let mut captured = vec![];
+    let output: &mut dyn Write = match &expected {
+        Some(_) => &mut captured,
+        None => {
+            stdout_lock = stdout.lock();
+            &mut stdout_lock
+        }
+    };
+
+    let mut trace_out = match &opts.trace {
+        Some(trace) => match File::create(&trace.file) {
+            Ok(f) => Some(BufWriter::new(f)),
+            Err(e) => return io_error(&trace.file, "cannot write trace", &e, opt) as i32,
+        },
+        None => None,
+    };
+
+    let mut vm = MiniVM::new(&o0, input, output)
+        .with_step_limit(limits.steps)
+        .with_intrinsics(opts.intrinsics);
+    if let Some(timeout) = limits.timeout {
+        vm = vm.with_timeout(timeout);
+    }
+    if let Some(bytes) = limits.heap_bytes {
+        vm = vm.with_heap_limit(bytes);
+    }
+    if let Some(depth) = limits.call_depth {
+        vm = vm.with_call_depth_limit(depth);
+    }
+    if opts.profile || opts.folded.is_some() {
+        vm = vm.with_profile();
+    }
+    if opts.profile_out.is_some() {
+        vm = vm.with_coverage();
+    }
+    if let (Some(trace), Some(out)) = (&opts.trace, &mut trace_out) {
+        for name in &trace.functions {
+            let found =
+                (0..o0.functions.len()).any(|f| function_name(&o0, Some(f as u16)) == *name);
+            if !found && name != ".start" {
+                eprintln!("{}: no function `{}` to trace", file.display(), name);
+            }
+        }
+        let functions: Vec<_> = trace.functions.iter().map(String::as_str).collect();
+        vm = vm.with_trace(trace.kind, &functions, out);
+    }
+    let result = vm.run();
+    let prof = vm.profile().cloned();
+    let counts = vm.coverage().and_then(|c| ExecProfile::new(&o0, c));
+    drop(vm);
+    if let (Some(trace), Some(mut out)) = (&opts.trace, trace_out) {
+        if let Err(e) = out.flush() {
+            eprintln!("{}: cannot write trace: {}", trace.file.display(), e);
+        }
+    }
+
+    // * A profile of a failed run still shows where it got stuck
+    if let Some(prof) = prof {
+        if opts.profile {
+            eprint!("{}", flat_profile(&o0, &prof));
+        }
+        if let Some(path) = &opts.folded {
+            if let Err(e) = std::fs::write(path, prof.folded(&o0)) {
+                eprintln!("{}: cannot write folded stacks: {}", path.display(), e);
+            }
+        }
+    }
+    if let (Some(path), Some(counts)) = (&opts.profile_out, counts) {
+        if let Err(e) = std::fs::write(path, counts.to_json()) {
+            eprintln!("{}: cannot write profile: {}", path.display(), e);
+        }
+    }
+
+    let code = match &result {
+        Ok(code) => *code,
+        Err(e) => {
+            if !opt.quiet {
+                eprintln!("{}: runtime error: {}", file.display(), e);
+            }
+            1
+        }
+    };
+    match &expected {
+        Some(expected) => {
+            let expected = String::from_utf8_lossy(expected);
+            let actual = String::from_utf8_lossy(&captured);
+            match first_difference(&expected, &actual) {
+                Some((ln, want, got)) => {
+                    eprintln!("{}: wrong output at line {}", file.display(), ln);
+                    eprintln!("  expected: {}", want.unwrap_or("<end of output>"));
+                    eprintln!("     found: {}", got.unwrap_or("<end of output>"));
+                    1
+                }
+                None if result.is_err() => 1,
+                None => {
+                    eprintln!("{}: ok", file.display());
+                    0
+                }
+            }
+        }
+        None => code,
+    }
+}
+
+/// First line where `actual` differs from `expected`, counted from 1, with
+/// both lines. Whitespace at the end of lines and of the output is ignored.
+fn first_difference<'s>(
+    expected: &'s str,
+    actual: &'s str,
+) -> Option<(usize, Option<&'s str>, Option<&'s str>)> {
+    let lines = |s: &'s str| {
+        let mut lines: Vec<_> = s.lines().map(str::trim_end).collect();
+        while lines.last() == Some(&"") {
+            lines.pop();
+        }
+        lines
+    };
+    let (expected, actual) = (lines(expected), lines(actual));
+    (0..expected.len().max(actual.len()))
+        .find(|&i| expected.get(i) != actual.get(i))
+        .map(|i| (i + 1, expected.get(i).copied(), actual.get(i).copied()))
+}
+
+/// A `gprof`-style flat profile, one line per function
+fn flat_profile(o0: &O0, prof: &Profile) -> String {
+    let total = prof.total().max(1);
+    let mut out = format!(
+        "\n{:>7} {:>12} {:>12} {:>10}  function\n",
+        "self %", "self", "total", "calls"
+    );
+    for f in prof.functions() {
+        out += &format!(
+            "{:>6.2}% {:>12} {:>12} {:>10}  {}\n",
+            f.self_insts as f64 * 100.0 / total as f64,
+            f.self_insts,
+            f.total_insts,
+            f.calls,
+            function_name(o0, f.func)
+        );
+    }
+    out += &format!("\n{} instructions executed\n", prof.total());
+    out
+}
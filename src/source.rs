@@ -0,0 +1,70 @@
+//! Reading the source a command works on, with its `#if` directives applied
+//! with the features `-D` defines. Every command reads sources this way, so
+//! they all compile the same lines of a file.
+
+use chigusa::{CompileError, SourceMap};
+use std::borrow::Cow;
+use std::fmt;
+use std::path::Path;
+
+/// A source as written and as compiled
+#[derive(Debug, Clone)]
+pub struct Source {
+    /// The source as written, which errors are shown in
+    pub text: String,
+    /// `text` as compiled, if it has directives
+    preprocessed: Option<String>,
+    /// Which `#if` compiled each line
+    pub map: SourceMap,
+}
+
+/// Why a source could not be loaded
+#[derive(Debug)]
+pub enum LoadError {
+    Io(std::io::Error),
+    /// The directives are wrong
+    Compile(CompileError),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "cannot read file: {}", e),
+            LoadError::Compile(e) => write!(f, "parse error: {}", e),
+        }
+    }
+}
+
+impl Source {
+    /// `text` compiled with `defines`
+    pub fn new(text: String, defines: &[String]) -> Result<Source, CompileError> {
+        let (compiled, map) = chigusa::preprocess_mapped(&text, defines)?;
+        let preprocessed = match compiled {
+            Cow::Borrowed(_) => None,
+            Cow::Owned(compiled) => Some(compiled),
+        };
+        Ok(Source {
+            text,
+            preprocessed,
+            map,
+        })
+    }
+
+    /// `text` with its directives and the lines they leave out turned into
+    /// spaces. Spans into it are spans into `text`.
+    pub fn compiled(&self) -> &str {
+        self.preprocessed.as_deref().unwrap_or(&self.text)
+    }
+
+    /// `e`, found in the compiled source, with notes on the `#if` directives
+    /// compiling where it is
+    pub fn annotate(&self, e: impl Into<CompileError>) -> CompileError {
+        self.map.annotate(e.into())
+    }
+}
+
+/// Read `file`, compiled with `defines`
+pub fn load(file: &Path, defines: &[String]) -> Result<Source, LoadError> {
+    let text = std::fs::read_to_string(file).map_err(LoadError::Io)?;
+    Source::new(text, defines).map_err(LoadError::Compile)
+}
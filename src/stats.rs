@@ -0,0 +1,135 @@
+//! `--stats`: counters of what each compiler pass produced.
+
+use crate::time_passes::bytes;
+use chigusa::c0::ast::*;
+use chigusa::minivm::O0;
+use chigusa::prelude::{maybe_grow, Ptr};
+
+/// Counters are filled in as passes finish; the ones of passes that did not
+/// run are left empty and not printed.
+#[derive(Debug, Default)]
+pub struct Stats {
+    pub tokens: Option<usize>,
+    pub ast_nodes: Option<usize>,
+    pub symbols: Option<usize>,
+    pub instructions: Option<usize>,
+    pub peak_heap: Option<usize>,
+}
+
+impl Stats {
+    pub fn count_ast(&mut self, prog: &Program) {
+        let mut counter = AstCounter::default();
+        counter.block(&prog.blk);
+        self.ast_nodes = Some(counter.nodes);
+        self.symbols = Some(counter.symbols);
+    }
+
+    pub fn count_instructions(&mut self, s0: &O0) {
+        let start = s0.start_code.ins.len();
+        let functions: usize = s0.functions.iter().map(|f| f.ins.len()).sum();
+        self.instructions = Some(start + functions);
+    }
+
+    /// Print counters to stderr
+    pub fn report(&self) {
+        let counters = [
+            ("tokens", self.tokens.map(|n| n.to_string())),
+            ("ast nodes", self.ast_nodes.map(|n| n.to_string())),
+            ("symbols", self.symbols.map(|n| n.to_string())),
+            ("instructions", self.instructions.map(|n| n.to_string())),
+            ("peak heap", self.peak_heap.map(|n| bytes(n as f64))),
+        ];
+        for (name, val) in counters.iter() {
+            if let Some(val) = val {
+                eprintln!("{:<12} {:>12}", name, val);
+            }
+        }
+    }
+}
+
+/// Counts statements, expressions and symbols defined in the program.
+/// Symbols of the root scope (built-in types) are not counted.
+#[derive(Default)]
+struct AstCounter {
+    nodes: usize,
+    symbols: usize,
+}
+
+impl AstCounter {
+    fn block(&mut self, blk: &Block) {
+        let scope = blk.scope.borrow();
+        self.symbols += scope.defs.len();
+        for def in scope.defs.values() {
+            if let SymbolDef::Var { typ, .. } = &*def.borrow() {
+                if let TypeDef::Function(func) = &*typ.borrow() {
+                    if let Some(body) = &func.body {
+                        self.block(body);
+                    }
+                }
+            }
+        }
+        drop(scope);
+
+        for stmt in &blk.stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        self.nodes += 1;
+        match &stmt.var {
+            StmtVariant::If(i) => {
+                self.expr(&i.cond);
+                self.stmt(&i.if_block.borrow());
+                for (cond, block) in &i.else_ifs {
+                    self.expr(cond);
+                    self.stmt(&block.borrow());
+                }
+                if let Some(else_block) = &i.else_block {
+                    self.stmt(&else_block.borrow());
+                }
+            }
+            StmtVariant::While(w) => {
+                self.expr(&w.cond);
+                self.stmt(&w.block.borrow());
+            }
+            StmtVariant::Block(b) => self.block(b),
+            StmtVariant::Expr(e) => self.expr(e),
+            StmtVariant::Print(es) | StmtVariant::ManyExpr(es) => {
+                for e in es {
+                    self.expr(e);
+                }
+            }
+            StmtVariant::Return(Some(e)) => self.expr(e),
+            StmtVariant::Scan(..)
+            | StmtVariant::Return(None)
+            | StmtVariant::Break(_)
+            | StmtVariant::Empty => (),
+        }
+    }
+
+    fn expr(&mut self, expr: &Ptr<Expr>) {
+        maybe_grow(|| {
+            self.nodes += 1;
+            match &expr.borrow().var {
+                ExprVariant::Ident(_) | ExprVariant::Literal(_) => (),
+                ExprVariant::TypeConversion(t) => self.expr(&t.expr),
+                ExprVariant::UnaryOp(u) => self.expr(&u.val),
+                ExprVariant::BinaryOp(b) => {
+                    self.expr(&b.lhs);
+                    self.expr(&b.rhs);
+                }
+                ExprVariant::FunctionCall(f) => {
+                    for p in &f.params {
+                        self.expr(p);
+                    }
+                }
+                ExprVariant::StructChild(s) => self.expr(&s.val),
+                ExprVariant::ArrayChild(a) => {
+                    self.expr(&a.val);
+                    self.expr(&a.idx);
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,125 @@
+use super::exec;
+use crate::c0::alias::{Aliases, Place};
+use crate::c0::ast::{PrimitiveType, PrimitiveTypeVar, TypeDef};
+use crate::c0::type_checker::lower;
+use crate::minivm::*;
+use crate::parse;
+use crate::prelude::*;
+
+const SRC: &str = r#"void set(&int p, int v) {
+    *p = v;
+}
+
+int main() {
+    int a = 1;
+    int b = 2;
+    double d = 3.0;
+    double e = 4.0;
+    &double pe = &e;
+    &int p = &a;
+    set(p, 3);
+    set(&d, 4);
+    return b;
+}
+"#;
+
+fn key(id: usize, name: &str) -> (usize, String) {
+    (id, name.into())
+}
+
+#[test]
+fn test_points_to() {
+    let aliases = Aliases::new(&lower(&parse(SRC).unwrap()).unwrap());
+    let names = |place: Place| -> Vec<String> {
+        let targets = aliases.targets(&place);
+        targets.into_iter().map(|(_, name)| name).collect()
+    };
+    assert_eq!(names(Place::Through(key(2, "p"))), ["a"]);
+    // * `&d` becomes a `&int`, so `p` of `set` refers to a double too
+    assert_eq!(names(Place::Through(key(1, "p"))), ["a", "d"]);
+    assert_eq!(names(Place::Var(key(2, "b"))), ["b"]);
+    assert!(aliases.punned.contains(&key(2, "d")));
+
+    // * Any `&int` may refer to `a`, or to `d` through the conversion, but
+    // * not to `e`
+    let int = Ptr::new(TypeDef::Primitive(PrimitiveType {
+        var: PrimitiveTypeVar::SignedInt,
+        occupy_bytes: 4,
+    }));
+    assert_eq!(names(Place::Anywhere(int)), ["a", "d"]);
+
+    let through = Place::Through(key(2, "p"));
+    assert!(aliases.may_alias(&through, &Place::Var(key(2, "a"))));
+    assert!(!aliases.may_alias(&through, &Place::Var(key(2, "b"))));
+    assert!(!aliases.may_alias(&through, &Place::Through(key(2, "pe"))));
+}
+
+fn run(src: &str, cse: bool) -> (usize, String) {
+    let prog = parse(src).unwrap();
+    let o0 = Codegen::new(&prog).with_cse(cse).compile().unwrap();
+    let main = o0.functions.last().unwrap();
+    let calls = (main.ins.iter())
+        .filter(|i| matches!(i, Inst::Call(_)))
+        .count();
+    (calls, exec(&o0, "").1)
+}
+
+#[test]
+fn test_cse_across_stores() {
+    let src = r#"int g;
+int sq(int x) {
+    return x * x + g;
+}
+int main() {
+    int a = 3;
+    int b = 4;
+    &int p = &b;
+    &int q = &a;
+    int r = sq(a);
+    *p = 10;
+    int s = sq(a) + sq(b);
+    *q = 5;
+    int t = sq(a);
+    g = 1;
+    int u = sq(a);
+    print(r, s, t, u, sq(b));
+    return 0;
+}
+"#;
+    let out = "9 109 25 26 101\n";
+    assert_eq!(run(src, false), (6, out.into()));
+    // * `*p = 10` can't change `a`, so `sq(a)` is reused once. `*q = 5` can,
+    // * and writing `g` may change any call
+    assert_eq!(run(src, true), (5, out.into()));
+}
+
+#[test]
+fn test_no_cse_across_branches() {
+    let src = r#"int g;
+int sq(int x) {
+    return x * x;
+}
+int main() {
+    int a = 3;
+    int r = 0;
+    if (g > 5) {
+        r = sq(a);
+    }
+    int s = sq(a);
+    a = 4;
+    int t = sq(a);
+    while (r < 2) {
+        r = r + 1;
+        t = t + sq(a);
+    }
+    t = t + sq(a);
+    print(s, t);
+    return 0;
+}
+"#;
+    // * `sq(a)` in the `if` may not run, after `a = 4` it has to run again,
+    // * and it does after the loop as well
+    let out = "9 64\n";
+    assert_eq!(run(src, false), (5, out.into()));
+    assert_eq!(run(src, true), (5, out.into()));
+}
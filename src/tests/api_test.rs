@@ -0,0 +1,79 @@
+use crate::{
+    check, check_for, codegen, codegen_with, lex, parse, CompilerPass, Diagnostic, ErrorCode, Inst,
+    InstSink, PassManager, Stage, Target, TokenType,
+};
+
+#[test]
+fn test_api_pipeline() {
+    let src = "int main() {\n    // answer\n    print(42);\n    return 0;\n}\n";
+    let tokens = lex(src);
+    assert_eq!(tokens.len(), 14);
+    assert!(!tokens
+        .iter()
+        .any(|tok| matches!(tok.var, TokenType::Comment(_))));
+
+    let prog = parse(src).unwrap();
+    assert!(check(&prog).is_empty());
+    let o0 = codegen(&prog).unwrap();
+    assert!(o0.to_string().contains("iprint"));
+}
+
+#[test]
+fn test_api_diagnostics() {
+    let e = parse("int main() {\n    return x;\n}\n").unwrap_err();
+    assert_eq!(e.stage, Stage::Parse);
+    assert_eq!(e.code, ErrorCode(201));
+    assert_eq!(e.span.unwrap().start.ln, 1);
+    assert!(e.to_string().starts_with("error[E0201]: "));
+
+    let prog = parse("int main() {\n    return 99999999999;\n}\n").unwrap();
+    let diags = check(&prog);
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].stage, Stage::Compile);
+    assert_eq!(diags[0].code, ErrorCode(319));
+    assert_eq!(Diagnostic::from(codegen(&prog).unwrap_err()), diags[0]);
+}
+
+#[test]
+fn test_api_check_for_target() {
+    let prog = parse("int main() {\n    double d = 1.5;\n    return 0;\n}\n").unwrap();
+    assert!(check(&prog).is_empty());
+    let o0_32 = Target::from_name("o0-32").unwrap();
+    let diags = check_for(&prog, o0_32);
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].code, ErrorCode(327));
+}
+
+#[test]
+fn test_compile_error_is_std_error() {
+    fn compile(src: &str) -> Result<(), Box<dyn std::error::Error>> {
+        codegen(&parse(src)?)?;
+        Ok(())
+    }
+    assert!(compile("int main() { return 0; }").is_ok());
+    let e = compile("int f() {}\nint main() { return f(); }").unwrap_err();
+    assert!(e.to_string().contains("E0401"), "{}", e);
+}
+
+#[test]
+fn test_api_custom_pass() {
+    struct EntryNop;
+    impl CompilerPass for EntryNop {
+        fn name(&self) -> &str {
+            "entry-nop"
+        }
+
+        fn run_on_function(&self, name: &str, code: &mut InstSink) -> Result<(), String> {
+            if name == "main" {
+                code.insert_before(0, &[Inst::Nop]);
+            }
+            Ok(())
+        }
+    }
+
+    let mut prog = parse("int main() { return 0; }").unwrap();
+    let passes = PassManager::default().with_pass(EntryNop).unwrap();
+    let o0 = codegen_with(&mut prog, passes).unwrap();
+    assert_eq!(o0.functions[0].ins[0], Inst::Nop);
+    assert_ne!(o0.start_code.ins.first(), Some(&Inst::Nop));
+}
@@ -0,0 +1,103 @@
+use crate::c0::ast_diff::{ast_diff, Change, Node};
+use crate::c0::err::str_span;
+use crate::c0::parser::parse_no_panic;
+
+/// Differences from `old` to `new`, with spans shown as the source they
+/// cover
+fn diff(old: &str, new: &str) -> Vec<String> {
+    let (a, b) = (parse_no_panic(old).unwrap(), parse_no_panic(new).unwrap());
+    (ast_diff(&a, &b).into_iter())
+        .map(|change| match change {
+            Change::Added(node, span) => format!("+ {} `{}`", node, str_span(new, span)),
+            Change::Removed(node, span) => format!("- {} `{}`", node, str_span(old, span)),
+            Change::Changed {
+                node,
+                old: o,
+                new: n,
+            } => format!(
+                "~ {} `{}` -> `{}`",
+                node,
+                str_span(old, o),
+                str_span(new, n)
+            ),
+        })
+        .collect()
+}
+
+const PROG: &str = "int g = 1;
+int sq(int x) { return x * x; }
+int main() {
+    int a = 2;
+    if (a < 3) {
+        print(sq(a) + 1);
+    }
+    return 0;
+}
+";
+
+#[test]
+fn test_ast_diff_ignores_formatting() {
+    let reformatted = "int g=1;
+int sq(int x)
+{
+    return (x * x); // squared
+}
+int main() { int a = 2; if (a < 3) print(sq(a) + 1); return 0; }
+";
+    // * `{ print(...); }` and `print(...);` are different trees
+    assert_eq!(
+        diff(PROG, reformatted),
+        ["~ statement `{\n        print(sq(a) + 1);\n    }` -> `print(sq(a) + 1);`"]
+    );
+    assert!(diff(PROG, PROG).is_empty());
+}
+
+#[test]
+fn test_ast_diff_finds_smallest_change() {
+    let changed = PROG.replace("+ 1", "+ 2").replace("int a", "double a");
+    assert_eq!(
+        diff(PROG, &changed),
+        [
+            "~ variable 'a' `a = 2` -> `a = 2`",
+            "~ expression `1` -> `2`"
+        ]
+    );
+}
+
+#[test]
+fn test_ast_diff_added_and_removed() {
+    let changed = "int h = 1;
+int sq(int x) { return x * x; }
+int cube(int x) { return x * x * x; }
+int main() {
+    int a = 2;
+    if (a < 3) {
+        print(sq(a) + 1);
+    }
+    scan(a);
+    return 0;
+}
+";
+    assert_eq!(
+        diff(PROG, changed),
+        [
+            "- variable 'g' `g = 1`",
+            "+ statement `scan(a);`",
+            "+ variable 'h' `h = 1`",
+            "+ function 'cube' `cube(int x)`",
+            // * The initializer of the global
+            "~ expression `g` -> `h`",
+        ]
+    );
+}
+
+#[test]
+fn test_ast_diff_function_signature() {
+    let changed = PROG.replace("int sq(int x)", "double sq(int x)");
+    assert_eq!(
+        diff(PROG, &changed),
+        ["~ function 'sq' `sq(int x)` -> `sq(int x)`"]
+    );
+    let node = Node::Function("sq".into());
+    assert_eq!(node.to_string(), "function 'sq'");
+}
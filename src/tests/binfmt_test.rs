@@ -0,0 +1,85 @@
+use super::compile;
+use crate::minivm::binfmt::{self, BinError};
+use crate::minivm::{Endian, O0};
+
+fn to_bytes(o0: &O0) -> Vec<u8> {
+    let mut buf = vec![];
+    o0.write_binary(&mut buf).unwrap();
+    buf
+}
+
+#[test]
+fn test_binfmt_layout() {
+    let o0 = compile("int main() {\n    return 1;\n}\n");
+    let bytes = to_bytes(&o0);
+    #[rustfmt::skip]
+    let expected = [
+        0x43, 0x30, 0x3a, 0x29, // magic
+        0x00, 0x00, 0x00, 0x01, // version
+        0x00, 0x01, // constants_count
+        0x00, 0x00, 0x04, b'm', b'a', b'i', b'n', // "main"
+        0x00, 0x01, 0x0c, 0x00, 0x00, 0x00, 0x00, // start_code: snew 0
+        0x00, 0x01, // functions_count
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x01, // name_index, params_size, level
+    ];
+    assert_eq!(bytes[..expected.len()], expected);
+    let ins = &o0.functions[0].ins;
+    assert_eq!(
+        bytes[expected.len()..expected.len() + 2],
+        (ins.len() as u16).to_be_bytes()
+    );
+}
+
+#[test]
+fn test_binfmt_round_trip() {
+    let src = include_str!("../../tests/cases/fib.c0");
+    // * Relocations aren't part of the binary
+    let o0 = O0 {
+        relocs: vec![],
+        ..compile(src)
+    };
+    let bytes = to_bytes(&o0);
+    let read = O0::read_binary(&mut &bytes[..]).unwrap();
+    assert_eq!(read, o0);
+    assert_eq!(to_bytes(&read), bytes);
+
+    let o0 = compile("int main() { double x = 1.5; print(x, 'c', \"s\"); return 0; }");
+    let little = O0 {
+        endian: Endian::Little,
+        ..o0
+    };
+    let bytes = to_bytes(&little);
+    assert_eq!(bytes[..4], [0x29, 0x3a, 0x30, 0x43]);
+    assert_eq!(binfmt::read(&mut &bytes[..]).unwrap(), little);
+}
+
+#[test]
+fn test_binfmt_read_errors() {
+    let bytes = to_bytes(&compile("int main() { return 0; }"));
+
+    let mut bad = bytes.clone();
+    bad[0] = 0;
+    assert!(matches!(
+        O0::read_binary(&mut &bad[..]),
+        Err(BinError::BadMagic(_))
+    ));
+
+    let mut bad = bytes.clone();
+    bad[7] = 2;
+    assert!(matches!(
+        O0::read_binary(&mut &bad[..]),
+        Err(BinError::BadVersion(2))
+    ));
+
+    let mut bad = bytes.clone();
+    *bad.last_mut().unwrap() = 0xff;
+    assert!(matches!(
+        O0::read_binary(&mut &bad[..]),
+        Err(BinError::BadOpcode(0xff))
+    ));
+
+    assert!(matches!(
+        O0::read_binary(&mut &bytes[..bytes.len() - 1]),
+        Err(BinError::Io(_))
+    ));
+}
@@ -0,0 +1,80 @@
+use crate::c0::callgraph::*;
+use crate::prelude::*;
+use crate::{call_graph, parse};
+
+fn graph(src: &str) -> CallGraph {
+    call_graph(&parse(src).unwrap())
+}
+
+fn names(g: &CallGraph, fns: &[usize]) -> Vec<String> {
+    fns.iter().map(|&f| g.fns[f].name.clone()).collect()
+}
+
+#[test]
+fn test_call_graph() {
+    let g = graph(
+        "int h() { return 1; }\n\
+         int k() { return h() + h(); }\n\
+         int x = h();\n\
+         int f(int n) { while (n) { n = n - k(); } return n; }\n\
+         void dead() { k(); }\n\
+         int main() { print(f(1)); return 0; }\n",
+    );
+    assert_eq!(
+        names(&g, &(0..g.fns.len()).collect::<Vec<_>>()),
+        ["h", "k", "f", "dead", "main"]
+    );
+    assert_eq!(g.fns[1].calls, [0]);
+    assert_eq!(g.fns[2].calls, [1]);
+    assert_eq!(names(&g, &g.roots), ["h", "main"]);
+    assert_eq!(names(&g, &g.unreachable()), ["dead"]);
+    assert!(g.cycles().is_empty());
+    assert_eq!(g.max_depth(), Some(4));
+    assert_eq!(g.fns[g.find("dead").unwrap()].span.start.ln, 4);
+}
+
+#[test]
+fn test_recursion() {
+    let g = graph(
+        "int fact(int n) { if (n) return n * fact(n - 1); return 1; }\n\
+         int main() { return fact(5); }\n",
+    );
+    assert_eq!(g.cycles(), [vec![0]]);
+    assert_eq!(g.max_depth(), None);
+    let dot = g.to_dot();
+    assert!(dot.contains("\"fact\" -> \"fact\" [color=red];"), "{}", dot);
+    assert!(dot.contains("\"main\" -> \"fact\";"), "{}", dot);
+    assert!(dot.contains("unbounded"), "{}", dot);
+
+    // * Recursion that is never reached doesn't make depth unbounded
+    let g = graph("void loop() { loop(); }\nint main() { return 0; }\n");
+    assert_eq!(g.max_depth(), Some(1));
+    assert!(g.to_dot().contains("\"loop\" [style=dashed];"));
+}
+
+#[test]
+fn test_indirect_recursion() {
+    // * C0 has no forward declarations, so build the graph by hand
+    let node = |name: &str, calls: Vec<usize>| FnNode {
+        name: name.into(),
+        span: Span::zero(),
+        is_extern: false,
+        calls,
+    };
+    let g = CallGraph {
+        fns: vec![
+            node("main", vec![1]),
+            node("a", vec![2]),
+            node("b", vec![3]),
+            node("c", vec![1, 4]),
+            node("d", vec![]),
+        ],
+        roots: vec![0],
+    };
+    assert_eq!(g.cycles(), [vec![1, 2, 3]]);
+    assert_eq!(g.max_depth(), None);
+    let cycles = g.cycles();
+    assert!(g.is_recursive_call(&cycles, 3, 1));
+    assert!(!g.is_recursive_call(&cycles, 3, 4));
+    assert!(!g.is_recursive_call(&cycles, 0, 1));
+}
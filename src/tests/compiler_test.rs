@@ -0,0 +1,326 @@
+use super::{exec, run};
+use crate::c0::lexer::Lexer;
+use crate::c0::parser::*;
+use crate::minivm::*;
+
+fn compile(input: &str) -> CompileResult<O0> {
+    let lexer = Lexer::new(input);
+    let mut parser = Parser::new(lexer);
+    let prog = parser.parse().expect("Failed to parse program");
+
+    Codegen::new(&prog).compile()
+}
+
+#[test]
+fn test_if_stmt() {
+    let input = r#"
+int main(){
+    int a = 1;
+    if (a > 0) {
+        a = 2;
+    } else if (a < 0) {
+        a = 3;
+    } else
+        a = 4;
+    if (a) print(a);
+    return a;
+}
+    "#;
+
+    let res = compile(input);
+
+    assert!(res.is_ok(), "{:#?}", res);
+}
+
+#[test]
+fn test_while_stmt() {
+    let input = r#"
+int main(){
+    int a = 10, b = 0;
+    while (a > 0) {
+        b = b + a;
+        a = a - 1;
+        if (b > 20) break;
+    }
+    return b;
+}
+    "#;
+
+    let res = compile(input);
+
+    assert!(res.is_ok(), "{:#?}", res);
+}
+
+#[test]
+fn test_if_missing_return() {
+    let input = r#"
+int main(){
+    int a = 1;
+    if (a) return 1;
+}
+    "#;
+
+    let res = compile(input);
+
+    assert!(res.is_err(), "{:#?}", res);
+}
+
+#[test]
+fn test_labeled_break() {
+    let input = r#"
+int main(){
+    int i = 0, j;
+    outer: while (i < 10) {
+        j = 0;
+        inner: while (j < 10) {
+            if (i * j > 20) break outer;
+            if (j > i) break inner;
+            j = j + 1;
+        }
+        i = i + 1;
+    }
+    return i;
+}
+    "#;
+
+    let res = compile(input);
+
+    assert!(res.is_ok(), "{:#?}", res);
+}
+
+#[test]
+fn test_break_unknown_label() {
+    let input = r#"
+int main(){
+    int i = 0;
+    outer: while (i < 10) {
+        while (1) break inner;
+        i = i + 1;
+    }
+    return i;
+}
+    "#;
+
+    let res = compile(input);
+
+    assert!(res.is_err(), "{:#?}", res);
+}
+
+#[test]
+fn test_long_else_if_chain() {
+    let mut input = String::from("int main(){\n    int a = 0;\n    if (a == 0) a = 1;\n");
+    for i in 1..500 {
+        input.push_str(&format!("    else if (a == {}) a = {};\n", i, i + 1));
+    }
+    input.push_str("    return a;\n}\n");
+
+    let res = compile(&input);
+
+    assert!(res.is_ok(), "{:#?}", res);
+}
+
+#[test]
+fn test_deeply_nested_expr() {
+    let depth = 100_000;
+    let input = format!(
+        "int main(){{\n    int a = 1;\n    return {}a{};\n}}\n",
+        "(a + ".repeat(depth),
+        ")".repeat(depth)
+    );
+
+    let res = compile(&input);
+
+    assert!(res.is_ok(), "{:#?}", res.map(|_| ()));
+}
+
+#[test]
+fn test_deeply_nested_stmts() {
+    let depth = 10_000;
+    let input = format!(
+        "int main(){{\n    int a = 1;\n    {}a = a + 1;{}\n    return a;\n}}\n",
+        "if (1) while (0) { ".repeat(depth),
+        " }".repeat(depth)
+    );
+
+    let res = compile(&input);
+
+    assert!(res.is_ok(), "{:#?}", res.map(|_| ()));
+}
+
+#[test]
+fn test_long_expr() {
+    // * Left-nested, as `a + a + a` parses
+    let input = format!(
+        "int main(){{\n    int a = 1;\n    return a{};\n}}\n",
+        " + a".repeat(100_000)
+    );
+
+    let res = compile(&input);
+
+    assert!(res.is_ok(), "{:#?}", res.map(|_| ()));
+}
+
+#[test]
+fn test_literal_range() {
+    let code = |src: &str| {
+        compile(src)
+            .err()
+            .map(|e| (e.var.code().0, e.span.unwrap().start.pos))
+    };
+
+    assert_eq!(code("int main(){ int a = -2147483648; return a; }"), None);
+    assert_eq!(code("int main(){ char c = 255; return 0; }"), None);
+    assert_eq!(
+        code("int main(){ char c = 256; return 0; }"),
+        Some((319, 21))
+    );
+    assert_eq!(
+        code("int main(){ char c; c = -1; return 0; }"),
+        Some((319, 24))
+    );
+    assert_eq!(
+        code("int f(char c){ return c; }\nint main(){ return f(300); }"),
+        Some((319, 21))
+    );
+    assert_eq!(
+        code("char f(){ return 1000; }\nint main(){ return f(); }"),
+        Some((319, 17))
+    );
+    assert_eq!(code("int main(){ return 2147483648; }"), Some((319, 19)));
+    assert_eq!(
+        code("int main(){ print(2147483648); return 0; }"),
+        Some((312, 18))
+    );
+}
+
+#[test]
+fn test_allow_overflow() {
+    let src = "int main(){ char c = 300; int a = 4294967297; print(-2147483649); return a; }";
+    let prog = Parser::new(Lexer::new(src)).parse().unwrap();
+    // * Unoptimized, so that the stores of the literals are left
+    let o0 = Codegen::new(&prog)
+        .with_allow_overflow(true)
+        .with_peephole(false)
+        .compile()
+        .unwrap();
+
+    let main = &o0.functions[0].ins;
+    assert!(main.contains(&Inst::IPush(300)));
+    assert!(main.contains(&Inst::IPush(1)));
+    assert!(main.contains(&Inst::IPush(2147483647)));
+}
+
+#[test]
+fn test_assignment_standard() {
+    let src = "int main(){ int a, b; a = b = 3; while ((b = b - 1) > 0) print(b); if (a = 4) print(a); return 0; }";
+    let prog = Parser::new(Lexer::new(src)).parse().unwrap();
+
+    let err = Codegen::new(&prog).compile().unwrap_err();
+    assert_eq!(err.var.code().0, 320);
+    let err = crate::CompileError::from(err);
+    assert!(err.notes.is_empty());
+
+    let o0 = Codegen::new(&prog)
+        .with_standard(Standard::C0_EXT)
+        .compile()
+        .unwrap();
+    assert_eq!(exec(&o0, "").1, "2\n1\n4\n");
+
+    let src = "int main(){ int a; if (a = 1) a = 2; return 0; }";
+    let prog = Parser::new(Lexer::new(src)).parse().unwrap();
+    let err = crate::CompileError::from(Codegen::new(&prog).compile().unwrap_err());
+    assert_eq!(err.code.0, 321);
+    // * At the `=`, which the fix replaces
+    assert_eq!(err.span.unwrap().start.pos, 25);
+    assert_eq!(err.notes[0].message, "did you mean `==`?");
+    assert_eq!(err.fixes[0].replacement, "==");
+}
+
+#[test]
+fn test_comma_operator() {
+    let src = "int f(int x){ print(x); return x; }\nint main(){ int a = 1, b = (a = 5, a + 1), c; c = (f(7), 8); print(a, b, c); return 0; }";
+    assert_eq!(run(src), "7\n5 6 8\n");
+}
+
+#[test]
+fn test_short_circuit() {
+    let src = "int calls = 0;\n\
+               int touch(int v) { calls = calls + 1; return v; }\n\
+               int main() {\n\
+                   int a, b, i = 0;\n\
+                   scan(a); scan(b);\n\
+                   if (a && touch(b)) print(1);\n\
+                   if (b || touch(a)) print(2);\n\
+                   if (b != 0 && a / b > 1) print(3);\n\
+                   if ((a || touch(b)) && touch(a - 1)) print(4);\n\
+                   while (i < 3 && touch(a)) i = i + 1;\n\
+                   print(i, calls);\n\
+                   return 0;\n\
+               }\n";
+    let o0 = compile(src).unwrap();
+    // * The right side runs only where the left side does not decide, so
+    // * `a / b` never divides by zero
+    assert_eq!(exec(&o0, "1 0").1, "2\n3 6\n");
+    assert_eq!(exec(&o0, "0 2").1, "2\n4\n0 3\n");
+    assert_eq!(exec(&o0, "6 2").1, "1\n2\n3\n4\n3 5\n");
+
+    let err = compile("int main() { int a = 1, b = a && 0; return b; }").unwrap_err();
+    assert!(
+        matches!(err.var, CompileErrorVar::LogicAsValue),
+        "{:?}",
+        err
+    );
+}
+
+#[test]
+fn test_fold_global_calls() {
+    let src =
+        "int fib(int n) {\n    if (n <= 1) return n;\n    return fib(n - 1) + fib(n - 2);\n}\n\
+               int n = 3;\n\
+               int a = fib(20) + 1;\n\
+               double b = fib(5) / 2.0;\n\
+               int c = fib(n);\n\
+               int main() { print(a, b, c); return 0; }\n";
+    let streamed = crate::codegen_streamed(src, |codegen| codegen).unwrap();
+    for o0 in [compile(src).unwrap(), streamed] {
+        // * Only `fib(n)` reads a variable, so only it is left to run
+        let calls = (o0.start_code.ins.iter())
+            .filter(|i| matches!(i, Inst::Call(_)))
+            .count();
+        assert_eq!(calls, 1);
+        assert!(o0.start_code.ins.contains(&Inst::IPush(6766)));
+
+        assert_eq!(exec(&o0, "").1, "6766 2.500000 2\n");
+    }
+}
+
+#[test]
+fn test_fold_global_consts() {
+    let src = "const int N = 4;\n\
+               const char C = 'a' + 1;\n\
+               const int M = N * N;\n\
+               const int P = 2147483647 + 1;\n\
+               int total = M + N;\n\
+               int fib(int n) {\n\
+                   if (n <= 1) return n;\n\
+                   return fib(n - 1) + fib(n - 2);\n\
+               }\n\
+               const int F = fib(10);\n\
+               int main() {\n\
+                   int x = N;\n\
+                   print(x, C, M, total, P, F);\n\
+                   return 0;\n\
+               }\n";
+    let streamed = crate::codegen_streamed(src, |codegen| codegen).unwrap();
+    for o0 in [compile(src).unwrap(), streamed] {
+        // * Only `total`, and `P` that wraps around, are stored
+        assert_eq!(o0.start_code.ins.first(), Some(&Inst::SNew(2)));
+        assert!(o0.start_code.ins.contains(&Inst::IPush(20)));
+        assert!(!o0.start_code.ins.iter().any(|i| matches!(i, Inst::Call(_))));
+
+        assert_eq!(exec(&o0, "").1, "4 b 16 20 -2147483648 55\n");
+    }
+
+    let err = compile("const int N = 4;\nint main() { N = 5; return 0; }\n").unwrap_err();
+    assert!(matches!(err.var, CompileErrorVar::AssignConst), "{:?}", err);
+}
@@ -0,0 +1,112 @@
+use crate::c0::ast::*;
+use crate::consteval::*;
+use crate::error::ErrorCode;
+
+/// Evaluate the initializer of the last constant declared in `src`
+fn eval_last(src: &str) -> Result<Value, EvalError> {
+    let prog = crate::parse(src).unwrap();
+    let scope = prog.blk.scope.cp();
+    let def = scope.borrow().defs.values().last().unwrap().cp();
+    let value = match &*def.borrow() {
+        SymbolDef::Var {
+            value: Some(value), ..
+        } => value.cp(),
+        _ => panic!("Not a constant"),
+    };
+    let value = value.borrow();
+    eval(&value, &scope)
+}
+
+#[test]
+fn test_eval_arithmetic() {
+    assert_eq!(
+        eval_last("const int X = 1 + 2 * 3 - 8 / 3;"),
+        Ok(Value::Int(5))
+    );
+    assert_eq!(eval_last("const int X = -(7 / -2);"), Ok(Value::Int(3)));
+    assert_eq!(eval_last("const int X = 6 ^ 3;"), Ok(Value::Int(5)));
+    assert_eq!(eval_last("const int X = 2 < 3 == 1;"), Ok(Value::Int(1)));
+}
+
+#[test]
+fn test_eval_constants() {
+    let src = "const int N = 6; const int M = N * 7;";
+    assert_eq!(eval_last(src), Ok(Value::Int(42)));
+}
+
+#[test]
+fn test_eval_conversions() {
+    assert_eq!(
+        eval_last("const double X = 1 / 2.0;"),
+        Ok(Value::Double(0.5))
+    );
+    assert_eq!(eval_last("const int X = (int)2.9;"), Ok(Value::Int(2)));
+    assert_eq!(
+        eval_last("const char X = (char)('a' + 1);"),
+        Ok(Value::Char(b'b'))
+    );
+    assert_eq!(eval_last("const int X = 'a' + 1;"), Ok(Value::Int(98)));
+}
+
+#[test]
+fn test_eval_short_circuit() {
+    assert_eq!(eval_last("const int X = 0 && 1 / 0;"), Ok(Value::Int(0)));
+    assert_eq!(eval_last("const int X = 2 || 1 / 0;"), Ok(Value::Int(1)));
+}
+
+#[test]
+fn test_eval_errors() {
+    let overflow = eval_last("const int X = 2147483647 + 1;").unwrap_err();
+    assert!(matches!(overflow, EvalError::Overflow(_)));
+    assert_eq!(overflow.code(), ErrorCode(317));
+
+    let div = eval_last("const int X = 1 / (2 - 2);").unwrap_err();
+    assert!(matches!(div, EvalError::DivideByZero(_)));
+
+    let var = eval_last("int y = 3; const int X = y + 1;").unwrap_err();
+    assert!(matches!(var, EvalError::NotConstant(_)));
+    assert_eq!(
+        crate::CompileError::from(var).span.map(|s| s.start.pos),
+        Some(25)
+    );
+}
+
+#[test]
+fn test_eval_pure_calls() {
+    let fib =
+        "int fib(int n) {\n    if (n <= 1) return n;\n    return fib(n - 1) + fib(n - 2);\n}\n";
+    let src = format!("{}const int X = fib(10) * 2;", fib);
+    assert_eq!(eval_last(&src), Ok(Value::Int(110)));
+
+    let src = "const int N = 5;\n\
+               double avg(int n) {\n\
+                   int i = 1;\n\
+                   int sum;\n\
+                   while (1) {\n\
+                       if (i > n) break;\n\
+                       sum = sum + i;\n\
+                       i = i + 1;\n\
+                   }\n\
+                   return sum / (double)n;\n\
+               }\n\
+               const double X = avg(N);";
+    assert_eq!(eval_last(src), Ok(Value::Double(3.0)));
+}
+
+#[test]
+fn test_eval_impure_calls() {
+    let src = "int g;\nint f() {\n    g = 1;\n    return 2;\n}\nconst int X = f();";
+    assert!(matches!(eval_last(src), Err(EvalError::NotConstant(_))));
+    let src = "int f() {\n    print(1);\n    return 2;\n}\nconst int X = f();";
+    assert!(matches!(eval_last(src), Err(EvalError::NotConstant(_))));
+    // * Only paths taken need to be pure
+    let src = "int f(int x) {\n    if (x) print(1);\n    return 2;\n}\nconst int X = f(0);";
+    assert_eq!(eval_last(src), Ok(Value::Int(2)));
+
+    let src = "int f() {\n    while (1) {}\n    return 0;\n}\nconst int X = f();";
+    let fuel = eval_last(src).unwrap_err();
+    assert!(matches!(fuel, EvalError::OutOfFuel(_)));
+    assert_eq!(fuel.code(), ErrorCode(323));
+    let src = "int f(int n) {\n    return f(n + 1);\n}\nconst int X = f(0);";
+    assert!(matches!(eval_last(src), Err(EvalError::OutOfFuel(_))));
+}
@@ -0,0 +1,86 @@
+use crate::minivm::vm::{Coverage, MiniVM};
+use crate::minivm::*;
+use crate::parse;
+
+const SRC: &str = "int main() {
+    int a;
+    scan(a);
+    if (a > 0) {
+        print(1);
+    } else {
+        print(2);
+    }
+    return 0;
+}
+";
+
+fn compile() -> O0 {
+    Codegen::new(&parse(SRC).unwrap())
+        .with_debug_info(true)
+        .compile()
+        .unwrap()
+}
+
+fn run(o0: &O0, input: &str) -> Coverage {
+    let mut input = input.as_bytes();
+    let mut output = vec![];
+    let mut vm = MiniVM::new(o0, &mut input, &mut output).with_coverage();
+    vm.run().unwrap();
+    vm.coverage().unwrap().clone()
+}
+
+#[test]
+fn test_coverage_map() {
+    let o0 = compile();
+    let map = CoverageMap::new(&o0).unwrap();
+    let main = &o0.functions[0];
+    for r in &map.ranges {
+        assert_eq!((r.function, r.name.as_str()), (Some(0), "main"));
+        assert!(r.start < r.end && r.end <= main.ins.len(), "{:?}", r);
+    }
+    let lines: Vec<_> = map.ranges.iter().map(|r| r.line).collect();
+    for line in [3, 4, 5, 7, 9] {
+        assert!(lines.contains(&line), "{:?}", lines);
+    }
+
+    let json: serde_json::Value = serde_json::from_str(&map.to_json()).unwrap();
+    assert_eq!(json["ranges"][0]["name"], "main");
+
+    let o0 = Codegen::new(&parse(SRC).unwrap()).compile().unwrap();
+    assert_eq!(CoverageMap::new(&o0), None);
+}
+
+#[test]
+fn test_coverage_counts() {
+    let o0 = compile();
+    let map = CoverageMap::new(&o0).unwrap();
+
+    let mut coverage = run(&o0, "1");
+    let lines = map.line_counts(&coverage);
+    assert_eq!((lines[&5], lines[&7]), (1, 0));
+    let all = 0..o0.functions[0].ins.len();
+    assert_ne!(coverage.executed_ranges(Some(0)), vec![all]);
+    let report = render_coverage(SRC, &lines);
+    assert!(
+        report.contains("    #####:    7:        print(2);"),
+        "{}",
+        report
+    );
+    assert!(
+        report.contains("        -:    6:    } else {"),
+        "{}",
+        report
+    );
+
+    coverage.merge(&run(&o0, "-1"));
+    let lines = map.line_counts(&coverage);
+    assert_eq!((lines[&3], lines[&5], lines[&7]), (2, 1, 1));
+    let all = 0..o0.functions[0].ins.len();
+    assert_eq!(coverage.executed_ranges(Some(0)), vec![all]);
+    let report = render_coverage(SRC, &lines);
+    assert!(
+        report.ends_with("Lines executed: 100.00% of 5\n"),
+        "{}",
+        report
+    );
+}
@@ -0,0 +1,83 @@
+use crate::minivm::{binfmt, disassemble, Codegen, VarKind};
+use crate::parse;
+
+const SRC: &str = "int twice(int x) {\n    return x * 2;\n}\n\nint main() {\n    print(\"hi\", twice(21));\n    return 0;\n}\n";
+
+/// Whether `asm` has a line of `text` commented with `comment`
+fn has_line(asm: &str, text: &str, comment: &str) -> bool {
+    asm.lines().any(|l| match l.split_once(" # ") {
+        Some((t, c)) => t.trim_end() == text && c == comment,
+        None => false,
+    })
+}
+
+#[test]
+fn test_disasm_cross_references() {
+    let o0 = Codegen::new(&parse(SRC).unwrap()).compile().unwrap();
+    assert!(o0.debug.is_none());
+    let asm = disassemble(&o0, Some(SRC));
+    assert!(has_line(&asm, "0 S \"twice\"", "name of .F0"), "{}", asm);
+    assert!(has_line(&asm, "2 S \"hi\"", ".F1:1"), "{}", asm);
+    assert!(has_line(&asm, "1 loadc 2", "S \"hi\""), "{}", asm);
+    assert!(asm.lines().any(|l| l
+        .split_once(" # ")
+        .is_some_and(|(t, c)| t.trim_end().ends_with("call 0") && c == "twice")));
+    assert!(has_line(&asm, ".F0:", "twice"), "{}", asm);
+    // * Without debug info there are no source lines
+    assert!(!asm.contains(" | "), "{}", asm);
+}
+
+#[test]
+fn test_disasm_source_lines() {
+    let o0 = Codegen::new(&parse(SRC).unwrap())
+        .with_debug_info(true)
+        .compile()
+        .unwrap();
+    let debug = o0.debug.as_ref().unwrap();
+    assert_eq!(debug.fn_lines.len(), o0.functions.len());
+    for (f, lines) in o0.functions.iter().zip(&debug.fn_lines) {
+        assert_eq!(f.ins.len(), lines.len());
+    }
+    assert!(debug.fn_lines[1].contains(&Some(5)));
+
+    let asm = disassemble(&o0, Some(SRC));
+    let expected = "#    2 |     return x * 2;\n";
+    assert!(asm.contains(expected), "{}", asm);
+    let print = asm.find("#    6 |     print(\"hi\", twice(21));").unwrap();
+    let ret = asm.find("#    7 |     return 0;").unwrap();
+    assert!(asm[print..ret].contains("call 0"), "{}", asm);
+
+    let mut bytes = vec![];
+    binfmt::write_debug(debug, &mut bytes).unwrap();
+    assert_eq!(&binfmt::read_debug(&mut &bytes[..]).unwrap(), debug);
+}
+
+#[test]
+fn test_debug_variables() {
+    let src = "int g;\n\nint main() {\n    int x = 1;\n    {\n        double x = 2.0;\n        char c = 'a';\n    }\n    return x;\n}\n";
+    let o0 = Codegen::new(&parse(src).unwrap())
+        .with_debug_info(true)
+        .compile()
+        .unwrap();
+    let debug = o0.debug.as_ref().unwrap();
+    assert_eq!(debug.globals.len(), 1);
+    assert_eq!(debug.globals[0].name, "g");
+    assert_eq!(debug.globals[0].lines, (0, u32::MAX));
+
+    let vars = &debug.fn_vars[0];
+    let found: Vec<_> = (vars.iter())
+        .map(|v| (v.name.as_str(), v.kind, v.offset, v.slots, v.lines))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            ("x", VarKind::Int, 0, 1, (3, 9)),
+            ("x", VarKind::Double, 1, 2, (5, 7)),
+            ("c", VarKind::Char, 3, 1, (6, 7)),
+        ]
+    );
+
+    let mut bytes = vec![];
+    binfmt::write_debug(debug, &mut bytes).unwrap();
+    assert_eq!(&binfmt::read_debug(&mut &bytes[..]).unwrap(), debug);
+}
@@ -0,0 +1,85 @@
+use crate::c0::doc::*;
+use crate::c0::ide::document_symbols;
+use crate::c0::parser::parse_no_panic;
+
+const SRC: &str = "/// Largest value accepted.
+///
+/// Inputs above it are
+/// clamped.
+const int LIMIT = 100;
+
+int count;
+
+/// Clamp `x` to `[0, LIMIT]`, as `x < 0` is <em>wrong</em>
+int clamp(int x) {
+    if (x > LIMIT)
+        return LIMIT;
+    return x;
+}
+";
+
+#[test]
+fn test_doc_markdown() {
+    let prog = parse_no_panic(SRC).unwrap();
+    let expected = "# clamp
+
+## Constants
+
+### `LIMIT`
+
+```c
+const int LIMIT
+```
+
+Largest value accepted.
+
+Inputs above it are clamped.
+
+## Global variables
+
+### `count`
+
+```c
+int count
+```
+
+## Functions
+
+### `clamp`
+
+```c
+int clamp(int x)
+```
+
+Clamp `x` to `[0, LIMIT]`, as `x < 0` is <em>wrong</em>
+";
+    assert_eq!(render(&prog, "clamp", DocFormat::Markdown), expected);
+}
+
+#[test]
+fn test_doc_html() {
+    let prog = parse_no_panic(SRC).unwrap();
+    let html = render(&prog, "a <b>", DocFormat::Html);
+    assert!(html.starts_with("<!DOCTYPE html>\n"));
+    assert!(html.contains("<title>a &lt;b&gt;</title>"));
+    assert!(html.contains("<h2>Constants</h2>\n<section id=\"LIMIT\">"));
+    assert!(html.contains("<pre><code>int clamp(int x)</code></pre>"));
+    assert!(html.contains(
+        "<p>Clamp <code>x</code> to <code>[0, LIMIT]</code>, as <code>x &lt; 0</code> \
+         is &lt;em&gt;wrong&lt;/em&gt;</p>"
+    ));
+    assert!(html.ends_with("</body>\n</html>\n"));
+}
+
+#[test]
+fn test_doc_symbols() {
+    let prog = parse_no_panic(SRC).unwrap();
+    let docs: Vec<_> = (document_symbols(&prog).into_iter())
+        .map(|s| (s.name, s.doc))
+        .collect();
+    assert_eq!(docs[1], ("count".into(), None));
+    assert_eq!(
+        docs[2].1.as_deref(),
+        Some("Clamp `x` to `[0, LIMIT]`, as `x < 0` is <em>wrong</em>")
+    );
+}
@@ -0,0 +1,116 @@
+use super::{compile, run};
+use crate::c0::alias::Aliases;
+use crate::c0::escape::escaping;
+use crate::c0::type_checker::lower;
+use crate::minivm::*;
+use crate::parse;
+
+const SRC: &str = r#"&int shared;
+
+void set(&int p, int v) {
+    *p = v;
+}
+
+&int same(&int p) {
+    return p;
+}
+
+&int leak(int v) {
+    int x = v;
+    int kept = 0;
+    set(&kept, 1);
+    return same(&x);
+}
+
+void publish() {
+    int y = 7;
+    shared = &y;
+}
+
+&int param(int v) {
+    return &v;
+}
+
+int main() {
+    int a = 1;
+    &int p = &a;
+    set(p, *p + 2);
+    &int r = leak(5);
+    &int s = leak(6);
+    publish();
+    print(a, *r, *s, *shared, *param(8));
+    *r = 9;
+    print(*r, *s);
+    return 0;
+}
+"#;
+
+#[test]
+fn test_escaping() {
+    let typed = lower(&parse(SRC).unwrap()).unwrap();
+    let names: Vec<_> = escaping(&Aliases::new(&typed))
+        .into_iter()
+        .map(|(_, name)| name)
+        .collect();
+    // * `kept` and `a` only go into calls, which return before they do
+    assert_eq!(names, ["x", "y", "v"]);
+}
+
+#[test]
+fn test_references() {
+    assert_eq!(run(SRC), "3 5 6 7 8\n9 6\n");
+}
+
+#[test]
+fn test_boxes_only_escaping() {
+    let o0 = compile(SRC);
+    let news = |f: usize| {
+        o0.functions[f]
+            .ins
+            .iter()
+            .filter(|i| **i == Inst::New)
+            .count()
+    };
+    // * Functions in order: set, same, leak, publish, param, main
+    assert_eq!((0..6).map(news).collect::<Vec<_>>(), [0, 0, 1, 1, 1, 0]);
+}
+
+#[test]
+fn test_box_per_iteration() {
+    let src = "&int last;\n\
+               int main() {\n\
+                   int i = 0;\n\
+                   &int first;\n\
+                   while (i < 3) {\n\
+                       int v = i * 10;\n\
+                       last = &v;\n\
+                       if (i == 0) first = last;\n\
+                       i = i + 1;\n\
+                   }\n\
+                   print(*first, *last);\n\
+                   return 0;\n\
+               }\n";
+    assert_eq!(run(src), "0 20\n");
+}
+
+#[test]
+fn test_reference_errors() {
+    let code = |src: &str| {
+        let prog = parse(src).unwrap();
+        Codegen::new(&prog)
+            .compile()
+            .unwrap_err()
+            .var
+            .code()
+            .to_string()
+    };
+    assert_eq!(code("int main() { int a = 1; return *a; }"), "E0324");
+    assert_eq!(
+        code("int main() { const int c = 1; &int p = &c; return 0; }"),
+        "E0325"
+    );
+    assert_eq!(
+        code("int main() { int a = 1; &int p = &(a + 1); return 0; }"),
+        "E0326"
+    );
+}
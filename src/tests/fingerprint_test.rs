@@ -0,0 +1,117 @@
+use crate::c0::fingerprint::*;
+use crate::c0::obfuscate::obfuscate;
+use crate::c0::parser::parse_no_panic;
+
+const SORT: &str = "int a0, a1, a2, a3;
+
+void swap_if(int i) {
+    // Keep the smaller one first
+    if (i == 0) {
+        if (a0 > a1) { int t = a0; a0 = a1; a1 = t; }
+    } else if (i == 1) {
+        if (a1 > a2) { int t = a1; a1 = a2; a2 = t; }
+    } else {
+        if (a2 > a3) { int t = a2; a2 = a3; a3 = t; }
+    }
+}
+
+int main() {
+    int pass = 0;
+    scan(a0); scan(a1); scan(a2); scan(a3);
+    while (pass < 3) {
+        int i = 0;
+        while (i < 3) {
+            swap_if(i);
+            i = i + 1;
+        }
+        pass = pass + 1;
+    }
+    print(a0, a1, a2, a3);
+    return 0;
+}
+";
+
+const FIB: &str = "int fib(int n) {
+    if (n <= 1)
+        return n;
+    return fib(n - 1) + fib(n - 2);
+}
+
+int main() {
+    int n;
+    double avg = 0.0;
+    scan(n);
+    print(\"fib\", n, fib(n));
+    avg = (double)fib(n) / n;
+    print(avg);
+    return 0;
+}
+";
+
+fn fingerprint(src: &str) -> Fingerprint {
+    Fingerprint::new(&parse_no_panic(src).unwrap())
+}
+
+#[test]
+fn test_same_structure() {
+    let sort = fingerprint(SORT);
+    assert!(!sort.marks.is_empty());
+    assert_eq!(sort, fingerprint(SORT));
+
+    // * Renamed, without comments and laid out differently
+    let renamed = obfuscate(SORT).unwrap();
+    assert_eq!(sort.similarity(&fingerprint(&renamed)), 1.0);
+    let consts = SORT.replace('3', "5").replace("i == 0", "i == 2");
+    assert_eq!(sort.similarity(&fingerprint(&consts)), 1.0);
+}
+
+#[test]
+fn test_similarity() {
+    let sort = fingerprint(SORT);
+    let fib = fingerprint(FIB);
+    assert_eq!(sort.similarity(&fib), 0.0);
+    assert_eq!(fib.similarity(&sort), 0.0);
+    assert!(sort.shared(&fib).is_empty());
+
+    // * `pass` counted once after the loop
+    let moved = SORT.replacen("        pass = pass + 1;\n", "", 1).replacen(
+        "    print(a0",
+        "    pass = pass + 1;\n    print(a0",
+        1,
+    );
+    let moved = fingerprint(&moved);
+    let score = sort.similarity(&moved);
+    assert!(0.5 < score && score < 1.0, "{}", score);
+    assert_eq!(score, moved.similarity(&sort));
+
+    let empty = fingerprint("");
+    assert!(empty.marks.is_empty());
+    assert_eq!(empty.similarity(&empty), 1.0);
+    assert_eq!(empty.similarity(&sort), 0.0);
+}
+
+#[test]
+fn test_shared() {
+    // * The same function, copied into another program
+    let copied = format!("int twice(int x) {{ return x + x; }}\n\n{}", FIB);
+    let shared = fingerprint(FIB).shared(&fingerprint(&copied));
+    assert!(!shared.is_empty());
+    for (ours, theirs) in shared {
+        let ours = crate::c0::err::str_span(FIB, ours);
+        let theirs = crate::c0::err::str_span(&copied, theirs);
+        assert_eq!(ours, theirs);
+    }
+}
+
+#[test]
+fn test_config() {
+    let prog = parse_no_panic(FIB).unwrap();
+    let every = FingerprintConfig { k: 3, window: 1 };
+    let coarse = FingerprintConfig { k: 3, window: 8 };
+    let every = Fingerprint::with_config(&prog, &every);
+    let coarse = Fingerprint::with_config(&prog, &coarse);
+    // * With a window of 1 every k-gram is kept
+    assert!(every.marks.len() > coarse.marks.len());
+    let hashes: Vec<_> = every.marks.iter().map(|m| m.hash).collect();
+    assert!(coarse.marks.iter().all(|m| hashes.contains(&m.hash)));
+}
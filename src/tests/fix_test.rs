@@ -0,0 +1,70 @@
+use crate::{apply_fixes, check, parse, CompileError, Fix};
+
+/// `src` with the fix of its parse error made
+fn fix_parse(src: &str) -> String {
+    let err = parse(src).unwrap_err();
+    let (fixed, made) = apply_fixes(src, &err.fixes);
+    assert_eq!(made, 1, "{}: {:?}", src, err);
+    fixed
+}
+
+#[test]
+fn test_parse_fixes() {
+    assert_eq!(
+        fix_parse("int main() {\n    int a = 1\n    a = a + 1\n    return a;\n}"),
+        "int main() {\n    int a = 1;\n    a = a + 1\n    return a;\n}"
+    );
+    assert_eq!(
+        fix_parse("int main() {\n    int a = 1;\n    a = a + 1\n    return a;\n}"),
+        "int main() {\n    int a = 1;\n    a = a + 1;\n    return a;\n}"
+    );
+    assert_eq!(
+        fix_parse("int main() { return 0 }"),
+        "int main() { return 0; }"
+    );
+    assert_eq!(
+        fix_parse("int total;\nint main() { print(totl); return 0; }"),
+        "int total;\nint main() { print(total); return 0; }"
+    );
+    assert_eq!(
+        fix_parse("int twice(int x) { return x * 2; }\nint main() { return twise(1); }"),
+        "int twice(int x) { return x * 2; }\nint main() { return twice(1); }"
+    );
+    assert_eq!(
+        fix_parse("int main() { itn a; return 0; }"),
+        "int main() { int a; return 0; }"
+    );
+
+    // * Nothing is surely missing or alike here
+    let err: CompileError = parse("int main() { int a = 1 a = 2; }").unwrap_err();
+    assert!(err.fixes.is_empty());
+    let err = parse("int main() { print(something); return 0; }").unwrap_err();
+    assert!(err.fixes.is_empty());
+    // * Only functions are suggested for calls
+    let err = parse("int add1;\nint main() { return add(1); }").unwrap_err();
+    assert!(err.fixes.is_empty());
+}
+
+#[test]
+fn test_check_fixes() {
+    let src = "int main() { char c = 300; return 0; }";
+    let diags = check(&parse(src).unwrap());
+    assert_eq!(diags[0].fixes[0].message, "cast to `char`");
+    let (fixed, _) = apply_fixes(src, &diags[0].fixes);
+    assert_eq!(fixed, "int main() { char c = (char)300; return 0; }");
+    assert!(check(&parse(&fixed).unwrap()).is_empty());
+}
+
+#[test]
+fn test_apply_fixes() {
+    use crate::prelude::{Pos, Span};
+    let span = |a, b| Span::from(Pos::new(0, a, a), Pos::new(0, b, b));
+    let fixes = [
+        Fix::new("", span(4, 5), "ö"),
+        Fix::new("", span(0, 1), "α"),
+        Fix::new("", span(4, 6), "x"),
+        Fix::new("", span(6, 6), "!"),
+    ];
+    // * Indices count chars, and the fix overlapping another is left out
+    assert_eq!(apply_fixes("abc éfg", &fixes), ("αbc öf!g".into(), 3));
+}
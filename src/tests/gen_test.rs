@@ -0,0 +1,66 @@
+use super::exec;
+use crate::c0::gen::*;
+use crate::c0::interpreter::Interpreter;
+use crate::c0::parser::parse_no_panic;
+
+/// Exit code and output of `src` on the interpreter and on the VM
+fn run_both(src: &str) -> ((i32, String), (i32, String)) {
+    let prog = parse_no_panic(src).unwrap();
+    let mut input = "".as_bytes();
+    let mut output = vec![];
+    let code = (Interpreter::new(&prog, &mut input, &mut output).with_step_limit(10_000_000))
+        .run()
+        .unwrap();
+    let interpreted = (code, String::from_utf8(output).unwrap());
+
+    let o0 = crate::codegen(&prog).unwrap();
+    (interpreted, exec(&o0, ""))
+}
+
+#[test]
+fn test_generated_programs_agree() {
+    for seed in 0..20 {
+        let config = GenConfig {
+            seed,
+            ..GenConfig::default()
+        };
+        let src = generate(&config);
+        let (interpreted, compiled) = run_both(&src);
+        assert_eq!(interpreted, compiled, "seed {}:\n{}", seed, src);
+        assert_eq!(interpreted.0, 0);
+    }
+}
+
+#[test]
+fn test_generate_deterministic() {
+    let config = GenConfig {
+        functions: 30,
+        depth: 6,
+        seed: 42,
+    };
+    assert_eq!(generate(&config), generate(&config));
+    let other = GenConfig {
+        seed: 43,
+        ..config.clone()
+    };
+    assert_ne!(generate(&config), generate(&other));
+}
+
+#[test]
+fn test_generate_functions() {
+    for functions in [0, 1, 50] {
+        let config = GenConfig {
+            functions,
+            depth: 2,
+            seed: 7,
+        };
+        let src = generate(&config);
+        let defined = src
+            .lines()
+            .filter(|l| !l.starts_with(' ') && l.ends_with(") {"))
+            .count();
+        assert_eq!(defined, functions + 1, "{}", src);
+        assert!(src.contains("\nint main() {\n"));
+        run_both(&src);
+    }
+}
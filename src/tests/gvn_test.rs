@@ -0,0 +1,73 @@
+use super::exec;
+use crate::c0::gen::*;
+use crate::minivm::*;
+use crate::parse;
+
+fn compile(src: &str, level: u8) -> O0 {
+    Codegen::new(&parse(src).unwrap())
+        .with_passes(PassManager::for_level(level).unwrap())
+        .compile()
+        .unwrap()
+}
+
+/// How many times function `idx` of `o0` multiplies
+fn muls(o0: &O0, idx: usize) -> usize {
+    let ins = &o0.functions[idx].ins;
+    ins.iter().filter(|i| matches!(i, Inst::IMul)).count()
+}
+
+#[test]
+fn test_values_across_blocks() {
+    let src = "int g = 3;\nint h = 4;\n\
+               int f(int a) {\n\
+                   int x = g * h;\n\
+                   if (a > 0) { print(g * h + a); } else { print(g * h - a); }\n\
+                   while (a > 0) { a = a - 1; print(g * h); }\n\
+                   g = 1;\n\
+                   return x + g * h;\n\
+               }\n\
+               int main() { print(f(2)); return 0; }\n";
+    let (o1, o2) = (compile(src, 1), compile(src, 2));
+    let out = (0, "14\n12\n12\n16\n".into());
+    assert_eq!(exec(&o1, ""), out);
+    assert_eq!(exec(&o2, ""), out);
+    assert_eq!(muls(&o1, 0), 5);
+    // * Nothing writes `g` or `h` until `g = 1`
+    assert_eq!(muls(&o2, 0), 2);
+}
+
+#[test]
+fn test_writes_end_reuse() {
+    let src = "int g = 2;\n\
+               void bump() { g = g + 1; }\n\
+               int f(int a) {\n\
+                   &int p = &a;\n\
+                   int x = a * a;\n\
+                   *p = 3;\n\
+                   int y = a * a;\n\
+                   int z = g * g;\n\
+                   bump();\n\
+                   int i = 0;\n\
+                   while (i < 2) { print(g * g, i * i); i = i + 1; }\n\
+                   return x + y + z;\n\
+               }\n\
+               int main() { print(f(5)); return 0; }\n";
+    let (o1, o2) = (compile(src, 1), compile(src, 2));
+    let out = (0, "9 0\n9 1\n38\n".into());
+    assert_eq!(exec(&o1, ""), out);
+    assert_eq!(exec(&o2, ""), out);
+    assert_eq!(muls(&o2, 1), muls(&o1, 1));
+}
+
+#[test]
+fn test_generated_programs() {
+    for seed in 0..40 {
+        let config = GenConfig {
+            seed,
+            ..GenConfig::default()
+        };
+        let src = generate(&config);
+        let (o1, o2) = (compile(&src, 1), compile(&src, 2));
+        assert_eq!(exec(&o2, ""), exec(&o1, ""), "seed {}:\n{}", seed, src);
+    }
+}
@@ -0,0 +1,67 @@
+use crate::c0::highlight::*;
+
+/// Text of each token of `src` with its class
+fn classes(src: &str) -> Vec<(String, TokenClass)> {
+    highlight(src)
+        .into_iter()
+        .map(|(span, class)| {
+            let text = src
+                .chars()
+                .skip(span.start.index)
+                .take(span.end.index - span.start.index)
+                .collect();
+            (text, class)
+        })
+        .collect()
+}
+
+#[test]
+fn test_highlight() {
+    let src = "int f(int a) { // add\n    return a + 1.5 * 'c'; }\nvoid main() { print(f /* x */ (1), \"s\", true); @ }";
+
+    let classes = classes(src);
+    let classes: Vec<_> = classes.iter().map(|(t, c)| (&t[..], *c)).collect();
+
+    use TokenClass::*;
+    assert_eq!(
+        classes,
+        vec![
+            ("int", Type),
+            ("f", Function),
+            ("(", Punctuation),
+            ("int", Type),
+            ("a", Identifier),
+            (")", Punctuation),
+            ("{", Punctuation),
+            ("// add", Comment),
+            ("return", Keyword),
+            ("a", Identifier),
+            ("+", Operator),
+            ("1.5", Number),
+            ("*", Operator),
+            ("'c'", Char),
+            (";", Punctuation),
+            ("}", Punctuation),
+            ("void", Type),
+            ("main", Function),
+            ("(", Punctuation),
+            (")", Punctuation),
+            ("{", Punctuation),
+            ("print", Keyword),
+            ("(", Punctuation),
+            ("f", Function),
+            ("/* x */", Comment),
+            ("(", Punctuation),
+            ("1", Number),
+            (")", Punctuation),
+            (",", Punctuation),
+            ("\"s\"", String),
+            (",", Punctuation),
+            ("true", Boolean),
+            (")", Punctuation),
+            (";", Punctuation),
+            ("@", Error),
+            ("}", Punctuation),
+        ]
+    );
+}
@@ -0,0 +1,96 @@
+use super::exec;
+use crate::minivm::vm::{MiniVM, VmError};
+use crate::{codegen, parse_with_host, HostSig, O0};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const SRC: &str = r#"
+int main() {
+    int t = clock();
+    put('o');
+    put('k');
+    print(scale(1.5, 3), clock() - t);
+    return clock();
+}
+"#;
+
+fn host() -> Vec<HostSig> {
+    vec![
+        HostSig::of("clock", &|| 0),
+        HostSig::of("scale", &|_: f64, _: i32| 0.0),
+        HostSig::of("put", &|_: u8| ()),
+    ]
+}
+
+fn compile(src: &str) -> O0 {
+    codegen(&parse_with_host(src, &host()).unwrap()).unwrap()
+}
+
+#[test]
+fn test_host_fns() {
+    let o0 = compile(SRC);
+    assert_eq!(o0.host_fns.len(), 3);
+
+    let mut input = "".as_bytes();
+    let mut output = vec![];
+    let mut vm = MiniVM::new(&o0, &mut input, &mut output);
+    let ticks = Rc::new(RefCell::new(0));
+    let put = Rc::new(RefCell::new(String::new()));
+    let t = ticks.clone();
+    vm.register_host_fn("clock", move || {
+        *t.borrow_mut() += 10;
+        *t.borrow()
+    })
+    .unwrap();
+    vm.register_host_fn("scale", |x: f64, n: i32| x * n as f64)
+        .unwrap();
+    let p = put.clone();
+    vm.register_host_fn("put", move |c: u8| p.borrow_mut().push(c as char))
+        .unwrap();
+
+    assert_eq!(vm.run().unwrap(), 30);
+    drop(vm);
+    assert_eq!(String::from_utf8(output).unwrap(), "4.500000 10\n");
+    assert_eq!(*put.borrow(), "ok");
+}
+
+#[test]
+fn test_host_fn_errors() {
+    let o0 = compile(SRC);
+    let mut input = "".as_bytes();
+    let mut output = vec![];
+    let mut vm = MiniVM::new(&o0, &mut input, &mut output);
+    assert!(matches!(
+        vm.register_host_fn("clock", || 1.0),
+        Err(VmError::HostFnMismatch(_))
+    ));
+    // * Only functions called need registering
+    vm.register_host_fn("main", || 0).unwrap();
+    assert!(matches!(vm.run(), Err(VmError::NoHostFn(f)) if f == "clock"));
+
+    vm.register_host_fn("clock", || Err::<i32, _>("no clock".to_string()))
+        .unwrap();
+    match vm.run() {
+        Err(e @ VmError::HostFnFailed(..)) => {
+            assert_eq!(e.to_string(), "Host function `clock` failed: no clock")
+        }
+        res => panic!("expected the host function to fail, got {:?}", res),
+    }
+}
+
+#[test]
+fn test_host_fns_give_way() {
+    // * Stubs are left out unless called, so nothing else changes
+    let plain = super::compile("int main() { return 0; }");
+    assert_eq!(compile("int main() { return 0; }"), plain);
+
+    // * A program's own functions take the place of the host's
+    let o0 = compile("int put; int clock() { return 7; } int main() { return clock(); }");
+    assert!(o0.host_fns.is_empty());
+    assert_eq!(exec(&o0, "").0, 7);
+
+    let o0 = compile("int main() { put('a'); return 0; }");
+    let names: Vec<_> = o0.host_fns.iter().map(|(_, sig)| &sig.name[..]).collect();
+    assert_eq!(names, ["put"]);
+    assert_eq!(o0.host_fns[0].0 as usize, o0.functions.len() - 1);
+}
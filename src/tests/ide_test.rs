@@ -0,0 +1,202 @@
+use crate::c0::ide::*;
+use crate::c0::parser::parse_no_panic;
+
+const SRC: &str = "int g = 1;
+const double pi = 3.14;
+
+int add(int a, int b) {
+    int c = a + b;
+    {
+        int g = c;
+        c = g;
+    }
+    return c + g;
+}
+
+void main() {
+    print(add(1, 2));
+}
+";
+
+/// Symbol at line `ln`, column `col`, with the line and column its name is
+/// declared at
+fn lookup(ln: usize, col: usize) -> Option<(SymbolInfo, (usize, usize))> {
+    let prog = parse_no_panic(SRC).unwrap();
+    symbol_at(&prog, pos_at(SRC, ln, col)).map(|sym| {
+        let start = sym.def.name_span.start;
+        (sym.def, (start.ln, start.pos))
+    })
+}
+
+#[test]
+fn test_pos_at() {
+    let src = "ab\r\ncd\nef";
+    assert_eq!(pos_at(src, 0, 1).index, 1);
+    assert_eq!(pos_at(src, 1, 1).index, 5);
+    assert_eq!(pos_at(src, 2, 0).index, 7);
+    // * Clamped to the end of the line
+    assert_eq!(pos_at(src, 0, 10).index, 2);
+    assert_eq!(pos_at(src, 2, 10).index, 9);
+}
+
+#[test]
+fn test_symbol_at() {
+    let (def, at) = lookup(7, 12).unwrap();
+    assert_eq!(
+        (def.kind, &def.detail[..], at),
+        (SymbolKind::Variable, "int g", (6, 12))
+    );
+
+    let (def, at) = lookup(9, 15).unwrap();
+    assert_eq!(
+        (def.kind, &def.detail[..], at),
+        (SymbolKind::Variable, "int g", (0, 4))
+    );
+
+    let (def, at) = lookup(4, 12).unwrap();
+    assert_eq!(
+        (def.kind, &def.detail[..], at),
+        (SymbolKind::Parameter, "int a", (3, 12))
+    );
+
+    let (def, at) = lookup(13, 11).unwrap();
+    assert_eq!(
+        (def.kind, &def.detail[..], at),
+        (SymbolKind::Function, "int add(int a, int b)", (3, 4))
+    );
+
+    // * Names in declarations refer to themselves
+    let (def, at) = lookup(1, 14).unwrap();
+    assert_eq!(
+        (def.kind, &def.detail[..], at),
+        (SymbolKind::Constant, "const double pi", (1, 13))
+    );
+
+    assert_eq!(lookup(2, 0), None);
+    assert_eq!(lookup(13, 4), None);
+}
+
+#[test]
+fn test_document_symbols() {
+    let prog = parse_no_panic(SRC).unwrap();
+    let syms = document_symbols(&prog);
+    let names: Vec<_> = syms.iter().map(|s| (&s.name[..], s.kind)).collect();
+    assert_eq!(
+        names,
+        vec![
+            ("g", SymbolKind::Variable),
+            ("pi", SymbolKind::Constant),
+            ("add", SymbolKind::Function),
+            ("main", SymbolKind::Function),
+        ]
+    );
+
+    let add = &syms[2];
+    assert_eq!((add.span.start.ln, add.span.end.ln), (3, 10));
+    let children: Vec<_> = add.children.iter().map(|s| (&s.name[..], s.kind)).collect();
+    assert_eq!(
+        children,
+        vec![
+            ("a", SymbolKind::Parameter),
+            ("b", SymbolKind::Parameter),
+            ("c", SymbolKind::Variable),
+            ("g", SymbolKind::Variable),
+        ]
+    );
+}
+
+#[test]
+fn test_xref() {
+    let prog = parse_no_panic(SRC).unwrap();
+    let xref = Xref::new(&prog);
+    // * Name, where it is declared and where it is used, from 0
+    let at = |span: crate::Span| (span.start.ln, span.start.pos);
+    let listing: Vec<_> = (xref.symbols.iter())
+        .map(|sym| {
+            let uses: Vec<_> = sym.uses.iter().map(|span| at(*span)).collect();
+            (sym.def.name.as_str(), at(sym.def.name_span), uses)
+        })
+        .collect();
+    assert_eq!(
+        listing,
+        [
+            ("g", (0, 4), vec![(9, 15)]),
+            ("pi", (1, 13), vec![]),
+            ("add", (3, 4), vec![(13, 10)]),
+            ("a", (3, 12), vec![(4, 12)]),
+            ("b", (3, 19), vec![(4, 16)]),
+            ("c", (4, 8), vec![(6, 16), (7, 8), (9, 11)]),
+            ("g", (6, 12), vec![(7, 12)]),
+            ("main", (12, 5), vec![]),
+        ]
+    );
+    assert_eq!(xref.symbols[3].def.kind, SymbolKind::Parameter);
+    let add = xref.find(xref.symbols[2].def.name_span).unwrap();
+    assert_eq!(add.def.detail, "int add(int a, int b)");
+    assert!(add.def.children.is_empty());
+
+    let text = xref.to_string();
+    assert!(text.starts_with("g                    variable  1:5      int g\n    used at 10:16\n"));
+    assert!(
+        text.contains("pi                   constant  2:14     const double pi\n    never used\n")
+    );
+    assert!(xref
+        .to_json()
+        .contains("\"detail\": \"int add(int a, int b)\""));
+}
+
+#[test]
+fn test_rename() {
+    let prog = parse_no_panic(SRC).unwrap();
+    let rename_at = |ln, col, name| rename(&prog, pos_at(SRC, ln, col), name);
+    let edits = |ln, col, name| -> Vec<_> {
+        (rename_at(ln, col, name).unwrap().into_iter())
+            .map(|edit| {
+                assert_eq!(edit.text, name);
+                (edit.span.start.ln, edit.span.start.pos, edit.span.end.pos)
+            })
+            .collect()
+    };
+    let conflict = |ln, col, name| match rename_at(ln, col, name) {
+        Err(RenameError::Conflict(other, span)) => (other, span.start.ln, span.start.pos),
+        res => panic!("{:?}", res),
+    };
+
+    // * From a use or the declaration alike
+    let c = [(4, 8, 9), (6, 16, 17), (7, 8, 9), (9, 11, 12)];
+    assert_eq!(edits(7, 8, "total"), c);
+    assert_eq!(edits(4, 8, "d"), c);
+    // * The parameter hides the global `pi`, which `add` does not use
+    assert_eq!(edits(4, 12, "pi"), [(3, 12, 13), (4, 12, 13)]);
+
+    assert_eq!(conflict(4, 8, "a"), ("a".into(), 3, 12));
+    assert_eq!(conflict(4, 8, "g"), ("g".into(), 6, 12));
+    assert_eq!(conflict(6, 12, "c"), ("c".into(), 4, 8));
+    assert_eq!(conflict(0, 4, "b"), ("b".into(), 3, 19));
+    assert_eq!(conflict(3, 4, "g"), ("g".into(), 0, 4));
+
+    assert_eq!(
+        rename_at(12, 5, "start"),
+        Err(RenameError::Fixed("main".into()))
+    );
+    assert_eq!(
+        rename_at(4, 8, "while"),
+        Err(RenameError::BadName("while".into()))
+    );
+    assert_eq!(
+        rename_at(4, 8, "int"),
+        Err(RenameError::BadName("int".into()))
+    );
+    assert_eq!(
+        rename_at(4, 8, "1x"),
+        Err(RenameError::BadName("1x".into()))
+    );
+    assert_eq!(rename_at(2, 0, "x"), Err(RenameError::NoSymbol));
+
+    let src = "int main() {\n    int x;\n    scan(x);\n    return x;\n}\n";
+    let prog = parse_no_panic(src).unwrap();
+    let spans: Vec<_> = (rename(&prog, pos_at(src, 2, 9), "y").unwrap().iter())
+        .map(|edit| (edit.span.start.ln, edit.span.start.pos))
+        .collect();
+    assert_eq!(spans, [(1, 8), (2, 9), (3, 11)]);
+}
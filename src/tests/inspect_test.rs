@@ -0,0 +1,151 @@
+use crate::minivm::binfmt::{read_snapshot, write_snapshot};
+use crate::minivm::vm::{HeapBlock, MiniVM};
+use crate::minivm::*;
+use crate::parse;
+
+const SRC: &str = r#"int total;
+double avg;
+char grade = 'b';
+
+int add(int x) {
+    int before = total;
+    total = total + x;
+    return before;
+}
+
+int main() {
+    int i = 1;
+    while (i <= 4) {
+        add(i);
+        i = i + 1;
+    }
+    avg = total / 4.0;
+    return 0;
+}
+"#;
+
+fn compile(debug: bool) -> O0 {
+    Codegen::new(&parse(SRC).unwrap())
+        .with_debug_info(debug)
+        .compile()
+        .unwrap()
+}
+
+#[test]
+fn test_read_globals() {
+    let o0 = compile(true);
+    let mut input = "".as_bytes();
+    let mut output = vec![];
+    let mut vm = MiniVM::new(&o0, &mut input, &mut output);
+    assert_eq!(vm.read_global("total"), None);
+    vm.run().unwrap();
+    assert_eq!(vm.read_global("total"), Some(Value::Int(10)));
+    assert_eq!(vm.read_global("avg"), Some(Value::Double(2.5)));
+    assert_eq!(vm.read_global("grade"), Some(Value::Char(b'b')));
+    assert_eq!(vm.read_global("i"), None);
+
+    // * Only debug info tells where globals are
+    let o0 = compile(false);
+    let mut vm = MiniVM::new(&o0, &mut input, &mut output);
+    vm.run().unwrap();
+    assert_eq!(vm.read_global("total"), None);
+}
+
+#[test]
+fn test_read_stack_frame() {
+    let o0 = compile(true);
+    let mut input = "".as_bytes();
+    let mut output = vec![];
+    let mut vm = MiniVM::new(&o0, &mut input, &mut output);
+    // * Stop in the third call of `add`, on its last line
+    let mut calls = 0;
+    loop {
+        vm.step().unwrap();
+        let frame = vm.read_stack_frame(0).unwrap();
+        if frame.name == "add" && frame.line == Some(7) {
+            calls += 1;
+            if calls == 3 {
+                break;
+            }
+            while vm.read_stack_frame(0).unwrap().name == "add" {
+                vm.step().unwrap();
+            }
+        }
+    }
+
+    let add = vm.read_stack_frame(0).unwrap();
+    let vars: Vec<_> = (add.vars.iter())
+        .map(|v| (v.name.as_str(), v.value.clone()))
+        .collect();
+    assert_eq!(
+        vars,
+        [("x", Some(Value::Int(3))), ("before", Some(Value::Int(3)))]
+    );
+
+    let main = vm.read_stack_frame(1).unwrap();
+    assert_eq!((main.name.as_str(), main.line), ("main", Some(13)));
+    assert_eq!(main.vars[0].value, Some(Value::Int(3)));
+
+    let start = vm.read_stack_frame(2).unwrap();
+    assert_eq!(start.func, None);
+    let globals: Vec<_> = start.vars.iter().map(|v| v.name.as_str()).collect();
+    assert_eq!(globals, ["total", "avg", "grade"]);
+    assert!(vm.read_stack_frame(3).is_none());
+}
+
+#[test]
+fn test_heap_blocks() {
+    let main = vec![
+        Inst::IPush(2),
+        Inst::New,
+        Inst::IPush(7),
+        Inst::IStore,
+        Inst::IPush(1),
+        Inst::New,
+        Inst::Pop1,
+        Inst::Ret,
+    ];
+    let o0 = O0 {
+        version: 1,
+        constants: vec![Constant::String(b"main".to_vec())],
+        start_code: StartCodeInfo { ins: vec![] },
+        functions: vec![FnInfo {
+            name_idx: 0,
+            param_siz: 0,
+            lvl: 1,
+            ins: main,
+        }],
+        endian: Endian::Big,
+        debug: None,
+        relocs: vec![],
+        host_fns: vec![],
+    };
+    let mut input = "".as_bytes();
+    let mut output = vec![];
+    let mut vm = MiniVM::new(&o0, &mut input, &mut output);
+    vm.run().unwrap();
+
+    let blocks = vm.heap_blocks();
+    assert_eq!(blocks.len(), 2);
+    assert_eq!(blocks[0].slots, [7, 0]);
+    assert_eq!(blocks[0].read(0, value::Kind::Int), Some(Value::Int(7)));
+    assert_eq!(blocks[0].read(1, value::Kind::Double), None);
+    assert_eq!(blocks[1].addr, blocks[0].addr + 2);
+    assert_eq!(
+        vm.read(blocks[0].addr, value::Kind::UInt),
+        Some(Value::UInt(7))
+    );
+    assert_eq!(vm.read(blocks[1].addr + 1, value::Kind::Int), None);
+
+    // * Blocks are still there in a machine the program is restored into
+    let mut file = vec![];
+    write_snapshot(&vm.snapshot(), &mut file).unwrap();
+    let snapshot = read_snapshot(&mut file.as_slice()).unwrap();
+    let mut input = "".as_bytes();
+    let mut output = vec![];
+    let mut restored = MiniVM::new(&o0, &mut input, &mut output);
+    restored.restore(&snapshot).unwrap();
+    let addrs = |blocks: Vec<HeapBlock>| blocks.iter().map(|b| b.addr).collect::<Vec<_>>();
+    assert_eq!(addrs(restored.heap_blocks()), addrs(vm.heap_blocks()));
+    assert_eq!(restored.heap_blocks()[0].slots, [7, 0]);
+}
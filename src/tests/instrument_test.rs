@@ -0,0 +1,91 @@
+use crate::minivm::vm::MiniVM;
+use crate::minivm::*;
+use crate::parse;
+
+const SRC: &str = "int g = 3;
+int sq(int x) {
+    return x * x;
+}
+int main() {
+    int i = 0;
+    int s = 0;
+    int n;
+    scan(n);
+    while (i < n) {
+        if (i - i / 3 * 3 == 0) {
+            s = s + sq(i);
+        } else {
+            s = s - g;
+        }
+        i = i + 1;
+    }
+    print(s);
+    return 0;
+}
+";
+
+fn compile(optimize: bool) -> (O0, CounterMap) {
+    Codegen::new(&parse(SRC).unwrap())
+        .with_debug_info(true)
+        .with_peephole(optimize)
+        .with_unroll(0, 0)
+        .compile_instrumented()
+        .unwrap()
+}
+
+/// Output of running `o0` on `input`, and line counts of the VM
+fn run(o0: &O0, input: &str) -> (String, CoverageMap, vm::Coverage) {
+    let mut input = input.as_bytes();
+    let mut output = vec![];
+    let mut vm = MiniVM::new(o0, &mut input, &mut output).with_coverage();
+    vm.run().unwrap();
+    let coverage = vm.coverage().unwrap().clone();
+    drop(vm);
+    let map = CoverageMap::new(o0).unwrap();
+    (String::from_utf8(output).unwrap(), map, coverage)
+}
+
+#[test]
+fn test_instrument_counts() {
+    for optimize in [false, true] {
+        let (o0, map) = compile(optimize);
+        let (out, lines, coverage) = run(&o0, "10");
+        let prog = parse(SRC).unwrap();
+        let plain = Codegen::new(&prog).with_debug_info(true).compile();
+        let (plain_out, ..) = run(&plain.unwrap(), "10");
+        let (program, dump) = out.split_at(out.find(DUMP_MARK).unwrap());
+        assert_eq!(program, plain_out);
+
+        // * Counts the program printed agree with what the VM saw
+        let counts = map.parse_dump(dump).unwrap();
+        let mut expected = lines.line_counts(&coverage);
+        expected.retain(|line, _| *line > 1);
+        assert_eq!(map.line_counts(&counts), expected, "{}", optimize);
+        assert_eq!(map.line_counts(&counts)[&3], 4);
+        assert_eq!(map.line_counts(&counts)[&14], 6);
+        assert!(map
+            .counters
+            .iter()
+            .all(|c| c.function == "sq" || c.function == "main"));
+    }
+}
+
+#[test]
+fn test_counter_map() {
+    let (o0, map) = compile(true);
+    assert_eq!(CounterMap::from_json(&map.to_json()).unwrap(), map);
+
+    let (out, ..) = run(&o0, "0");
+    let counts = map.parse_dump(&out).unwrap();
+    let lines = map.line_counts(&counts);
+    assert_eq!((lines[&3], lines[&6], lines[&12]), (0, 1, 0));
+
+    // * Lines of counts that don't fit the map are not used
+    assert_eq!(map.parse_dump("1\n"), None);
+    assert_eq!(map.parse_dump(&format!("{} 1 2\n", DUMP_MARK)), None);
+    let wrapped = out.replace(" 1", " -1");
+    assert!(map
+        .parse_dump(&wrapped)
+        .unwrap()
+        .contains(&(u32::MAX as u64)));
+}
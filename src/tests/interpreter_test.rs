@@ -0,0 +1,106 @@
+use crate::c0::interpreter::*;
+use crate::c0::lexer::Lexer;
+use crate::c0::parser::*;
+
+fn run(input: &str, stdin: &str) -> (RuntimeResult<i32>, String) {
+    let lexer = Lexer::new(input);
+    let mut parser = Parser::new(lexer);
+    let prog = parser.parse().unwrap();
+
+    let mut stdin = stdin.as_bytes();
+    let mut output = vec![];
+    let res = Interpreter::new(&prog, &mut stdin, &mut output)
+        .with_step_limit(100_000)
+        .run();
+    (res, String::from_utf8(output).unwrap())
+}
+
+#[test]
+fn test_interpret_fib() {
+    let input = r#"
+int fib(int n) {
+    if (n <= 1)
+        return n;
+    return fib(n - 1) + fib(n - 2);
+}
+
+int main() {
+    int n;
+    scan(n);
+    print("fib", n, fib(n));
+    return fib(n) / 11;
+}
+    "#;
+    let (res, output) = run(input, "10\n");
+    assert_eq!(res.unwrap(), 5);
+    assert_eq!(output, "fib 10 55\n");
+}
+
+#[test]
+fn test_interpret_types() {
+    let input = r#"
+int main() {
+    char c = 'a';
+    double d = 7;
+    int i = 7 / 2;
+    print(c, c + 1, (char)(c + 1));
+    print(d / 2, i, (int)(d / 2), -d);
+    print(1 < 2, 2.0 == 2, 1 && 0, 0 || 3);
+    return 0;
+}
+    "#;
+    let (res, output) = run(input, "");
+    assert_eq!(res.unwrap(), 0);
+    assert_eq!(output, "a 98 b\n3.500000 3 3 -7.000000\n1 1 0 1\n");
+}
+
+#[test]
+fn test_interpret_scopes_and_loops() {
+    let input = r#"
+int x = 1;
+
+void main() {
+    int i = 0;
+    print(x);
+    {
+        int x = x + 10;
+        print(x);
+    }
+    outer: while (i < 10) {
+        int j = 0;
+        while (1) {
+            if (j == 2)
+                break;
+            if (i == 3)
+                break outer;
+            j = j + 1;
+        }
+        i = i + 1;
+    }
+    print(x, i);
+}
+    "#;
+    let (res, output) = run(input, "");
+    assert_eq!(res.unwrap(), 0);
+    assert_eq!(output, "1\n11\n1 3\n");
+}
+
+#[test]
+fn test_interpret_errors() {
+    let (res, output) = run("void main() { print(1); print(1 / 0); }", "");
+    match res {
+        Err(RuntimeError::DivideByZero) => (),
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(output, "1\n");
+
+    match run("void main() { while (1) {} }", "").0 {
+        Err(RuntimeError::StepLimitExceeded(_)) => (),
+        other => panic!("{:?}", other),
+    }
+
+    match run("int main() { int a; scan(a); return a; }", " ").0 {
+        Err(RuntimeError::UnexpectedEof) => (),
+        other => panic!("{:?}", other),
+    }
+}
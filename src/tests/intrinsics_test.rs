@@ -0,0 +1,132 @@
+use crate::minivm::binfmt::{read_snapshot, write_snapshot};
+use crate::minivm::vm::{intrinsic_sigs, Intrinsics, MiniVM};
+use crate::{codegen, parse_with_host};
+
+/// Output of `src` run with intrinsics given by `opts`
+fn run(src: &str, opts: Intrinsics) -> String {
+    let o0 = codegen(&parse_with_host(src, &intrinsic_sigs()).unwrap()).unwrap();
+    let mut input = "".as_bytes();
+    let mut output = vec![];
+    let mut vm = MiniVM::new(&o0, &mut input, &mut output).with_intrinsics(opts);
+    vm.run().unwrap();
+    drop(vm);
+    String::from_utf8(output).unwrap()
+}
+
+const RAND: &str = r#"
+int main() {
+    int i = 0;
+    while (i < 3) {
+        print(rand());
+        i = i + 1;
+    }
+    srand(7);
+    print(rand());
+    srand(7);
+    print(rand());
+    return 0;
+}
+"#;
+
+#[test]
+fn test_rand_seeded() {
+    let seeded = |seed| Intrinsics {
+        seed: Some(seed),
+        virtual_clock: false,
+    };
+    let out = run(RAND, seeded(1));
+    assert_eq!(out, run(RAND, seeded(1)));
+    assert_ne!(out, run(RAND, seeded(2)));
+
+    let nums: Vec<i64> = out.lines().map(|l| l.parse().unwrap()).collect();
+    assert_eq!(nums.len(), 5);
+    assert!(nums.iter().all(|n| (0..=i32::MAX as i64).contains(n)));
+    assert_ne!(nums[0], nums[1]);
+    // * `srand` starts the same sequence over, whatever the seed before
+    assert_eq!(nums[3], nums[4]);
+    assert_eq!(run(RAND, seeded(2)).lines().last(), out.lines().last());
+}
+
+#[test]
+fn test_virtual_clock() {
+    let src = r#"
+int main() {
+    int i = 0;
+    int t = clock();
+    while (i < 2000) {
+        i = i + 1;
+    }
+    print(t, clock() - t);
+    return 0;
+}
+"#;
+    let opts = Intrinsics {
+        seed: None,
+        virtual_clock: true,
+    };
+    let out = run(src, opts);
+    assert_eq!(out, run(src, opts));
+    let times: Vec<i32> = out.split_whitespace().map(|t| t.parse().unwrap()).collect();
+    assert_eq!(times[0], 0);
+    assert!(times[1] > 2, "{}", out);
+}
+
+#[test]
+fn test_own_rand() {
+    // * Programs from before the intrinsics keep their own
+    let src =
+        "int rand() {\n    return 4;\n}\n\nint main() {\n    print(rand());\n    return 0;\n}\n";
+    assert_eq!(run(src, Intrinsics::default()).trim(), "4");
+}
+
+const RAND_ONLY: &str = r#"
+int main() {
+    int i = 0;
+    while (i < 4) {
+        print(rand());
+        i = i + 1;
+    }
+    return 0;
+}
+"#;
+
+#[test]
+fn test_rand_runs_again() {
+    let o0 = codegen(&parse_with_host(RAND_ONLY, &intrinsic_sigs()).unwrap()).unwrap();
+    let mut input = "".as_bytes();
+    let mut output = vec![];
+    let mut vm = MiniVM::new(&o0, &mut input, &mut output).with_intrinsics(Intrinsics::default());
+    vm.run().unwrap();
+    vm.run().unwrap();
+    drop(vm);
+    let out = String::from_utf8(output).unwrap();
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines.len(), 8);
+    assert_eq!(lines[..4], lines[4..]);
+}
+
+#[test]
+fn test_rand_steps_back() {
+    let o0 = codegen(&parse_with_host(RAND_ONLY, &intrinsic_sigs()).unwrap()).unwrap();
+    let mut input = "".as_bytes();
+    let mut output = vec![];
+    let mut vm = MiniVM::new(&o0, &mut input, &mut output)
+        .with_intrinsics(Intrinsics::default())
+        .with_history(10_000);
+    while vm.step().unwrap().is_none() {}
+    let steps = vm.snapshot().steps as usize;
+    assert_eq!(vm.step_back(steps / 2), steps / 2);
+    let snapshot = vm.snapshot();
+    assert!(snapshot.rng.is_some());
+    let mut file = vec![];
+    write_snapshot(&snapshot, &mut file).unwrap();
+    assert_eq!(read_snapshot(&mut file.as_slice()).unwrap(), snapshot);
+    vm.resume().unwrap();
+    drop(vm);
+    let out = String::from_utf8(output).unwrap();
+    let lines: Vec<&str> = out.lines().collect();
+    // * Numbers printed again after going back are the ones printed before
+    let again = lines.len() - 4;
+    assert!((1..4).contains(&again), "{}", out);
+    assert_eq!(lines[4 - again..4], lines[4..]);
+}
@@ -0,0 +1,29 @@
+use crate::minivm::Inst::*;
+use crate::minivm::*;
+
+#[test]
+fn test_forward_jumps_are_backpatched() {
+    let mut labels = Labels::new();
+    let (end, top) = (labels.new_label(), labels.new_label());
+    labels.place(top, 0);
+    let mut ins = vec![IPush(1), JE(end.0), Jmp(top.0), Ret];
+    assert_eq!(labels.resolve(&mut ins), Err(end));
+
+    labels.place(end, 3);
+    labels.resolve(&mut ins).unwrap();
+    assert_eq!(ins, [IPush(1), JE(3), Jmp(0), Ret]);
+}
+
+#[test]
+fn test_labels_move_with_code() {
+    let mut ins = vec![IPush(0), JE(4), IPush(1), Jmp(0), Ret];
+    let mut labels = Labels::of_jumps(&mut ins);
+    let placed: Vec<_> = labels.placed().map(|(_, at)| at).collect();
+    assert_eq!(placed, [4, 0]);
+
+    // * Insert an instruction at the start, moving everything down by one
+    ins.insert(0, SNew(1));
+    labels.move_all(|at| at + 1);
+    labels.resolve(&mut ins).unwrap();
+    assert_eq!(ins, [SNew(1), IPush(0), JE(5), IPush(1), Jmp(1), Ret]);
+}
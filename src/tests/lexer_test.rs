@@ -1,6 +1,4 @@
-use crate::c0::err::*;
 use crate::c0::lexer::*;
-use crate::prelude::*;
 
 #[test]
 fn test_lex_valid_ints() {
@@ -10,15 +8,9 @@ fn test_lex_valid_ints() {
 0xabcd
     "#;
 
-    let mut lexer = Lexer::new(src.chars());
+    let mut lexer = Lexer::new(src);
 
-    assert!(lexer.all(|token| {
-        if let TokenType::Literal(Literal::Integer(_)) = token.var {
-            true
-        } else {
-            false
-        }
-    }));
+    assert!(lexer.all(|token| { matches!(token.var, TokenType::Literal(Literal::Integer(_))) }));
 }
 
 #[test]
@@ -29,15 +21,9 @@ fn test_lex_valid_floats() {
 1e10
     "#;
 
-    let mut lexer = Lexer::new(src.chars());
+    let mut lexer = Lexer::new(src);
 
-    assert!(lexer.all(|token| {
-        if let TokenType::Literal(Literal::Float(_)) = token.var {
-            true
-        } else {
-            false
-        }
-    }));
+    assert!(lexer.all(|token| { matches!(token.var, TokenType::Literal(Literal::Float(_))) }));
 }
 
 #[test]
@@ -50,11 +36,10 @@ fn test_lex_valid_strings() {
 "Hello\u1234world"
     "#;
 
-    let lexer = Lexer::new(src.chars());
+    let lexer = Lexer::new(src);
 
     lexer.for_each(|token| {
         if let TokenType::Literal(Literal::String(_)) = &token.var {
-            ()
         } else {
             panic!("{:?}", token)
         }
@@ -71,12 +56,11 @@ i9
 test
     "#;
 
-    let lexer = Lexer::new(src.chars());
+    let lexer = Lexer::new(src);
 
     lexer.for_each(|token| {
         dbg!(&token);
         if let TokenType::Identifier(_) = &token.var {
-            ()
         } else {
             panic!("{:?}", token)
         }
@@ -87,6 +71,7 @@ test
 fn test_lex_keywords() {
     let src = r#"
 const
+auto
 as
 if
 else
@@ -98,13 +83,13 @@ print
 scan
     "#;
 
-    let lexer = Lexer::new(src.chars());
+    let lexer = Lexer::new(src);
 
     let vars: Vec<_> = lexer.map(|token| token.var).collect();
 
     use TokenType::*;
     let expected = [
-        Const, As, If, Else, While, Break, Continue, Return, Print, Scan,
+        Const, Auto, As, If, Else, While, Break, Continue, Return, Print, Scan,
     ];
     assert_eq!(vars, expected);
 }
@@ -112,10 +97,10 @@ scan
 #[test]
 fn test_lex_ops() {
     let src = r#"
-; - + * / ! & | && || ^ ++ -- == != < <= > >= ( ) [ ] { } = ,
+; - + * / ! & | && || ^ ++ -- == != < <= > >= ( ) [ ] { } = , :
     "#;
 
-    let lexer = Lexer::new(src.chars());
+    let lexer = Lexer::new(src);
 
     let vars: Vec<_> = lexer.map(|token| token.var).collect();
 
@@ -148,6 +133,7 @@ fn test_lex_ops() {
         RCurlyBrace,
         Assign,
         Comma,
+        Colon,
     ];
     assert_eq!(vars, expected);
 }
@@ -161,17 +147,17 @@ $
 `
 ~
 \
-:
 ?"#;
 
     let lines = src.lines();
     for line in lines {
-        let chars = line.chars();
-        let result = Lexer::new(chars).next().unwrap();
+        let result = Lexer::new(line).next().unwrap();
 
         assert!(
             result.is_err(),
-            format!("token {:?} in '{}' does not result in error!", result, line)
+            "token {:?} in '{}' does not result in error!",
+            result,
+            line
         );
     }
 }
@@ -191,12 +177,129 @@ fn test_lex_err_strings() {
 
     let lines = src.lines();
     for line in lines {
-        let chars = line.chars();
-        let result = Lexer::new(chars).next().unwrap();
+        let result = Lexer::new(line).next().unwrap();
 
         assert!(
             result.is_err(),
-            format!("token {:?} in '{}' does not result in error!", result, line)
+            "token {:?} in '{}' does not result in error!",
+            result,
+            line
         );
     }
 }
+
+#[test]
+fn test_lex_comments() {
+    let src = "a // line\nb /* block\n*/ c // crlf\r\nd";
+
+    let mut lexer = Lexer::new(src);
+    let mut tokens = vec![];
+    while let Some(tok) = lexer.get_next_token() {
+        tokens.push(tok);
+    }
+    let vars: Vec<_> = tokens.iter().map(|t| t.var.clone()).collect();
+    assert_eq!(
+        vars,
+        vec![
+            TokenType::Identifier("a".into()),
+            TokenType::Comment(" line".into()),
+            TokenType::Identifier("b".into()),
+            TokenType::Comment(" block\n".into()),
+            TokenType::Identifier("c".into()),
+            TokenType::Comment(" crlf".into()),
+            TokenType::Identifier("d".into()),
+        ]
+    );
+
+    // * Comment spans cover the whole comment
+    let indexes: Vec<_> = tokens
+        .iter()
+        .map(|t| (t.span.start.index, t.span.end.index))
+        .collect();
+    assert_eq!(indexes[1], (2, 9));
+    assert_eq!(indexes[3], (12, 23));
+    assert_eq!(indexes[5], (26, 33));
+}
+
+#[test]
+fn test_lex_doc_comments() {
+    let src = "/// doc\n//// rule\n// plain\n///\nint x;";
+    let vars: Vec<_> = Lexer::new(src).map(|t| t.var).collect();
+    assert_eq!(
+        vars,
+        vec![
+            TokenType::DocComment(" doc".into()),
+            TokenType::DocComment("".into()),
+            TokenType::Identifier("int".into()),
+            TokenType::Identifier("x".into()),
+            TokenType::Semicolon,
+        ]
+    );
+}
+
+/// Malformed numbers, many of them found by fuzzing the parser, are each
+/// read whole into one error token, and lexing goes on right after them
+#[test]
+fn test_lex_err_numbers() {
+    use crate::c0::err::LexError::*;
+    let cases = [
+        ("123abc;", BadSuffix("abc".into()), 6),
+        ("1.2.3;", MisplacedPoint, 5),
+        ("1.2.3.4.5;", MisplacedPoint, 9),
+        ("0x1.5;", MisplacedPoint, 5),
+        ("1e5.2;", MisplacedPoint, 5),
+        ("0x;", NoDigits("0x".into()), 2),
+        ("0b;", NoDigits("0b".into()), 2),
+        ("0xg;", NoDigits("0x".into()), 3),
+        ("0b102;", BadDigit('2', 2), 5),
+        ("0o78;", BadDigit('8', 8), 4),
+        ("0x1fg;", BadSuffix("g".into()), 5),
+        ("1e;", EmptyExponent, 2),
+        ("1e+;", EmptyExponent, 3),
+        ("2eggs;", EmptyExponent, 5),
+        ("1.e;", EmptyExponent, 3),
+        ("1e99999999999;", NumberOutOfRange, 13),
+        ("1e5000;", NumberOutOfRange, 6),
+        ("1_000;", BadSuffix("_000".into()), 5),
+        ("9x;", BadSuffix("x".into()), 2),
+    ];
+    for (src, err, len) in cases {
+        let tokens: Vec<_> = Lexer::new(src).collect();
+        assert_eq!(tokens.len(), 2, "{}: {:?}", src, tokens);
+        assert_eq!(tokens[0].var, TokenType::Error(err), "{}", src);
+        assert_eq!(tokens[0].span.end.pos, len, "{}", src);
+        assert_eq!(tokens[1].var, TokenType::Semicolon, "{}", src);
+    }
+
+    // * The parser reports the number, and reads on past it
+    let err = crate::parse("int main() { int a = (1.2.3 + 1; return 0; }").unwrap_err();
+    assert_eq!(err.code, crate::ErrorCode(12));
+    let err = crate::parse("int main() { 12ab; }").unwrap_err();
+    assert_eq!(err.message, "Number literal has a bad suffix 'ab'");
+}
+
+#[test]
+fn test_lex_borrows_text() {
+    use std::borrow::Cow;
+
+    let src = "\"héllo\" \"a\\tb\" // ünïcode\nx";
+    let tokens: Vec<_> = Lexer::new(src).collect();
+    match &tokens[0].var {
+        TokenType::Literal(Literal::String(Cow::Borrowed(s))) => assert_eq!(*s, "héllo"),
+        var => panic!("{:?} is not a borrowed string", var),
+    }
+    // * Escapes make the literal differ from the source, so it is copied
+    match &tokens[1].var {
+        TokenType::Literal(Literal::String(Cow::Owned(s))) => assert_eq!(s, "a\tb"),
+        var => panic!("{:?} is not an owned string", var),
+    }
+    assert_eq!(tokens[2].var, TokenType::Identifier("x".into()));
+    // * Positions still count chars, not bytes
+    assert_eq!(tokens[2].span.start.index, 26);
+
+    let mut lexer = Lexer::new(src);
+    lexer.get_next_token();
+    lexer.get_next_token();
+    let comment = lexer.get_next_token().unwrap().var.into_owned();
+    assert_eq!(comment, TokenType::Comment(" ünïcode".into()));
+}
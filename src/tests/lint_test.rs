@@ -0,0 +1,99 @@
+use crate::c0::lint::{Forbidden, LintRule, LintRules};
+use crate::{lint_with, parse, QueryError, Severity};
+
+const SRC: &str = "const int LIMIT = 10;
+int count;
+
+/// Factorial of `n`
+int fact(int n) {
+    if (n <= 1) { return 1; }
+    return n * fact(n - 1);
+}
+
+int sum(int a, int b, int c) {
+    return a + b + c;
+}
+
+int main() {
+    int i = 0;
+    while (i < LIMIT) {
+        count = count + fact(i);
+        i = i + 1;
+    }
+    print(sum(count, 1, 2));
+    return 0;
+}
+";
+
+fn violations(rules: LintRules) -> Vec<(LintRule, usize)> {
+    (rules.check(&parse(SRC).unwrap()).unwrap().into_iter())
+        .map(|v| (v.rule, v.span.start.ln + 1))
+        .collect()
+}
+
+#[test]
+fn test_rules() {
+    assert_eq!(violations(LintRules::default()), []);
+    let rules = LintRules {
+        max_fn_lines: Some(5),
+        max_params: Some(2),
+        ..LintRules::default()
+    };
+    assert_eq!(
+        violations(rules),
+        [(LintRule::MaxParams, 10), (LintRule::MaxFnLines, 14)]
+    );
+    let rules = LintRules {
+        forbid_recursion: true,
+        forbid_globals: true,
+        ..LintRules::default()
+    };
+    assert_eq!(
+        violations(rules),
+        [(LintRule::Globals, 2), (LintRule::Recursion, 7)]
+    );
+    let rules = LintRules {
+        require_doc: true,
+        ..LintRules::default()
+    };
+    assert_eq!(
+        violations(rules),
+        [(LintRule::Doc, 10), (LintRule::Doc, 14)]
+    );
+}
+
+#[test]
+fn test_forbidden() {
+    let rules = LintRules {
+        forbid: vec![Forbidden {
+            query: "While, FunctionCall[name=sum]".into(),
+            message: Some("use recursion".into()),
+        }],
+        deny: true,
+        ..LintRules::default()
+    };
+    let diags = lint_with(&parse(SRC).unwrap(), &rules).unwrap();
+    let found: Vec<_> = (diags.iter())
+        .map(|d| (d.severity, d.code.to_string(), d.span.unwrap().start.ln + 1))
+        .collect();
+    assert_eq!(
+        found,
+        [
+            (Severity::Error, "E0507".into(), 16),
+            (Severity::Error, "E0507".into(), 20)
+        ]
+    );
+    assert_eq!(diags[0].message, "While is forbidden: use recursion");
+
+    let rules = LintRules {
+        forbid: vec![Forbidden {
+            query: "Loop".into(),
+            message: None,
+        }],
+        ..LintRules::default()
+    };
+    assert_eq!(
+        lint_with(&parse(SRC).unwrap(), &rules),
+        Err(QueryError::UnknownKind("Loop".into()))
+    );
+}
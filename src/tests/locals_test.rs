@@ -0,0 +1,187 @@
+use super::exec;
+use crate::c0::gen::*;
+use crate::minivm::*;
+use crate::parse;
+
+fn compile(src: &str, passes: PassManager) -> O0 {
+    Codegen::new(&parse(src).unwrap())
+        .with_passes(passes)
+        .compile()
+        .unwrap()
+}
+
+/// Instructions of function `idx` of `o0` that `f` picks
+fn count(o0: &O0, idx: usize, f: fn(&Inst) -> bool) -> usize {
+    o0.functions[idx].ins.iter().filter(|i| f(i)).count()
+}
+
+/// Conditional jumps in `main`
+fn tests(o0: &O0) -> usize {
+    count(o0, o0.functions.len() - 1, |i| {
+        use Inst::*;
+        matches!(i, JE(_) | JNe(_) | JL(_) | JGe(_) | JG(_) | JLe(_))
+    })
+}
+
+#[test]
+fn test_constants_across_branches() {
+    let src = "int main() {\n\
+                   int x;\n\
+                   int d = 1;\n\
+                   scan(x);\n\
+                   if (x > 0) { d = 1; print(x); }\n\
+                   if (d) { print(1); } else { print(2); }\n\
+                   while (d - 1) { print(3); }\n\
+                   return d;\n\
+               }\n";
+    let (o0, o1) = (
+        compile(src, PassManager::for_level(0).unwrap()),
+        compile(src, PassManager::default()),
+    );
+    for input in ["5", "-5"] {
+        assert_eq!(exec(&o0, input), exec(&o1, input));
+    }
+    assert_eq!(exec(&o1, "5"), (1, "5\n1\n".into()));
+    // * `d` is 1 on both paths into the second `if`, and the loop never runs
+    assert_eq!(tests(&o1), 1);
+}
+
+#[test]
+fn test_escaped_locals() {
+    let src = "void set(&int p) { *p = 2; }\n\
+               int main() {\n\
+                   int a = 1;\n\
+                   int b = 1;\n\
+                   set(&a);\n\
+                   &int p = &b;\n\
+                   *p = 3;\n\
+                   if (a == 1) { print(1); }\n\
+                   if (b == 1) { print(1); }\n\
+                   return a + b;\n\
+               }\n";
+    let o0 = compile(src, PassManager::default());
+    assert_eq!(exec(&o0, ""), (5, "".into()));
+    assert_eq!(tests(&o0), 2);
+}
+
+#[test]
+fn test_copies_and_dead_stores() {
+    let src = "int n;\n\
+               int next() { n = n + 1; return n; }\n\
+               int f(int a) {\n\
+                   int b = a;\n\
+                   int c = b;\n\
+                   b = 0;\n\
+                   return c + a;\n\
+               }\n\
+               int main() {\n\
+                   int x = next();\n\
+                   x = f(3);\n\
+                   print(x, n);\n\
+                   return 0;\n\
+               }\n";
+    let stores = |i: &Inst| matches!(i, Inst::IStore);
+    let o0 = compile(src, PassManager::for_level(0).unwrap());
+    let o1 = compile(src, PassManager::default());
+    assert_eq!(exec(&o1, ""), (0, "6 1\n".into()));
+    assert_eq!(exec(&o0, ""), exec(&o1, ""));
+    // * `c + a` is `a + a`, and nothing reads `b` after it is set to 0
+    assert_eq!(count(&o0, 1, stores), 3);
+    assert_eq!(count(&o1, 1, stores), 0);
+    // * `next()` is still called for its side effect, and stored over
+    assert_eq!(count(&o1, 2, |i| matches!(i, Inst::Call(_))), 2);
+    assert_eq!(count(&o1, 2, stores), 1);
+
+    // * Debuggers read locals, so stores to them stay with debug info
+    let o1 = Codegen::new(&parse(src).unwrap())
+        .with_debug_info(true)
+        .compile()
+        .unwrap();
+    assert_eq!(count(&o1, 1, stores), 3);
+}
+
+#[test]
+fn test_stores_through_references() {
+    let src = "int main() {\n\
+                   int a = 1;\n\
+                   &int p = &a;\n\
+                   a = 2;\n\
+                   int b = a;\n\
+                   *p = 3;\n\
+                   print(*p, b, a);\n\
+                   return 0;\n\
+               }\n";
+    let o1 = compile(src, PassManager::default());
+    assert_eq!(exec(&o1, ""), (0, "3 2 3\n".into()));
+}
+
+#[test]
+fn test_generated_programs() {
+    for seed in 0..40 {
+        let config = GenConfig {
+            seed,
+            ..GenConfig::default()
+        };
+        let src = generate(&config);
+        let o0 = compile(&src, PassManager::for_level(0).unwrap());
+        let expected = exec(&o0, "");
+        for pass in ["sccp", "copyprop", "dse"] {
+            let passes = PassManager::new().with_pipeline(pass).unwrap();
+            let out = exec(&compile(&src, passes), "");
+            assert_eq!(out, expected, "{} on seed {}:\n{}", pass, seed, src);
+        }
+        let o1 = compile(&src, PassManager::default());
+        assert_eq!(exec(&o1, ""), expected, "seed {}:\n{}", seed, src);
+    }
+}
+
+#[test]
+fn test_stores_read_through_escaped_addresses() {
+    let passed = "void show(&int p) { print(*p); }\n\
+                  int main() { int x = 3; show(&x); return 0; }\n";
+    let aliased = "int main() { int x = 3; &int q = &x; print(*q); return 0; }\n";
+    for src in [passed, aliased] {
+        for pass in ["sccp", "copyprop", "dse"] {
+            let passes = PassManager::new().with_pipeline(pass).unwrap();
+            assert_eq!(
+                exec(&compile(src, passes), ""),
+                (0, "3\n".into()),
+                "{}",
+                pass
+            );
+        }
+        for level in 1..=2 {
+            let passes = PassManager::for_level(level).unwrap();
+            assert_eq!(
+                exec(&compile(src, passes), ""),
+                (0, "3\n".into()),
+                "-O{}",
+                level
+            );
+        }
+    }
+}
+
+#[test]
+fn test_functions_never_returning() {
+    let srcs = [
+        "int loop() { while (1) {} return 1; }\n\
+         int main() { int x = loop(); print(x); return 0; }\n",
+        "int loop(int n) { while (1) {} return n; }\n\
+         int main() { int x = loop(1); print(x); return 0; }\n",
+        "int loop(int n) { while (1) {} return n; }\n\
+         const int X = loop(1);\n\
+         int main() { print(X); return 0; }\n",
+    ];
+    for src in srcs {
+        for pass in ["sccp", "sccp,dce"] {
+            let passes = PassManager::new().with_pipeline(pass).unwrap();
+            let o0 = compile(src, passes);
+            // * The return never reached says `loop` returns an int
+            assert_eq!(count(&o0, 0, |i| matches!(i, Inst::IRet)), 1, "{}", pass);
+        }
+        for level in 1..=2 {
+            compile(src, PassManager::for_level(level).unwrap());
+        }
+    }
+}
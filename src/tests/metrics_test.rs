@@ -0,0 +1,43 @@
+use crate::{metrics, parse};
+
+const SRC: &str = "int fact(int n) {
+    if (n <= 1 || n > 20) { return 1; }
+    return n * fact(n - 1);
+}
+int main() {
+    int i = 0, s;
+    while (i < 5) {
+        if (i == 2) { print(fact(i)); } else if (i == 3) { s = 1; } else { s = 2; }
+        i = i + 1;
+    }
+    return 0;
+}
+";
+
+#[test]
+fn test_metrics() {
+    let m = metrics(&parse(SRC).unwrap());
+    let names: Vec<_> = m.functions.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(names, ["fact", "main"]);
+
+    let fact = &m.functions[0];
+    assert_eq!(fact.line, 1);
+    assert_eq!(fact.statements, 3);
+    assert_eq!(fact.cyclomatic, 3);
+    assert_eq!(fact.max_nesting, 1);
+    // * `if`, `<=`, `||`, `>`, `return` twice, `*`, `fact()`, `-`
+    assert_eq!((fact.distinct_operators, fact.operators), (8, 9));
+    // * `n` 4 times, `1` 3 times, `20`
+    assert_eq!((fact.distinct_operands, fact.operands), (3, 8));
+    let volume = 17.0 * 11f64.log2();
+    assert!((fact.volume - volume).abs() < 1e-9);
+
+    let main = &m.functions[1];
+    assert_eq!(main.line, 5);
+    assert_eq!(main.statements, 9);
+    assert_eq!(main.cyclomatic, 4);
+    assert_eq!(main.max_nesting, 2);
+
+    assert!(m.to_string().lines().nth(1).unwrap().starts_with("fact "));
+    assert!(m.to_json().contains("\"cyclomatic\": 4"));
+}
@@ -1,3 +1,80 @@
+mod alias_test;
+mod api_test;
+mod ast_diff_test;
+mod binfmt_test;
+mod callgraph_test;
 mod compiler_test;
+mod consteval_test;
+mod coverage_test;
+mod disasm_test;
+mod doc_test;
+mod escape_test;
+mod fingerprint_test;
+mod fix_test;
+mod gen_test;
+mod gvn_test;
+mod highlight_test;
+mod host_fn_test;
+mod ide_test;
+mod inspect_test;
+mod instrument_test;
+mod interpreter_test;
+mod intrinsics_test;
+mod label_test;
 mod lexer_test;
+mod lint_test;
+mod locals_test;
+mod metrics_test;
+mod mutate_test;
+mod num_test;
+mod obfuscate_test;
 mod parser_test;
+mod passes_test;
+mod peephole_test;
+mod pgo_test;
+mod playground_test;
+mod preprocess_test;
+mod pretty_test;
+mod profile_test;
+mod purity_test;
+mod query_test;
+mod reduce_test;
+mod reloc_test;
+mod remarks_test;
+mod reproducible_test;
+mod sanitize_test;
+mod schedule_test;
+mod size_test;
+mod stream_test;
+mod symbol_test;
+mod target_test;
+mod type_checker_test;
+mod unroll_test;
+mod validate_test;
+mod value_test;
+mod verify_test;
+mod vm_limits_test;
+mod vm_snapshot_test;
+mod vm_trace_test;
+
+use crate::minivm::{vm::MiniVM, O0};
+
+/// `src` compiled as `chigusa` compiles it by default
+fn compile(src: &str) -> O0 {
+    crate::codegen(&crate::parse(src).unwrap()).unwrap()
+}
+
+/// Exit code and output of `o0` on the VM, run on `input`
+fn exec(o0: &O0, input: &str) -> (i32, String) {
+    let mut input = input.as_bytes();
+    let mut output = vec![];
+    let code = (MiniVM::new(o0, &mut input, &mut output).with_step_limit(10_000_000))
+        .run()
+        .unwrap();
+    (code, String::from_utf8(output).unwrap())
+}
+
+/// Output of `src`, compiled by [`compile`], run on no input
+fn run(src: &str) -> String {
+    exec(&compile(src), "").1
+}
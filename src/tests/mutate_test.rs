@@ -0,0 +1,126 @@
+use crate::c0::ast::ast_eq;
+use crate::c0::mutate::*;
+use crate::c0::parser::parse_no_panic;
+use crate::minivm::vm;
+
+const PROG: &str = "int max(int a, int b) {
+    if (a > b) {
+        return a;
+    }
+    return b;
+}
+
+int main() {
+    int i = 0;
+    while (i < 3) {
+        print(max(i, 1) * 2);
+        i = i + 1;
+    }
+    return 0;
+}
+";
+
+/// Output of running `mutant` on the VM, or `None` if it doesn't compile
+/// or fails
+fn output(mutant: &Mutant) -> Option<String> {
+    let o0 = crate::codegen(&mutant.prog).ok()?;
+    let mut input = "".as_bytes();
+    let mut output = vec![];
+    (vm::MiniVM::new(&o0, &mut input, &mut output).with_step_limit(10_000))
+        .run()
+        .ok()?;
+    String::from_utf8(output).ok()
+}
+
+#[test]
+fn test_mutations() {
+    let all = mutations(PROG).unwrap();
+    let described: Vec<_> = all.iter().take(8).map(|m| m.to_string()).collect();
+    assert_eq!(
+        described,
+        [
+            "negate the condition",
+            "replace `>` with `>=`",
+            "replace 0 with -1",
+            "replace 0 with 1",
+            "negate the condition",
+            "replace `<` with `<=`",
+            "replace 3 with 2",
+            "replace 3 with 4",
+        ]
+    );
+    assert_eq!(all.len(), 18);
+    assert!(all.iter().enumerate().all(|(idx, m)| m.id == idx));
+    assert_eq!(crate::c0::err::str_span(PROG, all[0].span), "a > b");
+
+    // * `==` and `!=` are only swapped, which negates them already
+    let kinds: Vec<_> = mutations("int main() { if (1 == 2) return 1; return 0; }")
+        .unwrap()
+        .into_iter()
+        .map(|m| m.kind)
+        .filter(|k| !matches!(k, MutationKind::OffByOne { .. }))
+        .collect();
+    assert_eq!(
+        kinds,
+        [MutationKind::SwapOperator {
+            from: crate::c0::ast::OpVar::Eq,
+            to: crate::c0::ast::OpVar::Neq
+        }]
+    );
+}
+
+#[test]
+fn test_mutant() {
+    let original = parse_no_panic(PROG).unwrap();
+    for mutation in mutations(PROG).unwrap() {
+        let mutant = mutant(PROG, &mutation).unwrap();
+        assert!(!ast_eq(&original, &mutant.prog), "{}", mutation);
+    }
+
+    let all = mutations(PROG).unwrap();
+    let negated = mutant(PROG, &all[0]).unwrap();
+    assert!(negated.src.contains("if (a <= b)"), "{}", negated.src);
+    let swapped = mutant(PROG, &all[10]).unwrap();
+    assert!(swapped.src.contains("max(i, 1) / 2"), "{}", swapped.src);
+
+    // * Conditions that are not comparisons are compared with 0
+    let src = "int main() { int a = 1; while (a) a = a - 1; return 0; }";
+    let negated = mutant(src, &mutations(src).unwrap()[2]).unwrap();
+    assert!(negated.src.contains("while (a == 0)"), "{}", negated.src);
+}
+
+#[test]
+fn test_mutants_are_caught() {
+    let expected = "2\n2\n4\n";
+    let all = crate::mutants(PROG, 0, 100).unwrap();
+    assert_eq!(all.len(), 18);
+    let survived: Vec<_> = (all.iter())
+        .filter(|m| output(m).as_deref() == Some(expected))
+        .map(|m| m.mutation.to_string())
+        .collect();
+    // * `a > b` and `a >= b` only differ when `a == b`, when both return the
+    // * same value, and the exit code is not checked
+    assert_eq!(
+        survived,
+        [
+            "replace `>` with `>=`",
+            "replace 0 with -1",
+            "replace 0 with 1"
+        ]
+    );
+    assert_eq!(all[17].mutation.span.start.ln, 13);
+}
+
+#[test]
+fn test_sample_is_seeded() {
+    let ids = |seed| -> Vec<usize> {
+        (sample(PROG, seed, 5).unwrap().iter())
+            .map(|m| m.mutation.id)
+            .collect()
+    };
+    assert_eq!(ids(42), ids(42));
+    assert_eq!(ids(42).len(), 5);
+    assert!(ids(42).windows(2).all(|w| w[0] < w[1]));
+    assert_ne!(ids(1), ids(2));
+    assert!(sample("int x;", 0, 5).unwrap().is_empty());
+}
@@ -0,0 +1,100 @@
+//! Literal values are checked against fixed results, so that every big
+//! number backend evaluates constants the same way.
+
+use crate::c0::lexer::*;
+use crate::c0::num;
+
+fn lex_literal(src: &str) -> Literal<'_> {
+    match Lexer::new(src).next().map(|tok| tok.var) {
+        Some(TokenType::Literal(lit)) => lit,
+        tok => panic!("{} lexed to {:?}", src, tok),
+    }
+}
+
+fn int(src: &str) -> Option<i32> {
+    match lex_literal(src) {
+        Literal::Integer(val) => num::to_i32(&val),
+        lit => panic!("{} lexed to {:?}", src, lit),
+    }
+}
+
+fn float(src: &str) -> f64 {
+    match lex_literal(src) {
+        Literal::Float(val) => num::to_f64(&val),
+        lit => panic!("{} lexed to {:?}", src, lit),
+    }
+}
+
+#[test]
+fn test_int_literals() {
+    assert_eq!(int("0"), Some(0));
+    assert_eq!(int("42"), Some(42));
+    assert_eq!(int("0x1F"), Some(31));
+    assert_eq!(int("2147483647"), Some(i32::MAX));
+    assert_eq!(int("0x7fffffff"), Some(i32::MAX));
+    assert_eq!(int("2147483648"), None);
+    assert_eq!(int("0x80000000"), None);
+    assert_eq!(int("123456789012345678901234567890"), None);
+}
+
+#[test]
+fn test_float_literals() {
+    assert_eq!(float("0.1"), 0.1);
+    assert_eq!(float("1.5"), 1.5);
+    assert_eq!(float("2.5e-3"), 0.0025);
+    assert_eq!(float("1e10"), 1e10);
+    assert_eq!(float("1e308"), 1e308);
+    assert_eq!(float("1e309"), f64::INFINITY);
+    assert_eq!(float("0.000001"), 0.000001);
+}
+
+#[test]
+fn test_rational_parts() {
+    let (numer, denom) = num::into_parts(&num::rational(num::pow(10, 3), num::pow(10, 5)));
+    assert_eq!(numer.to_string(), "1");
+    assert_eq!(denom.to_string(), "100");
+    assert_eq!(
+        num::parse_int("ff", 16).map(|i| i.to_string()),
+        Some("255".into())
+    );
+    assert!(num::parse_int("12a", 10).is_none());
+}
+
+#[test]
+fn test_float_rounding() {
+    // * Halfway between 2^53 and the next `f64`, so ties to even
+    assert_eq!(float("9007199254740993.0"), 9007199254740992.0);
+    assert_eq!(float("9007199254740995.0"), 9007199254740996.0);
+    // * Just under halfway, decided by the last of many digits
+    assert_eq!(
+        float("9007199254740992.99999999999999999999999999"),
+        9007199254740992.0
+    );
+    assert_eq!(float("0.30000000000000004"), 0.1 + 0.2);
+    assert_eq!(float("2.2250738585072011e-308"), 2.225073858507201e-308);
+    assert_eq!(float("2.2250738585072014e-308"), f64::MIN_POSITIVE);
+    assert_eq!(float("1.7976931348623157e308"), f64::MAX);
+    assert_eq!(float("1.7976931348623158e308"), f64::MAX);
+    assert_eq!(float("1.7976931348623159e308"), f64::INFINITY);
+    assert_eq!(float("4.9406564584124654e-324"), 5e-324);
+    assert_eq!(float("2.4703282292062328e-324"), 5e-324);
+    assert_eq!(float("2.4703282292062327e-324"), 0.0);
+    assert_eq!(float("1e-400"), 0.0);
+    assert_eq!(
+        float("123456789012345678901234567890e-10"),
+        12345678901234567890.0
+    );
+}
+
+#[test]
+fn test_decimal_parts() {
+    let decimal = |src: &str| match lex_literal(src) {
+        Literal::Float(val) => num::to_decimal(&val).map(|(m, e)| (m.to_string(), e)),
+        lit => panic!("{} lexed to {:?}", src, lit),
+    };
+    assert_eq!(decimal("1.50"), Some(("15".into(), 1)));
+    assert_eq!(decimal("2.5e-3"), Some(("25".into(), 4)));
+    assert_eq!(decimal("1e3"), Some(("1000".into(), 0)));
+    let third = num::rational(num::Int::from(1), num::Int::from(3));
+    assert!(num::to_decimal(&third).is_none());
+}
@@ -0,0 +1,95 @@
+use crate::c0::gen::{generate, GenConfig};
+use crate::c0::interpreter::Interpreter;
+use crate::c0::obfuscate::*;
+use crate::c0::parser::parse_no_panic;
+
+/// Exit code and output of running `src` on the interpreter
+fn run(src: &str) -> (i32, String) {
+    let prog = parse_no_panic(src).unwrap();
+    let mut input = "".as_bytes();
+    let mut output = vec![];
+    let code = (Interpreter::new(&prog, &mut input, &mut output).with_step_limit(10_000_000))
+        .run()
+        .unwrap();
+    (code, String::from_utf8(output).unwrap())
+}
+
+#[test]
+fn test_obfuscate() {
+    let src = "// Sum of factorials
+int total = 0;
+
+int fact(int n) {
+    if (n <= 1) return 1; /* the end */
+    return n * fact(n - 1);
+}
+
+int main() {
+    int i = 0;
+    outer: while (i < 5) {
+        total = total + fact(i);
+        if (total > 20) break outer;
+        i = i + 1;
+    }
+    print(\"total\", total);
+    return 0;
+}
+";
+    let expected = "int v0 = 0;
+
+int v1(int v2) {
+    if (v2 <= 1)
+        return 1;
+    return v2 * v1(v2 - 1);
+}
+
+int main() {
+    int v3 = 0;
+    v4: while (v3 < 5) {
+        v0 = v0 + v1(v3);
+        if (v0 > 20)
+            break v4;
+        v3 = v3 + 1;
+    }
+    print(\"total\", v0);
+    return 0;
+}
+";
+    let obfuscated = obfuscate(src).unwrap();
+    assert_eq!(obfuscated, expected);
+    assert_eq!(run(&obfuscated), run(src));
+}
+
+#[test]
+fn test_obfuscate_shadowing() {
+    // * The first `x` in `main` is the global one, declared again after it
+    let src = "int x = 1;
+int main() {
+    int y = x;
+    int x = 2;
+    {
+        double x = 0.5;
+        print(x, y);
+    }
+    print(x, y);
+    return 0;
+}
+";
+    let obfuscated = obfuscate(src).unwrap();
+    assert!(obfuscated.contains("int v1 = v0;"), "{}", obfuscated);
+    assert!(!obfuscated.contains('x'), "{}", obfuscated);
+    assert_eq!(run(&obfuscated), run(src));
+}
+
+#[test]
+fn test_obfuscate_generated() {
+    for seed in 0..10 {
+        let config = GenConfig {
+            seed,
+            ..GenConfig::default()
+        };
+        let src = generate(&config);
+        let obfuscated = obfuscate(&src).unwrap();
+        assert_eq!(run(&obfuscated), run(&src), "seed {}", seed);
+    }
+}
@@ -1,11 +1,11 @@
 use crate::c0::ast::*;
 use crate::c0::err::*;
-use crate::c0::lexer::Lexer;
+use crate::c0::lexer::{Lexer, TokenType};
 use crate::c0::parser::*;
-use crate::prelude::*;
+use crate::ErrorCode;
 
 fn parse(input: &str) -> ParseResult<Program> {
-    let lexer = Lexer::new(input.chars());
+    let lexer = Lexer::new(input);
     let mut parser = Parser::new(lexer);
 
     parser.parse()
@@ -24,7 +24,7 @@ const double b = 1.0;
 
     let res = parse(input);
 
-    assert!(res.is_ok(), format!("{:#?}", res));
+    assert!(res.is_ok(), "{:#?}", res);
 }
 
 #[test]
@@ -44,7 +44,7 @@ void main(int arg){}
 
     let res = parse(input);
 
-    assert!(res.is_ok(), format!("{:#?}", res));
+    assert!(res.is_ok(), "{:#?}", res);
 }
 
 #[test]
@@ -77,7 +77,7 @@ void main(){
 
     let res = parse(input);
 
-    assert!(res.is_ok(), format!("{:#?}", res));
+    assert!(res.is_ok(), "{:#?}", res);
 }
 
 #[test]
@@ -142,10 +142,7 @@ void main(){
     for input in inputs.iter() {
         let res = parse(input);
 
-        assert!(
-            res.is_err(),
-            format!("'{}' does not result in error!", input)
-        );
+        assert!(res.is_err(), "'{}' does not result in error!", input);
     }
 }
 
@@ -178,9 +175,395 @@ void main()}
     for input in inputs.iter() {
         let res = parse(input);
 
-        assert!(
-            res.is_err(),
-            format!("'{}' does not result in error!", input)
-        );
+        assert!(res.is_err(), "'{}' does not result in error!", input);
     }
 }
+
+#[test]
+fn test_control_flow_in_expr() {
+    let inputs = [
+        r#"
+void main(){
+    int a = if (1) 2; else 3;
+}
+    "#,
+        r#"
+void main(){
+    int a;
+    a = while (a) a = a - 1;
+}
+    "#,
+        r#"
+void main(){
+    int a;
+    a = 1 + { 2 };
+}
+    "#,
+    ];
+
+    for input in inputs.iter() {
+        let res = parse(input);
+
+        match res {
+            Err(ParseError {
+                var: ParseErrVariant::ControlFlowInExpr(..),
+                ..
+            }) => (),
+            _ => panic!(
+                "'{}' does not result in ControlFlowInExpr: {:?}",
+                input, res
+            ),
+        }
+    }
+}
+
+#[test]
+fn test_labeled_loop() {
+    let input = r#"
+void main(){
+    int i = 0, j;
+    outer: while (i < 10) {
+        j = 0;
+        while (j < 10) {
+            if (i * j > 20) break outer;
+            j = j + 1;
+        }
+        i = i + 1;
+    }
+}
+    "#;
+
+    let res = parse(input);
+
+    assert!(res.is_ok(), "{:#?}", res);
+}
+
+#[test]
+fn test_break_with_value() {
+    let input = r#"
+void main(){
+    while (1) break 1;
+}
+    "#;
+
+    let res = parse(input);
+
+    match res {
+        Err(ParseError {
+            var: ParseErrVariant::BreakWithValue,
+            ..
+        }) => (),
+        _ => panic!("break with value does not result in error: {:?}", res),
+    }
+}
+
+#[test]
+fn test_long_else_if_chain() {
+    let mut input = String::from("void main(){\n    int a = 0;\n    if (a == 0) a = 1;\n");
+    for i in 1..500 {
+        input.push_str(&format!("    else if (a == {}) a = {};\n", i, i + 1));
+    }
+    input.push_str("    else a = 0;\n}\n");
+
+    let res = parse(&input);
+
+    assert!(res.is_ok(), "{:#?}", res);
+}
+
+#[test]
+fn test_deeply_nested_parens() {
+    let depth = 100_000;
+    let input = format!(
+        "void main(){{\n    int a = {}1{};\n}}\n",
+        "(".repeat(depth),
+        ")".repeat(depth)
+    );
+
+    let res = parse(&input);
+
+    assert!(res.is_ok(), "{:#?}", res.map(|_| ()));
+}
+
+#[test]
+fn test_deeply_nested_stmts() {
+    let depth = 100_000;
+    let input = format!(
+        "void main(){{\n    int a = 1;\n    {}a = 2;{}\n}}\n",
+        "if (1) while (0) { ".repeat(depth),
+        " }".repeat(depth)
+    );
+
+    let res = parse(&input);
+
+    assert!(res.is_ok(), "{:#?}", res.map(|_| ()));
+
+    // * Blocks never closed
+    let input = format!("int main() {}", "{".repeat(depth));
+    assert!(parse(&input).is_err());
+}
+
+#[test]
+fn test_parse_no_panic() {
+    let inputs = [
+        "'",
+        "int a = '",
+        "int a = 'a",
+        "double a = 1e999999999;",
+        "double a = 1e-2147483648;",
+        "void main(){ outer: }",
+        "void main(){ break }",
+        "int main(){ return ((((1); }",
+        "\0",
+        "int é = 1;",
+    ];
+    for input in inputs.iter() {
+        let res = crate::c0::parse_no_panic(input);
+        if let Err(ParseError {
+            var: ParseErrVariant::InternalErr(..),
+            ..
+        }) = res
+        {
+            panic!("'{}' panicked inside the parser: {:?}", input, res)
+        }
+    }
+}
+
+#[test]
+fn test_negative_literals() {
+    let prog =
+        parse("const int a = -2147483648, b = -(-5), c = - -a, d = -(-a), e = -(+1), f = 3 - -2;")
+            .unwrap();
+    let init = |name: &str| -> String {
+        let def = prog.blk.scope.borrow().find_def_self(name).unwrap();
+        let def = def.borrow();
+        match &*def {
+            SymbolDef::Var {
+                value: Some(value), ..
+            } => format!("{:?}", value.borrow().var),
+            _ => unreachable!(),
+        }
+    };
+
+    assert_eq!(init("a"), "-2147483648");
+    assert_eq!(init("b"), "5");
+    assert_eq!(init("c"), "(Neg (Neg Identifier(a)))");
+    assert_eq!(init("d"), init("c"));
+    assert_eq!(init("e"), "(Neg (Pos 1))");
+    assert_eq!(init("f"), "(Sub 3 -2)");
+}
+
+#[test]
+fn test_logic_precedence() {
+    let prog = parse("const int a = 1, b = a != 0 && a / 2 > 1 || a == 2;").unwrap();
+    let def = prog.blk.scope.borrow().find_def_self("b").unwrap();
+    let def = def.borrow();
+    let init = match &*def {
+        SymbolDef::Var {
+            value: Some(value), ..
+        } => format!("{:?}", value.borrow().var),
+        _ => unreachable!(),
+    };
+
+    assert_eq!(
+        init,
+        "(Or (And (Neq Identifier(a) 0) (Gt (Div Identifier(a) 2) 1)) (Eq Identifier(a) 2))"
+    );
+}
+
+#[test]
+fn test_many_declarators() {
+    let prog = parse("int a = 1, b, c = (a = 2, a + 1);").unwrap();
+    let scope = prog.blk.scope.borrow();
+    let decl = |name: &str| match &*scope.find_def_self(name).unwrap().borrow() {
+        SymbolDef::Var { decl_span, .. } => (decl_span.start.pos, decl_span.end.pos),
+        _ => unreachable!(),
+    };
+    assert_eq!(decl("a"), (4, 9));
+    assert_eq!(decl("b"), (11, 12));
+    assert_eq!(decl("c"), (14, 31));
+    match &prog.blk.stmts[0].var {
+        StmtVariant::ManyExpr(inits) => {
+            assert_eq!(inits.len(), 2);
+            assert_eq!(
+                format!("{:?}", inits[1].borrow().var),
+                "(_Asn Identifier(c) (_Com (_Asn Identifier(a) 2) (Add Identifier(a) 1)))"
+            );
+        }
+        other => panic!("{:?}", other),
+    }
+
+    let err = parse("int a = 1, b = 2, a = 3;").unwrap_err();
+    assert_eq!(err.span.start.pos, 18);
+}
+
+#[test]
+fn test_parameter_redefinition() {
+    let err = parse("int f(int a, double a) { return 0; }").unwrap_err();
+    assert_eq!(err.var.code(), ErrorCode(211));
+    assert_eq!(err.span.start.pos, 20);
+
+    let err = parse("int f(int a) {\n    int a = 1;\n    return a;\n}").unwrap_err();
+    assert_eq!(err.var.code(), ErrorCode(212));
+    assert_eq!((err.span.start.ln, err.span.start.pos), (1, 8));
+
+    parse("int f(int a) {\n    {\n        int a = 1;\n    }\n    return a;\n}").unwrap();
+    parse("int f(int a) { return a; }\nint g(int b) { int a = b; return a; }").unwrap();
+}
+
+#[test]
+fn test_function_redeclaration_span() {
+    let err = parse("int x;\nint x() { return 1; }").unwrap_err();
+    assert_eq!(err.var.code(), ErrorCode(210));
+    assert_eq!((err.span.start.ln, err.span.start.pos), (1, 4));
+}
+
+#[test]
+fn test_auto_declaration() {
+    let prog = parse("auto a = 1, b;").unwrap();
+    let scope = prog.blk.scope.borrow();
+    for name in ["a", "b"] {
+        match &*scope.find_def_self(name).unwrap().borrow() {
+            SymbolDef::Var { typ, is_const, .. } => {
+                assert_eq!(*typ.borrow(), TypeDef::Unknown);
+                assert!(!is_const);
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    let err = parse("auto f() { return 1; }").unwrap_err();
+    assert_eq!(err.var.code(), ErrorCode(213));
+}
+
+#[test]
+fn test_call_names_its_function() {
+    let prog = parse("int f() { return 1; }\nint main() {\n    { return f(); }\n}").unwrap();
+    let main = prog.blk.scope.borrow().find_def_self("main").unwrap();
+    let main = main.borrow();
+    let typ = match &*main {
+        SymbolDef::Var { typ, .. } => typ.borrow(),
+        _ => unreachable!(),
+    };
+    let body = match &*typ {
+        TypeDef::Function(func) => func.body.as_ref().unwrap(),
+        _ => unreachable!(),
+    };
+    let inner = match &body.stmts[0].var {
+        StmtVariant::Block(b) => b,
+        other => panic!("{:?}", other),
+    };
+    let call = match &inner.stmts[0].var {
+        StmtVariant::Return(Some(e)) => match &e.borrow().var {
+            ExprVariant::FunctionCall(call) => call.clone(),
+            other => panic!("{:?}", other),
+        },
+        other => panic!("{:?}", other),
+    };
+    let f = prog.blk.scope.borrow().find_def_self("f").unwrap();
+    assert_eq!(call.def(&inner.scope).unwrap(), f);
+
+    let missing = FunctionCall {
+        func: "g".into(),
+        params: vec![],
+    };
+    assert!(missing.def(&inner.scope).is_none());
+}
+
+#[test]
+fn test_doc_comments() {
+    let prog = parse(
+        "/// The answer.
+///
+///   Computed slowly.
+const int answer = 42;
+
+// Not documented
+int plain;
+
+/// Where it starts
+int main() {
+    /// Local
+    int x = answer;
+    /// Not on a declaration, so left out
+    print(x);
+    return 0;
+}
+/// At the end",
+    )
+    .unwrap();
+    let scope = prog.blk.scope.borrow();
+    let doc = |scope: &Scope, name: &str| match &*scope.find_def_self(name).unwrap().borrow() {
+        SymbolDef::Var { doc, .. } => doc.clone(),
+        _ => unreachable!(),
+    };
+    assert_eq!(
+        doc(&scope, "answer").as_deref(),
+        Some("The answer.\n\n  Computed slowly.")
+    );
+    assert_eq!(doc(&scope, "plain"), None);
+    assert_eq!(doc(&scope, "main").as_deref(), Some("Where it starts"));
+
+    let main = scope.find_def_self("main").unwrap();
+    let main = main.borrow();
+    let body = match &*main {
+        SymbolDef::Var { typ, .. } => match &*typ.borrow() {
+            TypeDef::Function(f) => f.body.as_ref().unwrap().scope.cp(),
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
+    };
+    assert_eq!(doc(&body.borrow(), "x").as_deref(), Some("Local"));
+}
+
+#[test]
+fn test_unclosed_delimiter() {
+    let unclosed = |input: &str| match parse(input) {
+        Err(ParseError {
+            var: ParseErrVariant::UnclosedDelimiter(open, open_span),
+            span,
+            fix,
+            ..
+        }) => (
+            open,
+            open_span.start.ln,
+            open_span.start.pos,
+            span.start,
+            fix,
+        ),
+        res => panic!(
+            "'{}' does not result in UnclosedDelimiter: {:?}",
+            input, res
+        ),
+    };
+
+    // * The `}` of the `if` is missing, and the `}` meant for `f` closes it
+    let src = "int f(int x) {\n    if (x > 0) {\n        return 1;\n    return 0;\n}\nint main() {\n    return f(1);\n}\n";
+    let (open, ln, pos, at, fix) = unclosed(src);
+    assert_eq!((open, ln, pos), (TokenType::LCurlyBrace.to_string(), 0, 13));
+    assert_eq!((at.ln, at.pos), (7, 1));
+    assert!(fix.is_none());
+
+    let (open, ln, pos, at, fix) = unclosed("int main() {\n    print((1 + 2);\n}\n");
+    assert_eq!((open, ln, pos), (TokenType::LParenthesis.to_string(), 1, 9));
+    assert_eq!((at.ln, at.pos), (1, 17));
+    let fix = fix.unwrap();
+    assert_eq!((fix.span.start.pos, fix.replacement.as_str()), (17, ")"));
+
+    let (open, ln, _, at, _) = unclosed("int main() {\n    if (1 > 2 {\n    }\n}\n");
+    assert_eq!(
+        (open, ln, at.ln, at.pos),
+        (TokenType::LParenthesis.to_string(), 1, 1, 14)
+    );
+
+    // * A delimiter opened after the error does not explain it
+    match parse("int main() {\n    retur 0;\n    print((1);\n}\n") {
+        Err(ParseError {
+            var: ParseErrVariant::CannotFindIdent(..),
+            ..
+        }) => (),
+        res => panic!("unexpected result: {:?}", res),
+    }
+    let err = crate::parse("int main() { print(1; }").unwrap_err();
+    assert_eq!(err.code, ErrorCode(111));
+    assert_eq!(err.notes[0].message, "unclosed delimiter opened here");
+}
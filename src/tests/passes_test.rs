@@ -0,0 +1,268 @@
+use super::exec;
+use crate::c0::ast;
+use crate::minivm::passes::{compile_order, CompilerPass, PassError, PassKind, START};
+use crate::minivm::*;
+use crate::parse;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const SRC: &str = "int g = 1 + 2;
+int sq(int x) { return x * x; }
+int main() {
+    int i = 0;
+    int s = 0;
+    while (i < 4) {
+        s = s + sq(i) + sq(i);
+        i = i + 1;
+    }
+    print(s, g);
+    return 0;
+}
+";
+
+fn compile_with(passes: PassManager) -> O0 {
+    Codegen::new(&parse(SRC).unwrap())
+        .with_passes(passes)
+        .compile()
+        .unwrap()
+}
+
+fn compile(opt: OptFilter) -> O0 {
+    Codegen::new(&parse(SRC).unwrap())
+        .with_opt_filter(opt)
+        .compile()
+        .unwrap()
+}
+
+fn unoptimized() -> O0 {
+    Codegen::new(&parse(SRC).unwrap())
+        .with_peephole(false)
+        .with_cse(false)
+        .with_unroll(0, 0)
+        .compile()
+        .unwrap()
+}
+
+/// Code of start code and each function
+fn code(o0: &O0) -> Vec<Vec<Inst>> {
+    let fns = o0.functions.iter().map(|f| f.ins.clone());
+    std::iter::once(o0.start_code.ins.clone())
+        .chain(fns)
+        .collect()
+}
+
+#[test]
+fn test_compile_order() {
+    let order = compile_order(&parse(SRC).unwrap());
+    assert_eq!(order, vec![START, "sq", "main"]);
+    for pass in Pass::ALL.iter() {
+        assert_eq!(Pass::from_name(pass.name()), Some(*pass));
+    }
+    assert_eq!(Pass::from_name("licm"), None);
+}
+
+#[test]
+fn test_opt_bisect() {
+    let opt = code(&compile(OptFilter::default()));
+    let plain = code(&unoptimized());
+    assert_ne!(opt, plain);
+
+    for n in 0..=3 {
+        let limit = OptFilter {
+            limit: Some(n),
+            ..OptFilter::default()
+        };
+        let code = code(&compile(limit));
+        for (idx, f) in code.iter().enumerate() {
+            match idx < n {
+                true => assert_eq!(f, &opt[idx], "{} of {}", idx, n),
+                false => assert_eq!(f, &plain[idx], "{} of {}", idx, n),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_opt_functions_and_passes() {
+    let opt = code(&compile(OptFilter::default()));
+    let plain = code(&unoptimized());
+    let only_main = OptFilter {
+        functions: Some(vec!["main".into()]),
+        ..OptFilter::default()
+    };
+    let code_main = code(&compile(only_main));
+    assert_eq!(code_main[..2], plain[..2]);
+    assert_eq!(code_main[2], opt[2]);
+
+    let no_cse = OptFilter {
+        passes: vec![
+            Pass::Unroll,
+            Pass::Layout,
+            Pass::Fold,
+            Pass::Dce,
+            Pass::Peephole,
+        ],
+        ..OptFilter::default()
+    };
+    let o0 = Codegen::new(&parse(SRC).unwrap())
+        .with_cse(false)
+        .compile()
+        .unwrap();
+    assert_eq!(code(&compile(no_cse)), code(&o0));
+}
+
+#[test]
+fn test_pass_manager() {
+    assert_eq!(PassManager::for_level(0).unwrap().pipeline(), &[]);
+    let o1 = PassManager::for_level(1).unwrap();
+    assert!(!o1.runs(Pass::Gvn) && o1.runs(Pass::Cse));
+    assert_eq!(PassManager::for_level(2).unwrap().pipeline(), &Pass::ALL);
+    assert!(PassManager::for_level(3).is_none());
+
+    let pm = PassManager::new();
+    assert_eq!(pm.get("alias").unwrap().kind, PassKind::Analysis);
+    assert_eq!(
+        pm.get("cse").unwrap().requires,
+        vec![Pass::Alias, Pass::Purity]
+    );
+    let names: Vec<_> = pm.passes().iter().map(|p| p.name()).collect();
+    assert_eq!(names.len(), Pass::ALL.len());
+
+    // * Analyses needed come along, and passes run in their own order
+    let pm = pm.with_pipeline("dce, cse,fold").unwrap();
+    assert_eq!(
+        pm.pipeline(),
+        &[Pass::Alias, Pass::Purity, Pass::Cse, Pass::Fold, Pass::Dce]
+    );
+    assert!(pm.runs(Pass::Fold) && !pm.runs(Pass::Peephole));
+    let names = PassManager::new().names();
+    assert_eq!(
+        PassManager::new().with_pipeline("fold,licm").err(),
+        Some(PassError::Unknown("licm".into(), names))
+    );
+}
+
+#[test]
+fn test_pipelines() {
+    let code_of = |names: &str| {
+        code(&compile_with(
+            PassManager::new().with_pipeline(names).unwrap(),
+        ))
+    };
+    assert_eq!(
+        code(&compile_with(PassManager::default())),
+        code(&compile(OptFilter::default()))
+    );
+    assert_eq!(code_of(""), code(&unoptimized()));
+
+    // * `g = 1 + 2` is computed when compiling, with nothing else changed
+    let folded = code_of("fold");
+    assert!(folded[0].contains(&Inst::IPush(3)));
+    assert!(!folded[0].contains(&Inst::IAdd));
+    assert_eq!(folded[1..], code(&unoptimized())[1..]);
+
+    let all = code_of("cse,unroll,inline,layout,fold,dce,peephole");
+    assert_eq!(all, code(&compile_with(PassManager::default())));
+}
+
+/// Puts `nop` before the first instruction of every loop, and records the
+/// functions it saw
+#[derive(Default)]
+struct LoopNops(Rc<RefCell<Vec<String>>>);
+
+impl CompilerPass for LoopNops {
+    fn name(&self) -> &str {
+        "loop-nops"
+    }
+
+    fn run_on_function(&self, name: &str, code: &mut InstSink) -> Result<(), String> {
+        self.0.borrow_mut().push(name.into());
+        let mut heads: Vec<_> = (code.inner().iter().enumerate())
+            .filter_map(|(at, inst)| jump_target(inst).filter(|to| (*to as usize) < at))
+            .collect();
+        heads.sort_unstable();
+        heads.dedup();
+        for at in heads.into_iter().rev() {
+            code.insert_before(at as usize, &[Inst::Nop]);
+        }
+        Ok(())
+    }
+}
+
+/// Pops what is not there
+struct Underflow;
+
+impl CompilerPass for Underflow {
+    fn name(&self) -> &str {
+        "underflow"
+    }
+
+    fn run_on_program(&self, prog: &mut ast::Program) -> Result<(), String> {
+        let scope = prog.blk.scope.borrow();
+        match scope.defs.iter().any(|(name, _)| name == "g") {
+            true => Ok(()),
+            false => Err("no `g`".into()),
+        }
+    }
+
+    fn run_on_function(&self, _: &str, code: &mut InstSink) -> Result<(), String> {
+        code.insert_before(0, &[Inst::Pop1]);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_custom_pass() {
+    let seen = LoopNops::default();
+    let names = seen.0.clone();
+    let pm = PassManager::default().with_pass(seen).unwrap();
+    assert_eq!(pm.names().last().unwrap(), "loop-nops");
+    let o0 = compile_with(pm);
+    assert_eq!(*names.borrow(), vec![START, "sq", "main"]);
+
+    // * Jumps back go to the `nop`, and the loop runs as before
+    let main = &o0.functions[1].ins;
+    let nops: Vec<_> = (main.iter().enumerate())
+        .filter(|(_, inst)| **inst == Inst::Nop)
+        .map(|(at, _)| at as u16)
+        .collect();
+    assert!(!nops.is_empty());
+    for to in main.iter().filter_map(jump_target) {
+        assert!(nops.contains(&to) || main[to as usize - 1] != Inst::Nop);
+    }
+    assert_eq!(exec(&o0, "").1, exec(&compile(OptFilter::default()), "").1);
+
+    // * Left out of the pipeline, it doesn't run
+    let pm = PassManager::default()
+        .with_pass(LoopNops::default())
+        .unwrap();
+    let o0 = compile_with(pm.with_pipeline("fold").unwrap());
+    assert!(!o0.functions[1].ins.contains(&Inst::Nop));
+
+    let twice = PassManager::new()
+        .with_pass(Underflow)
+        .unwrap()
+        .with_pass(Underflow);
+    assert_eq!(twice.err(), Some(PassError::Duplicate("underflow".into())));
+    let renamed = PassManager::new().with_pass(LoopNops::default()).unwrap();
+    assert!(renamed.with_pipeline("loop-nops,fold").is_ok());
+}
+
+#[test]
+fn test_broken_custom_pass() {
+    let pm = PassManager::new().with_pass(Underflow).unwrap();
+    let err = Codegen::new(&parse(SRC).unwrap())
+        .with_passes(pm.clone())
+        .compile()
+        .unwrap_err();
+    assert!(
+        matches!(err.var, CompileErrorVar::InternalError(_)),
+        "{}",
+        err
+    );
+
+    let mut prog = parse("int main() { return 0; }").unwrap();
+    let err = pm.run_on_program(&mut prog).unwrap_err();
+    assert_eq!(err.to_string(), "Pass `underflow` failed: no `g`");
+    assert!(pm.run_on_program(&mut parse(SRC).unwrap()).is_ok());
+}
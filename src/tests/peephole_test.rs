@@ -0,0 +1,114 @@
+use super::exec;
+use crate::minivm::*;
+use crate::parse;
+
+fn compile(src: &str, peephole: bool) -> O0 {
+    let prog = parse(src).unwrap();
+    Codegen::new(&prog)
+        .with_peephole(peephole)
+        .with_debug_info(true)
+        .compile()
+        .unwrap()
+}
+
+fn main_ins(o0: &O0) -> &[Inst] {
+    &o0.functions.last().unwrap().ins
+}
+
+#[test]
+fn test_peephole_folds_constants() {
+    let src = "int main() {\n    return 1 + 2 * 3 - 4 / 2;\n}\n";
+    let ins = main_ins(&compile(src, true)).to_vec();
+    assert!(ins.contains(&Inst::IPush(5)), "{:?}", ins);
+    assert!(!ins
+        .iter()
+        .any(|i| matches!(i, Inst::IAdd | Inst::ISub | Inst::IMul | Inst::IDiv)));
+
+    let ins = main_ins(&compile(src, false)).to_vec();
+    assert!(ins.contains(&Inst::IMul), "{:?}", ins);
+
+    // * The VM reports division by zero when it runs
+    let ins = main_ins(&compile("int main() { return 1 / 0; }", true)).to_vec();
+    assert!(ins.contains(&Inst::IDiv), "{:?}", ins);
+}
+
+#[test]
+fn test_peephole_jumps() {
+    let src = include_str!("../../tests/cases/fib.c0");
+    let opt = compile(src, true);
+    let plain = compile(src, false);
+    assert!(main_ins(&opt).len() < main_ins(&plain).len());
+
+    for f in &opt.functions {
+        for (idx, inst) in f.ins.iter().enumerate() {
+            if let Inst::Jmp(to) | Inst::JNe(to) = inst {
+                assert_ne!(*to as usize, idx + 1, "{:?}", f.ins);
+                assert!(!matches!(f.ins[*to as usize], Inst::Jmp(_)), "{:?}", f.ins);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_peephole_keeps_debug_lines() {
+    let o0 = compile(include_str!("../../tests/cases/fib.c0"), true);
+    let debug = o0.debug.as_ref().unwrap();
+    assert_eq!(debug.start_lines.len(), o0.start_code.ins.len());
+    for (f, lines) in o0.functions.iter().zip(&debug.fn_lines) {
+        assert_eq!(f.ins.len(), lines.len());
+    }
+    assert_eq!(verify(&o0), Ok(()));
+}
+
+#[test]
+fn test_peephole_known_conditions() {
+    // * `while (1)` tests nothing, and neither does a loop on entry when
+    // * its counter was just set
+    let src = "int main() {\n    int i = 0;\n    while (i < 3) {\n        \
+               while (1) {\n            i = i + 1;\n            break;\n        }\n    \
+               }\n    print(i);\n    return 0;\n}\n";
+    let o0 = compile(src, true);
+    let ins = main_ins(&o0);
+    let conds = ins
+        .iter()
+        .filter(|i| matches!(i, Inst::JE(_) | Inst::JNe(_)));
+    assert_eq!(conds.count(), 1, "{:?}", ins);
+    assert_eq!(exec(&o0, "").1, "3\n");
+
+    // * A flag set on both branches is never tested
+    let src = "int main() {\n    int f = 0;\n    int n;\n    scan(n);\n    \
+               if (n > 0) {\n        f = 1;\n    } else {\n        f = 2;\n    }\n    \
+               if (f == 1)\n        print(1);\n    else\n        print(2);\n    \
+               return 0;\n}\n";
+    let o0 = compile(src, true);
+    let ins = main_ins(&o0);
+    let loads_f = ins
+        .windows(2)
+        .filter(|w| w == &[Inst::LoadA(0, 0), Inst::ILoad]);
+    assert_eq!(loads_f.count(), 0, "{:?}", ins);
+    assert_eq!(
+        (exec(&o0, "5").1, exec(&o0, "-5").1),
+        ("1\n".into(), "2\n".into())
+    );
+    assert_eq!(verify(&o0), Ok(()));
+}
+
+#[test]
+fn test_branch_layout() {
+    // * The loop body falls through from the test, and the empty block
+    // * after `if` is skipped
+    let src = include_str!("../../tests/cases/loops.c0");
+    let opt = compile(src, true);
+    let plain = compile(src, false);
+    let jumps = |o0: &O0| {
+        let ins = main_ins(o0);
+        ins.iter().filter(|i| jump_target(i).is_some()).count()
+    };
+    assert!(jumps(&opt) < jumps(&plain), "{:?}", main_ins(&opt));
+    assert_eq!(exec(&opt, "").1, exec(&plain, "").1);
+    for (idx, inst) in main_ins(&opt).iter().enumerate() {
+        if let Some(to) = jump_target(inst) {
+            assert_ne!(to as usize, idx + 1);
+        }
+    }
+}
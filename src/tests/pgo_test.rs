@@ -0,0 +1,99 @@
+use crate::minivm::vm::MiniVM;
+use crate::minivm::*;
+use crate::parse;
+
+const SRC: &str = "int sq(int x) { return x * x; }
+int main() {
+    int i = 0;
+    int s = 0;
+    int n;
+    scan(n);
+    while (i < n) {
+        if (i - i / 10 * 10 == 9) {
+            s = s - 1;
+        } else {
+            s = s + sq(i);
+        }
+        i = i + 1;
+    }
+    print(s);
+    return 0;
+}
+";
+
+fn compile(profile: Option<ExecProfile>) -> O0 {
+    Codegen::new(&parse(SRC).unwrap())
+        .with_debug_info(true)
+        .with_profile(profile)
+        .compile()
+        .unwrap()
+}
+
+/// Output of running `o0` on `input`, instructions run, and the profile
+fn run(o0: &O0, input: &str) -> (String, u64, ExecProfile) {
+    let mut input = input.as_bytes();
+    let mut output = vec![];
+    let mut vm = MiniVM::new(o0, &mut input, &mut output)
+        .with_coverage()
+        .with_profile();
+    vm.run().unwrap();
+    let total = vm.profile().unwrap().total();
+    let profile = ExecProfile::new(o0, vm.coverage().unwrap()).unwrap();
+    drop(vm);
+    (String::from_utf8(output).unwrap(), total, profile)
+}
+
+fn calls(o0: &O0) -> usize {
+    let main = o0.functions.last().unwrap();
+    main.ins
+        .iter()
+        .filter(|i| matches!(i, Inst::Call(_)))
+        .count()
+}
+
+#[test]
+fn test_block_counts() {
+    let (out, _, profile) = run(&compile(None), "100");
+    assert_eq!(out, "290930\n");
+    assert_eq!(profile.calls("sq"), 90);
+    assert_eq!(profile.calls("main"), 1);
+    let blocks = &profile.functions["main"].blocks;
+    for count in [1, 100, 90, 10] {
+        assert!(blocks.contains(&count), "{:?}", blocks);
+    }
+
+    let json = profile.to_json();
+    assert_eq!(ExecProfile::from_json(&json).unwrap(), profile);
+    assert!(ExecProfile::from_json("{}").is_err());
+}
+
+#[test]
+fn test_profile_use() {
+    let plain = compile(None);
+    let (out, insts, profile) = run(&plain, "100");
+    let pgo = compile(Some(profile));
+    assert_eq!((calls(&plain), calls(&pgo)), (1, 0));
+
+    let (pgo_out, pgo_insts, _) = run(&pgo, "100");
+    assert_eq!(pgo_out, out);
+    assert!(pgo_insts < insts, "{} >= {}", pgo_insts, insts);
+    // * Other input still gets the same output
+    assert_eq!(run(&pgo, "37").0, run(&plain, "37").0);
+}
+
+#[test]
+fn test_cold_profile() {
+    // * `sq` is called too few times to inline, and blocks only fall
+    // * through differently
+    let plain = compile(None);
+    let (_, _, profile) = run(&plain, "3");
+    let pgo = compile(Some(profile));
+    assert_eq!(calls(&pgo), 1);
+    assert_eq!(run(&pgo, "25").0, run(&plain, "25").0);
+
+    // * Counts of a function whose blocks changed are not used
+    let mut stale = run(&plain, "3").2;
+    stale.functions.get_mut("main").unwrap().blocks.push(0);
+    let main = |o0: &O0| o0.functions.last().unwrap().ins.clone();
+    assert_eq!(main(&compile(Some(stale))), main(&plain));
+}
@@ -0,0 +1,61 @@
+use crate::playground::{compile_to_json, Runner};
+use serde_json::Value;
+
+fn compile(src: &str) -> Value {
+    serde_json::from_str(&compile_to_json(src)).unwrap()
+}
+
+#[test]
+fn test_compile_to_json() {
+    let res = compile("int main() { print(1 + 2); return 0; }");
+    assert_eq!(res["ok"], true);
+    assert!(res["assembly"].as_str().unwrap().contains(".functions:"));
+    assert!(!res["binary"].as_array().unwrap().is_empty());
+}
+
+#[test]
+fn test_compile_to_json_errors() {
+    let res = compile("int main() {\n    return 99999999999;\n}");
+    assert_eq!(res["ok"], false);
+    assert_eq!(res["error"]["kind"], "compile");
+    assert_eq!(res["error"]["code"], "E0319");
+    assert_eq!(res["error"]["span"]["start"]["line"], 1);
+
+    let res = compile("int main() {");
+    assert_eq!(res["ok"], false);
+    assert_eq!(res["error"]["kind"], "parse");
+}
+
+/// Run `src` on `input` in slices of `steps` instructions, returning the
+/// last result and all output
+fn run_sliced(src: &str, input: &str, steps: u32) -> (Value, String) {
+    let res = compile(src);
+    let binary: Vec<u8> = (res["binary"].as_array().unwrap().iter())
+        .map(|b| b.as_u64().unwrap() as u8)
+        .collect();
+    let mut runner = Runner::new(&binary, input).unwrap();
+    let mut output = String::new();
+    loop {
+        let res: Value = serde_json::from_str(&runner.run_steps(steps)).unwrap();
+        output += res["output"].as_str().unwrap();
+        if res["state"] != "paused" {
+            return (res, output);
+        }
+    }
+}
+
+#[test]
+fn test_runner() {
+    let src = "int main() {\n    int n;\n    int i = 0;\n    scan(n);\n    while (i < n) {\n        print(i);\n        i = i + 1;\n    }\n    return n;\n}\n";
+    let (res, output) = run_sliced(src, "5\n", 7);
+    assert_eq!(res["state"], "finished");
+    assert_eq!(res["code"], 5);
+    assert_eq!(output, "0\n1\n2\n3\n4\n");
+    assert_eq!(run_sliced(src, "5\n", 1_000_000).1, output);
+
+    let (res, _) = run_sliced("int main() {\n    return 1 / 0;\n}\n", "", 7);
+    assert_eq!(res["state"], "trapped");
+    assert_eq!(res["error"], "Integer division by zero");
+
+    assert!(Runner::new(b"not a binary", "").is_err());
+}
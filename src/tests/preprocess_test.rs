@@ -0,0 +1,152 @@
+use super::exec;
+use crate::c0::lexer::{Lexer, Token};
+use crate::minivm::*;
+use crate::{parse, preprocess, preprocess_mapped, ErrorCode};
+
+const SRC: &str = "int main() {
+    int a;
+    int b;
+#if EXT
+    a = b = 3;
+#else
+    b = 3;
+    a = b;
+#endif
+    print(a, b);
+    return 0;
+}
+";
+
+/// Output of compiling `src` with `features` for `standard`, and running it
+fn run(src: &str, features: &[&str], standard: Standard) -> String {
+    let src = preprocess(src, features).unwrap();
+    let o0 = Codegen::new(&parse(&src).unwrap())
+        .with_standard(standard)
+        .compile()
+        .unwrap();
+    exec(&o0, "").1
+}
+
+#[test]
+fn test_features() {
+    assert_eq!(run(SRC, &[], Standard::C0), "3 3\n");
+    assert_eq!(run(SRC, &["EXT"], Standard::C0_EXT), "3 3\n");
+    // * Plain C0 has no assignments as values, so only the lines without
+    // * `EXT` compile
+    let ext = preprocess(SRC, &["EXT"]).unwrap();
+    let err = Codegen::new(&parse(&ext).unwrap()).compile().unwrap_err();
+    assert_eq!(err.span.unwrap().start.ln, 4);
+
+    // * Tokens left are where they are in the source
+    let tokens: Vec<Token> = Lexer::new(&ext).collect();
+    let print = tokens.iter().find(|t| t.span.start.ln == 9).unwrap();
+    assert_eq!(&SRC[print.span.start.index..print.span.end.index], "print");
+    assert_eq!(ext.lines().count(), SRC.lines().count());
+
+    let plain = "int main() { return 0; }\n";
+    assert!(matches!(
+        preprocess(plain, &["EXT"]),
+        Ok(std::borrow::Cow::Borrowed(_))
+    ));
+}
+
+#[test]
+fn test_nested() {
+    let src = "int main() {
+    #if A
+        #if B
+            print(1);
+        #else // not B
+            print(2);
+        #endif
+    #else
+        #if 0
+            print(3);
+        #endif
+        print(4);
+    #endif
+    return 0;
+}
+";
+    assert_eq!(run(src, &["A", "B"], Standard::C0), "1\n");
+    assert_eq!(run(src, &["A"], Standard::C0), "2\n");
+    assert_eq!(run(src, &["B"], Standard::C0), "4\n");
+}
+
+#[test]
+fn test_bad_directives() {
+    let code = |src: &str| {
+        let err = preprocess(src, &[] as &[&str]).unwrap_err();
+        (err.code, err.span.unwrap().start.ln)
+    };
+    assert_eq!(code("int a;\n#else\n"), (ErrorCode(113), 1));
+    assert_eq!(code("#if A\n#endif\n#endif\n"), (ErrorCode(113), 2));
+    assert_eq!(code("#if A\n#else\n#else\n#endif\n"), (ErrorCode(113), 2));
+    assert_eq!(code("int a;\n  #if A\nint b;\n"), (ErrorCode(114), 1));
+    assert_eq!(code("#if\n#endif\n"), (ErrorCode(115), 0));
+    assert_eq!(code("#if A B\n#endif\n"), (ErrorCode(115), 0));
+    assert_eq!(code("#if A\n#endif A\n"), (ErrorCode(116), 1));
+
+    let err = preprocess("int a;\n  #if A\n", &["A"]).unwrap_err();
+    let span = err.span.unwrap();
+    assert_eq!((span.start.pos, span.end.pos), (2, 7));
+}
+
+#[test]
+fn test_hash_lines() {
+    let src = "/*
+# Notes
+#if A
+*/
+int main() {
+    print(\"/*\");
+#if A
+    print(1);
+#endif
+    return 0;
+}
+";
+    assert_eq!(run(src, &["A"], Standard::C0), "/*\n1\n");
+    assert_eq!(run(src, &[], Standard::C0), "/*\n");
+
+    // * Other lines starting with `#` are for the lexer to reject
+    let src = "#include <a>\nint main() { return 0; }\n";
+    let kept = preprocess(src, &[] as &[&str]).unwrap();
+    assert_eq!(kept, src);
+    assert!(parse(&kept).is_err());
+}
+
+#[test]
+fn test_source_map() {
+    let src = "int main() {
+#if A
+    #if B
+        print(1);
+    #else
+        print(c);
+    #endif
+#endif
+    return 0;
+}
+";
+    let (src, map) = preprocess_mapped(src, &["A"]).unwrap();
+    let err = map.annotate(parse(&src).unwrap_err());
+    let notes: Vec<_> = (err.notes.iter())
+        .filter_map(|n| Some((n.span?.start.ln, n.message.as_str())))
+        .collect();
+    assert_eq!(
+        notes,
+        vec![
+            (2, "compiled as `#if B` does not hold"),
+            (1, "compiled as `#if A` holds")
+        ]
+    );
+
+    let line = |ln| crate::Span::from(crate::Pos::new(ln, 0, 0), crate::Pos::new(ln, 0, 0));
+    // * Lines left out are only under `A`, and its `#endif` under nothing
+    assert_eq!(map.notes(line(0)).len(), 0);
+    assert_eq!(map.notes(line(3)).len(), 1);
+    assert_eq!(map.notes(line(7)).len(), 0);
+    let (_, map) = preprocess_mapped("int main() { return 0; }\n", &["A"]).unwrap();
+    assert!(map.notes(line(0)).is_empty());
+}
@@ -0,0 +1,631 @@
+use crate::c0::ast::*;
+use crate::c0::err::*;
+use crate::c0::lexer::Lexer;
+use crate::c0::parser::*;
+use crate::c0::pretty::*;
+use crate::c0::validate::validate;
+use proptest::prelude::*;
+use std::fmt::Write;
+
+fn parse(input: &str) -> ParseResult<Program> {
+    let lexer = Lexer::new(input);
+    let mut parser = Parser::new(lexer);
+
+    parser.parse()
+}
+
+fn assert_round_trip(input: &str) {
+    let prog = parse(input).unwrap_or_else(|e| panic!("{}\n{}", e, input));
+    validate(&prog).unwrap_or_else(|e| panic!("{}\n{}", e, input));
+    let printed = pretty_print(&prog);
+    let reparsed = parse(&printed).unwrap_or_else(|e| panic!("{}\n{}", e, printed));
+
+    assert!(
+        ast_eq(&prog, &reparsed),
+        "AST changed after printing.\n{}\n-----\n{}",
+        input,
+        printed
+    );
+    assert_eq!(printed, pretty_print(&reparsed));
+}
+
+#[test]
+fn test_pretty_print() {
+    let input = r#"
+int g = 1;
+const double pi = 3.14;
+
+int f(int a, double b) {
+    int c = (a + 1) * 2, d;
+    if (a > 0) { c = -(-a); } else if (b < 1.0) c = f(a - 1, b / 2.0); else { d = 0; }
+    outer: while (c) {
+        while (1)
+            if (d) break outer; else break;
+    }
+    print("a\n\"b\"", 'c', (double)c);
+    return (c = d = 1) - (1 - 2);
+}
+    "#;
+
+    let expected = r#"int g = 1;
+
+const double pi = 3.14;
+
+int f(int a, double b) {
+    int c = (a + 1) * 2, d;
+    if (a > 0) {
+        c = -(-a);
+    } else if (b < 1.0)
+        c = f(a - 1, b / 2.0);
+    else {
+        d = 0;
+    }
+    outer: while (c) {
+        while (1)
+            if (d)
+                break outer;
+            else
+                break;
+    }
+    print("a\n\"b\"", 'c', (double)c);
+    return (c = d = 1) - (1 - 2);
+}
+"#;
+
+    let prog = parse(input).unwrap();
+    assert_eq!(pretty_print(&prog), expected);
+    assert_round_trip(input);
+}
+
+#[test]
+fn test_pretty_print_comma() {
+    assert_round_trip(
+        "int f(int a, int b) { return a; }\nint main() { int a = (1, 2), b; b = (a = 3, a), f((a, b), 1); return b; }",
+    );
+}
+
+#[test]
+fn test_pretty_print_negative_literals() {
+    let input =
+        "int main() {\n    int a = -1;\n    a = 1 - -2 + +(-3);\n    (-4);\n    return -(-a);\n}\n";
+    let expected =
+        "int main() {\n    int a = -1;\n    a = 1 - -2 + +(-3);\n    (-4);\n    return -(-a);\n}\n";
+
+    assert_eq!(pretty_print(&parse(input).unwrap()), expected);
+    assert_round_trip(input);
+}
+
+#[test]
+fn test_ast_eq_ignores_spans() {
+    let a = parse("int main() { return 1 + 2 * 3; }").unwrap();
+    let b = parse("int main()\n{\n    return (1) + (2 * 3);\n}").unwrap();
+    let c = parse("int main() { return (1 + 2) * 3; }").unwrap();
+
+    assert!(ast_eq(&a, &b));
+    assert!(!ast_eq(&a, &c));
+}
+
+// * Random programs are generated as a small tree first, then rendered to
+// * source so that every identifier refers to something declared.
+
+const UNARY: &[&str] = &["-", "+"];
+const BINARY: &[&str] = &[
+    "+", "-", "*", "/", "<", ">", "<=", ">=", "==", "!=", "&&", "||", "&", "|", "^",
+];
+
+#[derive(Debug, Clone)]
+enum GExpr {
+    Var(usize),
+    Int(u32),
+    Float(u32, u8, Option<i8>),
+    Char(char),
+    Unary(&'static str, Box<GExpr>),
+    PostInc(usize),
+    Binary(&'static str, Box<GExpr>, Box<GExpr>),
+    Conv(bool, Box<GExpr>),
+    Call(usize, Box<GExpr>, Box<GExpr>),
+    Assign(usize, Box<GExpr>),
+}
+
+#[derive(Debug, Clone)]
+struct GBody {
+    braced: bool,
+    stmts: Vec<GStmt>,
+}
+
+#[derive(Debug, Clone)]
+enum GStmt {
+    Decl {
+        double: bool,
+        konst: bool,
+        inits: Vec<Option<GExpr>>,
+    },
+    Expr(GExpr),
+    If(GExpr, GBody, Vec<(GExpr, GBody)>, Option<GBody>),
+    While(bool, GExpr, GBody),
+    Break(Option<usize>),
+    Print(Vec<GExpr>, Option<String>),
+    Scan(usize),
+    Return(GExpr),
+    Block(Vec<GStmt>),
+}
+
+#[derive(Debug, Clone)]
+enum GTop {
+    Global(GStmt),
+    Func(Vec<GStmt>),
+}
+
+fn g_expr() -> impl Strategy<Value = GExpr> {
+    let leaf = prop_oneof![
+        any::<usize>().prop_map(GExpr::Var),
+        any::<u32>().prop_map(GExpr::Int),
+        (any::<u32>(), 0..6u8, prop::option::of(-12..12i8))
+            .prop_map(|(m, d, e)| GExpr::Float(m, d, e)),
+        prop_oneof![
+            prop::char::range('a', 'z'),
+            prop::sample::select(vec!['\'', '"', '\\', '\n', '\t', ' '])
+        ]
+        .prop_map(GExpr::Char),
+        any::<usize>().prop_map(GExpr::PostInc),
+    ];
+    leaf.prop_recursive(4, 24, 2, |inner| {
+        prop_oneof![
+            (prop::sample::select(UNARY), inner.clone())
+                .prop_map(|(op, e)| GExpr::Unary(op, Box::new(e))),
+            (prop::sample::select(BINARY), inner.clone(), inner.clone())
+                .prop_map(|(op, l, r)| GExpr::Binary(op, Box::new(l), Box::new(r))),
+            (any::<bool>(), inner.clone()).prop_map(|(d, e)| GExpr::Conv(d, Box::new(e))),
+            (any::<usize>(), inner.clone(), inner.clone()).prop_map(|(f, a, b)| GExpr::Call(
+                f,
+                Box::new(a),
+                Box::new(b)
+            )),
+            (any::<usize>(), inner).prop_map(|(v, e)| GExpr::Assign(v, Box::new(e))),
+        ]
+    })
+}
+
+fn g_decl() -> impl Strategy<Value = GStmt> {
+    (
+        any::<bool>(),
+        any::<bool>(),
+        prop::collection::vec(prop::option::of(g_expr()), 1..3),
+    )
+        .prop_map(|(double, konst, inits)| GStmt::Decl {
+            double,
+            konst,
+            inits,
+        })
+}
+
+fn g_stmt() -> impl Strategy<Value = GStmt> {
+    let leaf = prop_oneof![
+        g_decl(),
+        g_expr().prop_map(GStmt::Expr),
+        prop::option::of(any::<usize>()).prop_map(GStmt::Break),
+        (
+            prop::collection::vec(g_expr(), 0..3),
+            prop::option::of("[a-z \n\t\"\\\\']{0,6}")
+        )
+            .prop_map(|(e, s)| GStmt::Print(e, s)),
+        any::<usize>().prop_map(GStmt::Scan),
+        g_expr().prop_map(GStmt::Return),
+    ];
+    leaf.prop_recursive(3, 24, 3, |inner| {
+        let body = (any::<bool>(), prop::collection::vec(inner.clone(), 1..3))
+            .prop_map(|(braced, stmts)| GBody { braced, stmts });
+        prop_oneof![
+            (
+                g_expr(),
+                body.clone(),
+                prop::collection::vec((g_expr(), body.clone()), 0..3),
+                prop::option::of(body.clone())
+            )
+                .prop_map(|(c, t, elifs, e)| GStmt::If(c, t, elifs, e)),
+            (any::<bool>(), g_expr(), body).prop_map(|(l, c, b)| GStmt::While(l, c, b)),
+            prop::collection::vec(inner, 0..4).prop_map(GStmt::Block),
+        ]
+    })
+}
+
+fn g_program() -> impl Strategy<Value = Vec<GTop>> {
+    prop::collection::vec(
+        prop_oneof![
+            g_decl().prop_map(GTop::Global),
+            prop::collection::vec(g_stmt(), 0..5).prop_map(GTop::Func),
+        ],
+        1..5,
+    )
+}
+
+/// Renders generated trees to source code, keeping track of what's in scope.
+#[derive(Default)]
+struct Renderer {
+    out: String,
+    scopes: Vec<Vec<String>>,
+    labels: Vec<String>,
+    fns: usize,
+    counter: usize,
+}
+
+impl Renderer {
+    fn fresh(&mut self, prefix: &str) -> String {
+        self.counter += 1;
+        format!("{}{}", prefix, self.counter)
+    }
+
+    fn var(&self, idx: usize) -> String {
+        let vars: Vec<_> = self.scopes.iter().flatten().collect();
+        vars[idx % vars.len()].clone()
+    }
+
+    fn escape(c: char, out: &mut String) {
+        match c {
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\\' | '\'' | '"' => {
+                out.push('\\');
+                out.push(c);
+            }
+            c => out.push(c),
+        }
+    }
+
+    fn expr(&mut self, e: &GExpr) {
+        match e {
+            GExpr::Var(v) => self.out += &self.var(*v),
+            GExpr::Int(i) => write!(self.out, "{}", i).unwrap(),
+            GExpr::Float(m, d, e) => {
+                let digits = format!("{:0>width$}", m, width = *d as usize + 1);
+                let (int, frac) = digits.split_at(digits.len() - *d as usize);
+                write!(self.out, "{}.{}", int, frac).unwrap();
+                if frac.is_empty() {
+                    self.out.push('0');
+                }
+                if let Some(e) = e {
+                    write!(self.out, "e{}", e).unwrap();
+                }
+            }
+            GExpr::Char(c) => {
+                self.out.push('\'');
+                Self::escape(*c, &mut self.out);
+                self.out.push('\'');
+            }
+            GExpr::Unary(op, e) => {
+                write!(self.out, "({}", op).unwrap();
+                self.expr(e);
+                self.out.push(')');
+            }
+            GExpr::PostInc(v) => {
+                let v = self.var(*v);
+                write!(self.out, "({}++)", v).unwrap();
+            }
+            GExpr::Binary(op, l, r) => {
+                self.out.push('(');
+                self.expr(l);
+                write!(self.out, " {} ", op).unwrap();
+                self.expr(r);
+                self.out.push(')');
+            }
+            GExpr::Conv(double, e) => {
+                self.out += if *double { "((double)" } else { "((int)" };
+                self.expr(e);
+                self.out.push(')');
+            }
+            // Global initializers may come before any function is declared
+            GExpr::Call(_, a, _) if self.fns == 0 => self.expr(a),
+            GExpr::Call(f, a, b) => {
+                write!(self.out, "f{}(", f % self.fns).unwrap();
+                self.expr(a);
+                self.out += ", ";
+                self.expr(b);
+                self.out.push(')');
+            }
+            GExpr::Assign(v, e) => {
+                let v = self.var(*v);
+                write!(self.out, "({} = ", v).unwrap();
+                self.expr(e);
+                self.out.push(')');
+            }
+        }
+    }
+
+    fn body(&mut self, body: &GBody) {
+        if body.braced {
+            self.block(&body.stmts);
+        } else {
+            self.stmt(&body.stmts[0]);
+        }
+    }
+
+    fn block(&mut self, stmts: &[GStmt]) {
+        self.out += "{\n";
+        self.scopes.push(vec![]);
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+        self.scopes.pop();
+        self.out += "}\n";
+    }
+
+    fn stmt(&mut self, stmt: &GStmt) {
+        match stmt {
+            GStmt::Decl {
+                double,
+                konst,
+                inits,
+            } => {
+                if *konst {
+                    self.out += "const ";
+                }
+                self.out += if *double { "double " } else { "int " };
+                let mut names = vec![];
+                for (idx, init) in inits.iter().enumerate() {
+                    if idx != 0 {
+                        self.out += ", ";
+                    }
+                    let name = self.fresh("v");
+                    self.out += &name;
+                    match init {
+                        Some(init) => {
+                            self.out += " = ";
+                            self.expr(init);
+                        }
+                        None if *konst => self.out += " = 0",
+                        None => (),
+                    }
+                    names.push(name);
+                }
+                self.out += ";\n";
+                self.scopes.last_mut().unwrap().extend(names);
+            }
+            GStmt::Expr(e) => {
+                self.expr(e);
+                self.out += ";\n";
+            }
+            GStmt::If(cond, then, elifs, els) => {
+                self.out += "if (";
+                self.expr(cond);
+                self.out += ") ";
+                self.body(then);
+                for (cond, body) in elifs {
+                    self.out += "else if (";
+                    self.expr(cond);
+                    self.out += ") ";
+                    self.body(body);
+                }
+                if let Some(els) = els {
+                    self.out += "else ";
+                    self.body(els);
+                }
+            }
+            GStmt::While(labeled, cond, body) => {
+                let label = if *labeled {
+                    let label = self.fresh("l");
+                    write!(self.out, "{}: ", label).unwrap();
+                    Some(label)
+                } else {
+                    None
+                };
+                self.out += "while (";
+                self.expr(cond);
+                self.out += ") ";
+                if let Some(label) = &label {
+                    self.labels.push(label.clone());
+                }
+                self.body(body);
+                if label.is_some() {
+                    self.labels.pop();
+                }
+            }
+            GStmt::Break(label) => match label {
+                Some(idx) if !self.labels.is_empty() => {
+                    let label = self.labels[idx % self.labels.len()].clone();
+                    writeln!(self.out, "break {};", label).unwrap();
+                }
+                _ => self.out += "break;\n",
+            },
+            GStmt::Print(exprs, s) => {
+                self.out += "print(";
+                let mut first = true;
+                if let Some(s) = s {
+                    self.out.push('"');
+                    for c in s.chars() {
+                        Self::escape(c, &mut self.out);
+                    }
+                    self.out.push('"');
+                    first = false;
+                }
+                for e in exprs {
+                    if !first {
+                        self.out += ", ";
+                    }
+                    first = false;
+                    self.expr(e);
+                }
+                if first {
+                    self.out.push('0');
+                }
+                self.out += ");\n";
+            }
+            GStmt::Scan(v) => {
+                let v = self.var(*v);
+                writeln!(self.out, "scan({});", v).unwrap();
+            }
+            GStmt::Return(e) => {
+                self.out += "return ";
+                self.expr(e);
+                self.out += ";\n";
+            }
+            GStmt::Block(stmts) => self.block(stmts),
+        }
+    }
+
+    fn program(prog: &[GTop]) -> String {
+        let mut r = Renderer {
+            scopes: vec![vec!["g0".into()]],
+            ..Default::default()
+        };
+        r.out += "int g0;\n";
+        for top in prog {
+            match top {
+                GTop::Global(decl) => r.stmt(decl),
+                GTop::Func(body) => {
+                    let (a, b) = (r.fresh("p"), r.fresh("p"));
+                    writeln!(r.out, "int f{}(int {}, double {}) {{", r.fns, a, b).unwrap();
+                    r.fns += 1;
+                    r.scopes.push(vec![a, b]);
+                    for stmt in body {
+                        r.stmt(stmt);
+                    }
+                    r.scopes.pop();
+                    r.out += "}\n";
+                }
+            }
+        }
+        r.out
+    }
+}
+
+#[test]
+fn test_format_keeps_comments() {
+    let input = r#"// Global state
+int g = 1; // trailing
+/* block
+   comment */
+int add(int a,int b){
+    int c=a+b;
+
+
+    // about to loop
+    while(c>0){c=c-1;/* dec */}
+    return c; // done
+    // end of body
+}
+// eof
+"#;
+
+    let expected = r#"// Global state
+int g = 1; // trailing
+
+/* block
+   comment */
+int add(int a, int b) {
+    int c = a + b;
+
+    // about to loop
+    while (c > 0) {
+        c = c - 1; /* dec */
+    }
+    return c; // done
+    // end of body
+}
+
+// eof
+"#;
+
+    let config = PrettyConfig::default();
+    let formatted = format(input, &config).unwrap();
+    assert_eq!(formatted, expected);
+    assert_eq!(format(&formatted, &config).unwrap(), formatted);
+}
+
+#[test]
+fn test_format_config() {
+    let input = "int f(int a) { if (a) { return 1; } else return 2; }";
+
+    let expected = r#"int f(int a)
+{
+  if (a)
+  {
+    return 1;
+  }
+  else
+    return 2;
+}
+"#;
+
+    let config = PrettyConfig {
+        indent_width: 2,
+        brace_style: BraceStyle::NextLine,
+    };
+    let formatted = format(input, &config).unwrap();
+    assert_eq!(formatted, expected);
+    assert_eq!(format(&formatted, &config).unwrap(), formatted);
+}
+
+#[test]
+fn test_format_keeps_literals() {
+    let input = r#"int main() {
+    double big = 1e300;
+    double k = 1.5E3;
+    int max = 0x7fffffff;
+    char c = '\x41';
+    print("a\u{1F600}", -0x10, - -1, 1.0e-3);
+    return 0;
+}
+"#;
+    let config = PrettyConfig::default();
+    let expected = input.replace("- -1", "1");
+    assert_eq!(format(input, &config).unwrap(), expected);
+}
+
+#[test]
+fn test_format_directives() {
+    let input = r#"#if EXT
+int f() { return 1; }
+#else
+int   f() {return 2;}
+#endif
+int main() {
+    int a = f()
+#if EXT
+        + 1
+#endif
+    ;
+    return a;
+}
+"#;
+    let config = PrettyConfig::default();
+    let err = format_with_features(input, &["EXT"], &config).unwrap_err();
+    assert!(matches!(err.var, ParseErrVariant::DirectiveInStatement));
+    assert_eq!(err.span.start.ln, 7);
+
+    let input = &input[..input.find("int main").unwrap()];
+    let expected = r#"#if EXT
+int f() {
+    return 1;
+}
+#else
+int   f() {return 2;}
+#endif
+"#;
+    let formatted = format_with_features(input, &["EXT"], &config).unwrap();
+    assert_eq!(formatted, expected);
+    let expected = r#"#if EXT
+int f() { return 1; }
+#else
+int f() {
+    return 2;
+}
+#endif
+"#;
+    assert_eq!(format(input, &config).unwrap(), expected);
+}
+
+proptest! {
+    #[test]
+    fn test_pretty_print_round_trip(prog in g_program()) {
+        let input = Renderer::program(&prog);
+        assert_round_trip(&input);
+    }
+
+    #[test]
+    fn test_format_idempotent(prog in g_program()) {
+        let input = Renderer::program(&prog);
+        let config = PrettyConfig::default();
+        let formatted = format(&input, &config).unwrap();
+        prop_assert_eq!(format(&formatted, &config).unwrap(), formatted);
+    }
+}
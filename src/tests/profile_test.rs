@@ -0,0 +1,79 @@
+use crate::minivm::vm::{function_name, MiniVM, Profile};
+use crate::minivm::*;
+use crate::parse;
+
+const SRC: &str = "int fact(int n) {
+    if (n < 2) return 1;
+    return n * fact(n - 1);
+}
+int twice(int x) {
+    return x + x;
+}
+int main() {
+    print(fact(5));
+    print(twice(3));
+    print(twice(4));
+    return 0;
+}
+";
+
+fn profile(o0: &O0) -> Profile {
+    let mut input = "".as_bytes();
+    let mut output = vec![];
+    let mut vm = MiniVM::new(o0, &mut input, &mut output).with_profile();
+    vm.run().unwrap();
+    vm.profile().unwrap().clone()
+}
+
+#[test]
+fn test_profile_calls() {
+    let o0 = Codegen::new(&parse(SRC).unwrap()).compile().unwrap();
+    let prof = profile(&o0);
+    let funcs = prof.functions();
+    let calls = |name: &str| {
+        let f = (funcs.iter())
+            .find(|f| function_name(&o0, f.func) == name)
+            .unwrap();
+        (f.calls, f.self_insts <= f.total_insts)
+    };
+    assert_eq!(calls("fact"), (5, true));
+    assert_eq!(calls("twice"), (2, true));
+    assert_eq!(calls("main"), (1, true));
+
+    // * Everything runs below `main`, and recursion is not counted twice
+    let main = funcs.iter().find(|f| f.func == Some(2)).unwrap();
+    let start = funcs.iter().find(|f| f.func.is_none()).unwrap();
+    assert_eq!(main.total_insts + start.self_insts, prof.total());
+    assert!(funcs.iter().all(|f| f.total_insts <= prof.total()));
+    assert_eq!(
+        funcs.iter().map(|f| f.self_insts).sum::<u64>(),
+        prof.total()
+    );
+}
+
+#[test]
+fn test_profile_folded() {
+    let o0 = Codegen::new(&parse(SRC).unwrap()).compile().unwrap();
+    let prof = profile(&o0);
+    let folded = prof.folded(&o0);
+    let stacks: Vec<_> = (folded.lines())
+        .map(|l| l.rsplit_once(' ').unwrap().0)
+        .collect();
+    assert_eq!(
+        stacks,
+        [
+            ".start",
+            "main",
+            "main;fact",
+            "main;fact;fact",
+            "main;fact;fact;fact",
+            "main;fact;fact;fact;fact",
+            "main;fact;fact;fact;fact;fact",
+            "main;twice",
+        ]
+    );
+    let sum: u64 = (folded.lines())
+        .map(|l| l.rsplit_once(' ').unwrap().1.parse::<u64>().unwrap())
+        .sum();
+    assert_eq!(sum, prof.total());
+}
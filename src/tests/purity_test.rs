@@ -0,0 +1,120 @@
+use super::{exec, run};
+use crate::c0::purity::Impurity;
+use crate::minivm::*;
+use crate::{check, parse, purity, Severity};
+
+const SRC: &str = r#"int total;
+
+int square(int x) {
+    return x * x;
+}
+
+int sum_to(int n) {
+    int s = 0;
+    while (n > 0) {
+        s = s + square(n);
+        n = n - 1;
+    }
+    return s + total;
+}
+
+void add(int x) {
+    total = total + x;
+}
+
+int noisy(int x) {
+    print(x);
+    return square(x);
+}
+
+int calls_noisy() {
+    return noisy(1);
+}
+
+int main() {
+    add(square(2));
+    square(3);
+    sum_to(2), add(1);
+    print(noisy(2) + sum_to(total));
+    return 0;
+}
+"#;
+
+#[test]
+fn test_purity() {
+    let p = purity(&parse(SRC).unwrap());
+    assert!(p.is_pure("square"));
+    // * Reading globals is fine, writing them is not
+    assert!(p.is_pure("sum_to"));
+    assert!(matches!(&p.fns["add"], Some(Impurity::WritesGlobal(name, _)) if name == "total"));
+    assert!(matches!(p.fns["noisy"], Some(Impurity::Io(_))));
+    assert!(matches!(&p.fns["calls_noisy"], Some(Impurity::Calls(name, _)) if name == "noisy"));
+    assert!(!p.is_pure("main"));
+    assert!(!p.is_pure("no_such_function"));
+}
+
+#[test]
+fn test_recursion_is_pure() {
+    let src = "int fact(int n) {\n    if (n <= 1) return 1;\n    return n * fact(n - 1);\n}\n\
+               int main() { print(fact(5)); return 0; }\n";
+    let p = purity(&parse(src).unwrap());
+    assert!(p.is_pure("fact"));
+}
+
+#[test]
+fn test_unused_result_warning() {
+    let diags = check(&parse(SRC).unwrap());
+    let warnings: Vec<_> = (diags.iter())
+        .map(|d| {
+            assert_eq!(d.severity, Severity::Warning);
+            assert_eq!(d.code.to_string(), "E0501");
+            d.span.unwrap().start.ln
+        })
+        .collect();
+    // * `square(3);` and `sum_to(2)` in `sum_to(2), add(1);`
+    assert_eq!(warnings, [30, 31]);
+    assert!(diags[0]
+        .to_string()
+        .starts_with("warning[E0501]: Result of `square`"));
+}
+
+#[test]
+fn test_cse_pure_calls() {
+    let src = "int sq(int x) { return x * x; }\n\
+               double half(double x) { return x / 2.0; }\n\
+               int main() {\n\
+                   int a = 3;\n\
+                   print(sq(a) * sq(a), half(5.0) + half(5.0), sq(a) - sq(a + 1));\n\
+                   return 0;\n\
+               }\n";
+    let prog = parse(src).unwrap();
+    let run = |cse: bool| {
+        let o0 = Codegen::new(&prog).with_cse(cse).compile().unwrap();
+        let main = o0.functions.last().unwrap();
+        let calls = (main.ins.iter())
+            .filter(|i| matches!(i, Inst::Call(_)))
+            .count();
+        (calls, exec(&o0, "").1)
+    };
+    assert_eq!(run(false), (6, "81 5.000000 -7\n".into()));
+    // * `sq(a) - sq(a + 1)` has different sides, so both are called
+    assert_eq!(run(true), (4, "81 5.000000 -7\n".into()));
+}
+
+#[test]
+fn test_no_cse_impure_calls() {
+    let src = "int n;\n\
+               int next() { n = n + 1; return n; }\n\
+               int main() { print(next() * next()); return 0; }\n";
+    assert_eq!(run(src), "2\n");
+}
+
+#[test]
+fn test_writes_through_reference() {
+    let src = "int twice(int x) {\n    &int p = &x;\n    return *p + *p;\n}\n\
+               int bump(int x) {\n    &int p = &x;\n    *p = x + 1;\n    return x;\n}\n\
+               int main() { print(twice(1), bump(1)); return 0; }\n";
+    let p = purity(&parse(src).unwrap());
+    assert!(p.is_pure("twice"));
+    assert!(matches!(p.fns["bump"], Some(Impurity::WritesThrough(_))));
+}
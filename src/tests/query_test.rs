@@ -0,0 +1,80 @@
+use crate::c0::query::*;
+use crate::parse;
+
+const SRC: &str = "int g = 1;
+int foo(int a) {
+    return a + 1;
+}
+int main() {
+    int x = foo(2);
+    while (x < 10) {
+        x = foo(x) * 2;
+    }
+    print(x / 3);
+    return 0;
+}
+";
+
+fn find(query: &str) -> Vec<Match> {
+    Query::parse(query).unwrap().find_all(&parse(SRC).unwrap())
+}
+
+fn lines(found: &[Match]) -> Vec<&str> {
+    found.iter().map(|m| m.attr("line").unwrap()).collect()
+}
+
+#[test]
+fn test_find_all() {
+    let calls = find("FunctionCall[name=foo]");
+    assert_eq!(lines(&calls), ["6", "8"]);
+    assert!(calls.iter().all(|m| m.function.as_deref() == Some("main")));
+    assert_eq!(calls[0].attr("args"), Some("1"));
+
+    assert_eq!(lines(&find("While FunctionCall")), ["8"]);
+    assert_eq!(lines(&find("Function[name=main] > Return")), ["11"]);
+    assert_eq!(lines(&find("Function Return")), ["3", "11"]);
+    assert!(find("While > FunctionCall").is_empty());
+    assert_eq!(lines(&find("BinaryOp[op=/], BinaryOp[op=+]")), ["3", "10"]);
+    assert_eq!(lines(&find("BinaryOp[op!=<][op!=*]")), ["3", "10"]);
+
+    let decls = find("Decl");
+    assert_eq!(lines(&decls), ["1", "6"]);
+    assert_eq!(decls[0].function, None);
+    assert_eq!(decls[1].attr("name"), Some("x"));
+    assert_eq!(decls[1].attr("type"), Some("int"));
+    assert_eq!(lines(&find("Decl FunctionCall")), ["6"]);
+
+    let fns = find("Function[name=foo]");
+    assert_eq!(fns.len(), 1);
+    assert_eq!(fns[0].function.as_deref(), Some("foo"));
+    assert_eq!(fns[0].attr("params"), Some("1"));
+    assert_eq!(lines(&find("*[name=x]")).len(), 6);
+}
+
+#[test]
+fn test_builder() {
+    let prog = parse(SRC).unwrap();
+    let query = Query::select(Selector::of(NodeKind::Return))
+        .child_of(Selector::of(NodeKind::Function).attr("name", "main"));
+    assert_eq!(query, Query::parse("Function[name=main] > Return").unwrap());
+    assert_eq!(query.find_all(&prog), find("Function[name=main] > Return"));
+
+    let query = Query::select(Selector::of(NodeKind::FunctionCall))
+        .inside(Selector::of(NodeKind::While))
+        .or(Selector::of(NodeKind::Decl).has("type").into());
+    assert_eq!(lines(&query.find_all(&prog)), ["1", "6", "8"]);
+}
+
+#[test]
+fn test_errors() {
+    let err = |query| Query::parse(query).unwrap_err();
+    assert_eq!(err("Call"), QueryError::UnknownKind("Call".into()));
+    assert_eq!(
+        err("Return[name=x]"),
+        QueryError::UnknownAttr("Return".into(), "name".into())
+    );
+    assert!(matches!(err(""), QueryError::Syntax(_)));
+    assert!(matches!(err("While >"), QueryError::Syntax(_)));
+    assert!(matches!(err("Decl[name=x"), QueryError::Syntax(_)));
+    assert!(matches!(err("Decl,"), QueryError::Syntax(_)));
+}
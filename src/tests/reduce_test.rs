@@ -0,0 +1,46 @@
+use crate::c0::reduce::reduce;
+
+const PROG: &str = "
+int square(int x) {
+    return x * x;
+}
+
+int unused(int y) {
+    while (y > 0) {
+        y = y - 1;
+    }
+    return y;
+}
+
+int main() {
+    int a = 3, b = 4;
+    if (a < b) {
+        print(square(a) + 17);
+    } else {
+        print(b);
+    }
+    print(unused(b));
+    return 0;
+}
+";
+
+#[test]
+fn test_reduce_keeps_interesting_part() {
+    let reduced = reduce(PROG, |src| src.contains("17")).unwrap();
+    assert!(reduced.contains("17"), "{}", reduced);
+    assert!(!reduced.contains("unused"), "{}", reduced);
+    assert!(!reduced.contains("square"), "{}", reduced);
+    assert!(reduced.len() < 40, "{}", reduced);
+}
+
+#[test]
+fn test_reduce_result_parses() {
+    let reduced = reduce(PROG, |src| src.contains("while")).unwrap();
+    assert!(reduced.contains("while"), "{}", reduced);
+    assert!(crate::parse(&reduced).is_ok(), "{}", reduced);
+}
+
+#[test]
+fn test_reduce_uninteresting_input() {
+    assert_eq!(reduce(PROG, |src| src.contains("for")), None);
+}
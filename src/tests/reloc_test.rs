@@ -0,0 +1,64 @@
+use super::{compile, exec};
+use crate::minivm::*;
+
+#[test]
+fn test_calls_are_relocated() {
+    // * `two` reads a variable, so `x` is not folded to a constant
+    let src = "int n = 2;\n\
+               int one() { return 1; }\n\
+               int two() { return n; }\n\
+               int x = two();\n\
+               int main() { print(one(), x); return 0; }\n";
+    let o0 = compile(src);
+    let calls: Vec<_> = (o0.relocs.iter())
+        .map(|r| (r.func, r.symbol.as_str()))
+        .collect();
+    assert_eq!(calls, [(None, "two"), (Some(2), "one")]);
+    for r in &o0.relocs {
+        let ins = match r.func {
+            Some(f) => &o0.functions[f as usize].ins,
+            None => &o0.start_code.ins,
+        };
+        assert!(matches!(ins[r.idx], Inst::Call(_)), "{:?}", ins[r.idx]);
+    }
+    assert!(!o0.to_string().contains('@'));
+    verify(&o0).unwrap();
+}
+
+#[test]
+fn test_patch_calls() {
+    let src = "int one() { return 1; }\n\
+               int two() { return 2; }\n\
+               int main() { print(one(), one(), two()); return 0; }\n";
+    let mut o0 = compile(src);
+    assert_eq!(exec(&o0, "").1, "1 1 2\n");
+    assert_eq!(o0.patch_calls("one", 1), 2);
+    assert_eq!(o0.patch_calls("three", 1), 0);
+    assert_eq!(exec(&o0, "").1, "2 2 2\n");
+}
+
+#[test]
+fn test_unresolved_symbol() {
+    let mut relocator = Relocator::new();
+    assert_eq!(relocator.symbol("f"), 0);
+    assert_eq!(relocator.symbol("g"), 1);
+    assert_eq!(relocator.symbol("f"), 0);
+    assert_eq!(relocator.name(1), Some("g"));
+
+    let mut o0 = O0 {
+        version: 1,
+        constants: vec![],
+        start_code: StartCodeInfo {
+            ins: vec![Inst::_Call(1)],
+        },
+        functions: vec![],
+        endian: Endian::Big,
+        debug: None,
+        relocs: vec![],
+        host_fns: vec![],
+    };
+    let e = relocator.relocate(&mut o0, |_| None).unwrap_err();
+    assert!(matches!(&e.var, CompileErrorVar::NonExistFunc(f) if f == "g"));
+    relocator.relocate(&mut o0, |_| Some(7)).unwrap();
+    assert_eq!(o0.start_code.ins, [Inst::Call(7)]);
+}
@@ -0,0 +1,130 @@
+use crate::minivm::vm::MiniVM;
+use crate::minivm::*;
+use crate::parse;
+
+/// Remarks of `passes` compiling `src` with `profile`, as the line they are
+/// at, counting from 1, and what they say
+fn remarks(src: &str, passes: &[Pass], profile: Option<ExecProfile>) -> Vec<(usize, String)> {
+    let remarks = Remarks::new(passes.to_vec());
+    Codegen::new(&parse(src).unwrap())
+        .with_profile(profile)
+        .with_passes(PassManager::for_level(2).unwrap())
+        .with_remarks(remarks.clone())
+        .compile()
+        .unwrap();
+    (remarks.take().into_iter())
+        .map(|r| (r.span.map_or(0, |s| s.start.ln + 1), r.to_string()))
+        .collect()
+}
+
+/// Profile of running `src` on `input`
+fn profile(src: &str, input: &str) -> ExecProfile {
+    let o0 = Codegen::new(&parse(src).unwrap())
+        .with_debug_info(true)
+        .compile()
+        .unwrap();
+    let mut input = input.as_bytes();
+    let mut output = vec![];
+    let mut vm = MiniVM::new(&o0, &mut input, &mut output).with_coverage();
+    vm.run().unwrap();
+    ExecProfile::new(&o0, vm.coverage().unwrap()).unwrap()
+}
+
+#[test]
+fn test_inline() {
+    let src = "int sq(int x) { return x * x; }\n\
+               int inc(int x) { int y = x + 1; return y; }\n\
+               int main() {\n\
+                   int i = 0;\n\
+                   int s = 0;\n\
+                   int n;\n\
+                   scan(n);\n\
+                   while (i < n) {\n\
+                       s = s + sq(i) + inc(i);\n\
+                       i = i + 1;\n\
+                   }\n\
+                   print(s);\n\
+                   return 0;\n\
+               }\n";
+    let hot = profile(src, "100");
+    assert_eq!(
+        remarks(src, &[Pass::Inline], Some(hot)),
+        vec![
+            (9, "remark[inline]: call to `sq` inlined into `main`".into()),
+            (
+                9,
+                "remark[inline]: call to `inc` not inlined: callee does more than return a value"
+                    .into()
+            ),
+        ]
+    );
+
+    let cold = profile(src, "3");
+    let why = "callee called 3 times in the profile, fewer than";
+    let remarks = remarks(src, &[Pass::Inline], Some(cold));
+    assert!(remarks[0].1.contains(why), "{:?}", remarks);
+
+    let none = self::remarks(src, &[Pass::Inline], None);
+    assert_eq!(none.len(), 1);
+    assert!(none[0].1.contains("nothing inlined"), "{:?}", none);
+}
+
+#[test]
+fn test_unroll() {
+    let src = "int main() {\n\
+                   int i = 0;\n\
+                   int s = 0;\n\
+                   int n;\n\
+                   scan(n);\n\
+                   while (i < 2) {\n\
+                       int j = 0;\n\
+                       while (j < n) {\n\
+                           s = s + j;\n\
+                           j = j + 1;\n\
+                       }\n\
+                       i = i + 1;\n\
+                   }\n\
+                   print(s);\n\
+                   return 0;\n\
+               }\n";
+    // * The inner loop is compiled once for each copy of the outer one, and
+    // * remarked on once
+    assert_eq!(
+        remarks(src, &[Pass::Unroll], None),
+        vec![
+            (
+                6,
+                "remark[unroll]: loop unrolled fully, into 2 copies of its body".into()
+            ),
+            (
+                8,
+                "remark[unroll]: loop not unrolled: the times it runs are not known when compiling"
+                    .into()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_reuse_and_locals() {
+    let src = "int sq(int x) { return x * x; }\n\
+               int main() {\n\
+                   int a;\n\
+                   scan(a);\n\
+                   int b = a;\n\
+                   print(sq(b) + sq(b));\n\
+                   print(sq(b));\n\
+                   print(a * 3);\n\
+                   return 0;\n\
+               }\n";
+    let passes = [Pass::Cse, Pass::Gvn, Pass::CopyProp, Pass::Dse];
+    let remarks = remarks(src, &passes, None);
+    let cse = "remark[cse]: both sides of the operator the same pure value, computed once";
+    assert!(remarks.contains(&(6, cse.into())), "{:?}", remarks);
+    let gvn = "remark[gvn]: call to `sq` not made again, its result reused";
+    assert!(remarks.contains(&(7, gvn.into())), "{:?}", remarks);
+    // * Passes over instructions remark at the body of the function
+    let dse = "remark[dse]: in `main`: 1 store never read again dropped";
+    assert!(remarks.contains(&(2, dse.into())), "{:?}", remarks);
+    assert!(remarks.iter().all(|(_, r)| !r.contains("remark[sccp]")));
+}
@@ -0,0 +1,56 @@
+use crate::minivm::{binfmt, Codegen};
+use crate::parse;
+
+/// Binary and debug info of `src`, parsed and compiled from scratch
+fn build(src: &str) -> (Vec<u8>, Vec<u8>) {
+    let prog = parse(src).unwrap();
+    let o0 = Codegen::new(&prog).with_debug_info(true).compile().unwrap();
+    let mut binary = vec![];
+    o0.write_binary(&mut binary).unwrap();
+    let mut debug = vec![];
+    binfmt::write_debug(o0.debug.as_ref().unwrap(), &mut debug).unwrap();
+    (binary, debug)
+}
+
+const MANY_NAMES: &str = r#"
+const int zeta = 26, alpha = 1;
+int mid = 13, beta;
+double gamma = 3.5;
+int f3(int c, int b, int a) { return a - b * c; }
+void f1() { print("one", "two", 'x', 1.25); }
+int f2(double y) { return 2; }
+int main() {
+    int q = 1, p = 2, o = 3;
+    f1();
+    print(f3(q, p, o), f2(gamma), "two", "three");
+    return zeta + alpha + mid;
+}
+"#;
+
+#[test]
+fn test_reproducible_binaries() {
+    assert_eq!(build(MANY_NAMES), build(MANY_NAMES));
+
+    let mut sources = vec![MANY_NAMES.to_string()];
+    for entry in std::fs::read_dir("tests/cases").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|e| e == "c0") {
+            sources.push(std::fs::read_to_string(&path).unwrap());
+        }
+    }
+
+    for src in sources {
+        let compiles = parse(&src).is_ok_and(|p| Codegen::new(&p).compile().is_ok());
+        if !compiles {
+            continue;
+        }
+        let first = build(&src);
+        assert_eq!(first, build(&src));
+        // * A fresh thread starts with fresh thread-local state
+        let other = std::thread::spawn({
+            let src = src.clone();
+            move || build(&src)
+        });
+        assert_eq!(first, other.join().unwrap());
+    }
+}
@@ -0,0 +1,124 @@
+use super::exec;
+use crate::minivm::vm::{MiniVM, UbError, UbKind, VmError};
+use crate::minivm::*;
+use crate::parse;
+
+fn compile(src: &str, checks: bool) -> O0 {
+    Codegen::new(&parse(src).unwrap())
+        .with_ub_checks(checks)
+        .compile()
+        .unwrap()
+}
+
+/// Output of running `src` compiled with checks on `input`, and what
+/// undefined behavior stopped it if any
+fn run(src: &str, input: &str) -> (String, Option<UbError>) {
+    let o0 = compile(src, true);
+    let mut input = input.as_bytes();
+    let mut output = vec![];
+    let res = MiniVM::new(&o0, &mut input, &mut output).run();
+    let ub = match res {
+        Ok(_) => None,
+        Err(VmError::UndefinedBehavior(e)) => Some(e),
+        Err(e) => panic!("{}", e),
+    };
+    (String::from_utf8(output).unwrap(), ub)
+}
+
+const ARITH: &str = "int f(int a, int b, int op) {
+    if (op == 0) { return a + b; }
+    if (op == 1) { return a - b; }
+    if (op == 2) { return a * b; }
+    if (op == 3) { return a / b; }
+    return -a;
+}
+int main() {
+    int a;
+    int b;
+    int op;
+    scan(a);
+    scan(b);
+    scan(op);
+    print(f(a, b, op));
+    return 0;
+}
+";
+
+#[test]
+fn test_overflow() {
+    let ub = |input| run(ARITH, input).1.map(|e| e.kind);
+    assert_eq!(run(ARITH, "2147483646 1 0"), ("2147483647\n".into(), None));
+    assert_eq!(
+        ub("2147483647 1 0"),
+        Some(UbKind::Overflow(2147483647, '+', 1))
+    );
+    assert_eq!(
+        ub("-2147483648 1 1"),
+        Some(UbKind::Overflow(-2147483648, '-', 1))
+    );
+    assert_eq!(
+        ub("65536 32768 2"),
+        Some(UbKind::Overflow(65536, '*', 32768))
+    );
+    assert_eq!(
+        ub("-2147483648 -1 3"),
+        Some(UbKind::Overflow(-2147483648, '/', -1))
+    );
+    assert_eq!(ub("1 0 3"), Some(UbKind::DivideByZero));
+    assert_eq!(
+        ub("-2147483648 0 4"),
+        Some(UbKind::NegOverflow(-2147483648))
+    );
+    assert_eq!(ub("-7 2 3"), None);
+
+    let (_, ub) = run(ARITH, "2147483647 1 0");
+    let ub = ub.unwrap();
+    assert_eq!((ub.line, ub.col), (2, 27));
+    assert_eq!(
+        ub.to_string(),
+        "undefined behavior at 2:27: signed integer overflow: \
+         2147483647 + 1 cannot be represented in type `int`"
+    );
+
+    // * Without checks, `int`s wrap around
+    let o0 = compile(ARITH, false);
+    assert_eq!(exec(&o0, "2147483647 1 0").1, "-2147483648\n");
+}
+
+#[test]
+fn test_null_and_uninit() {
+    let src = "int g;
+&int h;
+int main() {
+    &int p = &g;
+    int x;
+    int n;
+    scan(n);
+    *p = 1;
+    if (n == 1) { *h = 1; }
+    if (n == 2) { print(x); }
+    x = 3;
+    while (n > 2) {
+        int y;
+        if (n == 3) { y = 1; }
+        n = n - 1;
+        print(x + y);
+    }
+    print(g);
+    return 0;
+}
+";
+    assert_eq!(run(src, "0"), ("1\n".into(), None));
+    let (_, ub) = run(src, "1");
+    let ub = ub.unwrap();
+    assert_eq!((ub.kind, ub.line, ub.col), (UbKind::NullDeref, 9, 19));
+    let (_, ub) = run(src, "2");
+    let ub = ub.unwrap();
+    assert_eq!((ub.kind, ub.line, ub.col), (UbKind::Uninit, 10, 25));
+    // * `y` is uninitialized again the second time round
+    let (out, ub) = run(src, "4");
+    assert_eq!(out, "");
+    assert_eq!(ub.map(|e| (e.kind, e.line)), Some((UbKind::Uninit, 16)));
+    let (out, ub) = run(src, "3");
+    assert_eq!((out.as_str(), ub), ("4\n1\n", None));
+}
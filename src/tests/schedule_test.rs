@@ -0,0 +1,71 @@
+use super::{compile, exec};
+use crate::minivm::*;
+use crate::parse;
+
+#[test]
+fn test_schedule_deep_operand_first() {
+    // * `a` is a parameter, so that it is not known to be 1 when compiling
+    let src = "int f(int a) {\n    return a + (a + (a + (a + a)));\n}\n\
+               int main() {\n    return f(1);\n}\n";
+    let o0 = compile(src);
+    assert_eq!(stack_depth(&o0).unwrap().functions, [2, 1]);
+    assert_eq!(exec(&o0, "").0, 5);
+
+    // * Subtraction keeps its order
+    let src = "int f(int a) {\n    return a - (a - (a - a));\n}\n\
+               int main() {\n    return f(1);\n}\n";
+    let o0 = compile(src);
+    assert_eq!(stack_depth(&o0).unwrap().functions, [4, 1]);
+    assert_eq!(exec(&o0, "").0, 0);
+}
+
+#[test]
+fn test_schedule_keeps_meaning() {
+    let src = r#"
+int main() {
+    int a = 3;
+    double d = 0.5;
+    print(a < (a + (a + 1)), a >= (a * (a - 1)));
+    if (a == (a + (a - a)))
+        print(a);
+    print(d + (a + (a + d)));
+    return 0;
+}
+"#;
+    assert_eq!(exec(&compile(src), "").1, "1 0\n3\n7.000000\n");
+}
+
+#[test]
+fn test_schedule_keeps_side_effects_in_order() {
+    let src = r#"
+int g = 0;
+int next() {
+    g = g + 1;
+    print(g);
+    return g;
+}
+int main() {
+    return next() * (next() + (next() + 1));
+}
+"#;
+    let (code, out) = exec(&compile(src), "");
+    assert_eq!(out, "1\n2\n3\n");
+    assert_eq!(code, 6);
+}
+
+#[test]
+fn test_schedule_max_stack_depth() {
+    let src = "int f(int a) {\n    return a - (a - (a - a));\n}\n\
+               int main() {\n    return f(1);\n}\n";
+    let prog = parse(src).unwrap();
+    let e = Codegen::new(&prog)
+        .with_max_stack_depth(Some(3))
+        .compile()
+        .unwrap_err();
+    assert_eq!(e.var.code(), crate::ErrorCode(407));
+    assert!(e.span.is_some());
+    assert!(Codegen::new(&prog)
+        .with_max_stack_depth(Some(4))
+        .compile()
+        .is_ok());
+}
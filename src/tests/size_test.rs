@@ -0,0 +1,79 @@
+use super::compile;
+use crate::minivm::{Codegen, SizeReport};
+use crate::{codegen, parse};
+
+#[test]
+fn test_size_report_adds_up() {
+    let src = r#"
+int square(int x) {
+    return x * x;
+}
+int main() {
+    print("square:", square(3), 1.5);
+    return 0;
+}
+"#;
+    let o0 = compile(src);
+    let report = SizeReport::new(&o0);
+
+    let mut binary = vec![];
+    o0.write_binary(&mut binary).unwrap();
+    assert_eq!(report.total_bytes, binary.len());
+    // * Magic, version, and the counts of constants and functions
+    let parts = 12
+        + report.constant_bytes
+        + report.start.bytes
+        + report.functions.iter().map(|f| f.bytes).sum::<usize>();
+    assert_eq!(parts, report.total_bytes);
+
+    let main = &report.functions[0];
+    assert_eq!(main.name, "main");
+    assert_eq!(main.constants, 2);
+    // * "square:" and 1.5
+    assert_eq!(main.constant_bytes, (1 + 2 + 7) + (1 + 8));
+    let square = &report.functions[1];
+    assert_eq!(square.name, "square");
+    assert_eq!(
+        (square.param_slots, square.local_slots, square.stack_slots),
+        (1, 0, 2)
+    );
+    assert_eq!(square.frame_slots, 3);
+    assert_eq!(square.instructions, o0.functions[0].ins.len());
+}
+
+#[test]
+fn test_size_report_formats() {
+    let o0 = compile("int main() { return 0; }");
+    let report = SizeReport::new(&o0);
+
+    let table = report.to_string();
+    assert!(table.starts_with("function"), "{}", table);
+    assert!(table.contains("\nmain "), "{}", table);
+    assert!(table.contains(&format!("{} bytes", report.total_bytes)));
+
+    let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+    assert_eq!(json["total_bytes"], report.total_bytes);
+    assert_eq!(json["functions"][0]["name"], "main");
+}
+
+#[test]
+fn test_max_frame_size() {
+    let src = "int square(int x) {\n    int y = x * x;\n    return y;\n}\n\
+               int main() {\n    return square(3);\n}\n";
+    let prog = parse(src).unwrap();
+    let report = SizeReport::new(&codegen(&prog).unwrap());
+    let square = report.functions.iter().find(|f| f.name == "square");
+    let frame = square.unwrap().frame_slots;
+    assert_eq!(frame, 1 + 1 + 3);
+
+    let e = Codegen::new(&prog)
+        .with_max_frame_size(Some(frame - 1))
+        .compile()
+        .unwrap_err();
+    assert_eq!(e.var.code(), crate::ErrorCode(408));
+    assert_eq!(e.span.unwrap().start.ln, 0);
+    assert!(Codegen::new(&prog)
+        .with_max_frame_size(Some(frame))
+        .compile()
+        .is_ok());
+}
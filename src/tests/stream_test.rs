@@ -0,0 +1,93 @@
+use super::{compile, exec};
+use crate::c0::ast::{SymbolDef, TypeDef};
+use crate::c0::gen::*;
+use crate::c0::lexer::Lexer;
+use crate::c0::parser::{Decl, Parser};
+use crate::{codegen_streamed, ErrorCode, Symbol};
+
+#[test]
+fn test_parse_decls() {
+    let src = "int g = 1;\nint f(int a) { return a + g; }\nint main() { return f(2); }\n";
+    let mut parser = Parser::new(Lexer::new(src));
+    let mut prog = parser.begin().unwrap();
+    let mut decls = vec![];
+    loop {
+        let decl = parser.parse_decl(&mut prog).unwrap();
+        decls.push(decl);
+        if decl == Decl::End {
+            break;
+        }
+    }
+    let fns = [Symbol::intern("f"), Symbol::intern("main")];
+    assert_eq!(
+        decls,
+        [Decl::Other, Decl::Fn(fns[0]), Decl::Fn(fns[1]), Decl::End]
+    );
+
+    let def = prog.blk.scope.borrow().find_def("f").unwrap();
+    assert!(def.borrow().take_body().is_some());
+    assert!(def.borrow().take_body().is_none());
+}
+
+#[test]
+fn test_bodies_skipped() {
+    let src = "int f(int a) { { a = a + 1; } return a; }\nint main() { return f(2); }\n";
+    let prog = Parser::new(Lexer::new(src))
+        .with_bodies_skipped()
+        .parse()
+        .unwrap();
+    for name in ["f", "main"] {
+        let def = prog.blk.scope.borrow().find_def(name).unwrap();
+        let def = def.borrow();
+        let SymbolDef::Var { typ, .. } = &*def else {
+            panic!("{} is not a variable", name);
+        };
+        assert!(matches!(&*typ.borrow(), TypeDef::Function(f) if f.body.is_none()));
+    }
+
+    let e = Parser::new(Lexer::new("int main() { {\n"))
+        .with_bodies_skipped()
+        .parse()
+        .unwrap_err();
+    assert!(e.to_string().contains("Unclosed"), "{}", e);
+}
+
+#[test]
+fn test_streamed_same_code() {
+    let src = "int g = 3;\nauto h = 2.5;\nint fib(int n) {\n    if (n < 2) { return n; }\n    \
+        return fib(n - 1) + fib(n - 2);\n}\nint k = 4;\nint main() {\n    auto x = fib(10) + g + k;\n    \
+        print(x, h);\n    return 0;\n}\n";
+    let o0 = compile(src);
+    let streamed = codegen_streamed(src, |codegen| codegen).unwrap();
+    assert_eq!(streamed.to_string(), o0.to_string());
+    assert_eq!(exec(&streamed, ""), (0, "62 2.500000\n".into()));
+}
+
+#[test]
+fn test_streamed_generated_programs() {
+    for seed in 0..20 {
+        let config = GenConfig {
+            seed,
+            ..GenConfig::default()
+        };
+        let src = generate(&config);
+        let o0 = compile(&src);
+        let streamed = codegen_streamed(&src, |codegen| codegen).unwrap();
+        assert_eq!(
+            exec(&streamed, ""),
+            exec(&o0, ""),
+            "seed {}:\n{}",
+            seed,
+            src
+        );
+    }
+}
+
+#[test]
+fn test_streamed_errors() {
+    let e = codegen_streamed("int main() {\n    return x;\n}\n", |codegen| codegen).unwrap_err();
+    assert_eq!(e.code, ErrorCode(201));
+    let e =
+        codegen_streamed("int f() {}\nint main() { return f(); }", |codegen| codegen).unwrap_err();
+    assert_eq!(e.code, ErrorCode(401));
+}
@@ -0,0 +1,43 @@
+use crate::c0::lexer::*;
+use crate::c0::symbol::{with_fresh_names, Symbol};
+
+#[test]
+fn test_intern() {
+    let a = Symbol::intern("counter");
+    assert_eq!(a, Symbol::intern("counter"));
+    assert_ne!(a, Symbol::intern("count"));
+    assert_eq!(a.as_str(), "counter");
+    assert_eq!(Symbol::intern(""), Symbol::EMPTY);
+    assert!(Symbol::intern("a") < Symbol::intern("b"));
+    assert!(Symbol::intern("zz") > Symbol::intern("b"));
+}
+
+#[test]
+fn test_lexer_interns_identifiers() {
+    let idents: Vec<_> = Lexer::new("foo bar foo")
+        .filter_map(|token| match token.var {
+            TokenType::Identifier(name) => Some(name),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(idents.len(), 3);
+    assert_eq!(idents[0], idents[2]);
+    assert_ne!(idents[0], idents[1]);
+    assert_eq!(idents[1], "bar");
+}
+
+#[test]
+fn test_fresh_names() {
+    let here = Symbol::intern("zebra_name");
+    // * The other thread numbers its names from scratch, so its first name
+    // * may have the number of `here`, but never its name
+    let there = with_fresh_names(|| {
+        let giraffe = Symbol::intern("giraffe");
+        (
+            giraffe.as_str().to_string(),
+            Symbol::intern("giraffe") == giraffe,
+        )
+    });
+    assert_eq!(there, ("giraffe".to_string(), true));
+    assert_eq!(here.as_str(), "zebra_name");
+}
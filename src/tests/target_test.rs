@@ -0,0 +1,61 @@
+use crate::minivm::Endian;
+use crate::{codegen_for, parse, ErrorCode, Target};
+
+#[test]
+fn test_target_from_name() {
+    assert_eq!(Target::from_name("o0"), Some(Target::O0));
+    assert_eq!(Target::from_name("o0-32"), Some(Target::O0_32));
+    assert_eq!(Target::from_name("x86"), None);
+    assert_eq!(Target::default(), Target::O0);
+}
+
+#[test]
+fn test_target_instructions() {
+    let ints = parse("int main() { int x = 1; print(x + 2); return 0; }").unwrap();
+    assert!(codegen_for(&ints, Target::O0_32).is_ok());
+
+    let doubles =
+        parse("int main() {\n    double x = 1.5;\n    print(x);\n    return 0;\n}\n").unwrap();
+    assert!(codegen_for(&doubles, Target::O0).is_ok());
+    let e = codegen_for(&doubles, Target::O0_32).unwrap_err();
+    assert_eq!(e.code, ErrorCode(327));
+    assert_eq!(e.message, "Type `double` is not supported on target o0-32");
+    // * At the declaration, not the whole function
+    let span = e.span.unwrap();
+    assert_eq!((span.start.ln, span.start.pos), (1, 11));
+
+    // * Values of the type count too, with nothing declared of it
+    let literal = parse("int main() {\n    print((int)1.5);\n    return 0;\n}\n").unwrap();
+    let e = codegen_for(&literal, Target::O0_32).unwrap_err();
+    assert_eq!(e.code, ErrorCode(327));
+    assert_eq!(e.span.unwrap().start.ln, 1);
+
+    let no_io = Target {
+        io: false,
+        ..Target::O0
+    };
+    assert!(codegen_for(&ints, no_io).is_err());
+}
+
+#[test]
+fn test_target_endian() {
+    let prog = parse("int main() { return 0; }").unwrap();
+    let mut big = vec![];
+    codegen_for(&prog, Target::O0)
+        .unwrap()
+        .write_binary(&mut big)
+        .unwrap();
+    assert_eq!(big[..4], [0x43, 0x30, 0x3a, 0x29]);
+
+    let little = Target {
+        endian: Endian::Little,
+        ..Target::O0
+    };
+    let mut le = vec![];
+    codegen_for(&prog, little)
+        .unwrap()
+        .write_binary(&mut le)
+        .unwrap();
+    assert_eq!(le[..4], [0x29, 0x3a, 0x30, 0x43]);
+    assert_eq!(le.len(), big.len());
+}
@@ -0,0 +1,208 @@
+use crate::c0::hir::*;
+use crate::c0::ide::{pos_at, type_at};
+use crate::c0::parser::parse_no_panic;
+use crate::c0::type_checker::lower;
+use crate::minivm::{Codegen, CompileErrorVar};
+
+const SRC: &str = "int g = 1;
+
+double half(int x) {
+    return x / 2.0;
+}
+
+void main() {
+    char c = 'a';
+    print(c + 1, half(g) * 2, 1 < 2, \"s\");
+}
+";
+
+/// Type of the innermost expression at line `ln`, column `col` of `src`
+fn type_of(src: &str, ln: usize, col: usize) -> Option<String> {
+    let prog = parse_no_panic(src).unwrap();
+    let typed = lower(&prog).unwrap();
+    type_at(&typed, pos_at(src, ln, col)).map(|(_, typ)| typ)
+}
+
+#[test]
+fn test_lower_program() {
+    let prog = parse_no_panic(SRC).unwrap();
+    let typed = lower(&prog).unwrap();
+
+    assert_eq!(typed.blk.vars.len(), 1);
+    assert_eq!(typed.blk.vars[0].name, "g");
+    assert_eq!(type_name(&typed.blk.vars[0].typ.borrow()), "int");
+
+    let names: Vec<_> = typed.fns.iter().map(|f| f.name.as_str()).collect();
+    assert_eq!(names, ["half", "main"]);
+    let half = &typed.fns[0];
+    assert_eq!(half.params.len(), 1);
+    assert_eq!(type_name(&half.return_type.borrow()), "double");
+    // * Parameters are not listed again among the variables of the body
+    assert!(half.body.as_ref().unwrap().vars.is_empty());
+    assert_eq!(typed.fns[1].body.as_ref().unwrap().vars[0].name, "c");
+
+    let body = typed.fns[1].body.as_ref().unwrap();
+    let print = match &body.stmts.last().unwrap().var {
+        StmtVariant::Print(es) => es,
+        other => panic!("{:?}", other),
+    };
+    let types: Vec<_> = print.iter().map(|e| type_name(&e.typ.borrow())).collect();
+    assert_eq!(types, ["char", "double", "bool", "&char"]);
+    match &print[1].var {
+        ExprVariant::Binary(_, lhs, _) => match &lhs.var {
+            ExprVariant::Call { func, args } => {
+                assert_eq!(func.name, "half");
+                assert!(matches!(&args[0].var, ExprVariant::Var(g) if g.name == "g"));
+            }
+            other => panic!("{:?}", other),
+        },
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn test_type_at() {
+    // * `x / 2.0` converts `x` to a double
+    assert_eq!(type_of(SRC, 3, 11).as_deref(), Some("int"));
+    assert_eq!(type_of(SRC, 3, 13).as_deref(), Some("double"));
+    assert_eq!(type_of(SRC, 3, 15).as_deref(), Some("double"));
+    assert_eq!(type_of(SRC, 8, 20).as_deref(), Some("double"));
+    assert_eq!(type_of(SRC, 8, 22).as_deref(), Some("int"));
+    assert_eq!(type_of(SRC, 0, 0), None);
+}
+
+#[test]
+fn test_lower_errors() {
+    let err = |src: &str| {
+        let prog = parse_no_panic(src).unwrap();
+        lower(&prog).unwrap_err()
+    };
+
+    let e = err("void f() {} void main() { int x = f() + 1; }");
+    assert!(matches!(e.var, CompileErrorVar::AssignVoid));
+    let e = err("int f(int a) { return a; } void main() { f(); }");
+    assert!(matches!(e.var, CompileErrorVar::ParamLengthMismatch));
+    let e = err("void main() { return 1; }");
+    assert!(matches!(e.var, CompileErrorVar::ReturnTypeMismatch(_)));
+    let e = err("const int x = 1; void main() { x = 2; }");
+    assert!(matches!(e.var, CompileErrorVar::AssignConst));
+    let e = err("void main() { int x = \"s\"; }");
+    assert!(matches!(e.var, CompileErrorVar::MakePrimitiveFromRef));
+    assert_eq!(e.span.unwrap().start.ln, 0);
+    let e = err("void main() { break; }");
+    assert!(matches!(e.var, CompileErrorVar::NoTargetToBreak));
+}
+
+#[test]
+fn test_synthetic_nodes() {
+    let src =
+        "int f(double d) { return d; }\nvoid g(int n) {}\nvoid main() { double x = 1; g(x + 2); }";
+    let prog = parse_no_panic(src).unwrap();
+    let typed = lower(&prog).unwrap();
+    let span_of = |e: &Expr| crate::c0::err::str_span(src, e.span).to_owned();
+
+    // * `return d` narrows `d` to an int
+    match &typed.fns[0].body.as_ref().unwrap().stmts[0].var {
+        StmtVariant::Return(Some(e)) => {
+            assert_eq!(e.origin, Some(SyntheticOrigin::Conversion));
+            assert_eq!(type_name(&e.typ.borrow()), "int");
+            assert_eq!(span_of(e), "d");
+        }
+        other => panic!("{:?}", other),
+    }
+
+    let stmts = &typed.fns[2].body.as_ref().unwrap().stmts;
+    match &stmts[0].var {
+        StmtVariant::Exprs(es) => {
+            assert_eq!(es[0].origin, Some(SyntheticOrigin::Initializer));
+            assert_eq!(span_of(&es[0]), "x = 1");
+            match &es[0].var {
+                ExprVariant::Assign { val, .. } => {
+                    assert_eq!(val.origin, Some(SyntheticOrigin::Conversion));
+                    assert_eq!(span_of(val), "1");
+                }
+                other => panic!("{:?}", other),
+            }
+        }
+        other => panic!("{:?}", other),
+    }
+    // * `2` is converted to a double, then `x + 2` to an int
+    match &stmts[1].var {
+        StmtVariant::Exprs(es) => {
+            assert_eq!(es[0].origin, None);
+            let arg = match &es[0].var {
+                ExprVariant::Call { args, .. } => &args[0],
+                other => panic!("{:?}", other),
+            };
+            assert_eq!(arg.origin, Some(SyntheticOrigin::Conversion));
+            assert_eq!(span_of(arg), "x + 2");
+            match &arg.var {
+                ExprVariant::Conv(sum) => match &sum.var {
+                    ExprVariant::Binary(_, lhs, rhs) => {
+                        assert_eq!(lhs.origin, None);
+                        assert_eq!(rhs.origin, Some(SyntheticOrigin::Conversion));
+                        assert_eq!(type_name(&rhs.typ.borrow()), "double");
+                    }
+                    other => panic!("{:?}", other),
+                },
+                other => panic!("{:?}", other),
+            }
+        }
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn test_infer_auto() {
+    let src = "auto g = 2;
+double half(int x) { return x / 2.0; }
+void main() {
+    auto h = half(g);
+    auto s;
+    s = 'a';
+    print(g, h, s);
+}
+";
+    let prog = parse_no_panic(src).unwrap();
+    let typed = lower(&prog).unwrap();
+    assert_eq!(type_name(&typed.blk.vars[0].typ.borrow()), "int");
+    let vars = &typed.fns[1].body.as_ref().unwrap().vars;
+    let types: Vec<_> = vars.iter().map(|v| type_name(&v.typ.borrow())).collect();
+    assert_eq!(types, ["double", "char"]);
+    assert_eq!(typed.inferred.len(), 3);
+    Codegen::new(&prog).check().unwrap();
+
+    let err = |src: &str| {
+        let prog = parse_no_panic(src).unwrap();
+        lower(&prog).unwrap_err()
+    };
+    let e = err("void main() { auto x; print(x); }");
+    assert!(matches!(e.var, CompileErrorVar::CannotInferType(ref x) if x == "x"));
+    let e = err("void main() { auto x; }");
+    assert!(matches!(e.var, CompileErrorVar::CannotInferType(_)));
+    let e = err("void f() {} void main() { auto x = f(); }");
+    assert!(matches!(e.var, CompileErrorVar::VoidVariable(_)));
+}
+
+#[test]
+fn test_lower_agrees_with_codegen() {
+    let cases = std::fs::read_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/cases")).unwrap();
+    for case in cases {
+        let path = case.unwrap().path();
+        if path.extension().is_none_or(|e| e != "c0") {
+            continue;
+        }
+        let src = std::fs::read_to_string(&path).unwrap();
+        let prog = match parse_no_panic(&src) {
+            Ok(prog) => prog,
+            Err(_) => continue,
+        };
+        // * Code generation also checks control flow, so it may fail where
+        // * types are fine, but not the other way around
+        let typed = lower(&prog).map(|_| ()).map_err(|e| e.var.code());
+        let compiled = Codegen::new(&prog).check().map_err(|e| e.var.code());
+        if typed.is_err() || compiled.is_ok() {
+            assert_eq!(typed, compiled, "{}", path.display());
+        }
+    }
+}
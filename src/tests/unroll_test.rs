@@ -0,0 +1,102 @@
+use super::exec;
+use crate::minivm::*;
+use crate::parse;
+
+/// Output of `src` compiled with unrolling factor `factor`, and the jumps
+/// and stores in `main`
+fn run(src: &str, factor: u32) -> (String, usize, usize) {
+    let prog = parse(src).unwrap();
+    let o0 = Codegen::new(&prog)
+        .with_passes(passes())
+        .with_unroll(factor, 256)
+        .compile()
+        .unwrap();
+    let main = o0.functions.last().unwrap();
+    let count = |f: fn(&Inst) -> bool| main.ins.iter().filter(|i| f(i)).count();
+    let jumps = count(|i| matches!(i, Inst::Jmp(_) | Inst::JNe(_) | Inst::JE(_)));
+    let stores = count(|i| matches!(i, Inst::IStore));
+    (exec(&o0, "").1, jumps, stores)
+}
+
+/// Passes of `-O1` but those on locals, which would compute the copies of a
+/// loop body running a known number of times away
+fn passes() -> PassManager {
+    let on_locals = [Pass::Sccp, Pass::CopyProp, Pass::Dse];
+    let pipeline = PassManager::default().pipeline().to_vec();
+    let names: Vec<_> = (pipeline.iter())
+        .filter(|pass| !on_locals.contains(pass))
+        .map(|pass| pass.name())
+        .collect();
+    PassManager::new().with_pipeline(&names.join(",")).unwrap()
+}
+
+fn counting(init: &str, cond: &str, step: &str) -> String {
+    format!(
+        "int main() {{\n    int i = {};\n    int s = 0;\n    while ({}) {{\n        \
+         int t = i * i;\n        s = s + t;\n        i = {};\n    }}\n    \
+         print(s, i);\n    return 0;\n}}\n",
+        init, cond, step
+    )
+}
+
+#[test]
+fn test_full_unroll() {
+    let src = counting("0", "i < 4", "i + 1");
+    let (out, jumps, stores) = run(&src, 0);
+    assert_eq!(out, "14 4\n");
+    assert!(jumps > 0);
+    // * Three stores in the body, one copy of it
+    assert_eq!(stores, 2 + 3);
+
+    assert_eq!(run(&src, 8), (out, 0, 2 + 4 * 3));
+}
+
+#[test]
+fn test_trip_counts() {
+    let cases = [
+        ("10", "i >= 1", "i - 3", "166 -2\n", 4),
+        ("0", "i <= 6", "i + 2", "56 8\n", 4),
+        ("1", "i != 7", "i + 3", "17 7\n", 2),
+        ("5", "i < 5", "i + 1", "0 5\n", 0),
+    ];
+    for (init, cond, step, out, trips) in cases.iter() {
+        let src = counting(init, cond, step);
+        assert_eq!(run(&src, 0).0, *out, "{}", src);
+        assert_eq!(run(&src, 8), (out.to_string(), 0, 2 + trips * 3), "{}", src);
+    }
+}
+
+#[test]
+fn test_partial_unroll() {
+    // * 12 iterations take more than 4 copies, so the loop runs 4 copies 3
+    // * times. It is entered without checking its condition first, so only
+    // * the jump back is left.
+    let src = counting("0", "i < 12", "i + 1");
+    let (out, jumps, _) = run(&src, 0);
+    assert_eq!(out, "506 12\n");
+    assert!(jumps > 1);
+    assert_eq!(run(&src, 4), (out, 1, 2 + 4 * 3));
+}
+
+#[test]
+fn test_not_unrolled() {
+    let loops = [
+        // * Bound not constant
+        "int n = 3;\n    while (i < n) {\n        s = s + i;\n        i = i + 1;\n    }",
+        // * Counter written in the body
+        "while (i < 3) {\n        i = i + s;\n        s = s + 1;\n        i = i + 1;\n    }",
+        // * Breaks out
+        "while (i < 3) {\n        if (s > 0) break;\n        s = s + 1;\n        i = i + 1;\n    }",
+        // * Address of the counter taken
+        "&int p = &i;\n    while (i < 3) {\n        *p = *p + 0;\n        i = i + 1;\n    }",
+        // * Step of zero never ends
+        "while (i < 3) {\n        s = s + 1;\n        if (s > 5) break;\n        i = i + 0;\n    }",
+    ];
+    for body in loops.iter() {
+        let src = format!(
+            "int main() {{\n    int i = 0;\n    int s = 0;\n    {}\n    print(s, i);\n    return 0;\n}}\n",
+            body
+        );
+        assert_eq!(run(&src, 8), run(&src, 0), "{}", src);
+    }
+}
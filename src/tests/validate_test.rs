@@ -0,0 +1,60 @@
+use crate::c0::ast::*;
+use crate::c0::lexer::Lexer;
+use crate::c0::parser::Parser;
+use crate::c0::validate::*;
+use crate::lex;
+
+#[test]
+fn test_validate_cases() {
+    for entry in std::fs::read_dir("tests/cases").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_none_or(|ext| ext != "c0") {
+            continue;
+        }
+        let src = std::fs::read_to_string(&path).unwrap();
+        validate_tokens(&lex(&src)).unwrap();
+        if let Ok(prog) = Parser::new(Lexer::new(&src)).parse() {
+            if let Err(e) = validate(&prog) {
+                panic!("{}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_validate_catches_broken_trees() {
+    let mut tokens = lex("int a = 1;");
+    validate_tokens(&tokens).unwrap();
+    tokens.swap(1, 2);
+    assert!(validate_tokens(&tokens).is_err());
+
+    let src = "int main() {\n    int a = 1;\n    return a;\n}\n";
+    let prog = Parser::new(Lexer::new(src)).parse().unwrap();
+    validate(&prog).unwrap();
+    let main = prog.blk.scope.borrow().find_def_self("main").unwrap();
+    if let SymbolDef::Var { typ, .. } = &*main.borrow() {
+        if let TypeDef::Function(func) = &mut *typ.borrow_mut() {
+            func.body.as_mut().unwrap().stmts.swap(0, 1);
+        }
+    }
+    let e = validate(&prog).unwrap_err();
+    assert_eq!(e.span.start.ln, 1);
+    assert!(e.what.contains("before"), "{}", e);
+}
+
+#[test]
+fn test_postfix_spans_cover_operand() {
+    let src = "int a;\nint b = a++;\n";
+    let prog = Parser::new(Lexer::new(src)).parse().unwrap();
+    validate(&prog).unwrap();
+    match &prog.blk.stmts[1].var {
+        StmtVariant::ManyExpr(es) => match &es[0].borrow().var {
+            ExprVariant::BinaryOp(b) => {
+                let span = b.rhs.borrow().span;
+                assert_eq!((span.start.pos, span.end.pos), (8, 11));
+            }
+            other => panic!("{:?}", other),
+        },
+        other => panic!("{:?}", other),
+    }
+}
@@ -0,0 +1,121 @@
+use super::run;
+use crate::minivm::value::*;
+use std::cmp::Ordering;
+
+#[test]
+fn test_int_arithmetic_wraps() {
+    let max = Value::Int(i32::MAX);
+    assert_eq!(
+        max.binary(BinOp::Add, &Value::Int(1)),
+        Ok(Value::Int(i32::MIN))
+    );
+    assert_eq!(
+        max.checked_binary(BinOp::Add, &Value::Int(1)),
+        Err(ValueError::Overflow)
+    );
+    let min = Value::Int(i32::MIN);
+    assert_eq!(min.binary(BinOp::Div, &Value::Int(-1)), Ok(min.clone()));
+    assert_eq!(min.unary(UnOp::Neg), Ok(min.clone()));
+    assert_eq!(min.checked_unary(UnOp::Neg), Err(ValueError::Overflow));
+}
+
+#[test]
+fn test_division() {
+    let div = |l: i32, r: i32| Value::Int(l).binary(BinOp::Div, &Value::Int(r));
+    assert_eq!(div(7, -2), Ok(Value::Int(-3)));
+    assert_eq!(div(-7, 2), Ok(Value::Int(-3)));
+    assert_eq!(div(1, 0), Err(ValueError::DivideByZero));
+    assert_eq!(
+        Value::Double(1.0).binary(BinOp::Div, &Value::Int(0)),
+        Ok(Value::Double(f64::INFINITY))
+    );
+}
+
+#[test]
+fn test_promotion() {
+    assert_eq!(
+        Value::Char(b'a').binary(BinOp::Add, &Value::Bool(true)),
+        Ok(Value::Int(98))
+    );
+    assert_eq!(
+        Value::Int(1).binary(BinOp::Div, &Value::Double(4.0)),
+        Ok(Value::Double(0.25))
+    );
+    assert_eq!(
+        Value::Int(-1).binary(BinOp::Gt, &Value::UInt(1)),
+        Ok(Value::Int(1))
+    );
+    assert_eq!(Value::Char(b'a').unary(UnOp::Pos), Ok(Value::Int(97)));
+    assert!(matches!(
+        Value::Double(1.0).binary(BinOp::BitXor, &Value::Int(1)),
+        Err(ValueError::Unsupported(_))
+    ));
+    assert_eq!(
+        Value::Struct(vec![]).binary(BinOp::Add, &Value::Int(1)),
+        Err(ValueError::Unsupported("`+` on struct".into()))
+    );
+}
+
+#[test]
+fn test_comparison() {
+    let nan = Value::Double(f64::NAN);
+    assert_eq!(nan.compare(&Value::Int(0)), Ok(None));
+    assert_eq!(nan.binary(BinOp::Eq, &nan), Ok(Value::Int(0)));
+    assert_eq!(nan.binary(BinOp::Ne, &nan), Ok(Value::Int(1)));
+    assert_eq!(
+        Value::Char(b'b').compare(&Value::Double(97.5)),
+        Ok(Some(Ordering::Greater))
+    );
+    assert_eq!(Value::Int(0).unary(UnOp::Not), Ok(Value::Int(1)));
+    assert_eq!(Value::Void.is_true(), Err(ValueError::Void));
+}
+
+#[test]
+fn test_cast() {
+    assert_eq!(Value::Double(-2.9).cast(Kind::Int), Ok(Value::Int(-2)));
+    assert_eq!(Value::Double(3e9).cast(Kind::Int), Ok(Value::Int(i32::MAX)));
+    assert_eq!(
+        Value::Double(3e9).checked_cast(Kind::Int),
+        Err(ValueError::Overflow)
+    );
+    assert_eq!(Value::Int(321).cast(Kind::Char), Ok(Value::Char(65)));
+    assert_eq!(
+        Value::Int(321).checked_cast(Kind::Char),
+        Err(ValueError::Overflow)
+    );
+    assert_eq!(Value::Double(0.5).cast(Kind::Bool), Ok(Value::Bool(true)));
+    assert_eq!(Kind::Double.zero(), Value::Double(0.0));
+}
+
+#[test]
+fn test_display() {
+    assert_eq!(Value::Double(0.1).to_string(), "0.100000");
+    assert_eq!(Value::Double(0.0078125).to_string(), "0.007812");
+    assert_eq!(Value::Double(-0.0).to_string(), "-0.000000");
+    assert_eq!(Value::Double(f64::NAN).to_string(), "nan");
+    assert_eq!(Value::Double(f64::NEG_INFINITY).to_string(), "-inf");
+    assert_eq!(Value::Char(b'x').to_string(), "x");
+    assert_eq!(Value::Bool(true).to_string(), "1");
+    let arr = Value::Array(vec![Value::Int(1), Value::Int(2)]);
+    assert_eq!(arr.to_string(), "{1, 2}");
+}
+
+#[test]
+fn test_nan_in_vm() {
+    let src = "int main() {
+    double nan = 0.0 / 0.0;
+    print(nan, 1.0 / 0.0);
+    if (nan == nan) print(1); else print(0);
+    if (nan != nan) print(1); else print(0);
+    if (nan < 1.0) print(1); else print(0);
+    if (nan <= 1.0) print(1); else print(0);
+    if (nan > 1.0) print(1); else print(0);
+    if (nan >= 1.0) print(1); else print(0);
+    if (1.0 > nan) print(1); else print(0);
+    if (1.0 >= nan) print(1); else print(0);
+    int lt = nan < nan, gt = nan > nan, ge = nan >= nan;
+    print(lt, gt, ge);
+    return 0;
+}";
+    assert_eq!(run(src), "nan inf\n0\n1\n0\n0\n0\n0\n0\n0\n0 0 0\n");
+}
@@ -0,0 +1,120 @@
+use crate::minivm::*;
+use crate::{codegen, parse};
+
+fn o0(start: Vec<Inst>, functions: Vec<(u16, Vec<Inst>)>) -> O0 {
+    O0 {
+        version: 1,
+        constants: vec![Constant::String(b"f".to_vec()), Constant::Float(1.5)],
+        start_code: StartCodeInfo { ins: start },
+        functions: functions
+            .into_iter()
+            .map(|(param_siz, ins)| FnInfo {
+                name_idx: 0,
+                param_siz,
+                lvl: 1,
+                ins,
+            })
+            .collect(),
+        endian: Endian::Big,
+        debug: None,
+        relocs: vec![],
+        host_fns: vec![],
+    }
+}
+
+fn kind_of(o0: &O0) -> VerifyErrorKind {
+    verify(o0).unwrap_err().kind
+}
+
+#[test]
+fn test_verify_compiled_cases() {
+    for entry in std::fs::read_dir("tests/cases").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|e| e == "c0") {
+            let src = std::fs::read_to_string(&path).unwrap();
+            // * Cases expected to fail compiling have nothing to verify
+            if let Ok(o0) = parse(&src)
+                .map_err(|_| ())
+                .and_then(|p| codegen(&p).map_err(|_| ()))
+            {
+                assert_eq!(verify(&o0), Ok(()), "{}", path.display());
+            }
+        }
+    }
+}
+
+#[test]
+fn test_verify_accepts_branches() {
+    use Inst::*;
+    let f = vec![
+        LoadA(0, 0),
+        ILoad,
+        JE(5),
+        IPush(1),
+        IRet,
+        LoadC(1),
+        D2I,
+        IRet,
+    ];
+    let call = vec![SNew(1), IPush(3), Call(0), Pop1];
+    assert_eq!(verify(&o0(call, vec![(1, f)])), Ok(()));
+}
+
+#[test]
+fn test_verify_references() {
+    use Inst::*;
+    use VerifyErrorKind::*;
+    assert_eq!(kind_of(&o0(vec![LoadC(2)], vec![])), BadConstant(2));
+    assert_eq!(kind_of(&o0(vec![Call(0)], vec![])), BadFunction(0));
+    assert_eq!(kind_of(&o0(vec![LoadA(0, 0)], vec![])), BadFrame(0, 0));
+    assert_eq!(
+        kind_of(&o0(vec![SNew(1)], vec![(0, vec![LoadA(2, 0), Ret])])),
+        BadFrame(2, 0)
+    );
+    assert_eq!(kind_of(&o0(vec![Jmp(1)], vec![])), BadJump(1));
+    assert_eq!(kind_of(&o0(vec![_Gt], vec![])), PseudoInstruction);
+}
+
+#[test]
+fn test_verify_stack() {
+    use Inst::*;
+    use VerifyErrorKind::*;
+    assert_eq!(kind_of(&o0(vec![IAdd], vec![])), StackUnderflow);
+    assert_eq!(
+        kind_of(&o0(vec![IPush(0), JE(3), IPush(1), Nop], vec![])),
+        StackMismatch(0, 1)
+    );
+    assert_eq!(
+        kind_of(&o0(vec![LoadC(0), IPrint], vec![])),
+        TypeMismatch {
+            expected: Slot::Int,
+            found: Slot::Addr
+        }
+    );
+    assert_eq!(
+        kind_of(&o0(vec![SNew(1), LoadA(0, 0), LoadC(0), IStore], vec![])),
+        TypeMismatch {
+            expected: Slot::Int,
+            found: Slot::Addr
+        }
+    );
+}
+
+#[test]
+fn test_verify_returns() {
+    use Inst::*;
+    use VerifyErrorKind::*;
+    let err = verify(&o0(vec![], vec![(0, vec![IPush(1)])])).unwrap_err();
+    assert_eq!(err.kind, FallsOffEnd);
+    assert_eq!(
+        err.to_string(),
+        ".F0:1: Control reaches the end of function without returning"
+    );
+    assert_eq!(
+        kind_of(&o0(
+            vec![],
+            vec![(0, vec![IPush(0), JE(3), Ret, IPush(1), IRet])]
+        )),
+        ReturnMismatch
+    );
+}
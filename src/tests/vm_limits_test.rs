@@ -0,0 +1,101 @@
+use crate::minivm::vm::{Limit, MiniVM, VmError};
+use crate::minivm::*;
+use std::time::Duration;
+
+/// A program whose `main` runs `ins`
+fn o0(ins: Vec<Inst>) -> O0 {
+    O0 {
+        version: 1,
+        constants: vec![Constant::String(b"main".to_vec())],
+        start_code: StartCodeInfo { ins: vec![] },
+        functions: vec![FnInfo {
+            name_idx: 0,
+            param_siz: 0,
+            lvl: 1,
+            ins,
+        }],
+        endian: Endian::Big,
+        debug: None,
+        relocs: vec![],
+        host_fns: vec![],
+    }
+}
+
+fn limit_of(o0: &O0, f: impl FnOnce(MiniVM) -> MiniVM) -> Option<Limit> {
+    let mut input = "".as_bytes();
+    let mut output = vec![];
+    let mut vm = f(MiniVM::new(o0, &mut input, &mut output));
+    match vm.run() {
+        Err(VmError::LimitExceeded(limit)) => Some(limit),
+        Ok(_) => None,
+        Err(e) => panic!("unexpected error: {}", e),
+    }
+}
+
+#[test]
+fn test_step_limit() {
+    let o0 = o0(vec![Inst::Jmp(0)]);
+    assert_eq!(
+        limit_of(&o0, |vm| vm.with_step_limit(1000)),
+        Some(Limit::Steps(1000))
+    );
+}
+
+#[test]
+fn test_timeout() {
+    let o0 = o0(vec![Inst::Jmp(0)]);
+    let timeout = Duration::from_millis(50);
+    assert_eq!(
+        limit_of(&o0, |vm| vm.with_timeout(timeout)),
+        Some(Limit::Time(timeout))
+    );
+}
+
+#[test]
+fn test_call_depth_limit() {
+    let o0 = o0(vec![Inst::Call(0), Inst::Ret]);
+    assert_eq!(
+        limit_of(&o0, |vm| vm.with_call_depth_limit(100)),
+        Some(Limit::CallDepth(100))
+    );
+}
+
+#[test]
+fn test_stack_limit() {
+    let o0 = o0(vec![Inst::IPush(1), Inst::Jmp(0)]);
+    assert_eq!(
+        limit_of(&o0, |vm| vm.with_stack_limit(100)),
+        Some(Limit::StackSlots(100))
+    );
+    let o0 = self::o0(vec![Inst::SNew(1000), Inst::Ret]);
+    assert_eq!(
+        limit_of(&o0, |vm| vm.with_stack_limit(100)),
+        Some(Limit::StackSlots(100))
+    );
+}
+
+#[test]
+fn test_heap_limit() {
+    let new = |slots| o0(vec![Inst::IPush(slots), Inst::New, Inst::Pop1, Inst::Ret]);
+    assert_eq!(limit_of(&new(256), |vm| vm.with_heap_limit(1024)), None);
+    assert_eq!(
+        limit_of(&new(257), |vm| vm.with_heap_limit(1024)),
+        Some(Limit::HeapBytes(1024))
+    );
+}
+
+fn all_limits(vm: MiniVM) -> MiniVM {
+    vm.with_step_limit(1000)
+        .with_stack_limit(10)
+        .with_call_depth_limit(10)
+        .with_heap_limit(10)
+        .with_timeout(Duration::from_secs(10))
+}
+
+#[test]
+fn test_limits_within_bounds() {
+    let ok = o0(vec![Inst::IPush(1), Inst::IRet]);
+    assert_eq!(limit_of(&ok, all_limits), None);
+    let recursive = o0(vec![Inst::Call(0), Inst::Ret]);
+    assert_eq!(limit_of(&recursive, all_limits), Some(Limit::CallDepth(10)));
+}
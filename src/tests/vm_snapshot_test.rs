@@ -0,0 +1,179 @@
+use super::{compile, exec};
+use crate::minivm::binfmt::{read_snapshot, write_snapshot};
+use crate::minivm::vm::{Limit, MiniVM, RunState, SavedFrame, Snapshot, VmError};
+
+const SRC: &str = r#"
+int calls;
+
+int fib(int n) {
+    calls = calls + 1;
+    if (n <= 1) return n;
+    return fib(n - 1) + fib(n - 2);
+}
+
+int main() {
+    int i = 0;
+    while (i < 12) {
+        print(fib(i));
+        i = i + 1;
+    }
+    return calls;
+}
+"#;
+
+/// Exit code and output of running `SRC` without stopping
+fn uninterrupted() -> (i32, String) {
+    exec(&compile(SRC), "")
+}
+
+#[test]
+fn test_resume_from_saved_snapshot() {
+    let o0 = compile(SRC);
+
+    let mut input = "".as_bytes();
+    let mut before = vec![];
+    let mut vm = MiniVM::new(&o0, &mut input, &mut before).with_step_limit(2000);
+    match vm.run() {
+        Err(VmError::LimitExceeded(Limit::Steps(2000))) => (),
+        res => panic!("expected to stop at the step limit, got {:?}", res),
+    }
+    let snapshot = vm.snapshot();
+    assert_eq!(snapshot.steps, 2000);
+    assert!(snapshot.frames.len() > 2, "stopped inside `fib`");
+
+    let mut file = vec![];
+    write_snapshot(&snapshot, &mut file).unwrap();
+    let snapshot = read_snapshot(&mut file.as_slice()).unwrap();
+
+    let mut input = "".as_bytes();
+    let mut after = vec![];
+    let mut vm = MiniVM::new(&o0, &mut input, &mut after);
+    vm.restore(&snapshot).unwrap();
+    let code = vm.resume().unwrap();
+
+    let output = String::from_utf8(before).unwrap() + &String::from_utf8(after).unwrap();
+    assert_eq!((code, output), uninterrupted());
+}
+
+#[test]
+fn test_restore_goes_back() {
+    let o0 = compile(SRC);
+    let mut input = "".as_bytes();
+    let mut output = vec![];
+    let mut vm = MiniVM::new(&o0, &mut input, &mut output).with_step_limit(500);
+    assert!(vm.run().is_err());
+    let early = vm.snapshot();
+
+    vm = vm.with_step_limit(u64::MAX);
+    let first = vm.resume().unwrap();
+    assert_ne!(vm.snapshot(), early);
+    vm.restore(&early).unwrap();
+    assert_eq!(vm.snapshot(), early);
+    assert_eq!(vm.resume().unwrap(), first);
+}
+
+#[test]
+fn test_resume_before_run() {
+    let o0 = compile(SRC);
+    let mut input = "".as_bytes();
+    let mut output = vec![];
+    let code = MiniVM::new(&o0, &mut input, &mut output).resume().unwrap();
+    assert_eq!((code, String::from_utf8(output).unwrap()), uninterrupted());
+}
+
+#[test]
+fn test_run_steps() {
+    let o0 = compile(SRC);
+    let mut input = "".as_bytes();
+    let mut output = vec![];
+    let mut vm = MiniVM::new(&o0, &mut input, &mut output);
+    let mut slices = 0;
+    let code = loop {
+        match vm.run_steps(100) {
+            RunState::Paused => slices += 1,
+            RunState::Finished(code) => break code,
+            RunState::Trapped(e) => panic!("{}", e),
+        }
+    };
+    assert!(slices > 10);
+    assert!(matches!(vm.run_steps(100), RunState::Finished(c) if c == code));
+    drop(vm);
+    assert_eq!((code, String::from_utf8(output).unwrap()), uninterrupted());
+
+    let o0 = compile("int main() {\n    return 1 / 0;\n}\n");
+    let mut output = vec![];
+    let mut vm = MiniVM::new(&o0, &mut input, &mut output);
+    assert!(matches!(
+        vm.run_steps(100),
+        RunState::Trapped(VmError::DivideByZero)
+    ));
+}
+
+#[test]
+fn test_restore_bad_snapshot() {
+    let o0 = compile(SRC);
+    let mut input = "".as_bytes();
+    let mut output = vec![];
+    let mut vm = MiniVM::new(&o0, &mut input, &mut output);
+    let frame = |func| SavedFrame {
+        func,
+        base: 0,
+        ip: 0,
+    };
+
+    let snapshot = Snapshot {
+        frames: vec![frame(None), frame(Some(100))],
+        ..Snapshot::default()
+    };
+    assert!(matches!(
+        vm.restore(&snapshot),
+        Err(VmError::BadFunction(100))
+    ));
+
+    let snapshot = Snapshot {
+        frames: vec![frame(Some(0))],
+        ..Snapshot::default()
+    };
+    assert!(matches!(
+        vm.restore(&snapshot),
+        Err(VmError::BadSnapshot(_))
+    ));
+
+    let snapshot = Snapshot {
+        heap: vec![0; 2],
+        allocs: vec![(1, 2)],
+        ..Snapshot::default()
+    };
+    assert!(matches!(
+        vm.restore(&snapshot),
+        Err(VmError::BadSnapshot(_))
+    ));
+
+    assert!(read_snapshot(&mut [0u8; 16].as_slice()).is_err());
+}
+
+#[test]
+fn test_step_back() {
+    let o0 = compile(SRC);
+    let mut input = "".as_bytes();
+    let mut output = vec![];
+    let mut vm = MiniVM::new(&o0, &mut input, &mut output).with_history(100);
+
+    let mut states = vec![vm.snapshot()];
+    for _ in 0..300 {
+        assert_eq!(vm.step().unwrap(), None);
+        states.push(vm.snapshot());
+    }
+    assert_eq!(vm.step_back(1), 1);
+    assert_eq!(vm.snapshot(), states[299]);
+    assert_eq!(vm.step_back(50), 50);
+    assert_eq!(vm.snapshot(), states[249]);
+    // * Only the last 100 steps are kept, one of them gone back over already
+    assert_eq!(vm.step_back(1000), 49);
+    assert_eq!(vm.snapshot(), states[200]);
+    assert_eq!(vm.step_back(1), 0);
+
+    let code = vm.resume().unwrap();
+    assert_eq!(code, uninterrupted().0);
+    assert_eq!(vm.step().unwrap(), Some(code));
+}
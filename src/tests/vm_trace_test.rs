@@ -0,0 +1,63 @@
+use crate::minivm::vm::{MiniVM, TraceKind};
+use crate::minivm::Codegen;
+use crate::parse;
+
+const SRC: &str = r#"
+int twice(int x) {
+    return x + x;
+}
+
+int main() {
+    int i = 0;
+    while (i < 2) {
+        print(twice(i));
+        i = i + 1;
+    }
+    return 0;
+}
+"#;
+
+fn trace_of(kind: TraceKind, functions: &[&str]) -> String {
+    // * The loop is kept, for its jumps to show
+    let o0 = Codegen::new(&parse(SRC).unwrap())
+        .with_debug_info(true)
+        .with_unroll(0, 0)
+        .compile()
+        .unwrap();
+    let mut input = "".as_bytes();
+    let mut output = vec![];
+    let mut trace = vec![];
+    MiniVM::new(&o0, &mut input, &mut output)
+        .with_trace(kind, functions, &mut trace)
+        .run()
+        .unwrap();
+    String::from_utf8(trace).unwrap()
+}
+
+#[test]
+fn test_trace_calls() {
+    let trace = trace_of(TraceKind::Calls, &[]);
+    let lines: Vec<_> = trace.lines().collect();
+    assert_eq!(lines.len(), 5, "{}", trace);
+    assert!(lines[0].starts_with("  main ") && lines[0].contains("call 0 (0)  ; line 9"));
+    assert!(lines[1].starts_with("    twice ") && lines[1].contains("iret (0)  ; line 3"));
+    assert!(lines[2].contains("call 0 (1)"));
+    assert!(lines[3].contains("iret (2)"));
+    assert!(lines[4].contains("iret (0)  ; line 12"));
+}
+
+#[test]
+fn test_trace_jumps() {
+    let trace = trace_of(TraceKind::Jumps, &[]);
+    assert!(!trace.is_empty());
+    assert!(trace.lines().all(|l| l.trim_start().starts_with("main ")));
+    assert!(trace.lines().all(|l| l.contains(": j")), "{}", trace);
+}
+
+#[test]
+fn test_trace_functions() {
+    let trace = trace_of(TraceKind::All, &["twice"]);
+    assert!(trace.lines().all(|l| l.trim_start().starts_with("twice ")));
+    assert!(trace.contains("iadd (1, 1)  ; line 3"), "{}", trace);
+    assert_eq!(trace_of(TraceKind::All, &["none"]), "");
+}
@@ -0,0 +1,139 @@
+//! `--time-passes`: wall time and heap memory used by each compiler pass.
+//!
+//! Every pass also runs in a `pass` tracing span, whether timed or not.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// The system allocator, keeping count of live and peak heap bytes.
+pub struct CountingAlloc;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+fn record_alloc(size: usize) {
+    let now = ALLOCATED.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK.fetch_max(now, Ordering::Relaxed);
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            record_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+            record_alloc(new_size);
+        }
+        new_ptr
+    }
+}
+
+struct PassStat {
+    name: &'static str,
+    time: Duration,
+    /// Peak heap usage during this pass, above what was live when it started
+    peak: usize,
+    /// Heap still held when this pass ends, compared to when it started
+    retained: isize,
+}
+
+/// Collects statistics of passes if enabled. Does nothing otherwise.
+pub struct PassTimes {
+    enabled: bool,
+    passes: Vec<PassStat>,
+    /// Peak heap usage of all passes so far
+    peak: usize,
+}
+
+impl PassTimes {
+    pub fn new(enabled: bool) -> PassTimes {
+        PassTimes {
+            enabled,
+            passes: vec![],
+            peak: 0,
+        }
+    }
+
+    /// Run `f` as pass `name`, inside a tracing span of the same name
+    pub fn time<T>(&mut self, name: &'static str, f: impl FnOnce() -> T) -> T {
+        let _span = tracing::info_span!("pass", name).entered();
+        crate::ice::set_phase(name);
+        if !self.enabled {
+            return f();
+        }
+
+        let before = ALLOCATED.load(Ordering::Relaxed);
+        PEAK.store(before, Ordering::Relaxed);
+        let start = Instant::now();
+
+        let res = f();
+
+        let time = start.elapsed();
+        let after = ALLOCATED.load(Ordering::Relaxed);
+        let peak = PEAK.load(Ordering::Relaxed);
+        self.peak = self.peak.max(peak);
+        self.passes.push(PassStat {
+            name,
+            time,
+            peak: peak - before,
+            retained: after as isize - before as isize,
+        });
+        res
+    }
+
+    /// Highest heap usage seen while running passes, in bytes
+    pub fn peak(&self) -> usize {
+        self.peak
+    }
+
+    /// Print collected statistics to stderr
+    pub fn report(&self) {
+        if !self.enabled {
+            return;
+        }
+
+        eprintln!(
+            "{:<10} {:>12} {:>12} {:>12}",
+            "pass", "time", "peak mem", "retained"
+        );
+        for pass in &self.passes {
+            eprintln!(
+                "{:<10} {:>12} {:>12} {:>12}",
+                pass.name,
+                format!("{:.3?}", pass.time),
+                bytes(pass.peak as f64),
+                bytes(pass.retained as f64)
+            );
+        }
+        let total: Duration = self.passes.iter().map(|p| p.time).sum();
+        eprintln!("{:<10} {:>12}", "total", format!("{:.3?}", total));
+    }
+}
+
+pub fn bytes(n: f64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut n = n;
+    let mut unit = 0;
+    while n.abs() >= 1024.0 && unit < UNITS.len() - 1 {
+        n /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", n, UNITS[unit])
+    } else {
+        format!("{:.1} {}", n, UNITS[unit])
+    }
+}
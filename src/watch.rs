@@ -0,0 +1,113 @@
+//! `chigusa watch`: recompile and rerun a program whenever it is saved.
+
+use crate::err_disp;
+use crate::source::{self, LoadError};
+use chigusa::c0::symbol::with_fresh_names;
+use chigusa::{Diagnostic, Severity};
+use notify::{RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// Editors often save a file in several writes; changes this close together
+/// are handled once.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Check `file`, then run `chigusa <args>` with `file` added, once now and
+/// again each time the file changes. Only returns if watching fails.
+pub fn watch(file: &Path, args: &[String], defines: &[String]) -> Result<(), String> {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).map_err(|e| e.to_string())?;
+    // * Editors may save by replacing the file, which ends a watch on the file
+    // * itself, so the directory is watched instead
+    let dir = match file.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    watcher
+        .watch(dir, RecursiveMode::NonRecursive)
+        .map_err(|e| e.to_string())?;
+    let file_name = file.file_name().ok_or("not a file")?.to_owned();
+
+    loop {
+        eprintln!("[watch] {}", file.display());
+        with_fresh_names(|| rerun(file, args, defines));
+        eprintln!("[watch] waiting for changes");
+
+        // * Wait for a change to the file, then let the burst of events settle
+        loop {
+            let event = rx.recv().map_err(|e| e.to_string())?;
+            let event = event.map_err(|e| e.to_string())?;
+            if event
+                .paths
+                .iter()
+                .any(|p| p.file_name() == Some(&file_name))
+            {
+                break;
+            }
+        }
+        while rx.recv_timeout(DEBOUNCE).is_ok() {}
+    }
+}
+
+fn rerun(file: &Path, args: &[String], defines: &[String]) {
+    let diags = match source::load(file, defines) {
+        Ok(src) => match chigusa::parse(src.compiled()) {
+            Ok(prog) => chigusa::check(&prog),
+            Err(e) => vec![Diagnostic::from(src.annotate(e))],
+        },
+        Err(LoadError::Compile(e)) => vec![Diagnostic::from(e)],
+        Err(e) => {
+            eprintln!("{}: {}", file.display(), e);
+            return;
+        }
+    };
+    for d in &diags {
+        eprintln!("{}", err_disp::concise(file, d));
+    }
+    if diags.iter().any(|d| d.severity == Severity::Error) {
+        return;
+    }
+    if args.is_empty() {
+        eprintln!("{}: ok", file.display());
+        return;
+    }
+
+    let exe = match std::env::current_exe() {
+        Ok(exe) => exe,
+        Err(e) => {
+            eprintln!("[watch] cannot find chigusa: {}", e);
+            return;
+        }
+    };
+    match Command::new(exe)
+        .args(command_line(file, args, defines))
+        .status()
+    {
+        Ok(status) => match status.code() {
+            Some(code) => eprintln!("[watch] exited with code {}", code),
+            None => eprintln!("[watch] killed"),
+        },
+        Err(e) => eprintln!("[watch] cannot run chigusa: {}", e),
+    }
+}
+
+/// Arguments to run `args` on `file` with `defines`: the file goes after the
+/// subcommand, as in `run <file> ...`, or first if there is none, as in
+/// `<file> -s ...`, and the features defined before both
+fn command_line(file: &Path, args: &[String], defines: &[String]) -> Vec<PathBuf> {
+    let mut line: Vec<PathBuf> = args.iter().map(PathBuf::from).collect();
+    let at = match args.first() {
+        Some(arg) if !arg.starts_with('-') => 1,
+        _ => 0,
+    };
+    line.insert(at, file.to_owned());
+    for name in defines.iter().rev() {
+        line.splice(
+            0..0,
+            [PathBuf::from("-D"), PathBuf::from(name)].iter().cloned(),
+        );
+    }
+    line
+}
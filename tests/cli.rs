@@ -0,0 +1,291 @@
+//! Tests of the command line. Each runs the `chigusa` binary on files written
+//! to a directory of its own, and checks its exit code and output.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// An empty directory for test `name`
+fn dir(name: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Run `chigusa <args>` in `dir`
+fn chigusa(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_chigusa"))
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout(out: &Output) -> String {
+    String::from_utf8_lossy(&out.stdout).into_owned()
+}
+
+fn stderr(out: &Output) -> String {
+    String::from_utf8_lossy(&out.stderr).into_owned()
+}
+
+#[test]
+fn test_run_missing_file() {
+    let dir = dir("run_missing_file");
+    let out = chigusa(&dir, &["run", "missing.c0"]);
+    assert_eq!(out.status.code(), Some(3), "{}", stderr(&out));
+    assert!(stderr(&out).contains("missing.c0: cannot read file"));
+}
+
+/// A program printing 1 with `EXT` defined and 2 without
+const WITH_IF: &str = "int main() {
+#if EXT
+    print(1);
+#else
+    print(2);
+#endif
+    return 0;
+}
+";
+
+#[test]
+fn test_run_defines() {
+    let dir = dir("run_defines");
+    fs::write(dir.join("f.c0"), WITH_IF).unwrap();
+    let out = chigusa(&dir, &["-D", "EXT", "run", "f.c0"]);
+    assert_eq!(out.status.code(), Some(0), "{}", stderr(&out));
+    assert_eq!(stdout(&out), "1\n");
+    let out = chigusa(&dir, &["run", "f.c0"]);
+    assert_eq!(stdout(&out), "2\n");
+}
+
+#[test]
+fn test_fmt_defines() {
+    let dir = dir("fmt_defines");
+    let src = "int main() {
+#if EXT
+  print(1 +1);
+#else
+  print(2 +2);
+#endif
+return 0;
+}
+";
+    fs::write(dir.join("f.c0"), src).unwrap();
+    let out = chigusa(&dir, &["-D", "EXT", "fmt", "f.c0"]);
+    assert_eq!(out.status.code(), Some(0), "{}", stderr(&out));
+    // * Only the lines compiled are formatted
+    let formatted = "int main() {
+#if EXT
+    print(1 + 1);
+#else
+  print(2 +2);
+#endif
+    return 0;
+}
+";
+    assert_eq!(fs::read_to_string(dir.join("f.c0")).unwrap(), formatted);
+    let out = chigusa(&dir, &["-D", "EXT", "fmt", "--check", "f.c0"]);
+    assert_eq!(out.status.code(), Some(0), "{}", stdout(&out));
+}
+
+/// A program with a parse error on line 2
+const BAD: &str = "int main() {
+    return 0
+}
+";
+
+#[test]
+fn test_errors_like_check() {
+    let dir = dir("errors_like_check");
+    fs::write(dir.join("f.c0"), BAD).unwrap();
+    fs::write(dir.join("g.c0"), BAD).unwrap();
+    for cmd in [
+        "check",
+        "run",
+        "difftest",
+        "fmt",
+        "doc",
+        "metrics",
+        "obfuscate",
+        "cov",
+        "debug",
+    ] {
+        let out = chigusa(&dir, &[cmd, "f.c0"]);
+        assert_eq!(out.status.code(), Some(1), "{}: {}", cmd, stderr(&out));
+        assert!(
+            stderr(&out).starts_with("f.c0:3:1: error[E"),
+            "{}: {}",
+            cmd,
+            stderr(&out)
+        );
+        let out = chigusa(&dir, &["-q", cmd, "f.c0"]);
+        assert_eq!(out.status.code(), Some(1), "{}", cmd);
+        assert_eq!(stderr(&out), "", "{}", cmd);
+    }
+    for cmd in ["check", "difftest", "fmt"] {
+        let out = chigusa(&dir, &["--max-errors", "1", cmd, "f.c0", "g.c0"]);
+        assert_eq!(stderr(&out).lines().count(), 1, "{}: {}", cmd, stderr(&out));
+    }
+}
+
+#[test]
+fn test_read_failures() {
+    let dir = dir("read_failures");
+    fs::write(dir.join("f.c0"), "int main() { return 0; }").unwrap();
+    let runs: &[&[&str]] = &[
+        &["obfuscate", "missing.c0"],
+        &["ast-diff", "f.c0", "missing.c0"],
+        &["cov", "missing.c0"],
+        &["cov", "f.c0", "--input", "missing.in"],
+        &["cov-report", "f.c0", "--counters", "missing.counters"],
+        &["debug", "missing.c0"],
+        &["reduce", "missing.c0"],
+    ];
+    for args in runs {
+        let out = chigusa(&dir, args);
+        assert_eq!(out.status.code(), Some(3), "{:?}: {}", args, stderr(&out));
+        assert!(
+            stderr(&out).contains("missing."),
+            "{:?}: {}",
+            args,
+            stderr(&out)
+        );
+        let out = chigusa(&dir, &[&["-q"], *args].concat());
+        assert_eq!(out.status.code(), Some(3), "{:?}", args);
+        assert_eq!(stderr(&out), "", "{:?}", args);
+    }
+}
+
+#[test]
+fn test_write_failures() {
+    let dir = dir("write_failures");
+    fs::write(dir.join("f.c0"), "int main() { return 0; }").unwrap();
+    for cmd in ["obfuscate", "doc", "metrics"] {
+        let out = chigusa(&dir, &[cmd, "f.c0", "-o", "missing/out"]);
+        assert_eq!(out.status.code(), Some(3), "{}: {}", cmd, stderr(&out));
+        assert!(
+            stderr(&out).starts_with("missing/out: cannot write"),
+            "{}",
+            cmd
+        );
+    }
+    let out = chigusa(&dir, &["gen", "-q", "-o", "missing/out"]);
+    assert_eq!(out.status.code(), Some(3), "{}", stderr(&out));
+    assert_eq!(stderr(&out), "");
+}
+
+#[test]
+fn test_disasm_failures() {
+    let dir = dir("disasm_failures");
+    fs::write(dir.join("f.c0"), "int main() { return 0; }").unwrap();
+    let out = chigusa(&dir, &["disasm", "missing.o0"]);
+    assert_eq!(out.status.code(), Some(3), "{}", stderr(&out));
+    assert!(stderr(&out).starts_with("missing.o0: cannot read file"));
+    let out = chigusa(&dir, &["disasm", "f.c0"]);
+    assert_eq!(out.status.code(), Some(3), "{}", stderr(&out));
+    assert!(stderr(&out).starts_with("f.c0: cannot disassemble: Not an O0 file"));
+    let out = chigusa(&dir, &["-q", "disasm", "f.c0"]);
+    assert_eq!(out.status.code(), Some(3));
+    assert_eq!(stderr(&out), "");
+}
+
+#[test]
+fn test_check_notes() {
+    let dir = dir("check_notes");
+    let src = "int main() {
+#if EXT
+    return x;
+#endif
+    return 0;
+}
+";
+    fs::write(dir.join("f.c0"), src).unwrap();
+    let out = chigusa(&dir, &["-D", "EXT", "check", "f.c0"]);
+    assert_eq!(out.status.code(), Some(1), "{}", stderr(&out));
+    assert!(
+        stderr(&out).starts_with("f.c0:3:12: error[E"),
+        "{}",
+        stderr(&out)
+    );
+    assert!(
+        stderr(&out).contains("f.c0:2:1: note: compiled as `#if EXT` holds\n"),
+        "{}",
+        stderr(&out)
+    );
+}
+
+#[test]
+fn test_difftest_references() {
+    let dir = dir("difftest_references");
+    let src = "void set(&int p, int v) {
+    *p = v;
+}
+
+int main() {
+    int a = 1;
+    set(&a, 2);
+    print(a);
+    return 0;
+}
+";
+    fs::write(dir.join("f.c0"), src).unwrap();
+    fs::write(dir.join("g.c0"), WITH_IF).unwrap();
+    let out = chigusa(&dir, &["run", "f.c0"]);
+    assert_eq!(stdout(&out), "2\n", "{}", stderr(&out));
+    // * The interpreter has no references, which is no divergence
+    let out = chigusa(&dir, &["difftest", "f.c0", "g.c0"]);
+    assert_eq!(out.status.code(), Some(0), "{}", stdout(&out));
+    assert!(stdout(&out).contains("f.c0: skipped: "), "{}", stdout(&out));
+    assert!(
+        stdout(&out).contains("1 agreed, 0 diverged, 1 skipped"),
+        "{}",
+        stdout(&out)
+    );
+}
+
+#[test]
+fn test_fix_assign_in_condition() {
+    let dir = dir("fix_assign_in_condition");
+    let src = "int main() {
+    int a = 1;
+    if (a=2) {
+        print(a);
+    }
+    while (a  =  3) {
+        return 0;
+    }
+    return 0;
+}
+";
+    fs::write(dir.join("f.c0"), src).unwrap();
+    let out = chigusa(&dir, &["check", "f.c0"]);
+    assert!(
+        stderr(&out).starts_with("f.c0:3:10: error[E0321]"),
+        "{}",
+        stderr(&out)
+    );
+    let out = chigusa(&dir, &["fix", "f.c0"]);
+    assert_eq!(out.status.code(), Some(0), "{}", stderr(&out));
+    assert!(
+        stderr(&out).contains("f.c0: made 2 fixes"),
+        "{}",
+        stderr(&out)
+    );
+    let fixed = src.replace("a=2", "a==2").replace("a  =  3", "a  ==  3");
+    assert_eq!(fs::read_to_string(dir.join("f.c0")).unwrap(), fixed);
+
+    fs::write(
+        dir.join("g.c0"),
+        "int main() { int a; if (a = 1) a = 2; return 0; }",
+    )
+    .unwrap();
+    let out = chigusa(&dir, &["fix", "g.c0"]);
+    assert_eq!(out.status.code(), Some(0), "{}", stderr(&out));
+    assert!(
+        stderr(&out).contains("g.c0: made 1 fix\n"),
+        "{}",
+        stderr(&out)
+    );
+}
@@ -0,0 +1,119 @@
+//! End-to-end tests. Every `tests/cases/<name>.c0` is compiled and run on the
+//! built-in VM, feeding `<name>.in` as stdin if it exists. The outcome is
+//! compared against `<name>.expected`.
+//!
+//! ```sh
+//! # Run all cases, or only those whose names contain `fib`
+//! $ cargo test --test run_suite
+//! $ cargo test --test run_suite -- fib
+//!
+//! # Rewrite `.expected` files with the current outcome
+//! $ cargo test --test run_suite -- --bless
+//! ```
+
+use chigusa::minivm::vm::MiniVM;
+use chigusa::{codegen, parse};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Programs running longer than this are considered stuck
+const STEP_LIMIT: u64 = 10_000_000;
+
+/// Compile and run one program, describing what happened as text.
+///
+/// The first line is the exit status; program output follows.
+fn run_case(src: &str, input: &[u8]) -> String {
+    let prog = match parse(src) {
+        Ok(prog) => prog,
+        Err(e) => return format!("parse error[{}]: {}\n", e.code, e.message),
+    };
+    let o0 = match codegen(&prog) {
+        Ok(o0) => o0,
+        Err(e) => return format!("compile error[{}]: {}\n", e.code, e.message),
+    };
+
+    let mut input = input;
+    let mut output = Vec::new();
+    let res = MiniVM::new(&o0, &mut input, &mut output)
+        .with_step_limit(STEP_LIMIT)
+        .run();
+    let status = match res {
+        Ok(code) => format!("exit code: {}", code),
+        Err(e) => format!("runtime error: {}", e),
+    };
+    format!("{}\n{}", status, String::from_utf8_lossy(&output))
+}
+
+fn cases(dir: &Path) -> Vec<PathBuf> {
+    let mut cases: Vec<_> = fs::read_dir(dir)
+        .expect("Cannot read test case directory")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "c0"))
+        .collect();
+    cases.sort();
+    cases
+}
+
+fn main() {
+    let mut bless = false;
+    let mut filters = vec![];
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--bless" => bless = true,
+            // Flags cargo passes to every test binary
+            s if s.starts_with('-') => (),
+            s => filters.push(s.to_owned()),
+        }
+    }
+
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/cases");
+    let mut failed = vec![];
+    let mut count = 0;
+
+    for case in cases(&dir) {
+        let name = case.file_stem().unwrap().to_string_lossy().into_owned();
+        if !filters.is_empty() && !filters.iter().any(|f| name.contains(f.as_str())) {
+            continue;
+        }
+        count += 1;
+
+        let src = fs::read_to_string(&case).unwrap();
+        let input = fs::read(case.with_extension("in")).unwrap_or_default();
+        let actual = run_case(&src, &input);
+
+        let expected_path = case.with_extension("expected");
+        if bless {
+            fs::write(&expected_path, &actual).unwrap();
+            println!("blessed {}", name);
+            continue;
+        }
+
+        match fs::read_to_string(&expected_path) {
+            Ok(expected) if expected == actual => println!("case {} ... ok", name),
+            Ok(expected) => {
+                println!("case {} ... FAILED", name);
+                println!("--- expected\n{}--- actual\n{}---", expected, actual);
+                failed.push(name);
+            }
+            Err(_) => {
+                println!("case {} ... FAILED (no {:?})", name, expected_path);
+                println!("--- actual\n{}---", actual);
+                failed.push(name);
+            }
+        }
+    }
+
+    if bless {
+        return;
+    }
+    println!(
+        "\nsuite result: {} passed; {} failed",
+        count - failed.len(),
+        failed.len()
+    );
+    if !failed.is_empty() {
+        println!("failed cases: {}", failed.join(", "));
+        println!("run with `--bless` to accept the new output");
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,128 @@
+//! Snapshot tests of compiler output. Every `tests/cases/<name>.c0` is
+//! lowered to typed IR and compiled to assembly, and the output is compared
+//! against `tests/snapshots/<name>.tast` and `tests/snapshots/<name>.s0`, the
+//! same as `--emit typed-ast` and `--emit s0` write, less the aliases. Any
+//! change in lowering or code generation shows up here, to be reviewed and
+//! blessed explicitly.
+//!
+//! ```sh
+//! # Check all snapshots, or only those whose names contain `fib`
+//! $ cargo test --test snapshots
+//! $ cargo test --test snapshots -- fib
+//!
+//! # Rewrite snapshots with the current output
+//! $ cargo test --test snapshots -- --bless
+//! ```
+
+use chigusa::{codegen, parse, typed};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Typed IR and assembly of one program, or the error compiling it
+fn snapshot(src: &str) -> (String, String) {
+    let prog = match parse(src) {
+        Ok(prog) => prog,
+        Err(e) => {
+            let err = format!("parse error[{}]: {}\n", e.code, e.message);
+            return (err.clone(), err);
+        }
+    };
+    let tast = match typed(&prog) {
+        Ok(typed) => typed.to_string(),
+        Err(e) => format!("type error[{}]: {}\n", e.code, e.message),
+    };
+    let s0 = match codegen(&prog) {
+        Ok(o0) => o0.to_string(),
+        Err(e) => format!("compile error[{}]: {}\n", e.code, e.message),
+    };
+    (tast, s0)
+}
+
+fn cases(dir: &Path) -> Vec<PathBuf> {
+    let mut cases: Vec<_> = fs::read_dir(dir)
+        .expect("Cannot read test case directory")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "c0"))
+        .collect();
+    cases.sort();
+    cases
+}
+
+/// Compare `actual` against the snapshot at `path`, saying what differs
+fn check(path: &Path, actual: &str) -> Result<(), String> {
+    match fs::read_to_string(path) {
+        Ok(expected) if expected == actual => Ok(()),
+        Ok(expected) => Err(format!(
+            "{:?} differs\n--- expected\n{}--- actual\n{}---",
+            path, expected, actual
+        )),
+        Err(_) => Err(format!("no {:?}\n--- actual\n{}---", path, actual)),
+    }
+}
+
+fn main() {
+    let mut bless = false;
+    let mut filters = vec![];
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--bless" => bless = true,
+            // Flags cargo passes to every test binary
+            s if s.starts_with('-') => (),
+            s => filters.push(s.to_owned()),
+        }
+    }
+
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let snapshots = root.join("tests/snapshots");
+    let mut failed = vec![];
+    let mut count = 0;
+
+    for case in cases(&root.join("tests/cases")) {
+        let name = case.file_stem().unwrap().to_string_lossy().into_owned();
+        if !filters.is_empty() && !filters.iter().any(|f| name.contains(f.as_str())) {
+            continue;
+        }
+        count += 1;
+
+        let src = fs::read_to_string(&case).unwrap();
+        let (tast, s0) = snapshot(&src);
+        let outputs = [
+            (snapshots.join(format!("{}.tast", name)), tast),
+            (snapshots.join(format!("{}.s0", name)), s0),
+        ];
+
+        if bless {
+            fs::create_dir_all(&snapshots).unwrap();
+            for (path, actual) in &outputs {
+                fs::write(path, actual).unwrap();
+            }
+            println!("blessed {}", name);
+            continue;
+        }
+
+        let diffs: Vec<_> = (outputs.iter())
+            .filter_map(|(path, actual)| check(path, actual).err())
+            .collect();
+        if diffs.is_empty() {
+            println!("snapshot {} ... ok", name);
+        } else {
+            println!("snapshot {} ... FAILED", name);
+            diffs.iter().for_each(|diff| println!("{}", diff));
+            failed.push(name);
+        }
+    }
+
+    if bless {
+        return;
+    }
+    println!(
+        "\nsnapshot result: {} passed; {} failed",
+        count - failed.len(),
+        failed.len()
+    );
+    if !failed.is_empty() {
+        println!("failed snapshots: {}", failed.join(", "));
+        println!("run with `--bless` to accept the new output");
+        std::process::exit(1);
+    }
+}